@@ -0,0 +1,544 @@
+// Copyright 2020 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fmt;
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+use micro_http::{Body, ClientError, HttpClient, Response, StatusCode};
+use vmm::vmm_config::boot_source::BootSourceConfig;
+use vmm::vmm_config::drive::BlockDeviceConfig;
+use vmm::vmm_config::instance_info::InstanceInfo;
+use vmm::vmm_config::logger::LoggerConfig;
+use vmm::vmm_config::machine_config::VmConfig;
+use vmm::vmm_config::metrics::MetricsConfig;
+use vmm::vmm_config::net::{NetworkInterfaceConfig, NetworkInterfaceUpdateConfig};
+use vmm::vmm_config::rate_limiter_group::RateLimiterGroupConfig;
+use vmm::vmm_config::snapshot::{CreateSnapshotParams, LoadSnapshotParams, Vm};
+use vmm::vmm_config::vsock::VsockDeviceConfig;
+use ApiServer;
+
+/// Errors associated with the requests sent by `ApiClient`.
+#[derive(Debug)]
+pub enum Error {
+    /// The request could not be sent, or its response could not be received.
+    Http(ClientError),
+    /// The body of the request could not be serialized, or the body of the response
+    /// could not be deserialized.
+    SerdeJson(serde_json::Error),
+    /// The API server answered with an error status code and fault message.
+    Fault(StatusCode, String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Http(ref err) => write!(f, "HTTP error: {}", err),
+            Error::SerdeJson(ref err) => write!(f, "Serde error: {}", err),
+            Error::Fault(status_code, ref msg) => write!(
+                f,
+                "The API server answered with {}: {}",
+                String::from_utf8_lossy(status_code.raw()),
+                msg
+            ),
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// A client for the Firecracker API, which sends the `vmm_config` structures to the
+/// API server and parses its answers back.
+///
+/// The requests which don't have a helper can be sent with the underlying `HttpClient`.
+///
+/// The API server closes the connections which stay idle for 60 seconds. The GET and
+/// PUT requests are then sent again on a new connection, while the PATCH requests fail
+/// with an `Http` error and can be retried after a call to `reconnect`.
+pub struct ApiClient {
+    client: HttpClient,
+}
+
+impl ApiClient {
+    /// Connects to the API server listening on the Unix domain socket at `path_to_socket`.
+    pub fn connect<P: AsRef<Path>>(path_to_socket: P) -> Result<Self> {
+        HttpClient::connect(path_to_socket)
+            .map(ApiClient::new)
+            .map_err(Error::Http)
+    }
+
+    /// Creates a new `ApiClient` which sends its requests with `client`.
+    pub fn new(client: HttpClient) -> Self {
+        ApiClient { client }
+    }
+
+    /// Returns the underlying `HttpClient`.
+    pub fn http_client(&mut self) -> &mut HttpClient {
+        &mut self.client
+    }
+
+    /// Replaces the connection to the API server with a new one, to the same socket.
+    pub fn reconnect(&mut self) -> Result<()> {
+        self.client.reconnect().map_err(Error::Http)
+    }
+
+    /// Retrieves the general information about the microVM.
+    pub fn get_instance_info(&mut self) -> Result<InstanceInfo> {
+        self.get("/")
+    }
+
+    /// Starts the microVM.
+    pub fn start_instance(&mut self) -> Result<()> {
+        self.put_action("InstanceStart")
+    }
+
+    /// Flushes the metrics.
+    pub fn flush_metrics(&mut self) -> Result<()> {
+        self.put_action("FlushMetrics")
+    }
+
+    /// Sends CTRL+ALT+DEL to the microVM. Only supported on x86_64.
+    pub fn send_ctrl_alt_del(&mut self) -> Result<()> {
+        self.put_action("SendCtrlAltDel")
+    }
+
+    /// Configures the boot source of the microVM.
+    pub fn put_boot_source(&mut self, config: &BootSourceConfig) -> Result<()> {
+        self.put("/boot-source", config)
+    }
+
+    /// Retrieves the machine configuration of the microVM.
+    pub fn get_machine_config(&mut self) -> Result<VmConfig> {
+        self.get("/machine-config")
+    }
+
+    /// Replaces the machine configuration of the microVM.
+    pub fn put_machine_config(&mut self, config: &VmConfig) -> Result<()> {
+        self.put("/machine-config", config)
+    }
+
+    /// Updates the fields of the machine configuration which are set in `config`.
+    pub fn patch_machine_config(&mut self, config: &VmConfig) -> Result<()> {
+        self.patch("/machine-config", config)
+    }
+
+    /// Adds a block device, or replaces the one with the same ID.
+    pub fn put_drive(&mut self, config: &BlockDeviceConfig) -> Result<()> {
+        self.put(&format!("/drives/{}", config.drive_id), config)
+    }
+
+    /// Adds a network interface, or replaces the one with the same ID.
+    pub fn put_network_interface(&mut self, config: &NetworkInterfaceConfig) -> Result<()> {
+        self.put(&format!("/network-interfaces/{}", config.iface_id), config)
+    }
+
+    /// Updates the rate limiters and the link state of a network interface.
+    pub fn patch_network_interface(&mut self, config: &NetworkInterfaceUpdateConfig) -> Result<()> {
+        self.patch(&format!("/network-interfaces/{}", config.iface_id), config)
+    }
+
+    /// Creates a rate limiter group, or replaces the one with the same ID.
+    pub fn put_rate_limiter_group(&mut self, config: &RateLimiterGroupConfig) -> Result<()> {
+        self.put(&format!("/rate-limiter-groups/{}", config.group_id), config)
+    }
+
+    /// Configures the vsock device of the microVM.
+    pub fn put_vsock(&mut self, config: &VsockDeviceConfig) -> Result<()> {
+        self.put("/vsock", config)
+    }
+
+    /// Configures the logger.
+    pub fn put_logger(&mut self, config: &LoggerConfig) -> Result<()> {
+        self.put("/logger", config)
+    }
+
+    /// Configures the metrics system.
+    pub fn put_metrics(&mut self, config: &MetricsConfig) -> Result<()> {
+        self.put("/metrics", config)
+    }
+
+    /// Retrieves the MMDS contents.
+    pub fn get_mmds(&mut self) -> Result<Value> {
+        self.get("/mmds")
+    }
+
+    /// Replaces the MMDS contents.
+    pub fn put_mmds(&mut self, value: &Value) -> Result<()> {
+        self.put("/mmds", value)
+    }
+
+    /// Merges `value` into the MMDS contents.
+    pub fn patch_mmds(&mut self, value: &Value) -> Result<()> {
+        self.patch("/mmds", value)
+    }
+
+    /// Retrieves the contents of the MMDS instance identified by `id`.
+    pub fn get_mmds_instance(&mut self, id: &str) -> Result<Value> {
+        self.get(&format!("/mmds/instances/{}", id))
+    }
+
+    /// Replaces the contents of the MMDS instance identified by `id`, creating the instance
+    /// if it does not exist yet.
+    pub fn put_mmds_instance(&mut self, id: &str, value: &Value) -> Result<()> {
+        self.put(&format!("/mmds/instances/{}", id), value)
+    }
+
+    /// Merges `value` into the contents of the MMDS instance identified by `id`.
+    pub fn patch_mmds_instance(&mut self, id: &str, value: &Value) -> Result<()> {
+        self.patch(&format!("/mmds/instances/{}", id), value)
+    }
+
+    /// Creates a snapshot of the paused microVM. Only supported on x86_64.
+    pub fn create_snapshot(&mut self, params: &CreateSnapshotParams) -> Result<()> {
+        self.put("/snapshot/create", params)
+    }
+
+    /// Loads a snapshot into a microVM which was not configured yet. Only supported on x86_64.
+    pub fn load_snapshot(&mut self, params: &LoadSnapshotParams) -> Result<()> {
+        self.put("/snapshot/load", params)
+    }
+
+    /// Pauses or resumes the microVM.
+    pub fn patch_vm(&mut self, vm: &Vm) -> Result<()> {
+        self.patch("/vm", vm)
+    }
+
+    fn put_action(&mut self, action_type: &str) -> Result<()> {
+        let body = ApiServer::basic_json_body("action_type", action_type);
+        let response = self
+            .client
+            .put("/actions", Body::new(body))
+            .map_err(Error::Http)?;
+        check_response(response).map(|_| ())
+    }
+
+    fn get<T: DeserializeOwned>(&mut self, uri: &str) -> Result<T> {
+        let response = self.client.get(uri).map_err(Error::Http)?;
+        let body = check_response(response)?
+            .body()
+            .map(|body| body.body)
+            .unwrap_or_default();
+        serde_json::from_slice(&body).map_err(Error::SerdeJson)
+    }
+
+    fn put<T: Serialize>(&mut self, uri: &str, config: &T) -> Result<()> {
+        let body = serde_json::to_string(config).map_err(Error::SerdeJson)?;
+        let response = self.client.put(uri, Body::new(body)).map_err(Error::Http)?;
+        check_response(response).map(|_| ())
+    }
+
+    fn patch<T: Serialize>(&mut self, uri: &str, config: &T) -> Result<()> {
+        let body = serde_json::to_string(config).map_err(Error::SerdeJson)?;
+        let response = self
+            .client
+            .patch(uri, Body::new(body))
+            .map_err(Error::Http)?;
+        check_response(response).map(|_| ())
+    }
+}
+
+// Turns the responses which don't report a success into a `Fault` error.
+fn check_response(response: Response) -> Result<Response> {
+    match response.status() {
+        StatusCode::OK | StatusCode::NoContent => Ok(response),
+        status_code => Err(Error::Fault(status_code, fault_message(&response))),
+    }
+}
+
+// Returns the fault message of an error response, or the whole body if it doesn't
+// hold one.
+fn fault_message(response: &Response) -> String {
+    let body = response.body().map(|body| body.body).unwrap_or_default();
+    serde_json::from_slice::<Value>(&body)
+        .ok()
+        .and_then(|value| {
+            value
+                .get("fault_message")
+                .and_then(Value::as_str)
+                .map(String::from)
+        })
+        .unwrap_or_else(|| String::from_utf8_lossy(&body).into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::{Read, Write};
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+    use micro_http::Request;
+    use parsed_request::ParsedRequest;
+    use vmm::rpc_interface::VmmAction;
+    use vmm::vmm_config::machine_config::CpuFeaturesTemplate;
+    use vmm::vmm_config::net::LinkState;
+    use vmm::vmm_config::snapshot::VmState;
+
+    // Runs `send` against a server which answers with `response`, and returns the
+    // outcome of `send` along with the request it sent.
+    fn exchange<T, F>(response: &[u8], send: F) -> (Result<T>, Request)
+    where
+        F: FnOnce(&mut ApiClient) -> Result<T>,
+    {
+        let (client_stream, mut server_stream) = UnixStream::pair().unwrap();
+        let mut api_client = ApiClient::new(HttpClient::new(client_stream));
+        server_stream.write_all(response).unwrap();
+
+        let outcome = send(&mut api_client);
+
+        server_stream
+            .set_read_timeout(Some(Duration::from_millis(100)))
+            .unwrap();
+        let mut request_bytes = vec![];
+        let mut buf = [0; 1024];
+        while let Ok(len) = server_stream.read(&mut buf) {
+            if len == 0 {
+                break;
+            }
+            request_bytes.extend_from_slice(&buf[..len]);
+        }
+        (outcome, Request::try_from(&request_bytes).unwrap())
+    }
+
+    // Returns the `VmmAction` the API server would carry out for `request`.
+    fn vmm_action(request: &Request) -> VmmAction {
+        match ParsedRequest::try_from_request(request) {
+            Ok(ParsedRequest::Sync(vmm_action)) => vmm_action,
+            _ => panic!("Expected a VmmAction."),
+        }
+    }
+
+    const NO_CONTENT: &[u8] = b"HTTP/1.1 204 \r\n\r\n";
+
+    #[test]
+    fn test_put_helpers() {
+        // The requests are routed to the expected `VmmAction`s, with the same configuration.
+        let (outcome, request) = exchange(NO_CONTENT, |api_client| api_client.start_instance());
+        outcome.unwrap();
+        assert!(vmm_action(&request) == VmmAction::StartMicroVm);
+
+        let boot_source = BootSourceConfig {
+            kernel_image_path: String::from("/vmlinux"),
+            initrd_path: None,
+            boot_args: Some(String::from("console=ttyS0")),
+        };
+        let (outcome, request) = exchange(NO_CONTENT, |api_client| {
+            api_client.put_boot_source(&boot_source)
+        });
+        outcome.unwrap();
+        assert!(vmm_action(&request) == VmmAction::ConfigureBootSource(boot_source));
+
+        let drive = BlockDeviceConfig {
+            drive_id: String::from("rootfs"),
+            path_on_host: String::from("/rootfs.ext4"),
+            is_root_device: true,
+            partuuid: None,
+            is_read_only: false,
+            rate_limiter: None,
+        };
+        let (outcome, request) = exchange(NO_CONTENT, |api_client| api_client.put_drive(&drive));
+        outcome.unwrap();
+        assert_eq!(request.uri().get_abs_path(), "/drives/rootfs");
+        assert!(vmm_action(&request) == VmmAction::InsertBlockDevice(drive));
+
+        let net_update = NetworkInterfaceUpdateConfig {
+            iface_id: String::from("eth0"),
+            rx_rate_limiter: None,
+            tx_rate_limiter: None,
+            link_state: Some(LinkState::Down),
+        };
+        let (outcome, request) = exchange(NO_CONTENT, |api_client| {
+            api_client.patch_network_interface(&net_update)
+        });
+        outcome.unwrap();
+        assert_eq!(request.uri().get_abs_path(), "/network-interfaces/eth0");
+        assert!(vmm_action(&request) == VmmAction::UpdateNetworkInterface(net_update));
+
+        let (outcome, request) = exchange(NO_CONTENT, |api_client| {
+            api_client.patch_vm(&Vm {
+                state: VmState::Paused,
+            })
+        });
+        outcome.unwrap();
+        assert!(vmm_action(&request) == VmmAction::Pause);
+
+        #[cfg(target_arch = "x86_64")]
+        {
+            let params = LoadSnapshotParams {
+                snapshot_path: std::path::PathBuf::from("/vmstate"),
+                mem_file_path: std::path::PathBuf::from("/mem"),
+                enable_diff_snapshots: false,
+            };
+            let (outcome, request) =
+                exchange(NO_CONTENT, |api_client| api_client.load_snapshot(&params));
+            outcome.unwrap();
+            assert!(vmm_action(&request) == VmmAction::LoadSnapshot(params));
+        }
+
+        let value: Value =
+            serde_json::from_str("{ \"latest\": { \"meta-data\": \"value\" } }").unwrap();
+        let (outcome, request) = exchange(NO_CONTENT, |api_client| api_client.put_mmds(&value));
+        outcome.unwrap();
+        match ParsedRequest::try_from_request(&request) {
+            Ok(ParsedRequest::PutMMDS(None, parsed_value)) => assert_eq!(parsed_value, value),
+            _ => panic!("Expected PutMMDS."),
+        }
+
+        let (outcome, request) = exchange(NO_CONTENT, |api_client| {
+            api_client.patch_mmds_instance("mmds_1", &value)
+        });
+        outcome.unwrap();
+        match ParsedRequest::try_from_request(&request) {
+            Ok(ParsedRequest::PatchMMDS(Some(id), parsed_value)) => {
+                assert_eq!(id, "mmds_1");
+                assert_eq!(parsed_value, value);
+            }
+            _ => panic!("Expected PatchMMDS."),
+        }
+    }
+
+    #[test]
+    fn test_get_helpers() {
+        let (outcome, request) = exchange(
+            b"HTTP/1.1 200 \r\n\
+              Content-Length: 71\r\n\r\n\
+              { \"id\": \"vm\", \"started\": true, \"vmm_version\": \"0.1\", \"app_name\": \"fc\" }",
+            |api_client| api_client.get_instance_info(),
+        );
+        let instance_info = outcome.unwrap();
+        assert_eq!(instance_info.id, "vm");
+        assert!(instance_info.started);
+        assert_eq!(request.uri().get_abs_path(), "/");
+
+        // The machine configuration is read the way the API server reports it.
+        for cpu_template in [None, Some(CpuFeaturesTemplate::C3)].iter() {
+            let config = VmConfig {
+                cpu_template: *cpu_template,
+                ..Default::default()
+            };
+            let response_body = config.to_string();
+            let mut response = Response::new(micro_http::Version::Http11, StatusCode::OK);
+            response.set_body(Body::new(response_body));
+            let mut response_bytes = vec![];
+            response.write_all(&mut response_bytes).unwrap();
+            let (outcome, request) = exchange(&response_bytes, |api_client| {
+                api_client.get_machine_config()
+            });
+            assert_eq!(outcome.unwrap(), config);
+            match ParsedRequest::try_from_request(&request) {
+                Ok(ParsedRequest::Sync(VmmAction::GetVmConfiguration)) => (),
+                _ => panic!("Expected GetVmConfiguration."),
+            }
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        // The fault message of error responses is extracted...
+        let (outcome, _) = exchange(
+            b"HTTP/1.1 400 \r\n\
+              Content-Length: 40\r\n\r\n\
+              {\n  \"fault_message\": \"Invalid config.\"\n}",
+            |api_client| api_client.start_instance(),
+        );
+        match outcome {
+            Err(Error::Fault(StatusCode::BadRequest, ref msg)) if msg == "Invalid config." => (),
+            _ => panic!("Expected Fault."),
+        }
+        assert_eq!(
+            outcome.unwrap_err().to_string(),
+            "The API server answered with 400: Invalid config."
+        );
+
+        // ... and the whole body is kept when there's none.
+        let (outcome, _) = exchange(
+            b"HTTP/1.1 503 \r\n\
+              Content-Length: 40\r\n\r\n\
+              { \"error\": \"Too many open connections\" }",
+            |api_client| api_client.flush_metrics(),
+        );
+        match outcome {
+            Err(Error::Fault(StatusCode::ServiceUnavailable, ref msg)) => {
+                assert_eq!(msg, "{ \"error\": \"Too many open connections\" }")
+            }
+            _ => panic!("Expected Fault."),
+        }
+
+        // Bodies which don't match the expected structure.
+        let (outcome, _) = exchange(
+            b"HTTP/1.1 200 \r\nContent-Length: 2\r\n\r\n{}",
+            |api_client| api_client.get_instance_info(),
+        );
+        match outcome {
+            Err(Error::SerdeJson(_)) => (),
+            _ => panic!("Expected SerdeJson."),
+        }
+
+        // Connection errors.
+        let (outcome, _) = exchange(b"HTTP/1.1 299 \r\n\r\n", |api_client| api_client.get_mmds());
+        match outcome {
+            Err(Error::Http(_)) => (),
+            _ => panic!("Expected Http."),
+        }
+    }
+
+    #[test]
+    fn test_reconnect() {
+        let path_to_socket = "/tmp/api_client_test_socket.sock";
+        fs::remove_file(path_to_socket).unwrap_or_default();
+        let listener = UnixListener::bind(path_to_socket).unwrap();
+        let mut api_client = ApiClient::connect(path_to_socket).unwrap();
+
+        let server = thread::spawn(move || {
+            // The first connection is closed without an answer, the way the API server
+            // closes the idle ones.
+            drop(listener.accept().unwrap());
+            // The GET request is sent again on a new connection, which is closed
+            // once the request is answered.
+            let (mut stream, _) = listener.accept().unwrap();
+            assert!(stream.read(&mut [0; 1024]).unwrap() > 0);
+            stream
+                .write_all(
+                    b"HTTP/1.1 200 \r\n\
+                      Content-Length: 71\r\n\r\n\
+                      { \"id\": \"vm\", \"started\": true, \"vmm_version\": \"0.1\", \"app_name\": \"fc\" }",
+                )
+                .unwrap();
+            drop(stream);
+            // The PATCH request is only sent again after an explicit reconnect.
+            let (mut stream, _) = listener.accept().unwrap();
+            assert!(stream.read(&mut [0; 1024]).unwrap() > 0);
+            stream.write_all(NO_CONTENT).unwrap();
+            stream
+        });
+
+        let instance_info = api_client.get_instance_info().unwrap();
+        assert_eq!(instance_info.id, "vm");
+
+        let vm = Vm {
+            state: VmState::Paused,
+        };
+        match api_client.patch_vm(&vm) {
+            Err(Error::Http(_)) => (),
+            _ => panic!("Expected Http."),
+        }
+        api_client.reconnect().unwrap();
+        api_client.patch_vm(&vm).unwrap();
+
+        server.join().unwrap();
+        fs::remove_file(path_to_socket).unwrap();
+
+        // A client built from a connected stream doesn't know where to reconnect.
+        let (client_stream, _server_stream) = UnixStream::pair().unwrap();
+        let mut api_client = ApiClient::new(HttpClient::new(client_stream));
+        match api_client.reconnect() {
+            Err(Error::Http(ClientError::CannotReconnect)) => (),
+            _ => panic!("Expected CannotReconnect."),
+        }
+    }
+}
@@ -13,20 +13,23 @@ extern crate seccomp;
 extern crate utils;
 extern crate vmm;
 
+pub mod client;
 mod parsed_request;
 mod request;
 
 use std::path::PathBuf;
 use std::sync::{mpsc, Arc, Mutex, RwLock};
+use std::time::Duration;
 use std::{fmt, io};
 
+pub use client::ApiClient;
 use logger::{Metric, METRICS};
 pub use micro_http::{
-    Body, HttpServer, Method, Request, RequestError, Response, ServerError, ServerRequest,
-    ServerResponse, StatusCode, Version,
+    Body, ClientError, HttpClient, HttpServer, Method, Request, RequestError, Response,
+    ServerError, ServerRequest, ServerResponse, StatusCode, Version,
 };
 use mmds::data_store;
-use mmds::data_store::Mmds;
+use mmds::data_store::{Mmds, MmdsInstances};
 use parsed_request::ParsedRequest;
 use seccomp::{BpfProgram, SeccompFilter};
 use utils::eventfd::EventFd;
@@ -38,6 +41,11 @@ pub type ApiRequest = Box<VmmAction>;
 /// Shorthand type for a response containing a boxed Result.
 pub type ApiResponse = Box<std::result::Result<VmmData, VmmActionError>>;
 
+/// Time a client has to send a complete request once it started sending it.
+const API_READ_TIMEOUT: Duration = Duration::from_secs(10);
+/// Time an idle client connection is kept open.
+const API_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+
 pub enum Error {
     Io(io::Error),
     Eventfd(io::Error),
@@ -64,8 +72,8 @@ impl fmt::Debug for Error {
 pub type Result<T> = std::result::Result<T, Error>;
 
 pub struct ApiServer {
-    /// MMDS info directly accessible from the API thread.
-    mmds_info: Arc<Mutex<Mmds>>,
+    /// MMDS instances directly accessible from the API thread.
+    mmds_info: MmdsInstances,
     /// VMM instance info directly accessible from the API thread.
     vmm_shared_info: Arc<RwLock<InstanceInfo>>,
     /// Sender which allows passing messages to the VMM.
@@ -79,7 +87,7 @@ pub struct ApiServer {
 
 impl ApiServer {
     pub fn new(
-        mmds_info: Arc<Mutex<Mmds>>,
+        mmds_info: MmdsInstances,
         vmm_shared_info: Arc<RwLock<InstanceInfo>>,
         api_request_sender: mpsc::Sender<ApiRequest>,
         vmm_response_receiver: mpsc::Receiver<ApiResponse>,
@@ -102,6 +110,8 @@ impl ApiServer {
         seccomp_filter: BpfProgram,
     ) -> Result<()> {
         let mut server = HttpServer::new(path).expect("Error creating the HTTP server");
+        server.set_read_timeout(Some(API_READ_TIMEOUT));
+        server.set_idle_timeout(Some(API_IDLE_TIMEOUT));
 
         if let Some(start_time) = start_time_us {
             let delta_us =
@@ -161,9 +171,9 @@ impl ApiServer {
         match ParsedRequest::try_from_request(request) {
             Ok(ParsedRequest::Sync(vmm_action)) => self.serve_vmm_action_request(vmm_action),
             Ok(ParsedRequest::GetInstanceInfo) => self.get_instance_info(),
-            Ok(ParsedRequest::GetMMDS) => self.get_mmds(),
-            Ok(ParsedRequest::PatchMMDS(value)) => self.patch_mmds(value),
-            Ok(ParsedRequest::PutMMDS(value)) => self.put_mmds(value),
+            Ok(ParsedRequest::GetMMDS(id)) => self.get_mmds(id),
+            Ok(ParsedRequest::PatchMMDS(id, value)) => self.patch_mmds(id, value),
+            Ok(ParsedRequest::PutMMDS(id, value)) => self.put_mmds(id, value),
             Err(e) => {
                 error!("{}", e);
                 e.into()
@@ -199,19 +209,40 @@ impl ApiServer {
         }
     }
 
-    fn get_mmds(&self) -> Response {
-        ApiServer::json_response(
-            StatusCode::OK,
-            self.mmds_info
-                .lock()
-                .expect("Failed to acquire lock on MMDS info")
-                .get_data_str(),
-        )
+    // Looks up the MMDS instance identified by `id`, or the default instance if `id` is `None`.
+    // Only `PUT` requests create missing instances.
+    fn mmds_instance(&self, id: Option<String>) -> std::result::Result<Arc<Mutex<Mmds>>, Response> {
+        self.mmds_info
+            .get(id.as_ref().map(String::as_str))
+            .ok_or_else(|| {
+                ApiServer::json_response(
+                    StatusCode::NotFound,
+                    ApiServer::json_fault_message(format!(
+                        "The MMDS instance {} does not exist.",
+                        id.unwrap_or_default()
+                    )),
+                )
+            })
     }
 
-    fn patch_mmds(&self, value: serde_json::Value) -> Response {
-        let mmds_response = self
-            .mmds_info
+    fn get_mmds(&self, id: Option<String>) -> Response {
+        let mmds = match self.mmds_instance(id) {
+            Ok(mmds) => mmds,
+            Err(response) => return response,
+        };
+        let body = mmds
+            .lock()
+            .expect("Failed to acquire lock on MMDS info")
+            .get_data_str();
+        ApiServer::json_response(StatusCode::OK, body)
+    }
+
+    fn patch_mmds(&self, id: Option<String>, value: serde_json::Value) -> Response {
+        let mmds = match self.mmds_instance(id) {
+            Ok(mmds) => mmds,
+            Err(response) => return response,
+        };
+        let mmds_response = mmds
             .lock()
             .expect("Failed to acquire lock on MMDS info")
             .patch_data(value);
@@ -221,6 +252,10 @@ impl ApiServer {
             Err(e) => match e {
                 data_store::Error::NotFound => unreachable!(),
                 data_store::Error::UnsupportedValueType => unreachable!(),
+                data_store::Error::DataStoreLimitExceeded(_) => ApiServer::json_response(
+                    StatusCode::PayloadTooLarge,
+                    ApiServer::json_fault_message(e.to_string()),
+                ),
                 data_store::Error::NotInitialized => ApiServer::json_response(
                     StatusCode::BadRequest,
                     ApiServer::json_fault_message(e.to_string()),
@@ -229,14 +264,19 @@ impl ApiServer {
         }
     }
 
-    fn put_mmds(&self, value: serde_json::Value) -> Response {
+    fn put_mmds(&self, id: Option<String>, value: serde_json::Value) -> Response {
         let mmds_response = self
             .mmds_info
+            .get_or_create(id.as_ref().map(String::as_str))
             .lock()
             .expect("Failed to acquire lock on MMDS info")
             .put_data(value);
         match mmds_response {
             Ok(_) => Response::new(Version::Http11, StatusCode::NoContent),
+            Err(e @ data_store::Error::DataStoreLimitExceeded(_)) => ApiServer::json_response(
+                StatusCode::PayloadTooLarge,
+                ApiServer::json_fault_message(e.to_string()),
+            ),
             Err(e) => ApiServer::json_response(
                 StatusCode::BadRequest,
                 ApiServer::json_fault_message(e.to_string()),
@@ -278,7 +318,6 @@ mod tests {
 
     use super::*;
     use micro_http::HttpConnection;
-    use mmds::MMDS;
     use vmm::builder::StartMicrovmError;
     use vmm::rpc_interface::VmmActionError;
     use vmm::vmm_config::instance_info::InstanceInfo;
@@ -323,7 +362,7 @@ mod tests {
         let to_vmm_fd = EventFd::new(libc::EFD_NONBLOCK).unwrap();
         let (api_request_sender, _from_api) = channel();
         let (to_api, vmm_response_receiver) = channel();
-        let mmds_info = MMDS.clone();
+        let mmds_info = MmdsInstances::default();
 
         let api_server = ApiServer::new(
             mmds_info,
@@ -355,7 +394,7 @@ mod tests {
         let to_vmm_fd = EventFd::new(libc::EFD_NONBLOCK).unwrap();
         let (api_request_sender, _from_api) = channel();
         let (_to_api, vmm_response_receiver) = channel();
-        let mmds_info = MMDS.clone();
+        let mmds_info = MmdsInstances::default();
 
         let api_server = ApiServer::new(
             mmds_info,
@@ -382,7 +421,7 @@ mod tests {
         let to_vmm_fd = EventFd::new(libc::EFD_NONBLOCK).unwrap();
         let (api_request_sender, _from_api) = channel();
         let (_to_api, vmm_response_receiver) = channel();
-        let mmds_info = MMDS.clone();
+        let mmds_info = MmdsInstances::default();
 
         let api_server = ApiServer::new(
             mmds_info,
@@ -393,7 +432,7 @@ mod tests {
         )
         .unwrap();
 
-        let response = api_server.get_mmds();
+        let response = api_server.get_mmds(None);
         assert_eq!(response.status(), StatusCode::OK);
     }
 
@@ -409,7 +448,7 @@ mod tests {
         let to_vmm_fd = EventFd::new(libc::EFD_NONBLOCK).unwrap();
         let (api_request_sender, _from_api) = channel();
         let (_to_api, vmm_response_receiver) = channel();
-        let mmds_info = MMDS.clone();
+        let mmds_info = MmdsInstances::default();
 
         let api_server = ApiServer::new(
             mmds_info,
@@ -420,7 +459,7 @@ mod tests {
         )
         .unwrap();
 
-        let response = api_server.put_mmds(serde_json::Value::String("string".to_string()));
+        let response = api_server.put_mmds(None, serde_json::Value::String("string".to_string()));
         assert_eq!(response.status(), StatusCode::NoContent);
     }
 
@@ -436,10 +475,10 @@ mod tests {
         let to_vmm_fd = EventFd::new(libc::EFD_NONBLOCK).unwrap();
         let (api_request_sender, _from_api) = channel();
         let (_to_api, vmm_response_receiver) = channel();
-        let mmds_info = Arc::new(Mutex::new(Mmds::default()));
+        let mmds_info = MmdsInstances::default();
 
         let api_server = ApiServer::new(
-            mmds_info,
+            mmds_info.clone(),
             vmm_shared_info,
             api_request_sender,
             vmm_response_receiver,
@@ -448,16 +487,93 @@ mod tests {
         .unwrap();
 
         // MMDS data store is not yet initialized.
-        let response = api_server.patch_mmds(serde_json::Value::Bool(true));
+        let response = api_server.patch_mmds(None, serde_json::Value::Bool(true));
         assert_eq!(response.status(), StatusCode::BadRequest);
 
-        let response = api_server.put_mmds(serde_json::Value::String("string".to_string()));
+        let response = api_server.put_mmds(None, serde_json::Value::String("string".to_string()));
+        assert_eq!(response.status(), StatusCode::NoContent);
+
+        let response = api_server.patch_mmds(
+            None,
+            serde_json::Value::String("{ \"key\" : \"value\" }".to_string()),
+        );
         assert_eq!(response.status(), StatusCode::NoContent);
 
-        let response = api_server.patch_mmds(serde_json::Value::String(
-            "{ \"key\" : \"value\" }".to_string(),
-        ));
+        // The data store can't grow over its size limit.
+        let size = mmds_info
+            .default_instance()
+            .lock()
+            .unwrap()
+            .get_data_str()
+            .len();
+        mmds_info
+            .default_instance()
+            .lock()
+            .unwrap()
+            .set_data_store_limit(size)
+            .unwrap();
+        let value = serde_json::Value::String("a".repeat(size));
+        let response = api_server.patch_mmds(None, value.clone());
+        assert_eq!(response.status(), StatusCode::PayloadTooLarge);
+        let response = api_server.put_mmds(None, value);
+        assert_eq!(response.status(), StatusCode::PayloadTooLarge);
+    }
+
+    #[test]
+    fn test_mmds_instances() {
+        let vmm_shared_info = Arc::new(RwLock::new(InstanceInfo {
+            started: false,
+            id: "test_mmds_instances".to_string(),
+            vmm_version: "version 0.1.0".to_string(),
+            app_name: "app name".to_string(),
+        }));
+
+        let to_vmm_fd = EventFd::new(libc::EFD_NONBLOCK).unwrap();
+        let (api_request_sender, _from_api) = channel();
+        let (_to_api, vmm_response_receiver) = channel();
+        let mmds_info = MmdsInstances::default();
+
+        let api_server = ApiServer::new(
+            mmds_info.clone(),
+            vmm_shared_info,
+            api_request_sender,
+            vmm_response_receiver,
+            to_vmm_fd,
+        )
+        .unwrap();
+
+        // Only PUT requests create instances.
+        let id = Some("mmds_1".to_string());
+        let response = api_server.get_mmds(id.clone());
+        assert_eq!(response.status(), StatusCode::NotFound);
+        let response = api_server.patch_mmds(id.clone(), serde_json::Value::Bool(true));
+        assert_eq!(response.status(), StatusCode::NotFound);
+        assert!(mmds_info.get(Some("mmds_1")).is_none());
+
+        let response = api_server.put_mmds(id.clone(), serde_json::from_str("{\"a\":1}").unwrap());
         assert_eq!(response.status(), StatusCode::NoContent);
+        let response =
+            api_server.patch_mmds(id.clone(), serde_json::from_str("{\"b\":2}").unwrap());
+        assert_eq!(response.status(), StatusCode::NoContent);
+        let response = api_server.put_mmds(None, serde_json::from_str("{\"c\":3}").unwrap());
+        assert_eq!(response.status(), StatusCode::NoContent);
+
+        // Each instance holds its own contents.
+        assert_eq!(
+            mmds_info
+                .get(Some("mmds_1"))
+                .unwrap()
+                .lock()
+                .unwrap()
+                .get_data_str(),
+            "{\"a\":1,\"b\":2}"
+        );
+        assert_eq!(
+            mmds_info.default_instance().lock().unwrap().get_data_str(),
+            "{\"c\":3}"
+        );
+        let response = api_server.get_mmds(id);
+        assert_eq!(response.status(), StatusCode::OK);
     }
 
     #[test]
@@ -472,7 +588,7 @@ mod tests {
         let to_vmm_fd = EventFd::new(libc::EFD_NONBLOCK).unwrap();
         let (api_request_sender, _from_api) = channel();
         let (to_api, vmm_response_receiver) = channel();
-        let mmds_info = MMDS.clone();
+        let mmds_info = MmdsInstances::default();
 
         let api_server = ApiServer::new(
             mmds_info,
@@ -574,7 +690,7 @@ mod tests {
         let to_vmm_fd = EventFd::new(libc::EFD_NONBLOCK).unwrap();
         let (api_request_sender, _from_api) = channel();
         let (_to_api, vmm_response_receiver) = channel();
-        let mmds_info = MMDS.clone();
+        let mmds_info = MmdsInstances::default();
 
         thread::Builder::new()
             .name("fc_api_test".to_owned())
@@ -7,7 +7,7 @@ use super::VmmData;
 use micro_http::{Body, Method, Request, Response, StatusCode, Version};
 use request::actions::parse_put_actions;
 use request::boot_source::parse_put_boot_source;
-use request::drive::{parse_patch_drive, parse_put_drive};
+use request::drive::{parse_get_drive, parse_patch_drive, parse_put_drive};
 use request::instance_info::parse_get_instance_info;
 use request::logger::parse_put_logger;
 use request::machine_configuration::{
@@ -15,11 +15,12 @@ use request::machine_configuration::{
 };
 use request::metrics::parse_put_metrics;
 use request::mmds::{parse_get_mmds, parse_patch_mmds, parse_put_mmds};
-use request::net::{parse_patch_net, parse_put_net};
+use request::net::{parse_get_net, parse_patch_net, parse_put_net};
+use request::rate_limiter_group::parse_put_rate_limiter_group;
 use request::snapshot::parse_patch_vm_state;
 #[cfg(target_arch = "x86_64")]
 use request::snapshot::parse_put_snapshot;
-use request::vsock::parse_put_vsock;
+use request::vsock::{parse_get_vsock, parse_put_vsock};
 use ApiServer;
 
 use vmm::rpc_interface::{VmmAction, VmmActionError};
@@ -27,9 +28,11 @@ use vmm::rpc_interface::{VmmAction, VmmActionError};
 #[allow(clippy::large_enum_variant)]
 pub enum ParsedRequest {
     GetInstanceInfo,
-    GetMMDS,
-    PatchMMDS(Value),
-    PutMMDS(Value),
+    /// Requests on the MMDS contents, which target the instance identified by the ID, or the
+    /// default instance when the ID is missing.
+    GetMMDS(Option<String>),
+    PatchMMDS(Option<String>, Value),
+    PutMMDS(Option<String>, Value),
     Sync(VmmAction),
 }
 
@@ -48,36 +51,56 @@ impl ParsedRequest {
             path_tokens[0]
         };
 
-        match (request.method(), path, request.body.as_ref()) {
+        // `HEAD` requests are routed like `GET` requests, and the server removes the body
+        // of their responses.
+        let method = match request.method() {
+            Method::Head => Method::Get,
+            method => method,
+        };
+
+        match (method, path, request.body.as_ref()) {
             (Method::Get, "", None) => parse_get_instance_info(),
+            (Method::Get, "drives", None) => parse_get_drive(path_tokens.get(1)),
             (Method::Get, "machine-config", None) => parse_get_machine_config(),
-            (Method::Get, "mmds", None) => parse_get_mmds(),
-            (Method::Get, _, Some(_)) => method_to_error(Method::Get),
+            (Method::Get, "mmds", None) => parse_get_mmds(path_tokens.get(1), path_tokens.get(2)),
+            (Method::Get, "network-interfaces", None) => parse_get_net(path_tokens.get(1)),
+            (Method::Get, "vsock", None) => parse_get_vsock(path_tokens.get(1)),
+            (Method::Get, _, Some(_)) => method_to_error(request.method()),
             (Method::Put, "actions", Some(body)) => parse_put_actions(body),
             (Method::Put, "boot-source", Some(body)) => parse_put_boot_source(body),
             (Method::Put, "drives", Some(body)) => parse_put_drive(body, path_tokens.get(1)),
             (Method::Put, "logger", Some(body)) => parse_put_logger(body),
             (Method::Put, "machine-config", Some(body)) => parse_put_machine_config(body),
             (Method::Put, "metrics", Some(body)) => parse_put_metrics(body),
-            (Method::Put, "mmds", Some(body)) => parse_put_mmds(body, path_tokens.get(1)),
+            (Method::Put, "mmds", Some(body)) => {
+                parse_put_mmds(body, path_tokens.get(1), path_tokens.get(2))
+            }
             (Method::Put, "network-interfaces", Some(body)) => {
                 parse_put_net(body, path_tokens.get(1))
             }
+            (Method::Put, "rate-limiter-groups", Some(body)) => {
+                parse_put_rate_limiter_group(body, path_tokens.get(1))
+            }
             #[cfg(target_arch = "x86_64")]
             (Method::Put, "snapshot", Some(body)) => parse_put_snapshot(body, path_tokens.get(1)),
-            (Method::Put, "vsock", Some(body)) => parse_put_vsock(body),
+            (Method::Put, "vsock", Some(body)) => {
+                parse_put_vsock(body, path_tokens.get(1), path_tokens.get(2))
+            }
             (Method::Put, _, None) => method_to_error(Method::Put),
             (Method::Patch, "drives", Some(body)) => parse_patch_drive(body, path_tokens.get(1)),
             (Method::Patch, "machine-config", Some(body)) => parse_patch_machine_config(body),
-            (Method::Patch, "mmds", Some(body)) => parse_patch_mmds(body),
+            (Method::Patch, "mmds", Some(body)) => {
+                parse_patch_mmds(body, path_tokens.get(1), path_tokens.get(2))
+            }
             (Method::Patch, "network-interfaces", Some(body)) => {
                 parse_patch_net(body, path_tokens.get(1))
             }
             (Method::Patch, "vm", Some(body)) => parse_patch_vm_state(body),
             (Method::Patch, _, None) => method_to_error(Method::Patch),
-            (method, unknown_uri, _) => {
-                Err(Error::InvalidPathMethod(unknown_uri.to_string(), method))
-            }
+            (_, unknown_uri, _) => Err(Error::InvalidPathMethod(
+                unknown_uri.to_string(),
+                request.method(),
+            )),
         }
     }
 
@@ -86,6 +109,14 @@ impl ParsedRequest {
     ) -> Response {
         match request_outcome {
             Ok(vmm_data) => match vmm_data {
+                VmmData::BlockDeviceStats(stats) => {
+                    info!("The request was executed successfully. Status code: 200 OK.");
+                    let mut response = Response::new(Version::Http11, StatusCode::OK);
+                    response.set_body(Body::new(
+                        serde_json::to_string(&stats).expect("Serialization failed"),
+                    ));
+                    response
+                }
                 VmmData::Empty => {
                     info!("The request was executed successfully. Status code: 204 No Content.");
                     Response::new(Version::Http11, StatusCode::NoContent)
@@ -96,6 +127,22 @@ impl ParsedRequest {
                     response.set_body(Body::new(vm_config.to_string()));
                     response
                 }
+                VmmData::NetworkInterfaceStats(stats) => {
+                    info!("The request was executed successfully. Status code: 200 OK.");
+                    let mut response = Response::new(Version::Http11, StatusCode::OK);
+                    response.set_body(Body::new(
+                        serde_json::to_string(&stats).expect("Serialization failed"),
+                    ));
+                    response
+                }
+                VmmData::VsockConnections(connections) => {
+                    info!("The request was executed successfully. Status code: 200 OK.");
+                    let mut response = Response::new(Version::Http11, StatusCode::OK);
+                    response.set_body(Body::new(
+                        serde_json::to_string(&connections).expect("Serialization failed"),
+                    ));
+                    response
+                }
                 VmmData::NotFound => {
                     info!("The request was executed successfully, but there is not an implementation \
                      for it at this moment. Status code: 501 Not Implemented.");
@@ -133,9 +180,10 @@ fn log_received_api_request(api_description: String) {
 /// * `path` - path of the API request
 /// * `body` - body of the API request
 fn describe(method: Method, path: &str, body: Option<&Body>) -> String {
-    match (path, body) {
-        ("/mmds", Some(_)) | (_, None) => format!("{:?} request on {:?}", method, path),
-        (_, Some(value)) => format!(
+    // The MMDS contents are not logged.
+    let is_mmds_contents = path == "/mmds" || path.starts_with("/mmds/instances/");
+    match body {
+        Some(value) if !is_mmds_contents => format!(
             "{:?} request on {:?} with body {:?}",
             method,
             path,
@@ -143,6 +191,7 @@ fn describe(method: Method, path: &str, body: Option<&Body>) -> String {
                 .unwrap_or("inconvertible to UTF-8")
                 .to_string()
         ),
+        _ => format!("{:?} request on {:?}", method, path),
     }
 }
 
@@ -161,6 +210,18 @@ pub fn method_to_error(method: Method) -> Result<ParsedRequest, Error> {
             StatusCode::BadRequest,
             "Empty PATCH request.".to_string(),
         )),
+        Method::Delete => Err(Error::Generic(
+            StatusCode::BadRequest,
+            "Empty DELETE request.".to_string(),
+        )),
+        Method::Post => Err(Error::Generic(
+            StatusCode::BadRequest,
+            "Empty POST request.".to_string(),
+        )),
+        Method::Head => Err(Error::Generic(
+            StatusCode::BadRequest,
+            "HEAD request cannot have a body.".to_string(),
+        )),
     }
 }
 
@@ -240,6 +301,7 @@ mod tests {
     use std::str::FromStr;
 
     use micro_http::HttpConnection;
+    use serde_json::Map;
     use vmm::builder::StartMicrovmError;
     use vmm::rpc_interface::VmmActionError;
     use vmm::vmm_config::machine_config::VmConfig;
@@ -251,13 +313,17 @@ mod tests {
                     sync_req == other_sync_req
                 }
                 (&ParsedRequest::GetInstanceInfo, &ParsedRequest::GetInstanceInfo) => true,
-                (&ParsedRequest::GetMMDS, &ParsedRequest::GetMMDS) => true,
-                (&ParsedRequest::PutMMDS(ref val), &ParsedRequest::PutMMDS(ref other_val)) => {
-                    val == other_val
-                }
-                (&ParsedRequest::PatchMMDS(ref val), &ParsedRequest::PatchMMDS(ref other_val)) => {
-                    val == other_val
+                (&ParsedRequest::GetMMDS(ref id), &ParsedRequest::GetMMDS(ref other_id)) => {
+                    id == other_id
                 }
+                (
+                    &ParsedRequest::PutMMDS(ref id, ref val),
+                    &ParsedRequest::PutMMDS(ref other_id, ref other_val),
+                ) => id == other_id && val == other_val,
+                (
+                    &ParsedRequest::PatchMMDS(ref id, ref val),
+                    &ParsedRequest::PatchMMDS(ref other_id, ref other_val),
+                ) => id == other_id && val == other_val,
                 _ => false,
             }
         }
@@ -300,6 +366,29 @@ mod tests {
         };
     }
 
+    #[test]
+    fn test_invalid_head() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        sender
+            .write_all(
+                b"HEAD /mmds HTTP/1.1\r\n\
+                Content-Type: text/plain\r\n\
+                Content-Length: 4\r\n\r\nbody",
+            )
+            .unwrap();
+        assert!(connection.try_read().is_ok());
+        let req = connection.pop_parsed_request().unwrap();
+        match ParsedRequest::try_from_request(&req) {
+            Err(Error::Generic(StatusCode::BadRequest, err_msg)) => {
+                if err_msg != "HEAD request cannot have a body." {
+                    panic!("HEAD request with body.");
+                }
+            }
+            _ => panic!("HEAD request with body."),
+        };
+    }
+
     #[test]
     fn test_invalid_put() {
         let (mut sender, receiver) = UnixStream::pair().unwrap();
@@ -452,6 +541,18 @@ mod tests {
             describe(Method::Put, "path", Some(&Body::new("body"))),
             "Put request on \"path\" with body \"body\""
         );
+        assert_eq!(
+            describe(Method::Put, "/mmds", Some(&Body::new("body"))),
+            "Put request on \"/mmds\""
+        );
+        assert_eq!(
+            describe(
+                Method::Patch,
+                "/mmds/instances/id",
+                Some(&Body::new("body"))
+            ),
+            "Patch request on \"/mmds/instances/id\""
+        );
     }
 
     #[test]
@@ -521,6 +622,25 @@ mod tests {
         assert!(ParsedRequest::try_from_request(&req).is_ok());
     }
 
+    #[test]
+    fn test_try_from_head() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        sender.write_all(b"HEAD / HTTP/1.1\r\n\r\n").unwrap();
+        assert!(connection.try_read().is_ok());
+        let req = connection.pop_parsed_request().unwrap();
+        assert!(ParsedRequest::try_from_request(&req).is_ok());
+
+        // There are no resources which can be deleted or posted to.
+        sender.write_all(b"DELETE /mmds HTTP/1.1\r\n\r\n").unwrap();
+        assert!(connection.try_read().is_ok());
+        let req = connection.pop_parsed_request().unwrap();
+        match ParsedRequest::try_from_request(&req) {
+            Err(Error::InvalidPathMethod(path, Method::Delete)) => assert_eq!(path, "mmds"),
+            _ => panic!("Test failed."),
+        }
+    }
+
     #[test]
     fn test_try_from_get_machine_config() {
         let (mut sender, receiver) = UnixStream::pair().unwrap();
@@ -533,6 +653,42 @@ mod tests {
         assert!(ParsedRequest::try_from_request(&req).is_ok());
     }
 
+    #[test]
+    fn test_try_from_get_drive() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        sender
+            .write_all(b"GET /drives/string HTTP/1.1\r\n\r\n")
+            .unwrap();
+        assert!(connection.try_read().is_ok());
+        let req = connection.pop_parsed_request().unwrap();
+        assert!(ParsedRequest::try_from_request(&req).is_ok());
+    }
+
+    #[test]
+    fn test_try_from_get_net() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        sender
+            .write_all(b"GET /network-interfaces/string HTTP/1.1\r\n\r\n")
+            .unwrap();
+        assert!(connection.try_read().is_ok());
+        let req = connection.pop_parsed_request().unwrap();
+        assert!(ParsedRequest::try_from_request(&req).is_ok());
+    }
+
+    #[test]
+    fn test_try_from_get_vsock_connections() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        sender
+            .write_all(b"GET /vsock/connections HTTP/1.1\r\n\r\n")
+            .unwrap();
+        assert!(connection.try_read().is_ok());
+        let req = connection.pop_parsed_request().unwrap();
+        assert!(ParsedRequest::try_from_request(&req).is_ok());
+    }
+
     #[test]
     fn test_try_from_get_mmds() {
         let (mut sender, receiver) = UnixStream::pair().unwrap();
@@ -540,7 +696,17 @@ mod tests {
         sender.write_all(b"GET /mmds HTTP/1.1\r\n\r\n").unwrap();
         assert!(connection.try_read().is_ok());
         let req = connection.pop_parsed_request().unwrap();
-        assert!(ParsedRequest::try_from_request(&req).is_ok());
+        assert!(ParsedRequest::try_from_request(&req).unwrap() == ParsedRequest::GetMMDS(None));
+
+        sender
+            .write_all(b"GET /mmds/instances/mmds_1 HTTP/1.1\r\n\r\n")
+            .unwrap();
+        assert!(connection.try_read().is_ok());
+        let req = connection.pop_parsed_request().unwrap();
+        assert!(
+            ParsedRequest::try_from_request(&req).unwrap()
+                == ParsedRequest::GetMMDS(Some("mmds_1".to_string()))
+        );
     }
 
     #[test]
@@ -697,6 +863,20 @@ mod tests {
         assert!(connection.try_read().is_ok());
         let req = connection.pop_parsed_request().unwrap();
         assert!(ParsedRequest::try_from_request(&req).is_ok());
+
+        sender
+            .write_all(
+                b"PUT /mmds/instances/mmds_1 HTTP/1.1\r\n\
+                Content-Type: application/json\r\n\
+                Content-Length: 2\r\n\r\n{}",
+            )
+            .unwrap();
+        assert!(connection.try_read().is_ok());
+        let req = connection.pop_parsed_request().unwrap();
+        assert!(
+            ParsedRequest::try_from_request(&req).unwrap()
+                == ParsedRequest::PutMMDS(Some("mmds_1".to_string()), Value::Object(Map::new()))
+        );
     }
 
     #[test]
@@ -801,6 +981,29 @@ mod tests {
         assert!(ParsedRequest::try_from_request(&req).is_ok());
     }
 
+    #[test]
+    fn test_try_from_put_rate_limiter_group() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        sender
+            .write_all(
+                b"PUT /rate-limiter-groups/string HTTP/1.1\r\n\
+                Content-Type: application/json\r\n\
+                Content-Length: 85\r\n\r\n{ \
+                \"group_id\": \"string\", \
+                \"ops\": { \
+                    \"size\": 0, \
+                    \"one_time_burst\": 0, \
+                    \"refill_time\": 0 \
+                } \
+            }",
+            )
+            .unwrap();
+        assert!(connection.try_read().is_ok());
+        let req = connection.pop_parsed_request().unwrap();
+        assert!(ParsedRequest::try_from_request(&req).is_ok());
+    }
+
     #[test]
     fn test_try_from_put_vsock() {
         let (mut sender, receiver) = UnixStream::pair().unwrap();
@@ -821,6 +1024,25 @@ mod tests {
         assert!(ParsedRequest::try_from_request(&req).is_ok());
     }
 
+    #[test]
+    fn test_try_from_put_vsock_connection_reset() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        let mut connection = HttpConnection::new(receiver);
+        sender
+            .write_all(
+                b"PUT /vsock/connections/reset HTTP/1.1\r\n\
+                Content-Type: application/json\r\n\
+                Content-Length: 39\r\n\r\n{ \
+                \"local_port\": 1024, \
+                \"peer_port\": 52 \
+            }",
+            )
+            .unwrap();
+        assert!(connection.try_read().is_ok());
+        let req = connection.pop_parsed_request().unwrap();
+        assert!(ParsedRequest::try_from_request(&req).is_ok());
+    }
+
     #[test]
     fn test_try_from_patch_drives() {
         let (mut sender, receiver) = UnixStream::pair().unwrap();
@@ -875,6 +1097,20 @@ mod tests {
         assert!(connection.try_read().is_ok());
         let req = connection.pop_parsed_request().unwrap();
         assert!(ParsedRequest::try_from_request(&req).is_ok());
+
+        sender
+            .write_all(
+                b"PATCH /mmds/instances/mmds_1 HTTP/1.1\r\n\
+                Content-Type: application/json\r\n\
+                Content-Length: 2\r\n\r\n{}",
+            )
+            .unwrap();
+        assert!(connection.try_read().is_ok());
+        let req = connection.pop_parsed_request().unwrap();
+        assert!(
+            ParsedRequest::try_from_request(&req).unwrap()
+                == ParsedRequest::PatchMMDS(Some("mmds_1".to_string()), Value::Object(Map::new()))
+        );
     }
 
     #[test]
@@ -15,6 +15,7 @@ enum ActionType {
     FlushMetrics,
     InstanceStart,
     SendCtrlAltDel,
+    VsockTransportReset,
 }
 
 // The model of the json body from a sync request. We use Serde to transform each associated
@@ -46,6 +47,7 @@ pub fn parse_put_actions(body: &Body) -> Result<ParsedRequest, Error> {
             #[cfg(target_arch = "x86_64")]
             Ok(ParsedRequest::Sync(VmmAction::SendCtrlAltDel))
         }
+        ActionType::VsockTransportReset => Ok(ParsedRequest::Sync(VmmAction::ResetVsockTransport)),
     }
 }
 
@@ -100,5 +102,16 @@ mod tests {
             assert!(result.is_ok());
             assert!(result.unwrap().eq(&req));
         }
+
+        {
+            let json = r#"{
+                "action_type": "VsockTransportReset"
+            }"#;
+
+            let req: ParsedRequest = ParsedRequest::Sync(VmmAction::ResetVsockTransport);
+            let result = parse_put_actions(&Body::new(json));
+            assert!(result.is_ok());
+            assert!(result.unwrap().eq(&req));
+        }
     }
 }
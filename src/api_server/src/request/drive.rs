@@ -75,6 +75,20 @@ impl PatchDrivePayload {
     }
 }
 
+pub fn parse_get_drive(id_from_path: Option<&&str>) -> Result<ParsedRequest, Error> {
+    METRICS.get_api_requests.drive_count.inc();
+    let id = if let Some(id) = id_from_path {
+        checked_id(id)?
+    } else {
+        METRICS.get_api_requests.drive_fails.inc();
+        return Err(Error::EmptyID);
+    };
+
+    Ok(ParsedRequest::Sync(VmmAction::GetBlockDeviceStats(
+        id.to_string(),
+    )))
+}
+
 pub fn parse_put_drive(body: &Body, id_from_path: Option<&&str>) -> Result<ParsedRequest, Error> {
     METRICS.put_api_requests.drive_count.inc();
     let id = if let Some(id) = id_from_path {
@@ -140,6 +154,19 @@ pub fn parse_patch_drive(body: &Body, id_from_path: Option<&&str>) -> Result<Par
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_parse_get_drive_request() {
+        // The `id_from_path` cannot be None.
+        assert!(parse_get_drive(None).is_err());
+        // The id must be valid.
+        assert!(parse_get_drive(Some(&"foo/bar")).is_err());
+
+        match parse_get_drive(Some(&"foo")) {
+            Ok(ParsedRequest::Sync(VmmAction::GetBlockDeviceStats(id))) => assert_eq!(id, "foo"),
+            _ => panic!("Test failed."),
+        }
+    }
+
     #[test]
     fn test_parse_patch_drive_request() {
         assert!(parse_patch_drive(&Body::new("invalid_payload"), None).is_err());
@@ -152,6 +179,16 @@ mod tests {
               }"#;
         assert!(parse_patch_drive(&Body::new(body), Some(&"2")).is_err());
 
+        // PATCH with a rate limiter group, which can only be set when the drive is created.
+        let body = r#"{
+                "drive_id": "bar",
+                "path_on_host": "dummy",
+                "rate_limiter": {
+                    "group": "foo"
+                }
+              }"#;
+        assert!(parse_patch_drive(&Body::new(body), Some(&"bar")).is_err());
+
         // PATCH with invalid types on fields. Adding a drive_id as number instead of string.
         let body = r#"{
                 "drive_id": 1000,
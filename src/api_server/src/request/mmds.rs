@@ -2,36 +2,70 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use micro_http::StatusCode;
-use request::{Body, Error, ParsedRequest};
+use request::{checked_id, Body, Error, ParsedRequest};
 use vmm::rpc_interface::VmmAction::SetMmdsConfiguration;
 use vmm::vmm_config::mmds::MmdsConfig;
+use Method;
 
-pub fn parse_get_mmds() -> Result<ParsedRequest, Error> {
-    Ok(ParsedRequest::GetMMDS)
+// Parses the path of a request on the MMDS contents, which is either `/mmds` for the default
+// instance or `/mmds/instances/{id}` for the instance identified by `id`.
+fn parse_instance_id(
+    resource_from_path: Option<&&str>,
+    id_from_path: Option<&&str>,
+    method: Method,
+) -> Result<Option<String>, Error> {
+    match (resource_from_path, id_from_path) {
+        (None, None) => Ok(None),
+        (Some(&"instances"), Some(id)) => Ok(Some(checked_id(id)?.to_string())),
+        (Some(&"instances"), None) => Err(Error::EmptyID),
+        (resource, id) => {
+            let path: Vec<&str> = resource.into_iter().chain(id).cloned().collect();
+            Err(Error::InvalidPathMethod(
+                format!("/mmds/{}", path.join("/")),
+                method,
+            ))
+        }
+    }
+}
+
+pub fn parse_get_mmds(
+    resource_from_path: Option<&&str>,
+    id_from_path: Option<&&str>,
+) -> Result<ParsedRequest, Error> {
+    Ok(ParsedRequest::GetMMDS(parse_instance_id(
+        resource_from_path,
+        id_from_path,
+        Method::Get,
+    )?))
 }
 
 pub fn parse_put_mmds(
     body: &Body,
     path_second_token: Option<&&str>,
+    path_third_token: Option<&&str>,
 ) -> Result<ParsedRequest, Error> {
     match path_second_token {
-        Some(config_path) => match *config_path {
-            "config" => Ok(ParsedRequest::Sync(SetMmdsConfiguration(
-                serde_json::from_slice::<MmdsConfig>(body.raw()).map_err(Error::SerdeJson)?,
-            ))),
-            _ => Err(Error::Generic(
-                StatusCode::BadRequest,
-                format!("Unrecognized PUT request path `{}`.", *config_path),
-            )),
-        },
-        None => Ok(ParsedRequest::PutMMDS(
+        Some(&"config") => Ok(ParsedRequest::Sync(SetMmdsConfiguration(
+            serde_json::from_slice::<MmdsConfig>(body.raw()).map_err(Error::SerdeJson)?,
+        ))),
+        Some(&"instances") | None => Ok(ParsedRequest::PutMMDS(
+            parse_instance_id(path_second_token, path_third_token, Method::Put)?,
             serde_json::from_slice(body.raw()).map_err(Error::SerdeJson)?,
         )),
+        Some(unknown_path) => Err(Error::Generic(
+            StatusCode::BadRequest,
+            format!("Unrecognized PUT request path `{}`.", *unknown_path),
+        )),
     }
 }
 
-pub fn parse_patch_mmds(body: &Body) -> Result<ParsedRequest, Error> {
+pub fn parse_patch_mmds(
+    body: &Body,
+    resource_from_path: Option<&&str>,
+    id_from_path: Option<&&str>,
+) -> Result<ParsedRequest, Error> {
     Ok(ParsedRequest::PatchMMDS(
+        parse_instance_id(resource_from_path, id_from_path, Method::Patch)?,
         serde_json::from_slice(body.raw()).map_err(Error::SerdeJson)?,
     ))
 }
@@ -40,9 +74,21 @@ pub fn parse_patch_mmds(body: &Body) -> Result<ParsedRequest, Error> {
 mod tests {
     use super::*;
 
+    use serde_json::Value;
+
     #[test]
     fn test_parse_get_mmds_request() {
-        assert!(parse_get_mmds().is_ok());
+        match parse_get_mmds(None, None) {
+            Ok(ParsedRequest::GetMMDS(None)) => {}
+            _ => panic!("Test failed."),
+        }
+        match parse_get_mmds(Some(&"instances"), Some(&"mmds_1")) {
+            Ok(ParsedRequest::GetMMDS(Some(id))) => assert_eq!(id, "mmds_1"),
+            _ => panic!("Test failed."),
+        }
+        assert!(parse_get_mmds(Some(&"instances"), None).is_err());
+        assert!(parse_get_mmds(Some(&"instances"), Some(&"mmds!")).is_err());
+        assert!(parse_get_mmds(Some(&"config"), None).is_err());
     }
 
     #[test]
@@ -50,31 +96,82 @@ mod tests {
         let body = r#"{
                 "foo": "bar"
               }"#;
-        assert!(parse_put_mmds(&Body::new(body), None).is_ok());
+        assert!(parse_put_mmds(&Body::new(body), None, None).is_ok());
         let invalid_body = "invalid_body";
-        assert!(parse_put_mmds(&Body::new(invalid_body), None).is_err());
+        assert!(parse_put_mmds(&Body::new(invalid_body), None, None).is_err());
 
         let body = r#"{
                 "ipv4_address": "169.254.170.2"
               }"#;
         let path = "config";
-        assert!(parse_put_mmds(&Body::new(body), Some(&path)).is_ok());
+        assert!(parse_put_mmds(&Body::new(body), Some(&path), None).is_ok());
+
+        let body = r#"{
+                "ipv4_address": "169.254.170.2",
+                "ipv6_address": "fd00:ec2::254"
+              }"#;
+        assert!(parse_put_mmds(&Body::new(body), Some(&path), None).is_ok());
+
+        let body = r#"{
+                "ipv4_address": "169.254.170.2",
+                "version": "V2"
+              }"#;
+        assert!(parse_put_mmds(&Body::new(body), Some(&path), None).is_ok());
+
+        let body = r#"{
+                "version": "V3"
+              }"#;
+        assert!(parse_put_mmds(&Body::new(body), Some(&path), None).is_err());
+
+        let body = r#"{
+                "access_policies": {
+                    "/operator": "Hidden",
+                    "/latest/meta-data/credentials": "TokenRequired"
+                }
+              }"#;
+        assert!(parse_put_mmds(&Body::new(body), Some(&path), None).is_ok());
+
+        let body = r#"{
+                "access_policies": {
+                    "/operator": "Public"
+                }
+              }"#;
+        assert!(parse_put_mmds(&Body::new(body), Some(&path), None).is_err());
+
+        let body = r#"{
+                "ipv6_address": "169.254.170.2"
+              }"#;
+        assert!(parse_put_mmds(&Body::new(body), Some(&path), None).is_err());
 
         let body = r#"{
                 "ipv4_address": ""
               }"#;
-        assert!(parse_put_mmds(&Body::new(body), Some(&path)).is_err());
+        assert!(parse_put_mmds(&Body::new(body), Some(&path), None).is_err());
 
         // Equivalent to reset the mmds configuration.
         let empty_body = r#"{}"#;
-        assert!(parse_put_mmds(&Body::new(empty_body), Some(&path)).is_ok());
+        assert!(parse_put_mmds(&Body::new(empty_body), Some(&path), None).is_ok());
 
         let invalid_config_body = r#"{
                 "invalid_config": "invalid_value"
               }"#;
-        assert!(parse_put_mmds(&Body::new(invalid_config_body), Some(&path)).is_err());
-        assert!(parse_put_mmds(&Body::new(body), Some(&"invalid_path")).is_err());
-        assert!(parse_put_mmds(&Body::new(invalid_body), Some(&path)).is_err());
+        assert!(parse_put_mmds(&Body::new(invalid_config_body), Some(&path), None).is_err());
+        assert!(parse_put_mmds(&Body::new(body), Some(&"invalid_path"), None).is_err());
+        assert!(parse_put_mmds(&Body::new(invalid_body), Some(&path), None).is_err());
+
+        let body = r#"{
+                "foo": "bar"
+              }"#;
+        let instances = "instances";
+        match parse_put_mmds(&Body::new(body), Some(&instances), Some(&"mmds_1")) {
+            Ok(ParsedRequest::PutMMDS(Some(id), value)) => {
+                assert_eq!(id, "mmds_1");
+                assert_eq!(value, serde_json::from_str::<Value>(body).unwrap());
+            }
+            _ => panic!("Test failed."),
+        }
+        assert!(parse_put_mmds(&Body::new(body), Some(&instances), None).is_err());
+        assert!(parse_put_mmds(&Body::new(body), Some(&instances), Some(&"")).is_err());
     }
 
     #[test]
@@ -82,7 +179,16 @@ mod tests {
         let body = r#"{
                 "foo": "bar"
               }"#;
-        assert!(parse_patch_mmds(&Body::new(body)).is_ok());
-        assert!(parse_patch_mmds(&Body::new("invalid_body")).is_err());
+        assert!(parse_patch_mmds(&Body::new(body), None, None).is_ok());
+        assert!(parse_patch_mmds(&Body::new("invalid_body"), None, None).is_err());
+        match parse_patch_mmds(&Body::new(body), Some(&"instances"), Some(&"mmds_1")) {
+            Ok(ParsedRequest::PatchMMDS(Some(id), value)) => {
+                assert_eq!(id, "mmds_1");
+                assert_eq!(value, serde_json::from_str::<Value>(body).unwrap());
+            }
+            _ => panic!("Test failed."),
+        }
+        assert!(parse_patch_mmds(&Body::new(body), Some(&"instances"), None).is_err());
+        assert!(parse_patch_mmds(&Body::new(body), Some(&"unknown"), None).is_err());
     }
 }
@@ -10,6 +10,7 @@ pub mod machine_configuration;
 pub mod metrics;
 pub mod mmds;
 pub mod net;
+pub mod rate_limiter_group;
 pub mod snapshot;
 pub mod vsock;
 pub use micro_http::{
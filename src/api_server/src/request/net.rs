@@ -6,6 +6,20 @@ use logger::{Metric, METRICS};
 use request::{checked_id, Body, Error, ParsedRequest, StatusCode};
 use vmm::vmm_config::net::{NetworkInterfaceConfig, NetworkInterfaceUpdateConfig};
 
+pub fn parse_get_net(id_from_path: Option<&&str>) -> Result<ParsedRequest, Error> {
+    METRICS.get_api_requests.network_count.inc();
+    let id = if let Some(id) = id_from_path {
+        checked_id(id)?
+    } else {
+        METRICS.get_api_requests.network_fails.inc();
+        return Err(Error::EmptyID);
+    };
+
+    Ok(ParsedRequest::Sync(VmmAction::GetNetworkInterfaceStats(
+        id.to_string(),
+    )))
+}
+
 pub fn parse_put_net(body: &Body, id_from_path: Option<&&str>) -> Result<ParsedRequest, Error> {
     METRICS.put_api_requests.network_count.inc();
     let id = if let Some(id) = id_from_path {
@@ -50,6 +64,19 @@ pub fn parse_patch_net(body: &Body, id_from_path: Option<&&str>) -> Result<Parse
             "The id from the path does not match the id from the body!".to_string(),
         ));
     }
+    // A rate limiter can only join a group when its device is created.
+    if netif
+        .rx_rate_limiter
+        .iter()
+        .chain(netif.tx_rate_limiter.iter())
+        .any(|rate_limiter| rate_limiter.group.is_some())
+    {
+        METRICS.patch_api_requests.network_fails.inc();
+        return Err(Error::Generic(
+            StatusCode::BadRequest,
+            "The rate limiter group of a network interface cannot be updated.".to_string(),
+        ));
+    }
     Ok(ParsedRequest::Sync(VmmAction::UpdateNetworkInterface(
         netif,
     )))
@@ -60,6 +87,22 @@ mod tests {
     use serde_json;
 
     use super::*;
+    use vmm::vmm_config::net::LinkState;
+
+    #[test]
+    fn test_parse_get_net_request() {
+        // The `id_from_path` cannot be None.
+        assert!(parse_get_net(None).is_err());
+        // The id must be valid.
+        assert!(parse_get_net(Some(&"foo/bar")).is_err());
+
+        match parse_get_net(Some(&"foo")) {
+            Ok(ParsedRequest::Sync(VmmAction::GetNetworkInterfaceStats(id))) => {
+                assert_eq!(id, "foo")
+            }
+            _ => panic!("Test failed."),
+        }
+    }
 
     #[test]
     fn test_parse_put_net_request() {
@@ -145,5 +188,50 @@ mod tests {
             }
         }"#;
         assert!(parse_patch_net(&Body::new(body), Some(&"foo")).is_err());
+
+        // 5. Link state update.
+        let body = r#"{
+                "iface_id": "foo",
+                "link_state": "Down"
+        }"#;
+        match parse_patch_net(&Body::new(body), Some(&"foo")) {
+            Ok(ParsedRequest::Sync(VmmAction::UpdateNetworkInterface(netif))) => {
+                assert_eq!(netif.link_state, Some(LinkState::Down))
+            }
+            _ => panic!("Test failed."),
+        }
+
+        // 6. Serde error for invalid link state.
+        let body = r#"{
+                "iface_id": "foo",
+                "link_state": "Unplugged"
+        }"#;
+        assert!(parse_patch_net(&Body::new(body), Some(&"foo")).is_err());
+
+        // 7. The rate limiter groups cannot be updated.
+        let body = r#"{
+                "iface_id": "foo",
+                "rx_rate_limiter": {
+                    "group": "bar"
+                }
+        }"#;
+        match parse_patch_net(&Body::new(body), Some(&"foo")) {
+            Err(Error::Generic(StatusCode::BadRequest, _)) => (),
+            _ => panic!("Test failed."),
+        }
+        let body = r#"{
+                "iface_id": "foo",
+                "tx_rate_limiter": {
+                    "bandwidth": {
+                        "size": 62500,
+                        "refill_time": 1000
+                    },
+                    "group": "bar"
+                }
+        }"#;
+        match parse_patch_net(&Body::new(body), Some(&"foo")) {
+            Err(Error::Generic(StatusCode::BadRequest, _)) => (),
+            _ => panic!("Test failed."),
+        }
     }
 }
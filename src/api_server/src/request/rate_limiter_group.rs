@@ -0,0 +1,79 @@
+// Copyright 2020 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use super::super::VmmAction;
+use logger::{Metric, METRICS};
+use request::{checked_id, Body, Error, ParsedRequest, StatusCode};
+use vmm::vmm_config::rate_limiter_group::RateLimiterGroupConfig;
+
+pub fn parse_put_rate_limiter_group(
+    body: &Body,
+    id_from_path: Option<&&str>,
+) -> Result<ParsedRequest, Error> {
+    METRICS.put_api_requests.rate_limiter_group_count.inc();
+    let id = if let Some(id) = id_from_path {
+        checked_id(id)?
+    } else {
+        METRICS.put_api_requests.rate_limiter_group_fails.inc();
+        return Err(Error::EmptyID);
+    };
+
+    let group_cfg = serde_json::from_slice::<RateLimiterGroupConfig>(body.raw()).map_err(|e| {
+        METRICS.put_api_requests.rate_limiter_group_fails.inc();
+        Error::SerdeJson(e)
+    })?;
+
+    if id != group_cfg.group_id {
+        METRICS.put_api_requests.rate_limiter_group_fails.inc();
+        Err(Error::Generic(
+            StatusCode::BadRequest,
+            "The id from the path does not match the id from the body!".to_string(),
+        ))
+    } else {
+        Ok(ParsedRequest::Sync(VmmAction::SetRateLimiterGroup(
+            group_cfg,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use vmm::vmm_config::TokenBucketConfig;
+
+    #[test]
+    fn test_parse_put_rate_limiter_group_request() {
+        let body = r#"{
+                "group_id": "foo",
+                "bandwidth": {
+                    "size": 1000,
+                    "refill_time": 100
+                }
+              }"#;
+        assert!(parse_put_rate_limiter_group(&Body::new(body), None).is_err());
+        assert!(parse_put_rate_limiter_group(&Body::new(body), Some(&"bar")).is_err());
+
+        let expected_cfg = RateLimiterGroupConfig {
+            group_id: "foo".to_string(),
+            bandwidth: Some(TokenBucketConfig {
+                size: 1000,
+                one_time_burst: None,
+                refill_time: 100,
+            }),
+            ops: None,
+        };
+        match parse_put_rate_limiter_group(&Body::new(body), Some(&"foo")) {
+            Ok(ParsedRequest::Sync(VmmAction::SetRateLimiterGroup(cfg))) => {
+                assert_eq!(cfg, expected_cfg)
+            }
+            _ => panic!("Test failed."),
+        }
+
+        let body = r#"{
+                "group_id": "foo",
+                "invalid_field": false
+              }"#;
+        assert!(parse_put_rate_limiter_group(&Body::new(body), Some(&"foo")).is_err());
+    }
+}
@@ -3,18 +3,97 @@
 
 use super::super::VmmAction;
 use request::{Body, Error, ParsedRequest};
-use vmm::vmm_config::vsock::VsockDeviceConfig;
+use vmm::vmm_config::vsock::{VsockConnectionResetParams, VsockDeviceConfig};
+use Method;
 
-pub fn parse_put_vsock(body: &Body) -> Result<ParsedRequest, Error> {
-    Ok(ParsedRequest::Sync(VmmAction::SetVsockDevice(
-        serde_json::from_slice::<VsockDeviceConfig>(body.raw()).map_err(Error::SerdeJson)?,
-    )))
+pub fn parse_get_vsock(resource_from_path: Option<&&str>) -> Result<ParsedRequest, Error> {
+    match resource_from_path {
+        Some(&"connections") => Ok(ParsedRequest::Sync(VmmAction::GetVsockConnections)),
+        Some(&resource) => Err(Error::InvalidPathMethod(
+            format!("/vsock/{}", resource),
+            Method::Get,
+        )),
+        None => Err(Error::InvalidPathMethod("/vsock".to_string(), Method::Get)),
+    }
+}
+
+pub fn parse_put_vsock(
+    body: &Body,
+    resource_from_path: Option<&&str>,
+    action_from_path: Option<&&str>,
+) -> Result<ParsedRequest, Error> {
+    match (resource_from_path, action_from_path) {
+        (None, None) => Ok(ParsedRequest::Sync(VmmAction::SetVsockDevice(
+            serde_json::from_slice::<VsockDeviceConfig>(body.raw()).map_err(Error::SerdeJson)?,
+        ))),
+        (Some(&"connections"), Some(&"reset")) => {
+            Ok(ParsedRequest::Sync(VmmAction::ResetVsockConnection(
+                serde_json::from_slice::<VsockConnectionResetParams>(body.raw())
+                    .map_err(Error::SerdeJson)?,
+            )))
+        }
+        (Some(&resource), None) => Err(Error::InvalidPathMethod(
+            format!("/vsock/{}", resource),
+            Method::Put,
+        )),
+        (resource, Some(&action)) => Err(Error::InvalidPathMethod(
+            format!("/vsock/{}/{}", resource.unwrap_or(&""), action),
+            Method::Put,
+        )),
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_parse_get_vsock_request() {
+        match parse_get_vsock(Some(&"connections")) {
+            Ok(ParsedRequest::Sync(VmmAction::GetVsockConnections)) => (),
+            _ => panic!("Test failed."),
+        }
+        assert!(parse_get_vsock(Some(&"invalid")).is_err());
+        assert!(parse_get_vsock(None).is_err());
+    }
+
+    #[test]
+    fn test_parse_put_vsock_connection_reset_request() {
+        let body = r#"{
+                "local_port": 1024,
+                "peer_port": 52
+              }"#;
+        let expected_params = VsockConnectionResetParams {
+            local_port: 1024,
+            peer_port: 52,
+        };
+        match parse_put_vsock(&Body::new(body), Some(&"connections"), Some(&"reset")) {
+            Ok(ParsedRequest::Sync(VmmAction::ResetVsockConnection(params))) => {
+                assert_eq!(params, expected_params)
+            }
+            _ => panic!("Test failed."),
+        }
+
+        let body = r#"{
+                "local_port": 1024
+              }"#;
+        assert!(parse_put_vsock(&Body::new(body), Some(&"connections"), Some(&"reset")).is_err());
+
+        let body = r#"{
+                "local_port": 1024,
+                "peer_port": 52,
+                "invalid_field": false
+              }"#;
+        assert!(parse_put_vsock(&Body::new(body), Some(&"connections"), Some(&"reset")).is_err());
+
+        let body = r#"{
+                "local_port": 1024,
+                "peer_port": 52
+              }"#;
+        assert!(parse_put_vsock(&Body::new(body), Some(&"connections"), None).is_err());
+        assert!(parse_put_vsock(&Body::new(body), Some(&"connections"), Some(&"kill")).is_err());
+    }
+
     #[test]
     fn test_parse_put_vsock_request() {
         let body = r#"{
@@ -22,13 +101,49 @@ mod tests {
                 "guest_cid": 42,
                 "uds_path": "vsock.sock"
               }"#;
-        assert!(parse_put_vsock(&Body::new(body)).is_ok());
+        assert!(parse_put_vsock(&Body::new(body), None, None).is_ok());
+
+        let body = r#"{
+                "vsock_id": "foo",
+                "guest_cid": 42,
+                "backend": "Vhost"
+              }"#;
+        assert!(parse_put_vsock(&Body::new(body), None, None).is_ok());
+
+        let body = r#"{
+                "vsock_id": "foo",
+                "guest_cid": 42,
+                "backend": "Invalid"
+              }"#;
+        assert!(parse_put_vsock(&Body::new(body), None, None).is_err());
+
+        let body = r#"{
+                "vsock_id": "foo",
+                "guest_cid": 42,
+                "uds_path": "vsock.sock",
+                "guest_port_routes": [
+                    {"port": 52, "uds_path": "agent.sock"},
+                    {"port": 53},
+                    {"port": 54, "tcp_port": 8080}
+                ],
+                "host_allowed_ports": [1024],
+                "mmds_port": 80
+              }"#;
+        assert!(parse_put_vsock(&Body::new(body), None, None).is_ok());
+
+        let body = r#"{
+                "vsock_id": "foo",
+                "guest_cid": 42,
+                "uds_path": "vsock.sock",
+                "guest_port_routes": [{"port": 52, "path": "agent.sock"}]
+              }"#;
+        assert!(parse_put_vsock(&Body::new(body), None, None).is_err());
 
         let body = r#"{
                 "vsock_id": "foo",
                 "guest_cid": 42,
                 "invalid_field": false
               }"#;
-        assert!(parse_put_vsock(&Body::new(body)).is_err());
+        assert!(parse_put_vsock(&Body::new(body), None, None).is_err());
     }
 }
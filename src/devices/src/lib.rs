@@ -12,6 +12,7 @@ extern crate libc;
 extern crate dumbo;
 #[macro_use]
 extern crate logger;
+extern crate mmds;
 extern crate net_gen;
 extern crate polly;
 extern crate rate_limiter;
@@ -19,6 +20,8 @@ extern crate snapshot;
 extern crate versionize;
 extern crate versionize_derive;
 extern crate vm_memory;
+#[macro_use]
+extern crate vmm_sys_util;
 
 use rate_limiter::Error as RateLimiterError;
 use std::io;
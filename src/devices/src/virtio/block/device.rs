@@ -13,10 +13,10 @@ use std::os::linux::fs::MetadataExt;
 use std::path::PathBuf;
 use std::result;
 use std::sync::atomic::{AtomicUsize, Ordering};
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use logger::{Metric, METRICS};
-use rate_limiter::{RateLimiter, TokenType};
+use rate_limiter::{RateLimiter, RateLimiterGroup, TokenType};
 use utils::eventfd::EventFd;
 use virtio_gen::virtio_blk::*;
 use vm_memory::{Bytes, GuestMemoryMmap};
@@ -295,6 +295,16 @@ impl Block {
     pub fn is_root_device(&self) -> bool {
         self.root_device
     }
+
+    /// Provides the rate limiter of this block device.
+    pub fn rate_limiter(&self) -> &RateLimiter {
+        &self.rate_limiter
+    }
+
+    /// Makes the rate limiter of this block device a member of `group`.
+    pub fn set_rate_limiter_group(&mut self, group: Arc<Mutex<RateLimiterGroup>>) {
+        self.rate_limiter.set_group(group);
+    }
 }
 
 impl VirtioDevice for Block {
@@ -410,10 +420,6 @@ pub(crate) mod tests {
         fn set_rate_limiter(&mut self, rl: RateLimiter) {
             self.rate_limiter = rl;
         }
-
-        fn rate_limiter(&mut self) -> &RateLimiter {
-            &self.rate_limiter
-        }
     }
 
     /// Create a default Block instance to be used in tests.
@@ -7,7 +7,8 @@ use std::io;
 use std::sync::atomic::AtomicUsize;
 use std::sync::Arc;
 
-use rate_limiter::{persist::RateLimiterState, RateLimiter};
+use rate_limiter::persist::{RateLimiterState, RestoredGroups};
+use rate_limiter::RateLimiter;
 use snapshot::Persist;
 use versionize::{VersionMap, Versionize, VersionizeResult};
 use versionize_derive::Versionize;
@@ -31,6 +32,8 @@ pub struct BlockState {
 
 pub struct BlockConstructorArgs {
     pub mem: GuestMemoryMmap,
+    /// The rate limiter groups restored along with the other devices.
+    pub rate_limiter_groups: RestoredGroups,
 }
 
 impl Persist<'_> for Block {
@@ -54,7 +57,10 @@ impl Persist<'_> for Block {
         state: &Self::State,
     ) -> Result<Self, Self::Error> {
         let is_disk_read_only = state.virtio_state.avail_features & (1u64 << VIRTIO_BLK_F_RO) != 0;
-        let rate_limiter = RateLimiter::restore((), &state.rate_limiter_state)?;
+        let rate_limiter = RateLimiter::restore(
+            constructor_args.rate_limiter_groups,
+            &state.rate_limiter_state,
+        )?;
 
         let mut block = Block::new(
             state.id.clone(),
@@ -124,6 +130,7 @@ mod tests {
         let restored_block = Block::restore(
             BlockConstructorArgs {
                 mem: guest_mem.clone(),
+                rate_limiter_groups: RestoredGroups::default(),
             },
             &BlockState::deserialize(&mut mem.as_slice(), &version_map, 1).unwrap(),
         )
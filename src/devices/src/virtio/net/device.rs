@@ -7,29 +7,36 @@
 
 use crate::virtio::net::Error;
 use crate::virtio::net::Result;
-use crate::virtio::net::{MAX_BUFFER_SIZE, QUEUE_SIZE, QUEUE_SIZES, RX_INDEX, TX_INDEX};
+use crate::virtio::net::{
+    CTRL_INDEX, MAX_BUFFER_SIZE, QUEUE_SIZE, QUEUE_SIZES, RX_INDEX, TX_INDEX,
+};
 use crate::virtio::{
-    ActivateResult, DeviceState, Queue, VirtioDevice, TYPE_NET, VIRTIO_MMIO_INT_VRING,
+    ActivateResult, DescriptorChain, DeviceState, Queue, VirtioDevice, TYPE_NET,
+    VIRTIO_MMIO_INT_VRING,
 };
 use crate::{report_net_event_fail, Error as DeviceError};
 use dumbo::ns::MmdsNetworkStack;
 use dumbo::{EthernetFrame, MacAddr, MAC_ADDR_LEN};
 use libc::EAGAIN;
-use logger::{Metric, METRICS};
-use rate_limiter::{RateLimiter, TokenBucket, TokenType};
+use logger::{Metric, NetInterfaceMetrics, METRICS};
+use mmds::data_store::Mmds;
+use rate_limiter::{RateLimiter, RateLimiterGroup, TokenBucket, TokenType};
 #[cfg(not(test))]
 use std::io::Read;
 use std::io::Write;
 use std::sync::atomic::AtomicUsize;
 use std::sync::atomic::Ordering;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 use std::{cmp, io, mem, result};
 use utils::eventfd::EventFd;
 use utils::net::Tap;
 use virtio_gen::virtio_net::{
-    virtio_net_hdr_v1, VIRTIO_F_VERSION_1, VIRTIO_NET_F_CSUM, VIRTIO_NET_F_GUEST_CSUM,
-    VIRTIO_NET_F_GUEST_TSO4, VIRTIO_NET_F_GUEST_UFO, VIRTIO_NET_F_HOST_TSO4, VIRTIO_NET_F_HOST_UFO,
-    VIRTIO_NET_F_MAC,
+    virtio_net_hdr_v1, VIRTIO_F_VERSION_1, VIRTIO_NET_CTRL_ANNOUNCE, VIRTIO_NET_CTRL_ANNOUNCE_ACK,
+    VIRTIO_NET_ERR, VIRTIO_NET_F_CSUM, VIRTIO_NET_F_CTRL_VQ, VIRTIO_NET_F_GUEST_ANNOUNCE,
+    VIRTIO_NET_F_GUEST_CSUM, VIRTIO_NET_F_GUEST_TSO4, VIRTIO_NET_F_GUEST_UFO,
+    VIRTIO_NET_F_HOST_TSO4, VIRTIO_NET_F_HOST_UFO, VIRTIO_NET_F_MAC, VIRTIO_NET_F_MRG_RXBUF,
+    VIRTIO_NET_F_MTU, VIRTIO_NET_F_STATUS, VIRTIO_NET_OK, VIRTIO_NET_S_ANNOUNCE,
+    VIRTIO_NET_S_LINK_UP,
 };
 use vm_memory::{ByteValued, Bytes, GuestAddress, GuestMemoryError, GuestMemoryMmap};
 
@@ -56,15 +63,33 @@ fn init_vnet_hdr(buf: &mut [u8]) {
     }
 }
 
+// Sets the number of buffers a received frame is spread over. This is the last field of the
+// VNET hdr, and it is only used when VIRTIO_NET_F_MRG_RXBUF is negotiated.
+fn set_vnet_hdr_num_buffers(buf: &mut [u8], num_buffers: u16) {
+    let offset = vnet_hdr_len() - mem::size_of::<u16>();
+    buf[offset..vnet_hdr_len()].copy_from_slice(&num_buffers.to_le_bytes());
+}
+
 #[derive(Clone, Copy)]
+#[repr(C)]
 pub struct ConfigSpace {
     pub guest_mac: [u8; MAC_ADDR_LEN],
+    // Only valid if VIRTIO_NET_F_STATUS is negotiated: a combination of VIRTIO_NET_S_LINK_UP
+    // and VIRTIO_NET_S_ANNOUNCE. This field is read-only for the driver.
+    pub status: u16,
+    // Only valid if VIRTIO_NET_F_MQ is negotiated, which is never the case.
+    pub max_virtqueue_pairs: u16,
+    // Only valid if VIRTIO_NET_F_MTU is negotiated: the maximum MTU the driver should use.
+    pub mtu: u16,
 }
 
 impl Default for ConfigSpace {
     fn default() -> ConfigSpace {
         ConfigSpace {
             guest_mac: [0; MAC_ADDR_LEN],
+            status: VIRTIO_NET_S_LINK_UP as u16,
+            max_virtqueue_pairs: 0,
+            mtu: 0,
         }
     }
 }
@@ -91,6 +116,8 @@ pub struct Net {
 
     rx_bytes_read: usize,
     rx_frame_buf: [u8; MAX_BUFFER_SIZE],
+    // The head index and written length of the RX chains used by the current frame.
+    rx_used_chains: Vec<(u16, u32)>,
 
     tx_iovec: Vec<(GuestAddress, usize)>,
     tx_frame_buf: [u8; MAX_BUFFER_SIZE],
@@ -105,23 +132,40 @@ pub struct Net {
     pub(crate) activate_evt: EventFd,
 
     pub(crate) mmds_ns: Option<MmdsNetworkStack>,
+    // The ID of the MMDS instance `mmds_ns` is bound to, `None` standing for the default one.
+    pub(crate) mmds_id: Option<String>,
+    // Written to by the MMDS whenever its data store is updated, which may answer the long-poll
+    // requests held by `mmds_ns`.
+    pub(crate) mmds_update_evt: Arc<EventFd>,
+
+    pub(crate) metrics: Arc<NetInterfaceMetrics>,
 
     #[cfg(test)]
     test_mutators: tests::TestMutators,
 }
 
 impl Net {
-    /// Create a new virtio network device with the given TAP interface.
+    /// Create a new virtio network device with the given TAP interface. When `mmds` is provided,
+    /// the guest requests heading to the MMDS are detoured to it.
     pub fn new_with_tap(
         id: String,
         tap_if_name: String,
         guest_mac: Option<&MacAddr>,
         rx_rate_limiter: RateLimiter,
         tx_rate_limiter: RateLimiter,
-        allow_mmds_requests: bool,
+        mmds: Option<Arc<Mutex<Mmds>>>,
+        mtu: Option<u16>,
     ) -> Result<Self> {
         let tap = Tap::open_named(&tap_if_name).map_err(Error::TapOpen)?;
 
+        // Changing the MTU requires privileges, so only do it if the TAP is not already
+        // configured accordingly.
+        if let Some(mtu) = mtu {
+            if tap.mtu().map_err(Error::TapSetMtu)? != mtu {
+                tap.set_mtu(mtu).map_err(Error::TapSetMtu)?;
+            }
+        }
+
         // Set offload flags to match the virtio features below.
         tap.set_offload(
             net_gen::TUN_F_CSUM | net_gen::TUN_F_UFO | net_gen::TUN_F_TSO4 | net_gen::TUN_F_TSO6,
@@ -138,6 +182,9 @@ impl Net {
             | 1 << VIRTIO_NET_F_GUEST_UFO
             | 1 << VIRTIO_NET_F_HOST_TSO4
             | 1 << VIRTIO_NET_F_HOST_UFO
+            | 1 << VIRTIO_NET_F_STATUS
+            | 1 << VIRTIO_NET_F_CTRL_VQ
+            | 1 << VIRTIO_NET_F_GUEST_ANNOUNCE
             | 1 << VIRTIO_F_VERSION_1;
 
         let mut config_space = ConfigSpace::default();
@@ -147,6 +194,12 @@ impl Net {
             // Otherwise, it should attempt to read the device MAC address from the config space.
             avail_features |= 1 << VIRTIO_NET_F_MAC;
         }
+        if let Some(mtu) = mtu {
+            config_space.mtu = mtu;
+            // Frames larger than a page are better received in mergeable buffers, rather
+            // than having the driver post a large buffer for each of them.
+            avail_features |= 1 << VIRTIO_NET_F_MTU | 1 << VIRTIO_NET_F_MRG_RXBUF;
+        }
 
         let mut queue_evts = Vec::new();
         for _ in QUEUE_SIZES.iter() {
@@ -155,11 +208,14 @@ impl Net {
 
         let queues = QUEUE_SIZES.iter().map(|&s| Queue::new(s)).collect();
 
-        let mmds_ns = if allow_mmds_requests {
-            Some(MmdsNetworkStack::new_with_defaults(None))
-        } else {
-            None
-        };
+        let mmds_update_evt = Arc::new(EventFd::new(libc::EFD_NONBLOCK).map_err(Error::EventFd)?);
+        let mmds_ns = mmds.map(|mmds| {
+            mmds.lock()
+                .expect("Poisoned lock")
+                .add_update_listener(&mmds_update_evt);
+            MmdsNetworkStack::new_with_defaults(None, mmds)
+        });
+        let metrics = METRICS.net_interfaces.get_or_create(&id);
         Ok(Net {
             id,
             tap,
@@ -176,12 +232,16 @@ impl Net {
             rx_frame_buf: [0u8; MAX_BUFFER_SIZE],
             tx_frame_buf: [0u8; MAX_BUFFER_SIZE],
             tx_iovec: Vec::with_capacity(QUEUE_SIZE as usize),
+            rx_used_chains: Vec::with_capacity(QUEUE_SIZE as usize),
             interrupt_status: Arc::new(AtomicUsize::new(0)),
             interrupt_evt: EventFd::new(libc::EFD_NONBLOCK).map_err(Error::EventFd)?,
             device_state: DeviceState::Inactive,
             activate_evt: EventFd::new(libc::EFD_NONBLOCK).map_err(Error::EventFd)?,
             config_space,
             mmds_ns,
+            mmds_id: None,
+            mmds_update_evt,
+            metrics,
             guest_mac: guest_mac.copied(),
 
             #[cfg(test)]
@@ -189,6 +249,21 @@ impl Net {
         })
     }
 
+    /// Provides the metrics of this net device.
+    pub fn metrics(&self) -> &NetInterfaceMetrics {
+        &self.metrics
+    }
+
+    /// Provides the rate limiter of the RX path of this net device.
+    pub fn rx_rate_limiter(&self) -> &RateLimiter {
+        &self.rx_rate_limiter
+    }
+
+    /// Provides the rate limiter of the TX path of this net device.
+    pub fn tx_rate_limiter(&self) -> &RateLimiter {
+        &self.tx_rate_limiter
+    }
+
     /// Provides the ID of this net device.
     pub fn id(&self) -> &String {
         &self.id
@@ -199,11 +274,59 @@ impl Net {
         self.guest_mac.as_ref()
     }
 
+    /// Provides the ID of the MMDS instance answering the guest requests, `None` standing
+    /// for the default instance.
+    pub fn mmds_id(&self) -> Option<&str> {
+        self.mmds_id.as_ref().map(String::as_str)
+    }
+
+    /// Records the ID of the MMDS instance passed at creation, so that the device is bound
+    /// to the same instance when restored from a snapshot.
+    pub fn set_mmds_id(&mut self, mmds_id: Option<String>) {
+        self.mmds_id = mmds_id;
+    }
+
     /// Provides a mutable reference to the `MmdsNetworkStack`.
     pub fn mmds_ns_mut(&mut self) -> Option<&mut MmdsNetworkStack> {
         self.mmds_ns.as_mut()
     }
 
+    /// Provides the link state reported to the guest driver.
+    pub fn link_up(&self) -> bool {
+        self.config_space.status & VIRTIO_NET_S_LINK_UP as u16 != 0
+    }
+
+    /// Sets the link state reported to the guest driver through the config space.
+    /// Returns whether the state changed, in which case the caller is responsible for
+    /// notifying the driver through a configuration change interrupt.
+    pub fn set_link_up(&mut self, link_up: bool) -> bool {
+        if self.link_up() == link_up {
+            return false;
+        }
+        self.config_space.status ^= VIRTIO_NET_S_LINK_UP as u16;
+        true
+    }
+
+    /// Asks the guest driver to announce its presence on the network (e.g. by sending
+    /// gratuitous ARPs). Returns whether the driver supports announcements, in which case
+    /// the caller is responsible for raising a configuration change interrupt.
+    pub fn announce(&mut self) -> bool {
+        if self.acked_features & (1 << VIRTIO_NET_F_GUEST_ANNOUNCE) == 0 {
+            return false;
+        }
+        self.config_space.status |= VIRTIO_NET_S_ANNOUNCE as u16;
+        true
+    }
+
+    // The control queue exists only if the driver negotiated VIRTIO_NET_F_CTRL_VQ.
+    fn num_active_queues(&self) -> usize {
+        if self.acked_features & (1 << VIRTIO_NET_F_CTRL_VQ) != 0 {
+            self.queues.len()
+        } else {
+            CTRL_INDEX
+        }
+    }
+
     fn signal_used_queue(&self) -> result::Result<(), DeviceError> {
         self.interrupt_status
             .fetch_or(VIRTIO_MMIO_INT_VRING as usize, Ordering::SeqCst);
@@ -248,6 +371,41 @@ impl Net {
         success
     }
 
+    // Returns the number of descriptor chains from the RX queue needed to hold a frame of
+    // `frame_len` bytes, or `None` if there aren't enough of them. The queue is left untouched.
+    fn count_rx_buffers(
+        rx_queue: &mut Queue,
+        mem: &GuestMemoryMmap,
+        frame_len: usize,
+    ) -> Option<u16> {
+        let mut capacity = 0;
+        let mut num_buffers = 0u16;
+        while capacity < frame_len {
+            let mut next_desc = rx_queue.pop(mem);
+            if next_desc.is_none() {
+                break;
+            }
+            num_buffers += 1;
+            while let Some(desc) = next_desc {
+                if !desc.is_write_only() {
+                    break;
+                }
+                capacity += desc.len as usize;
+                next_desc = desc.next_descriptor();
+            }
+        }
+
+        for _ in 0..num_buffers {
+            rx_queue.undo_pop();
+        }
+
+        if capacity >= frame_len {
+            Some(num_buffers)
+        } else {
+            None
+        }
+    }
+
     // Copies a single frame from `self.rx_frame_buf` into the guest. Returns true
     // if a buffer was used, and false if the frame must be deferred until a buffer
     // is made available by the driver.
@@ -258,57 +416,108 @@ impl Net {
             DeviceState::Inactive => unreachable!(),
         };
         let rx_queue = &mut self.queues[RX_INDEX];
-        let mut next_desc = rx_queue.pop(mem);
-        if next_desc.is_none() {
-            METRICS.net.no_rx_avail_buffer.inc();
-            return false;
+        let mrg_rxbuf = self.acked_features & (1 << VIRTIO_NET_F_MRG_RXBUF) != 0;
+
+        // With mergeable RX buffers, the frame can be spread over multiple descriptor chains,
+        // all of which must be available before starting to write it.
+        let num_buffers = if mrg_rxbuf {
+            match Self::count_rx_buffers(rx_queue, mem, self.rx_bytes_read) {
+                Some(num_buffers) => num_buffers,
+                None => {
+                    METRICS.net.no_rx_avail_buffer.inc();
+                    return false;
+                }
+            }
+        } else {
+            1
+        };
+        if mrg_rxbuf {
+            set_vnet_hdr_num_buffers(&mut self.rx_frame_buf, num_buffers);
         }
 
-        // We just checked that the head descriptor exists.
-        let head_index = next_desc.as_ref().unwrap().index;
+        // The chains are only marked as used once the whole frame was written, so that a
+        // failed write can still change what the guest is told about them.
+        self.rx_used_chains.clear();
+        let mut write_failed = false;
         let mut write_count = 0;
+        'chains: for _ in 0..num_buffers {
+            let mut next_desc = rx_queue.pop(mem);
+            if next_desc.is_none() {
+                for _ in 0..self.rx_used_chains.len() {
+                    rx_queue.undo_pop();
+                }
+                METRICS.net.no_rx_avail_buffer.inc();
+                return false;
+            }
 
-        // Copy from frame into buffer, which may span multiple descriptors.
-        loop {
-            match next_desc {
-                Some(desc) => {
-                    if !desc.is_write_only() {
-                        break;
-                    }
-
-                    let limit = cmp::min(write_count + desc.len as usize, self.rx_bytes_read);
-                    let source_slice = &self.rx_frame_buf[write_count..limit];
-                    let write_result = mem.write_slice(source_slice, desc.addr);
+            // We just checked that the head descriptor exists.
+            let head_index = next_desc.as_ref().unwrap().index;
+            let chain_start = write_count;
 
-                    match write_result {
-                        Ok(()) => {
-                            METRICS.net.rx_count.inc();
-                            write_count += source_slice.len();
+            // Copy from frame into buffer, which may span multiple descriptors.
+            loop {
+                match next_desc {
+                    Some(desc) => {
+                        if !desc.is_write_only() {
+                            break;
                         }
-                        Err(e) => {
-                            error!("Failed to write slice: {:?}", e);
-                            METRICS.net.rx_fails.inc();
-                            if let GuestMemoryError::PartialBuffer { completed, .. } = e {
-                                write_count += completed;
+
+                        let limit = cmp::min(write_count + desc.len as usize, self.rx_bytes_read);
+                        let source_slice = &self.rx_frame_buf[write_count..limit];
+                        let write_result = mem.write_slice(source_slice, desc.addr);
+
+                        match write_result {
+                            Ok(()) => {
+                                METRICS.net.rx_count.inc();
+                                write_count += source_slice.len();
+                            }
+                            Err(e) => {
+                                error!("Failed to write slice: {:?}", e);
+                                METRICS.net.rx_fails.inc();
+                                self.metrics.rx_fails.inc();
+                                if let GuestMemoryError::PartialBuffer { completed, .. } = e {
+                                    write_count += completed;
+                                }
+                                self.rx_used_chains
+                                    .push((head_index, (write_count - chain_start) as u32));
+                                write_failed = true;
+                                break 'chains;
                             }
+                        };
+
+                        if write_count >= self.rx_bytes_read {
                             break;
                         }
-                    };
-
-                    if write_count >= self.rx_bytes_read {
+                        next_desc = desc.next_descriptor();
+                    }
+                    None => {
+                        // Running out of descriptors is expected when the frame is spread
+                        // over multiple buffers.
+                        if !mrg_rxbuf {
+                            warn!("Receiving buffer is too small to hold frame of current size");
+                            METRICS.net.rx_fails.inc();
+                            self.metrics.rx_fails.inc();
+                        }
                         break;
                     }
-                    next_desc = desc.next_descriptor();
-                }
-                None => {
-                    warn!("Receiving buffer is too small to hold frame of current size");
-                    METRICS.net.rx_fails.inc();
-                    break;
                 }
             }
+
+            self.rx_used_chains
+                .push((head_index, (write_count - chain_start) as u32));
         }
 
-        rx_queue.add_used(mem, head_index, write_count as u32);
+        // The VNET hdr already claims `num_buffers` buffers, so none of them can hold part of
+        // the frame when one can't be written: they are handed back to the driver empty, which
+        // discards them, and the frame is deferred like on any other write failure.
+        if write_failed && mrg_rxbuf {
+            for (_, len) in self.rx_used_chains.iter_mut() {
+                *len = 0;
+            }
+        }
+        for &(head_index, len) in self.rx_used_chains.iter() {
+            rx_queue.add_used(mem, head_index, len);
+        }
 
         // Mark that we have at least one pending packet and we need to interrupt the guest.
         self.rx_deferred_irqs = true;
@@ -316,6 +525,8 @@ impl Net {
         if write_count >= self.rx_bytes_read {
             METRICS.net.rx_bytes_count.add(write_count);
             METRICS.net.rx_packets_count.inc();
+            self.metrics.rx_bytes_count.add(write_count);
+            self.metrics.rx_packets_count.inc();
             true
         } else {
             false
@@ -332,10 +543,12 @@ impl Net {
         frame_buf: &[u8],
         tap: &mut Tap,
         guest_mac: Option<MacAddr>,
+        metrics: &NetInterfaceMetrics,
     ) -> bool {
         if let Some(ns) = mmds_ns {
             if ns.detour_frame(frame_bytes_from_buf(frame_buf)) {
                 METRICS.mmds.rx_accepted.inc();
+                metrics.mmds_rx_frames.inc();
 
                 // MMDS frames are not accounted by the rate limiter.
                 rate_limiter.manual_replenish(frame_buf.len() as u64, TokenType::Bytes);
@@ -364,10 +577,13 @@ impl Net {
                 METRICS.net.tx_bytes_count.add(frame_buf.len());
                 METRICS.net.tx_packets_count.inc();
                 METRICS.net.tx_count.inc();
+                metrics.tx_bytes_count.add(frame_buf.len());
+                metrics.tx_packets_count.inc();
             }
             Err(e) => {
                 error!("Failed to write to tap: {:?}", e);
                 METRICS.net.tx_fails.inc();
+                metrics.tx_fails.inc();
             }
         };
         false
@@ -381,6 +597,7 @@ impl Net {
                 let len = len.get();
                 METRICS.mmds.tx_frames.inc();
                 METRICS.mmds.tx_bytes.add(len);
+                self.metrics.mmds_tx_frames.inc();
                 init_vnet_hdr(&mut self.rx_frame_buf);
                 return Ok(vnet_hdr_len() + len);
             }
@@ -409,6 +626,7 @@ impl Net {
                         _ => {
                             error!("Failed to read tap: {:?}", e);
                             METRICS.net.rx_fails.inc();
+                            self.metrics.rx_fails.inc();
                             return Err(DeviceError::FailedReadTap);
                         }
                     };
@@ -515,6 +733,7 @@ impl Net {
                     Err(e) => {
                         error!("Failed to read slice: {:?}", e);
                         METRICS.net.tx_fails.inc();
+                        self.metrics.tx_fails.inc();
                         if let GuestMemoryError::PartialBuffer { completed, .. } = e {
                             read_count += completed;
                         }
@@ -529,6 +748,7 @@ impl Net {
                 &self.tx_frame_buf[..read_count],
                 &mut self.tap,
                 self.guest_mac,
+                &self.metrics,
             ) && !self.rx_deferred_frame
             {
                 // MMDS consumed this frame/request, let's also try to process the response.
@@ -553,6 +773,80 @@ impl Net {
         }
     }
 
+    // Handles a single control request and returns the number of bytes written back to the
+    // driver. A control request consists of a `{class: u8, command: u8}` header, optional
+    // command-specific data and a device-writable byte that holds the ack.
+    fn handle_ctrl_request(config_space: &mut ConfigSpace, head: DescriptorChain) -> u32 {
+        let mem = head.mem;
+        let mut header: Option<[u8; 2]> = None;
+        let mut next_desc = Some(head);
+
+        while let Some(desc) = next_desc {
+            if desc.is_write_only() {
+                let ack = match header {
+                    Some([class, cmd])
+                        if u32::from(class) == VIRTIO_NET_CTRL_ANNOUNCE
+                            && u32::from(cmd) == VIRTIO_NET_CTRL_ANNOUNCE_ACK =>
+                    {
+                        config_space.status &= !(VIRTIO_NET_S_ANNOUNCE as u16);
+                        VIRTIO_NET_OK
+                    }
+                    _ => {
+                        warn!("Net: unsupported control request: {:?}", header);
+                        VIRTIO_NET_ERR
+                    }
+                };
+
+                return match mem.write_obj(ack as u8, desc.addr) {
+                    Ok(()) => 1,
+                    Err(e) => {
+                        error!("Failed to write control ack: {:?}", e);
+                        METRICS.net.event_fails.inc();
+                        0
+                    }
+                };
+            }
+
+            if header.is_none() {
+                let mut buf = [0u8; 2];
+                if let Err(e) = mem.read_slice(&mut buf, desc.addr) {
+                    error!("Failed to read control header: {:?}", e);
+                    METRICS.net.event_fails.inc();
+                    return 0;
+                }
+                header = Some(buf);
+            }
+            next_desc = desc.next_descriptor();
+        }
+
+        warn!("Net: control request without an ack descriptor");
+        METRICS.net.event_fails.inc();
+        0
+    }
+
+    fn process_ctrl(&mut self) -> result::Result<(), DeviceError> {
+        let mem = match self.device_state {
+            DeviceState::Activated(ref mem) => mem,
+            // This should never happen, it's been already validated in the event handler.
+            DeviceState::Inactive => unreachable!(),
+        };
+
+        let mut raise_irq = false;
+        let ctrl_queue = &mut self.queues[CTRL_INDEX];
+        while let Some(head) = ctrl_queue.pop(mem) {
+            let head_index = head.index;
+            let len = Self::handle_ctrl_request(&mut self.config_space, head);
+            ctrl_queue.add_used(mem, head_index, len);
+            raise_irq = true;
+        }
+
+        if raise_irq {
+            self.signal_used_queue()
+        } else {
+            Ok(())
+        }
+    }
+
     /// Updates the parameters for the rate limiters
     pub fn patch_rate_limiters(
         &mut self,
@@ -565,6 +859,20 @@ impl Net {
         self.tx_rate_limiter.update_buckets(tx_bytes, tx_ops);
     }
 
+    /// Makes the RX and TX rate limiters members of the given rate limiter groups.
+    pub fn set_rate_limiter_groups(
+        &mut self,
+        rx_group: Option<Arc<Mutex<RateLimiterGroup>>>,
+        tx_group: Option<Arc<Mutex<RateLimiterGroup>>>,
+    ) {
+        if let Some(group) = rx_group {
+            self.rx_rate_limiter.set_group(group);
+        }
+        if let Some(group) = tx_group {
+            self.tx_rate_limiter.set_group(group);
+        }
+    }
+
     #[cfg(not(test))]
     fn read_tap(&mut self) -> io::Result<usize> {
         self.tap.read(&mut self.rx_frame_buf)
@@ -586,12 +894,34 @@ impl Net {
     }
 
     pub fn process_tap_rx_event(&mut self) {
+        METRICS.net.rx_tap_event_count.inc();
+        self.process_rx_available();
+    }
+
+    pub fn process_mmds_update_event(&mut self) {
+        if let Err(e) = self.mmds_update_evt.read() {
+            error!("Failed to get MMDS update event: {:?}", e);
+            METRICS.net.event_fails.inc();
+            return;
+        }
+
+        let has_frames = self
+            .mmds_ns
+            .as_mut()
+            .map_or(false, |ns| ns.process_data_store_update());
+        // The responses to the long-poll requests are received like the frames from the tap.
+        if has_frames {
+            self.process_rx_available();
+        }
+    }
+
+    // Receives the frames which became available from either the tap or the MMDS.
+    fn process_rx_available(&mut self) {
         let mem = match self.device_state {
             DeviceState::Activated(ref mem) => mem,
             // This should never happen, it's been already validated in the event handler.
             DeviceState::Inactive => unreachable!(),
         };
-        METRICS.net.rx_tap_event_count.inc();
         if self.queues[RX_INDEX].is_empty(mem) {
             METRICS.net.no_rx_avail_buffer.inc();
             return;
@@ -631,6 +961,15 @@ impl Net {
         }
     }
 
+    pub fn process_ctrl_queue_event(&mut self) {
+        if let Err(e) = self.queue_evts[CTRL_INDEX].read() {
+            error!("Failed to get ctrl queue event: {:?}", e);
+            METRICS.net.event_fails.inc();
+        } else {
+            self.process_ctrl().unwrap_or_else(report_net_event_fail);
+        }
+    }
+
     pub fn process_rx_rate_limiter_event(&mut self) {
         METRICS.net.rx_event_rate_limiter_count.inc();
         // Upon rate limiter event, call the rate limiter handler
@@ -665,17 +1004,25 @@ impl Net {
     }
 }
 
+impl Drop for Net {
+    fn drop(&mut self) {
+        // The metrics of the interface are not reported once the device is gone.
+        METRICS.net_interfaces.remove(&self.id);
+    }
+}
+
 impl VirtioDevice for Net {
     fn device_type(&self) -> u32 {
         TYPE_NET
     }
 
     fn queues(&self) -> &[Queue] {
-        &self.queues
+        &self.queues[..self.num_active_queues()]
     }
 
     fn queues_mut(&mut self) -> &mut [Queue] {
-        &mut self.queues
+        let num_active_queues = self.num_active_queues();
+        &mut self.queues[..num_active_queues]
     }
 
     fn queue_events(&self) -> &[EventFd] {
@@ -722,7 +1069,8 @@ impl VirtioDevice for Net {
     fn write_config(&mut self, offset: u64, data: &[u8]) {
         let data_len = data.len() as u64;
         let config_space_bytes = self.config_space.as_mut_slice();
-        let config_len = config_space_bytes.len() as u64;
+        // Only the MAC address is writable by the driver.
+        let config_len = MAC_ADDR_LEN as u64;
         if offset + data_len > config_len {
             error!("Failed to write config space");
             METRICS.net.cfg_fails.inc();
@@ -766,11 +1114,11 @@ pub(crate) mod tests {
         frame_bytes_from_buf, frame_bytes_from_buf_mut, init_vnet_hdr, vnet_hdr_len,
     };
 
-    use crate::virtio::net::QUEUE_SIZES;
+    use crate::virtio::net::{QUEUE_SIZE, QUEUE_SIZES};
     use crate::virtio::queue::tests::VirtQueue;
     use crate::virtio::{
-        Net, Queue, VirtioDevice, MAX_BUFFER_SIZE, RX_INDEX, TX_INDEX, TYPE_NET,
-        VIRTIO_MMIO_INT_VRING, VIRTQ_DESC_F_WRITE,
+        Net, Queue, VirtioDevice, CTRL_INDEX, MAX_BUFFER_SIZE, RX_INDEX, TX_INDEX, TYPE_NET,
+        VIRTIO_MMIO_INT_VRING, VIRTQ_DESC_F_NEXT, VIRTQ_DESC_F_WRITE,
     };
     use dumbo::{
         EthIPv4ArpFrame, EthernetFrame, MacAddr, ETHERTYPE_ARP, ETH_IPV4_FRAME_LEN, MAC_ADDR_LEN,
@@ -780,9 +1128,12 @@ pub(crate) mod tests {
     use rate_limiter::{RateLimiter, TokenBucket, TokenType};
     use utils::epoll::{EpollEvent, EventSet};
     use virtio_gen::virtio_net::{
-        virtio_net_hdr_v1, VIRTIO_F_VERSION_1, VIRTIO_NET_F_CSUM, VIRTIO_NET_F_GUEST_CSUM,
+        virtio_net_hdr_v1, VIRTIO_F_VERSION_1, VIRTIO_NET_CTRL_ANNOUNCE,
+        VIRTIO_NET_CTRL_ANNOUNCE_ACK, VIRTIO_NET_CTRL_RX, VIRTIO_NET_ERR, VIRTIO_NET_F_CSUM,
+        VIRTIO_NET_F_CTRL_VQ, VIRTIO_NET_F_GUEST_ANNOUNCE, VIRTIO_NET_F_GUEST_CSUM,
         VIRTIO_NET_F_GUEST_TSO4, VIRTIO_NET_F_GUEST_UFO, VIRTIO_NET_F_HOST_TSO4,
-        VIRTIO_NET_F_HOST_UFO, VIRTIO_NET_F_MAC,
+        VIRTIO_NET_F_HOST_UFO, VIRTIO_NET_F_MAC, VIRTIO_NET_F_MRG_RXBUF, VIRTIO_NET_F_MTU,
+        VIRTIO_NET_F_STATUS, VIRTIO_NET_OK, VIRTIO_NET_S_ANNOUNCE, VIRTIO_NET_S_LINK_UP,
     };
 
     static NEXT_INDEX: AtomicUsize = AtomicUsize::new(1);
@@ -821,7 +1172,8 @@ pub(crate) mod tests {
                 Some(&guest_mac),
                 RateLimiter::default(),
                 RateLimiter::default(),
-                true,
+                Some(Arc::new(Mutex::new(Mmds::default()))),
+                None,
             )
             .unwrap();
             net.tap.enable().unwrap();
@@ -866,6 +1218,7 @@ pub(crate) mod tests {
             self.queues.clear();
             self.queues.push(rxq);
             self.queues.push(txq);
+            self.queues.push(Queue::new(QUEUE_SIZE));
         }
     }
 
@@ -932,6 +1285,9 @@ pub(crate) mod tests {
             | 1 << VIRTIO_NET_F_GUEST_UFO
             | 1 << VIRTIO_NET_F_HOST_TSO4
             | 1 << VIRTIO_NET_F_HOST_UFO
+            | 1 << VIRTIO_NET_F_STATUS
+            | 1 << VIRTIO_NET_F_CTRL_VQ
+            | 1 << VIRTIO_NET_F_GUEST_ANNOUNCE
             | 1 << VIRTIO_F_VERSION_1;
 
         assert_eq!(net.avail_features_by_page(0), features as u32);
@@ -958,9 +1314,14 @@ pub(crate) mod tests {
         net.read_config(0, &mut config_mac);
         assert_eq!(config_mac, mac.get_bytes());
 
+        // The link is reported as up by default.
+        let mut status = [0u8; 2];
+        net.read_config(MAC_ADDR_LEN as u64, &mut status);
+        assert_eq!(u16::from_le_bytes(status), VIRTIO_NET_S_LINK_UP as u16);
+
         // Invalid read.
         config_mac = [0u8; MAC_ADDR_LEN];
-        net.read_config(MAC_ADDR_LEN as u64 + 1, &mut config_mac);
+        net.read_config(MAC_ADDR_LEN as u64 + 2, &mut config_mac);
         assert_eq!(config_mac, [0u8, 0u8, 0u8, 0u8, 0u8, 0u8]);
     }
 
@@ -993,6 +1354,176 @@ pub(crate) mod tests {
         new_config_read = [0u8; 6];
         net.read_config(0, &mut new_config_read);
         assert_eq!(new_config, new_config_read);
+
+        // The status field is read-only.
+        net.write_config(MAC_ADDR_LEN as u64, &[0u8; 2]);
+        assert!(net.link_up());
+    }
+
+    #[test]
+    fn test_mtu() {
+        let next_tap = NEXT_INDEX.fetch_add(1, Ordering::SeqCst);
+        let net = Net::new_with_tap(
+            format!("net-device{}", next_tap),
+            format!("net-device{}", next_tap),
+            None,
+            RateLimiter::default(),
+            RateLimiter::default(),
+            None,
+            Some(9000),
+        )
+        .unwrap();
+
+        assert_eq!(net.tap.mtu().unwrap(), 9000);
+        assert_ne!(net.avail_features() & (1 << VIRTIO_NET_F_MTU), 0);
+        assert_ne!(net.avail_features() & (1 << VIRTIO_NET_F_MRG_RXBUF), 0);
+
+        // The MTU follows the MAC, the status and the max_virtqueue_pairs fields.
+        let mut mtu = [0u8; 2];
+        net.read_config(MAC_ADDR_LEN as u64 + 4, &mut mtu);
+        assert_eq!(u16::from_le_bytes(mtu), 9000);
+
+        // Without an MTU, neither feature is offered.
+        let net = Net::default_net(TestMutators::default());
+        assert_eq!(net.avail_features() & (1 << VIRTIO_NET_F_MTU), 0);
+        assert_eq!(net.avail_features() & (1 << VIRTIO_NET_F_MRG_RXBUF), 0);
+    }
+
+    #[test]
+    fn test_rx_mergeable_buffers() {
+        let mut net = Net::default_net(TestMutators::default());
+        let mem = Net::default_guest_memory();
+        let (rxq, txq) = Net::virtqueues(&mem);
+        net.assign_queues(rxq.create_queue(), txq.create_queue());
+        net.set_acked_features(1 << VIRTIO_NET_F_MRG_RXBUF);
+        net.activate(mem.clone()).unwrap();
+
+        // A frame which needs three 0x400 bytes buffers.
+        net.rx_bytes_read = 0x900;
+        init_vnet_hdr(&mut net.rx_frame_buf);
+        rxq.dtable[0].set(0x4000, 0x400, VIRTQ_DESC_F_WRITE, 0);
+        rxq.dtable[1].set(0x5000, 0x400, VIRTQ_DESC_F_WRITE, 0);
+        rxq.avail.ring[0].set(0);
+        rxq.avail.ring[1].set(1);
+        rxq.avail.idx.set(2);
+
+        // Not enough buffers, so the frame is deferred and none of them is used.
+        check_metric_after_block!(
+            &METRICS.net.no_rx_avail_buffer,
+            1,
+            assert!(!net.rx_single_frame())
+        );
+        assert_eq!(rxq.used.idx.get(), 0);
+
+        rxq.dtable[2].set(0x6000, 0x400, VIRTQ_DESC_F_WRITE, 0);
+        rxq.avail.ring[2].set(2);
+        rxq.avail.idx.set(3);
+        assert!(net.rx_single_frame());
+        assert_eq!(rxq.used.idx.get(), 3);
+        assert_eq!(rxq.used.ring[0].get().len, 0x400);
+        assert_eq!(rxq.used.ring[1].get().len, 0x400);
+        assert_eq!(rxq.used.ring[2].get().len, 0x100);
+
+        // The number of buffers is reported in the VNET hdr, at the start of the first one.
+        let num_buffers: u16 = mem
+            .read_obj(GuestAddress(0x4000 + vnet_hdr_len() as u64 - 2))
+            .unwrap();
+        assert_eq!(num_buffers, 3);
+
+        // The second buffer runs past the end of the guest memory, so the write fails midway.
+        rxq.dtable[3].set(0x4000, 0x400, VIRTQ_DESC_F_WRITE, 0);
+        rxq.dtable[4].set(0xfe00, 0x400, VIRTQ_DESC_F_WRITE, 0);
+        rxq.dtable[5].set(0x6000, 0x400, VIRTQ_DESC_F_WRITE, 0);
+        rxq.avail.ring[3].set(3);
+        rxq.avail.ring[4].set(4);
+        rxq.avail.ring[5].set(5);
+        rxq.avail.idx.set(6);
+        check_metric_after_block!(&METRICS.net.rx_fails, 1, assert!(!net.rx_single_frame()));
+        // The buffers used so far are returned empty, and the last one is left available.
+        assert_eq!(rxq.used.idx.get(), 5);
+        assert_eq!(rxq.used.ring[3].get().id, 3);
+        assert_eq!(rxq.used.ring[3].get().len, 0);
+        assert_eq!(rxq.used.ring[4].get().id, 4);
+        assert_eq!(rxq.used.ring[4].get().len, 0);
+        assert_eq!(net.queues[RX_INDEX].len(&mem), 1);
+    }
+
+    #[test]
+    fn test_link_state() {
+        let mut net = Net::default_net(TestMutators::default());
+        assert!(net.link_up());
+
+        // Setting the same state is a no-op.
+        assert!(!net.set_link_up(true));
+        assert!(net.set_link_up(false));
+        assert!(!net.link_up());
+        let mut status = [0u8; 2];
+        net.read_config(MAC_ADDR_LEN as u64, &mut status);
+        assert_eq!(u16::from_le_bytes(status), 0);
+
+        assert!(net.set_link_up(true));
+        assert!(net.link_up());
+        net.read_config(MAC_ADDR_LEN as u64, &mut status);
+        assert_eq!(u16::from_le_bytes(status), VIRTIO_NET_S_LINK_UP as u16);
+    }
+
+    #[test]
+    fn test_announce() {
+        let mut event_manager = EventManager::new().unwrap();
+        let mut net = Net::default_net(TestMutators::default());
+        let mem = Net::default_guest_memory();
+        let (rxq, txq) = Net::virtqueues(&mem);
+        let ctrlq = VirtQueue::new(GuestAddress(0x2000), &mem, 16);
+        assert!(ctrlq.end().0 < 0x4000);
+        net.assign_queues(rxq.create_queue(), txq.create_queue());
+        net.queues[CTRL_INDEX] = ctrlq.create_queue();
+
+        // Announcements are only possible if the driver acked the feature.
+        assert!(!net.announce());
+        net.set_acked_features(1 << VIRTIO_NET_F_CTRL_VQ | 1 << VIRTIO_NET_F_GUEST_ANNOUNCE);
+        assert_eq!(net.queues().len(), QUEUE_SIZES.len());
+        net.activate(mem.clone()).unwrap();
+
+        assert!(net.announce());
+        let expected_status = (VIRTIO_NET_S_LINK_UP | VIRTIO_NET_S_ANNOUNCE) as u16;
+        assert_eq!(net.config_space.status, expected_status);
+
+        let (hdr_addr, ack_addr) = (0x4000, 0x5000);
+        ctrlq.dtable[0].set(hdr_addr, 2, VIRTQ_DESC_F_NEXT, 1);
+        ctrlq.dtable[1].set(ack_addr, 1, VIRTQ_DESC_F_WRITE, 0);
+        ctrlq.avail.ring[0].set(0);
+        ctrlq.avail.idx.set(1);
+
+        // An unsupported request is nacked and doesn't touch the status.
+        mem.write_slice(&[VIRTIO_NET_CTRL_RX as u8, 0], GuestAddress(hdr_addr))
+            .unwrap();
+        net.queue_evts[CTRL_INDEX].write(1).unwrap();
+        let ctrl_event =
+            EpollEvent::new(EventSet::IN, net.queue_evts[CTRL_INDEX].as_raw_fd() as u64);
+        net.process(&ctrl_event, &mut event_manager);
+        assert_eq!(ctrlq.used.idx.get(), 1);
+        assert_eq!(ctrlq.used.ring[0].get().len, 1);
+        let ack: u8 = mem.read_obj(GuestAddress(ack_addr)).unwrap();
+        assert_eq!(u32::from(ack), VIRTIO_NET_ERR);
+        assert_eq!(net.config_space.status, expected_status);
+
+        // The announce ack clears the announce bit.
+        mem.write_slice(
+            &[
+                VIRTIO_NET_CTRL_ANNOUNCE as u8,
+                VIRTIO_NET_CTRL_ANNOUNCE_ACK as u8,
+            ],
+            GuestAddress(hdr_addr),
+        )
+        .unwrap();
+        ctrlq.avail.ring[1].set(0);
+        ctrlq.avail.idx.set(2);
+        net.queue_evts[CTRL_INDEX].write(1).unwrap();
+        net.process(&ctrl_event, &mut event_manager);
+        assert_eq!(ctrlq.used.idx.get(), 2);
+        let ack: u8 = mem.read_obj(GuestAddress(ack_addr)).unwrap();
+        assert_eq!(u32::from(ack), VIRTIO_NET_OK);
+        assert_eq!(net.config_space.status, VIRTIO_NET_S_LINK_UP as u16);
     }
 
     #[test]
@@ -1198,6 +1729,7 @@ pub(crate) mod tests {
                 &net.tx_frame_buf[..packet_len],
                 &mut net.tap,
                 Some(sha),
+                &net.metrics,
             ))
         );
 
@@ -1207,6 +1739,60 @@ pub(crate) mod tests {
             1,
             net.read_from_mmds_or_tap().unwrap()
         );
+
+        // The interface metrics account for the frames detoured to and from MMDS.
+        assert_eq!(net.metrics.mmds_rx_frames.count(), 1);
+        assert_eq!(net.metrics.mmds_tx_frames.count(), 1);
+        assert_eq!(net.metrics.tx_packets_count.count(), 0);
+        assert!(Arc::ptr_eq(
+            &net.metrics,
+            &METRICS.net_interfaces.get(net.id()).unwrap()
+        ));
+    }
+
+    #[test]
+    fn test_metrics_removed_on_drop() {
+        let net = Net::default_net(TestMutators::default());
+        let id = net.id().clone();
+        assert!(METRICS.net_interfaces.get(&id).is_some());
+
+        drop(net);
+        assert!(METRICS.net_interfaces.get(&id).is_none());
+    }
+
+    #[test]
+    fn test_mmds_update_event() {
+        let mut event_manager = EventManager::new().unwrap();
+        let mut net = Net::default_net(TestMutators::default());
+        let mem = Net::default_guest_memory();
+        let (rxq, txq) = Net::virtqueues(&mem);
+        net.assign_queues(rxq.create_queue(), txq.create_queue());
+        net.activate(mem).unwrap();
+
+        // Data store updates are watched for as long as the device serves MMDS requests.
+        let mmds_update_fd = net.mmds_update_evt.as_raw_fd();
+        assert!(net
+            .interest_list()
+            .iter()
+            .any(|event| event.fd() == mmds_update_fd));
+
+        // The event is consumed, even when there's no long-poll request to answer.
+        net.mmds_update_evt.write(1).unwrap();
+        net.process(
+            &EpollEvent::new(EventSet::IN, mmds_update_fd as u64),
+            &mut event_manager,
+        );
+        assert_eq!(
+            net.mmds_update_evt.read().unwrap_err().kind(),
+            io::ErrorKind::WouldBlock
+        );
+        assert_eq!(rxq.used.idx.get(), 0);
+
+        net.mmds_ns = None;
+        assert!(!net
+            .interest_list()
+            .iter()
+            .any(|event| event.fd() == mmds_update_fd));
     }
 
     #[test]
@@ -1258,6 +1844,7 @@ pub(crate) mod tests {
                 &net.tx_frame_buf[..packet_len],
                 &mut net.tap,
                 Some(guest_mac),
+                &net.metrics,
             )
         );
 
@@ -1271,6 +1858,7 @@ pub(crate) mod tests {
                 &net.tx_frame_buf[..packet_len],
                 &mut net.tap,
                 Some(not_guest_mac),
+                &net.metrics,
             )
         );
     }
@@ -1718,9 +2306,9 @@ pub(crate) mod tests {
         net.assign_queues(rxq.create_queue(), txq.create_queue());
         net.activate(mem.clone()).unwrap();
 
-        // Test queues count (TX and RX).
+        // Test queues count (TX and RX). The control queue is hidden until negotiated.
         let queues = net.queues();
-        assert_eq!(queues.len(), QUEUE_SIZES.len());
+        assert_eq!(queues.len(), CTRL_INDEX);
         assert_eq!(queues[RX_INDEX].size, rxq.size());
         assert_eq!(queues[TX_INDEX].size, txq.size());
 
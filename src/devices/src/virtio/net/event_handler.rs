@@ -8,7 +8,7 @@ use polly::event_manager::{EventManager, Subscriber};
 use utils::epoll::{EpollEvent, EventSet};
 
 use crate::virtio::net::device::Net;
-use crate::virtio::{VirtioDevice, RX_INDEX, TX_INDEX};
+use crate::virtio::{VirtioDevice, CTRL_INDEX, RX_INDEX, TX_INDEX};
 
 impl Net {
     fn process_activate_event(&self, event_manager: &mut EventManager) {
@@ -62,18 +62,22 @@ impl Subscriber for Net {
         if self.is_activated() {
             let virtq_rx_ev_fd = self.queue_evts[RX_INDEX].as_raw_fd();
             let virtq_tx_ev_fd = self.queue_evts[TX_INDEX].as_raw_fd();
+            let virtq_ctrl_ev_fd = self.queue_evts[CTRL_INDEX].as_raw_fd();
             let rx_rate_limiter_fd = self.rx_rate_limiter.as_raw_fd();
             let tx_rate_limiter_fd = self.tx_rate_limiter.as_raw_fd();
             let tap_fd = self.tap.as_raw_fd();
             let activate_fd = self.activate_evt.as_raw_fd();
+            let mmds_update_fd = self.mmds_update_evt.as_raw_fd();
 
             // Looks better than C style if/else if/else.
             match source {
                 _ if source == virtq_rx_ev_fd => self.process_rx_queue_event(),
                 _ if source == tap_fd => self.process_tap_rx_event(),
                 _ if source == virtq_tx_ev_fd => self.process_tx_queue_event(),
+                _ if source == virtq_ctrl_ev_fd => self.process_ctrl_queue_event(),
                 _ if source == rx_rate_limiter_fd => self.process_rx_rate_limiter_event(),
                 _ if source == tx_rate_limiter_fd => self.process_tx_rate_limiter_event(),
+                _ if source == mmds_update_fd => self.process_mmds_update_event(),
                 _ if activate_fd == source => self.process_activate_event(evmgr),
                 _ => {
                     warn!("Net: Spurious event received: {:?}", source);
@@ -94,16 +98,24 @@ impl Subscriber for Net {
         //  - on device activation (is-activated already true at this point),
         //  - on device restore from snapshot.
         if self.is_activated() {
-            vec![
+            let mut events = vec![
                 EpollEvent::new(EventSet::IN, self.queue_evts[RX_INDEX].as_raw_fd() as u64),
                 EpollEvent::new(EventSet::IN, self.queue_evts[TX_INDEX].as_raw_fd() as u64),
+                EpollEvent::new(EventSet::IN, self.queue_evts[CTRL_INDEX].as_raw_fd() as u64),
                 EpollEvent::new(EventSet::IN, self.rx_rate_limiter.as_raw_fd() as u64),
                 EpollEvent::new(EventSet::IN, self.tx_rate_limiter.as_raw_fd() as u64),
                 EpollEvent::new(
                     EventSet::IN | EventSet::EDGE_TRIGGERED,
                     self.tap.as_raw_fd() as u64,
                 ),
-            ]
+            ];
+            if self.mmds_ns.is_some() {
+                events.push(EpollEvent::new(
+                    EventSet::IN,
+                    self.mmds_update_evt.as_raw_fd() as u64,
+                ));
+            }
+            events
         } else {
             vec![EpollEvent::new(
                 EventSet::IN,
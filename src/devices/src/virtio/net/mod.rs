@@ -6,12 +6,14 @@ use utils::net::TapError;
 
 pub const MAX_BUFFER_SIZE: usize = 65562;
 pub const QUEUE_SIZE: u16 = 256;
-pub const NUM_QUEUES: usize = 2;
+pub const NUM_QUEUES: usize = 3;
 pub const QUEUE_SIZES: &[u16] = &[QUEUE_SIZE; NUM_QUEUES];
 // The index of the rx queue from Net device queues/queues_evts vector.
 pub const RX_INDEX: usize = 0;
 // The index of the tx queue from Net device queues/queues_evts vector.
 pub const TX_INDEX: usize = 1;
+// The index of the control queue from Net device queues/queues_evts vector.
+pub const CTRL_INDEX: usize = 2;
 
 pub mod device;
 pub mod event_handler;
@@ -28,6 +30,8 @@ pub enum Error {
     TapSetOffload(TapError),
     /// Setting vnet header size failed.
     TapSetVnetHdrSize(TapError),
+    /// Setting tap interface MTU failed.
+    TapSetMtu(TapError),
     /// Enabling tap interface failed.
     TapEnable(TapError),
     /// EventFd
@@ -8,10 +8,13 @@ use std::sync::atomic::AtomicUsize;
 use std::sync::Arc;
 
 use dumbo::{ns::MmdsNetworkStack, persist::MmdsNetworkStackState, MacAddr, MAC_ADDR_LEN};
-use rate_limiter::{persist::RateLimiterState, RateLimiter};
+use mmds::data_store::MmdsInstances;
+use rate_limiter::persist::{RateLimiterState, RestoredGroups};
+use rate_limiter::RateLimiter;
 use snapshot::Persist;
 use versionize::{VersionMap, Versionize, VersionizeResult};
 use versionize_derive::Versionize;
+use virtio_gen::virtio_net::VIRTIO_NET_S_LINK_UP;
 use vm_memory::GuestMemoryMmap;
 
 use super::device::{ConfigSpace, Net};
@@ -22,6 +25,21 @@ use crate::virtio::{DeviceState, Queue};
 #[derive(Versionize)]
 pub struct NetConfigSpaceState {
     guest_mac: [u8; MAC_ADDR_LEN],
+    #[version(start = 2, default_fn = "default_status")]
+    status: u16,
+    #[version(start = 2, default_fn = "default_mtu")]
+    mtu: u16,
+}
+
+impl NetConfigSpaceState {
+    fn default_status(_: u16) -> u16 {
+        // The link of the devices saved before it could be set down was always up.
+        VIRTIO_NET_S_LINK_UP as u16
+    }
+
+    fn default_mtu(_: u16) -> u16 {
+        0
+    }
 }
 
 #[derive(Versionize)]
@@ -31,12 +49,25 @@ pub struct NetState {
     rx_rate_limiter_state: RateLimiterState,
     tx_rate_limiter_state: RateLimiterState,
     mmds_ns: Option<MmdsNetworkStackState>,
+    #[version(start = 2, default_fn = "default_mmds_id")]
+    mmds_id: Option<String>,
     config_space: NetConfigSpaceState,
     virtio_state: VirtioDeviceState,
 }
 
+impl NetState {
+    fn default_mmds_id(_: u16) -> Option<String> {
+        None
+    }
+}
+
 pub struct NetConstructorArgs {
     pub mem: GuestMemoryMmap,
+    /// The MMDS instances, one of which answers the guest requests if the device was
+    /// detouring them when saved.
+    pub mmds: MmdsInstances,
+    /// The rate limiter groups restored along with the other devices.
+    pub rate_limiter_groups: RestoredGroups,
 }
 
 #[derive(Debug)]
@@ -57,8 +88,11 @@ impl Persist<'_> for Net {
             rx_rate_limiter_state: self.rx_rate_limiter.save(),
             tx_rate_limiter_state: self.tx_rate_limiter.save(),
             mmds_ns: self.mmds_ns.as_ref().map(|mmds| mmds.save()),
+            mmds_id: self.mmds_id.clone(),
             config_space: NetConfigSpaceState {
                 guest_mac: self.config_space.guest_mac,
+                status: self.config_space.status,
+                mtu: self.config_space.mtu,
             },
             virtio_state: VirtioDeviceState::from_device(self),
         }
@@ -69,38 +103,53 @@ impl Persist<'_> for Net {
         state: &Self::State,
     ) -> std::result::Result<Self, Self::Error> {
         // RateLimiter::restore() can fail at creating a timerfd.
-        let rx_rate_limiter = RateLimiter::restore((), &state.rx_rate_limiter_state)
-            .map_err(Error::CreateRateLimiter)?;
-        let tx_rate_limiter = RateLimiter::restore((), &state.tx_rate_limiter_state)
-            .map_err(Error::CreateRateLimiter)?;
+        let rx_rate_limiter = RateLimiter::restore(
+            constructor_args.rate_limiter_groups.clone(),
+            &state.rx_rate_limiter_state,
+        )
+        .map_err(Error::CreateRateLimiter)?;
+        let tx_rate_limiter = RateLimiter::restore(
+            constructor_args.rate_limiter_groups.clone(),
+            &state.tx_rate_limiter_state,
+        )
+        .map_err(Error::CreateRateLimiter)?;
+        let mmds_id = state.mmds_id.as_ref().map(String::as_str);
+        let mmds = state
+            .mmds_ns
+            .as_ref()
+            .map(|_| constructor_args.mmds.get_or_create(mmds_id));
         let mut net = Net::new_with_tap(
             state.id.clone(),
             state.tap_if_name.clone(),
             None,
             rx_rate_limiter,
             tx_rate_limiter,
-            state.mmds_ns.is_some(),
+            mmds.clone(),
+            // A zero MTU means it was never configured.
+            Some(state.config_space.mtu).filter(|&mtu| mtu != 0),
         )
         .map_err(Error::CreateNet)?;
+        net.set_mmds_id(state.mmds_id.clone());
 
         // Safe to unwrap because MmdsNetworkStack::restore() cannot fail.
-        net.mmds_ns = state
-            .mmds_ns
-            .as_ref()
-            .map(|mmds_state| MmdsNetworkStack::restore((), &mmds_state).unwrap());
-
-        // Safe to unwrap because Queue::restore() cannot fail.
-        net.queues = state
-            .virtio_state
-            .queues
-            .iter()
-            .map(|queue_state| Queue::restore((), &queue_state).unwrap())
-            .collect();
+        net.mmds_ns = state.mmds_ns.as_ref().and_then(|mmds_state| {
+            mmds.map(|mmds| MmdsNetworkStack::restore(mmds, &mmds_state).unwrap())
+        });
+
+        // The control queue is only saved if it was negotiated by the driver, otherwise
+        // the default one created above is kept.
+        for (queue, queue_state) in net.queues.iter_mut().zip(&state.virtio_state.queues) {
+            // Safe to unwrap because Queue::restore() cannot fail.
+            *queue = Queue::restore((), queue_state).unwrap();
+        }
         net.interrupt_status = Arc::new(AtomicUsize::new(state.virtio_state.interrupt_status));
         net.avail_features = state.virtio_state.avail_features;
         net.acked_features = state.virtio_state.acked_features;
         net.config_space = ConfigSpace {
             guest_mac: state.config_space.guest_mac,
+            status: state.config_space.status,
+            mtu: state.config_space.mtu,
+            ..Default::default()
         };
 
         net.guest_mac = Some(MacAddr::from_bytes_unchecked(
@@ -124,11 +173,41 @@ mod tests {
 
     use std::sync::atomic::Ordering;
 
+    // The version map of the snapshots which save the link state, the MTU and the MMDS
+    // instance of the devices.
+    fn version_map() -> VersionMap {
+        let mut version_map = VersionMap::new();
+        version_map
+            .new_version()
+            .set_type_version(NetConfigSpaceState::type_id(), 2)
+            .set_type_version(NetState::type_id(), 2)
+            .set_type_version(RateLimiterState::type_id(), 2)
+            .set_type_version(MmdsNetworkStackState::type_id(), 2);
+        version_map
+    }
+
+    // The layout of the net device state saved by Firecracker v0.23.
+    #[derive(Versionize)]
+    struct NetConfigSpaceStateV1 {
+        guest_mac: [u8; MAC_ADDR_LEN],
+    }
+
+    #[derive(Versionize)]
+    struct NetStateV1 {
+        id: String,
+        tap_if_name: String,
+        rx_rate_limiter_state: RateLimiterState,
+        tx_rate_limiter_state: RateLimiterState,
+        mmds_ns: Option<MmdsNetworkStackState>,
+        config_space: NetConfigSpaceStateV1,
+        virtio_state: VirtioDeviceState,
+    }
+
     #[test]
     fn test_persistence() {
         let guest_mem = Net::default_guest_memory();
         let mut mem = vec![0; 4096];
-        let version_map = VersionMap::new();
+        let version_map = version_map();
 
         let id;
         let tap_if_name;
@@ -138,10 +217,11 @@ mod tests {
         // Create and save the net device.
         {
             let mut net = Net::default_net(TestMutators::default());
+            net.set_mmds_id(Some("mmds_1".to_string()));
             net.activate(guest_mem.clone()).unwrap();
 
             <Net as Persist>::save(&net)
-                .serialize(&mut mem.as_mut_slice(), &version_map, 1)
+                .serialize(&mut mem.as_mut_slice(), &version_map, 2)
                 .unwrap();
 
             // Save some fields that we want to check later.
@@ -153,11 +233,14 @@ mod tests {
 
         // Deserialize and restore the net device.
         {
-            let restored_net = Net::restore(
+            let mmds = MmdsInstances::default();
+            let mut restored_net = Net::restore(
                 NetConstructorArgs {
                     mem: guest_mem.clone(),
+                    mmds: mmds.clone(),
+                    rate_limiter_groups: RestoredGroups::default(),
                 },
-                &NetState::deserialize(&mut mem.as_slice(), &version_map, 1).unwrap(),
+                &NetState::deserialize(&mut mem.as_slice(), &version_map, 2).unwrap(),
             )
             .unwrap();
 
@@ -175,8 +258,64 @@ mod tests {
             assert_eq!(&restored_net.id, &id);
             assert_eq!(&restored_net.tap_if_name, &tap_if_name);
             assert_eq!(restored_net.mmds_ns.is_some(), allow_mmds_requests);
+            // The device is bound to the same MMDS instance.
+            assert_eq!(restored_net.mmds_id(), Some("mmds_1"));
+            assert!(Arc::ptr_eq(
+                restored_net.mmds_ns_mut().unwrap().mmds(),
+                &mmds.get(Some("mmds_1")).unwrap()
+            ));
             assert_eq!(restored_net.rx_rate_limiter, RateLimiter::default());
             assert_eq!(restored_net.tx_rate_limiter, RateLimiter::default());
+            assert!(restored_net.link_up());
+        }
+    }
+
+    #[test]
+    fn test_restore_v1_state() {
+        let guest_mem = Net::default_guest_memory();
+        let mut blob = Vec::new();
+        {
+            let mut net = Net::default_net(TestMutators::default());
+            net.activate(guest_mem.clone()).unwrap();
+            let state = <Net as Persist>::save(&net);
+            NetStateV1 {
+                id: state.id,
+                tap_if_name: state.tap_if_name,
+                rx_rate_limiter_state: state.rx_rate_limiter_state,
+                tx_rate_limiter_state: state.tx_rate_limiter_state,
+                mmds_ns: state.mmds_ns,
+                config_space: NetConfigSpaceStateV1 {
+                    guest_mac: state.config_space.guest_mac,
+                },
+                virtio_state: state.virtio_state,
+            }
+            .serialize(&mut blob, &version_map(), 1)
+            .unwrap();
         }
+
+        // The whole v1 blob is read.
+        let mut reader = blob.as_slice();
+        let state = NetState::deserialize(&mut reader, &version_map(), 1).unwrap();
+        assert!(reader.is_empty());
+        let mmds = MmdsInstances::default();
+        let mut restored_net = Net::restore(
+            NetConstructorArgs {
+                mem: guest_mem,
+                mmds: mmds.clone(),
+                rate_limiter_groups: RestoredGroups::default(),
+            },
+            &state,
+        )
+        .unwrap();
+
+        // The link is up, the MTU isn't configured and the default MMDS instance answers.
+        assert!(restored_net.link_up());
+        assert_eq!(restored_net.config_space.mtu, 0);
+        assert_eq!(restored_net.mmds_id(), None);
+        assert!(Arc::ptr_eq(
+            restored_net.mmds_ns_mut().unwrap().mmds(),
+            &mmds.default_instance()
+        ));
+        assert!(restored_net.rx_rate_limiter.group().is_none());
     }
 }
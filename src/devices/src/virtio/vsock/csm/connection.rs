@@ -13,6 +13,11 @@
 /// - `VsockEpollListener` for getting notified about the availability of data or free buffer
 ///   space at the host stream.
 ///
+/// SOCK_SEQPACKET connections work the same way, except that the host stream is expected to
+/// preserve message boundaries (i.e. each `read()` yields, and each `write()` sends, exactly one
+/// message). Messages are fragmented into as many RW packets as needed, the last of which is
+/// marked with the VSOCK_FLAGS_SEQ_EOM flag.
+///
 /// Note: there is a certain asymmetry to the RX and TX data flows:
 ///       - RX transfers do not need any data buffering, since data is read straight from the
 ///         host stream and into the guest-provided RX buffer;
@@ -83,14 +88,15 @@ use std::num::Wrapping;
 use std::os::unix::io::{AsRawFd, RawFd};
 use std::time::{Duration, Instant};
 
+use logger::{Metric, METRICS};
 use utils::epoll::EventSet;
 
 use super::super::defs::uapi;
 use super::super::packet::VsockPacket;
 use super::super::{Result as VsockResult, VsockChannel, VsockEpollListener, VsockError};
 use super::defs;
-use super::txbuf::TxBuf;
-use super::{ConnState, Error, PendingRx, PendingRxSet, Result};
+use super::txbuf::{TxBuf, TxMsgBuf};
+use super::{ConnInfo, ConnState, Error, PendingRx, PendingRxSet, Result};
 
 /// A self-managing connection object, that handles communication between a guest-side AF_VSOCK
 /// socket and a host-side `Read + Write + AsRawFd` stream.
@@ -105,10 +111,17 @@ pub struct VsockConnection<S: Read + Write + AsRawFd> {
     local_port: u32,
     /// The peer (guest) port.
     peer_port: u32,
+    /// The vsock socket type (VSOCK_TYPE_STREAM or VSOCK_TYPE_SEQPACKET).
+    sock_type: u16,
     /// The (connected) host-side stream.
     stream: S,
     /// The TX buffer for this connection.
     tx_buf: TxBuf,
+    /// The TX buffer for this connection, if it is a SOCK_SEQPACKET one.
+    tx_msg_buf: TxMsgBuf,
+    /// The part of the last message read from the host stream that is yet to be sent to the
+    /// peer (SOCK_SEQPACKET connections only).
+    rx_msg: Vec<u8>,
     /// Total number of bytes that have been successfully written to `self.stream`, either
     /// directly, or flushed from `self.tx_buf`.
     fwd_cnt: Wrapping<u32>,
@@ -207,11 +220,19 @@ where
         // Oh wait, before we start bringing in the big data, can our peer handle receiving so
         // much bytey goodness?
         if self.need_credit_update_from_peer() {
+            METRICS.vsock.conns_credit_starved.inc();
             self.last_fwd_cnt_to_peer = self.fwd_cnt;
             pkt.set_op(uapi::VSOCK_OP_CREDIT_REQUEST);
             return Ok(());
         }
 
+        if self.is_seqpacket() {
+            self.fill_msg_pkt(pkt)?;
+            self.rx_cnt += Wrapping(pkt.len());
+            self.last_fwd_cnt_to_peer = self.fwd_cnt;
+            return Ok(());
+        }
+
         let buf = pkt.buf_mut().ok_or(VsockError::PktBufMissing)?;
 
         // The maximum amount of data we can read in is limited by both the RX buffer size and
@@ -222,16 +243,8 @@ where
         match self.stream.read(&mut buf[..max_len]) {
             Ok(read_cnt) => {
                 if read_cnt == 0 {
-                    // A 0-length read means the host stream was closed down. In that case,
-                    // we'll ask our peer to shut down the connection. We can neither send nor
-                    // receive any more data.
-                    self.state = ConnState::LocalClosed;
-                    self.expiry = Some(
-                        Instant::now() + Duration::from_millis(defs::CONN_SHUTDOWN_TIMEOUT_MS),
-                    );
-                    pkt.set_op(uapi::VSOCK_OP_SHUTDOWN)
-                        .set_flag(uapi::VSOCK_FLAGS_SHUTDOWN_RCV)
-                        .set_flag(uapi::VSOCK_FLAGS_SHUTDOWN_SEND);
+                    // A 0-length read means the host stream was closed down.
+                    self.close_local(pkt);
                 } else {
                     // On a successful data read, we fill in the packet with the RW op, and
                     // length of the read data.
@@ -267,6 +280,13 @@ where
         self.peer_buf_alloc = pkt.buf_alloc();
         self.peer_fwd_cnt = Wrapping(pkt.fwd_cnt());
 
+        // If we were waiting for credit to send the rest of a message, we can carry on. No
+        // epoll event would tell us so, since the message has already been read from the host
+        // stream.
+        if !self.rx_msg.is_empty() && !self.need_credit_update_from_peer() {
+            self.pending_rx.insert(PendingRx::Rw);
+        }
+
         match self.state {
             // Most frequent case: this is an established connection that needs to forward some
             // data to the host stream. Also works for a connection that has begun shutting
@@ -284,7 +304,12 @@ where
 
                 // Unwrapping here is safe, since we just checked `pkt.buf()` above.
                 let buf_slice = &pkt.buf().unwrap()[..(pkt.len() as usize)];
-                if let Err(err) = self.send_bytes(buf_slice) {
+                let res = if self.is_seqpacket() {
+                    self.send_msg(buf_slice, pkt.flags() & uapi::VSOCK_FLAGS_SEQ_EOM != 0)
+                } else {
+                    self.send_bytes(buf_slice)
+                };
+                if let Err(err) = res {
                     // If we can't write to the host stream, that's an unrecoverable error, so
                     // we'll terminate this connection.
                     warn!(
@@ -320,7 +345,7 @@ where
                 let send_off = pkt.flags() & uapi::VSOCK_FLAGS_SHUTDOWN_SEND != 0;
                 self.state = ConnState::PeerClosed(recv_off, send_off);
                 if recv_off && send_off {
-                    if self.tx_buf.is_empty() {
+                    if self.tx_buf_is_empty() {
                         self.pending_rx.insert(PendingRx::Rst);
                     } else {
                         self.expiry = Some(
@@ -337,7 +362,7 @@ where
             {
                 *recv_off = *recv_off || (pkt.flags() & uapi::VSOCK_FLAGS_SHUTDOWN_RCV != 0);
                 *send_off = *send_off || (pkt.flags() & uapi::VSOCK_FLAGS_SHUTDOWN_SEND != 0);
-                if *recv_off && *send_off && self.tx_buf.is_empty() {
+                if *recv_off && *send_off && self.tx_buf_is_empty() {
                     self.pending_rx.insert(PendingRx::Rst);
                 }
             }
@@ -401,7 +426,7 @@ where
     /// - data can be written to the host stream, and the TX buffer needs to be flushed.
     fn get_polled_evset(&self) -> EventSet {
         let mut evset = EventSet::empty();
-        if !self.tx_buf.is_empty() {
+        if !self.tx_buf_is_empty() {
             // There's data waiting in the TX buffer, so we are interested in being notified
             // when writing to the host stream wouldn't block.
             evset.insert(EventSet::OUT);
@@ -427,26 +452,28 @@ where
         if evset.contains(EventSet::OUT) {
             // Data can be written to the host stream. Time to flush out the TX buffer.
             //
-            if self.tx_buf.is_empty() {
+            if self.tx_buf_is_empty() {
                 info!("vsock: connection received unexpected EPOLLOUT event");
                 return;
             }
-            let flushed = self
-                .tx_buf
-                .flush_to(&mut self.stream)
-                .unwrap_or_else(|err| {
-                    warn!(
-                        "vsock: error flushing TX buf for (lp={}, pp={}): {:?}",
-                        self.local_port, self.peer_port, err
-                    );
-                    self.kill();
-                    0
-                });
+            let flushed = if self.is_seqpacket() {
+                self.tx_msg_buf.flush_to(&mut self.stream)
+            } else {
+                self.tx_buf.flush_to(&mut self.stream)
+            };
+            let flushed = flushed.unwrap_or_else(|err| {
+                warn!(
+                    "vsock: error flushing TX buf for (lp={}, pp={}): {:?}",
+                    self.local_port, self.peer_port, err
+                );
+                self.kill();
+                0
+            });
             self.fwd_cnt += Wrapping(flushed as u32);
 
             // If this connection was shutting down, but is waiting to drain the TX buffer
             // before forceful termination, the wait might be over.
-            if self.state == ConnState::PeerClosed(true, true) && self.tx_buf.is_empty() {
+            if self.state == ConnState::PeerClosed(true, true) && self.tx_buf_is_empty() {
                 self.pending_rx.insert(PendingRx::Rst);
             } else if self.peer_needs_credit_update() {
                 // If we've freed up some more buffer space, we may need to let the peer know it
@@ -475,9 +502,12 @@ where
             peer_cid,
             local_port,
             peer_port,
+            sock_type: uapi::VSOCK_TYPE_STREAM,
             stream,
             state: ConnState::PeerInit,
             tx_buf: TxBuf::new(),
+            tx_msg_buf: TxMsgBuf::new(),
+            rx_msg: Vec::new(),
             fwd_cnt: Wrapping(0),
             peer_buf_alloc,
             peer_fwd_cnt: Wrapping(0),
@@ -501,9 +531,12 @@ where
             peer_cid,
             local_port,
             peer_port,
+            sock_type: uapi::VSOCK_TYPE_STREAM,
             stream,
             state: ConnState::LocalInit,
             tx_buf: TxBuf::new(),
+            tx_msg_buf: TxMsgBuf::new(),
+            rx_msg: Vec::new(),
             fwd_cnt: Wrapping(0),
             peer_buf_alloc: 0,
             peer_fwd_cnt: Wrapping(0),
@@ -514,6 +547,18 @@ where
         }
     }
 
+    /// Turn this connection into a SOCK_SEQPACKET one. The host stream must then preserve
+    /// message boundaries.
+    pub fn into_seqpacket(mut self) -> Self {
+        self.sock_type = uapi::VSOCK_TYPE_SEQPACKET;
+        self
+    }
+
+    /// Check if this is a SOCK_SEQPACKET connection.
+    pub fn is_seqpacket(&self) -> bool {
+        self.sock_type == uapi::VSOCK_TYPE_SEQPACKET
+    }
+
     /// Check if there is an expiry (kill) timer set for this connection, sometime in the
     /// future.
     pub fn will_expire(&self) -> bool {
@@ -547,8 +592,12 @@ where
     /// Send some raw data (a byte-slice) to the host stream.
     ///
     /// Raw data can either be sent straight to the host stream, or to our TX buffer, if the
-    /// former fails.
+    /// former fails. On SOCK_SEQPACKET connections, the data is sent as a single message.
     pub fn send_bytes(&mut self, buf: &[u8]) -> Result<()> {
+        if self.is_seqpacket() {
+            return self.send_msg(buf, true);
+        }
+
         // If there is data in the TX buffer, that means we're already registered for EPOLLOUT
         // events on the underlying stream. Therefore, there's no point in attempting a write
         // at this point. `self.notify()` will get called when EPOLLOUT arrives, and it will
@@ -583,11 +632,104 @@ where
         Ok(())
     }
 
+    /// Send a message fragment to the host stream. `eom` marks the last fragment of a message.
+    ///
+    /// Fragments are buffered until their message is complete, since the host stream expects
+    /// whole messages. Complete messages are then flushed out right away, if the host stream can
+    /// take them.
+    fn send_msg(&mut self, buf: &[u8], eom: bool) -> Result<()> {
+        self.tx_msg_buf.push(buf, eom)?;
+        if eom {
+            let flushed = self.tx_msg_buf.flush_to(&mut self.stream)?;
+            self.fwd_cnt += Wrapping(flushed as u32);
+        }
+        Ok(())
+    }
+
+    /// Fill in an RW packet with the next fragment of a host message, reading a new message
+    /// from the host stream if needed (SOCK_SEQPACKET connections only).
+    fn fill_msg_pkt(&mut self, pkt: &mut VsockPacket) -> VsockResult<()> {
+        let buf_len = pkt.buf().ok_or(VsockError::PktBufMissing)?.len();
+
+        if self.rx_msg.is_empty() {
+            self.rx_msg.resize(defs::CONN_MAX_MSG_SIZE, 0);
+            match self.stream.read(&mut self.rx_msg) {
+                Ok(read_cnt) => self.rx_msg.truncate(read_cnt),
+                Err(err) => {
+                    self.rx_msg.clear();
+                    error!(
+                        "vsock: error reading from backing stream: lp={}, pp={}, err={:?}",
+                        self.local_port, self.peer_port, err
+                    );
+                    pkt.set_op(uapi::VSOCK_OP_RST);
+                    return Ok(());
+                }
+            }
+            if self.rx_msg.is_empty() {
+                // A 0-length read means the host stream was closed down.
+                self.close_local(pkt);
+                return Ok(());
+            }
+        }
+
+        // The fragment length is limited by both the RX buffer size and the peer available
+        // buffer space.
+        let len = std::cmp::min(
+            std::cmp::min(buf_len, self.peer_avail_credit()),
+            self.rx_msg.len(),
+        );
+        // Unwrapping here is safe, since we've already checked `pkt.buf()` above.
+        pkt.buf_mut().unwrap()[..len].copy_from_slice(&self.rx_msg[..len]);
+        self.rx_msg.drain(..len);
+        pkt.set_op(uapi::VSOCK_OP_RW).set_len(len as u32);
+
+        if self.rx_msg.is_empty() {
+            pkt.set_flag(uapi::VSOCK_FLAGS_SEQ_EOM);
+        } else if !self.need_credit_update_from_peer() {
+            // There's more of this message to send, and the peer can take it.
+            self.pending_rx.insert(PendingRx::Rw);
+        } else {
+            METRICS.vsock.conns_credit_starved.inc();
+        }
+
+        Ok(())
+    }
+
+    /// Handle the host stream closing down: we'll ask our peer to shut down the connection,
+    /// since we can neither send nor receive any more data.
+    fn close_local(&mut self, pkt: &mut VsockPacket) {
+        self.state = ConnState::LocalClosed;
+        self.expiry = Some(Instant::now() + Duration::from_millis(defs::CONN_SHUTDOWN_TIMEOUT_MS));
+        pkt.set_op(uapi::VSOCK_OP_SHUTDOWN)
+            .set_flag(uapi::VSOCK_FLAGS_SHUTDOWN_RCV)
+            .set_flag(uapi::VSOCK_FLAGS_SHUTDOWN_SEND);
+    }
+
+    /// Check if the TX buffer holds any data that still needs to be flushed to the host stream.
+    fn tx_buf_is_empty(&self) -> bool {
+        self.tx_buf.is_empty() && self.tx_msg_buf.is_empty()
+    }
+
     /// Return the connections state.
     pub fn state(&self) -> ConnState {
         self.state
     }
 
+    /// Get a snapshot of the connection state and flow control counters.
+    pub fn info(&self) -> ConnInfo {
+        ConnInfo {
+            local_port: self.local_port,
+            peer_port: self.peer_port,
+            seqpacket: self.is_seqpacket(),
+            state: self.state,
+            tx_buf_len: self.tx_buf.len() + self.tx_msg_buf.len(),
+            rx_in_flight: (self.rx_cnt - self.peer_fwd_cnt).0,
+            peer_credit: self.peer_avail_credit(),
+            rx_cnt: self.rx_cnt.0,
+            fwd_cnt: self.fwd_cnt.0,
+        }
+    }
+
     /// Check if the credit information the peer has last received from us is outdated.
     fn peer_needs_credit_update(&self) -> bool {
         (self.fwd_cnt - self.last_fwd_cnt_to_peer).0 as usize >= defs::CONN_CREDIT_UPDATE_THRESHOLD
@@ -618,7 +760,7 @@ where
             .set_dst_cid(self.peer_cid)
             .set_src_port(self.local_port)
             .set_dst_port(self.peer_port)
-            .set_type(uapi::VSOCK_TYPE_STREAM)
+            .set_type(self.sock_type)
             .set_buf_alloc(defs::CONN_TX_BUF_SIZE as u32)
             .set_fwd_cnt(self.fwd_cnt.0)
     }
@@ -916,6 +1058,45 @@ mod tests {
         assert_eq!(ctx.pkt.op(), uapi::VSOCK_OP_RST);
     }
 
+    #[test]
+    fn test_conn_info() {
+        let mut ctx = CsmTestContext::new_established();
+        let info = ctx.conn.info();
+        assert_eq!(info.local_port, LOCAL_PORT);
+        assert_eq!(info.peer_port, PEER_PORT);
+        assert!(!info.seqpacket);
+        assert_eq!(info.state, ConnState::Established);
+        assert_eq!(info.tx_buf_len, 0);
+        assert_eq!(info.rx_in_flight, 0);
+        assert_eq!(info.peer_credit, PEER_BUF_ALLOC as usize);
+
+        // Data sent to the guest is in flight, until the guest reports it as consumed.
+        let data = &[1, 2, 3, 4];
+        ctx.set_stream(TestStream::new_with_read_buf(data));
+        ctx.notify_epollin();
+        ctx.recv();
+        let info = ctx.conn.info();
+        assert_eq!(info.rx_cnt, data.len() as u32);
+        assert_eq!(info.rx_in_flight, data.len() as u32);
+        assert_eq!(info.peer_credit, PEER_BUF_ALLOC as usize - data.len());
+
+        // Guest data that can't be written to the host stream is accounted for in the TX buffer.
+        let mut stream = TestStream::new();
+        stream.write_state = StreamState::WouldBlock;
+        ctx.set_stream(stream);
+        ctx.init_data_pkt(data);
+        ctx.send();
+        let info = ctx.conn.info();
+        assert_eq!(info.tx_buf_len, data.len());
+        assert_eq!(info.fwd_cnt, 0);
+
+        ctx.set_stream(TestStream::new());
+        ctx.notify_epollout();
+        let info = ctx.conn.info();
+        assert_eq!(info.tx_buf_len, 0);
+        assert_eq!(info.fwd_cnt, data.len() as u32);
+    }
+
     #[test]
     fn test_local_close() {
         let mut ctx = CsmTestContext::new_established();
@@ -1168,4 +1349,73 @@ mod tests {
         ctx.recv();
         assert_eq!(ctx.pkt.op(), uapi::VSOCK_OP_RST);
     }
+
+    #[test]
+    fn test_seqpacket() {
+        let mut ctx = CsmTestContext::new_established();
+        ctx.conn = ctx.conn.into_seqpacket();
+        assert!(ctx.conn.is_seqpacket());
+
+        // Test case: host messages larger than the RX buffer are fragmented, and the last
+        // fragment is marked with the EOM flag.
+        let buf_len = ctx.pkt.buf().unwrap().len();
+        let data: Vec<u8> = (0..(buf_len + 4)).map(|i| i as u8).collect();
+        ctx.set_stream(TestStream::new_with_read_buf(data.as_slice()));
+        ctx.notify_epollin();
+        ctx.recv();
+        assert_eq!(ctx.pkt.op(), uapi::VSOCK_OP_RW);
+        assert_eq!(ctx.pkt.type_(), uapi::VSOCK_TYPE_SEQPACKET);
+        assert_eq!(ctx.pkt.len() as usize, buf_len);
+        assert_eq!(ctx.pkt.flags() & uapi::VSOCK_FLAGS_SEQ_EOM, 0);
+        assert_eq!(ctx.pkt.buf().unwrap(), &data[..buf_len]);
+        // The rest of the message is still pending, even without any EPOLLIN event.
+        assert!(ctx.conn.has_pending_rx());
+        ctx.recv();
+        assert_eq!(ctx.pkt.op(), uapi::VSOCK_OP_RW);
+        assert_eq!(ctx.pkt.len(), 4);
+        assert_ne!(ctx.pkt.flags() & uapi::VSOCK_FLAGS_SEQ_EOM, 0);
+        assert_eq!(&ctx.pkt.buf().unwrap()[..4], &data[buf_len..]);
+        assert!(!ctx.conn.has_pending_rx());
+
+        // Test case: a fragment cut short by the peer credit is resumed after a credit update.
+        ctx.set_stream(TestStream::new_with_read_buf(&[1, 2, 3, 4]));
+        ctx.set_peer_credit(2);
+        ctx.notify_epollin();
+        ctx.recv();
+        assert_eq!(ctx.pkt.len(), 2);
+        assert_eq!(ctx.pkt.flags() & uapi::VSOCK_FLAGS_SEQ_EOM, 0);
+        assert!(!ctx.conn.has_pending_rx());
+        let peer_fwd_cnt = ctx.conn.rx_cnt.0;
+        ctx.init_pkt(uapi::VSOCK_OP_CREDIT_UPDATE, 0)
+            .set_fwd_cnt(peer_fwd_cnt);
+        ctx.send();
+        assert!(ctx.conn.has_pending_rx());
+        ctx.recv();
+        assert_eq!(ctx.pkt.len(), 2);
+        assert_ne!(ctx.pkt.flags() & uapi::VSOCK_FLAGS_SEQ_EOM, 0);
+        assert_eq!(&ctx.pkt.buf().unwrap()[..2], &[3, 4]);
+
+        // Test case: guest message fragments are only written to the host stream once the
+        // message is complete.
+        ctx.init_data_pkt(&[1, 2]);
+        ctx.send();
+        assert!(ctx.conn.stream.write_buf.is_empty());
+        ctx.init_data_pkt(&[3, 4]);
+        ctx.pkt.set_flag(uapi::VSOCK_FLAGS_SEQ_EOM);
+        ctx.send();
+        assert_eq!(ctx.conn.stream.write_buf, [1, 2, 3, 4]);
+        assert_eq!(ctx.conn.fwd_cnt.0, 4);
+
+        // Test case: complete messages are buffered while the host stream would block.
+        ctx.conn.stream.write_state = StreamState::WouldBlock;
+        ctx.init_data_pkt(&[5, 6]);
+        ctx.pkt.set_flag(uapi::VSOCK_FLAGS_SEQ_EOM);
+        ctx.send();
+        assert!(ctx.conn.get_polled_evset().contains(EventSet::OUT));
+        ctx.conn.stream.write_state = StreamState::Ready;
+        ctx.notify_epollout();
+        assert!(ctx.conn.tx_msg_buf.is_empty());
+        assert_eq!(ctx.conn.stream.write_buf, [1, 2, 3, 4, 5, 6]);
+        assert_eq!(ctx.conn.fwd_cnt.0, 6);
+    }
 }
@@ -21,6 +21,10 @@ pub mod defs {
 
     /// Connection graceful shutdown timeout, in millis.
     pub const CONN_SHUTDOWN_TIMEOUT_MS: u64 = 2000;
+
+    /// Maximum size of a message read from a SOCK_SEQPACKET host socket. Longer messages are
+    /// truncated.
+    pub const CONN_MAX_MSG_SIZE: usize = 64 * 1024;
 }
 
 #[derive(Debug)]
@@ -29,6 +33,9 @@ pub enum Error {
     TxBufFull,
     /// An I/O error occurred, when attempting to flush the connection TX buffer.
     TxBufFlush(std::io::Error),
+    /// A message of `len` bytes was cut short to `written` bytes, when flushed out of the
+    /// connection TX buffer.
+    TxMsgTruncated { len: usize, written: usize },
     /// An I/O error occurred, when attempting to write data to the host-side stream.
     StreamWrite(std::io::Error),
 }
@@ -54,6 +61,29 @@ pub enum ConnState {
     Killed,
 }
 
+/// A snapshot of the state of a vsock connection, used for introspection.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ConnInfo {
+    /// The host-side port.
+    pub local_port: u32,
+    /// The guest-side port.
+    pub peer_port: u32,
+    /// Whether this is a SOCK_SEQPACKET connection.
+    pub seqpacket: bool,
+    /// The connection state.
+    pub state: ConnState,
+    /// Number of bytes received from the guest, still waiting to be flushed to the host stream.
+    pub tx_buf_len: usize,
+    /// Number of bytes sent to the guest, that the guest hasn't yet reported as consumed.
+    pub rx_in_flight: u32,
+    /// Number of bytes that can still be sent to the guest, without overflowing its buffer.
+    pub peer_credit: usize,
+    /// Total number of bytes sent to the guest (modulo 2^32).
+    pub rx_cnt: u32,
+    /// Total number of bytes forwarded from the guest to the host stream (modulo 2^32).
+    pub fwd_cnt: u32,
+}
+
 /// An RX indication, used by `VsockConnection` to schedule future `recv_pkt()` responses.
 /// For instance, after being notified that there is available data to be read from the host stream
 /// (via `notify()`), the connection will store a `PendingRx::Rw` to be later inspected by
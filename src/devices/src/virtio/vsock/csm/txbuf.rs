@@ -2,7 +2,8 @@
 // SPDX-License-Identifier: Apache-2.0
 //
 
-use std::io::Write;
+use std::collections::VecDeque;
+use std::io::{ErrorKind, Write};
 use std::num::Wrapping;
 
 use super::defs;
@@ -131,6 +132,91 @@ impl TxBuf {
     }
 }
 
+/// A message-preserving buffer, used by SOCK_SEQPACKET connections to buffer TX (guest -> host)
+/// data. Guest messages can span several packets, so each message is assembled here until its
+/// last fragment arrives, and is then flushed out with a single write.
+pub struct TxMsgBuf {
+    /// Complete messages, waiting to be flushed out.
+    msgs: VecDeque<Vec<u8>>,
+    /// The message being assembled.
+    partial: Vec<u8>,
+    /// Number of bytes held by this buffer, including the partial message.
+    len: usize,
+}
+
+impl TxMsgBuf {
+    /// Total buffer size, in bytes.
+    const SIZE: usize = defs::CONN_TX_BUF_SIZE;
+
+    /// Message buffer constructor.
+    pub fn new() -> Self {
+        Self {
+            msgs: VecDeque::new(),
+            partial: Vec::new(),
+            len: 0,
+        }
+    }
+
+    /// Get the used length of this buffer - number of bytes that have been pushed in, but not
+    /// yet flushed out.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Push a message fragment onto the buffer. `eom` marks the last fragment of a message.
+    ///
+    /// Either the entire fragment will be pushed to the buffer, or none of it, if there isn't
+    /// enough room, in which case `Err(Error::TxBufFull)` is returned.
+    pub fn push(&mut self, src: &[u8], eom: bool) -> Result<()> {
+        if self.len + src.len() > Self::SIZE {
+            return Err(Error::TxBufFull);
+        }
+
+        self.partial.extend_from_slice(src);
+        self.len += src.len();
+        if eom {
+            self.msgs
+                .push_back(std::mem::replace(&mut self.partial, Vec::new()));
+        }
+
+        Ok(())
+    }
+
+    /// Flush the complete messages to a writable sink, one write per message, until the sink
+    /// would block.
+    ///
+    /// Return the number of bytes that have been transferred out of the buffer and into the
+    /// writable sink, or `Err(Error::TxMsgTruncated)` if the sink only took part of a message.
+    pub fn flush_to<W>(&mut self, sink: &mut W) -> Result<usize>
+    where
+        W: Write,
+    {
+        let mut flushed = 0;
+        while let Some(msg) = self.msgs.front() {
+            match sink.write(msg) {
+                Ok(written) if written == msg.len() => (),
+                Ok(written) => {
+                    return Err(Error::TxMsgTruncated {
+                        len: msg.len(),
+                        written,
+                    })
+                }
+                Err(ref err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) => return Err(Error::TxBufFlush(err)),
+            }
+            flushed += msg.len();
+            self.len -= msg.len();
+            self.msgs.pop_front();
+        }
+        Ok(flushed)
+    }
+
+    /// Check if the buffer holds any complete message that hasn't yet been flushed out.
+    pub fn is_empty(&self) -> bool {
+        self.msgs.is_empty()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -259,4 +345,48 @@ mod tests {
             other => panic!("Unexpected result: {:?}", other),
         }
     }
+
+    #[test]
+    fn test_msg_buf() {
+        let mut msgbuf = TxMsgBuf::new();
+        let mut sink = TestSink::new();
+        assert!(msgbuf.is_empty());
+
+        // Fragments are held back until the message is complete.
+        msgbuf.push(&[1, 2], false).unwrap();
+        assert!(msgbuf.is_empty());
+        assert_eq!(msgbuf.flush_to(&mut sink).unwrap(), 0);
+        msgbuf.push(&[3, 4], true).unwrap();
+        msgbuf.push(&[5, 6], true).unwrap();
+        assert!(!msgbuf.is_empty());
+        assert_eq!(msgbuf.len(), 6);
+
+        // A sink that would block keeps the messages buffered.
+        sink.set_err(IoError::new(ErrorKind::WouldBlock, "EAGAIN"));
+        assert_eq!(msgbuf.flush_to(&mut sink).unwrap(), 0);
+        assert_eq!(msgbuf.len(), 6);
+
+        assert_eq!(msgbuf.flush_to(&mut sink).unwrap(), 6);
+        assert!(msgbuf.is_empty());
+        assert_eq!(msgbuf.len(), 0);
+        assert_eq!(sink.data, [1, 2, 3, 4, 5, 6]);
+
+        // A message that doesn't fit the sink in a single write is an error.
+        msgbuf.push(&[7, 8, 9, 10], true).unwrap();
+        sink.clear();
+        sink.set_capacity(2);
+        match msgbuf.flush_to(&mut sink) {
+            Err(Error::TxMsgTruncated { len: 4, written: 2 }) => (),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+        msgbuf = TxMsgBuf::new();
+
+        // Partial messages count towards the buffer capacity.
+        let tmp = vec![0u8; TxMsgBuf::SIZE - 1];
+        msgbuf.push(tmp.as_slice(), false).unwrap();
+        match msgbuf.push(&[1, 2], true) {
+            Err(Error::TxBufFull) => (),
+            other => panic!("Unexpected result: {:?}", other),
+        }
+    }
 }
@@ -23,9 +23,10 @@ use std::result;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 
+use logger::{Metric, METRICS};
 use utils::byte_order;
 use utils::eventfd::EventFd;
-use vm_memory::GuestMemoryMmap;
+use vm_memory::{Bytes, GuestMemoryMmap};
 
 use super::super::super::Error as DeviceError;
 use super::super::{
@@ -44,6 +45,8 @@ pub(crate) const EVQ_INDEX: usize = 2;
 /// - VIRTIO_F_VERSION_1: the device conforms to at least version 1.0 of the VirtIO spec.
 /// - VIRTIO_F_IN_ORDER: the device returns used buffers in the same order that the driver makes
 ///   them available.
+///
+/// The backend may add its own features, e.g. VIRTIO_VSOCK_F_SEQPACKET.
 pub(crate) const AVAIL_FEATURES: u64 =
     1 << uapi::VIRTIO_F_VERSION_1 as u64 | 1 << uapi::VIRTIO_F_IN_ORDER as u64;
 
@@ -63,6 +66,9 @@ pub struct Vsock<B> {
     // continuous triggers from happening before the device gets activated.
     pub(crate) activate_evt: EventFd,
     pub(crate) device_state: DeviceState,
+    // Set when a transport reset event needs to be delivered to the driver, which only happens
+    // once the driver makes an event queue buffer available.
+    pub(crate) pending_transport_reset: bool,
 }
 
 // TODO: Detect / handle queue deadlock:
@@ -88,13 +94,14 @@ where
             cid,
             queues,
             queue_events,
+            avail_features: AVAIL_FEATURES | backend.avail_features(),
             backend,
-            avail_features: AVAIL_FEATURES,
             acked_features: 0,
             interrupt_status: Arc::new(AtomicUsize::new(0)),
             interrupt_evt: EventFd::new(libc::EFD_NONBLOCK).map_err(VsockError::EventFd)?,
             activate_evt: EventFd::new(libc::EFD_NONBLOCK).map_err(VsockError::EventFd)?,
             device_state: DeviceState::Inactive,
+            pending_transport_reset: false,
         })
     }
 
@@ -119,6 +126,10 @@ where
         &self.backend
     }
 
+    pub fn backend_mut(&mut self) -> &mut B {
+        &mut self.backend
+    }
+
     /// Signal the guest driver that we've used some virtio buffers that it had previously made
     /// available.
     pub fn signal_used_queue(&self) -> result::Result<(), DeviceError> {
@@ -148,6 +159,8 @@ where
             let used_len = match VsockPacket::from_rx_virtq_head(&head) {
                 Ok(mut pkt) => {
                     if self.backend.recv_pkt(&mut pkt).is_ok() {
+                        METRICS.vsock.rx_packets_count.inc();
+                        METRICS.vsock.rx_bytes_count.add(pkt.len() as usize);
                         pkt.hdr().len() as u32 + pkt.len()
                     } else {
                         // We are using a consuming iterator over the virtio buffers, so, if we can't
@@ -158,6 +171,7 @@ where
                 }
                 Err(e) => {
                     warn!("vsock: RX queue error: {:?}", e);
+                    METRICS.vsock.rx_fails.inc();
                     0
                 }
             };
@@ -187,6 +201,7 @@ where
                 Ok(pkt) => pkt,
                 Err(e) => {
                     error!("vsock: error reading TX packet: {:?}", e);
+                    METRICS.vsock.tx_fails.inc();
                     have_used = true;
                     self.queues[TXQ_INDEX].add_used(mem, head.index, 0);
                     continue;
@@ -197,6 +212,8 @@ where
                 self.queues[TXQ_INDEX].undo_pop();
                 break;
             }
+            METRICS.vsock.tx_packets_count.inc();
+            METRICS.vsock.tx_bytes_count.add(pkt.len() as usize);
 
             have_used = true;
             self.queues[TXQ_INDEX].add_used(mem, head.index, 0);
@@ -204,6 +221,59 @@ where
 
         have_used
     }
+
+    /// Walk the driver-provided event queue buffers and deliver the pending transport reset
+    /// event, if any. Return `true` if descriptors have been added to the used ring, and `false`
+    /// otherwise.
+    pub fn process_evq(&mut self) -> bool {
+        debug!("vsock: process_evq()");
+        let mem = match self.device_state {
+            DeviceState::Activated(ref mem) => mem,
+            DeviceState::Inactive => return false,
+        };
+
+        let mut have_used = false;
+
+        while self.pending_transport_reset {
+            let head = match self.queues[EVQ_INDEX].pop(mem) {
+                Some(head) => head,
+                None => break,
+            };
+
+            let used_len = if !head.is_write_only() || head.len < uapi::VIRTIO_VSOCK_EVENT_SIZE {
+                warn!("vsock: invalid event queue buffer");
+                0
+            } else {
+                match mem.write_obj(uapi::VIRTIO_VSOCK_EVENT_TRANSPORT_RESET.to_le(), head.addr) {
+                    Ok(()) => {
+                        METRICS.vsock.transport_resets.inc();
+                        self.pending_transport_reset = false;
+                        uapi::VIRTIO_VSOCK_EVENT_SIZE
+                    }
+                    Err(e) => {
+                        warn!("vsock: failed to write transport reset event: {:?}", e);
+                        0
+                    }
+                }
+            };
+
+            have_used = true;
+            self.queues[EVQ_INDEX].add_used(mem, head.index, used_len);
+        }
+
+        have_used
+    }
+
+    /// Ask the guest driver to drop all its connections, by sending it a transport reset event.
+    /// If the device isn't active, or the driver hasn't made any event queue buffer available,
+    /// the event is delivered as soon as possible.
+    pub fn send_transport_reset_event(&mut self) -> result::Result<(), DeviceError> {
+        self.pending_transport_reset = true;
+        if self.process_evq() {
+            self.signal_used_queue()?;
+        }
+        Ok(())
+    }
 }
 
 impl<B> VirtioDevice for Vsock<B>
@@ -255,15 +325,19 @@ where
             4 if data.len() == 4 => {
                 byte_order::write_le_u32(data, ((self.cid() >> 32) & 0xffff_ffff) as u32)
             }
-            _ => warn!(
-                "vsock: virtio-vsock received invalid read request of {} bytes at offset {}",
-                data.len(),
-                offset
-            ),
+            _ => {
+                METRICS.vsock.cfg_fails.inc();
+                warn!(
+                    "vsock: virtio-vsock received invalid read request of {} bytes at offset {}",
+                    data.len(),
+                    offset
+                )
+            }
         }
     }
 
     fn write_config(&mut self, offset: u64, data: &[u8]) {
+        METRICS.vsock.cfg_fails.inc();
         warn!(
             "vsock: guest driver attempted to write device config (offset={:x}, len={:x})",
             offset,
@@ -273,6 +347,7 @@ where
 
     fn activate(&mut self, mem: GuestMemoryMmap) -> ActivateResult {
         if self.queues.len() != defs::NUM_QUEUES {
+            METRICS.vsock.activate_fails.inc();
             error!(
                 "Cannot perform activate. Expected {} queue(s), got {}",
                 defs::NUM_QUEUES,
@@ -282,10 +357,12 @@ where
         }
 
         if self.activate_evt.write(1).is_err() {
+            METRICS.vsock.activate_fails.inc();
             error!("Cannot write to activate_evt",);
             return Err(ActivateError::BadActivate);
         }
 
+        self.backend.set_acked_features(self.acked_features);
         self.device_state = DeviceState::Activated(mem);
 
         Ok(())
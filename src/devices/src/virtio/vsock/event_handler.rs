@@ -24,6 +24,7 @@
 ///   - again, attempt to fetch any incoming packets queued by the backend into virtio RX buffers.
 use std::os::unix::io::AsRawFd;
 
+use logger::{Metric, METRICS};
 use polly::event_manager::{EventManager, Subscriber};
 use utils::epoll::{EpollEvent, EventSet};
 
@@ -47,6 +48,7 @@ where
         let mut raise_irq = false;
         if let Err(e) = self.queue_events[RXQ_INDEX].read() {
             error!("Failed to get vsock rx queue event: {:?}", e);
+            METRICS.vsock.rx_queue_event_fails.inc();
         } else if self.backend.has_pending_rx() {
             raise_irq |= self.process_rx();
         }
@@ -65,6 +67,7 @@ where
         let mut raise_irq = false;
         if let Err(e) = self.queue_events[TXQ_INDEX].read() {
             error!("Failed to get vsock tx queue event: {:?}", e);
+            METRICS.vsock.tx_queue_event_fails.inc();
         } else {
             raise_irq |= self.process_tx();
             // The backend may have queued up responses to the packets we sent during
@@ -88,8 +91,11 @@ where
 
         if let Err(e) = self.queue_events[EVQ_INDEX].read() {
             error!("Failed to consume vsock evq event: {:?}", e);
+            METRICS.vsock.ev_queue_event_fails.inc();
         }
-        false
+
+        // The driver made new event buffers available, so we can deliver any pending event.
+        self.process_evq()
     }
 
     fn notify_backend(&mut self, event: &EpollEvent) -> bool {
@@ -409,6 +415,101 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_transport_reset_event() {
+        use crate::virtio::VIRTQ_DESC_F_WRITE;
+        use vm_memory::{Bytes, GuestAddress};
+
+        const EVENT_ADDR: u64 = 0x0060_0000;
+
+        // Test case: the event is delivered right away, when an event buffer is available.
+        {
+            let test_ctx = TestContext::new();
+            let mut ctx = test_ctx.create_event_handler_context();
+            ctx.guest_evvq.dtable[0].set(EVENT_ADDR, 4, VIRTQ_DESC_F_WRITE, 0);
+            ctx.guest_evvq.avail.ring[0].set(0);
+            ctx.guest_evvq.avail.idx.set(1);
+            test_ctx
+                .mem
+                .write_obj(0xffff_ffffu32, GuestAddress(EVENT_ADDR))
+                .unwrap();
+            ctx.mock_activate(test_ctx.mem.clone());
+
+            ctx.device.send_transport_reset_event().unwrap();
+            assert!(!ctx.device.pending_transport_reset);
+            assert_eq!(ctx.guest_evvq.used.idx.get(), 1);
+            assert_eq!(ctx.guest_evvq.used.ring[0].get().len, 4);
+            assert_eq!(
+                test_ctx
+                    .mem
+                    .read_obj::<u32>(GuestAddress(EVENT_ADDR))
+                    .unwrap(),
+                uapi::VIRTIO_VSOCK_EVENT_TRANSPORT_RESET
+            );
+            assert_eq!(ctx.device.interrupt_evt.read().unwrap(), 1);
+        }
+
+        // Test case: the event is delivered once the driver makes an event buffer available.
+        {
+            let test_ctx = TestContext::new();
+            let mut ctx = test_ctx.create_event_handler_context();
+            ctx.mock_activate(test_ctx.mem.clone());
+
+            ctx.device.send_transport_reset_event().unwrap();
+            assert!(ctx.device.pending_transport_reset);
+            assert_eq!(ctx.guest_evvq.used.idx.get(), 0);
+
+            ctx.guest_evvq.dtable[0].set(EVENT_ADDR, 4, VIRTQ_DESC_F_WRITE, 0);
+            ctx.guest_evvq.avail.ring[0].set(0);
+            ctx.guest_evvq.avail.idx.set(1);
+            ctx.device.queue_events[EVQ_INDEX].write(1).unwrap();
+            assert!(ctx
+                .device
+                .handle_evq_event(&EpollEvent::new(EventSet::IN, 0)));
+            assert!(!ctx.device.pending_transport_reset);
+            assert_eq!(ctx.guest_evvq.used.idx.get(), 1);
+            assert_eq!(ctx.guest_evvq.used.ring[0].get().len, 4);
+        }
+
+        // Test case: invalid event buffers are returned unused.
+        {
+            let test_ctx = TestContext::new();
+            let mut ctx = test_ctx.create_event_handler_context();
+            ctx.guest_evvq.dtable[0].set(EVENT_ADDR, 4, 0, 0);
+            ctx.guest_evvq.dtable[1].set(EVENT_ADDR, 2, VIRTQ_DESC_F_WRITE, 0);
+            ctx.guest_evvq.avail.ring[0].set(0);
+            ctx.guest_evvq.avail.ring[1].set(1);
+            ctx.guest_evvq.avail.idx.set(2);
+            ctx.mock_activate(test_ctx.mem.clone());
+
+            ctx.device.send_transport_reset_event().unwrap();
+            assert!(ctx.device.pending_transport_reset);
+            assert_eq!(ctx.guest_evvq.used.idx.get(), 2);
+            assert_eq!(ctx.guest_evvq.used.ring[0].get().len, 0);
+            assert_eq!(ctx.guest_evvq.used.ring[1].get().len, 0);
+        }
+
+        // Test case: the guest cannot be notified of the delivered event.
+        {
+            let test_ctx = TestContext::new();
+            let mut ctx = test_ctx.create_event_handler_context();
+            ctx.guest_evvq.dtable[0].set(EVENT_ADDR, 4, VIRTQ_DESC_F_WRITE, 0);
+            ctx.guest_evvq.avail.ring[0].set(0);
+            ctx.guest_evvq.avail.idx.set(1);
+            ctx.mock_activate(test_ctx.mem.clone());
+            // A saturated interrupt counter makes the (non-blocking) write fail.
+            ctx.device.interrupt_evt.write(std::u64::MAX - 1).unwrap();
+
+            match ctx.device.send_transport_reset_event() {
+                Err(crate::Error::FailedSignalingUsedQueue(_)) => (),
+                other => panic!("Unexpected result: {:?}", other),
+            }
+            // The event itself was still delivered.
+            assert!(!ctx.device.pending_transport_reset);
+            assert_eq!(ctx.guest_evvq.used.idx.get(), 1);
+        }
+    }
+
     #[test]
     fn test_backend_event() {
         // Test case:
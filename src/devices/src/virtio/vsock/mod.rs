@@ -11,12 +11,19 @@ mod event_handler;
 mod packet;
 pub mod persist;
 mod unix;
+mod vhost;
 
 use std::os::unix::io::AsRawFd;
 
+pub use self::csm::{ConnInfo as VsockConnectionInfo, ConnState as VsockConnState};
 pub use self::defs::uapi::VIRTIO_ID_VSOCK as TYPE_VSOCK;
+pub use self::defs::VSOCK_DEV_ID;
 pub use self::device::Vsock;
-pub use self::unix::{Error as VsockUnixBackendError, VsockUnixBackend};
+pub use self::unix::{
+    seqpacket_sock_path as vsock_seqpacket_sock_path, Error as VsockUnixBackendError,
+    VsockUnixBackend, VsockUnixPortRoute, VsockUnixRoutes,
+};
+pub use self::vhost::{Error as VhostVsockError, VhostVsock};
 
 use utils::epoll::EventSet;
 use vm_memory::GuestMemoryError;
@@ -48,6 +55,12 @@ mod defs {
         /// The device conforms to the virtio spec version 1.0.
         pub const VIRTIO_F_VERSION_1: u32 = 32;
 
+        /// Virtio vsock feature flags.
+        /// Defined in `/include/uapi/linux/virtio_vsock.h`.
+        ///
+        /// The device supports SOCK_SEQPACKET connections.
+        pub const VIRTIO_VSOCK_F_SEQPACKET: u32 = 1;
+
         /// Virtio vsock device ID.
         /// Defined in `include/uapi/linux/virtio_ids.h`.
         pub const VIRTIO_ID_VSOCK: u32 = 19;
@@ -77,14 +90,29 @@ mod defs {
         pub const VSOCK_FLAGS_SHUTDOWN_RCV: u32 = 1;
         /// Valid with a VSOCK_OP_SHUTDOWN packet: the packet sender will send no more data.
         pub const VSOCK_FLAGS_SHUTDOWN_SEND: u32 = 2;
+        /// Valid with a VSOCK_OP_RW packet of a SOCK_SEQPACKET connection: the packet holds the
+        /// last fragment of a message.
+        pub const VSOCK_FLAGS_SEQ_EOM: u32 = 1;
 
         /// Vsock packet type.
         /// Defined in `/include/uapi/linux/virtio_vsock.h`.
         ///
-        /// Stream / connection-oriented packet (the only currently valid type).
+        /// Stream / connection-oriented packet.
         pub const VSOCK_TYPE_STREAM: u16 = 1;
+        /// Sequenced packet / message-oriented connection packet. Only valid if
+        /// VIRTIO_VSOCK_F_SEQPACKET was negotiated.
+        pub const VSOCK_TYPE_SEQPACKET: u16 = 2;
 
         pub const VSOCK_HOST_CID: u64 = 2;
+
+        /// Vsock event IDs.
+        /// Defined in `/include/uapi/linux/virtio_vsock.h`.
+        ///
+        /// The communication has been interrupted, e.g. by a live migration. The driver must
+        /// drop all its connections.
+        pub const VIRTIO_VSOCK_EVENT_TRANSPORT_RESET: u32 = 0;
+        /// Size of a `struct virtio_vsock_event`.
+        pub const VIRTIO_VSOCK_EVENT_SIZE: u32 = 4;
     }
 }
 
@@ -153,7 +181,17 @@ pub trait VsockChannel {
 /// The vsock backend, which is basically an epoll-event-driven vsock channel.
 /// Currently, the only implementation we have is `crate::virtio::unix::muxer::VsockMuxer`, which
 /// translates guest-side vsock connections to host-side Unix domain socket connections.
-pub trait VsockBackend: VsockChannel + VsockEpollListener + Send {}
+pub trait VsockBackend: VsockChannel + VsockEpollListener + Send {
+    /// Get the virtio vsock features (e.g. `VIRTIO_VSOCK_F_SEQPACKET`) supported by the backend,
+    /// on top of the ones always offered by the device.
+    fn avail_features(&self) -> u64 {
+        0
+    }
+
+    /// Notify the backend of the features acked by the guest driver, when the device is
+    /// activated.
+    fn set_acked_features(&mut self, _acked_features: u64) {}
+}
 
 #[cfg(test)]
 pub(crate) mod tests {
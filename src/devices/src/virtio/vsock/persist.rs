@@ -4,9 +4,10 @@
 //! Defines state and support structures for persisting Vsock devices and backends.
 
 use std::sync::atomic::AtomicUsize;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use super::*;
+use mmds::data_store::Mmds;
 use snapshot::Persist;
 use versionize::{VersionMap, Versionize, VersionizeError, VersionizeResult};
 use versionize_derive::Versionize;
@@ -39,6 +40,60 @@ pub enum VsockBackendState {
 pub struct VsockUdsState {
     /// The path for the UDS socket.
     pub(crate) path: String,
+    /// The routes of guest-initiated connections.
+    #[version(start = 2, default_fn = "default_guest_port_routes")]
+    pub(crate) guest_port_routes: Vec<VsockUdsPortRouteState>,
+    /// The guest ports that host-initiated connections may target.
+    #[version(start = 2, default_fn = "default_host_allowed_ports")]
+    pub(crate) host_allowed_ports: Option<Vec<u32>>,
+    /// The maximum number of active connections.
+    #[version(start = 2, default_fn = "default_max_connections")]
+    pub(crate) max_connections: u64,
+    /// Whether connection metrics are also recorded per port.
+    #[version(start = 2, default_fn = "default_per_port_metrics")]
+    pub(crate) per_port_metrics: bool,
+    /// Whether SOCK_SEQPACKET connections are enabled.
+    #[version(start = 2, default_fn = "default_seqpacket")]
+    pub(crate) seqpacket: bool,
+}
+
+impl VsockUdsState {
+    fn default_guest_port_routes(_: u16) -> Vec<VsockUdsPortRouteState> {
+        Vec::new()
+    }
+
+    fn default_host_allowed_ports(_: u16) -> Option<Vec<u32>> {
+        None
+    }
+
+    fn default_max_connections(_: u16) -> u64 {
+        unix::defs::DEFAULT_MAX_CONNECTIONS as u64
+    }
+
+    fn default_per_port_metrics(_: u16) -> bool {
+        false
+    }
+
+    fn default_seqpacket(_: u16) -> bool {
+        false
+    }
+}
+
+/// The serializable state of a guest port route. Connections are denied when neither a path,
+/// nor a TCP port is set, and the port isn't served by the MMDS.
+#[derive(Versionize)]
+pub struct VsockUdsPortRouteState {
+    port: u32,
+    path: Option<String>,
+    #[version(start = 2, default_fn = "default_tcp_port")]
+    tcp_port: Option<u16>,
+    mmds: bool,
+}
+
+impl VsockUdsPortRouteState {
+    fn default_tcp_port(_: u16) -> Option<u16> {
+        None
+    }
 }
 
 /// A helper structure that holds the constructor arguments for VsockUnixBackend
@@ -51,6 +106,8 @@ pub struct VsockConstructorArgs<B> {
 pub struct VsockUdsConstructorArgs {
     // cid available in VsockFrontendState.
     pub cid: u64,
+    /// The MMDS serving the guest ports routed to it.
+    pub mmds: Arc<Mutex<Mmds>>,
 }
 
 impl Persist<'_> for VsockUnixBackend {
@@ -59,8 +116,32 @@ impl Persist<'_> for VsockUnixBackend {
     type Error = VsockUnixBackendError;
 
     fn save(&self) -> Self::State {
+        let routes = self.routes();
         VsockBackendState::Uds(VsockUdsState {
             path: self.host_sock_path.clone(),
+            guest_port_routes: routes
+                .guest_ports
+                .iter()
+                .map(|(port, route)| VsockUdsPortRouteState {
+                    port: *port,
+                    path: match route {
+                        Some(VsockUnixPortRoute::Unix(path)) => Some(path.clone()),
+                        _ => None,
+                    },
+                    tcp_port: match route {
+                        Some(VsockUnixPortRoute::Tcp(tcp_port)) => Some(*tcp_port),
+                        _ => None,
+                    },
+                    mmds: route == &Some(VsockUnixPortRoute::Mmds),
+                })
+                .collect(),
+            host_allowed_ports: routes
+                .host_allowed_ports
+                .as_ref()
+                .map(|ports| ports.iter().cloned().collect()),
+            max_connections: self.max_connections() as u64,
+            per_port_metrics: self.per_port_metrics(),
+            seqpacket: self.seqpacket(),
         })
     }
 
@@ -69,10 +150,36 @@ impl Persist<'_> for VsockUnixBackend {
         state: &Self::State,
     ) -> std::result::Result<Self, Self::Error> {
         match state {
-            VsockBackendState::Uds(uds_state) => Ok(VsockUnixBackend::new(
-                constructor_args.cid,
-                uds_state.path.clone(),
-            )?),
+            VsockBackendState::Uds(uds_state) => {
+                let mut backend =
+                    VsockUnixBackend::new(constructor_args.cid, uds_state.path.clone())?;
+                backend.set_routes(VsockUnixRoutes {
+                    guest_ports: uds_state
+                        .guest_port_routes
+                        .iter()
+                        .map(|route| {
+                            let target = match (&route.path, route.tcp_port) {
+                                _ if route.mmds => Some(VsockUnixPortRoute::Mmds),
+                                (_, Some(tcp_port)) => Some(VsockUnixPortRoute::Tcp(tcp_port)),
+                                (Some(path), None) => Some(VsockUnixPortRoute::Unix(path.clone())),
+                                (None, None) => None,
+                            };
+                            (route.port, target)
+                        })
+                        .collect(),
+                    host_allowed_ports: uds_state
+                        .host_allowed_ports
+                        .as_ref()
+                        .map(|ports| ports.iter().cloned().collect()),
+                });
+                backend.set_max_connections(uds_state.max_connections as usize);
+                backend.set_per_port_metrics(uds_state.per_port_metrics);
+                backend.set_mmds(constructor_args.mmds);
+                if uds_state.seqpacket {
+                    backend.enable_seqpacket()?;
+                }
+                Ok(backend)
+            }
         }
     }
 }
@@ -111,6 +218,7 @@ where
 
         vsock.acked_features = state.virtio_state.acked_features;
         vsock.avail_features = state.virtio_state.avail_features;
+        vsock.backend.set_acked_features(vsock.acked_features);
         vsock.interrupt_status = Arc::new(AtomicUsize::new(state.virtio_state.interrupt_status));
         vsock.device_state = if state.virtio_state.activated {
             DeviceState::Activated(constructor_args.mem)
@@ -130,6 +238,27 @@ pub(crate) mod tests {
     use crate::virtio::vsock::defs::uapi;
     use utils::byte_order;
 
+    // The version map of the snapshots which save the settings of the Unix backend.
+    fn version_map() -> VersionMap {
+        let mut version_map = VersionMap::new();
+        version_map
+            .new_version()
+            .set_type_version(VsockUdsState::type_id(), 2)
+            .set_type_version(VsockUdsPortRouteState::type_id(), 2);
+        version_map
+    }
+
+    // The layout of the Unix backend state saved by Firecracker v0.23.
+    #[derive(Versionize)]
+    enum VsockBackendStateV1 {
+        Uds(VsockUdsStateV1),
+    }
+
+    #[derive(Versionize)]
+    struct VsockUdsStateV1 {
+        path: String,
+    }
+
     impl Persist<'_> for TestBackend {
         type State = VsockBackendState;
         type ConstructorArgs = VsockUdsConstructorArgs;
@@ -138,6 +267,11 @@ pub(crate) mod tests {
         fn save(&self) -> Self::State {
             VsockBackendState::Uds(VsockUdsState {
                 path: "test".to_owned(),
+                guest_port_routes: Vec::new(),
+                host_allowed_ports: None,
+                max_connections: 1023,
+                per_port_metrics: false,
+                seqpacket: false,
             })
         }
 
@@ -231,4 +365,89 @@ pub(crate) mod tests {
         restored_device.read_config(2, &mut data);
         assert_eq!(data, [0u8, 1, 2, 3, 4, 5, 6, 7]);
     }
+
+    #[test]
+    fn test_persist_uds_routes() {
+        let mut uds_path = utils::tempfile::TempFile::new().unwrap();
+        // Remove the file so the path can be used by the socket.
+        uds_path.remove().unwrap();
+        let uds_path = String::from(uds_path.as_path().to_str().unwrap());
+
+        let mut routes = VsockUnixRoutes::default();
+        routes.guest_ports.insert(
+            52,
+            Some(VsockUnixPortRoute::Unix("/tmp/agent.sock".to_owned())),
+        );
+        routes.guest_ports.insert(53, None);
+        routes
+            .guest_ports
+            .insert(54, Some(VsockUnixPortRoute::Tcp(8080)));
+        routes
+            .guest_ports
+            .insert(55, Some(VsockUnixPortRoute::Mmds));
+        routes.host_allowed_ports = Some([52, 1024].iter().cloned().collect());
+
+        let mut backend = VsockUnixBackend::new(3, uds_path.clone()).unwrap();
+        backend.set_routes(routes.clone());
+        backend.enable_seqpacket().unwrap();
+
+        let mut mem = vec![0; 4096];
+        let version_map = version_map();
+        backend
+            .save()
+            .serialize(&mut mem.as_mut_slice(), &version_map, 2)
+            .unwrap();
+        drop(backend);
+        std::fs::remove_file(&uds_path).unwrap();
+        std::fs::remove_file(vsock_seqpacket_sock_path(&uds_path)).unwrap();
+
+        let restored_backend = VsockUnixBackend::restore(
+            VsockUdsConstructorArgs {
+                cid: 3,
+                mmds: Arc::new(Mutex::new(Mmds::default())),
+            },
+            &VsockBackendState::deserialize(&mut mem.as_slice(), &version_map, 2).unwrap(),
+        )
+        .unwrap();
+        assert_eq!(restored_backend.routes(), &routes);
+        assert!(restored_backend.seqpacket());
+        std::fs::remove_file(&uds_path).unwrap();
+        std::fs::remove_file(vsock_seqpacket_sock_path(&uds_path)).unwrap();
+    }
+
+    #[test]
+    fn test_restore_v1_uds_state() {
+        let mut uds_path = utils::tempfile::TempFile::new().unwrap();
+        // Remove the file so the path can be used by the socket.
+        uds_path.remove().unwrap();
+        let uds_path = String::from(uds_path.as_path().to_str().unwrap());
+
+        let mut blob = Vec::new();
+        VsockBackendStateV1::Uds(VsockUdsStateV1 {
+            path: uds_path.clone(),
+        })
+        .serialize(&mut blob, &version_map(), 1)
+        .unwrap();
+
+        // The whole v1 blob is read, and the backend is restored with the default settings.
+        let mut reader = blob.as_slice();
+        let state = VsockBackendState::deserialize(&mut reader, &version_map(), 1).unwrap();
+        assert!(reader.is_empty());
+        let restored_backend = VsockUnixBackend::restore(
+            VsockUdsConstructorArgs {
+                cid: 3,
+                mmds: Arc::new(Mutex::new(Mmds::default())),
+            },
+            &state,
+        )
+        .unwrap();
+        assert_eq!(restored_backend.routes(), &VsockUnixRoutes::default());
+        assert_eq!(
+            restored_backend.max_connections(),
+            unix::defs::DEFAULT_MAX_CONNECTIONS
+        );
+        assert!(!restored_backend.per_port_metrics());
+        assert!(!restored_backend.seqpacket());
+        std::fs::remove_file(&uds_path).unwrap();
+    }
 }
@@ -0,0 +1,151 @@
+// Copyright 2020 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+
+/// The host-side end of a vsock connection mediated by the muxer.
+///
+/// Most connections are bridged to host Unix sockets, but guest-initiated connections can also be
+/// forwarded to TCP sockets listening on the host loopback interface, or served by the in-process
+/// MMDS. All stream types are non-blocking, and pollable through their file descriptors, so the
+/// connection state machine handles them alike.
+use std::io::{self, Read, Write};
+use std::net::{Ipv4Addr, TcpStream};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::UnixStream;
+
+use super::mmds_stream::MmdsStream;
+
+/// A host-side connection stream.
+#[derive(Debug)]
+pub enum HostStream {
+    /// A Unix socket stream (either `SOCK_STREAM` or `SOCK_SEQPACKET`).
+    Unix(UnixStream),
+    /// A TCP stream, connected to a port of the host loopback interface.
+    Tcp(TcpStream),
+    /// An in-process stream, served by the MMDS.
+    Mmds(MmdsStream),
+}
+
+impl HostStream {
+    /// Connect to a TCP socket listening on `port` of the host loopback interface.
+    pub fn connect_tcp(port: u16) -> io::Result<Self> {
+        let stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port))?;
+        // Vsock traffic is mostly made of small request / response exchanges, which shouldn't
+        // be delayed by Nagle's algorithm.
+        stream.set_nodelay(true)?;
+        Ok(HostStream::Tcp(stream))
+    }
+
+    /// Move the stream into (or out of) non-blocking mode.
+    pub fn set_nonblocking(&self, nonblocking: bool) -> io::Result<()> {
+        match self {
+            HostStream::Unix(stream) => stream.set_nonblocking(nonblocking),
+            HostStream::Tcp(stream) => stream.set_nonblocking(nonblocking),
+            // The MMDS stream never blocks.
+            HostStream::Mmds(_) => Ok(()),
+        }
+    }
+}
+
+impl From<UnixStream> for HostStream {
+    fn from(stream: UnixStream) -> Self {
+        HostStream::Unix(stream)
+    }
+}
+
+impl Read for HostStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            HostStream::Unix(stream) => stream.read(buf),
+            HostStream::Tcp(stream) => stream.read(buf),
+            HostStream::Mmds(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for HostStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            HostStream::Unix(stream) => stream.write(buf),
+            HostStream::Tcp(stream) => stream.write(buf),
+            HostStream::Mmds(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            HostStream::Unix(stream) => stream.flush(),
+            HostStream::Tcp(stream) => stream.flush(),
+            HostStream::Mmds(stream) => stream.flush(),
+        }
+    }
+}
+
+impl AsRawFd for HostStream {
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            HostStream::Unix(stream) => stream.as_raw_fd(),
+            HostStream::Tcp(stream) => stream.as_raw_fd(),
+            HostStream::Mmds(stream) => stream.as_raw_fd(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_tcp_host_stream() {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0)).unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        let mut stream = HostStream::connect_tcp(port).unwrap();
+        let (mut server, _) = listener.accept().unwrap();
+        match stream {
+            HostStream::Tcp(ref tcp) => {
+                assert!(tcp.nodelay().unwrap());
+                assert_eq!(tcp.peer_addr().unwrap().port(), port);
+            }
+            _ => panic!("Unexpected stream type"),
+        }
+
+        stream.write_all(&[1, 2, 3]).unwrap();
+        let mut buf = [0u8; 3];
+        server.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [1, 2, 3]);
+
+        server.write_all(&[4, 5]).unwrap();
+        let mut buf = [0u8; 2];
+        stream.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [4, 5]);
+
+        // Reading from an empty non-blocking stream would block.
+        stream.set_nonblocking(true).unwrap();
+        assert_eq!(
+            stream.read(&mut buf).unwrap_err().kind(),
+            io::ErrorKind::WouldBlock
+        );
+        assert!(stream.as_raw_fd() >= 0);
+    }
+
+    #[test]
+    fn test_unix_host_stream() {
+        let (a, mut b) = UnixStream::pair().unwrap();
+        let mut stream = HostStream::from(a);
+
+        stream.write_all(&[1, 2]).unwrap();
+        stream.flush().unwrap();
+        let mut buf = [0u8; 2];
+        b.read_exact(&mut buf).unwrap();
+        assert_eq!(buf, [1, 2]);
+
+        stream.set_nonblocking(true).unwrap();
+        assert_eq!(
+            stream.read(&mut buf).unwrap_err().kind(),
+            io::ErrorKind::WouldBlock
+        );
+    }
+}
@@ -0,0 +1,258 @@
+// Copyright 2020 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+
+/// `MmdsStream` is an in-process host stream, which serves the microVM metadata service (MMDS)
+/// to a guest-initiated vsock connection, without going through the `dumbo` network stack.
+///
+/// Bytes written to the stream are gathered into HTTP requests, which are handed over to
+/// `mmds::parse_request()`, along with the MMDS the stream was created for. The responses are
+/// buffered, until they are read back out of the stream. Since the muxer polls the host end of
+/// every connection, the stream exposes an `EventFd` as its file descriptor. The event is
+/// readable for as long as there is buffered response data, and always writable.
+///
+/// Long-poll requests, which wait for an update of the MMDS data store, are held in the request
+/// buffer. The same `EventFd` is written to by the MMDS on every update, after which reading
+/// from the stream retries the pending request.
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::{Arc, Mutex};
+
+use logger::{Metric, METRICS};
+use mmds::data_store::Mmds;
+use mmds::parse_request;
+use utils::eventfd::EventFd;
+
+// One plus the size of the largest HTTP request we are willing to accept. This is the same bound
+// that the MMDS network stack enforces.
+const REQUEST_BUF_MAX_SIZE: usize = 2500;
+
+/// Finds the end of the first HTTP 1.x request in `buf`, by looking for the double new line that
+/// ends its headers.
+fn find_request_end(buf: &[u8]) -> Option<usize> {
+    (0..buf.len()).find_map(|i| {
+        if buf[i] != b'\n' {
+            None
+        } else if buf[i + 1..].starts_with(b"\n") {
+            Some(i + 2)
+        } else if buf[i + 1..].starts_with(b"\r\n") {
+            Some(i + 3)
+        } else {
+            None
+        }
+    })
+}
+
+/// An in-process stream, connecting a guest vsock connection to the MMDS.
+pub struct MmdsStream {
+    /// Bytes written to the stream, which don't yet make up a whole request.
+    request_buf: Vec<u8>,
+    /// Response bytes, waiting to be read from the stream.
+    response_buf: Vec<u8>,
+    /// Readable whenever `response_buf` isn't empty, or the MMDS data store was updated.
+    response_evt: Arc<EventFd>,
+    /// The MMDS answering the requests.
+    mmds: Arc<Mutex<Mmds>>,
+}
+
+impl MmdsStream {
+    /// Create a new stream to `mmds`, with no pending requests.
+    pub fn new(mmds: Arc<Mutex<Mmds>>) -> io::Result<Self> {
+        let response_evt = Arc::new(EventFd::new(libc::EFD_NONBLOCK)?);
+        mmds.lock()
+            .expect("Poisoned lock")
+            .add_update_listener(&response_evt);
+        METRICS.mmds.connections_created.inc();
+        Ok(MmdsStream {
+            request_buf: Vec::new(),
+            response_buf: Vec::new(),
+            response_evt,
+            mmds,
+        })
+    }
+
+    /// Answer all the complete requests in the request buffer, up to the first long-poll request
+    /// which has to wait for an update of the data store.
+    fn process_requests(&mut self) -> io::Result<()> {
+        let had_response = !self.response_buf.is_empty();
+        while let Some(end) = find_request_end(&self.request_buf) {
+            let mut mmds = self.mmds.lock().expect("Poisoned lock");
+            let response = match parse_request(&mut mmds, &self.request_buf[..end]) {
+                Some(response) => response,
+                None => break,
+            };
+            METRICS.mmds.rx_count.inc();
+            // The unwrap is safe because a Vec will allocate more space until all the writes
+            // succeed.
+            response.write_all(&mut self.response_buf).unwrap();
+            self.request_buf.drain(..end);
+        }
+        if !had_response && !self.response_buf.is_empty() {
+            self.response_evt.write(1)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for MmdsStream {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("MmdsStream")
+            .field("request_buf", &self.request_buf)
+            .field("response_buf", &self.response_buf)
+            .field("response_evt", &self.response_evt)
+            .finish()
+    }
+}
+
+impl Read for MmdsStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.response_buf.is_empty() {
+            // The data store may have been updated, so a pending request might be answered now.
+            self.process_requests()?;
+        }
+        if self.response_buf.is_empty() {
+            // Consume the update notification, if any, since there's still nothing to read.
+            if let Err(e) = self.response_evt.read() {
+                if e.kind() != io::ErrorKind::WouldBlock {
+                    return Err(e);
+                }
+            }
+            return Err(io::Error::from(io::ErrorKind::WouldBlock));
+        }
+        let len = std::cmp::min(buf.len(), self.response_buf.len());
+        buf[..len].copy_from_slice(&self.response_buf[..len]);
+        self.response_buf.drain(..len);
+        if self.response_buf.is_empty() {
+            // Nothing left to read, so the muxer shouldn't be notified about this stream anymore.
+            self.response_evt.read()?;
+        }
+        METRICS.mmds.tx_count.inc();
+        METRICS.mmds.tx_bytes.add(len);
+        Ok(len)
+    }
+}
+
+impl Write for MmdsStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.request_buf.extend_from_slice(buf);
+        self.process_requests()?;
+        if self.request_buf.len() >= REQUEST_BUF_MAX_SIZE {
+            // We still couldn't identify the end of a request, so we are over the maximum
+            // request size. The connection gets reset, as it would by the MMDS network stack.
+            METRICS.mmds.rx_accepted_err.inc();
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "MMDS request too large",
+            ));
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl AsRawFd for MmdsStream {
+    fn as_raw_fd(&self) -> RawFd {
+        self.response_evt.as_raw_fd()
+    }
+}
+
+impl Drop for MmdsStream {
+    fn drop(&mut self) {
+        METRICS.mmds.connections_destroyed.inc();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_request_end() {
+        assert_eq!(find_request_end(b""), None);
+        assert_eq!(find_request_end(b"GET / HTTP/1.1\r\n"), None);
+        assert_eq!(find_request_end(b"GET / HTTP/1.1\r\n\r\n"), Some(18));
+        assert_eq!(find_request_end(b"GET / HTTP/1.1\n\nGET"), Some(16));
+    }
+
+    #[test]
+    fn test_mmds_stream() {
+        let mmds = Arc::new(Mutex::new(Mmds::default()));
+        let mut stream = MmdsStream::new(mmds.clone()).unwrap();
+        let mut buf = [0u8; 512];
+        assert_eq!(
+            stream.read(&mut buf).unwrap_err().kind(),
+            io::ErrorKind::WouldBlock
+        );
+
+        // A partial request doesn't yield a response.
+        let request = b"GET /vsock-mmds-test HTTP/1.1\r\nAccept: application/json\r\n\r\n";
+        assert_eq!(stream.write(&request[..10]).unwrap(), 10);
+        assert_eq!(
+            stream.read(&mut buf).unwrap_err().kind(),
+            io::ErrorKind::WouldBlock
+        );
+
+        // Completing the request makes the response available, and the event readable.
+        stream.write_all(&request[10..]).unwrap();
+        assert_eq!(stream.response_evt.read().unwrap(), 1);
+        stream.response_evt.write(1).unwrap();
+        let len = stream.read(&mut buf).unwrap();
+        let response = std::str::from_utf8(&buf[..len]).unwrap();
+        assert!(response.starts_with("HTTP/1.1 404"));
+        assert!(response.ends_with("Resource not found: /vsock-mmds-test."));
+        assert_eq!(
+            stream.response_evt.read().unwrap_err().kind(),
+            io::ErrorKind::WouldBlock
+        );
+
+        // Pipelined requests are answered in order, and the responses can be read in chunks.
+        stream
+            .write_all(b"GET /vsock-mmds-test HTTP/1.1\r\n\r\nPUT / HTTP/1.1\r\n\r\n")
+            .unwrap();
+        let mut response = Vec::new();
+        while let Ok(len) = stream.read(&mut buf[..16]) {
+            response.extend_from_slice(&buf[..len]);
+        }
+        let response = String::from_utf8(response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 404"));
+        assert!(response.contains("HTTP/1.1 405"));
+        assert!(response.ends_with("Not allowed HTTP method."));
+
+        // Long-poll requests are held until the data store is updated.
+        let version = mmds.lock().unwrap().data_store_version();
+        let request = format!(
+            "GET /vsock-mmds-test?wait=true&version={} HTTP/1.1\r\n\r\n",
+            version
+        );
+        stream.write_all(request.as_bytes()).unwrap();
+        assert_eq!(stream.request_buf, request.as_bytes());
+        assert_eq!(
+            stream.read(&mut buf).unwrap_err().kind(),
+            io::ErrorKind::WouldBlock
+        );
+        // Notifications which don't answer the request are consumed.
+        stream.response_evt.write(1).unwrap();
+        assert_eq!(
+            stream.read(&mut buf).unwrap_err().kind(),
+            io::ErrorKind::WouldBlock
+        );
+        assert_eq!(
+            stream.response_evt.read().unwrap_err().kind(),
+            io::ErrorKind::WouldBlock
+        );
+        stream.request_buf.clear();
+
+        // Oversized requests are rejected.
+        assert_eq!(
+            stream
+                .write(&[b'a'; REQUEST_BUF_MAX_SIZE])
+                .unwrap_err()
+                .kind(),
+            io::ErrorKind::InvalidData
+        );
+    }
+}
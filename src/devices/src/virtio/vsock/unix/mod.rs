@@ -3,19 +3,25 @@
 //
 
 /// This module implements the Unix Domain Sockets backend for vsock - a mediator between
-/// guest-side AF_VSOCK sockets and host-side AF_UNIX sockets. The heavy lifting is performed by
+/// guest-side AF_VSOCK sockets and host-side AF_UNIX sockets (or, for some guest ports, host
+/// loopback TCP sockets, or the in-process MMDS). The heavy lifting is performed by
 /// `muxer::VsockMuxer`, a connection multiplexer that uses `super::csm::VsockConnection` for
 /// handling vsock connection states.
 /// Check out `muxer.rs` for a more detailed explanation of the inner workings of this backend.
+mod host_stream;
+mod mmds_stream;
 mod muxer;
 mod muxer_killq;
 mod muxer_rxq;
+mod seqpacket;
 
 pub use muxer::VsockMuxer as VsockUnixBackend;
 
-mod defs {
-    /// Maximum number of established connections that we can handle.
-    pub const MAX_CONNECTIONS: usize = 1023;
+use std::collections::{HashMap, HashSet};
+
+pub(crate) mod defs {
+    /// Default maximum number of established connections that we can handle.
+    pub const DEFAULT_MAX_CONNECTIONS: usize = 1023;
 
     /// Size of the muxer RX packet queue.
     pub const MUXER_RXQ_SIZE: usize = 256;
@@ -24,14 +30,79 @@ mod defs {
     pub const MUXER_KILLQ_SIZE: usize = 128;
 }
 
+/// Get the path of the `SOCK_SEQPACKET` Unix socket through which a backend bound at `uds_path`
+/// accepts host-initiated SOCK_SEQPACKET connections.
+pub fn seqpacket_sock_path(uds_path: &str) -> String {
+    format!("{}_seqpacket", uds_path)
+}
+
+/// The host endpoint to which guest-initiated connections to a port are forwarded.
+#[derive(Clone, Debug, PartialEq)]
+pub enum VsockUnixPortRoute {
+    /// A host Unix socket, bound at the given path.
+    Unix(String),
+    /// A host TCP socket, listening on the given port of the loopback interface. Only
+    /// SOCK_STREAM connections can be forwarded to TCP sockets.
+    Tcp(u16),
+    /// The microVM metadata service, served in-process over HTTP. Only SOCK_STREAM connections
+    /// can be forwarded to the MMDS.
+    Mmds,
+}
+
+/// Routing rules for the connections mediated by the Unix backend.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct VsockUnixRoutes {
+    /// Host endpoints to which guest-initiated connections are forwarded, keyed by destination
+    /// port. `None` denies connections to that port. Connections to ports without an entry are
+    /// forwarded to `<uds_path>_<port>`.
+    pub guest_ports: HashMap<u32, Option<VsockUnixPortRoute>>,
+    /// Guest ports that host-initiated connections may target. All ports are allowed if unset.
+    pub host_allowed_ports: Option<HashSet<u32>>,
+}
+
+impl VsockUnixRoutes {
+    /// Checks whether the host may connect to the given guest port.
+    pub fn is_host_port_allowed(&self, port: u32) -> bool {
+        self.host_allowed_ports
+            .as_ref()
+            .map_or(true, |ports| ports.contains(&port))
+    }
+
+    /// Checks whether a rule names the given port, either as the destination of guest-initiated
+    /// connections, or as an allowed target of host-initiated ones.
+    pub fn has_rule(&self, port: u32) -> bool {
+        self.guest_ports.contains_key(&port)
+            || self
+                .host_allowed_ports
+                .as_ref()
+                .map_or(false, |ports| ports.contains(&port))
+    }
+
+    /// Checks whether some guest-initiated connections are forwarded to TCP sockets.
+    pub fn has_tcp_routes(&self) -> bool {
+        self.guest_ports.values().any(|route| match route {
+            Some(VsockUnixPortRoute::Tcp(_)) => true,
+            _ => false,
+        })
+    }
+}
+
 #[derive(Debug)]
 pub enum Error {
+    /// No active connection matches the given (local port, peer port) pair.
+    ConnectionNotFound(u32, u32),
     /// Error registering a new epoll-listening FD.
     EpollAdd(std::io::Error),
     /// Error creating an epoll FD.
     EpollFdCreate(std::io::Error),
     /// The host made an invalid vsock port connection request.
     InvalidPortRequest,
+    /// Error creating an in-process MMDS stream.
+    MmdsStream(std::io::Error),
+    /// The host attempted to connect to a guest port that is not allowed.
+    PortNotAllowed(u32),
+    /// Error connecting to a host-side TCP socket.
+    TcpConnect(std::io::Error),
     /// Error accepting a new connection from the host-side Unix socket.
     UnixAccept(std::io::Error),
     /// Error binding to the host-side Unix socket.
@@ -45,4 +116,4 @@ pub enum Error {
 }
 
 type Result<T> = std::result::Result<T, Error>;
-type MuxerConnection = super::csm::VsockConnection<std::os::unix::net::UnixStream>;
+type MuxerConnection = super::csm::VsockConnection<host_stream::HostStream>;
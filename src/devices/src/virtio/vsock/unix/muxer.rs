@@ -18,8 +18,8 @@
 ///    belong to an existing connection and, as such, the muxer simply forwards them.
 /// 2. Event dispatcher
 ///    There are three event categories that the vsock backend is interested it:
-///    1. A new host-initiated connection is ready to be accepted from the listening host Unix
-///       socket;
+///    1. A new host-initiated connection is ready to be accepted from one of the listening host
+///       Unix sockets (a SOCK_STREAM one, and a SOCK_SEQPACKET one);
 ///    2. Data is available for reading from a newly-accepted host-initiated connection (i.e.
 ///       the host is ready to issue a vsock connection request, informing us of the
 ///       destination port to which it wants to connect);
@@ -34,20 +34,26 @@ use std::collections::{HashMap, HashSet};
 use std::io::Read;
 use std::os::unix::io::{AsRawFd, RawFd};
 use std::os::unix::net::{UnixListener, UnixStream};
+use std::sync::{Arc, Mutex};
 
+use logger::{Metric, VsockPortMetrics, METRICS};
+use mmds::data_store::Mmds;
 use utils::epoll::{ControlOperation, Epoll, EpollEvent, EventSet};
 
-use super::super::csm::ConnState;
+use super::super::csm::{ConnInfo, ConnState};
 use super::super::defs::uapi;
 use super::super::packet::VsockPacket;
 use super::super::{
     Result as VsockResult, VsockBackend, VsockChannel, VsockEpollListener, VsockError,
 };
 use super::defs;
+use super::host_stream::HostStream;
+use super::mmds_stream::MmdsStream;
 use super::muxer_killq::MuxerKillQ;
 use super::muxer_rxq::MuxerRxQ;
+use super::seqpacket;
 use super::MuxerConnection;
-use super::{Error, Result};
+use super::{seqpacket_sock_path, Error, Result, VsockUnixPortRoute, VsockUnixRoutes};
 
 /// A unique identifier of a `MuxerConnection` object. Connections are stored in a hash map,
 /// keyed by a `ConnMapKey` object.
@@ -62,8 +68,12 @@ pub struct ConnMapKey {
 pub enum MuxerRx {
     /// The packet must be fetched from the connection identified by `ConnMapKey`.
     ConnRx(ConnMapKey),
-    /// The muxer must produce an RST packet.
-    RstPkt { local_port: u32, peer_port: u32 },
+    /// The muxer must produce an RST packet, of the given socket type.
+    RstPkt {
+        local_port: u32,
+        peer_port: u32,
+        sock_type: u16,
+    },
 }
 
 /// An epoll listener, registered under the muxer's nested epoll FD.
@@ -74,9 +84,13 @@ enum EpollListener {
     Connection { key: ConnMapKey, evset: EventSet },
     /// A listener interested in new host-initiated connections.
     HostSock,
+    /// A listener interested in new host-initiated SOCK_SEQPACKET connections.
+    HostSeqpacketSock,
     /// A listener interested in reading host "connect <port>" commands from a freshly
     /// connected host socket.
     LocalStream(UnixStream),
+    /// Same as `LocalStream`, for a SOCK_SEQPACKET host socket.
+    LocalSeqpacket(UnixStream),
 }
 
 /// The vsock connection multiplexer.
@@ -98,6 +112,13 @@ pub struct VsockMuxer {
     killq: MuxerKillQ,
     /// The Unix socket, through which host-initiated connections are accepted.
     host_sock: UnixListener,
+    /// The SOCK_SEQPACKET Unix socket, through which host-initiated SOCK_SEQPACKET connections
+    /// are accepted. It is bound at `seqpacket_sock_path(host_sock_path)`, once SOCK_SEQPACKET
+    /// support is enabled.
+    host_seqpacket_sock: Option<UnixListener>,
+    /// Whether the guest driver negotiated `VIRTIO_VSOCK_F_SEQPACKET`. SOCK_SEQPACKET
+    /// connections are refused otherwise.
+    seqpacket_negotiated: bool,
     /// The file system path of the host-side Unix socket. This is used to figure out the path
     /// to Unix sockets listening on specific ports. I.e. "<this path>_<port number>".
     pub(crate) host_sock_path: String,
@@ -108,6 +129,15 @@ pub struct VsockMuxer {
     local_port_set: HashSet<u32>,
     /// The last used host-side port.
     local_port_last: u32,
+    /// The routing rules for guest-initiated and host-initiated connections.
+    routes: VsockUnixRoutes,
+    /// The maximum number of connections that can be active at the same time.
+    max_connections: usize,
+    /// Whether connection metrics are also recorded per port.
+    per_port_metrics: bool,
+    /// The MMDS serving the connections to `VsockUnixPortRoute::Mmds` routes, which are refused
+    /// when it is not set.
+    mmds: Option<Arc<Mutex<Mmds>>>,
 }
 
 impl VsockChannel for VsockMuxer {
@@ -132,6 +162,7 @@ impl VsockChannel for VsockMuxer {
                 MuxerRx::RstPkt {
                     local_port,
                     peer_port,
+                    sock_type,
                 } => {
                     pkt.set_op(uapi::VSOCK_OP_RST)
                         .set_src_cid(uapi::VSOCK_HOST_CID)
@@ -139,7 +170,7 @@ impl VsockChannel for VsockMuxer {
                         .set_src_port(local_port)
                         .set_dst_port(peer_port)
                         .set_len(0)
-                        .set_type(uapi::VSOCK_TYPE_STREAM)
+                        .set_type(sock_type)
                         .set_flags(0)
                         .set_buf_alloc(0)
                         .set_fwd_cnt(0);
@@ -153,6 +184,10 @@ impl VsockChannel for VsockMuxer {
                     self.apply_conn_mutation(key, |conn| {
                         conn_res = conn.recv_pkt(pkt);
                     });
+                    if let (Ok(()), Some(metrics)) = (&conn_res, self.conn_port_metrics(key)) {
+                        metrics.rx_packets_count.inc();
+                        metrics.rx_bytes_count.add(pkt.len() as usize);
+                    }
                     conn_res
                 }
             };
@@ -162,10 +197,12 @@ impl VsockChannel for VsockMuxer {
                 // terminate and remove this connection from the active connection pool.
                 //
                 if pkt.op() == uapi::VSOCK_OP_RST {
-                    self.remove_connection(ConnMapKey {
+                    let key = ConnMapKey {
                         local_port: pkt.src_port(),
                         peer_port: pkt.dst_port(),
-                    });
+                    };
+                    self.count_conn_reset(key);
+                    self.remove_connection(key);
                 }
 
                 debug!("vsock muxer: RX pkt: {:?}", pkt.hdr());
@@ -196,10 +233,13 @@ impl VsockChannel for VsockMuxer {
             pkt.hdr()
         );
 
-        // If this packet has an unsupported type (!=stream), we must send back an RST.
+        // If this packet has an unsupported type (neither stream, nor negotiated seqpacket), we
+        // must send back an RST.
         //
-        if pkt.type_() != uapi::VSOCK_TYPE_STREAM {
-            self.enq_rst(pkt.dst_port(), pkt.src_port());
+        if pkt.type_() != uapi::VSOCK_TYPE_STREAM
+            && (pkt.type_() != uapi::VSOCK_TYPE_SEQPACKET || !self.seqpacket_negotiated)
+        {
+            self.enq_rst(pkt.dst_port(), pkt.src_port(), pkt.type_());
             return Ok(());
         }
 
@@ -222,7 +262,7 @@ impl VsockChannel for VsockMuxer {
                 self.handle_peer_request_pkt(&pkt);
             } else {
                 // Send back an RST, to let the drive know we weren't expecting this packet.
-                self.enq_rst(pkt.dst_port(), pkt.src_port());
+                self.enq_rst(pkt.dst_port(), pkt.src_port(), pkt.type_());
             }
             return Ok(());
         }
@@ -231,6 +271,7 @@ impl VsockChannel for VsockMuxer {
         // However, if this is an RST, we have to forcefully terminate the connection, so
         // there's no point in forwarding it the packet.
         if pkt.op() == uapi::VSOCK_OP_RST {
+            self.count_conn_reset(conn_key);
             self.remove_connection(conn_key);
             return Ok(());
         }
@@ -240,6 +281,10 @@ impl VsockChannel for VsockMuxer {
         self.apply_conn_mutation(conn_key, |conn| {
             res = conn.send_pkt(pkt);
         });
+        if let (Ok(()), Some(metrics)) = (&res, self.conn_port_metrics(conn_key)) {
+            metrics.tx_packets_count.inc();
+            metrics.tx_bytes_count.add(pkt.len() as usize);
+        }
 
         res
     }
@@ -297,7 +342,20 @@ impl VsockEpollListener for VsockMuxer {
     }
 }
 
-impl VsockBackend for VsockMuxer {}
+impl VsockBackend for VsockMuxer {
+    fn avail_features(&self) -> u64 {
+        if self.host_seqpacket_sock.is_some() {
+            1 << uapi::VIRTIO_VSOCK_F_SEQPACKET as u64
+        } else {
+            0
+        }
+    }
+
+    fn set_acked_features(&mut self, acked_features: u64) {
+        self.seqpacket_negotiated =
+            acked_features & (1 << uapi::VIRTIO_VSOCK_F_SEQPACKET as u64) != 0;
+    }
+}
 
 impl VsockMuxer {
     /// Muxer constructor.
@@ -311,14 +369,20 @@ impl VsockMuxer {
         let mut muxer = Self {
             cid,
             host_sock,
+            host_seqpacket_sock: None,
+            seqpacket_negotiated: false,
             host_sock_path,
             epoll: Epoll::new().map_err(Error::EpollFdCreate)?,
             rxq: MuxerRxQ::new(),
-            conn_map: HashMap::with_capacity(defs::MAX_CONNECTIONS),
-            listener_map: HashMap::with_capacity(defs::MAX_CONNECTIONS + 1),
+            conn_map: HashMap::with_capacity(defs::DEFAULT_MAX_CONNECTIONS),
+            listener_map: HashMap::with_capacity(defs::DEFAULT_MAX_CONNECTIONS + 1),
             killq: MuxerKillQ::new(),
             local_port_last: (1u32 << 30) - 1,
-            local_port_set: HashSet::with_capacity(defs::MAX_CONNECTIONS),
+            local_port_set: HashSet::with_capacity(defs::DEFAULT_MAX_CONNECTIONS),
+            routes: VsockUnixRoutes::default(),
+            max_connections: defs::DEFAULT_MAX_CONNECTIONS,
+            per_port_metrics: false,
+            mmds: None,
         };
 
         // Listen on the host initiated socket, for incomming connections.
@@ -326,6 +390,106 @@ impl VsockMuxer {
         Ok(muxer)
     }
 
+    /// Enable SOCK_SEQPACKET connections, by binding the host socket through which
+    /// host-initiated ones are accepted, at `seqpacket_sock_path(host_sock_path)`. The
+    /// `VIRTIO_VSOCK_F_SEQPACKET` feature is only offered to the guest once this is done.
+    pub fn enable_seqpacket(&mut self) -> Result<()> {
+        if self.host_seqpacket_sock.is_some() {
+            return Ok(());
+        }
+        let sock = seqpacket::bind(&seqpacket_sock_path(&self.host_sock_path))
+            .and_then(|sock| sock.set_nonblocking(true).map(|_| sock))
+            .map_err(Error::UnixBind)?;
+        self.add_listener(sock.as_raw_fd(), EpollListener::HostSeqpacketSock)?;
+        self.host_seqpacket_sock = Some(sock);
+        Ok(())
+    }
+
+    /// Check if SOCK_SEQPACKET connections are enabled.
+    pub fn seqpacket(&self) -> bool {
+        self.host_seqpacket_sock.is_some()
+    }
+
+    /// Set the routing rules applied to new connections.
+    pub fn set_routes(&mut self, routes: VsockUnixRoutes) {
+        self.routes = routes;
+    }
+
+    /// Get the routing rules applied to new connections.
+    pub fn routes(&self) -> &VsockUnixRoutes {
+        &self.routes
+    }
+
+    /// Set the maximum number of connections that can be active at the same time. Connections
+    /// above the limit are refused. Already active connections are left untouched.
+    pub fn set_max_connections(&mut self, max_connections: usize) {
+        self.max_connections = max_connections;
+    }
+
+    /// Get the maximum number of connections that can be active at the same time.
+    pub fn max_connections(&self) -> usize {
+        self.max_connections
+    }
+
+    /// Enable or disable the recording of connection metrics per port. Connections are
+    /// accounted to their service port, i.e. the guest port of host-initiated connections, and
+    /// the host port of guest-initiated ones.
+    pub fn set_per_port_metrics(&mut self, per_port_metrics: bool) {
+        self.per_port_metrics = per_port_metrics;
+    }
+
+    /// Check if connection metrics are also recorded per port.
+    pub fn per_port_metrics(&self) -> bool {
+        self.per_port_metrics
+    }
+
+    /// Set the MMDS which serves the connections to `VsockUnixPortRoute::Mmds` routes.
+    pub fn set_mmds(&mut self, mmds: Arc<Mutex<Mmds>>) {
+        self.mmds = Some(mmds);
+    }
+
+    /// Get a snapshot of the state of all the active connections.
+    pub fn connections(&self) -> Vec<ConnInfo> {
+        self.conn_map.values().map(|conn| conn.info()).collect()
+    }
+
+    /// Forcefully terminate the connection identified by `local_port` and `peer_port`.
+    ///
+    /// The host-side stream is closed right away, and an RST packet is queued for the guest.
+    pub fn reset_connection(&mut self, local_port: u32, peer_port: u32) -> Result<()> {
+        let key = ConnMapKey {
+            local_port,
+            peer_port,
+        };
+        let sock_type = match self.conn_map.get(&key) {
+            Some(conn) if conn.is_seqpacket() => uapi::VSOCK_TYPE_SEQPACKET,
+            Some(_) => uapi::VSOCK_TYPE_STREAM,
+            None => return Err(Error::ConnectionNotFound(local_port, peer_port)),
+        };
+
+        info!(
+            "vsock: resetting connection (lp={}, pp={})",
+            local_port, peer_port
+        );
+        self.count_conn_reset(key);
+        self.remove_connection(key);
+        self.enq_rst(local_port, peer_port, sock_type);
+        Ok(())
+    }
+
+    /// Drop all the active connections, without notifying the guest.
+    ///
+    /// This is meant to be used alongside a transport reset event, which makes the guest driver
+    /// drop its side of all the connections on its own.
+    pub fn clear_connections(&mut self) {
+        let keys: Vec<ConnMapKey> = self.conn_map.keys().copied().collect();
+        for key in keys {
+            self.remove_connection(key);
+        }
+        self.rxq = MuxerRxQ::new();
+        self.killq = MuxerKillQ::new();
+    }
+
     /// Handle/dispatch an epoll event to its listener.
     fn handle_event(&mut self, fd: RawFd, evset: EventSet) {
         debug!(
@@ -349,59 +513,20 @@ impl VsockMuxer {
             }
 
             // A new host-initiated connection is ready to be accepted.
-            Some(EpollListener::HostSock) => {
-                if self.conn_map.len() == defs::MAX_CONNECTIONS {
-                    // If we're already maxed-out on connections, we'll just accept and
-                    // immediately discard this potentially new one.
-                    warn!("vsock: connection limit reached; refusing new host connection");
-                    self.host_sock.accept().map(|_| 0).unwrap_or(0);
-                    return;
-                }
-                self.host_sock
-                    .accept()
-                    .map_err(Error::UnixAccept)
-                    .and_then(|(stream, _)| {
-                        stream
-                            .set_nonblocking(true)
-                            .map(|_| stream)
-                            .map_err(Error::UnixAccept)
-                    })
-                    .and_then(|stream| {
-                        // Before forwarding this connection to a listening AF_VSOCK socket on
-                        // the guest side, we need to know the destination port. We'll read
-                        // that port from a "connect" command received on this socket, so the
-                        // next step is to ask to be notified the moment we can read from it.
-                        self.add_listener(stream.as_raw_fd(), EpollListener::LocalStream(stream))
-                    })
-                    .unwrap_or_else(|err| {
-                        warn!("vsock: unable to accept local connection: {:?}", err);
-                    });
-            }
+            Some(EpollListener::HostSock) => self.accept_local_connection(false),
+            Some(EpollListener::HostSeqpacketSock) => self.accept_local_connection(true),
 
             // Data is ready to be read from a host-initiated connection. That would be the
             // "connect" command that we're expecting.
-            Some(EpollListener::LocalStream(_)) => {
-                if let Some(EpollListener::LocalStream(mut stream)) = self.remove_listener(fd) {
-                    Self::read_local_stream_port(&mut stream)
-                        .and_then(|peer_port| Ok((self.allocate_local_port(), peer_port)))
-                        .and_then(|(local_port, peer_port)| {
-                            self.add_connection(
-                                ConnMapKey {
-                                    local_port,
-                                    peer_port,
-                                },
-                                MuxerConnection::new_local_init(
-                                    stream,
-                                    uapi::VSOCK_HOST_CID,
-                                    self.cid,
-                                    local_port,
-                                    peer_port,
-                                ),
-                            )
-                        })
-                        .unwrap_or_else(|err| {
-                            info!("vsock: error adding local-init connection: {:?}", err);
-                        })
+            Some(EpollListener::LocalStream(_)) | Some(EpollListener::LocalSeqpacket(_)) => {
+                match self.remove_listener(fd) {
+                    Some(EpollListener::LocalStream(stream)) => {
+                        self.handle_local_connection(stream, false)
+                    }
+                    Some(EpollListener::LocalSeqpacket(stream)) => {
+                        self.handle_local_connection(stream, true)
+                    }
+                    _ => (),
                 }
             }
 
@@ -411,10 +536,104 @@ impl VsockMuxer {
         }
     }
 
-    /// Parse a host "connect" command, and extract the destination vsock port.
-    fn read_local_stream_port(stream: &mut UnixStream) -> Result<u32> {
+    /// Accept a new host-initiated connection, from either the SOCK_STREAM or the
+    /// SOCK_SEQPACKET host socket.
+    fn accept_local_connection(&mut self, seqpacket: bool) {
+        let host_sock = match self.host_seqpacket_sock.as_ref() {
+            Some(sock) if seqpacket => sock,
+            // The SOCK_SEQPACKET listener is only registered once the socket is bound.
+            None if seqpacket => return,
+            _ => &self.host_sock,
+        };
+        if seqpacket && !self.seqpacket_negotiated {
+            // The guest driver can't handle SOCK_SEQPACKET connections, so we'll accept and
+            // immediately discard this one.
+            info!("vsock: SOCK_SEQPACKET not negotiated; refusing new host connection");
+            self.count_conn_rejected(None);
+            host_sock.accept().map(|_| 0).unwrap_or(0);
+            return;
+        }
+        host_sock
+            .accept()
+            .map_err(Error::UnixAccept)
+            .and_then(|(stream, _)| {
+                stream
+                    .set_nonblocking(true)
+                    .map(|_| stream)
+                    .map_err(Error::UnixAccept)
+            })
+            .and_then(|stream| {
+                // Before forwarding this connection to a listening AF_VSOCK socket on
+                // the guest side, we need to know the destination port. We'll read
+                // that port from a "connect" command received on this socket, so the
+                // next step is to ask to be notified the moment we can read from it.
+                let fd = stream.as_raw_fd();
+                if seqpacket {
+                    self.add_listener(fd, EpollListener::LocalSeqpacket(stream))
+                } else {
+                    self.add_listener(fd, EpollListener::LocalStream(stream))
+                }
+            })
+            .unwrap_or_else(|err| {
+                warn!("vsock: unable to accept local connection: {:?}", err);
+            });
+    }
+
+    /// Read the "connect" command of a host-initiated connection, and forward the connection
+    /// to the guest.
+    fn handle_local_connection(&mut self, mut stream: UnixStream, seqpacket: bool) {
+        let mut requested_port = None;
+        Self::read_local_stream_port(&mut stream, seqpacket)
+            .and_then(|peer_port| {
+                requested_port = Some(peer_port);
+                // Dropping the stream refuses the connection. The connection limit is only
+                // checked once the port is known, so that the refusal can be accounted to it.
+                if !self.routes.is_host_port_allowed(peer_port) {
+                    Err(Error::PortNotAllowed(peer_port))
+                } else if self.conn_map.len() >= self.max_connections {
+                    warn!("vsock: connection limit reached; refusing new host connection");
+                    Err(Error::TooManyConnections)
+                } else {
+                    Ok(peer_port)
+                }
+            })
+            .and_then(|peer_port| Ok((self.allocate_local_port(), peer_port)))
+            .and_then(|(local_port, peer_port)| {
+                let conn = MuxerConnection::new_local_init(
+                    stream.into(),
+                    uapi::VSOCK_HOST_CID,
+                    self.cid,
+                    local_port,
+                    peer_port,
+                );
+                self.add_connection(
+                    ConnMapKey {
+                        local_port,
+                        peer_port,
+                    },
+                    if seqpacket {
+                        conn.into_seqpacket()
+                    } else {
+                        conn
+                    },
+                )
+            })
+            .unwrap_or_else(|err| {
+                info!("vsock: error adding local-init connection: {:?}", err);
+                self.count_conn_rejected(requested_port);
+            })
+    }
+
+    /// Parse a host "connect" command, and extract the destination vsock port. On SOCK_SEQPACKET
+    /// host sockets, the command is expected to make up a whole message.
+    fn read_local_stream_port(stream: &mut UnixStream, seqpacket: bool) -> Result<u32> {
         let mut buf = [0u8; 32];
 
+        if seqpacket {
+            let blen = stream.read(&mut buf).map_err(Error::UnixRead)?;
+            return Self::parse_local_stream_port(&buf[..blen]);
+        }
+
         // This is the minimum number of bytes that we should be able to read, when parsing a
         // valid connection request. I.e. `b"connect 0\n".len()`.
         const MIN_READ_LEN: usize = 10;
@@ -435,7 +654,12 @@ impl VsockMuxer {
             blen += 1;
         }
 
-        let mut word_iter = std::str::from_utf8(&buf[..blen])
+        Self::parse_local_stream_port(&buf[..blen])
+    }
+
+    /// Parse a host "connect <port>" command.
+    fn parse_local_stream_port(buf: &[u8]) -> Result<u32> {
+        let mut word_iter = std::str::from_utf8(buf)
             .map_err(|_| Error::InvalidPortRequest)?
             .split_whitespace();
 
@@ -463,10 +687,10 @@ impl VsockMuxer {
         //   termination.
         self.sweep_killq();
 
-        if self.conn_map.len() >= defs::MAX_CONNECTIONS {
+        if self.conn_map.len() >= self.max_connections {
             info!(
                 "vsock: muxer connection limit reached ({})",
-                defs::MAX_CONNECTIONS
+                self.max_connections
             );
             return Err(Error::TooManyConnections);
         }
@@ -486,6 +710,11 @@ impl VsockMuxer {
                 self.rxq.push(MuxerRx::ConnRx(key));
             }
             self.conn_map.insert(key, conn);
+
+            METRICS.vsock.conns_accepted.inc();
+            if let Some(metrics) = self.conn_port_metrics(key) {
+                metrics.conns_accepted.inc();
+            }
             Ok(())
         })
     }
@@ -521,8 +750,8 @@ impl VsockMuxer {
     fn add_listener(&mut self, fd: RawFd, listener: EpollListener) -> Result<()> {
         let evset = match listener {
             EpollListener::Connection { evset, .. } => evset,
-            EpollListener::LocalStream(_) => EventSet::IN,
-            EpollListener::HostSock => EventSet::IN,
+            EpollListener::LocalStream(_) | EpollListener::LocalSeqpacket(_) => EventSet::IN,
+            EpollListener::HostSock | EpollListener::HostSeqpacketSock => EventSet::IN,
         };
 
         self.epoll
@@ -580,33 +809,87 @@ impl VsockMuxer {
 
     /// Handle a new connection request comming from our peer (the guest vsock driver).
     ///
-    /// This will attempt to connect to a host-side Unix socket, expected to be listening at
-    /// the file system path corresponing to the destination port. If successful, a new
-    /// connection object will be created and added to the connection pool. On failure, a new
-    /// RST packet will be scheduled for delivery to the guest.
+    /// This will attempt to connect to the host-side endpoint the destination port is routed
+    /// to: by default, a Unix socket expected to be listening at the file system path
+    /// corresponing to the destination port. If successful, a new connection object will be
+    /// created and added to the connection pool. On failure, or if the routing rules deny
+    /// connections to the destination port, a new RST packet will be scheduled for delivery to
+    /// the guest.
     fn handle_peer_request_pkt(&mut self, pkt: &VsockPacket) {
-        let port_path = format!("{}_{}", self.host_sock_path, pkt.dst_port());
+        // SOCK_SEQPACKET guest connections are forwarded to SOCK_SEQPACKET host sockets, so that
+        // message boundaries are preserved.
+        let seqpacket = pkt.type_() == uapi::VSOCK_TYPE_SEQPACKET;
+
+        let route = match self.routes.guest_ports.get(&pkt.dst_port()) {
+            // Neither TCP sockets, nor the MMDS can preserve message boundaries.
+            Some(Some(VsockUnixPortRoute::Tcp(_))) | Some(Some(VsockUnixPortRoute::Mmds))
+                if seqpacket =>
+            {
+                None
+            }
+            Some(Some(VsockUnixPortRoute::Mmds)) if self.mmds.is_none() => None,
+            Some(Some(route)) => Some(route.clone()),
+            Some(None) => None,
+            None => Some(VsockUnixPortRoute::Unix(format!(
+                "{}_{}",
+                self.host_sock_path,
+                pkt.dst_port()
+            ))),
+        };
+        let route = match route {
+            Some(route) => route,
+            None => {
+                info!("vsock: denying guest connection to port {}", pkt.dst_port());
+                self.refuse_peer_request(pkt);
+                return;
+            }
+        };
 
-        UnixStream::connect(port_path)
+        match route {
+            VsockUnixPortRoute::Unix(path) => if seqpacket {
+                seqpacket::connect(&path)
+            } else {
+                UnixStream::connect(path)
+            }
             .and_then(|stream| stream.set_nonblocking(true).map(|_| stream))
-            .map_err(Error::UnixConnect)
-            .and_then(|stream| {
-                self.add_connection(
-                    ConnMapKey {
-                        local_port: pkt.dst_port(),
-                        peer_port: pkt.src_port(),
-                    },
-                    MuxerConnection::new_peer_init(
-                        stream,
-                        uapi::VSOCK_HOST_CID,
-                        self.cid,
-                        pkt.dst_port(),
-                        pkt.src_port(),
-                        pkt.buf_alloc(),
-                    ),
-                )
-            })
-            .unwrap_or_else(|_| self.enq_rst(pkt.dst_port(), pkt.src_port()));
+            .map(HostStream::from)
+            .map_err(Error::UnixConnect),
+            VsockUnixPortRoute::Tcp(port) => HostStream::connect_tcp(port)
+                .and_then(|stream| stream.set_nonblocking(true).map(|_| stream))
+                .map_err(Error::TcpConnect),
+            // The unwrap is safe because the route is refused above when the MMDS is not set.
+            VsockUnixPortRoute::Mmds => MmdsStream::new(self.mmds.clone().unwrap())
+                .map(HostStream::Mmds)
+                .map_err(Error::MmdsStream),
+        }
+        .and_then(|stream| {
+            let conn = MuxerConnection::new_peer_init(
+                stream,
+                uapi::VSOCK_HOST_CID,
+                self.cid,
+                pkt.dst_port(),
+                pkt.src_port(),
+                pkt.buf_alloc(),
+            );
+            self.add_connection(
+                ConnMapKey {
+                    local_port: pkt.dst_port(),
+                    peer_port: pkt.src_port(),
+                },
+                if seqpacket {
+                    conn.into_seqpacket()
+                } else {
+                    conn
+                },
+            )
+        })
+        .unwrap_or_else(|_| self.refuse_peer_request(pkt));
+    }
+
+    /// Refuse a connection request comming from our peer, by scheduling an RST packet for it.
+    fn refuse_peer_request(&mut self, pkt: &VsockPacket) {
+        self.count_conn_rejected(Some(pkt.dst_port()));
+        self.enq_rst(pkt.dst_port(), pkt.src_port(), pkt.type_());
     }
 
     /// Perform an action that might mutate a connection's state.
@@ -717,6 +1000,7 @@ impl VsockMuxer {
                 .entry(key)
                 .and_modify(|conn| kill = conn.has_expired());
             if kill {
+                METRICS.vsock.muxer_killq_evictions.inc();
                 self.kill_connection(key);
             }
         }
@@ -729,15 +1013,56 @@ impl VsockMuxer {
         }
     }
 
+    /// Get the per-port metrics of `port`, if enabled.
+    fn port_metrics(&self, port: u32) -> Option<Arc<VsockPortMetrics>> {
+        if self.per_port_metrics {
+            Some(METRICS.vsock_ports.get_or_create(port))
+        } else {
+            None
+        }
+    }
+
+    /// Get the per-port metrics of the connection identified by `key`, if enabled. Host-initiated
+    /// connections are the ones using a local port allocated by the muxer.
+    fn conn_port_metrics(&self, key: ConnMapKey) -> Option<Arc<VsockPortMetrics>> {
+        if self.local_port_set.contains(&key.local_port) {
+            self.port_metrics(key.peer_port)
+        } else {
+            self.port_metrics(key.local_port)
+        }
+    }
+
+    /// Account for a refused connection attempt, targeting `port` (if known). Since the initiator
+    /// picks the port freely, the attempt is only accounted to the ports named by a routing rule,
+    /// so that the per-port metrics can't grow without limit.
+    fn count_conn_rejected(&self, port: Option<u32>) {
+        METRICS.vsock.conns_rejected.inc();
+        if let Some(metrics) = port
+            .filter(|port| self.routes.has_rule(*port))
+            .and_then(|port| self.port_metrics(port))
+        {
+            metrics.conns_rejected.inc();
+        }
+    }
+
+    /// Account for the forceful termination of the connection identified by `key`.
+    fn count_conn_reset(&self, key: ConnMapKey) {
+        METRICS.vsock.conns_reset.inc();
+        if let Some(metrics) = self.conn_port_metrics(key) {
+            metrics.conns_reset.inc();
+        }
+    }
+
     /// Enqueue an RST packet into `self.rxq`.
     ///
     /// Enqueue errors aren't propagated up the call chain, since there is nothing we can do to
     /// handle them. We do, however, log a warning, since not being able to enqueue an RST
     /// packet means we have to drop it, which is not normal operation.
-    fn enq_rst(&mut self, local_port: u32, peer_port: u32) {
+    fn enq_rst(&mut self, local_port: u32, peer_port: u32, sock_type: u16) {
         let pushed = self.rxq.push(MuxerRx::RstPkt {
             local_port,
             peer_port,
+            sock_type,
         });
         if !pushed {
             warn!(
@@ -774,6 +1099,9 @@ mod tests {
     impl Drop for MuxerTestContext {
         fn drop(&mut self) {
             std::fs::remove_file(self.muxer.host_sock_path.as_str()).unwrap();
+            if self.muxer.seqpacket() {
+                std::fs::remove_file(seqpacket_sock_path(&self.muxer.host_sock_path)).unwrap();
+            }
         }
     }
 
@@ -807,6 +1135,13 @@ mod tests {
             }
         }
 
+        fn negotiate_seqpacket(&mut self) {
+            self.muxer.enable_seqpacket().unwrap();
+            self.muxer.set_acked_features(
+                self.muxer.avail_features() | 1 << uapi::VIRTIO_F_VERSION_1 as u64,
+            );
+        }
+
         fn init_pkt(&mut self, local_port: u32, peer_port: u32, op: u16) -> &mut VsockPacket {
             for b in self.pkt.hdr_mut() {
                 *b = 0;
@@ -851,7 +1186,9 @@ mod tests {
             let mut conn_lsn_count = 0usize;
             for key in self.muxer.listener_map.values() {
                 match key {
-                    EpollListener::LocalStream(_) => local_lsn_count += 1,
+                    EpollListener::LocalStream(_) | EpollListener::LocalSeqpacket(_) => {
+                        local_lsn_count += 1
+                    }
                     EpollListener::Connection { .. } => conn_lsn_count += 1,
                     _ => (),
                 };
@@ -954,7 +1291,7 @@ mod tests {
     fn test_bad_peer_pkt() {
         const LOCAL_PORT: u32 = 1026;
         const PEER_PORT: u32 = 1025;
-        const SOCK_DGRAM: u16 = 2;
+        const SOCK_DGRAM: u16 = 3;
 
         let mut ctx = MuxerTestContext::new("bad_peer_pkt");
         ctx.init_pkt(LOCAL_PORT, PEER_PORT, uapi::VSOCK_OP_REQUEST)
@@ -962,7 +1299,7 @@ mod tests {
         ctx.send();
 
         // The guest sent a SOCK_DGRAM packet. Per the vsock spec, we need to reply with an RST
-        // packet, since vsock only supports stream sockets.
+        // packet, since vsock only supports stream and seqpacket sockets.
         assert!(ctx.muxer.has_pending_rx());
         ctx.recv();
         assert_eq!(ctx.pkt.op(), uapi::VSOCK_OP_RST);
@@ -1063,6 +1400,333 @@ mod tests {
         assert!(!ctx.muxer.has_pending_rx());
     }
 
+    #[test]
+    fn test_guest_port_routes() {
+        const ROUTED_PORT: u32 = 1026;
+        const DENIED_PORT: u32 = 1027;
+        const PEER_PORT: u32 = 1025;
+
+        let mut ctx = MuxerTestContext::new("guest_port_routes");
+        let routed_path = get_file("guest_port_routes_custom");
+        let mut routes = VsockUnixRoutes::default();
+        routes.guest_ports.insert(
+            ROUTED_PORT,
+            Some(VsockUnixPortRoute::Unix(routed_path.clone())),
+        );
+        routes.guest_ports.insert(DENIED_PORT, None);
+        ctx.muxer.set_routes(routes.clone());
+        assert_eq!(ctx.muxer.routes(), &routes);
+        assert!(!routes.has_tcp_routes());
+
+        // Connections to a routed port are forwarded to the configured path.
+        let mut listener = LocalListener::new(routed_path);
+        ctx.init_pkt(ROUTED_PORT, PEER_PORT, uapi::VSOCK_OP_REQUEST);
+        ctx.send();
+        assert_eq!(ctx.muxer.conn_map.len(), 1);
+        let _stream = listener.accept();
+        ctx.recv();
+        assert_eq!(ctx.pkt.op(), uapi::VSOCK_OP_RESPONSE);
+        assert_eq!(ctx.pkt.src_port(), ROUTED_PORT);
+
+        // Connections to a denied port are reset, even if someone listens on the default path.
+        let _default_listener = ctx.create_local_listener(DENIED_PORT);
+        ctx.init_pkt(DENIED_PORT, PEER_PORT, uapi::VSOCK_OP_REQUEST);
+        ctx.send();
+        assert_eq!(ctx.muxer.conn_map.len(), 1);
+        ctx.recv();
+        assert_eq!(ctx.pkt.op(), uapi::VSOCK_OP_RST);
+        assert_eq!(ctx.pkt.src_port(), DENIED_PORT);
+        assert_eq!(ctx.pkt.dst_port(), PEER_PORT);
+    }
+
+    #[test]
+    fn test_guest_port_tcp_route() {
+        const ROUTED_PORT: u32 = 1026;
+        const PEER_PORT: u32 = 1025;
+
+        let mut ctx = MuxerTestContext::new("guest_port_tcp_route");
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let tcp_port = listener.local_addr().unwrap().port();
+        let mut routes = VsockUnixRoutes::default();
+        routes
+            .guest_ports
+            .insert(ROUTED_PORT, Some(VsockUnixPortRoute::Tcp(tcp_port)));
+        assert!(routes.has_tcp_routes());
+        ctx.muxer.set_routes(routes);
+
+        // Connections to the routed port are forwarded to the TCP socket.
+        ctx.init_pkt(ROUTED_PORT, PEER_PORT, uapi::VSOCK_OP_REQUEST);
+        ctx.send();
+        assert_eq!(ctx.muxer.conn_map.len(), 1);
+        let (mut stream, _) = listener.accept().unwrap();
+        ctx.recv();
+        assert_eq!(ctx.pkt.op(), uapi::VSOCK_OP_RESPONSE);
+        assert_eq!(ctx.pkt.src_port(), ROUTED_PORT);
+
+        // Test guest -> host data flow.
+        let data = [1, 2, 3, 4];
+        ctx.init_data_pkt(ROUTED_PORT, PEER_PORT, &data);
+        ctx.send();
+        let mut buf = vec![0; data.len()];
+        stream.read_exact(buf.as_mut_slice()).unwrap();
+        assert_eq!(buf.as_slice(), data);
+
+        // Test host -> guest data flow.
+        let data = [5, 6, 7, 8];
+        stream.write_all(&data).unwrap();
+        ctx.notify_muxer();
+        assert!(ctx.muxer.has_pending_rx());
+        ctx.recv();
+        assert_eq!(ctx.pkt.op(), uapi::VSOCK_OP_RW);
+        assert_eq!(ctx.pkt.buf().unwrap()[..data.len()], data);
+
+        // SOCK_SEQPACKET connections can't be forwarded to TCP sockets.
+        ctx.init_pkt(ROUTED_PORT, PEER_PORT + 1, uapi::VSOCK_OP_REQUEST)
+            .set_type(uapi::VSOCK_TYPE_SEQPACKET);
+        ctx.send();
+        assert_eq!(ctx.muxer.conn_map.len(), 1);
+        ctx.recv();
+        assert_eq!(ctx.pkt.op(), uapi::VSOCK_OP_RST);
+        assert_eq!(ctx.pkt.type_(), uapi::VSOCK_TYPE_SEQPACKET);
+        assert_eq!(ctx.pkt.dst_port(), PEER_PORT + 1);
+
+        // Connection requests are refused when nothing listens on the TCP port.
+        drop(stream);
+        drop(listener);
+        let mut routes = VsockUnixRoutes::default();
+        routes
+            .guest_ports
+            .insert(ROUTED_PORT + 1, Some(VsockUnixPortRoute::Tcp(tcp_port)));
+        ctx.muxer.set_routes(routes);
+        ctx.init_pkt(ROUTED_PORT + 1, PEER_PORT, uapi::VSOCK_OP_REQUEST);
+        ctx.send();
+        ctx.recv();
+        assert_eq!(ctx.pkt.op(), uapi::VSOCK_OP_RST);
+        assert_eq!(ctx.pkt.src_port(), ROUTED_PORT + 1);
+    }
+
+    #[test]
+    fn test_guest_port_mmds_route() {
+        const MMDS_PORT: u32 = 1026;
+        const PEER_PORT: u32 = 1025;
+
+        let mut ctx = MuxerTestContext::new("guest_port_mmds_route");
+        let mut routes = VsockUnixRoutes::default();
+        routes
+            .guest_ports
+            .insert(MMDS_PORT, Some(VsockUnixPortRoute::Mmds));
+        ctx.muxer.set_routes(routes);
+
+        // Connections are refused until the muxer is given an MMDS to serve.
+        ctx.init_pkt(MMDS_PORT, PEER_PORT, uapi::VSOCK_OP_REQUEST);
+        ctx.send();
+        assert_eq!(ctx.muxer.conn_map.len(), 0);
+        ctx.recv();
+        assert_eq!(ctx.pkt.op(), uapi::VSOCK_OP_RST);
+        ctx.muxer.set_mmds(Arc::new(Mutex::new(Mmds::default())));
+
+        // Connections to the MMDS port are served in-process.
+        ctx.init_pkt(MMDS_PORT, PEER_PORT, uapi::VSOCK_OP_REQUEST);
+        ctx.send();
+        assert_eq!(ctx.muxer.conn_map.len(), 1);
+        ctx.recv();
+        assert_eq!(ctx.pkt.op(), uapi::VSOCK_OP_RESPONSE);
+        assert_eq!(ctx.pkt.src_port(), MMDS_PORT);
+        assert!(!ctx.muxer.has_pending_rx());
+
+        // The response is available once the request is complete.
+        ctx.init_data_pkt(MMDS_PORT, PEER_PORT, b"GET /muxer-test HTTP/1.1\r\n");
+        ctx.send();
+        ctx.notify_muxer();
+        assert!(!ctx.muxer.has_pending_rx());
+        ctx.init_data_pkt(MMDS_PORT, PEER_PORT, b"\r\n");
+        ctx.send();
+        ctx.notify_muxer();
+        assert!(ctx.muxer.has_pending_rx());
+        ctx.recv();
+        assert_eq!(ctx.pkt.op(), uapi::VSOCK_OP_RW);
+        let response = &ctx.pkt.buf().unwrap()[..ctx.pkt.len() as usize];
+        assert!(response.starts_with(b"HTTP/1.1 404"));
+        assert!(response.ends_with(b"Resource not found: /muxer-test."));
+        ctx.notify_muxer();
+        assert!(!ctx.muxer.has_pending_rx());
+
+        // SOCK_SEQPACKET connections can't be forwarded to the MMDS.
+        ctx.init_pkt(MMDS_PORT, PEER_PORT + 1, uapi::VSOCK_OP_REQUEST)
+            .set_type(uapi::VSOCK_TYPE_SEQPACKET);
+        ctx.send();
+        assert_eq!(ctx.muxer.conn_map.len(), 1);
+        ctx.recv();
+        assert_eq!(ctx.pkt.op(), uapi::VSOCK_OP_RST);
+        assert_eq!(ctx.pkt.dst_port(), PEER_PORT + 1);
+    }
+
+    #[test]
+    fn test_host_allowed_ports() {
+        let mut ctx = MuxerTestContext::new("host_allowed_ports");
+        let allowed_port = 1025;
+        let denied_port = 1026;
+        let mut routes = VsockUnixRoutes::default();
+        routes.host_allowed_ports = Some([allowed_port].iter().cloned().collect());
+        ctx.muxer.set_routes(routes);
+
+        // A connection request to a port outside the allow-list gets the host stream closed.
+        let mut stream = UnixStream::connect(ctx.muxer.host_sock_path.clone()).unwrap();
+        ctx.notify_muxer();
+        stream
+            .write_all(format!("CONNECT {}\n", denied_port).as_bytes())
+            .unwrap();
+        ctx.notify_muxer();
+        assert_eq!(ctx.count_epoll_listeners(), (0, 0));
+        assert!(ctx.muxer.conn_map.is_empty());
+        assert!(!ctx.muxer.has_pending_rx());
+        let mut buf = vec![0u8; 32];
+        assert_eq!(stream.read(&mut buf[..]).unwrap(), 0);
+
+        // Allowed ports can still be reached.
+        let (_stream, _) = ctx.local_connect(allowed_port);
+    }
+
+    #[test]
+    fn test_seqpacket_peer_connection() {
+        const LOCAL_PORT: u32 = 1026;
+        const PEER_PORT: u32 = 1025;
+
+        let mut ctx = MuxerTestContext::new("seqpacket_peer_connection");
+        ctx.negotiate_seqpacket();
+
+        // Test peer connection refused. The RST must match the connection type, or the guest
+        // would ignore it.
+        ctx.init_pkt(LOCAL_PORT, PEER_PORT, uapi::VSOCK_OP_REQUEST)
+            .set_type(uapi::VSOCK_TYPE_SEQPACKET);
+        ctx.send();
+        ctx.recv();
+        assert_eq!(ctx.pkt.op(), uapi::VSOCK_OP_RST);
+        assert_eq!(ctx.pkt.type_(), uapi::VSOCK_TYPE_SEQPACKET);
+
+        // Test peer connection accepted, through a SOCK_SEQPACKET host socket.
+        let listener_path = format!("{}_{}", ctx.muxer.host_sock_path, LOCAL_PORT);
+        let listener = seqpacket::bind(&listener_path).unwrap();
+        ctx.init_pkt(LOCAL_PORT, PEER_PORT, uapi::VSOCK_OP_REQUEST)
+            .set_type(uapi::VSOCK_TYPE_SEQPACKET);
+        ctx.send();
+        assert_eq!(ctx.muxer.conn_map.len(), 1);
+        let (mut stream, _) = listener.accept().unwrap();
+        ctx.recv();
+        assert_eq!(ctx.pkt.op(), uapi::VSOCK_OP_RESPONSE);
+        assert_eq!(ctx.pkt.type_(), uapi::VSOCK_TYPE_SEQPACKET);
+
+        // Test guest -> host data flow. A message split over two packets is received by the
+        // host in one piece.
+        ctx.init_data_pkt(LOCAL_PORT, PEER_PORT, &[1, 2])
+            .set_type(uapi::VSOCK_TYPE_SEQPACKET);
+        ctx.send();
+        ctx.init_data_pkt(LOCAL_PORT, PEER_PORT, &[3, 4])
+            .set_type(uapi::VSOCK_TYPE_SEQPACKET)
+            .set_flag(uapi::VSOCK_FLAGS_SEQ_EOM);
+        ctx.send();
+        let mut buf = vec![0; 16];
+        assert_eq!(stream.read(buf.as_mut_slice()).unwrap(), 4);
+        assert_eq!(&buf[..4], &[1, 2, 3, 4]);
+
+        // Test host -> guest data flow.
+        let data = [5u8, 6, 7];
+        stream.write_all(&data).unwrap();
+        ctx.notify_muxer();
+        assert!(ctx.muxer.has_pending_rx());
+        ctx.recv();
+        assert_eq!(ctx.pkt.op(), uapi::VSOCK_OP_RW);
+        assert_eq!(ctx.pkt.type_(), uapi::VSOCK_TYPE_SEQPACKET);
+        assert_eq!(ctx.pkt.len() as usize, data.len());
+        assert_ne!(ctx.pkt.flags() & uapi::VSOCK_FLAGS_SEQ_EOM, 0);
+        assert_eq!(ctx.pkt.buf().unwrap()[..data.len()], data);
+
+        std::fs::remove_file(listener_path).unwrap();
+    }
+
+    #[test]
+    fn test_seqpacket_local_connection() {
+        let mut ctx = MuxerTestContext::new("seqpacket_local_connection");
+        ctx.negotiate_seqpacket();
+        let peer_port = 1025;
+
+        let mut stream =
+            seqpacket::connect(&seqpacket_sock_path(&ctx.muxer.host_sock_path)).unwrap();
+        ctx.notify_muxer();
+        assert_eq!(ctx.count_epoll_listeners(), (1, 0));
+
+        // The connect command is a message of its own.
+        stream
+            .write_all(format!("CONNECT {}\n", peer_port).as_bytes())
+            .unwrap();
+        ctx.notify_muxer();
+        assert_eq!(ctx.count_epoll_listeners(), (0, 1));
+        let local_port = ctx.muxer.local_port_last;
+
+        ctx.recv();
+        assert_eq!(ctx.pkt.op(), uapi::VSOCK_OP_REQUEST);
+        assert_eq!(ctx.pkt.type_(), uapi::VSOCK_TYPE_SEQPACKET);
+        ctx.init_pkt(local_port, peer_port, uapi::VSOCK_OP_RESPONSE)
+            .set_type(uapi::VSOCK_TYPE_SEQPACKET);
+        ctx.send();
+
+        // The ack is a message of its own too.
+        let mut buf = vec![0u8; 32];
+        let len = stream.read(&mut buf[..]).unwrap();
+        assert_eq!(&buf[..len], format!("OK {}\n", local_port).as_bytes());
+
+        // Test host -> guest data flow.
+        stream.write_all(&[1, 2, 3]).unwrap();
+        ctx.notify_muxer();
+        ctx.recv();
+        assert_eq!(ctx.pkt.op(), uapi::VSOCK_OP_RW);
+        assert_eq!(ctx.pkt.len(), 3);
+        assert_ne!(ctx.pkt.flags() & uapi::VSOCK_FLAGS_SEQ_EOM, 0);
+    }
+
+    #[test]
+    fn test_seqpacket_not_negotiated() {
+        const LOCAL_PORT: u32 = 1026;
+        const PEER_PORT: u32 = 1025;
+
+        let mut ctx = MuxerTestContext::new("seqpacket_not_negotiated");
+
+        // The feature is only offered once SOCK_SEQPACKET connections are enabled.
+        assert_eq!(ctx.muxer.avail_features(), 0);
+        assert!(!ctx.muxer.seqpacket());
+        assert!(!Path::new(&seqpacket_sock_path(&ctx.muxer.host_sock_path)).exists());
+        ctx.muxer.enable_seqpacket().unwrap();
+        assert_eq!(
+            ctx.muxer.avail_features(),
+            1 << uapi::VIRTIO_VSOCK_F_SEQPACKET as u64
+        );
+        ctx.muxer
+            .set_acked_features(1 << uapi::VIRTIO_F_VERSION_1 as u64);
+
+        // Guest SOCK_SEQPACKET connection requests are refused, even if a host socket listens
+        // on the destination port.
+        let listener_path = format!("{}_{}", ctx.muxer.host_sock_path, LOCAL_PORT);
+        let _listener = seqpacket::bind(&listener_path).unwrap();
+        ctx.init_pkt(LOCAL_PORT, PEER_PORT, uapi::VSOCK_OP_REQUEST)
+            .set_type(uapi::VSOCK_TYPE_SEQPACKET);
+        ctx.send();
+        assert!(ctx.muxer.conn_map.is_empty());
+        ctx.recv();
+        assert_eq!(ctx.pkt.op(), uapi::VSOCK_OP_RST);
+        assert_eq!(ctx.pkt.type_(), uapi::VSOCK_TYPE_SEQPACKET);
+
+        // Host SOCK_SEQPACKET connections are dropped right away.
+        let mut stream =
+            seqpacket::connect(&seqpacket_sock_path(&ctx.muxer.host_sock_path)).unwrap();
+        ctx.notify_muxer();
+        assert_eq!(ctx.count_epoll_listeners(), (0, 0));
+        assert!(!ctx.muxer.has_pending_rx());
+        let mut buf = [0u8; 16];
+        assert_eq!(stream.read(&mut buf).unwrap(), 0);
+
+        std::fs::remove_file(listener_path).unwrap();
+    }
+
     #[test]
     fn test_local_connection() {
         let mut ctx = MuxerTestContext::new("local_connection");
@@ -1091,6 +1755,177 @@ mod tests {
         assert_eq!(ctx.pkt.buf().unwrap()[..data.len()], data);
     }
 
+    #[test]
+    fn test_reset_connection() {
+        let mut ctx = MuxerTestContext::new("reset_connection");
+        let peer_port = 1025;
+        let (mut stream, local_port) = ctx.local_connect(peer_port);
+
+        let conns = ctx.muxer.connections();
+        assert_eq!(conns.len(), 1);
+        assert_eq!(conns[0].local_port, local_port);
+        assert_eq!(conns[0].peer_port, peer_port);
+        assert_eq!(conns[0].state, ConnState::Established);
+
+        // Resetting an unknown connection fails.
+        match ctx.muxer.reset_connection(local_port, peer_port + 1) {
+            Err(Error::ConnectionNotFound(lp, pp)) => {
+                assert_eq!(lp, local_port);
+                assert_eq!(pp, peer_port + 1);
+            }
+            other => panic!("{:?}", other),
+        }
+
+        // Resetting a known connection closes the host stream, and yields an RST to the guest.
+        ctx.muxer.reset_connection(local_port, peer_port).unwrap();
+        assert!(ctx.muxer.connections().is_empty());
+        assert!(!ctx.muxer.local_port_set.contains(&local_port));
+        let (_, conn_lsn_count) = ctx.count_epoll_listeners();
+        assert_eq!(conn_lsn_count, 0);
+        let mut buf = vec![0u8; 16];
+        assert_eq!(stream.read(buf.as_mut_slice()).unwrap(), 0);
+
+        assert!(ctx.muxer.has_pending_rx());
+        ctx.recv();
+        assert_eq!(ctx.pkt.op(), uapi::VSOCK_OP_RST);
+        assert_eq!(ctx.pkt.type_(), uapi::VSOCK_TYPE_STREAM);
+        assert_eq!(ctx.pkt.src_port(), local_port);
+        assert_eq!(ctx.pkt.dst_port(), peer_port);
+        assert!(!ctx.muxer.has_pending_rx());
+    }
+
+    #[test]
+    fn test_clear_connections() {
+        let mut ctx = MuxerTestContext::new("clear_connections");
+        let (mut stream1, _) = ctx.local_connect(1025);
+        let (mut stream2, _) = ctx.local_connect(1026);
+        assert_eq!(ctx.muxer.connections().len(), 2);
+
+        // All the host streams get closed, and the guest isn't sent anything.
+        ctx.muxer.clear_connections();
+        assert!(ctx.muxer.connections().is_empty());
+        assert!(ctx.muxer.local_port_set.is_empty());
+        let (_, conn_lsn_count) = ctx.count_epoll_listeners();
+        assert_eq!(conn_lsn_count, 0);
+        assert!(!ctx.muxer.has_pending_rx());
+        let mut buf = vec![0u8; 16];
+        assert_eq!(stream1.read(buf.as_mut_slice()).unwrap(), 0);
+        assert_eq!(stream2.read(buf.as_mut_slice()).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_max_connections() {
+        const LOCAL_PORT: u32 = 1026;
+        const PEER_PORT: u32 = 1025;
+        const LIMITED_PORT: u32 = 2055;
+
+        let mut ctx = MuxerTestContext::new("max_connections");
+        assert_eq!(ctx.muxer.max_connections(), defs::DEFAULT_MAX_CONNECTIONS);
+        ctx.muxer.set_max_connections(1);
+        assert_eq!(ctx.muxer.max_connections(), 1);
+
+        let _listener = ctx.create_local_listener(LOCAL_PORT);
+        ctx.init_pkt(LOCAL_PORT, PEER_PORT, uapi::VSOCK_OP_REQUEST);
+        ctx.send();
+        ctx.recv();
+        assert_eq!(ctx.pkt.op(), uapi::VSOCK_OP_RESPONSE);
+        assert_eq!(ctx.muxer.conn_map.len(), 1);
+
+        // Connection requests above the limit are refused.
+        ctx.init_pkt(LOCAL_PORT, PEER_PORT + 1, uapi::VSOCK_OP_REQUEST);
+        ctx.send();
+        ctx.recv();
+        assert_eq!(ctx.pkt.op(), uapi::VSOCK_OP_RST);
+        assert_eq!(ctx.pkt.dst_port(), PEER_PORT + 1);
+        assert_eq!(ctx.muxer.conn_map.len(), 1);
+
+        // So are host-initiated connections, once their port is known.
+        ctx.muxer.set_per_port_metrics(true);
+        let mut routes = VsockUnixRoutes::default();
+        routes.host_allowed_ports = Some([LIMITED_PORT].iter().cloned().collect());
+        ctx.muxer.set_routes(routes);
+        let mut stream = UnixStream::connect(ctx.muxer.host_sock_path.clone()).unwrap();
+        ctx.notify_muxer();
+        stream
+            .write_all(format!("CONNECT {}\n", LIMITED_PORT).as_bytes())
+            .unwrap();
+        ctx.notify_muxer();
+        let (local_lsn_count, _) = ctx.count_epoll_listeners();
+        assert_eq!(local_lsn_count, 0);
+        assert_eq!(ctx.muxer.conn_map.len(), 1);
+        assert!(ctx.muxer.local_port_set.is_empty());
+        let mut buf = vec![0u8; 16];
+        assert_eq!(stream.read(buf.as_mut_slice()).unwrap(), 0);
+        let metrics = METRICS.vsock_ports.get(LIMITED_PORT).unwrap();
+        assert_eq!(metrics.conns_rejected.count(), 1);
+    }
+
+    #[test]
+    fn test_per_port_metrics() {
+        const GUEST_PORT: u32 = 2052;
+        const HOST_PORT: u32 = 2053;
+        const CLOSED_PORT: u32 = 2054;
+        const DENIED_PORT: u32 = 2056;
+        const PEER_PORT: u32 = 1025;
+
+        let mut ctx = MuxerTestContext::new("per_port_metrics");
+        assert!(!ctx.muxer.per_port_metrics());
+        ctx.muxer.set_per_port_metrics(true);
+        assert!(ctx.muxer.per_port_metrics());
+
+        // Host-initiated connections are accounted to their guest port.
+        let (mut stream, local_port) = ctx.local_connect(GUEST_PORT);
+        let data = [1, 2, 3, 4];
+        ctx.init_data_pkt(local_port, GUEST_PORT, &data);
+        ctx.send();
+        let mut buf = vec![0u8; data.len()];
+        stream.read_exact(buf.as_mut_slice()).unwrap();
+        ctx.muxer.reset_connection(local_port, GUEST_PORT).unwrap();
+        ctx.recv();
+        assert_eq!(ctx.pkt.op(), uapi::VSOCK_OP_RST);
+
+        let metrics = METRICS.vsock_ports.get(GUEST_PORT).unwrap();
+        assert_eq!(metrics.conns_accepted.count(), 1);
+        assert_eq!(metrics.conns_reset.count(), 1);
+        assert_eq!(metrics.tx_bytes_count.count(), data.len());
+        assert!(METRICS.vsock_ports.get(local_port).is_none());
+
+        // Guest-initiated connections are accounted to their host port.
+        let _listener = ctx.create_local_listener(HOST_PORT);
+        ctx.init_pkt(HOST_PORT, PEER_PORT, uapi::VSOCK_OP_REQUEST);
+        ctx.send();
+        ctx.recv();
+        assert_eq!(ctx.pkt.op(), uapi::VSOCK_OP_RESPONSE);
+
+        let metrics = METRICS.vsock_ports.get(HOST_PORT).unwrap();
+        assert_eq!(metrics.conns_accepted.count(), 1);
+        assert_eq!(metrics.rx_packets_count.count(), 1);
+        assert!(METRICS.vsock_ports.get(PEER_PORT).is_none());
+
+        // So are refused connection requests to the ports named by a routing rule.
+        let mut routes = VsockUnixRoutes::default();
+        routes.guest_ports.insert(DENIED_PORT, None);
+        ctx.muxer.set_routes(routes);
+        ctx.init_pkt(DENIED_PORT, PEER_PORT, uapi::VSOCK_OP_REQUEST);
+        ctx.send();
+        ctx.recv();
+        assert_eq!(ctx.pkt.op(), uapi::VSOCK_OP_RST);
+
+        let metrics = METRICS.vsock_ports.get(DENIED_PORT).unwrap();
+        assert_eq!(metrics.conns_rejected.count(), 1);
+        assert_eq!(metrics.conns_accepted.count(), 0);
+
+        // The refused requests to the other ports are only accounted in the device metrics.
+        let conns_rejected = METRICS.vsock.conns_rejected.count();
+        ctx.init_pkt(CLOSED_PORT, PEER_PORT, uapi::VSOCK_OP_REQUEST);
+        ctx.send();
+        ctx.recv();
+        assert_eq!(ctx.pkt.op(), uapi::VSOCK_OP_RST);
+
+        assert!(METRICS.vsock_ports.get(CLOSED_PORT).is_none());
+        assert!(METRICS.vsock.conns_rejected.count() > conns_rejected);
+    }
+
     #[test]
     fn test_local_close() {
         let peer_port = 1025;
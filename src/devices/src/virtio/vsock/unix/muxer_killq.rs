@@ -27,6 +27,8 @@
 use std::collections::{HashMap, VecDeque};
 use std::time::Instant;
 
+use logger::{Metric, METRICS};
+
 use super::defs;
 use super::muxer::ConnMapKey;
 use super::MuxerConnection;
@@ -93,7 +95,7 @@ impl MuxerKillQ {
     /// `CONN_SHUTDOWN_TIMEOUT_MS` from now (the push time).
     pub fn push(&mut self, key: ConnMapKey, kill_time: Instant) {
         if !self.is_synced() || self.is_full() {
-            self.synced = false;
+            self.lose_sync();
             return;
         }
         self.q.push_back(MuxerKillQItem { key, kill_time });
@@ -117,6 +119,14 @@ impl MuxerKillQ {
         self.synced
     }
 
+    /// Mark the queue as no longer synchronized with the connections awaiting termination.
+    fn lose_sync(&mut self) {
+        if self.synced {
+            METRICS.vsock.muxer_killq_sync_lost.inc();
+        }
+        self.synced = false;
+    }
+
     /// Check if the kill queue is empty, obviously.
     pub fn is_empty(&self) -> bool {
         self.q.len() == 0
@@ -17,6 +17,8 @@
 /// rebuild a synced one.
 use std::collections::{HashMap, VecDeque};
 
+use logger::{Metric, METRICS};
+
 use super::super::VsockChannel;
 use super::defs;
 use super::muxer::{ConnMapKey, MuxerRx};
@@ -89,13 +91,13 @@ impl MuxerRxQ {
                 for qi in self.q.iter_mut().rev() {
                     if let MuxerRx::ConnRx(_) = qi {
                         *qi = rx;
-                        self.synced = false;
+                        self.lose_sync();
                         return true;
                     }
                 }
             }
             MuxerRx::ConnRx(_) => {
-                self.synced = false;
+                self.lose_sync();
             }
         };
 
@@ -112,6 +114,14 @@ impl MuxerRxQ {
         self.synced
     }
 
+    /// Mark the queue as no longer synchronized with the connection pool.
+    fn lose_sync(&mut self) {
+        if self.synced {
+            METRICS.vsock.muxer_rxq_sync_lost.inc();
+        }
+        self.synced = false;
+    }
+
     /// Get the total number of items in the queue.
     pub fn len(&self) -> usize {
         self.q.len()
@@ -0,0 +1,126 @@
+// Copyright 2020 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+//
+
+/// Helpers for creating host-side `SOCK_SEQPACKET` Unix sockets.
+///
+/// The standard library only creates `SOCK_STREAM` Unix sockets. However, its `UnixListener`
+/// and `UnixStream` wrappers only issue plain `accept4()`, `read()` and `write()` calls, which
+/// preserve message boundaries on `SOCK_SEQPACKET` sockets. The sockets created here are thus
+/// handed over to these wrappers, so that the muxer can handle both socket types alike.
+use std::io;
+use std::mem;
+use std::os::unix::io::{FromRawFd, RawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+
+/// Backlog of the listening sockets, matching the one used by the standard library.
+const LISTEN_BACKLOG: libc::c_int = 128;
+
+/// Build the socket address of a Unix socket bound at `path`.
+fn sockaddr_un(path: &str) -> io::Result<(libc::sockaddr_un, libc::socklen_t)> {
+    // Safe because `sockaddr_un` only holds plain data, for which all zeroes is a valid value.
+    let mut addr: libc::sockaddr_un = unsafe { mem::zeroed() };
+    addr.sun_family = libc::AF_UNIX as libc::sa_family_t;
+
+    // The path must be NUL-terminated, so it has to be shorter than `sun_path`.
+    let bytes = path.as_bytes();
+    if bytes.len() >= addr.sun_path.len() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Unix socket path too long",
+        ));
+    }
+    for (dst, src) in addr.sun_path.iter_mut().zip(bytes) {
+        *dst = *src as libc::c_char;
+    }
+
+    let len = mem::size_of::<libc::sa_family_t>() + bytes.len() + 1;
+    Ok((addr, len as libc::socklen_t))
+}
+
+/// Create a new `SOCK_SEQPACKET` Unix socket.
+fn socket() -> io::Result<RawFd> {
+    // Safe because we check the return value.
+    let fd = unsafe { libc::socket(libc::AF_UNIX, libc::SOCK_SEQPACKET | libc::SOCK_CLOEXEC, 0) };
+    if fd < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(fd)
+}
+
+/// Bind a `SOCK_SEQPACKET` Unix socket at `path`, and listen on it.
+pub fn bind(path: &str) -> io::Result<UnixListener> {
+    let (addr, len) = sockaddr_un(path)?;
+    let fd = socket()?;
+    // Safe because we own the freshly created fd. Wrapping it right away makes sure it gets
+    // closed on error.
+    let listener = unsafe { UnixListener::from_raw_fd(fd) };
+
+    // Safe because `addr` is a valid socket address of length `len`, and we check the return
+    // value.
+    let ret = unsafe { libc::bind(fd, &addr as *const _ as *const libc::sockaddr, len) };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // Safe because we check the return value.
+    if unsafe { libc::listen(fd, LISTEN_BACKLOG) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(listener)
+}
+
+/// Connect to a `SOCK_SEQPACKET` Unix socket listening at `path`.
+pub fn connect(path: &str) -> io::Result<UnixStream> {
+    let (addr, len) = sockaddr_un(path)?;
+    let fd = socket()?;
+    // Safe because we own the freshly created fd. Wrapping it right away makes sure it gets
+    // closed on error.
+    let stream = unsafe { UnixStream::from_raw_fd(fd) };
+
+    // Safe because `addr` is a valid socket address of length `len`, and we check the return
+    // value.
+    let ret = unsafe { libc::connect(fd, &addr as *const _ as *const libc::sockaddr, len) };
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::io::{Read, Write};
+
+    use utils::tempfile::TempFile;
+
+    #[test]
+    fn test_seqpacket_sockets() {
+        let tmp = TempFile::new_with_prefix("vsock_seqpacket".to_owned()).unwrap();
+        let path = tmp.as_path().to_str().unwrap().to_owned();
+        drop(tmp);
+
+        let listener = bind(&path).unwrap();
+        let mut client = connect(&path).unwrap();
+        let (mut server, _) = listener.accept().unwrap();
+
+        // Message boundaries are preserved.
+        client.write_all(&[1, 2, 3]).unwrap();
+        client.write_all(&[4, 5]).unwrap();
+        let mut buf = [0u8; 16];
+        assert_eq!(server.read(&mut buf).unwrap(), 3);
+        assert_eq!(&buf[..3], &[1, 2, 3]);
+        assert_eq!(server.read(&mut buf).unwrap(), 2);
+        assert_eq!(&buf[..2], &[4, 5]);
+
+        // Connecting to a missing socket fails.
+        std::fs::remove_file(&path).unwrap();
+        assert!(connect(&path).is_err());
+
+        // So does binding to a path that doesn't fit in a socket address.
+        assert_eq!(
+            bind(&"a".repeat(128)).unwrap_err().kind(),
+            io::ErrorKind::InvalidInput
+        );
+    }
+}
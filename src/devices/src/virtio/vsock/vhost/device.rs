@@ -0,0 +1,217 @@
+// Copyright 2020 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::result;
+/// This is the `VirtioDevice` implementation for the vhost-vsock flavour of our vsock device. It
+/// handles feature negotiation and device configuration just like `Vsock`, but upon activation it
+/// hands the RX and TX queues over to the host kernel instead of processing them itself.
+///
+/// Upon its activation, the device registers handlers for the following events/FDs:
+/// - an RX queue call FD, signaled by the host kernel when it used RX buffers;
+/// - a TX queue call FD, signaled by the host kernel when it used TX buffers; and
+/// - an event queue FD.
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use utils::byte_order;
+use utils::eventfd::EventFd;
+use vm_memory::GuestMemoryMmap;
+
+use super::{Error, Result, VhostVsockHandle};
+use crate::virtio::vsock::device::{RXQ_INDEX, TXQ_INDEX};
+use crate::virtio::vsock::{defs, defs::uapi};
+use crate::virtio::{
+    ActivateError, ActivateResult, DeviceState, Queue as VirtQueue, VirtioDevice,
+    VIRTIO_MMIO_INT_VRING,
+};
+use crate::Error as DeviceError;
+
+/// The virtio features we are willing to expose on top of the ones supported by the host
+/// kernel. The event queue is still handled by the VMM, so ring features such as
+/// VIRTIO_RING_F_EVENT_IDX, which would affect all the queues, are left out.
+const VHOST_AVAIL_FEATURES: u64 =
+    1 << uapi::VIRTIO_F_VERSION_1 as u64 | 1 << uapi::VIRTIO_VSOCK_F_SEQPACKET as u64;
+
+pub struct VhostVsock {
+    cid: u64,
+    handle: VhostVsockHandle,
+    pub(crate) queues: Vec<VirtQueue>,
+    pub(crate) queue_events: Vec<EventFd>,
+    // The host kernel signals these EventFds whenever it has used buffers from the RX and TX
+    // queues. They are routed through the VMM so that the interrupt status gets updated before
+    // the guest is interrupted.
+    pub(crate) call_events: Vec<EventFd>,
+    pub(crate) avail_features: u64,
+    pub(crate) acked_features: u64,
+    pub(crate) interrupt_status: Arc<AtomicUsize>,
+    pub(crate) interrupt_evt: EventFd,
+    pub(crate) activate_evt: EventFd,
+    pub(crate) device_state: DeviceState,
+}
+
+impl VhostVsock {
+    /// Create a new vhost-vsock device and register the given guest CID with the host kernel.
+    pub fn new(cid: u64) -> Result<VhostVsock> {
+        let handle = VhostVsockHandle::new()?;
+        handle.set_guest_cid(cid)?;
+        let avail_features = handle.get_features()? & VHOST_AVAIL_FEATURES;
+
+        let queues: Vec<VirtQueue> = defs::QUEUE_SIZES
+            .iter()
+            .map(|&max_size| VirtQueue::new(max_size))
+            .collect();
+        let mut queue_events = Vec::new();
+        for _ in 0..queues.len() {
+            queue_events.push(EventFd::new(libc::EFD_NONBLOCK).map_err(Error::EventFd)?);
+        }
+        let call_events = vec![
+            EventFd::new(libc::EFD_NONBLOCK).map_err(Error::EventFd)?,
+            EventFd::new(libc::EFD_NONBLOCK).map_err(Error::EventFd)?,
+        ];
+
+        Ok(VhostVsock {
+            cid,
+            handle,
+            queues,
+            queue_events,
+            call_events,
+            avail_features,
+            acked_features: 0,
+            interrupt_status: Arc::new(AtomicUsize::new(0)),
+            interrupt_evt: EventFd::new(libc::EFD_NONBLOCK).map_err(Error::EventFd)?,
+            activate_evt: EventFd::new(libc::EFD_NONBLOCK).map_err(Error::EventFd)?,
+            device_state: DeviceState::Inactive,
+        })
+    }
+
+    pub fn id(&self) -> &str {
+        defs::VSOCK_DEV_ID
+    }
+
+    pub fn cid(&self) -> u64 {
+        self.cid
+    }
+
+    /// Signal the guest driver that we've used some virtio buffers that it had previously made
+    /// available.
+    pub fn signal_used_queue(&self) -> result::Result<(), DeviceError> {
+        debug!("vhost-vsock: raising IRQ");
+        self.interrupt_status
+            .fetch_or(VIRTIO_MMIO_INT_VRING as usize, Ordering::SeqCst);
+        self.interrupt_evt.write(1).map_err(|e| {
+            error!("Failed to signal used queue: {:?}", e);
+            DeviceError::FailedSignalingUsedQueue(e)
+        })
+    }
+
+    /// Configure the host kernel data path and start it.
+    fn start_vhost(&self, mem: &GuestMemoryMmap) -> Result<()> {
+        self.handle.set_features(self.acked_features)?;
+        self.handle.set_mem_table(mem)?;
+        for &index in [RXQ_INDEX, TXQ_INDEX].iter() {
+            self.handle.set_vring(
+                index,
+                &self.queues[index],
+                mem,
+                &self.queue_events[index],
+                &self.call_events[index],
+            )?;
+        }
+        self.handle.set_running(true)
+    }
+}
+
+impl VirtioDevice for VhostVsock {
+    fn avail_features(&self) -> u64 {
+        self.avail_features
+    }
+
+    fn acked_features(&self) -> u64 {
+        self.acked_features
+    }
+
+    fn set_acked_features(&mut self, acked_features: u64) {
+        self.acked_features = acked_features
+    }
+
+    fn device_type(&self) -> u32 {
+        uapi::VIRTIO_ID_VSOCK
+    }
+
+    fn queues(&self) -> &[VirtQueue] {
+        &self.queues
+    }
+
+    fn queues_mut(&mut self) -> &mut [VirtQueue] {
+        &mut self.queues
+    }
+
+    fn queue_events(&self) -> &[EventFd] {
+        &self.queue_events
+    }
+
+    fn interrupt_evt(&self) -> &EventFd {
+        &self.interrupt_evt
+    }
+
+    fn interrupt_status(&self) -> Arc<AtomicUsize> {
+        self.interrupt_status.clone()
+    }
+
+    fn read_config(&self, offset: u64, data: &mut [u8]) {
+        match offset {
+            0 if data.len() == 8 => byte_order::write_le_u64(data, self.cid()),
+            0 if data.len() == 4 => {
+                byte_order::write_le_u32(data, (self.cid() & 0xffff_ffff) as u32)
+            }
+            4 if data.len() == 4 => {
+                byte_order::write_le_u32(data, ((self.cid() >> 32) & 0xffff_ffff) as u32)
+            }
+            _ => warn!(
+                "vhost-vsock: received invalid read request of {} bytes at offset {}",
+                data.len(),
+                offset
+            ),
+        }
+    }
+
+    fn write_config(&mut self, offset: u64, data: &[u8]) {
+        warn!(
+            "vhost-vsock: guest driver attempted to write device config (offset={:x}, len={:x})",
+            offset,
+            data.len()
+        );
+    }
+
+    fn activate(&mut self, mem: GuestMemoryMmap) -> ActivateResult {
+        if self.queues.len() != defs::NUM_QUEUES {
+            error!(
+                "Cannot perform activate. Expected {} queue(s), got {}",
+                defs::NUM_QUEUES,
+                self.queues.len()
+            );
+            return Err(ActivateError::BadActivate);
+        }
+
+        if let Err(e) = self.start_vhost(&mem) {
+            error!("Cannot start the vhost-vsock data path: {:?}", e);
+            return Err(ActivateError::BadActivate);
+        }
+
+        if self.activate_evt.write(1).is_err() {
+            error!("Cannot write to activate_evt",);
+            return Err(ActivateError::BadActivate);
+        }
+
+        self.device_state = DeviceState::Activated(mem);
+
+        Ok(())
+    }
+
+    fn is_activated(&self) -> bool {
+        match self.device_state {
+            DeviceState::Inactive => false,
+            DeviceState::Activated(_) => true,
+        }
+    }
+}
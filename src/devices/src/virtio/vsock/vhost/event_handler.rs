@@ -0,0 +1,108 @@
+// Copyright 2020 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+/// The RX and TX queues of a vhost-vsock device are processed by the host kernel, so the event
+/// handler only needs to:
+/// 1. Forward the host kernel used buffer notifications to the guest, by raising our assigned IRQ;
+/// 2. Consume the event queue notifications, since the event queue is never processed.
+use std::os::unix::io::AsRawFd;
+
+use polly::event_manager::{EventManager, Subscriber};
+use utils::epoll::{EpollEvent, EventSet};
+
+use super::device::VhostVsock;
+use crate::virtio::vsock::device::{EVQ_INDEX, RXQ_INDEX, TXQ_INDEX};
+use crate::virtio::VirtioDevice;
+
+impl VhostVsock {
+    fn handle_call_event(&self, index: usize) {
+        debug!("vhost-vsock: call event on queue {}", index);
+        if let Err(e) = self.call_events[index].read() {
+            error!("Failed to consume vhost-vsock call event: {:?}", e);
+        } else {
+            self.signal_used_queue().unwrap_or_default();
+        }
+    }
+
+    fn handle_evq_event(&self) {
+        debug!("vhost-vsock: event queue event");
+        if let Err(e) = self.queue_events[EVQ_INDEX].read() {
+            error!("Failed to consume vhost-vsock evq event: {:?}", e);
+        }
+    }
+
+    fn handle_activate_event(&self, event_manager: &mut EventManager) {
+        debug!("vhost-vsock: activate event");
+        if let Err(e) = self.activate_evt.read() {
+            error!("Failed to consume vhost-vsock activate event: {:?}", e);
+        }
+        let activate_fd = self.activate_evt.as_raw_fd();
+        // The subscriber must exist as we previously registered activate_evt via
+        // `interest_list()`.
+        let self_subscriber = match event_manager.subscriber(activate_fd) {
+            Ok(subscriber) => subscriber,
+            Err(e) => {
+                error!("Failed to process vhost-vsock activate evt: {:?}", e);
+                return;
+            }
+        };
+
+        // Interest list changes when the device is activated.
+        let interest_list = self.interest_list();
+        for event in interest_list {
+            event_manager
+                .register(event.data() as i32, event, self_subscriber.clone())
+                .unwrap_or_else(|e| {
+                    error!("Failed to register vhost-vsock events: {:?}", e);
+                });
+        }
+
+        event_manager.unregister(activate_fd).unwrap_or_else(|e| {
+            error!("Failed to unregister vhost-vsock activate evt: {:?}", e);
+        });
+    }
+}
+
+impl Subscriber for VhostVsock {
+    fn process(&mut self, event: &EpollEvent, event_manager: &mut EventManager) {
+        let source = event.fd();
+        let rxq_call = self.call_events[RXQ_INDEX].as_raw_fd();
+        let txq_call = self.call_events[TXQ_INDEX].as_raw_fd();
+        let evq = self.queue_events[EVQ_INDEX].as_raw_fd();
+        let activate_evt = self.activate_evt.as_raw_fd();
+
+        if self.is_activated() {
+            match source {
+                _ if source == rxq_call => self.handle_call_event(RXQ_INDEX),
+                _ if source == txq_call => self.handle_call_event(TXQ_INDEX),
+                _ if source == evq => self.handle_evq_event(),
+                _ if source == activate_evt => self.handle_activate_event(event_manager),
+                _ => warn!("Unexpected vhost-vsock event received: {:?}", source),
+            }
+        } else {
+            warn!(
+                "Vhost-vsock: The device is not yet activated. Spurious event received: {:?}",
+                source
+            );
+        }
+    }
+
+    fn interest_list(&self) -> Vec<EpollEvent> {
+        // The RX and TX queue events are consumed by the host kernel and must not be polled.
+        if self.is_activated() {
+            vec![
+                EpollEvent::new(EventSet::IN, self.call_events[RXQ_INDEX].as_raw_fd() as u64),
+                EpollEvent::new(EventSet::IN, self.call_events[TXQ_INDEX].as_raw_fd() as u64),
+                EpollEvent::new(
+                    EventSet::IN,
+                    self.queue_events[EVQ_INDEX].as_raw_fd() as u64,
+                ),
+            ]
+        } else {
+            vec![EpollEvent::new(
+                EventSet::IN,
+                self.activate_evt.as_raw_fd() as u64,
+            )]
+        }
+    }
+}
@@ -0,0 +1,286 @@
+// Copyright 2020 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+/// This module implements the vhost-vsock data path for the vsock device. Instead of mediating
+/// guest vsock traffic in userspace, the RX and TX virtqueues are handed over to the host kernel
+/// `vhost_vsock` driver, and the guest CID is registered with the host `AF_VSOCK` address family,
+/// so that host processes can reach the guest through native `AF_VSOCK` sockets.
+///
+/// Only the event queue is still owned by the VMM, since the kernel driver never uses it.
+mod device;
+mod event_handler;
+
+pub use self::device::VhostVsock;
+
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::os::raw::{c_int, c_uint};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::AsRawFd;
+
+use utils::eventfd::EventFd;
+use vm_memory::{Address, ByteValued, GuestMemory, GuestMemoryMmap, GuestMemoryRegion};
+use vmm_sys_util::ioctl::{ioctl, ioctl_with_mut_ref, ioctl_with_ptr, ioctl_with_ref};
+
+use crate::virtio::Queue;
+
+mod defs {
+    /// Path to the host kernel vhost-vsock device.
+    pub const VHOST_VSOCK_PATH: &str = "/dev/vhost-vsock";
+}
+
+/// Ioctl type of the vhost commands.
+/// Defined in `include/uapi/linux/vhost.h`.
+const VHOST_VIRTIO: c_uint = 0xAF;
+
+ioctl_ior_nr!(VHOST_GET_FEATURES, VHOST_VIRTIO, 0x00, u64);
+ioctl_iow_nr!(VHOST_SET_FEATURES, VHOST_VIRTIO, 0x00, u64);
+ioctl_io_nr!(VHOST_SET_OWNER, VHOST_VIRTIO, 0x01);
+ioctl_iow_nr!(VHOST_SET_MEM_TABLE, VHOST_VIRTIO, 0x03, VhostMemory);
+ioctl_iow_nr!(VHOST_SET_VRING_NUM, VHOST_VIRTIO, 0x10, VhostVringState);
+ioctl_iow_nr!(VHOST_SET_VRING_ADDR, VHOST_VIRTIO, 0x11, VhostVringAddr);
+ioctl_iow_nr!(VHOST_SET_VRING_BASE, VHOST_VIRTIO, 0x12, VhostVringState);
+ioctl_iow_nr!(VHOST_SET_VRING_KICK, VHOST_VIRTIO, 0x20, VhostVringFile);
+ioctl_iow_nr!(VHOST_SET_VRING_CALL, VHOST_VIRTIO, 0x21, VhostVringFile);
+ioctl_iow_nr!(VHOST_VSOCK_SET_GUEST_CID, VHOST_VIRTIO, 0x60, u64);
+ioctl_iow_nr!(VHOST_VSOCK_SET_RUNNING, VHOST_VIRTIO, 0x61, c_int);
+
+/// Header of `struct vhost_memory`, which is followed by `nregions` memory regions.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+struct VhostMemory {
+    nregions: u32,
+    padding: u32,
+}
+
+// Safe because VhostMemory only holds plain data.
+unsafe impl ByteValued for VhostMemory {}
+
+/// Equivalent of `struct vhost_memory_region`.
+#[repr(C)]
+#[derive(Clone, Copy, Debug, Default)]
+struct VhostMemoryRegion {
+    guest_phys_addr: u64,
+    memory_size: u64,
+    userspace_addr: u64,
+    flags_padding: u64,
+}
+
+// Safe because VhostMemoryRegion only holds plain data.
+unsafe impl ByteValued for VhostMemoryRegion {}
+
+/// Equivalent of `struct vhost_vring_state`.
+#[repr(C)]
+#[derive(Debug, Default)]
+struct VhostVringState {
+    index: c_uint,
+    num: c_uint,
+}
+
+/// Equivalent of `struct vhost_vring_file`.
+#[repr(C)]
+#[derive(Debug, Default)]
+struct VhostVringFile {
+    index: c_uint,
+    fd: c_int,
+}
+
+/// Equivalent of `struct vhost_vring_addr`.
+#[repr(C)]
+#[derive(Debug, Default)]
+struct VhostVringAddr {
+    index: c_uint,
+    flags: c_uint,
+    desc_user_addr: u64,
+    used_user_addr: u64,
+    avail_user_addr: u64,
+    log_guest_addr: u64,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// Error creating an EventFd.
+    EventFd(io::Error),
+    /// A virtqueue address is not backed by guest memory.
+    InvalidQueueAddress,
+    /// Error opening the host vhost-vsock device.
+    OpenVhostVsock(io::Error),
+    /// A vhost ioctl returned an error.
+    VhostIoctl(io::Error),
+}
+
+type Result<T> = std::result::Result<T, Error>;
+
+fn ioctl_result(ret: c_int) -> Result<()> {
+    if ret < 0 {
+        return Err(Error::VhostIoctl(io::Error::last_os_error()));
+    }
+    Ok(())
+}
+
+/// An owned handle to the host kernel vhost-vsock device.
+pub struct VhostVsockHandle {
+    file: File,
+}
+
+impl VhostVsockHandle {
+    /// Opens the vhost-vsock device and takes ownership of it.
+    pub fn new() -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open(defs::VHOST_VSOCK_PATH)
+            .map_err(Error::OpenVhostVsock)?;
+        // Safe because the fd is valid and we check the return value.
+        ioctl_result(unsafe { ioctl(&file, VHOST_SET_OWNER()) })?;
+        Ok(VhostVsockHandle { file })
+    }
+
+    /// Returns the virtio features supported by the host kernel driver.
+    pub fn get_features(&self) -> Result<u64> {
+        let mut features: u64 = 0;
+        // Safe because the kernel only writes a u64 and we check the return value.
+        ioctl_result(unsafe {
+            ioctl_with_mut_ref(&self.file, VHOST_GET_FEATURES(), &mut features)
+        })?;
+        Ok(features)
+    }
+
+    /// Tells the host kernel driver which virtio features were acked by the guest.
+    pub fn set_features(&self, features: u64) -> Result<()> {
+        // Safe because the kernel only reads a u64 and we check the return value.
+        ioctl_result(unsafe { ioctl_with_ref(&self.file, VHOST_SET_FEATURES(), &features) })
+    }
+
+    /// Registers the guest memory layout with the host kernel driver.
+    pub fn set_mem_table(&self, mem: &GuestMemoryMmap) -> Result<()> {
+        let mut regions = Vec::new();
+        mem.with_regions_mut(|_, region| {
+            let userspace_addr = mem
+                .get_host_address(region.start_addr())
+                .map_err(|_| Error::InvalidQueueAddress)?;
+            regions.push(VhostMemoryRegion {
+                guest_phys_addr: region.start_addr().raw_value() as u64,
+                memory_size: region.len() as u64,
+                userspace_addr: userspace_addr as u64,
+                flags_padding: 0,
+            });
+            Ok(())
+        })?;
+
+        // `struct vhost_memory` ends with a flexible array of regions, so the header and the
+        // regions are laid out back to back in the same buffer.
+        let header = VhostMemory {
+            nregions: regions.len() as u32,
+            padding: 0,
+        };
+        let mut table = header.as_slice().to_vec();
+        for region in regions.iter() {
+            table.extend_from_slice(region.as_slice());
+        }
+
+        // Safe because the buffer holds a valid `struct vhost_memory` and we check the return
+        // value.
+        ioctl_result(unsafe { ioctl_with_ptr(&self.file, VHOST_SET_MEM_TABLE(), table.as_ptr()) })
+    }
+
+    /// Hands the virtqueue at `index` over to the host kernel driver. The driver is notified of
+    /// new buffers through `kick_evt`, and signals used buffers through `call_evt`.
+    pub fn set_vring(
+        &self,
+        index: usize,
+        queue: &Queue,
+        mem: &GuestMemoryMmap,
+        kick_evt: &EventFd,
+        call_evt: &EventFd,
+    ) -> Result<()> {
+        let index = index as c_uint;
+        let host_address = |addr| {
+            mem.get_host_address(addr)
+                .map(|host_addr| host_addr as u64)
+                .map_err(|_| Error::InvalidQueueAddress)
+        };
+
+        let num = VhostVringState {
+            index,
+            num: c_uint::from(queue.actual_size()),
+        };
+        // Safe because the kernel only reads the struct and we check the return value.
+        ioctl_result(unsafe { ioctl_with_ref(&self.file, VHOST_SET_VRING_NUM(), &num) })?;
+
+        let addr = VhostVringAddr {
+            index,
+            flags: 0,
+            desc_user_addr: host_address(queue.desc_table)?,
+            used_user_addr: host_address(queue.used_ring)?,
+            avail_user_addr: host_address(queue.avail_ring)?,
+            log_guest_addr: 0,
+        };
+        // Safe because the kernel only reads the struct and we check the return value.
+        ioctl_result(unsafe { ioctl_with_ref(&self.file, VHOST_SET_VRING_ADDR(), &addr) })?;
+
+        let base = VhostVringState {
+            index,
+            num: c_uint::from(queue.next_avail.0),
+        };
+        // Safe because the kernel only reads the struct and we check the return value.
+        ioctl_result(unsafe { ioctl_with_ref(&self.file, VHOST_SET_VRING_BASE(), &base) })?;
+
+        let kick = VhostVringFile {
+            index,
+            fd: kick_evt.as_raw_fd(),
+        };
+        // Safe because the kernel only reads the struct and we check the return value.
+        ioctl_result(unsafe { ioctl_with_ref(&self.file, VHOST_SET_VRING_KICK(), &kick) })?;
+
+        let call = VhostVringFile {
+            index,
+            fd: call_evt.as_raw_fd(),
+        };
+        // Safe because the kernel only reads the struct and we check the return value.
+        ioctl_result(unsafe { ioctl_with_ref(&self.file, VHOST_SET_VRING_CALL(), &call) })
+    }
+
+    /// Registers the guest CID with the host `AF_VSOCK` address family.
+    pub fn set_guest_cid(&self, cid: u64) -> Result<()> {
+        // Safe because the kernel only reads a u64 and we check the return value.
+        ioctl_result(unsafe { ioctl_with_ref(&self.file, VHOST_VSOCK_SET_GUEST_CID(), &cid) })
+    }
+
+    /// Starts or stops the host kernel data path.
+    pub fn set_running(&self, running: bool) -> Result<()> {
+        let running = c_int::from(running);
+        // Safe because the kernel only reads an int and we check the return value.
+        ioctl_result(unsafe { ioctl_with_ref(&self.file, VHOST_VSOCK_SET_RUNNING(), &running) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::mem::size_of;
+
+    #[test]
+    fn test_vhost_abi() {
+        // The layouts must match the ones from `include/uapi/linux/vhost_types.h`.
+        assert_eq!(size_of::<VhostMemory>(), 8);
+        assert_eq!(size_of::<VhostMemoryRegion>(), 32);
+        assert_eq!(size_of::<VhostVringState>(), 8);
+        assert_eq!(size_of::<VhostVringFile>(), 8);
+        assert_eq!(size_of::<VhostVringAddr>(), 40);
+
+        assert_eq!(VHOST_GET_FEATURES(), 0x8008_af00);
+        assert_eq!(VHOST_SET_FEATURES(), 0x4008_af00);
+        assert_eq!(VHOST_SET_OWNER(), 0xaf01);
+        assert_eq!(VHOST_SET_MEM_TABLE(), 0x4008_af03);
+        assert_eq!(VHOST_SET_VRING_NUM(), 0x4008_af10);
+        assert_eq!(VHOST_SET_VRING_ADDR(), 0x4028_af11);
+        assert_eq!(VHOST_SET_VRING_BASE(), 0x4008_af12);
+        assert_eq!(VHOST_SET_VRING_KICK(), 0x4008_af20);
+        assert_eq!(VHOST_SET_VRING_CALL(), 0x4008_af21);
+        assert_eq!(VHOST_VSOCK_SET_GUEST_CID(), 0x4008_af60);
+        assert_eq!(VHOST_VSOCK_SET_RUNNING(), 0x4004_af61);
+    }
+}
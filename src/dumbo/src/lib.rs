@@ -24,6 +24,7 @@ mod tcp;
 
 pub use mac::{MacAddr, MAC_ADDR_LEN};
 pub use pdu::arp::{EthIPv4ArpFrame, ETH_IPV4_FRAME_LEN};
+pub use pdu::dhcp::{DhcpLease, MAX_DNS_SERVERS as DHCP_MAX_DNS_SERVERS};
 pub use pdu::ethernet::{
     EthernetFrame, ETHERTYPE_ARP, ETHERTYPE_IPV4, PAYLOAD_OFFSET as ETHERNET_PAYLOAD_OFFSET,
 };
@@ -5,18 +5,33 @@
 #![allow(missing_docs)]
 
 use std::convert::From;
-use std::net::Ipv4Addr;
+use std::net::{Ipv4Addr, Ipv6Addr};
 use std::num::NonZeroUsize;
 use std::result::Result;
+use std::sync::{Arc, Mutex};
 
 use crate::MacAddr;
 use logger::{Metric, METRICS};
+use mmds::data_store::Mmds;
 use pdu::arp::{test_speculative_tpa, Error as ArpFrameError, EthIPv4ArpFrame, ETH_IPV4_FRAME_LEN};
-use pdu::ethernet::{Error as EthernetFrameError, EthernetFrame, ETHERTYPE_ARP, ETHERTYPE_IPV4};
-use pdu::ipv4::{test_speculative_dst_addr, Error as IPv4PacketError, IPv4Packet, PROTOCOL_TCP};
+use pdu::dhcp::{self, DhcpLease, DhcpMessage, Error as DhcpMessageError};
+use pdu::ethernet::{
+    Error as EthernetFrameError, EthernetFrame, ETHERTYPE_ARP, ETHERTYPE_IPV4, ETHERTYPE_IPV6,
+};
+use pdu::icmpv4::{self, Error as Icmpv4MessageError, Icmpv4Message};
+use pdu::icmpv6::{Error as Icmpv6MessageError, Icmpv6Message};
+use pdu::ipv4::{
+    test_speculative_dst_addr, Error as IPv4PacketError, IPv4Packet, PROTOCOL_ICMP, PROTOCOL_TCP,
+    PROTOCOL_UDP,
+};
+use pdu::ipv6::{
+    self, solicited_node_multicast_addr, Error as IPv6PacketError, IPv6Packet, DEFAULT_HOP_LIMIT,
+    IPV6_VERSION, PROTOCOL_ICMPV6,
+};
 use pdu::tcp::Error as TcpSegmentError;
+use pdu::udp::{Error as UdpDatagramError, UdpDatagram};
 use pdu::Incomplete;
-use tcp::handler::{self, RecvEvent, TcpIPv4Handler, WriteEvent};
+use tcp::handler::{self, RecvError, RecvEvent, TcpIPv4Handler, WriteEvent};
 use tcp::NextSegmentStatus;
 use utils::time::timestamp_cycles;
 
@@ -33,9 +48,35 @@ enum WriteArpFrameError {
     Ethernet(EthernetFrameError),
 }
 
+#[cfg_attr(test, derive(Debug, PartialEq))]
+enum WriteNdpFrameError {
+    NoPendingNdpReply,
+    Icmpv6(Icmpv6MessageError),
+    IPv6Packet(IPv6PacketError),
+    Ethernet(EthernetFrameError),
+}
+
+#[cfg_attr(test, derive(Debug, PartialEq))]
+enum WriteDhcpFrameError {
+    NoPendingDhcpReply,
+    Dhcp(DhcpMessageError),
+    Udp(UdpDatagramError),
+    IPv4Packet(IPv4PacketError),
+    Ethernet(EthernetFrameError),
+}
+
+#[cfg_attr(test, derive(Debug, PartialEq))]
+enum WriteIcmpFrameError {
+    NoPendingIcmpReply,
+    Icmpv4(Icmpv4MessageError),
+    IPv4Packet(IPv4PacketError),
+    Ethernet(EthernetFrameError),
+}
+
 #[cfg_attr(test, derive(Debug, PartialEq))]
 enum WritePacketError {
     IPv4Packet(IPv4PacketError),
+    IPv6Packet(IPv6PacketError),
     Ethernet(EthernetFrameError),
     TcpSegment(TcpSegmentError),
 }
@@ -44,11 +85,51 @@ impl From<handler::WriteNextError> for WritePacketError {
     fn from(error: handler::WriteNextError) -> Self {
         match error {
             handler::WriteNextError::IPv4Packet(inner) => WritePacketError::IPv4Packet(inner),
+            handler::WriteNextError::IPv6Packet(inner) => WritePacketError::IPv6Packet(inner),
             handler::WriteNextError::TcpSegment(inner) => WritePacketError::TcpSegment(inner),
         }
     }
 }
 
+// Updates the MMDS metrics based on the outcome of handing a packet over to the TCP handler.
+fn update_recv_metrics(result: Result<RecvEvent, RecvError>) {
+    match result {
+        Ok(event) => {
+            METRICS.mmds.rx_count.inc();
+            match event {
+                RecvEvent::NewConnectionSuccessful => METRICS.mmds.connections_created.inc(),
+                RecvEvent::NewConnectionReplacing => {
+                    METRICS.mmds.connections_created.inc();
+                    METRICS.mmds.connections_destroyed.inc();
+                }
+                RecvEvent::EndpointDone => {
+                    METRICS.mmds.connections_destroyed.inc();
+                }
+                _ => (),
+            }
+        }
+        Err(_) => METRICS.mmds.rx_accepted_err.inc(),
+    }
+}
+
+// The details of a DHCP client request, which are needed to write the reply.
+struct DhcpReply {
+    message_type: u8,
+    xid: u32,
+    flags: u16,
+    chaddr: MacAddr,
+    dst_addr: Ipv4Addr,
+}
+
+// An ICMP message which has yet to be sent back to the guest.
+struct IcmpReply {
+    message_type: u8,
+    code: u8,
+    rest_of_header: u32,
+    payload: Vec<u8>,
+    dst_addr: Ipv4Addr,
+}
+
 pub struct MmdsNetworkStack {
     // Network interface MAC address used by frames/packets heading to MMDS server.
     remote_mac_addr: MacAddr,
@@ -56,37 +137,58 @@ pub struct MmdsNetworkStack {
     pub(crate) mac_addr: MacAddr,
     // MMDS server IPv4 address.
     pub(crate) ipv4_addr: Ipv4Addr,
+    // MMDS server IPv6 address. The MMDS is not reachable over IPv6 when missing.
+    pub(crate) ipv6_addr: Option<Ipv6Addr>,
     // ARP reply destination IPv4 address (requester of address resolution reply).
     // It is the Ipv4Addr of the network interface for which the MmdsNetworkStack
     // routes the packets.
     pending_arp_reply_dest: Option<Ipv4Addr>,
+    // Neighbor Advertisement destination IPv6 address (the source of the Neighbor Solicitation
+    // which asked for the MMDS MAC address).
+    pending_ndp_reply_dest: Option<Ipv6Addr>,
+    // The network configuration handed out to the guest over DHCP. The DHCP requests of the
+    // guest are not answered when missing.
+    pub(crate) dhcp_lease: Option<DhcpLease>,
+    // The reply to the last DHCP request of the guest, which has yet to be sent.
+    pending_dhcp_reply: Option<DhcpReply>,
+    // The reply to the last ICMP echo request (or the error caused by the last UDP datagram)
+    // heading to the MMDS, which has yet to be sent.
+    pending_icmp_reply: Option<IcmpReply>,
     // This handles MMDS<->guest interaction at the TCP level.
     pub(crate) tcp_handler: TcpIPv4Handler,
 }
 
 impl MmdsNetworkStack {
+    // The requests received by the network stack are answered by `mmds`.
     pub fn new(
         mac_addr: MacAddr,
         ipv4_addr: Ipv4Addr,
         tcp_port: u16,
         max_connections: NonZeroUsize,
         max_pending_resets: NonZeroUsize,
+        mmds: Arc<Mutex<Mmds>>,
     ) -> Self {
         MmdsNetworkStack {
             remote_mac_addr: mac_addr,
             mac_addr,
             ipv4_addr,
+            ipv6_addr: None,
             pending_arp_reply_dest: None,
+            pending_ndp_reply_dest: None,
+            dhcp_lease: None,
+            pending_dhcp_reply: None,
+            pending_icmp_reply: None,
             tcp_handler: TcpIPv4Handler::new(
                 ipv4_addr,
                 tcp_port,
                 max_connections,
                 max_pending_resets,
+                mmds,
             ),
         }
     }
 
-    pub fn new_with_defaults(mmds_ipv4_addr: Option<Ipv4Addr>) -> Self {
+    pub fn new_with_defaults(mmds_ipv4_addr: Option<Ipv4Addr>, mmds: Arc<Mutex<Mmds>>) -> Self {
         // The unwrap is safe if parse_str() is implemented properly.
         let mac_addr = MacAddr::parse_str(DEFAULT_MAC_ADDR).unwrap();
         let ipv4_addr = mmds_ipv4_addr.unwrap_or_else(|| Ipv4Addr::from(DEFAULT_IPV4_ADDR));
@@ -98,6 +200,7 @@ impl MmdsNetworkStack {
             DEFAULT_TCP_PORT,
             NonZeroUsize::new(DEFAULT_MAX_CONNECTIONS).unwrap(),
             NonZeroUsize::new(DEFAULT_MAX_PENDING_RESETS).unwrap(),
+            mmds,
         )
     }
 
@@ -110,12 +213,39 @@ impl MmdsNetworkStack {
         Ipv4Addr::from(DEFAULT_IPV4_ADDR)
     }
 
+    // Setting the IPv6 address to None makes the MMDS unreachable over IPv6.
+    pub fn set_ipv6_addr(&mut self, ipv6_addr: Option<Ipv6Addr>) {
+        self.ipv6_addr = ipv6_addr;
+        self.pending_ndp_reply_dest = None;
+        self.tcp_handler.set_local_ipv6_addr(ipv6_addr);
+    }
+
+    // Setting the DHCP lease to None stops the MMDS network stack from answering DHCP requests.
+    pub fn set_dhcp_lease(&mut self, dhcp_lease: Option<DhcpLease>) {
+        self.dhcp_lease = dhcp_lease;
+        self.pending_dhcp_reply = None;
+    }
+
+    pub fn dhcp_lease(&self) -> Option<&DhcpLease> {
+        self.dhcp_lease.as_ref()
+    }
+
+    // The MMDS which answers the requests received by the network stack.
+    pub fn mmds(&self) -> &Arc<Mutex<Mmds>> {
+        self.tcp_handler.mmds()
+    }
+
     // This is the entry point into the MMDS network stack. The src slice should hold the contents
     // of an Ethernet frame (of that exact size, without the CRC).
     pub fn detour_frame(&mut self, src: &[u8]) -> bool {
-        // The frame cannot possibly contain an ARP request or IPv4 packet for the MMDS.
+        // The frame cannot possibly contain an ARP request, an IPv4/IPv6 packet for the MMDS, or
+        // a broadcast DHCP request.
         if !test_speculative_tpa(src, self.ipv4_addr)
             && !test_speculative_dst_addr(src, self.ipv4_addr)
+            && !(self.dhcp_lease.is_some() && test_speculative_dst_addr(src, Ipv4Addr::BROADCAST))
+            && !self
+                .ipv6_addr
+                .map_or(false, |addr| ipv6::test_speculative_dst_addr(src, addr))
         {
             return false;
         }
@@ -124,6 +254,7 @@ impl MmdsNetworkStack {
             match eth.ethertype() {
                 ETHERTYPE_ARP => return self.detour_arp(eth),
                 ETHERTYPE_IPV4 => return self.detour_ipv4(eth),
+                ETHERTYPE_IPV6 => return self.detour_ipv6(eth),
                 _ => (),
             };
         } else {
@@ -148,34 +279,27 @@ impl MmdsNetworkStack {
         // checksum computation from the guest driver to some other entity. Clear up this entire
         // context at some point!
         if let Ok(ip) = IPv4Packet::from_bytes(eth.payload(), false) {
-            if ip.protocol() == PROTOCOL_TCP {
-                // Note-1: `remote_mac_address` is actually the network device mac address, where
-                // this TCP segment came from.
-                // Note-2: For every routed packet we will have a single source MAC address, because
-                // each MmdsNetworkStack routes packets for only one network device.
-                self.remote_mac_addr = eth.src_mac();
-                match self.tcp_handler.receive_packet(&ip) {
-                    Ok(event) => {
-                        METRICS.mmds.rx_count.inc();
-                        match event {
-                            RecvEvent::NewConnectionSuccessful => {
-                                METRICS.mmds.connections_created.inc()
-                            }
-                            RecvEvent::NewConnectionReplacing => {
-                                METRICS.mmds.connections_created.inc();
-                                METRICS.mmds.connections_destroyed.inc();
-                            }
-                            RecvEvent::EndpointDone => {
-                                METRICS.mmds.connections_destroyed.inc();
-                            }
-                            _ => (),
-                        }
-                    }
-                    Err(_) => METRICS.mmds.rx_accepted_err.inc(),
+            if ip.protocol() == PROTOCOL_UDP && self.detour_dhcp(&eth, &ip) {
+                return true;
+            }
+            // Broadcast packets, other than DHCP requests, are none of our business.
+            if ip.destination_address() != self.ipv4_addr {
+                return false;
+            }
+
+            match ip.protocol() {
+                PROTOCOL_TCP => {
+                    // Note-1: `remote_mac_address` is actually the network device mac address,
+                    // where this TCP segment came from.
+                    // Note-2: For every routed packet we will have a single source MAC address,
+                    // because each MmdsNetworkStack routes packets for only one network device.
+                    self.remote_mac_addr = eth.src_mac();
+                    update_recv_metrics(self.tcp_handler.receive_packet(&ip));
                 }
-            } else {
-                // A non-TCP IPv4 packet heading towards the MMDS; we consider it unusual.
-                METRICS.mmds.rx_accepted_unusual.inc();
+                PROTOCOL_ICMP => self.detour_icmp(&eth, &ip),
+                PROTOCOL_UDP => self.detour_udp(&eth, &ip),
+                // Any other IPv4 packet heading towards the MMDS is considered unusual.
+                _ => METRICS.mmds.rx_accepted_unusual.inc(),
             }
             return true;
         }
@@ -183,6 +307,174 @@ impl MmdsNetworkStack {
         false
     }
 
+    fn detour_dhcp(&mut self, eth: &EthernetFrame<&[u8]>, ip: &IPv4Packet<&[u8]>) -> bool {
+        let lease = match self.dhcp_lease {
+            Some(ref lease) => lease,
+            None => return false,
+        };
+
+        // The same note about checksums from detour_ipv4 applies here.
+        let udp = match UdpDatagram::from_bytes(ip.payload(), None) {
+            Ok(udp)
+                if udp.source_port() == dhcp::CLIENT_PORT
+                    && udp.destination_port() == dhcp::SERVER_PORT =>
+            {
+                udp
+            }
+            _ => return false,
+        };
+        let request = match DhcpMessage::from_bytes(udp.payload()) {
+            Ok(request) if request.op() == dhcp::OP_BOOTREQUEST => request,
+            _ => return false,
+        };
+        // The guest may be talking to another DHCP server, reachable through the TAP device.
+        if request
+            .server_id()
+            .map_or(false, |server_id| server_id != self.ipv4_addr)
+        {
+            return false;
+        }
+
+        let message_type = match request.message_type() {
+            Some(dhcp::MESSAGE_TYPE_DISCOVER) => dhcp::MESSAGE_TYPE_OFFER,
+            Some(dhcp::MESSAGE_TYPE_REQUEST) => {
+                // Clients which renew their lease don't specify the requested address.
+                let requested_addr = request
+                    .requested_ipv4_addr()
+                    .unwrap_or_else(|| request.ciaddr());
+                if requested_addr == lease.ipv4_addr {
+                    dhcp::MESSAGE_TYPE_ACK
+                } else {
+                    dhcp::MESSAGE_TYPE_NAK
+                }
+            }
+            // The remaining messages (such as DHCPRELEASE) don't get a reply, and can't change
+            // the lease either.
+            _ => {
+                METRICS.mmds.rx_accepted_unusual.inc();
+                return true;
+            }
+        };
+
+        // DHCPNAK messages are always broadcast. The other replies are sent straight to the
+        // clients which already have an address.
+        let ciaddr = request.ciaddr();
+        let dst_addr = if message_type == dhcp::MESSAGE_TYPE_NAK || ciaddr.is_unspecified() {
+            Ipv4Addr::BROADCAST
+        } else {
+            ciaddr
+        };
+
+        self.remote_mac_addr = eth.src_mac();
+        self.pending_dhcp_reply = Some(DhcpReply {
+            message_type,
+            xid: request.xid(),
+            flags: request.flags(),
+            chaddr: request.chaddr(),
+            dst_addr,
+        });
+        METRICS.mmds.rx_count.inc();
+        true
+    }
+
+    fn detour_icmp(&mut self, eth: &EthernetFrame<&[u8]>, ip: &IPv4Packet<&[u8]>) {
+        match Icmpv4Message::from_bytes(ip.payload(), true) {
+            Ok(request) if request.message_type() == icmpv4::TYPE_ECHO_REQUEST => {
+                // Echo replies carry the identifier, sequence number and data of the request.
+                self.remote_mac_addr = eth.src_mac();
+                self.pending_icmp_reply = Some(IcmpReply {
+                    message_type: icmpv4::TYPE_ECHO_REPLY,
+                    code: 0,
+                    rest_of_header: request.rest_of_header(),
+                    payload: request.payload().to_vec(),
+                    dst_addr: ip.source_address(),
+                });
+                METRICS.mmds.rx_count.inc();
+            }
+            _ => METRICS.mmds.rx_accepted_unusual.inc(),
+        }
+    }
+
+    fn detour_udp(&mut self, eth: &EthernetFrame<&[u8]>, ip: &IPv4Packet<&[u8]>) {
+        // UDP datagrams heading to the MMDS are unusual, but the ones sent to other ports than
+        // the MMDS one are told that nothing listens there, so they don't have to time out.
+        match UdpDatagram::from_bytes(ip.payload(), None) {
+            Ok(udp) if udp.destination_port() != self.tcp_handler.local_port => {
+                // The error quotes the IP header and the beginning of the offending datagram.
+                let quote_len = std::cmp::min(
+                    ip.header_len() + icmpv4::UNREACHABLE_DATAGRAM_PREFIX_LEN,
+                    ip.len(),
+                );
+                self.remote_mac_addr = eth.src_mac();
+                self.pending_icmp_reply = Some(IcmpReply {
+                    message_type: icmpv4::TYPE_DESTINATION_UNREACHABLE,
+                    code: icmpv4::CODE_PORT_UNREACHABLE,
+                    rest_of_header: 0,
+                    payload: eth.payload()[..quote_len].to_vec(),
+                    dst_addr: ip.source_address(),
+                });
+            }
+            _ => (),
+        }
+        METRICS.mmds.rx_accepted_unusual.inc();
+    }
+
+    fn detour_ipv6(&mut self, eth: EthernetFrame<&[u8]>) -> bool {
+        let ipv6_addr = match self.ipv6_addr {
+            Some(addr) => addr,
+            None => return false,
+        };
+
+        let ip = match IPv6Packet::from_bytes(eth.payload()) {
+            Ok(ip) => ip,
+            Err(_) => return false,
+        };
+
+        let dst_addr = ip.destination_address();
+        if dst_addr == solicited_node_multicast_addr(ipv6_addr) {
+            // The solicited-node multicast address may be shared with other hosts, so we only
+            // detour the Neighbor Solicitations which are looking for the MMDS. Solicitations
+            // sent from the unspecified address are part of Duplicate Address Detection, which
+            // is not something we answer to.
+            let src_addr = ip.source_address();
+            if ip.next_header() == PROTOCOL_ICMPV6
+                && ip.hop_limit() == DEFAULT_HOP_LIMIT
+                && !src_addr.is_unspecified()
+            {
+                if let Ok(icmp) =
+                    Icmpv6Message::from_bytes(ip.payload(), Some((src_addr, dst_addr)))
+                {
+                    if icmp.neighbor_solicitation_target() == Some(ipv6_addr) {
+                        self.remote_mac_addr = eth.src_mac();
+                        self.pending_ndp_reply_dest = Some(src_addr);
+                        return true;
+                    }
+                }
+            }
+            return false;
+        }
+
+        if dst_addr != ipv6_addr {
+            return false;
+        }
+
+        if ip.next_header() == PROTOCOL_TCP {
+            // The same notes from detour_ipv4 apply here.
+            self.remote_mac_addr = eth.src_mac();
+            update_recv_metrics(self.tcp_handler.receive_ipv6_packet(&ip));
+        } else {
+            // A non-TCP IPv6 packet heading towards the MMDS; we consider it unusual.
+            METRICS.mmds.rx_accepted_unusual.inc();
+        }
+        true
+    }
+
+    // Has to be called after every update of the MMDS data store, so the long-poll requests which
+    // are waiting for it get answered. Returns true if there are frames to send as a result.
+    pub fn process_data_store_update(&mut self) -> bool {
+        self.tcp_handler.check_pending_requests()
+    }
+
     // Allows the MMDS network stack to write a frame to the specified buffer. Will return:
     // - None, if the MMDS network stack has no frame to send at this point. The buffer can be
     // used for something else by the device model.
@@ -201,6 +493,45 @@ impl MmdsNetworkStack {
                     None
                 }
             };
+        } else if self.pending_ndp_reply_dest.is_some() {
+            // Then Neighbor Advertisements, which are the IPv6 equivalent.
+            return match self.write_ndp_reply(buf) {
+                Ok(something) => {
+                    METRICS.mmds.tx_count.inc();
+                    self.pending_ndp_reply_dest = None;
+                    something
+                }
+                Err(_) => {
+                    METRICS.mmds.tx_errors.inc();
+                    None
+                }
+            };
+        } else if self.pending_dhcp_reply.is_some() {
+            return match self.write_dhcp_reply(buf) {
+                Ok(something) => {
+                    METRICS.mmds.tx_count.inc();
+                    self.pending_dhcp_reply = None;
+                    something
+                }
+                Err(_) => {
+                    METRICS.mmds.tx_errors.inc();
+                    None
+                }
+            };
+        } else if self.pending_icmp_reply.is_some() {
+            return match self.write_icmp_reply(buf) {
+                Ok(something) => {
+                    METRICS.mmds.tx_count.inc();
+                    self.pending_icmp_reply = None;
+                    something
+                }
+                Err(_) => {
+                    METRICS.mmds.tx_errors.inc();
+                    // There's no point in trying to send a reply which doesn't fit.
+                    self.pending_icmp_reply = None;
+                    None
+                }
+            };
         } else {
             let call_write = match self.tcp_handler.next_segment_status() {
                 NextSegmentStatus::Available => true,
@@ -261,6 +592,158 @@ impl MmdsNetworkStack {
         ))
     }
 
+    fn write_ndp_reply(&self, buf: &mut [u8]) -> Result<Option<NonZeroUsize>, WriteNdpFrameError> {
+        let ndp_reply_dest = self
+            .pending_ndp_reply_dest
+            .ok_or_else(|| WriteNdpFrameError::NoPendingNdpReply)?;
+        // There can't be a pending reply without an IPv6 address.
+        let ipv6_addr = self
+            .ipv6_addr
+            .ok_or_else(|| WriteNdpFrameError::NoPendingNdpReply)?;
+
+        let mut eth_unsized = self
+            .prepare_eth_unsized(buf, ETHERTYPE_IPV6)
+            .map_err(WriteNdpFrameError::Ethernet)?;
+
+        let packet_len = {
+            let mut packet = IPv6Packet::write_header(
+                eth_unsized.inner_mut().payload_mut(),
+                PROTOCOL_ICMPV6,
+                ipv6_addr,
+                ndp_reply_dest,
+            )
+            .map_err(WriteNdpFrameError::IPv6Packet)?;
+
+            let icmp_len = Icmpv6Message::write_neighbor_advertisement(
+                packet.inner_mut().payload_mut(),
+                ipv6_addr,
+                ndp_reply_dest,
+                ipv6_addr,
+                self.mac_addr,
+            )
+            .map_err(WriteNdpFrameError::Icmpv6)?
+            .len();
+
+            packet.with_payload_len_unchecked(icmp_len).len()
+        };
+
+        Ok(Some(
+            // The unwrap() is safe because packet_len > 0.
+            NonZeroUsize::new(eth_unsized.with_payload_len_unchecked(packet_len).len()).unwrap(),
+        ))
+    }
+
+    fn write_dhcp_reply(
+        &self,
+        buf: &mut [u8],
+    ) -> Result<Option<NonZeroUsize>, WriteDhcpFrameError> {
+        let reply = self
+            .pending_dhcp_reply
+            .as_ref()
+            .ok_or_else(|| WriteDhcpFrameError::NoPendingDhcpReply)?;
+        let lease = if reply.message_type == dhcp::MESSAGE_TYPE_NAK {
+            None
+        } else {
+            // There can't be a pending offer or acknowledgement without a lease.
+            Some(
+                self.dhcp_lease
+                    .as_ref()
+                    .ok_or_else(|| WriteDhcpFrameError::NoPendingDhcpReply)?,
+            )
+        };
+
+        // The DHCP message is written separately, since the UDP datagram is built around its
+        // payload.
+        let mut dhcp_buf = [0u8; dhcp::MAX_REPLY_LEN];
+        let dhcp_len = DhcpMessage::write_reply(
+            &mut dhcp_buf[..],
+            reply.message_type,
+            reply.xid,
+            reply.flags,
+            reply.chaddr,
+            self.ipv4_addr,
+            lease,
+        )
+        .map_err(WriteDhcpFrameError::Dhcp)?
+        .len();
+
+        let mut eth_unsized = self
+            .prepare_eth_unsized(buf, ETHERTYPE_IPV4)
+            .map_err(WriteDhcpFrameError::Ethernet)?;
+
+        let packet_len = {
+            let mut packet = IPv4Packet::write_header(
+                eth_unsized.inner_mut().payload_mut(),
+                PROTOCOL_UDP,
+                self.ipv4_addr,
+                reply.dst_addr,
+            )
+            .map_err(WriteDhcpFrameError::IPv4Packet)?;
+
+            let datagram_len = UdpDatagram::write_incomplete_datagram(
+                packet.inner_mut().payload_mut(),
+                &dhcp_buf[..dhcp_len],
+            )
+            .map_err(WriteDhcpFrameError::Udp)?
+            .finalize(
+                dhcp::SERVER_PORT,
+                dhcp::CLIENT_PORT,
+                Some((self.ipv4_addr, reply.dst_addr)),
+            )
+            .len();
+
+            packet
+                .with_payload_len_unchecked(usize::from(datagram_len), true)
+                .len()
+        };
+
+        Ok(Some(
+            // The unwrap() is safe because packet_len > 0.
+            NonZeroUsize::new(eth_unsized.with_payload_len_unchecked(packet_len).len()).unwrap(),
+        ))
+    }
+
+    fn write_icmp_reply(
+        &self,
+        buf: &mut [u8],
+    ) -> Result<Option<NonZeroUsize>, WriteIcmpFrameError> {
+        let reply = self
+            .pending_icmp_reply
+            .as_ref()
+            .ok_or_else(|| WriteIcmpFrameError::NoPendingIcmpReply)?;
+
+        let mut eth_unsized = self
+            .prepare_eth_unsized(buf, ETHERTYPE_IPV4)
+            .map_err(WriteIcmpFrameError::Ethernet)?;
+
+        let packet_len = {
+            let mut packet = IPv4Packet::write_header(
+                eth_unsized.inner_mut().payload_mut(),
+                PROTOCOL_ICMP,
+                self.ipv4_addr,
+                reply.dst_addr,
+            )
+            .map_err(WriteIcmpFrameError::IPv4Packet)?;
+
+            let icmp_len = Icmpv4Message::write_message(
+                packet.inner_mut().payload_mut(),
+                reply.message_type,
+                reply.code,
+                reply.rest_of_header,
+                &reply.payload,
+            )
+            .map_err(WriteIcmpFrameError::Icmpv4)?
+            .len();
+
+            packet.with_payload_len_unchecked(icmp_len, true).len()
+        };
+
+        Ok(Some(
+            // The unwrap() is safe because packet_len > 0.
+            NonZeroUsize::new(eth_unsized.with_payload_len_unchecked(packet_len).len()).unwrap(),
+        ))
+    }
+
     fn write_packet(&mut self, buf: &mut [u8]) -> Result<Option<NonZeroUsize>, WritePacketError> {
         let mut eth_unsized = self
             .prepare_eth_unsized(buf, ETHERTYPE_IPV4)
@@ -275,6 +758,11 @@ impl MmdsNetworkStack {
         }
 
         if let Some(packet_len) = maybe_len {
+            // The TCP handler picks the IP version of the packet based on its destination.
+            let eth = eth_unsized.inner_mut();
+            if eth.payload()[0] >> 4 == IPV6_VERSION {
+                eth.set_ethertype(ETHERTYPE_IPV6);
+            }
             return Ok(Some(
                 // The unwrap() is safe because packet_len > 0.
                 NonZeroUsize::new(
@@ -301,9 +789,13 @@ mod tests {
     // all we're interested in is having some address different from the MMDS one.
     const REMOTE_ADDR: Ipv4Addr = Ipv4Addr::LOCALHOST;
     const REMOTE_MAC_STR: &str = "11:11:11:22:22:22";
+    const REMOTE_IPV6_ADDR: Ipv6Addr =
+        Ipv6Addr::new(0xfe80, 0, 0, 0, 0x1311, 0x11ff, 0xfe22, 0x2222);
+    const MMDS_IPV6_ADDR: Ipv6Addr = Ipv6Addr::new(0xfd00, 0xec2, 0, 0, 0, 0, 0, 0x254);
     const MMDS_PORT: u16 = 80;
     const REMOTE_PORT: u16 = 1235;
     const SEQ_NUMBER: u32 = 123;
+    const DHCP_XID: u32 = 0x1234_5678;
 
     // Helper methods which only make sense for testing.
     impl MmdsNetworkStack {
@@ -355,7 +847,11 @@ mod tests {
                     None,
                 )
                 .unwrap()
-                .finalize(REMOTE_PORT, MMDS_PORT, Some((REMOTE_ADDR, addr)))
+                .finalize(
+                    REMOTE_PORT,
+                    MMDS_PORT,
+                    Some((REMOTE_ADDR.into(), addr.into())),
+                )
                 .len();
 
                 packet.with_payload_len_unchecked(segment_len, true).len()
@@ -364,6 +860,160 @@ mod tests {
             eth_unsized.with_payload_len_unchecked(packet_len).len()
         }
 
+        fn write_incoming_dhcp_request(
+            &self,
+            buf: &mut [u8],
+            dst_addr: Ipv4Addr,
+            src_port: u16,
+            message_type: u8,
+            ciaddr: Ipv4Addr,
+            server_id: Ipv4Addr,
+        ) -> usize {
+            // Write a reply and then turn it into a request.
+            let mut dhcp_buf = [0u8; dhcp::MAX_REPLY_LEN];
+            let dhcp_len = {
+                let mut request = DhcpMessage::write_reply(
+                    &mut dhcp_buf[..],
+                    message_type,
+                    DHCP_XID,
+                    0,
+                    MacAddr::parse_str(REMOTE_MAC_STR).unwrap(),
+                    server_id,
+                    None,
+                )
+                .unwrap();
+                request.set_op(dhcp::OP_BOOTREQUEST).set_ciaddr(ciaddr);
+                request.len()
+            };
+
+            let mut eth_unsized = self.prepare_eth_unsized(buf, ETHERTYPE_IPV4).unwrap();
+            eth_unsized
+                .inner_mut()
+                .set_src_mac(MacAddr::parse_str(REMOTE_MAC_STR).unwrap());
+            let packet_len = {
+                let mut packet = IPv4Packet::write_header(
+                    eth_unsized.inner_mut().payload_mut(),
+                    PROTOCOL_UDP,
+                    ciaddr,
+                    dst_addr,
+                )
+                .unwrap();
+
+                let datagram_len = UdpDatagram::write_incomplete_datagram(
+                    packet.inner_mut().payload_mut(),
+                    &dhcp_buf[..dhcp_len],
+                )
+                .unwrap()
+                .finalize(src_port, dhcp::SERVER_PORT, Some((ciaddr, dst_addr)))
+                .len();
+
+                packet
+                    .with_payload_len_unchecked(usize::from(datagram_len), true)
+                    .len()
+            };
+
+            eth_unsized.with_payload_len_unchecked(packet_len).len()
+        }
+
+        fn write_incoming_ipv4_packet<F>(
+            &self,
+            buf: &mut [u8],
+            dst_addr: Ipv4Addr,
+            protocol: u8,
+            write_payload: F,
+        ) -> usize
+        where
+            F: FnOnce(&mut [u8]) -> usize,
+        {
+            let mut eth_unsized = self.prepare_eth_unsized(buf, ETHERTYPE_IPV4).unwrap();
+            eth_unsized
+                .inner_mut()
+                .set_src_mac(MacAddr::parse_str(REMOTE_MAC_STR).unwrap());
+            let packet_len = {
+                let mut packet = IPv4Packet::write_header(
+                    eth_unsized.inner_mut().payload_mut(),
+                    protocol,
+                    REMOTE_ADDR,
+                    dst_addr,
+                )
+                .unwrap();
+                let payload_len = write_payload(packet.inner_mut().payload_mut());
+                packet.with_payload_len_unchecked(payload_len, true).len()
+            };
+
+            eth_unsized.with_payload_len_unchecked(packet_len).len()
+        }
+
+        fn write_incoming_ipv6_packet<F>(
+            &self,
+            buf: &mut [u8],
+            dst_addr: Ipv6Addr,
+            next_header: u8,
+            write_payload: F,
+        ) -> usize
+        where
+            F: FnOnce(&mut [u8]) -> usize,
+        {
+            let mut eth_unsized = self.prepare_eth_unsized(buf, ETHERTYPE_IPV6).unwrap();
+            eth_unsized
+                .inner_mut()
+                .set_src_mac(MacAddr::parse_str(REMOTE_MAC_STR).unwrap());
+            let packet_len = {
+                let mut packet = IPv6Packet::write_header(
+                    eth_unsized.inner_mut().payload_mut(),
+                    next_header,
+                    REMOTE_IPV6_ADDR,
+                    dst_addr,
+                )
+                .unwrap();
+                let payload_len = write_payload(packet.inner_mut().payload_mut());
+                packet.with_payload_len_unchecked(payload_len).len()
+            };
+
+            eth_unsized.with_payload_len_unchecked(packet_len).len()
+        }
+
+        fn write_neighbor_solicitation(&self, buf: &mut [u8], target: Ipv6Addr) -> usize {
+            let dst_addr = solicited_node_multicast_addr(target);
+            self.write_incoming_ipv6_packet(buf, dst_addr, PROTOCOL_ICMPV6, |payload| {
+                // Write an advertisement, and then turn it into a solicitation.
+                let len = Icmpv6Message::write_neighbor_advertisement(
+                    &mut payload[..],
+                    REMOTE_IPV6_ADDR,
+                    dst_addr,
+                    target,
+                    self.mac_addr,
+                )
+                .unwrap()
+                .len();
+                let mut icmp = Icmpv6Message::from_bytes_unchecked(&mut payload[..len]);
+                icmp.set_message_type(135).set_checksum(0);
+                let checksum = icmp.compute_checksum(REMOTE_IPV6_ADDR, dst_addr);
+                icmp.set_checksum(checksum);
+                len
+            })
+        }
+
+        fn write_incoming_ipv6_tcp_segment(
+            &self,
+            buf: &mut [u8],
+            addr: Ipv6Addr,
+            flags: TcpFlags,
+        ) -> usize {
+            self.write_incoming_ipv6_packet(buf, addr, PROTOCOL_TCP, |payload| {
+                TcpSegment::write_incomplete_segment::<[u8]>(
+                    payload, SEQ_NUMBER, 1234, flags, 10000, None, 0, None,
+                )
+                .unwrap()
+                .finalize(
+                    REMOTE_PORT,
+                    MMDS_PORT,
+                    Some((REMOTE_IPV6_ADDR.into(), addr.into())),
+                )
+                .len()
+            })
+        }
+
         fn next_frame_as_ipv4_packet<'a>(&mut self, buf: &'a mut [u8]) -> IPv4Packet<&'a [u8]> {
             let len = self.write_next_frame(buf).unwrap().get();
             let eth = EthernetFrame::from_bytes(&buf[..len]).unwrap();
@@ -373,11 +1023,14 @@ mod tests {
 
     #[test]
     fn test_ns_new_with_defaults() {
-        let ns = MmdsNetworkStack::new_with_defaults(None);
+        let ns = MmdsNetworkStack::new_with_defaults(None, Arc::new(Mutex::new(Mmds::default())));
         assert_eq!(ns.mac_addr, MacAddr::parse_str(DEFAULT_MAC_ADDR).unwrap());
         assert_eq!(ns.ipv4_addr, Ipv4Addr::from(DEFAULT_IPV4_ADDR));
 
-        let ns = MmdsNetworkStack::new_with_defaults(Some(Ipv4Addr::LOCALHOST));
+        let ns = MmdsNetworkStack::new_with_defaults(
+            Some(Ipv4Addr::LOCALHOST),
+            Arc::new(Mutex::new(Mmds::default())),
+        );
         assert_eq!(ns.mac_addr, MacAddr::parse_str(DEFAULT_MAC_ADDR).unwrap());
         assert_eq!(ns.ipv4_addr, Ipv4Addr::LOCALHOST);
     }
@@ -385,7 +1038,8 @@ mod tests {
     #[test]
     #[allow(clippy::cognitive_complexity)]
     fn test_ns() {
-        let mut ns = MmdsNetworkStack::new_with_defaults(None);
+        let mut ns =
+            MmdsNetworkStack::new_with_defaults(None, Arc::new(Mutex::new(Mmds::default())));
         let mut buf = [0u8; 2000];
         let mut bad_buf = [0u8; 1];
 
@@ -464,7 +1118,7 @@ mod tests {
 
             let s = TcpSegment::from_bytes(
                 ip.payload(),
-                Some((ip.source_address(), ip.destination_address())),
+                Some((ip.source_address().into(), ip.destination_address().into())),
             )
             .unwrap();
             assert_eq!(s.flags_after_ns(), TcpFlags::RST);
@@ -489,7 +1143,7 @@ mod tests {
 
             let s = TcpSegment::from_bytes(
                 ip.payload(),
-                Some((ip.source_address(), ip.destination_address())),
+                Some((ip.source_address().into(), ip.destination_address().into())),
             )
             .unwrap();
             assert_eq!(s.flags_after_ns(), TcpFlags::SYN | TcpFlags::ACK);
@@ -502,9 +1156,321 @@ mod tests {
         assert!(ns.write_next_frame(buf.as_mut()).is_none());
     }
 
+    #[test]
+    fn test_ns_ipv6() {
+        let mut ns =
+            MmdsNetworkStack::new_with_defaults(None, Arc::new(Mutex::new(Mmds::default())));
+        let mut buf = [0u8; 2000];
+        let remote_mac = MacAddr::parse_str(REMOTE_MAC_STR).unwrap();
+
+        // IPv6 is disabled by default.
+        let len = ns.write_neighbor_solicitation(buf.as_mut(), MMDS_IPV6_ADDR);
+        assert!(!ns.detour_frame(&buf[..len]));
+
+        ns.set_ipv6_addr(Some(MMDS_IPV6_ADDR));
+
+        // Not asking for the MMDS MAC address.
+        let other_addr = Ipv6Addr::new(0xfd00, 0xec2, 0, 0, 0, 0, 0, 0x253);
+        let len = ns.write_neighbor_solicitation(buf.as_mut(), other_addr);
+        assert!(!ns.detour_frame(&buf[..len]));
+        assert!(ns.write_next_frame(buf.as_mut()).is_none());
+
+        // Asking for the MMDS MAC address.
+        let len = ns.write_neighbor_solicitation(buf.as_mut(), MMDS_IPV6_ADDR);
+        assert!(ns.detour_frame(&buf[..len]));
+        assert_eq!(ns.remote_mac_addr, remote_mac);
+        assert_eq!(ns.pending_ndp_reply_dest, Some(REMOTE_IPV6_ADDR));
+
+        // There should be a Neighbor Advertisement to send.
+        {
+            let len = ns.write_next_frame(buf.as_mut()).unwrap().get();
+            let eth = EthernetFrame::from_bytes(&buf[..len]).unwrap();
+            assert_eq!(eth.ethertype(), ETHERTYPE_IPV6);
+            assert_eq!(eth.dst_mac(), remote_mac);
+            let ip = IPv6Packet::from_bytes(eth.payload()).unwrap();
+            assert_eq!(ip.source_address(), MMDS_IPV6_ADDR);
+            assert_eq!(ip.destination_address(), REMOTE_IPV6_ADDR);
+            assert_eq!(ip.hop_limit(), DEFAULT_HOP_LIMIT);
+            let icmp =
+                Icmpv6Message::from_bytes(ip.payload(), Some((MMDS_IPV6_ADDR, REMOTE_IPV6_ADDR)))
+                    .unwrap();
+            assert_eq!(icmp.message_type(), 136);
+            assert_eq!(&ip.payload()[8..24], &MMDS_IPV6_ADDR.octets());
+            assert_eq!(&ip.payload()[26..32], ns.mac_addr.get_bytes());
+        }
+        assert!(ns.write_next_frame(buf.as_mut()).is_none());
+
+        // A TCP segment heading to a different address is not for the MMDS.
+        let len = ns.write_incoming_ipv6_tcp_segment(buf.as_mut(), other_addr, TcpFlags::SYN);
+        assert!(!ns.detour_frame(&buf[..len]));
+
+        // Let's send a TCP SYN over IPv6.
+        let len = ns.write_incoming_ipv6_tcp_segment(buf.as_mut(), MMDS_IPV6_ADDR, TcpFlags::SYN);
+        assert!(ns.detour_frame(&buf[..len]));
+
+        // We should be getting a SYNACK back, over IPv6.
+        {
+            let len = ns.write_next_frame(buf.as_mut()).unwrap().get();
+            let eth = EthernetFrame::from_bytes(&buf[..len]).unwrap();
+            assert_eq!(eth.ethertype(), ETHERTYPE_IPV6);
+            let ip = IPv6Packet::from_bytes(eth.payload()).unwrap();
+            assert_eq!(ip.source_address(), MMDS_IPV6_ADDR);
+            assert_eq!(ip.destination_address(), REMOTE_IPV6_ADDR);
+
+            let s = TcpSegment::from_bytes(
+                ip.payload(),
+                Some((MMDS_IPV6_ADDR.into(), REMOTE_IPV6_ADDR.into())),
+            )
+            .unwrap();
+            assert_eq!(s.flags_after_ns(), TcpFlags::SYN | TcpFlags::ACK);
+            assert_eq!(s.source_port(), MMDS_PORT);
+            assert_eq!(s.destination_port(), REMOTE_PORT);
+            assert_eq!(s.ack_number(), SEQ_NUMBER.wrapping_add(1));
+        }
+
+        // Non-TCP packets heading to the MMDS are detoured, and considered unusual.
+        let len = ns.write_incoming_ipv6_packet(buf.as_mut(), MMDS_IPV6_ADDR, 17, |_| 0);
+        let curr_unusual = METRICS.mmds.rx_accepted_unusual.count();
+        assert!(ns.detour_frame(&buf[..len]));
+        assert_eq!(curr_unusual + 1, METRICS.mmds.rx_accepted_unusual.count());
+    }
+
+    #[test]
+    fn test_ns_dhcp() {
+        let mut ns =
+            MmdsNetworkStack::new_with_defaults(None, Arc::new(Mutex::new(Mmds::default())));
+        let mut buf = [0u8; 2000];
+        let mmds_addr = ns.ipv4_addr;
+        let broadcast = Ipv4Addr::BROADCAST;
+        let unspecified = Ipv4Addr::UNSPECIFIED;
+        let lease = DhcpLease {
+            ipv4_addr: Ipv4Addr::new(10, 0, 0, 2),
+            prefix_len: 24,
+            gateway: Some(Ipv4Addr::new(10, 0, 0, 1)),
+            dns_servers: vec![Ipv4Addr::new(10, 0, 0, 1)],
+        };
+
+        // Checks that the next frame holds a DHCP reply of the given type, sent to `dst_addr`.
+        let check_reply = |ns: &mut MmdsNetworkStack, message_type, dst_addr| {
+            let mut buf = [0u8; 2000];
+            let ip = ns.next_frame_as_ipv4_packet(buf.as_mut());
+            assert_eq!(ip.protocol(), PROTOCOL_UDP);
+            assert_eq!(ip.source_address(), mmds_addr);
+            assert_eq!(ip.destination_address(), dst_addr);
+            let udp = UdpDatagram::from_bytes(ip.payload(), Some((mmds_addr, dst_addr))).unwrap();
+            assert_eq!(udp.source_port(), dhcp::SERVER_PORT);
+            assert_eq!(udp.destination_port(), dhcp::CLIENT_PORT);
+            let reply = DhcpMessage::from_bytes(udp.payload()).unwrap();
+            assert_eq!(reply.op(), dhcp::OP_BOOTREPLY);
+            assert_eq!(reply.xid(), DHCP_XID);
+            assert_eq!(reply.chaddr(), MacAddr::parse_str(REMOTE_MAC_STR).unwrap());
+            assert_eq!(reply.message_type(), Some(message_type));
+            assert_eq!(reply.server_id(), Some(mmds_addr));
+            reply.yiaddr()
+        };
+
+        // DHCP requests are not answered without a lease.
+        let len = ns.write_incoming_dhcp_request(
+            buf.as_mut(),
+            broadcast,
+            dhcp::CLIENT_PORT,
+            dhcp::MESSAGE_TYPE_DISCOVER,
+            unspecified,
+            mmds_addr,
+        );
+        assert!(!ns.detour_frame(&buf[..len]));
+
+        ns.set_dhcp_lease(Some(lease.clone()));
+
+        // Discovering the DHCP server yields an offer.
+        assert!(ns.detour_frame(&buf[..len]));
+        assert_eq!(
+            check_reply(&mut ns, dhcp::MESSAGE_TYPE_OFFER, broadcast),
+            lease.ipv4_addr
+        );
+        assert!(ns.write_next_frame(buf.as_mut()).is_none());
+
+        // Requests which aren't meant for the MMDS are left alone.
+        let len = ns.write_incoming_dhcp_request(
+            buf.as_mut(),
+            broadcast,
+            dhcp::CLIENT_PORT,
+            dhcp::MESSAGE_TYPE_REQUEST,
+            unspecified,
+            Ipv4Addr::new(10, 0, 0, 1),
+        );
+        assert!(!ns.detour_frame(&buf[..len]));
+        let len = ns.write_incoming_dhcp_request(
+            buf.as_mut(),
+            broadcast,
+            1234,
+            dhcp::MESSAGE_TYPE_REQUEST,
+            unspecified,
+            mmds_addr,
+        );
+        assert!(!ns.detour_frame(&buf[..len]));
+        // So are other broadcast packets.
+        let len = ns.write_incoming_tcp_segment(buf.as_mut(), broadcast, TcpFlags::SYN);
+        assert!(!ns.detour_frame(&buf[..len]));
+        assert!(ns.write_next_frame(buf.as_mut()).is_none());
+
+        // Renewing the lease is acknowledged, straight to the guest.
+        let len = ns.write_incoming_dhcp_request(
+            buf.as_mut(),
+            mmds_addr,
+            dhcp::CLIENT_PORT,
+            dhcp::MESSAGE_TYPE_REQUEST,
+            lease.ipv4_addr,
+            mmds_addr,
+        );
+        assert!(ns.detour_frame(&buf[..len]));
+        assert_eq!(
+            check_reply(&mut ns, dhcp::MESSAGE_TYPE_ACK, lease.ipv4_addr),
+            lease.ipv4_addr
+        );
+
+        // Asking for any other address is refused.
+        let len = ns.write_incoming_dhcp_request(
+            buf.as_mut(),
+            mmds_addr,
+            dhcp::CLIENT_PORT,
+            dhcp::MESSAGE_TYPE_REQUEST,
+            Ipv4Addr::new(10, 0, 0, 3),
+            mmds_addr,
+        );
+        assert!(ns.detour_frame(&buf[..len]));
+        assert_eq!(
+            check_reply(&mut ns, dhcp::MESSAGE_TYPE_NAK, broadcast),
+            unspecified
+        );
+
+        // Other messages are accepted, but not answered.
+        let len = ns.write_incoming_dhcp_request(
+            buf.as_mut(),
+            mmds_addr,
+            dhcp::CLIENT_PORT,
+            dhcp::MESSAGE_TYPE_ACK,
+            lease.ipv4_addr,
+            mmds_addr,
+        );
+        assert!(ns.detour_frame(&buf[..len]));
+        assert!(ns.write_next_frame(buf.as_mut()).is_none());
+
+        // Removing the lease drops the pending replies.
+        let len = ns.write_incoming_dhcp_request(
+            buf.as_mut(),
+            broadcast,
+            dhcp::CLIENT_PORT,
+            dhcp::MESSAGE_TYPE_DISCOVER,
+            unspecified,
+            mmds_addr,
+        );
+        assert!(ns.detour_frame(&buf[..len]));
+        ns.set_dhcp_lease(None);
+        assert!(ns.write_next_frame(buf.as_mut()).is_none());
+        assert!(!ns.detour_frame(&buf[..len]));
+    }
+
+    #[test]
+    fn test_ns_icmp() {
+        let mut ns =
+            MmdsNetworkStack::new_with_defaults(None, Arc::new(Mutex::new(Mmds::default())));
+        let mut buf = [0u8; 2000];
+        let mmds_addr = ns.ipv4_addr;
+        let remote_mac = MacAddr::parse_str(REMOTE_MAC_STR).unwrap();
+        let echo_data = [7u8; 57];
+        let echo_id_and_seq = 0xabcd_0001;
+
+        let write_echo_request = |ns: &MmdsNetworkStack, buf: &mut [u8], dst_addr| {
+            ns.write_incoming_ipv4_packet(buf, dst_addr, PROTOCOL_ICMP, |payload| {
+                Icmpv4Message::write_message(
+                    payload,
+                    icmpv4::TYPE_ECHO_REQUEST,
+                    0,
+                    echo_id_and_seq,
+                    &echo_data,
+                )
+                .unwrap()
+                .len()
+            })
+        };
+        let write_udp_datagram = |ns: &MmdsNetworkStack, buf: &mut [u8], dst_port| {
+            ns.write_incoming_ipv4_packet(buf, mmds_addr, PROTOCOL_UDP, |payload| {
+                UdpDatagram::write_incomplete_datagram(payload, &[1, 2, 3])
+                    .unwrap()
+                    .finalize(REMOTE_PORT, dst_port, Some((REMOTE_ADDR, mmds_addr)))
+                    .len()
+                    .into()
+            })
+        };
+
+        // Echo requests heading to other addresses are left alone.
+        let len = write_echo_request(&ns, buf.as_mut(), Ipv4Addr::new(10, 0, 0, 1));
+        assert!(!ns.detour_frame(&buf[..len]));
+
+        // Pinging the MMDS gets an echo reply back.
+        let len = write_echo_request(&ns, buf.as_mut(), mmds_addr);
+        assert!(ns.detour_frame(&buf[..len]));
+        {
+            let len = ns.write_next_frame(buf.as_mut()).unwrap().get();
+            let eth = EthernetFrame::from_bytes(&buf[..len]).unwrap();
+            assert_eq!(eth.ethertype(), ETHERTYPE_IPV4);
+            assert_eq!(eth.dst_mac(), remote_mac);
+            let ip = IPv4Packet::from_bytes(eth.payload(), true).unwrap();
+            assert_eq!(ip.protocol(), PROTOCOL_ICMP);
+            assert_eq!(ip.source_address(), mmds_addr);
+            assert_eq!(ip.destination_address(), REMOTE_ADDR);
+            let icmp = Icmpv4Message::from_bytes(ip.payload(), true).unwrap();
+            assert_eq!(icmp.message_type(), icmpv4::TYPE_ECHO_REPLY);
+            assert_eq!(icmp.code(), 0);
+            assert_eq!(icmp.rest_of_header(), echo_id_and_seq);
+            assert_eq!(icmp.payload(), &echo_data[..]);
+        }
+        assert!(ns.write_next_frame(buf.as_mut()).is_none());
+
+        // Echo requests with an invalid checksum are dropped.
+        let len = write_echo_request(&ns, buf.as_mut(), mmds_addr);
+        buf[len - 1] = 0;
+        let curr_unusual = METRICS.mmds.rx_accepted_unusual.count();
+        assert!(ns.detour_frame(&buf[..len]));
+        assert_eq!(curr_unusual + 1, METRICS.mmds.rx_accepted_unusual.count());
+        assert!(ns.write_next_frame(buf.as_mut()).is_none());
+
+        // Replies which don't fit in the buffer are dropped.
+        let len = write_echo_request(&ns, buf.as_mut(), mmds_addr);
+        assert!(ns.detour_frame(&buf[..len]));
+        assert!(ns.write_next_frame(&mut buf[..len - 1]).is_none());
+        assert!(ns.write_next_frame(buf.as_mut()).is_none());
+
+        // UDP datagrams heading to the MMDS port are not answered.
+        let len = write_udp_datagram(&ns, buf.as_mut(), MMDS_PORT);
+        assert!(ns.detour_frame(&buf[..len]));
+        assert!(ns.write_next_frame(buf.as_mut()).is_none());
+
+        // The ones heading to any other port are told that the port is unreachable.
+        let len = write_udp_datagram(&ns, buf.as_mut(), 53);
+        let quote = EthernetFrame::from_bytes(&buf[..len]).unwrap().payload()[..28].to_vec();
+        assert!(ns.detour_frame(&buf[..len]));
+        {
+            let len = ns.write_next_frame(buf.as_mut()).unwrap().get();
+            let eth = EthernetFrame::from_bytes(&buf[..len]).unwrap();
+            let ip = IPv4Packet::from_bytes(eth.payload(), true).unwrap();
+            assert_eq!(ip.protocol(), PROTOCOL_ICMP);
+            assert_eq!(ip.destination_address(), REMOTE_ADDR);
+            let icmp = Icmpv4Message::from_bytes(ip.payload(), true).unwrap();
+            assert_eq!(icmp.message_type(), icmpv4::TYPE_DESTINATION_UNREACHABLE);
+            assert_eq!(icmp.code(), icmpv4::CODE_PORT_UNREACHABLE);
+            assert_eq!(icmp.rest_of_header(), 0);
+            // The IP header and the UDP header of the datagram are quoted.
+            assert_eq!(icmp.payload(), &quote[..]);
+        }
+        assert!(ns.write_next_frame(buf.as_mut()).is_none());
+    }
+
     #[test]
     fn test_set_ipv4_addr() {
-        let mut ns = MmdsNetworkStack::new_with_defaults(None);
+        let mut ns =
+            MmdsNetworkStack::new_with_defaults(None, Arc::new(Mutex::new(Mmds::default())));
         assert_ne!(ns.ipv4_addr, Ipv4Addr::LOCALHOST);
         assert_ne!(ns.tcp_handler.local_ipv4_addr(), Ipv4Addr::LOCALHOST);
         ns.set_ipv4_addr(Ipv4Addr::LOCALHOST);
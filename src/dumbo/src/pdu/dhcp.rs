@@ -0,0 +1,561 @@
+// Copyright 2020 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Contains support for parsing and writing DHCPv4 messages, limited to what's required to hand
+//! out a single, statically configured lease.
+//!
+//! Details about the DHCP message format can be found at [1], while the options are described
+//! at [2].
+//!
+//! [1]: https://tools.ietf.org/html/rfc2131#section-2
+//! [2]: https://tools.ietf.org/html/rfc2132
+
+use std::net::Ipv4Addr;
+use std::result::Result;
+
+use pdu::bytes::{InnerBytes, NetworkBytes, NetworkBytesMut};
+use MacAddr;
+
+const OP_OFFSET: usize = 0;
+const HTYPE_OFFSET: usize = 1;
+const HLEN_OFFSET: usize = 2;
+const HOPS_OFFSET: usize = 3;
+const XID_OFFSET: usize = 4;
+const SECS_OFFSET: usize = 8;
+const FLAGS_OFFSET: usize = 10;
+const CIADDR_OFFSET: usize = 12;
+const YIADDR_OFFSET: usize = 16;
+const SIADDR_OFFSET: usize = 20;
+const GIADDR_OFFSET: usize = 24;
+const CHADDR_OFFSET: usize = 28;
+const MAGIC_COOKIE_OFFSET: usize = 236;
+const OPTIONS_OFFSET: usize = 240;
+
+const HTYPE_ETHERNET: u8 = 1;
+const MAGIC_COOKIE: u32 = 0x6382_5363;
+
+/// The `op` of the messages sent by clients.
+pub const OP_BOOTREQUEST: u8 = 1;
+/// The `op` of the messages sent by servers.
+pub const OP_BOOTREPLY: u8 = 2;
+
+/// The UDP port DHCP servers listen on.
+pub const SERVER_PORT: u16 = 67;
+/// The UDP port DHCP clients listen on.
+pub const CLIENT_PORT: u16 = 68;
+
+/// The DHCP message type of DHCPDISCOVER messages.
+pub const MESSAGE_TYPE_DISCOVER: u8 = 1;
+/// The DHCP message type of DHCPOFFER messages.
+pub const MESSAGE_TYPE_OFFER: u8 = 2;
+/// The DHCP message type of DHCPREQUEST messages.
+pub const MESSAGE_TYPE_REQUEST: u8 = 3;
+/// The DHCP message type of DHCPACK messages.
+pub const MESSAGE_TYPE_ACK: u8 = 5;
+/// The DHCP message type of DHCPNAK messages.
+pub const MESSAGE_TYPE_NAK: u8 = 6;
+
+const OPTION_PAD: u8 = 0;
+const OPTION_SUBNET_MASK: u8 = 1;
+const OPTION_ROUTER: u8 = 3;
+const OPTION_DNS_SERVERS: u8 = 6;
+const OPTION_REQUESTED_IP_ADDR: u8 = 50;
+const OPTION_LEASE_TIME: u8 = 51;
+const OPTION_MESSAGE_TYPE: u8 = 53;
+const OPTION_SERVER_ID: u8 = 54;
+const OPTION_END: u8 = 255;
+
+// The lease never changes, so clients only have to renew it once a day.
+const LEASE_TIME: u32 = 86_400;
+
+// Some BOOTP relays and clients drop messages shorter than this [3].
+//
+// [3]: https://tools.ietf.org/html/rfc1542#section-2.1
+const MIN_MESSAGE_LEN: usize = 300;
+
+/// The maximum number of DNS servers which can be advertised in a single option.
+pub const MAX_DNS_SERVERS: usize = 63;
+
+/// The maximum length of the messages written by `write_reply`.
+pub const MAX_REPLY_LEN: usize = OPTIONS_OFFSET + 27 + 2 + 4 * MAX_DNS_SERVERS + 1;
+
+/// Describes the errors which may occur while handling DHCP messages.
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    /// The message doesn't start with the DHCP magic cookie.
+    MagicCookie,
+    /// The lease advertises more than `MAX_DNS_SERVERS` DNS servers.
+    TooManyDnsServers,
+    /// The specified byte sequence is shorter than the message.
+    SliceTooShort,
+}
+
+/// The network configuration handed out to a DHCP client.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DhcpLease {
+    /// The IPv4 address of the client.
+    pub ipv4_addr: Ipv4Addr,
+    /// The length of the network prefix of `ipv4_addr`.
+    pub prefix_len: u8,
+    /// The default gateway of the client.
+    pub gateway: Option<Ipv4Addr>,
+    /// The DNS servers of the client, in order of preference.
+    pub dns_servers: Vec<Ipv4Addr>,
+}
+
+impl DhcpLease {
+    /// Returns the subnet mask which corresponds to the network prefix length.
+    pub fn subnet_mask(&self) -> Ipv4Addr {
+        let mask = u32::max_value()
+            .checked_shl(32u32.saturating_sub(u32::from(self.prefix_len)))
+            .unwrap_or(0);
+        Ipv4Addr::from(mask)
+    }
+}
+
+/// Interprets the inner bytes as a DHCP message.
+pub struct DhcpMessage<'a, T: 'a> {
+    bytes: InnerBytes<'a, T>,
+}
+
+#[allow(clippy::len_without_is_empty)]
+impl<'a, T: NetworkBytes> DhcpMessage<'a, T> {
+    /// Interprets `bytes` as a DHCP message without any validity checks.
+    ///
+    /// # Panics
+    ///
+    /// This method does not panic, but further method calls on the resulting object may panic if
+    /// `bytes` contains invalid input.
+    #[inline]
+    pub fn from_bytes_unchecked(bytes: T) -> Self {
+        DhcpMessage {
+            bytes: InnerBytes::new(bytes),
+        }
+    }
+
+    /// Attempts to interpret `bytes` as a DHCP message. Plain BOOTP messages, which don't carry
+    /// the magic cookie that precedes the options, are rejected.
+    #[inline]
+    pub fn from_bytes(bytes: T) -> Result<Self, Error> {
+        if bytes.len() < OPTIONS_OFFSET {
+            return Err(Error::SliceTooShort);
+        }
+
+        let message = DhcpMessage::from_bytes_unchecked(bytes);
+        if message.bytes.ntohl_unchecked(MAGIC_COOKIE_OFFSET) != MAGIC_COOKIE {
+            return Err(Error::MagicCookie);
+        }
+
+        Ok(message)
+    }
+
+    /// Returns the `op` field, which tells requests and replies apart.
+    #[inline]
+    pub fn op(&self) -> u8 {
+        self.bytes[OP_OFFSET]
+    }
+
+    /// Returns the transaction ID chosen by the client.
+    #[inline]
+    pub fn xid(&self) -> u32 {
+        self.bytes.ntohl_unchecked(XID_OFFSET)
+    }
+
+    /// Returns the `flags` field.
+    #[inline]
+    pub fn flags(&self) -> u16 {
+        self.bytes.ntohs_unchecked(FLAGS_OFFSET)
+    }
+
+    /// Returns the client IP address, which is only set by clients which already have a lease.
+    #[inline]
+    pub fn ciaddr(&self) -> Ipv4Addr {
+        Ipv4Addr::from(self.bytes.ntohl_unchecked(CIADDR_OFFSET))
+    }
+
+    /// Returns the client hardware address, assuming it's an Ethernet MAC address.
+    #[inline]
+    pub fn chaddr(&self) -> MacAddr {
+        MacAddr::from_bytes_unchecked(&self.bytes[CHADDR_OFFSET..CHADDR_OFFSET + 6])
+    }
+
+    /// Returns the value of the first option with the given `code`, or `None` if there is no
+    /// such option, or the options are malformed.
+    pub fn option(&self, code: u8) -> Option<&[u8]> {
+        let options = &self.bytes[OPTIONS_OFFSET..];
+        let mut i = 0;
+        while i < options.len() {
+            match options[i] {
+                OPTION_END => break,
+                OPTION_PAD => i += 1,
+                current => {
+                    let len = usize::from(*options.get(i + 1)?);
+                    let value = options.get(i + 2..i + 2 + len)?;
+                    if current == code {
+                        return Some(value);
+                    }
+                    i += 2 + len;
+                }
+            }
+        }
+        None
+    }
+
+    /// Returns the DHCP message type, or `None` if the option is missing or malformed.
+    #[inline]
+    pub fn message_type(&self) -> Option<u8> {
+        match self.option(OPTION_MESSAGE_TYPE) {
+            Some(&[message_type]) => Some(message_type),
+            _ => None,
+        }
+    }
+
+    /// Returns the IP address the client asks for, if any.
+    #[inline]
+    pub fn requested_ipv4_addr(&self) -> Option<Ipv4Addr> {
+        self.option(OPTION_REQUESTED_IP_ADDR)
+            .and_then(ipv4_addr_option)
+    }
+
+    /// Returns the address of the server the message is meant for, if any.
+    #[inline]
+    pub fn server_id(&self) -> Option<Ipv4Addr> {
+        self.option(OPTION_SERVER_ID).and_then(ipv4_addr_option)
+    }
+
+    /// Returns the length of the message.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+}
+
+impl<'a, T: NetworkBytesMut> DhcpMessage<'a, T> {
+    /// Writes a reply of type `message_type` to `buf`, for the client request identified by `xid`,
+    /// `flags` and `chaddr`.
+    ///
+    /// The `lease` is handed out in DHCPOFFER and DHCPACK messages, and should be missing from
+    /// DHCPNAK messages. The `server_addr` is the address the client should send its requests to.
+    /// The length of `buf` has to be at least `MAX_REPLY_LEN`.
+    pub fn write_reply(
+        buf: T,
+        message_type: u8,
+        xid: u32,
+        flags: u16,
+        chaddr: MacAddr,
+        server_addr: Ipv4Addr,
+        lease: Option<&DhcpLease>,
+    ) -> Result<Self, Error> {
+        if lease.map_or(false, |lease| lease.dns_servers.len() > MAX_DNS_SERVERS) {
+            return Err(Error::TooManyDnsServers);
+        }
+        if buf.len() < MAX_REPLY_LEN {
+            return Err(Error::SliceTooShort);
+        }
+
+        let mut message = DhcpMessage::from_bytes_unchecked(buf);
+        for b in &mut message.bytes[..] {
+            *b = 0;
+        }
+
+        let bytes = &mut message.bytes;
+        bytes[OP_OFFSET] = OP_BOOTREPLY;
+        bytes[HTYPE_OFFSET] = HTYPE_ETHERNET;
+        bytes[HLEN_OFFSET] = chaddr.get_bytes().len() as u8;
+        bytes[HOPS_OFFSET] = 0;
+        bytes.htonl_unchecked(XID_OFFSET, xid);
+        bytes.htons_unchecked(SECS_OFFSET, 0);
+        bytes.htons_unchecked(FLAGS_OFFSET, flags);
+        let yiaddr = lease.map_or(Ipv4Addr::UNSPECIFIED, |lease| lease.ipv4_addr);
+        bytes.htonl_unchecked(YIADDR_OFFSET, u32::from(yiaddr));
+        bytes.htonl_unchecked(SIADDR_OFFSET, 0);
+        bytes.htonl_unchecked(GIADDR_OFFSET, 0);
+        bytes[CHADDR_OFFSET..CHADDR_OFFSET + 6].copy_from_slice(chaddr.get_bytes());
+        bytes.htonl_unchecked(MAGIC_COOKIE_OFFSET, MAGIC_COOKIE);
+
+        let mut options = OptionsWriter {
+            bytes: &mut bytes[OPTIONS_OFFSET..],
+            len: 0,
+        };
+        options.write(OPTION_MESSAGE_TYPE, &[message_type]);
+        options.write(OPTION_SERVER_ID, &server_addr.octets());
+        if let Some(lease) = lease {
+            options.write(OPTION_LEASE_TIME, &LEASE_TIME.to_be_bytes());
+            options.write(OPTION_SUBNET_MASK, &lease.subnet_mask().octets());
+            if let Some(gateway) = lease.gateway {
+                options.write(OPTION_ROUTER, &gateway.octets());
+            }
+            if !lease.dns_servers.is_empty() {
+                let dns_servers: Vec<u8> = lease
+                    .dns_servers
+                    .iter()
+                    .flat_map(|addr| addr.octets().to_vec())
+                    .collect();
+                options.write(OPTION_DNS_SERVERS, &dns_servers);
+            }
+        }
+        options.bytes[options.len] = OPTION_END;
+        let len = OPTIONS_OFFSET + options.len + 1;
+
+        message
+            .bytes
+            .shrink_unchecked(std::cmp::max(len, MIN_MESSAGE_LEN));
+        Ok(message)
+    }
+}
+
+// Appends options to the option field of a DHCP message, which has to be large enough.
+struct OptionsWriter<'a> {
+    bytes: &'a mut [u8],
+    len: usize,
+}
+
+impl<'a> OptionsWriter<'a> {
+    fn write(&mut self, code: u8, value: &[u8]) {
+        self.bytes[self.len] = code;
+        self.bytes[self.len + 1] = value.len() as u8;
+        self.bytes[self.len + 2..self.len + 2 + value.len()].copy_from_slice(value);
+        self.len += 2 + value.len();
+    }
+}
+
+// Interprets the value of an option which holds a single IPv4 address.
+fn ipv4_addr_option(value: &[u8]) -> Option<Ipv4Addr> {
+    match value {
+        &[a, b, c, d] => Some(Ipv4Addr::new(a, b, c, d)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt;
+
+    use super::*;
+
+    impl<'a, T: NetworkBytes> fmt::Debug for DhcpMessage<'a, T> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "(DHCP message)")
+        }
+    }
+
+    // Helper methods which only make sense for testing.
+    impl<'a, T: NetworkBytes> DhcpMessage<'a, T> {
+        pub(crate) fn yiaddr(&self) -> Ipv4Addr {
+            Ipv4Addr::from(self.bytes.ntohl_unchecked(YIADDR_OFFSET))
+        }
+    }
+
+    impl<'a, T: NetworkBytesMut> DhcpMessage<'a, T> {
+        pub(crate) fn set_op(&mut self, value: u8) -> &mut Self {
+            self.bytes[OP_OFFSET] = value;
+            self
+        }
+
+        pub(crate) fn set_ciaddr(&mut self, addr: Ipv4Addr) -> &mut Self {
+            self.bytes.htonl_unchecked(CIADDR_OFFSET, u32::from(addr));
+            self
+        }
+    }
+
+    fn lease() -> DhcpLease {
+        DhcpLease {
+            ipv4_addr: Ipv4Addr::new(10, 0, 0, 2),
+            prefix_len: 24,
+            gateway: Some(Ipv4Addr::new(10, 0, 0, 1)),
+            dns_servers: vec![Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(8, 8, 8, 8)],
+        }
+    }
+
+    #[test]
+    fn test_subnet_mask() {
+        let mut lease = lease();
+        assert_eq!(lease.subnet_mask(), Ipv4Addr::new(255, 255, 255, 0));
+        lease.prefix_len = 0;
+        assert_eq!(lease.subnet_mask(), Ipv4Addr::new(0, 0, 0, 0));
+        lease.prefix_len = 17;
+        assert_eq!(lease.subnet_mask(), Ipv4Addr::new(255, 255, 128, 0));
+        lease.prefix_len = 32;
+        assert_eq!(lease.subnet_mask(), Ipv4Addr::new(255, 255, 255, 255));
+    }
+
+    #[test]
+    fn test_write_reply() {
+        let mut buf = [0xffu8; MAX_REPLY_LEN];
+        let chaddr = MacAddr::parse_str("12:34:56:78:9a:bc").unwrap();
+        let server_addr = Ipv4Addr::new(169, 254, 169, 254);
+        let lease = lease();
+
+        let len = {
+            let m = DhcpMessage::write_reply(
+                buf.as_mut(),
+                MESSAGE_TYPE_OFFER,
+                0xdead_beef,
+                0x8000,
+                chaddr,
+                server_addr,
+                Some(&lease),
+            )
+            .unwrap();
+            assert_eq!(m.op(), OP_BOOTREPLY);
+            assert_eq!(m.xid(), 0xdead_beef);
+            assert_eq!(m.flags(), 0x8000);
+            assert_eq!(m.ciaddr(), Ipv4Addr::UNSPECIFIED);
+            assert_eq!(m.yiaddr(), lease.ipv4_addr);
+            assert_eq!(m.chaddr(), chaddr);
+            assert_eq!(m.message_type(), Some(MESSAGE_TYPE_OFFER));
+            assert_eq!(m.server_id(), Some(server_addr));
+            assert_eq!(m.requested_ipv4_addr(), None);
+            assert_eq!(m.option(OPTION_LEASE_TIME), Some(&[0, 1, 0x51, 0x80][..]));
+            assert_eq!(m.option(OPTION_SUBNET_MASK), Some(&[255, 255, 255, 0][..]));
+            assert_eq!(m.option(OPTION_ROUTER), Some(&[10, 0, 0, 1][..]));
+            assert_eq!(
+                m.option(OPTION_DNS_SERVERS),
+                Some(&[10, 0, 0, 1, 8, 8, 8, 8][..])
+            );
+            // Short replies are padded.
+            assert_eq!(m.len(), MIN_MESSAGE_LEN);
+            m.len()
+        };
+        assert_eq!(buf[HTYPE_OFFSET], HTYPE_ETHERNET);
+        assert_eq!(buf[HLEN_OFFSET], 6);
+        assert!(DhcpMessage::from_bytes(&buf[..len]).is_ok());
+
+        // DHCPNAK messages don't hand out a lease.
+        let m = DhcpMessage::write_reply(
+            buf.as_mut(),
+            MESSAGE_TYPE_NAK,
+            1,
+            0,
+            chaddr,
+            server_addr,
+            None,
+        )
+        .unwrap();
+        assert_eq!(m.message_type(), Some(MESSAGE_TYPE_NAK));
+        assert_eq!(m.yiaddr(), Ipv4Addr::UNSPECIFIED);
+        assert_eq!(m.option(OPTION_SUBNET_MASK), None);
+        assert_eq!(m.option(OPTION_LEASE_TIME), None);
+
+        // All the DNS servers have to fit in a single option.
+        let mut long_lease = lease.clone();
+        long_lease.dns_servers = vec![Ipv4Addr::LOCALHOST; MAX_DNS_SERVERS];
+        let m = DhcpMessage::write_reply(
+            buf.as_mut(),
+            MESSAGE_TYPE_ACK,
+            1,
+            0,
+            chaddr,
+            server_addr,
+            Some(&long_lease),
+        )
+        .unwrap();
+        assert_eq!(m.len(), MAX_REPLY_LEN);
+        assert_eq!(
+            m.option(OPTION_DNS_SERVERS).unwrap().len(),
+            4 * MAX_DNS_SERVERS
+        );
+        long_lease.dns_servers.push(Ipv4Addr::LOCALHOST);
+        assert_eq!(
+            DhcpMessage::write_reply(
+                buf.as_mut(),
+                MESSAGE_TYPE_ACK,
+                1,
+                0,
+                chaddr,
+                server_addr,
+                Some(&long_lease),
+            )
+            .unwrap_err(),
+            Error::TooManyDnsServers
+        );
+
+        assert_eq!(
+            DhcpMessage::write_reply(
+                &mut buf[..MAX_REPLY_LEN - 1],
+                MESSAGE_TYPE_ACK,
+                1,
+                0,
+                chaddr,
+                server_addr,
+                Some(&lease),
+            )
+            .unwrap_err(),
+            Error::SliceTooShort
+        );
+    }
+
+    #[test]
+    fn test_parse_request() {
+        let mut buf = [0u8; MAX_REPLY_LEN];
+        let chaddr = MacAddr::parse_str("12:34:56:78:9a:bc").unwrap();
+        let len = DhcpMessage::write_reply(
+            buf.as_mut(),
+            MESSAGE_TYPE_ACK,
+            7,
+            0,
+            chaddr,
+            Ipv4Addr::LOCALHOST,
+            None,
+        )
+        .unwrap()
+        .len();
+
+        // Turn the reply into a request, with padding before the requested address.
+        DhcpMessage::from_bytes_unchecked(buf.as_mut())
+            .set_op(OP_BOOTREQUEST)
+            .set_ciaddr(Ipv4Addr::new(10, 0, 0, 3));
+        let options = [
+            OPTION_MESSAGE_TYPE,
+            1,
+            MESSAGE_TYPE_REQUEST,
+            OPTION_PAD,
+            OPTION_REQUESTED_IP_ADDR,
+            4,
+            10,
+            0,
+            0,
+            2,
+            OPTION_END,
+            OPTION_SERVER_ID,
+            4,
+            10,
+            0,
+            0,
+            1,
+        ];
+        buf[OPTIONS_OFFSET..OPTIONS_OFFSET + options.len()].copy_from_slice(&options);
+        let m = DhcpMessage::from_bytes(&buf[..len]).unwrap();
+        assert_eq!(m.op(), OP_BOOTREQUEST);
+        assert_eq!(m.ciaddr(), Ipv4Addr::new(10, 0, 0, 3));
+        assert_eq!(m.chaddr(), chaddr);
+        assert_eq!(m.xid(), 7);
+        assert_eq!(m.message_type(), Some(MESSAGE_TYPE_REQUEST));
+        assert_eq!(m.requested_ipv4_addr(), Some(Ipv4Addr::new(10, 0, 0, 2)));
+        // Options which come after the end are ignored.
+        assert_eq!(m.server_id(), None);
+
+        // Options running past the end of the message are ignored.
+        buf[OPTIONS_OFFSET + 5] = 200;
+        let m = DhcpMessage::from_bytes(&buf[..len]).unwrap();
+        assert_eq!(m.message_type(), Some(MESSAGE_TYPE_REQUEST));
+        assert_eq!(m.requested_ipv4_addr(), None);
+
+        // A message type option which doesn't hold exactly one byte is invalid.
+        buf[OPTIONS_OFFSET + 1] = 2;
+        assert_eq!(
+            DhcpMessage::from_bytes(&buf[..len]).unwrap().message_type(),
+            None
+        );
+
+        assert_eq!(
+            DhcpMessage::from_bytes(&buf[..OPTIONS_OFFSET - 1]).unwrap_err(),
+            Error::SliceTooShort
+        );
+        buf[MAGIC_COOKIE_OFFSET] = 0;
+        assert_eq!(
+            DhcpMessage::from_bytes(&buf[..len]).unwrap_err(),
+            Error::MagicCookie
+        );
+    }
+}
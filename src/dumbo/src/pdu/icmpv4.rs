@@ -0,0 +1,253 @@
+// Copyright 2020 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Contains support for parsing and writing ICMP (for IPv4) messages, limited to what's required
+//! to answer echo requests and report unreachable ports.
+//!
+//! Details about the ICMP message format can be found at [1].
+//!
+//! [1]: https://tools.ietf.org/html/rfc792
+
+use std::result::Result;
+
+use pdu::bytes::{InnerBytes, NetworkBytes, NetworkBytesMut};
+
+const TYPE_OFFSET: usize = 0;
+const CODE_OFFSET: usize = 1;
+const CHECKSUM_OFFSET: usize = 2;
+const REST_OF_HEADER_OFFSET: usize = 4;
+
+/// The length of the ICMP header, which includes the four bytes following the checksum (used by
+/// echo messages for the identifier and sequence number, and left unused by most other messages).
+pub const HEADER_LEN: usize = 8;
+
+/// The message type of Echo Reply messages.
+pub const TYPE_ECHO_REPLY: u8 = 0;
+/// The message type of Destination Unreachable messages.
+pub const TYPE_DESTINATION_UNREACHABLE: u8 = 3;
+/// The message type of Echo messages.
+pub const TYPE_ECHO_REQUEST: u8 = 8;
+
+/// The Destination Unreachable code which signals that nothing listens on the destination port.
+pub const CODE_PORT_UNREACHABLE: u8 = 3;
+
+/// How many bytes of the datagram which caused a Destination Unreachable message are quoted in
+/// its payload, in addition to the IP header.
+pub const UNREACHABLE_DATAGRAM_PREFIX_LEN: usize = 8;
+
+/// Describes the errors which may occur while handling ICMP messages.
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    /// Invalid checksum.
+    Checksum,
+    /// The specified byte sequence is shorter than the message.
+    SliceTooShort,
+}
+
+/// Interprets the inner bytes as an ICMP message.
+pub struct Icmpv4Message<'a, T: 'a> {
+    bytes: InnerBytes<'a, T>,
+}
+
+#[allow(clippy::len_without_is_empty)]
+impl<'a, T: NetworkBytes> Icmpv4Message<'a, T> {
+    /// Interprets `bytes` as an ICMP message without any validity checks.
+    ///
+    /// # Panics
+    ///
+    /// This method does not panic, but further method calls on the resulting object may panic if
+    /// `bytes` contains invalid input.
+    #[inline]
+    pub fn from_bytes_unchecked(bytes: T) -> Self {
+        Icmpv4Message {
+            bytes: InnerBytes::new(bytes),
+        }
+    }
+
+    /// Attempts to interpret `bytes` as an ICMP message, optionally validating the checksum.
+    #[inline]
+    pub fn from_bytes(bytes: T, verify_checksum: bool) -> Result<Self, Error> {
+        if bytes.len() < HEADER_LEN {
+            return Err(Error::SliceTooShort);
+        }
+
+        let message = Icmpv4Message::from_bytes_unchecked(bytes);
+
+        if verify_checksum && message.compute_checksum() != 0 {
+            return Err(Error::Checksum);
+        }
+
+        Ok(message)
+    }
+
+    /// Returns the message type.
+    #[inline]
+    pub fn message_type(&self) -> u8 {
+        self.bytes[TYPE_OFFSET]
+    }
+
+    /// Returns the message code.
+    #[inline]
+    pub fn code(&self) -> u8 {
+        self.bytes[CODE_OFFSET]
+    }
+
+    /// Returns the four bytes which follow the checksum, as a single value.
+    #[inline]
+    pub fn rest_of_header(&self) -> u32 {
+        self.bytes.ntohl_unchecked(REST_OF_HEADER_OFFSET)
+    }
+
+    /// Returns a byte slice that contains the payload of the message.
+    #[inline]
+    pub fn payload(&self) -> &[u8] {
+        self.bytes.split_at(HEADER_LEN).1
+    }
+
+    /// Computes the ICMP checksum. Unlike the other checksums, it only covers the message itself.
+    pub fn compute_checksum(&self) -> u16 {
+        let len = self.bytes.len();
+        let mut sum = 0u32;
+
+        for i in 0..len / 2 {
+            sum += u32::from(self.bytes.ntohs_unchecked(i * 2));
+        }
+
+        if len % 2 != 0 {
+            sum += u32::from(self.bytes[len - 1]) << 8;
+        }
+
+        while sum >> 16 != 0 {
+            sum = (sum & 0xffff) + (sum >> 16);
+        }
+
+        !(sum as u16)
+    }
+
+    /// Returns the length of the message.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+}
+
+impl<'a, T: NetworkBytesMut> Icmpv4Message<'a, T> {
+    /// Writes a complete ICMP message to `buf`, computing the checksum along the way.
+    pub fn write_message(
+        buf: T,
+        message_type: u8,
+        code: u8,
+        rest_of_header: u32,
+        payload: &[u8],
+    ) -> Result<Self, Error> {
+        let len = HEADER_LEN + payload.len();
+        if buf.len() < len {
+            return Err(Error::SliceTooShort);
+        }
+
+        let mut message = Icmpv4Message::from_bytes_unchecked(buf);
+        message.bytes.shrink_unchecked(len);
+        message
+            .set_message_type(message_type)
+            .set_code(code)
+            .set_checksum(0)
+            .set_rest_of_header(rest_of_header);
+        message.bytes[HEADER_LEN..].copy_from_slice(payload);
+
+        let checksum = message.compute_checksum();
+        message.set_checksum(checksum);
+
+        Ok(message)
+    }
+
+    /// Sets the message type.
+    #[inline]
+    pub fn set_message_type(&mut self, value: u8) -> &mut Self {
+        self.bytes[TYPE_OFFSET] = value;
+        self
+    }
+
+    /// Sets the message code.
+    #[inline]
+    pub fn set_code(&mut self, value: u8) -> &mut Self {
+        self.bytes[CODE_OFFSET] = value;
+        self
+    }
+
+    /// Sets the value of the `checksum` field.
+    #[inline]
+    pub fn set_checksum(&mut self, value: u16) -> &mut Self {
+        self.bytes.htons_unchecked(CHECKSUM_OFFSET, value);
+        self
+    }
+
+    /// Sets the four bytes which follow the checksum.
+    #[inline]
+    pub fn set_rest_of_header(&mut self, value: u32) -> &mut Self {
+        self.bytes.htonl_unchecked(REST_OF_HEADER_OFFSET, value);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt;
+
+    use super::*;
+
+    impl<'a, T: NetworkBytes> fmt::Debug for Icmpv4Message<'a, T> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "(ICMP message)")
+        }
+    }
+
+    #[test]
+    fn test_icmpv4() {
+        let mut buf = [0u8; 100];
+        let payload = [1u8, 2, 3, 4, 5];
+
+        let len = {
+            let m = Icmpv4Message::write_message(
+                buf.as_mut(),
+                TYPE_ECHO_REPLY,
+                0,
+                0x1234_0001,
+                &payload,
+            )
+            .unwrap();
+            assert_eq!(m.message_type(), TYPE_ECHO_REPLY);
+            assert_eq!(m.code(), 0);
+            assert_eq!(m.rest_of_header(), 0x1234_0001);
+            assert_eq!(m.payload(), &payload);
+            assert_eq!(m.compute_checksum(), 0);
+            m.len()
+        };
+        assert_eq!(len, HEADER_LEN + payload.len());
+        assert_eq!(&buf[REST_OF_HEADER_OFFSET..HEADER_LEN], &[0x12, 0x34, 0, 1]);
+
+        assert!(Icmpv4Message::from_bytes(&buf[..len], true).is_ok());
+        // The checksum covers the payload, including the trailing odd byte.
+        buf[len - 1] = 0;
+        assert_eq!(
+            Icmpv4Message::from_bytes(&buf[..len], true).unwrap_err(),
+            Error::Checksum
+        );
+        assert!(Icmpv4Message::from_bytes(&buf[..len], false).is_ok());
+
+        assert_eq!(
+            Icmpv4Message::from_bytes(&buf[..HEADER_LEN - 1], false).unwrap_err(),
+            Error::SliceTooShort
+        );
+        assert_eq!(
+            Icmpv4Message::write_message(
+                &mut buf[..HEADER_LEN + payload.len() - 1],
+                TYPE_DESTINATION_UNREACHABLE,
+                CODE_PORT_UNREACHABLE,
+                0,
+                &payload
+            )
+            .unwrap_err(),
+            Error::SliceTooShort
+        );
+    }
+}
@@ -0,0 +1,295 @@
+// Copyright 2020 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Contains support for parsing and writing ICMPv6 messages, limited to what's required to
+//! take part in IPv6 Neighbor Discovery.
+//!
+//! Details about the ICMPv6 message format can be found at [1], while the Neighbor Solicitation
+//! and Neighbor Advertisement messages are described at [2].
+//!
+//! [1]: https://tools.ietf.org/html/rfc4443
+//! [2]: https://tools.ietf.org/html/rfc4861#section-4.3
+
+use std::net::Ipv6Addr;
+use std::result::Result;
+
+use pdu::bytes::{InnerBytes, NetworkBytes, NetworkBytesMut};
+use pdu::ChecksumProto;
+use MacAddr;
+
+const TYPE_OFFSET: usize = 0;
+const CODE_OFFSET: usize = 1;
+const CHECKSUM_OFFSET: usize = 2;
+const NDP_FLAGS_OFFSET: usize = 4;
+const NDP_TARGET_OFFSET: usize = 8;
+const NDP_OPTIONS_OFFSET: usize = 24;
+
+/// The length of the ICMPv6 header (type, code, and checksum).
+pub const HEADER_LEN: usize = 4;
+
+/// The message type of Neighbor Solicitation messages.
+pub const TYPE_NEIGHBOR_SOLICITATION: u8 = 135;
+/// The message type of Neighbor Advertisement messages.
+pub const TYPE_NEIGHBOR_ADVERTISEMENT: u8 = 136;
+
+// Neighbor Advertisement flags, as found in the first byte following the header.
+const NA_FLAG_SOLICITED: u8 = 0x40;
+const NA_FLAG_OVERRIDE: u8 = 0x20;
+
+// The Neighbor Discovery option which carries the link-layer address of the target, and its
+// length (expressed in units of 8 bytes) when the link layer is Ethernet.
+const OPTION_TARGET_LINK_LAYER_ADDR: u8 = 2;
+const OPTION_TARGET_LINK_LAYER_ADDR_LEN: u8 = 1;
+
+/// The length of the Neighbor Advertisement messages written by `write_neighbor_advertisement`.
+pub const NEIGHBOR_ADVERTISEMENT_LEN: usize = NDP_OPTIONS_OFFSET + 8;
+
+/// Describes the errors which may occur while handling ICMPv6 messages.
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    /// Invalid checksum.
+    Checksum,
+    /// The specified byte sequence is shorter than the message.
+    SliceTooShort,
+}
+
+/// Interprets the inner bytes as an ICMPv6 message.
+pub struct Icmpv6Message<'a, T: 'a> {
+    bytes: InnerBytes<'a, T>,
+}
+
+#[allow(clippy::len_without_is_empty)]
+impl<'a, T: NetworkBytes> Icmpv6Message<'a, T> {
+    /// Interprets `bytes` as an ICMPv6 message without any validity checks.
+    ///
+    /// # Panics
+    ///
+    /// This method does not panic, but further method calls on the resulting object may panic if
+    /// `bytes` contains invalid input.
+    #[inline]
+    pub fn from_bytes_unchecked(bytes: T) -> Self {
+        Icmpv6Message {
+            bytes: InnerBytes::new(bytes),
+        }
+    }
+
+    /// Attempts to interpret `bytes` as an ICMPv6 message. The pair of addresses from the
+    /// enclosing IPv6 packet must be provided if the checksum has to be validated.
+    #[inline]
+    pub fn from_bytes(
+        bytes: T,
+        verify_checksum: Option<(Ipv6Addr, Ipv6Addr)>,
+    ) -> Result<Self, Error> {
+        if bytes.len() < HEADER_LEN {
+            return Err(Error::SliceTooShort);
+        }
+
+        let message = Icmpv6Message::from_bytes_unchecked(bytes);
+
+        if let Some((src_addr, dst_addr)) = verify_checksum {
+            if message.compute_checksum(src_addr, dst_addr) != 0 {
+                return Err(Error::Checksum);
+            }
+        }
+
+        Ok(message)
+    }
+
+    /// Returns the message type.
+    #[inline]
+    pub fn message_type(&self) -> u8 {
+        self.bytes[TYPE_OFFSET]
+    }
+
+    /// Returns the message code.
+    #[inline]
+    pub fn code(&self) -> u8 {
+        self.bytes[CODE_OFFSET]
+    }
+
+    /// Returns the target address of a Neighbor Solicitation message, or `None` if `self` is not
+    /// a valid Neighbor Solicitation.
+    ///
+    /// The validity checks which depend on the enclosing IPv6 packet (such as the hop limit)
+    /// are left to the caller.
+    pub fn neighbor_solicitation_target(&self) -> Option<Ipv6Addr> {
+        if self.message_type() != TYPE_NEIGHBOR_SOLICITATION
+            || self.code() != 0
+            || self.bytes.len() < NDP_OPTIONS_OFFSET
+        {
+            return None;
+        }
+
+        let mut octets = [0u8; 16];
+        octets.copy_from_slice(&self.bytes[NDP_TARGET_OFFSET..NDP_OPTIONS_OFFSET]);
+        let target = Ipv6Addr::from(octets);
+
+        if target.is_multicast() {
+            return None;
+        }
+        Some(target)
+    }
+
+    /// Computes the ICMPv6 checksum, which also covers the IPv6 pseudo-header.
+    #[inline]
+    pub fn compute_checksum(&self, src_addr: Ipv6Addr, dst_addr: Ipv6Addr) -> u16 {
+        crate::pdu::compute_checksum(
+            &self.bytes,
+            src_addr.into(),
+            dst_addr.into(),
+            ChecksumProto::Icmpv6,
+        )
+    }
+
+    /// Returns the length of the message.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+}
+
+impl<'a, T: NetworkBytesMut> Icmpv6Message<'a, T> {
+    /// Writes a solicited Neighbor Advertisement message to `buf`, which announces that `target`
+    /// can be reached at the link-layer address `mac`.
+    ///
+    /// The `src_addr` and `dst_addr` parameters are the addresses of the enclosing IPv6 packet,
+    /// which are required to compute the checksum.
+    pub fn write_neighbor_advertisement(
+        buf: T,
+        src_addr: Ipv6Addr,
+        dst_addr: Ipv6Addr,
+        target: Ipv6Addr,
+        mac: MacAddr,
+    ) -> Result<Self, Error> {
+        if buf.len() < NEIGHBOR_ADVERTISEMENT_LEN {
+            return Err(Error::SliceTooShort);
+        }
+
+        let mut message = Icmpv6Message::from_bytes_unchecked(buf);
+        message.bytes.shrink_unchecked(NEIGHBOR_ADVERTISEMENT_LEN);
+        message
+            .set_message_type(TYPE_NEIGHBOR_ADVERTISEMENT)
+            .set_code(0)
+            .set_checksum(0);
+
+        let bytes = &mut message.bytes;
+        bytes[NDP_FLAGS_OFFSET] = NA_FLAG_SOLICITED | NA_FLAG_OVERRIDE;
+        // The remaining flag bits are reserved.
+        for b in &mut bytes[NDP_FLAGS_OFFSET + 1..NDP_TARGET_OFFSET] {
+            *b = 0;
+        }
+        bytes[NDP_TARGET_OFFSET..NDP_OPTIONS_OFFSET].copy_from_slice(&target.octets());
+        bytes[NDP_OPTIONS_OFFSET] = OPTION_TARGET_LINK_LAYER_ADDR;
+        bytes[NDP_OPTIONS_OFFSET + 1] = OPTION_TARGET_LINK_LAYER_ADDR_LEN;
+        bytes[NDP_OPTIONS_OFFSET + 2..NEIGHBOR_ADVERTISEMENT_LEN].copy_from_slice(mac.get_bytes());
+
+        let checksum = message.compute_checksum(src_addr, dst_addr);
+        message.set_checksum(checksum);
+
+        Ok(message)
+    }
+
+    /// Sets the message type.
+    #[inline]
+    pub fn set_message_type(&mut self, value: u8) -> &mut Self {
+        self.bytes[TYPE_OFFSET] = value;
+        self
+    }
+
+    /// Sets the message code.
+    #[inline]
+    pub fn set_code(&mut self, value: u8) -> &mut Self {
+        self.bytes[CODE_OFFSET] = value;
+        self
+    }
+
+    /// Sets the value of the `checksum` field.
+    #[inline]
+    pub fn set_checksum(&mut self, value: u16) -> &mut Self {
+        self.bytes.htons_unchecked(CHECKSUM_OFFSET, value);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt;
+
+    use super::*;
+
+    impl<'a, T: NetworkBytes> fmt::Debug for Icmpv6Message<'a, T> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "(ICMPv6 message)")
+        }
+    }
+
+    #[test]
+    fn test_neighbor_discovery() {
+        let mut buf = [0u8; 100];
+        let src = Ipv6Addr::new(0xfd00, 0xec2, 0, 0, 0, 0, 0, 0x254);
+        let dst = Ipv6Addr::new(0xfe80, 0, 0, 0, 1, 2, 3, 4);
+        let mac = MacAddr::parse_str("12:34:56:78:9a:bc").unwrap();
+
+        let len = {
+            let m = Icmpv6Message::write_neighbor_advertisement(buf.as_mut(), src, dst, src, mac)
+                .unwrap();
+            assert_eq!(m.message_type(), TYPE_NEIGHBOR_ADVERTISEMENT);
+            assert_eq!(m.code(), 0);
+            assert_eq!(m.compute_checksum(src, dst), 0);
+            // Not a solicitation.
+            assert_eq!(m.neighbor_solicitation_target(), None);
+            m.len()
+        };
+        assert_eq!(len, NEIGHBOR_ADVERTISEMENT_LEN);
+        assert_eq!(buf[NDP_FLAGS_OFFSET], 0x60);
+        assert_eq!(&buf[NDP_TARGET_OFFSET..NDP_OPTIONS_OFFSET], &src.octets());
+        assert_eq!(&buf[NDP_OPTIONS_OFFSET..NDP_OPTIONS_OFFSET + 2], &[2, 1]);
+        assert_eq!(&buf[NDP_OPTIONS_OFFSET + 2..len], mac.get_bytes());
+
+        assert!(Icmpv6Message::from_bytes(&buf[..len], Some((src, dst))).is_ok());
+        // The pseudo-header is covered by the checksum.
+        assert_eq!(
+            Icmpv6Message::from_bytes(&buf[..len], Some((dst, dst))).unwrap_err(),
+            Error::Checksum
+        );
+
+        // Turn the advertisement into a solicitation.
+        Icmpv6Message::from_bytes_unchecked(buf.as_mut())
+            .set_message_type(TYPE_NEIGHBOR_SOLICITATION);
+        assert_eq!(
+            Icmpv6Message::from_bytes(&buf[..len], None)
+                .unwrap()
+                .neighbor_solicitation_target(),
+            Some(src)
+        );
+        // Solicitations must be long enough to hold the target address...
+        assert_eq!(
+            Icmpv6Message::from_bytes(&buf[..NDP_OPTIONS_OFFSET - 1], None)
+                .unwrap()
+                .neighbor_solicitation_target(),
+            None
+        );
+        // ... and must not use a non-zero code.
+        Icmpv6Message::from_bytes_unchecked(buf.as_mut()).set_code(1);
+        assert_eq!(
+            Icmpv6Message::from_bytes_unchecked(&buf[..len]).neighbor_solicitation_target(),
+            None
+        );
+
+        assert_eq!(
+            Icmpv6Message::from_bytes(&buf[..HEADER_LEN - 1], None).unwrap_err(),
+            Error::SliceTooShort
+        );
+        assert_eq!(
+            Icmpv6Message::write_neighbor_advertisement(
+                &mut buf[..NEIGHBOR_ADVERTISEMENT_LEN - 1],
+                src,
+                dst,
+                src,
+                mac
+            )
+            .unwrap_err(),
+            Error::SliceTooShort
+        );
+    }
+}
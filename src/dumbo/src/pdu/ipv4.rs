@@ -32,6 +32,9 @@ pub const IPV4_VERSION: u8 = 0x04;
 /// Default TTL value
 pub const DEFAULT_TTL: u8 = 200;
 
+/// The IP protocol number associated with ICMP.
+pub const PROTOCOL_ICMP: u8 = 0x01;
+
 /// The IP protocol number associated with TCP.
 pub const PROTOCOL_TCP: u8 = 0x06;
 
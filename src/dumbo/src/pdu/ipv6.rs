@@ -0,0 +1,451 @@
+// Copyright 2020 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Contains support for parsing and writing IPv6 packets.
+//!
+//! A picture of the IPv6 packet header can be found [here]. Extension headers are not supported,
+//! so the `next header` field is expected to identify the upper layer protocol directly.
+//!
+//! [here]: https://en.wikipedia.org/wiki/IPv6_packet#Fixed_header
+
+use std::convert::From;
+use std::net::Ipv6Addr;
+use std::result::Result;
+
+use pdu::bytes::{InnerBytes, NetworkBytes, NetworkBytesMut};
+use pdu::ethernet;
+use pdu::Incomplete;
+
+const VERSION_AND_TRAFFIC_CLASS_OFFSET: usize = 0;
+const FLOW_LABEL_OFFSET: usize = 0;
+const PAYLOAD_LEN_OFFSET: usize = 4;
+const NEXT_HEADER_OFFSET: usize = 6;
+const HOP_LIMIT_OFFSET: usize = 7;
+const SOURCE_ADDRESS_OFFSET: usize = 8;
+const DESTINATION_ADDRESS_OFFSET: usize = 24;
+
+/// The length of the (fixed) IPv6 header.
+pub const HEADER_LEN: usize = 40;
+
+/// Indicates version 6 of the IP protocol
+pub const IPV6_VERSION: u8 = 0x06;
+/// Default hop limit value. Neighbor Discovery messages are only valid when sent with this value.
+pub const DEFAULT_HOP_LIMIT: u8 = 255;
+
+/// The next header value associated with ICMPv6.
+pub const PROTOCOL_ICMPV6: u8 = 0x3a;
+
+/// Describes the errors which may occur while handling IPv6 packets.
+#[derive(Debug, PartialEq)]
+pub enum Error {
+    /// The length of the given slice does not match the length of the packet.
+    SliceExactLen,
+    /// The length of the given slice is less than the IPv6 header length.
+    SliceTooShort,
+    /// The version header field is invalid.
+    Version,
+}
+
+/// Interprets the inner bytes as an IPv6 packet.
+pub struct IPv6Packet<'a, T: 'a> {
+    bytes: InnerBytes<'a, T>,
+}
+
+#[allow(clippy::len_without_is_empty)]
+impl<'a, T: NetworkBytes> IPv6Packet<'a, T> {
+    /// Interpret `bytes` as an IPv6Packet without checking the validity of the header fields, and
+    /// the length of the inner byte sequence.
+    ///
+    /// # Panics
+    ///
+    /// This method does not panic, but further method calls on the resulting object may panic if
+    /// `bytes` contains invalid input.
+    #[inline]
+    pub fn from_bytes_unchecked(bytes: T) -> Self {
+        IPv6Packet {
+            bytes: InnerBytes::new(bytes),
+        }
+    }
+
+    /// Attempts to interpret `bytes` as an IPv6 packet, checking the validity of the header fields
+    /// and the length of the inner byte sequence.
+    pub fn from_bytes(bytes: T) -> Result<Self, Error> {
+        let bytes_len = bytes.len();
+
+        if bytes_len < HEADER_LEN {
+            return Err(Error::SliceTooShort);
+        }
+
+        let packet = IPv6Packet::from_bytes_unchecked(bytes);
+
+        if packet.version() != IPV6_VERSION {
+            return Err(Error::Version);
+        }
+
+        if HEADER_LEN + packet.payload_len() as usize != bytes_len {
+            return Err(Error::SliceExactLen);
+        }
+
+        // As with the IPv4 TTL, we don't care about the hop limit in general. Protocols which
+        // do (such as Neighbor Discovery) have to check it themselves.
+
+        Ok(packet)
+    }
+
+    /// Returns the value of the `version` header field.
+    #[inline]
+    pub fn version(&self) -> u8 {
+        self.bytes[VERSION_AND_TRAFFIC_CLASS_OFFSET] >> 4
+    }
+
+    /// Returns the value of the `traffic class` header field.
+    #[inline]
+    pub fn traffic_class(&self) -> u8 {
+        (self.bytes.ntohs_unchecked(VERSION_AND_TRAFFIC_CLASS_OFFSET) >> 4) as u8
+    }
+
+    /// Returns the value of the `flow label` header field.
+    #[inline]
+    pub fn flow_label(&self) -> u32 {
+        self.bytes.ntohl_unchecked(FLOW_LABEL_OFFSET) & 0x000f_ffff
+    }
+
+    /// Returns the value of the `payload length` header field.
+    #[inline]
+    pub fn payload_len(&self) -> u16 {
+        self.bytes.ntohs_unchecked(PAYLOAD_LEN_OFFSET)
+    }
+
+    /// Returns the value of the `next header` header field.
+    #[inline]
+    pub fn next_header(&self) -> u8 {
+        self.bytes[NEXT_HEADER_OFFSET]
+    }
+
+    /// Returns the value of the `hop limit` header field.
+    #[inline]
+    pub fn hop_limit(&self) -> u8 {
+        self.bytes[HOP_LIMIT_OFFSET]
+    }
+
+    /// Returns the source IPv6 address of the packet.
+    #[inline]
+    pub fn source_address(&self) -> Ipv6Addr {
+        read_addr_unchecked(&self.bytes, SOURCE_ADDRESS_OFFSET)
+    }
+
+    /// Returns the destination IPv6 address of the packet.
+    #[inline]
+    pub fn destination_address(&self) -> Ipv6Addr {
+        read_addr_unchecked(&self.bytes, DESTINATION_ADDRESS_OFFSET)
+    }
+
+    /// Returns a byte slice that contains the payload of the packet.
+    #[inline]
+    pub fn payload(&self) -> &[u8] {
+        self.bytes.split_at(HEADER_LEN).1
+    }
+
+    /// Returns the length of the inner byte sequence.
+    ///
+    /// This is equal to `HEADER_LEN` plus the output of the `payload_len()` method for properly
+    /// constructed instances of `IPv6Packet`.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+}
+
+impl<'a, T: NetworkBytesMut> IPv6Packet<'a, T> {
+    /// Attempts to write an IPv6 packet header to `buf`, making sure there is enough space.
+    ///
+    /// This method returns an incomplete packet, because the size of the payload might be unknown
+    /// at this point. The `traffic class` and `flow label` fields are set to 0. The `hop limit`
+    /// is set to a default value. The `payload length` field will be set when the length of the
+    /// incomplete packet is determined.
+    pub fn write_header(
+        buf: T,
+        next_header: u8,
+        src_addr: Ipv6Addr,
+        dst_addr: Ipv6Addr,
+    ) -> Result<Incomplete<Self>, Error> {
+        if buf.len() < HEADER_LEN {
+            return Err(Error::SliceTooShort);
+        }
+        let mut packet = IPv6Packet::from_bytes_unchecked(buf);
+        packet
+            .set_version_traffic_class_and_flow_label(IPV6_VERSION, 0, 0)
+            .set_next_header(next_header)
+            .set_hop_limit(DEFAULT_HOP_LIMIT)
+            .set_source_address(src_addr)
+            .set_destination_address(dst_addr);
+
+        Ok(Incomplete::new(packet))
+    }
+
+    /// Sets the values of the `version`, `traffic class` and `flow label` header fields.
+    #[inline]
+    pub fn set_version_traffic_class_and_flow_label(
+        &mut self,
+        version: u8,
+        traffic_class: u8,
+        flow_label: u32,
+    ) -> &mut Self {
+        let value = (u32::from(version) << 28)
+            | (u32::from(traffic_class) << 20)
+            | (flow_label & 0x000f_ffff);
+        self.bytes.htonl_unchecked(FLOW_LABEL_OFFSET, value);
+        self
+    }
+
+    /// Sets the value of the `payload length` header field.
+    #[inline]
+    pub fn set_payload_len(&mut self, value: u16) -> &mut Self {
+        self.bytes.htons_unchecked(PAYLOAD_LEN_OFFSET, value);
+        self
+    }
+
+    /// Sets the value of the `next header` header field.
+    #[inline]
+    pub fn set_next_header(&mut self, value: u8) -> &mut Self {
+        self.bytes[NEXT_HEADER_OFFSET] = value;
+        self
+    }
+
+    /// Sets the value of the `hop limit` header field.
+    #[inline]
+    pub fn set_hop_limit(&mut self, value: u8) -> &mut Self {
+        self.bytes[HOP_LIMIT_OFFSET] = value;
+        self
+    }
+
+    /// Sets the source address of the packet.
+    #[inline]
+    pub fn set_source_address(&mut self, addr: Ipv6Addr) -> &mut Self {
+        self.bytes[SOURCE_ADDRESS_OFFSET..DESTINATION_ADDRESS_OFFSET]
+            .copy_from_slice(&addr.octets());
+        self
+    }
+
+    /// Sets the destination address of the packet.
+    #[inline]
+    pub fn set_destination_address(&mut self, addr: Ipv6Addr) -> &mut Self {
+        self.bytes[DESTINATION_ADDRESS_OFFSET..HEADER_LEN].copy_from_slice(&addr.octets());
+        self
+    }
+
+    /// Returns a mutable byte slice representing the payload of the packet.
+    #[inline]
+    pub fn payload_mut(&mut self) -> &mut [u8] {
+        self.bytes.split_at_mut(HEADER_LEN).1
+    }
+}
+
+/// An incomplete packet is one where the payload length has not been determined yet.
+///
+/// It can be transformed into an `IPv6Packet` by specifying the size of the payload, and
+/// shrinking the inner byte sequence to be as large as the packet itself (this includes setting
+/// the `payload length` header field).
+impl<'a, T: NetworkBytesMut> Incomplete<IPv6Packet<'a, T>> {
+    /// Transforms `self` into an `IPv6Packet` based on the supplied payload length. Unlike IPv4,
+    /// the IPv6 header has no checksum.
+    ///
+    /// # Panics
+    ///
+    /// This method may panic if the value of `payload_len` is invalid.
+    #[inline]
+    pub fn with_payload_len_unchecked(mut self, payload_len: usize) -> IPv6Packet<'a, T> {
+        {
+            let packet = &mut self.inner;
+            // This unchecked is fine as long as the total length is smaller than the length of
+            // the original slice, which should be the case if our code is not wrong.
+            packet.bytes.shrink_unchecked(HEADER_LEN + payload_len);
+            packet.set_payload_len(payload_len as u16);
+        }
+        self.inner
+    }
+}
+
+// Reads an IPv6 address from `bytes`, starting at `offset`. May panic if there are less than 16
+// bytes available.
+#[inline]
+fn read_addr_unchecked(bytes: &[u8], offset: usize) -> Ipv6Addr {
+    let mut octets = [0u8; 16];
+    octets.copy_from_slice(&bytes[offset..offset + 16]);
+    Ipv6Addr::from(octets)
+}
+
+/// Returns the solicited-node multicast address associated with `addr`, which is where Neighbor
+/// Solicitation messages for `addr` are sent.
+pub fn solicited_node_multicast_addr(addr: Ipv6Addr) -> Ipv6Addr {
+    let segments = addr.segments();
+    Ipv6Addr::new(
+        0xff02,
+        0,
+        0,
+        0,
+        0,
+        1,
+        0xff00 | (segments[6] & 0x00ff),
+        segments[7],
+    )
+}
+
+/// This function checks if `buf` may hold an IPv6Packet heading towards the given address, or
+/// the solicited-node multicast address associated with it. Cannot produce false negatives.
+#[inline]
+pub fn test_speculative_dst_addr(buf: &[u8], addr: Ipv6Addr) -> bool {
+    if buf.len() >= ethernet::PAYLOAD_OFFSET + HEADER_LEN {
+        let dst_addr =
+            read_addr_unchecked(&buf[ethernet::PAYLOAD_OFFSET..], DESTINATION_ADDRESS_OFFSET);
+        if dst_addr == addr || dst_addr == solicited_node_multicast_addr(addr) {
+            return true;
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fmt;
+
+    use crate::MacAddr;
+
+    use super::*;
+
+    impl<'a, T: NetworkBytes> fmt::Debug for IPv6Packet<'a, T> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "(IPv6 packet)")
+        }
+    }
+
+    impl<'a, T: NetworkBytes> fmt::Debug for Incomplete<IPv6Packet<'a, T>> {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            write!(f, "(Incomplete IPv6 packet)")
+        }
+    }
+
+    #[test]
+    fn test_set_get() {
+        let mut a = [0u8; 100];
+        let mut p = IPv6Packet::from_bytes_unchecked(a.as_mut());
+
+        assert_eq!(p.version(), 0);
+        assert_eq!(p.traffic_class(), 0);
+        assert_eq!(p.flow_label(), 0);
+        p.set_version_traffic_class_and_flow_label(IPV6_VERSION, 0xab, 0xfedcb);
+        assert_eq!(p.version(), IPV6_VERSION);
+        assert_eq!(p.traffic_class(), 0xab);
+        assert_eq!(p.flow_label(), 0xfedcb);
+
+        assert_eq!(p.payload_len(), 0);
+        p.set_payload_len(123);
+        assert_eq!(p.payload_len(), 123);
+
+        assert_eq!(p.next_header(), 0);
+        p.set_next_header(PROTOCOL_ICMPV6);
+        assert_eq!(p.next_header(), PROTOCOL_ICMPV6);
+
+        assert_eq!(p.hop_limit(), 0);
+        p.set_hop_limit(64);
+        assert_eq!(p.hop_limit(), 64);
+
+        let src = Ipv6Addr::new(0xfe80, 0, 0, 0, 1, 2, 3, 4);
+        let dst = Ipv6Addr::new(0xfd00, 0xec2, 0, 0, 0, 0, 0, 0x254);
+
+        assert_eq!(p.source_address(), Ipv6Addr::UNSPECIFIED);
+        p.set_source_address(src);
+        assert_eq!(p.source_address(), src);
+
+        assert_eq!(p.destination_address(), Ipv6Addr::UNSPECIFIED);
+        p.set_destination_address(dst);
+        assert_eq!(p.destination_address(), dst);
+        // Setting the destination doesn't overwrite the source.
+        assert_eq!(p.source_address(), src);
+    }
+
+    #[test]
+    fn test_constructors() {
+        // We fill this with 1 to notice if the appropriate values get zeroed out.
+        let mut buf = [1u8; 100];
+
+        let src = Ipv6Addr::new(0xfe80, 0, 0, 0, 1, 2, 3, 4);
+        let dst = Ipv6Addr::new(0xfd00, 0xec2, 0, 0, 0, 0, 0, 0x254);
+
+        let buf_len = buf.len();
+        let payload_len = buf_len - HEADER_LEN;
+
+        {
+            let p = IPv6Packet::write_header(buf.as_mut(), PROTOCOL_ICMPV6, src, dst)
+                .unwrap()
+                .with_payload_len_unchecked(payload_len);
+
+            assert_eq!(p.version(), IPV6_VERSION);
+            assert_eq!(p.traffic_class(), 0);
+            assert_eq!(p.flow_label(), 0);
+            assert_eq!(p.payload_len() as usize, payload_len);
+            assert_eq!(p.next_header(), PROTOCOL_ICMPV6);
+            assert_eq!(p.hop_limit(), DEFAULT_HOP_LIMIT);
+            assert_eq!(p.source_address(), src);
+            assert_eq!(p.destination_address(), dst);
+            assert_eq!(p.payload().len(), payload_len);
+            assert_eq!(p.len(), buf_len);
+        }
+
+        assert!(IPv6Packet::from_bytes(buf.as_ref()).is_ok());
+
+        let look_for_error = |buf: &[u8], err: Error| {
+            assert_eq!(IPv6Packet::from_bytes(buf).unwrap_err(), err);
+        };
+
+        // Payload length not matching slice length.
+        look_for_error(&buf[..buf_len - 1], Error::SliceExactLen);
+
+        // Invalid version.
+        IPv6Packet::from_bytes_unchecked(buf.as_mut())
+            .set_version_traffic_class_and_flow_label(4, 0, 0);
+        look_for_error(buf.as_ref(), Error::Version);
+
+        let mut small_buf = [0u8; HEADER_LEN - 1];
+        look_for_error(small_buf.as_ref(), Error::SliceTooShort);
+        assert_eq!(
+            IPv6Packet::write_header(small_buf.as_mut(), PROTOCOL_ICMPV6, src, dst).unwrap_err(),
+            Error::SliceTooShort
+        );
+    }
+
+    #[test]
+    fn test_solicited_node_multicast_addr() {
+        assert_eq!(
+            solicited_node_multicast_addr(Ipv6Addr::new(0xfd00, 0xec2, 0, 0, 0, 0, 0x1234, 0x5678)),
+            Ipv6Addr::new(0xff02, 0, 0, 0, 0, 1, 0xff34, 0x5678)
+        );
+    }
+
+    #[test]
+    fn test_speculative() {
+        let mut buf = [0u8; 1000];
+        let mac = MacAddr::from_bytes_unchecked(&[0; 6]);
+        let ip = Ipv6Addr::new(0xfd00, 0xec2, 0, 0, 0, 0, 0, 0x254);
+        let other_ip = Ipv6Addr::new(0xfd00, 0xec2, 0, 0, 0, 0, 0, 0x253);
+
+        let set_dst = |buf: &mut [u8], addr| {
+            let mut eth =
+                ::pdu::ethernet::EthernetFrame::write_incomplete(buf, mac, mac, 0).unwrap();
+            IPv6Packet::from_bytes_unchecked(eth.inner_mut().payload_mut())
+                .set_destination_address(addr);
+        };
+
+        set_dst(buf.as_mut(), ip);
+        assert!(test_speculative_dst_addr(buf.as_ref(), ip));
+
+        set_dst(buf.as_mut(), solicited_node_multicast_addr(ip));
+        assert!(test_speculative_dst_addr(buf.as_ref(), ip));
+
+        set_dst(buf.as_mut(), other_ip);
+        assert!(!test_speculative_dst_addr(buf.as_ref(), ip));
+
+        let small = [0u8; 1];
+        assert!(!test_speculative_dst_addr(small.as_ref(), ip));
+    }
+}
@@ -4,27 +4,54 @@
 //! Defines the structures needed for saving/restoring MmdsNetworkStack.
 
 use std::net::Ipv4Addr;
+use std::sync::{Arc, Mutex};
+
+use mmds::data_store::Mmds;
 
 use snapshot::Persist;
 use versionize::{VersionMap, Versionize, VersionizeResult};
 use versionize_derive::Versionize;
 
 use super::ns::MmdsNetworkStack;
+use super::pdu::dhcp::DhcpLease;
 use super::*;
 
+/// State of a DHCP lease handed out by a MmdsNetworkStack.
+#[derive(Versionize)]
+pub struct DhcpLeaseState {
+    ipv4_addr: u32,
+    prefix_len: u8,
+    gateway: Option<u32>,
+    dns_servers: Vec<u32>,
+}
+
 /// State of a MmdsNetworkStack.
 #[derive(Versionize)]
 pub struct MmdsNetworkStackState {
     mac_addr: [u8; MAC_ADDR_LEN],
     ipv4_addr: u32,
+    #[version(start = 2, default_fn = "default_ipv6_addr")]
+    ipv6_addr: Option<String>,
     tcp_port: u16,
     max_connections: usize,
     max_pending_resets: usize,
+    #[version(start = 2, default_fn = "default_dhcp_lease")]
+    dhcp_lease: Option<DhcpLeaseState>,
+}
+
+impl MmdsNetworkStackState {
+    fn default_ipv6_addr(_: u16) -> Option<String> {
+        None
+    }
+
+    fn default_dhcp_lease(_: u16) -> Option<DhcpLeaseState> {
+        None
+    }
 }
 
 impl Persist<'_> for MmdsNetworkStack {
     type State = MmdsNetworkStackState;
-    type ConstructorArgs = ();
+    type ConstructorArgs = Arc<Mutex<Mmds>>;
     type Error = ();
 
     fn save(&self) -> Self::State {
@@ -34,23 +61,46 @@ impl Persist<'_> for MmdsNetworkStack {
         MmdsNetworkStackState {
             mac_addr,
             ipv4_addr: self.ipv4_addr.into(),
+            ipv6_addr: self.ipv6_addr.map(|addr| addr.to_string()),
             tcp_port: self.tcp_handler.local_port,
             max_connections: self.tcp_handler.max_connections,
             max_pending_resets: self.tcp_handler.max_pending_resets,
+            dhcp_lease: self.dhcp_lease.as_ref().map(|lease| DhcpLeaseState {
+                ipv4_addr: lease.ipv4_addr.into(),
+                prefix_len: lease.prefix_len,
+                gateway: lease.gateway.map(u32::from),
+                dns_servers: lease.dns_servers.iter().cloned().map(u32::from).collect(),
+            }),
         }
     }
 
     fn restore(
-        _: Self::ConstructorArgs,
+        mmds: Self::ConstructorArgs,
         state: &Self::State,
     ) -> std::result::Result<Self, Self::Error> {
-        Ok(MmdsNetworkStack::new(
+        let mut ns = MmdsNetworkStack::new(
             MacAddr::from_bytes_unchecked(&state.mac_addr),
             Ipv4Addr::from(state.ipv4_addr),
             state.tcp_port,
             std::num::NonZeroUsize::new(state.max_connections).unwrap(),
             std::num::NonZeroUsize::new(state.max_pending_resets).unwrap(),
-        ))
+            mmds,
+        );
+        ns.set_ipv6_addr(state.ipv6_addr.as_ref().and_then(|addr| addr.parse().ok()));
+        ns.set_dhcp_lease(state.dhcp_lease.as_ref().map(|lease| {
+            DhcpLease {
+                ipv4_addr: Ipv4Addr::from(lease.ipv4_addr),
+                prefix_len: lease.prefix_len,
+                gateway: lease.gateway.map(Ipv4Addr::from),
+                dns_servers: lease
+                    .dns_servers
+                    .iter()
+                    .cloned()
+                    .map(Ipv4Addr::from)
+                    .collect(),
+            }
+        }));
+        Ok(ns)
     }
 }
 
@@ -58,25 +108,54 @@ impl Persist<'_> for MmdsNetworkStack {
 mod tests {
     use super::*;
 
+    // The version map of the snapshots which save the IPv6 address and the DHCP lease.
+    fn version_map() -> VersionMap {
+        let mut version_map = VersionMap::new();
+        version_map
+            .new_version()
+            .set_type_version(MmdsNetworkStackState::type_id(), 2);
+        version_map
+    }
+
+    // The layout of the MMDS network stack state saved by Firecracker v0.23.
+    #[derive(Versionize)]
+    struct MmdsNetworkStackStateV1 {
+        mac_addr: [u8; MAC_ADDR_LEN],
+        ipv4_addr: u32,
+        tcp_port: u16,
+        max_connections: usize,
+        max_pending_resets: usize,
+    }
+
     #[test]
     fn test_persistence() {
-        let ns = MmdsNetworkStack::new_with_defaults(None);
+        let mmds = Arc::new(Mutex::new(Mmds::default()));
+        let mut ns = MmdsNetworkStack::new_with_defaults(None, mmds.clone());
+        ns.set_ipv6_addr(Some("fd00:ec2::254".parse().unwrap()));
+        ns.set_dhcp_lease(Some(DhcpLease {
+            ipv4_addr: Ipv4Addr::new(10, 0, 0, 2),
+            prefix_len: 24,
+            gateway: None,
+            dns_servers: vec![Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(10, 0, 0, 254)],
+        }));
 
         let mut mem = vec![0; 4096];
-        let version_map = VersionMap::new();
+        let version_map = version_map();
 
         ns.save()
-            .serialize(&mut mem.as_mut_slice(), &version_map, 1)
+            .serialize(&mut mem.as_mut_slice(), &version_map, 2)
             .unwrap();
 
         let restored_ns = MmdsNetworkStack::restore(
-            (),
-            &MmdsNetworkStackState::deserialize(&mut mem.as_slice(), &version_map, 1).unwrap(),
+            mmds,
+            &MmdsNetworkStackState::deserialize(&mut mem.as_slice(), &version_map, 2).unwrap(),
         )
         .unwrap();
 
         assert_eq!(restored_ns.mac_addr, ns.mac_addr);
         assert_eq!(restored_ns.ipv4_addr, ns.ipv4_addr);
+        assert_eq!(restored_ns.ipv6_addr, ns.ipv6_addr);
+        assert_eq!(restored_ns.dhcp_lease, ns.dhcp_lease);
         assert_eq!(
             restored_ns.tcp_handler.local_port,
             ns.tcp_handler.local_port
@@ -90,4 +169,35 @@ mod tests {
             ns.tcp_handler.max_pending_resets
         );
     }
+
+    #[test]
+    fn test_restore_v1_state() {
+        let mmds = Arc::new(Mutex::new(Mmds::default()));
+        let ns = MmdsNetworkStack::new_with_defaults(None, mmds.clone());
+        let state = ns.save();
+        let mut blob = Vec::new();
+        MmdsNetworkStackStateV1 {
+            mac_addr: state.mac_addr,
+            ipv4_addr: state.ipv4_addr,
+            tcp_port: state.tcp_port,
+            max_connections: state.max_connections,
+            max_pending_resets: state.max_pending_resets,
+        }
+        .serialize(&mut blob, &version_map(), 1)
+        .unwrap();
+
+        // The whole v1 blob is read, and the stack has neither an IPv6 address, nor a lease.
+        let mut reader = blob.as_slice();
+        let state = MmdsNetworkStackState::deserialize(&mut reader, &version_map(), 1).unwrap();
+        assert!(reader.is_empty());
+        let restored_ns = MmdsNetworkStack::restore(mmds, &state).unwrap();
+        assert_eq!(restored_ns.mac_addr, ns.mac_addr);
+        assert_eq!(restored_ns.ipv4_addr, ns.ipv4_addr);
+        assert_eq!(restored_ns.ipv6_addr, None);
+        assert_eq!(restored_ns.dhcp_lease, None);
+        assert_eq!(
+            restored_ns.tcp_handler.local_port,
+            ns.tcp_handler.local_port
+        );
+    }
 }
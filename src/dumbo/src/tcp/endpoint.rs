@@ -12,8 +12,10 @@
 // Endpoint in here too for the time being.
 
 use std::num::{NonZeroU16, NonZeroU64, Wrapping};
+use std::sync::{Arc, Mutex};
 
 use logger::{Metric, METRICS};
+use mmds::data_store::Mmds;
 use mmds::parse_request;
 use pdu::bytes::NetworkBytes;
 use pdu::tcp::TcpSegment;
@@ -61,6 +63,11 @@ pub struct Endpoint {
     // We ignore incoming segments when this is set, and that happens when we decide to reset
     // the connection (or it decides to reset itself).
     stop_receiving: bool,
+    // Set while the request at the beginning of receive_buf is a long-poll request, which waits
+    // for an update of the MMDS data store before being answered.
+    waiting_for_update: bool,
+    // The MMDS which answers the requests received over this connection.
+    mmds: Arc<Mutex<Mmds>>,
 }
 
 // The "contract" for the Endpoint (if it implemented a trait or something) is something along
@@ -72,6 +79,8 @@ pub struct Endpoint {
 // increases a metric).
 // - After calling either of the previous functions, the user should also call is_done() to see
 // if the Endpoint is finished.
+// - Long-poll requests are held by the Endpoint until the MMDS data store is updated. The user
+// should then call check_pending_request(), which returns true if a response became available.
 // - The is_evictable() function returns true if the Endpoint can be destroyed as far as its
 // internal logic is concerned. It's going to be used by the connection handler when trying to
 // find a new slot for incoming connections if none are free (when replacing an existing connection
@@ -83,6 +92,7 @@ impl Endpoint {
         eviction_threshold: NonZeroU64,
         connection_rto_period: NonZeroU64,
         connection_rto_count_max: NonZeroU16,
+        mmds: Arc<Mutex<Mmds>>,
     ) -> Result<Self, PassiveOpenError> {
         // TODO: mention this in doc comment for function
         // This simplifies things, and is a very reasonable assumption.
@@ -108,11 +118,14 @@ impl Endpoint {
             last_segment_received_timestamp: timestamp_cycles(),
             eviction_threshold: eviction_threshold.get(),
             stop_receiving: false,
+            waiting_for_update: false,
+            mmds,
         })
     }
 
     pub fn new_with_defaults<T: NetworkBytes>(
         segment: &TcpSegment<T>,
+        mmds: Arc<Mutex<Mmds>>,
     ) -> Result<Self, PassiveOpenError> {
         // The unwraps are safe because the constants are greater than 0.
         Self::new(
@@ -120,6 +133,7 @@ impl Endpoint {
             NonZeroU64::new(EVICTION_THRESHOLD).unwrap(),
             NonZeroU64::new(CONNECTION_RTO_PERIOD).unwrap(),
             NonZeroU16::new(CONNECTION_RTO_COUNT_MAX).unwrap(),
+            mmds,
         )
     }
 
@@ -179,46 +193,7 @@ impl Endpoint {
         if self.response_buf.is_empty() {
             // There's no pending response currently, so we're back to waiting for a request to be
             // available in self.receive_buf.
-
-            // The following is some ugly but workable code that attempts to find the end of an
-            // HTTP 1.x request in receive_buf. We need to do this for now because parse_request()
-            // expects the entire request contents as parameter.
-            if self.receive_buf_left > 2 {
-                let b = self.receive_buf.as_mut();
-                for i in 0..self.receive_buf_left - 1 {
-                    // We're basically looking for a double new line, which can only appear at the
-                    // end of a valid request.
-                    if b[i] == b'\n' {
-                        let end = if b[i + 1] == b'\n' {
-                            i + 2
-                        } else if i + 3 <= self.receive_buf_left && &b[i + 1..i + 3] == b"\r\n" {
-                            i + 3
-                        } else {
-                            continue;
-                        };
-
-                        // We found a potential request, let's parse it.
-                        let response = parse_request(&b[..end]);
-                        // The unwrap is safe because a Vec will allocate more space until all the
-                        // writes succeed.
-                        response.write_all(&mut self.response_buf).unwrap();
-
-                        // Sanity check because the current logic operates under this assumption.
-                        assert!(self.response_buf.len() < u32::max_value() as usize);
-
-                        // We have to remove the bytes up to end from receive_buf, by shifting the
-                        // others to the beginning of the buffer, and updating receive_buf_left.
-                        // Also, advance the rwnd edge of the inner connection.
-                        // TODO: Maximum efficiency.
-                        for j in 0..b.len() - end {
-                            b[j] = b[j + end];
-                        }
-                        self.receive_buf_left -= end;
-                        self.connection.advance_local_rwnd_edge(end as u32);
-                        break;
-                    }
-                }
-            }
+            self.process_request();
 
             if self.receive_buf_left == self.receive_buf.len() {
                 // If we get here the buffer is full, but we still couldn't identify the end of a
@@ -230,12 +205,81 @@ impl Endpoint {
         }
 
         // We close the connection after receiving a FIN, and making sure there are no more
-        // responses to send.
-        if self.connection.fin_received() && self.response_buf.is_empty() {
+        // responses to send, or to wait for.
+        if self.connection.fin_received()
+            && self.response_buf.is_empty()
+            && !self.waiting_for_update
+        {
             self.connection.close();
         }
     }
 
+    // Looks for a complete request in receive_buf, and writes the response to response_buf. A
+    // long-poll request which has to wait for an update of the MMDS data store is left in
+    // receive_buf, and waiting_for_update is set instead.
+    fn process_request(&mut self) {
+        // The following is some ugly but workable code that attempts to find the end of an
+        // HTTP 1.x request in receive_buf. We need to do this for now because parse_request()
+        // expects the entire request contents as parameter.
+        if self.receive_buf_left > 2 {
+            let b = self.receive_buf.as_mut();
+            for i in 0..self.receive_buf_left - 1 {
+                // We're basically looking for a double new line, which can only appear at the
+                // end of a valid request.
+                if b[i] == b'\n' {
+                    let end = if b[i + 1] == b'\n' {
+                        i + 2
+                    } else if i + 3 <= self.receive_buf_left && &b[i + 1..i + 3] == b"\r\n" {
+                        i + 3
+                    } else {
+                        continue;
+                    };
+
+                    // We found a potential request, let's parse it. The lock can be held by
+                    // one thread only, so it is safe to unwrap. If another thread poisoned the
+                    // lock, we abort the execution.
+                    let mut mmds = self.mmds.lock().expect("Poisoned lock");
+                    let response = match parse_request(&mut mmds, &b[..end]) {
+                        Some(response) => response,
+                        None => {
+                            self.waiting_for_update = true;
+                            break;
+                        }
+                    };
+                    self.waiting_for_update = false;
+                    // The unwrap is safe because a Vec will allocate more space until all the
+                    // writes succeed.
+                    response.write_all(&mut self.response_buf).unwrap();
+
+                    // Sanity check because the current logic operates under this assumption.
+                    assert!(self.response_buf.len() < u32::max_value() as usize);
+
+                    // We have to remove the bytes up to end from receive_buf, by shifting the
+                    // others to the beginning of the buffer, and updating receive_buf_left.
+                    // Also, advance the rwnd edge of the inner connection.
+                    // TODO: Maximum efficiency.
+                    for j in 0..b.len() - end {
+                        b[j] = b[j + end];
+                    }
+                    self.receive_buf_left -= end;
+                    self.connection.advance_local_rwnd_edge(end as u32);
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Retries the long-poll request the endpoint is holding, if any, after an update of the MMDS
+    /// data store. Returns `true` if the request got answered, in which case there's a response
+    /// to send.
+    pub fn check_pending_request(&mut self) -> bool {
+        if !self.waiting_for_update || self.stop_receiving {
+            return false;
+        }
+        self.process_request();
+        !self.waiting_for_update
+    }
+
     pub fn write_next_segment<'a>(
         &mut self,
         buf: &'a mut [u8],
@@ -301,6 +345,8 @@ impl Endpoint {
 
 #[cfg(test)]
 mod tests {
+    extern crate serde_json;
+
     use super::*;
 
     use std::fmt;
@@ -335,15 +381,16 @@ mod tests {
 
         // Put another flag on the SYN so it becomes invalid.
         syn.set_flags_after_ns(TcpFlags::ACK);
+        let mmds = Arc::new(Mutex::new(Mmds::default()));
         assert_eq!(
-            Endpoint::new_with_defaults(&syn).unwrap_err(),
+            Endpoint::new_with_defaults(&syn, mmds.clone()).unwrap_err(),
             PassiveOpenError::InvalidSyn
         );
 
         // Fix the SYN and create an endpoint.
         syn.set_flags_after_ns(TcpFlags::SYN);
         let remote_isn = syn.sequence_number();
-        let mut e = Endpoint::new_with_defaults(&syn).unwrap();
+        let mut e = Endpoint::new_with_defaults(&syn, mmds).unwrap();
 
         // Let's complete the three-way handshake. The next segment sent by the endpoint should
         // be a SYNACK.
@@ -522,4 +569,75 @@ mod tests {
             assert_eq!(s.inner().flags_after_ns(), TcpFlags::RST);
         }
     }
+
+    #[test]
+    fn test_long_poll() {
+        let mut buf1 = [0u8; 500];
+        let mut buf2 = [0u8; 500];
+        let mut write_buf = [0u8; RCV_BUF_MAX_SIZE + 100];
+
+        let mut t = ConnectionTester::new();
+        let mut syn = t.write_syn(buf1.as_mut());
+        syn.set_flags_after_ns(TcpFlags::SYN);
+        let remote_isn = syn.sequence_number();
+        let mmds = Arc::new(Mutex::new(Mmds::default()));
+        let mut e = Endpoint::new_with_defaults(&syn, mmds.clone()).unwrap();
+
+        let endpoint_isn = e
+            .write_next_segment(write_buf.as_mut(), t.mss_reserved)
+            .unwrap()
+            .inner()
+            .sequence_number();
+        let mut ctrl = t.write_ctrl(buf2.as_mut());
+        ctrl.set_flags_after_ns(TcpFlags::ACK);
+        ctrl.set_ack_number(endpoint_isn.wrapping_add(1));
+        e.receive_segment(&ctrl);
+        assert!(e.connection.is_established());
+
+        // Wait for a data store version newer than the current one.
+        let version = mmds.lock().unwrap().data_store_version();
+        let request = format!(
+            "GET /asdfghjkl?wait=true&version={} HTTP/1.1\r\n\r\n",
+            version
+        );
+        {
+            let mut data = t.write_data(write_buf.as_mut(), request.as_bytes());
+            data.set_flags_after_ns(TcpFlags::ACK | TcpFlags::FIN);
+            data.set_sequence_number(remote_isn.wrapping_add(1));
+            data.set_ack_number(endpoint_isn.wrapping_add(1));
+            e.receive_segment(&data);
+        }
+
+        // The request is held, and the connection stays open in spite of the FIN.
+        assert!(e.waiting_for_update);
+        assert!(e.connection.fin_received());
+        assert_eq!(e.receive_buf_left, request.len());
+        {
+            let s = e
+                .write_next_segment(write_buf.as_mut(), t.mss_reserved)
+                .unwrap();
+            assert_eq!(s.inner().flags_after_ns(), TcpFlags::ACK);
+            assert_eq!(s.inner().payload_len(), 0);
+        }
+        assert!(!e.check_pending_request());
+        assert_eq!(e.next_segment_status(), NextSegmentStatus::Nothing);
+
+        // The response becomes available once the data store is updated.
+        mmds.lock()
+            .unwrap()
+            .put_data(serde_json::json!({}))
+            .unwrap();
+        assert!(e.check_pending_request());
+        assert!(!e.waiting_for_update);
+        assert_eq!(e.receive_buf_left, 0);
+        assert_eq!(e.next_segment_status(), NextSegmentStatus::Available);
+        let s = e
+            .write_next_segment(write_buf.as_mut(), t.mss_reserved)
+            .unwrap();
+        let response = from_utf8(s.inner().payload()).unwrap();
+        assert!(response.contains("404"));
+
+        // There's nothing left to retry.
+        assert!(!e.check_pending_request());
+    }
 }
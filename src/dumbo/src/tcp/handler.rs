@@ -1,22 +1,25 @@
 // Copyright 2018 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-//! Exposes simple TCP over IPv4 listener functionality via the [`TcpIPv4Handler`] structure.
+//! Exposes simple TCP over IPv4 (and IPv6) listener functionality via the [`TcpIPv4Handler`]
+//! structure.
 //!
 //! [`TcpIPv4Handler`]: struct.TcpIPv4Handler.html
 
 use std::collections::{HashMap, HashSet};
-use std::net::Ipv4Addr;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
 use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+
+use mmds::data_store::Mmds;
 
 use pdu::bytes::NetworkBytes;
 use pdu::ipv4::{Error as IPv4PacketError, IPv4Packet, PROTOCOL_TCP};
+use pdu::ipv6::{Error as IPv6PacketError, IPv6Packet};
 use pdu::tcp::{Error as TcpSegmentError, Flags as TcpFlags, TcpSegment};
 use tcp::endpoint::Endpoint;
 use tcp::{NextSegmentStatus, RstConfig};
 
-// TODO: This is currently IPv4 specific. Maybe change it to a more generic implementation.
-
 /// Describes events which may occur when the handler receives packets.
 #[cfg_attr(test, derive(Debug, PartialEq))]
 pub enum RecvEvent {
@@ -71,22 +74,26 @@ pub enum RecvError {
 pub enum WriteNextError {
     /// There was an error while writing the contents of the IPv4 packet.
     IPv4Packet(IPv4PacketError),
+    /// There was an error while writing the contents of the IPv6 packet.
+    IPv6Packet(IPv6PacketError),
     /// There was an error while writing the contents of the inner TCP segment.
     TcpSegment(TcpSegmentError),
 }
 
-// Generally speaking, a TCP/IPv4 connection is identified using the four-tuple (src_addr, src_port,
-// dst_addr, dst_port). However, the IPv4 address and TCP port of the MMDS endpoint are fixed, so
+// Generally speaking, a TCP/IP connection is identified using the four-tuple (src_addr, src_port,
+// dst_addr, dst_port). However, the IP addresses and TCP port of the MMDS endpoint are fixed, so
 // we can get away with uniquely identifying connections using just the remote address and port.
+// The version of the remote address also determines the version of the IP packets which carry
+// the segments of the connection.
 #[derive(Clone, Copy, Eq, Hash, PartialEq)]
 #[cfg_attr(test, derive(Debug))]
 struct ConnectionTuple {
-    remote_addr: Ipv4Addr,
+    remote_addr: IpAddr,
     remote_port: u16,
 }
 
 impl ConnectionTuple {
-    fn new(remote_addr: Ipv4Addr, remote_port: u16) -> Self {
+    fn new(remote_addr: IpAddr, remote_port: u16) -> Self {
         ConnectionTuple {
             remote_addr,
             remote_port,
@@ -106,8 +113,8 @@ impl ConnectionTuple {
 ///   creates new connections for incoming `SYN` segments, and enqueues `RST` replies in response
 ///   to any segments which cannot be associated with a connection (except other `RST` segments).
 ///   On success, also describes any internal status changes triggered by the reception of the
-///   packet.
-/// * [`write_next_packet`] writes the next IPv4 packet (if available) that would be sent by the
+///   packet. IPv6 packets are handled the same way by [`receive_ipv6_packet`].
+/// * [`write_next_packet`] writes the next IP packet (if available) that would be sent by the
 ///   handler itself (right now it can only mean an enqueued `RST`), or one of the existing
 ///   connections. On success, also describes any internal status changes triggered as the packet
 ///   gets transmitted.
@@ -116,12 +123,23 @@ impl ConnectionTuple {
 ///   to send for the moment. This is used to determine whether it's appropriate to call
 ///   [`write_next_packet`].
 ///
+/// Connections may also hold long-poll requests, which wait for an update of the MMDS data
+/// store. These are retried by [`check_pending_requests`] once the data store changes.
+///
+/// Despite the name, the handler is also able to serve connections over IPv6, once a local IPv6
+/// address has been configured. Packets sent to a remote endpoint use the same IP version as the
+/// ones received from it.
+///
 /// [`receive_packet`]: ../handler/struct.TcpIPv4Handler.html#method.receive_packet
+/// [`receive_ipv6_packet`]: ../handler/struct.TcpIPv4Handler.html#method.receive_ipv6_packet
 /// [`write_next_packet`]: ../handler/struct.TcpIPv4Handler.html#method.write_next_packet
 /// [`next_segment_status`]: ../handler/struct.TcpIPv4Handler.html#method.next_segment_status
+/// [`check_pending_requests`]: ../handler/struct.TcpIPv4Handler.html#method.check_pending_requests
 pub struct TcpIPv4Handler {
     // Handler IPv4 address used for every connection.
     local_ipv4_addr: Ipv4Addr,
+    // Handler IPv6 address used for every connection. IPv6 packets are not accepted when missing.
+    local_ipv6_addr: Option<Ipv6Addr>,
     // Handler TCP port used for every connection.
     pub(crate) local_port: u16,
     // This map holds the currently active endpoints, identified by their connection tuple.
@@ -137,6 +155,8 @@ pub struct TcpIPv4Handler {
     rst_queue: Vec<(ConnectionTuple, RstConfig)>,
     // Maximum size of the RST queue.
     pub(crate) max_pending_resets: usize,
+    // The MMDS which answers the requests received over every connection.
+    mmds: Arc<Mutex<Mmds>>,
 }
 
 // Only used locally, in the receive_packet method, to differentiate between different outcomes
@@ -153,18 +173,21 @@ impl TcpIPv4Handler {
     ///
     /// The handler acts as if bound to `local_addr`:`local_port`, and will accept at most
     /// `max_connections` concurrent connections. `RST` segments generated by unexpected incoming
-    /// segments are placed in a queue which is at most `max_pending_resets` long.
+    /// segments are placed in a queue which is at most `max_pending_resets` long. The requests
+    /// received over every connection are answered by `mmds`.
     #[inline]
     pub fn new(
         local_ipv4_addr: Ipv4Addr,
         local_port: u16,
         max_connections: NonZeroUsize,
         max_pending_resets: NonZeroUsize,
+        mmds: Arc<Mutex<Mmds>>,
     ) -> Self {
         let max_connections = max_connections.get();
         let max_pending_resets = max_pending_resets.get();
         TcpIPv4Handler {
             local_ipv4_addr,
+            local_ipv6_addr: None,
             local_port,
             connections: HashMap::with_capacity(max_connections),
             max_connections,
@@ -172,6 +195,7 @@ impl TcpIPv4Handler {
             next_timeout: None,
             rst_queue: Vec::with_capacity(max_pending_resets),
             max_pending_resets,
+            mmds,
         }
     }
 
@@ -180,24 +204,52 @@ impl TcpIPv4Handler {
         self.local_ipv4_addr = ipv4_addr;
     }
 
+    /// Setter for the local IPv6 address of this TCP handler. Setting it to `None` disables
+    /// IPv6 support.
+    pub fn set_local_ipv6_addr(&mut self, ipv6_addr: Option<Ipv6Addr>) {
+        self.local_ipv6_addr = ipv6_addr;
+    }
+
+    /// Returns the MMDS which answers the requests received over every connection.
+    pub fn mmds(&self) -> &Arc<Mutex<Mmds>> {
+        &self.mmds
+    }
+
     /// Contains logic for handling incoming segments.
     ///
     /// Any changes to the state if the handler are communicated through an `Ok(RecvEvent)`.
     pub fn receive_packet<T: NetworkBytes>(
         &mut self,
         packet: &IPv4Packet<T>,
+    ) -> Result<RecvEvent, RecvError> {
+        self.receive_segment(packet.source_address().into(), packet.payload())
+    }
+
+    /// Same as [`receive_packet`], but handles segments carried by IPv6 packets.
+    ///
+    /// [`receive_packet`]: ../handler/struct.TcpIPv4Handler.html#method.receive_packet
+    pub fn receive_ipv6_packet<T: NetworkBytes>(
+        &mut self,
+        packet: &IPv6Packet<T>,
+    ) -> Result<RecvEvent, RecvError> {
+        self.receive_segment(packet.source_address().into(), packet.payload())
+    }
+
+    fn receive_segment(
+        &mut self,
+        remote_addr: IpAddr,
+        payload: &[u8],
     ) -> Result<RecvEvent, RecvError> {
         // TODO: We skip verifying the checksum, just in case the device model relies on offloading
         // checksum computation from the guest to some other entity. Clear this up at some point!
         // (Issue #520)
-        let segment =
-            TcpSegment::from_bytes(packet.payload(), None).map_err(RecvError::TcpSegment)?;
+        let segment = TcpSegment::from_bytes(payload, None).map_err(RecvError::TcpSegment)?;
 
         if segment.destination_port() != self.local_port {
             return Err(RecvError::InvalidPort);
         }
 
-        let tuple = ConnectionTuple::new(packet.source_address(), segment.source_port());
+        let tuple = ConnectionTuple::new(remote_addr, segment.source_port());
 
         let outcome = if let Some(endpoint) = self.connections.get_mut(&tuple) {
             endpoint.receive_segment(&segment);
@@ -229,7 +281,7 @@ impl TcpIPv4Handler {
                 Ok(RecvEvent::Nothing)
             }
             RecvSegmentOutcome::NewConnection => {
-                let endpoint = match Endpoint::new_with_defaults(&segment) {
+                let endpoint = match Endpoint::new_with_defaults(&segment, self.mmds.clone()) {
                     Ok(endpoint) => endpoint,
                     Err(_) => return Ok(RecvEvent::FailedNewConnection),
                 };
@@ -364,10 +416,11 @@ impl TcpIPv4Handler {
         let mut writer_status = None;
         let mut event = WriteEvent::Nothing;
 
-        // Write an incomplete Ipv4 packet and complete it afterwards with missing information.
-        let mut packet =
-            IPv4Packet::write_header(buf, PROTOCOL_TCP, Ipv4Addr::LOCALHOST, Ipv4Addr::LOCALHOST)
-                .map_err(WriteNextError::IPv4Packet)?;
+        let local_ipv4_addr = self.local_ipv4_addr;
+        // The local IPv6 address is always present when there are IPv6 connections around, since
+        // it can only change before the guest boots.
+        let local_ipv6_addr = self.local_ipv6_addr.unwrap_or(Ipv6Addr::UNSPECIFIED);
+        let local_port = self.local_port;
 
         // We set mss_used to 0, because we don't add any IP options.
         // TODO: Maybe get this nicely from packet at some point.
@@ -378,35 +431,32 @@ impl TcpIPv4Handler {
         // any TCP options, or a payload.
         if let Some((tuple, rst_cfg)) = self.rst_queue.pop() {
             let (seq, ack, flags_after_ns) = rst_cfg.seq_ack_tcp_flags();
-            let segment_len = TcpSegment::write_incomplete_segment::<[u8]>(
-                packet.inner_mut().payload_mut(),
-                seq,
-                ack,
-                flags_after_ns,
-                10000,
-                None,
-                0,
-                None,
-            )
-            .map_err(WriteNextError::TcpSegment)?
-            .finalize(
-                self.local_port,
-                tuple.remote_port,
-                Some((self.local_ipv4_addr, tuple.remote_addr)),
-            )
-            .len();
-
-            packet
-                .inner_mut()
-                .set_source_address(self.local_ipv4_addr)
-                .set_destination_address(tuple.remote_addr);
-
-            let packet_len = packet.with_payload_len_unchecked(segment_len, true).len();
-            // The unwrap() is safe because packet_len > 0.
-            return Ok((
-                Some(NonZeroUsize::new(packet_len).unwrap()),
-                WriteEvent::Nothing,
-            ));
+            let packet_len = write_tcp_packet(
+                buf,
+                local_ipv4_addr,
+                local_ipv6_addr,
+                tuple.remote_addr,
+                |payload, addrs| {
+                    TcpSegment::write_incomplete_segment::<[u8]>(
+                        payload,
+                        seq,
+                        ack,
+                        flags_after_ns,
+                        10000,
+                        None,
+                        0,
+                        None,
+                    )
+                    .map(|segment| {
+                        Some(
+                            segment
+                                .finalize(local_port, tuple.remote_port, Some(addrs))
+                                .len(),
+                        )
+                    })
+                },
+            )?;
+            return Ok((packet_len, WriteEvent::Nothing));
         }
 
         for tuple in self
@@ -417,33 +467,27 @@ impl TcpIPv4Handler {
             // Tuples in self.active_connection or self.next_timeout should also appear as keys
             // in self.connections.
             let endpoint = self.connections.get_mut(tuple).unwrap();
-            // We need this block to clearly delimit the lifetime of the mutable borrow started by
-            // the following packet.inner_mut().
-            let segment_len = {
-                let maybe_segment =
-                    endpoint.write_next_segment(packet.inner_mut().payload_mut(), mss_reserved);
-
-                match maybe_segment {
-                    Some(segment) => segment
-                        .finalize(
-                            self.local_port,
-                            tuple.remote_port,
-                            Some((self.local_ipv4_addr, tuple.remote_addr)),
-                        )
-                        .len(),
-                    None => continue,
-                }
-            };
-
-            packet
-                .inner_mut()
-                .set_source_address(self.local_ipv4_addr)
-                .set_destination_address(tuple.remote_addr);
-
-            let ip_len = packet.with_payload_len_unchecked(segment_len, true).len();
+            let packet_len = write_tcp_packet(
+                buf,
+                local_ipv4_addr,
+                local_ipv6_addr,
+                tuple.remote_addr,
+                |payload, addrs| {
+                    Ok(endpoint
+                        .write_next_segment(payload, mss_reserved)
+                        .map(|segment| {
+                            segment
+                                .finalize(local_port, tuple.remote_port, Some(addrs))
+                                .len()
+                        }))
+                },
+            )?;
+
+            if packet_len.is_none() {
+                continue;
+            }
 
-            // The unwrap is safe because ip_len > 0.
-            len = Some(NonZeroUsize::new(ip_len).unwrap());
+            len = packet_len;
             writer_status = Some((*tuple, endpoint.is_done()));
 
             break;
@@ -466,6 +510,29 @@ impl TcpIPv4Handler {
         Ok((len, event))
     }
 
+    /// Retries the long-poll requests which the connections are holding, after an update of the
+    /// MMDS data store. Returns `true` if any of them got answered, in which case the handler has
+    /// segments to send.
+    pub fn check_pending_requests(&mut self) -> bool {
+        let answered: Vec<_> = self
+            .connections
+            .iter_mut()
+            .filter_map(|(tuple, endpoint)| {
+                if endpoint.check_pending_request() {
+                    Some((*tuple, endpoint.next_segment_status()))
+                } else {
+                    None
+                }
+            })
+            .collect();
+
+        let mut has_segments = false;
+        for (tuple, status) in answered {
+            has_segments |= self.check_next_segment_status(tuple, status);
+        }
+        has_segments
+    }
+
     /// Describes the status of the next segment to be sent by the handler.
     #[inline]
     pub fn next_segment_status(&self) -> NextSegmentStatus {
@@ -481,6 +548,52 @@ impl TcpIPv4Handler {
     }
 }
 
+// Writes an IP packet carrying a TCP segment to `buf`. The version of the packet matches the one of
+// `remote_addr`, and the local address is picked accordingly. The segment is written by the
+// `write_segment` closure, which receives the packet payload, and the pair of addresses required
+// to compute the TCP checksum. The closure returns the length of the segment, or `None` if there's
+// nothing to send, in which case no packet gets written.
+fn write_tcp_packet<F>(
+    buf: &mut [u8],
+    local_ipv4_addr: Ipv4Addr,
+    local_ipv6_addr: Ipv6Addr,
+    remote_addr: IpAddr,
+    write_segment: F,
+) -> Result<Option<NonZeroUsize>, WriteNextError>
+where
+    F: FnOnce(&mut [u8], (IpAddr, IpAddr)) -> Result<Option<usize>, TcpSegmentError>,
+{
+    let packet_len = match remote_addr {
+        IpAddr::V4(remote_addr) => {
+            let mut packet =
+                IPv4Packet::write_header(buf, PROTOCOL_TCP, local_ipv4_addr, remote_addr)
+                    .map_err(WriteNextError::IPv4Packet)?;
+            let addrs = (local_ipv4_addr.into(), remote_addr.into());
+            match write_segment(packet.inner_mut().payload_mut(), addrs)
+                .map_err(WriteNextError::TcpSegment)?
+            {
+                Some(segment_len) => packet.with_payload_len_unchecked(segment_len, true).len(),
+                None => return Ok(None),
+            }
+        }
+        IpAddr::V6(remote_addr) => {
+            let mut packet =
+                IPv6Packet::write_header(buf, PROTOCOL_TCP, local_ipv6_addr, remote_addr)
+                    .map_err(WriteNextError::IPv6Packet)?;
+            let addrs = (local_ipv6_addr.into(), remote_addr.into());
+            match write_segment(packet.inner_mut().payload_mut(), addrs)
+                .map_err(WriteNextError::TcpSegment)?
+            {
+                Some(segment_len) => packet.with_payload_len_unchecked(segment_len).len(),
+                None => return Ok(None),
+            }
+        }
+    };
+
+    // The unwrap is safe because packet_len > 0.
+    Ok(Some(NonZeroUsize::new(packet_len).unwrap()))
+}
+
 #[cfg(test)]
 mod tests {
     use pdu::bytes::NetworkBytesMut;
@@ -572,6 +685,7 @@ mod tests {
             local_port,
             NonZeroUsize::new(max_connections).unwrap(),
             NonZeroUsize::new(max_pending_resets).unwrap(),
+            Arc::new(Mutex::new(Mmds::default())),
         );
 
         // We start with a wrong destination address and destination port to check those error
@@ -669,8 +783,8 @@ mod tests {
         assert_eq!(h.next_segment_status(), NextSegmentStatus::Available);
         assert_eq!(drain_packets(&mut h, local_addr, remote_addr), Ok(1));
 
-        let remote_tuple = ConnectionTuple::new(remote_addr, remote_port);
-        let remote_tuple2 = ConnectionTuple::new(remote_addr, remote_port + 1);
+        let remote_tuple = ConnectionTuple::new(remote_addr.into(), remote_port);
+        let remote_tuple2 = ConnectionTuple::new(remote_addr.into(), remote_port + 1);
 
         // Also, there should be a retransmission timer associated with the previous SYNACK now.
         assert_eq!(h.active_connections.len(), 0);
@@ -728,7 +842,7 @@ mod tests {
         // The timeout associated with the SYNACK of the second connection should be next.
         assert_eq!(h.active_connections.len(), 0);
         if let Some((_, tuple)) = h.next_timeout {
-            assert_ne!(tuple, ConnectionTuple::new(remote_addr, remote_port));
+            assert_ne!(tuple, ConnectionTuple::new(remote_addr.into(), remote_port));
         } else {
             panic!("missing third expected timeout");
         }
@@ -774,4 +888,84 @@ mod tests {
         assert_eq!(h.connections.len(), 1);
         assert_eq!(h.active_connections.len(), 0);
     }
+
+    #[test]
+    fn test_handler_ipv6() {
+        let mut buf = [0u8; 100];
+        let mut buf2 = [0u8; 2000];
+
+        let local_addr = Ipv4Addr::new(169, 254, 169, 254);
+        let local_ipv6_addr = Ipv6Addr::new(0xfd00, 0xec2, 0, 0, 0, 0, 0, 0x254);
+        let local_port = 80;
+        let remote_addr = Ipv6Addr::new(0xfe80, 0, 0, 0, 1, 2, 3, 4);
+        let remote_port = 1012;
+
+        let mut h = TcpIPv4Handler::new(
+            local_addr,
+            local_port,
+            NonZeroUsize::new(2).unwrap(),
+            NonZeroUsize::new(2).unwrap(),
+            Arc::new(Mutex::new(Mmds::default())),
+        );
+        h.set_local_ipv6_addr(Some(local_ipv6_addr));
+
+        let p = {
+            let mut p =
+                IPv6Packet::write_header(buf.as_mut(), PROTOCOL_TCP, remote_addr, local_ipv6_addr)
+                    .unwrap();
+            let s_len = TcpSegment::write_segment::<[u8]>(
+                p.inner_mut().payload_mut(),
+                remote_port,
+                local_port,
+                123,
+                456,
+                TcpFlags::SYN,
+                10000,
+                None,
+                100,
+                None,
+                None,
+            )
+            .unwrap()
+            .len();
+            p.with_payload_len_unchecked(s_len)
+        };
+
+        assert_eq!(
+            h.receive_ipv6_packet(&p),
+            Ok(RecvEvent::NewConnectionSuccessful)
+        );
+        assert!(h
+            .connections
+            .contains_key(&ConnectionTuple::new(remote_addr.into(), remote_port)));
+
+        // The SYNACK is sent back over IPv6, with a valid checksum.
+        let (len, event) = h.write_next_packet(buf2.as_mut()).unwrap();
+        assert_eq!(event, WriteEvent::Nothing);
+        let p = IPv6Packet::from_bytes(&buf2[..len.unwrap().get()]).unwrap();
+        assert_eq!(p.next_header(), PROTOCOL_TCP);
+        assert_eq!(p.source_address(), local_ipv6_addr);
+        assert_eq!(p.destination_address(), remote_addr);
+        let s = TcpSegment::from_bytes(
+            p.payload(),
+            Some((local_ipv6_addr.into(), remote_addr.into())),
+        )
+        .unwrap();
+        assert_eq!(s.flags_after_ns(), TcpFlags::SYN | TcpFlags::ACK);
+        assert_eq!(s.source_port(), local_port);
+        assert_eq!(s.destination_port(), remote_port);
+
+        // A buffer which is too small to hold the IPv6 header.
+        let mut small_buf = [0u8; 10];
+        h.enqueue_rst_config(
+            ConnectionTuple::new(remote_addr.into(), remote_port),
+            h.connections[&ConnectionTuple::new(remote_addr.into(), remote_port)]
+                .connection()
+                .make_rst_config(),
+        );
+        assert_eq!(
+            h.write_next_packet(small_buf.as_mut()).unwrap_err(),
+            WriteNextError::IPv6Packet(IPv6PacketError::SliceTooShort)
+        );
+    }
 }
@@ -8,9 +8,9 @@ use std::sync::{Arc, Mutex, RwLock};
 use std::thread;
 
 use api_server::{ApiRequest, ApiResponse, ApiServer};
-use mmds::MMDS;
+use mmds::data_store::MmdsInstances;
 use polly::event_manager::{EventManager, Subscriber};
-use seccomp::BpfProgram;
+use seccomp::{BpfProgram, SeccompLevel};
 use utils::epoll::{EpollEvent, EventSet};
 use utils::eventfd::EventFd;
 use vmm::rpc_interface::{PrebootApiController, RuntimeApiController};
@@ -90,7 +90,8 @@ impl Subscriber for ApiServerAdapter {
 }
 
 pub fn run_with_api(
-    seccomp_filter: BpfProgram,
+    seccomp_level: SeccompLevel,
+    api_seccomp_filter: BpfProgram,
     config_json: Option<String>,
     bind_path: PathBuf,
     instance_info: InstanceInfo,
@@ -105,15 +106,15 @@ pub fn run_with_api(
     let (to_vmm, from_api) = channel();
     let (to_api, from_vmm) = channel();
 
-    // MMDS only supported with API.
-    let mmds_info = MMDS.clone();
+    // The MMDS contents are managed through the API, and served to the guest by the Vmm.
+    let mmds = MmdsInstances::default();
+    let mmds_info = mmds.clone();
     let api_shared_info = Arc::new(RwLock::new(instance_info.clone()));
     let vmm_shared_info = api_shared_info.clone();
     let to_vmm_event_fd = api_event_fd
         .try_clone()
         .expect("Failed to clone API event FD");
 
-    let api_seccomp_filter = seccomp_filter.clone();
     // Start the separate API thread.
     thread::Builder::new()
         .name("fc_api".to_owned())
@@ -160,11 +161,15 @@ pub fn run_with_api(
 
     // Configure, build and start the microVM.
     let (vm_resources, vmm) = match config_json {
-        Some(json) => {
-            super::build_microvm_from_json(seccomp_filter, &mut event_manager, json, &instance_info)
-        }
+        Some(json) => super::build_microvm_from_json(
+            seccomp_level,
+            &mut event_manager,
+            json,
+            &instance_info,
+            mmds,
+        ),
         None => PrebootApiController::build_microvm_from_requests(
-            seccomp_filter,
+            seccomp_level,
             &mut event_manager,
             instance_info,
             || {
@@ -183,6 +188,7 @@ pub fn run_with_api(
                     .send(Box::new(response))
                     .expect("one-shot channel closed")
             },
+            mmds,
         ),
     };
 
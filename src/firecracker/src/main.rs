@@ -26,8 +26,9 @@ use std::process;
 use std::sync::{Arc, Mutex};
 
 use logger::{Metric, LOGGER, METRICS};
+use mmds::data_store::MmdsInstances;
 use polly::event_manager::EventManager;
-use seccomp::{BpfProgram, SeccompLevel};
+use seccomp::SeccompLevel;
 use utils::arg_parser::{ArgParser, Argument};
 use utils::terminal::Terminal;
 use utils::validators::validate_instance_id;
@@ -212,12 +213,12 @@ fn main() {
 
     // It's safe to unwrap here because the field's been provided with a default value.
     let seccomp_level = arguments.value_as_string("seccomp-level").unwrap();
-    let seccomp_filter = get_seccomp_filter(
-        SeccompLevel::from_string(seccomp_level).unwrap_or_else(|err| {
-            panic!("Invalid value for seccomp-level: {}", err);
-        }),
-    )
-    .unwrap_or_else(|err| {
+    let seccomp_level = SeccompLevel::from_string(seccomp_level).unwrap_or_else(|err| {
+        panic!("Invalid value for seccomp-level: {}", err);
+    });
+    // This filter is loaded on the API thread. The VMM and vCPU threads build theirs along
+    // with the microVM, since the VMM thread filter depends on its configuration.
+    let seccomp_filter = get_seccomp_filter(seccomp_level).unwrap_or_else(|err| {
         panic!("Could not create seccomp filter: {}", err);
     });
 
@@ -244,6 +245,7 @@ fn main() {
                 .expect("'start-time-cpu-us' parameter expected to be of 'u64' type.")
         });
         api_server_adapter::run_with_api(
+            seccomp_level,
             seccomp_filter,
             vmm_config_json,
             bind_path,
@@ -252,25 +254,28 @@ fn main() {
             start_time_cpu_us,
         );
     } else {
-        run_without_api(seccomp_filter, vmm_config_json, &instance_info);
+        run_without_api(seccomp_level, vmm_config_json, &instance_info);
     }
 }
 
-// Configure and start a microVM as described by the command-line JSON.
+// Configure and start a microVM as described by the command-line JSON, serving it the
+// contents of the `mmds` instances.
 fn build_microvm_from_json(
-    seccomp_filter: BpfProgram,
+    seccomp_level: SeccompLevel,
     event_manager: &mut EventManager,
     config_json: String,
     instance_info: &InstanceInfo,
+    mmds: MmdsInstances,
 ) -> (VmResources, Arc<Mutex<vmm::Vmm>>) {
-    let vm_resources = VmResources::from_json(&config_json, instance_info).unwrap_or_else(|err| {
-        error!(
-            "Configuration for VMM from one single json failed: {:?}",
-            err
-        );
-        process::exit(i32::from(vmm::FC_EXIT_CODE_BAD_CONFIGURATION));
-    });
-    let vmm = vmm::builder::build_microvm(&vm_resources, event_manager, &seccomp_filter)
+    let vm_resources =
+        VmResources::from_json(&config_json, instance_info, mmds).unwrap_or_else(|err| {
+            error!(
+                "Configuration for VMM from one single json failed: {:?}",
+                err
+            );
+            process::exit(i32::from(vmm::FC_EXIT_CODE_BAD_CONFIGURATION));
+        });
+    let vmm = vmm::builder::build_microvm(&vm_resources, event_manager, seccomp_level)
         .unwrap_or_else(|err| {
             error!(
                 "Building VMM configured from cmdline json failed: {:?}",
@@ -284,7 +289,7 @@ fn build_microvm_from_json(
 }
 
 fn run_without_api(
-    seccomp_filter: BpfProgram,
+    seccomp_level: SeccompLevel,
     config_json: Option<String>,
     instance_info: &InstanceInfo,
 ) {
@@ -300,11 +305,13 @@ fn run_without_api(
     // - VmResources is not used without api,
     // - An `Arc` reference of the built `Vmm` is plugged in the `EventManager` by the builder.
     build_microvm_from_json(
-        seccomp_filter,
+        seccomp_level,
         &mut event_manager,
         // Safe to unwrap since '--no-api' requires this to be set.
         config_json.unwrap(),
         instance_info,
+        // The MMDS contents can't be set without the API, so they stay empty.
+        MmdsInstances::default(),
     );
 
     // Start the metrics.
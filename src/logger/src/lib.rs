@@ -20,7 +20,7 @@ mod metrics;
 pub use log::Level::*;
 pub use log::*;
 pub use logger::{LoggerError, LOGGER};
-pub use metrics::{Metric, MetricsError, METRICS};
+pub use metrics::{Metric, MetricsError, NetInterfaceMetrics, VsockPortMetrics, METRICS};
 
 use std::sync::LockResult;
 
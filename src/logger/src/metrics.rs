@@ -56,11 +56,12 @@
 //! something else, while working behind the same interface.
 
 use std;
+use std::collections::BTreeMap;
 use std::fmt;
 use std::io::Write;
 use std::ops::Deref;
 use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
-use std::sync::Mutex;
+use std::sync::{Arc, Mutex, RwLock};
 
 use serde::{Serialize, Serializer};
 
@@ -239,6 +240,30 @@ impl Serialize for SharedMetric {
     }
 }
 
+/// Representation of a metric which records the latest value of a quantity, such as a size,
+/// rather than counting events.
+#[derive(Default)]
+pub struct SharedStoreMetric(AtomicUsize);
+
+impl SharedStoreMetric {
+    /// Records `value` as the current value of the metric.
+    pub fn store(&self, value: usize) {
+        self.0.store(value, Ordering::Relaxed);
+    }
+
+    /// Returns the current value of the metric.
+    pub fn fetch(&self) -> usize {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl Serialize for SharedStoreMetric {
+    /// Unlike counters, the value is not reset when the metrics are flushed.
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(self.fetch() as u64)
+    }
+}
+
 // The following structs are used to define a certain organization for the set of metrics we
 // are interested in. Whenever the name of a field differs from its ideal textual representation
 // in the serialized form, we can use the #[serde(rename = "name")] attribute to, well, rename it.
@@ -259,6 +284,10 @@ pub struct ApiServerMetrics {
 /// Metrics specific to GET API Requests for counting user triggered actions and/or failures.
 #[derive(Default, Serialize)]
 pub struct GetRequestsMetrics {
+    /// Number of GETs for getting the statistics of a block device.
+    pub drive_count: SharedMetric,
+    /// Number of failures during GETs for getting the statistics of a block device.
+    pub drive_fails: SharedMetric,
     /// Number of GETs for getting information on the instance.
     pub instance_info_count: SharedMetric,
     /// Number of failures when obtaining information on the current instance.
@@ -267,6 +296,10 @@ pub struct GetRequestsMetrics {
     pub machine_cfg_count: SharedMetric,
     /// Number of failures during GETs for getting information on the instance.
     pub machine_cfg_fails: SharedMetric,
+    /// Number of GETs for getting the statistics of a network interface.
+    pub network_count: SharedMetric,
+    /// Number of failures during GETs for getting the statistics of a network interface.
+    pub network_fails: SharedMetric,
 }
 
 /// Metrics specific to PUT API Requests for counting user triggered actions and/or failures.
@@ -300,6 +333,10 @@ pub struct PutRequestsMetrics {
     pub network_count: SharedMetric,
     /// Number of failures in creating a new network interface.
     pub network_fails: SharedMetric,
+    /// Number of PUTs for creating a rate limiter group.
+    pub rate_limiter_group_count: SharedMetric,
+    /// Number of failures in creating a rate limiter group.
+    pub rate_limiter_group_fails: SharedMetric,
 }
 
 /// Metrics specific to PATCH API Requests for counting user triggered actions and/or failures.
@@ -397,6 +434,10 @@ pub struct MmdsMetrics {
     pub rx_bad_eth: SharedMetric,
     /// The total number of successful receive operations by the MMDS.
     pub rx_count: SharedMetric,
+    /// The number of metadata requests rejected for presenting an invalid or expired token.
+    pub rx_invalid_token: SharedMetric,
+    /// The number of metadata requests rejected for not presenting a token, when one is required.
+    pub rx_no_token: SharedMetric,
     /// The total number of bytes sent by the MMDS.
     pub tx_bytes: SharedMetric,
     /// The total number of successful send operations by the MMDS.
@@ -409,6 +450,8 @@ pub struct MmdsMetrics {
     pub connections_created: SharedMetric,
     /// The number of connections cleaned up by the MMDS TCP handler.
     pub connections_destroyed: SharedMetric,
+    /// The serialized size of the MMDS data store, in bytes.
+    pub data_store_size: SharedStoreMetric,
 }
 
 /// Network-related metrics.
@@ -454,6 +497,65 @@ pub struct NetDeviceMetrics {
     pub tx_spoofed_mac_count: SharedMetric,
 }
 
+/// Metrics for a single network interface.
+#[derive(Default, Serialize)]
+pub struct NetInterfaceMetrics {
+    /// Number of bytes received.
+    pub rx_bytes_count: SharedMetric,
+    /// Number of packets received.
+    pub rx_packets_count: SharedMetric,
+    /// Number of errors while receiving data.
+    pub rx_fails: SharedMetric,
+    /// Number of transmitted bytes.
+    pub tx_bytes_count: SharedMetric,
+    /// Number of transmitted packets.
+    pub tx_packets_count: SharedMetric,
+    /// Number of frames dropped while transmitting data.
+    pub tx_fails: SharedMetric,
+    /// Number of frames sent by the guest which were detoured to the MMDS.
+    pub mmds_rx_frames: SharedMetric,
+    /// Number of frames written to the guest by the MMDS.
+    pub mmds_tx_frames: SharedMetric,
+}
+
+/// Per interface network metrics, keyed by the interface id.
+#[derive(Default)]
+pub struct NetInterfacesMetrics(RwLock<BTreeMap<String, Arc<NetInterfaceMetrics>>>);
+
+impl NetInterfacesMetrics {
+    /// Returns the metrics of the interface with the given id, creating them if they
+    /// do not exist yet.
+    pub fn get_or_create(&self, iface_id: &str) -> Arc<NetInterfaceMetrics> {
+        if let Some(metrics) = self.get(iface_id) {
+            return metrics;
+        }
+        extract_guard(self.0.write())
+            .entry(iface_id.to_string())
+            .or_insert_with(|| Arc::new(NetInterfaceMetrics::default()))
+            .clone()
+    }
+
+    /// Returns the metrics of the interface with the given id, if any.
+    pub fn get(&self, iface_id: &str) -> Option<Arc<NetInterfaceMetrics>> {
+        extract_guard(self.0.read()).get(iface_id).cloned()
+    }
+
+    /// Removes the metrics of the interface with the given id, which are no longer reported.
+    pub fn remove(&self, iface_id: &str) {
+        extract_guard(self.0.write()).remove(iface_id);
+    }
+}
+
+impl Serialize for NetInterfacesMetrics {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(
+            extract_guard(self.0.read())
+                .iter()
+                .map(|(iface_id, metrics)| (iface_id, metrics.as_ref())),
+        )
+    }
+}
+
 /// Metrics specific to the i8042 device.
 #[derive(Default, Serialize)]
 pub struct RTCDeviceMetrics {
@@ -515,6 +617,100 @@ pub struct VmmMetrics {
     pub panic_count: SharedMetric,
 }
 
+/// Vsock-related metrics.
+#[derive(Default, Serialize)]
+pub struct VsockDeviceMetrics {
+    /// Number of times when activate failed on a vsock device.
+    pub activate_fails: SharedMetric,
+    /// Number of times when interacting with the space config of a vsock device failed.
+    pub cfg_fails: SharedMetric,
+    /// Number of times when handling RX queue events on a vsock device failed.
+    pub rx_queue_event_fails: SharedMetric,
+    /// Number of times when handling TX queue events on a vsock device failed.
+    pub tx_queue_event_fails: SharedMetric,
+    /// Number of times when handling event queue events on a vsock device failed.
+    pub ev_queue_event_fails: SharedMetric,
+    /// Number of packets delivered to the guest.
+    pub rx_packets_count: SharedMetric,
+    /// Number of data bytes delivered to the guest.
+    pub rx_bytes_count: SharedMetric,
+    /// Number of invalid RX buffers provided by the guest.
+    pub rx_fails: SharedMetric,
+    /// Number of packets sent by the guest.
+    pub tx_packets_count: SharedMetric,
+    /// Number of data bytes sent by the guest.
+    pub tx_bytes_count: SharedMetric,
+    /// Number of invalid TX packets sent by the guest.
+    pub tx_fails: SharedMetric,
+    /// Number of connections added to the connection pool.
+    pub conns_accepted: SharedMetric,
+    /// Number of connection attempts that were refused.
+    pub conns_rejected: SharedMetric,
+    /// Number of connections that were forcefully terminated (VSOCK_OP_RST).
+    pub conns_reset: SharedMetric,
+    /// Number of times a connection ran out of guest buffer space (peer credit).
+    pub conns_credit_starved: SharedMetric,
+    /// Number of times the muxer RX queue lost track of the connections with pending RX data.
+    pub muxer_rxq_sync_lost: SharedMetric,
+    /// Number of times the muxer kill queue lost track of the connections awaiting termination.
+    pub muxer_killq_sync_lost: SharedMetric,
+    /// Number of connections killed by the muxer kill queue, after their shutdown timed out.
+    pub muxer_killq_evictions: SharedMetric,
+    /// Number of transport reset events delivered to the guest.
+    pub transport_resets: SharedMetric,
+}
+
+/// Metrics for the vsock connections of a single port.
+#[derive(Default, Serialize)]
+pub struct VsockPortMetrics {
+    /// Number of packets delivered to the guest.
+    pub rx_packets_count: SharedMetric,
+    /// Number of data bytes delivered to the guest.
+    pub rx_bytes_count: SharedMetric,
+    /// Number of packets sent by the guest.
+    pub tx_packets_count: SharedMetric,
+    /// Number of data bytes sent by the guest.
+    pub tx_bytes_count: SharedMetric,
+    /// Number of connections added to the connection pool.
+    pub conns_accepted: SharedMetric,
+    /// Number of connection attempts that were refused.
+    pub conns_rejected: SharedMetric,
+    /// Number of connections that were forcefully terminated (VSOCK_OP_RST).
+    pub conns_reset: SharedMetric,
+}
+
+/// Per port vsock metrics, keyed by the port number.
+#[derive(Default)]
+pub struct VsockPortsMetrics(RwLock<BTreeMap<u32, Arc<VsockPortMetrics>>>);
+
+impl VsockPortsMetrics {
+    /// Returns the metrics of the given port, creating them if they do not exist yet.
+    pub fn get_or_create(&self, port: u32) -> Arc<VsockPortMetrics> {
+        if let Some(metrics) = self.get(port) {
+            return metrics;
+        }
+        extract_guard(self.0.write())
+            .entry(port)
+            .or_insert_with(|| Arc::new(VsockPortMetrics::default()))
+            .clone()
+    }
+
+    /// Returns the metrics of the given port, if any.
+    pub fn get(&self, port: u32) -> Option<Arc<VsockPortMetrics>> {
+        extract_guard(self.0.read()).get(&port).cloned()
+    }
+}
+
+impl Serialize for VsockPortsMetrics {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(
+            extract_guard(self.0.read())
+                .iter()
+                .map(|(port, metrics)| (port, metrics.as_ref())),
+        )
+    }
+}
+
 /// Metrics related to signals.
 #[derive(Default, Serialize)]
 pub struct SignalMetrics {
@@ -554,6 +750,8 @@ pub struct FirecrackerMetrics {
     pub mmds: MmdsMetrics,
     /// A network device's related metrics.
     pub net: NetDeviceMetrics,
+    /// Metrics of each network interface, keyed by the interface id.
+    pub net_interfaces: NetInterfacesMetrics,
     /// Metrics related to API PATCH requests.
     pub patch_api_requests: PatchRequestsMetrics,
     /// Metrics related to API PUT requests.
@@ -566,6 +764,11 @@ pub struct FirecrackerMetrics {
     pub vcpu: VcpuMetrics,
     /// Metrics related to the virtual machine manager.
     pub vmm: VmmMetrics,
+    /// Metrics related to the vsock device.
+    pub vsock: VsockDeviceMetrics,
+    /// Metrics of the vsock connections of each port, keyed by the port number. These are only
+    /// recorded if enabled in the vsock device configuration.
+    pub vsock_ports: VsockPortsMetrics,
     /// Metrics related to the UART device.
     pub uart: SerialDeviceMetrics,
     /// Metrics related to signals.
@@ -637,12 +840,74 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_store_metric() {
+        let m = SharedStoreMetric::default();
+        assert_eq!(m.fetch(), 0);
+        m.store(100);
+        m.store(42);
+        assert_eq!(m.fetch(), 42);
+
+        // Flushing the metric doesn't reset its value.
+        assert_eq!(serde_json::to_string(&m).unwrap(), "42");
+        assert_eq!(serde_json::to_string(&m).unwrap(), "42");
+    }
+
     #[test]
     fn test_serialize() {
         let s = serde_json::to_string(&FirecrackerMetrics::default());
         assert!(s.is_ok());
     }
 
+    #[test]
+    fn test_net_interfaces_metrics() {
+        let net_interfaces = NetInterfacesMetrics::default();
+        assert!(net_interfaces.get("eth0").is_none());
+
+        let eth0 = net_interfaces.get_or_create("eth0");
+        eth0.rx_bytes_count.add(100);
+        eth0.tx_packets_count.inc();
+        // The same instance is handed out for the same interface id.
+        assert!(Arc::ptr_eq(&eth0, &net_interfaces.get_or_create("eth0")));
+        assert!(Arc::ptr_eq(&eth0, &net_interfaces.get("eth0").unwrap()));
+        net_interfaces.get_or_create("eth1");
+
+        let json: serde_json::Value = serde_json::to_value(&net_interfaces).unwrap();
+        assert_eq!(json["eth0"]["rx_bytes_count"], 100);
+        assert_eq!(json["eth0"]["tx_packets_count"], 1);
+        assert_eq!(json["eth1"]["rx_bytes_count"], 0);
+
+        // Serialization only flushes the delta, the absolute count is preserved.
+        let json: serde_json::Value = serde_json::to_value(&net_interfaces).unwrap();
+        assert_eq!(json["eth0"]["rx_bytes_count"], 0);
+        assert_eq!(eth0.rx_bytes_count.count(), 100);
+
+        // The metrics of removed interfaces are no longer reported.
+        net_interfaces.remove("eth1");
+        assert!(net_interfaces.get("eth1").is_none());
+        let json: serde_json::Value = serde_json::to_value(&net_interfaces).unwrap();
+        assert!(json.get("eth1").is_none());
+        assert!(json.get("eth0").is_some());
+    }
+
+    #[test]
+    fn test_vsock_ports_metrics() {
+        let vsock_ports = VsockPortsMetrics::default();
+        assert!(vsock_ports.get(52).is_none());
+
+        let port = vsock_ports.get_or_create(52);
+        port.rx_bytes_count.add(100);
+        port.conns_accepted.inc();
+        // The same instance is handed out for the same port.
+        assert!(Arc::ptr_eq(&port, &vsock_ports.get_or_create(52)));
+        assert!(Arc::ptr_eq(&port, &vsock_ports.get(52).unwrap()));
+
+        let json: serde_json::Value = serde_json::to_value(&vsock_ports).unwrap();
+        assert_eq!(json["52"]["rx_bytes_count"], 100);
+        assert_eq!(json["52"]["conns_accepted"], 1);
+        assert_eq!(json["52"]["tx_bytes_count"], 0);
+    }
+
     #[test]
     fn test_error_messages() {
         assert_eq!(
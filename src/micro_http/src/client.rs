@@ -0,0 +1,475 @@
+// Copyright 2020 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use common::ascii::{CR, CRLF_LEN, LF};
+pub use common::ClientError;
+use common::{Body, Method, Version};
+use request::{find, Request};
+use response::{Response, StatusCode};
+
+const BUFFER_SIZE: usize = 1024;
+
+/// A client which sends HTTP requests over a Unix domain socket, such as the one of the
+/// Firecracker API server, and waits for their responses.
+///
+/// The requests are sent one at a time, on the same connection. The bodies of the responses
+/// are delimited by their `Content-Length`.
+///
+/// Servers may close idle connections, like the Firecracker API server does after 60 seconds
+/// of inactivity. When the client was created through `connect()`, the `GET`, `HEAD` and `PUT`
+/// requests which find the connection closed before any part of their response arrived are
+/// sent again on a new connection. The other requests fail with `ConnectionClosed` (or with
+/// a broken pipe `StreamError`), in which case `reconnect()` can be called before retrying
+/// them.
+///
+/// # Examples
+///
+/// ```no_run
+/// extern crate micro_http;
+/// use micro_http::{HttpClient, StatusCode};
+///
+/// let mut client = HttpClient::connect("/tmp/firecracker.socket").unwrap();
+/// let response = client.get("/machine-config").unwrap();
+/// assert_eq!(response.status(), StatusCode::OK);
+/// ```
+pub struct HttpClient {
+    /// The connection to the server.
+    stream: UnixStream,
+    /// The socket the server listens on, if known, used for reconnecting.
+    path_to_socket: Option<PathBuf>,
+    /// Bytes received from the server which are not part of a returned response yet.
+    buffer: Vec<u8>,
+}
+
+impl HttpClient {
+    /// Connects to the server listening on the Unix domain socket at `path_to_socket`.
+    ///
+    /// # Errors
+    /// Returns a `StreamError` when the connection can't be established.
+    pub fn connect<P: AsRef<Path>>(path_to_socket: P) -> Result<Self, ClientError> {
+        let stream =
+            UnixStream::connect(path_to_socket.as_ref()).map_err(ClientError::StreamError)?;
+        let mut client = Self::new(stream);
+        client.path_to_socket = Some(path_to_socket.as_ref().to_path_buf());
+        Ok(client)
+    }
+
+    /// Creates a new `HttpClient` which uses an already open connection. Such a client
+    /// can't reconnect.
+    pub fn new(stream: UnixStream) -> Self {
+        Self {
+            stream,
+            path_to_socket: None,
+            buffer: Vec::new(),
+        }
+    }
+
+    /// Replaces the connection to the server with a new one, keeping its timeouts. The bytes
+    /// received over the old connection which are not part of a returned response are
+    /// discarded.
+    ///
+    /// # Errors
+    /// `CannotReconnect` is returned when the client was not created through `connect()`.
+    /// `StreamError` is returned when the new connection can't be established.
+    pub fn reconnect(&mut self) -> Result<(), ClientError> {
+        let path_to_socket = self
+            .path_to_socket
+            .as_ref()
+            .ok_or(ClientError::CannotReconnect)?;
+        let stream = UnixStream::connect(path_to_socket).map_err(ClientError::StreamError)?;
+        self.stream
+            .read_timeout()
+            .and_then(|timeout| stream.set_read_timeout(timeout))
+            .and_then(|_| self.stream.write_timeout())
+            .and_then(|timeout| stream.set_write_timeout(timeout))
+            .map_err(ClientError::StreamError)?;
+        self.stream = stream;
+        self.buffer.clear();
+        Ok(())
+    }
+
+    /// Sets how long sending a request and receiving each part of its response can
+    /// block. There is no timeout by default.
+    ///
+    /// # Errors
+    /// Returns a `StreamError` when the timeout can't be set, for instance because it
+    /// is zero.
+    pub fn set_timeout(&self, timeout: Option<Duration>) -> Result<(), ClientError> {
+        self.stream
+            .set_read_timeout(timeout)
+            .and_then(|_| self.stream.set_write_timeout(timeout))
+            .map_err(ClientError::StreamError)
+    }
+
+    /// Sends a `GET` request for `uri`, and returns its response.
+    pub fn get(&mut self, uri: &str) -> Result<Response, ClientError> {
+        self.send_request(&Request::new(Method::Get, uri, Version::Http11))
+    }
+
+    /// Sends a `PUT` request with the specified `body` for `uri`, and returns its response.
+    pub fn put(&mut self, uri: &str, body: Body) -> Result<Response, ClientError> {
+        let mut request = Request::new(Method::Put, uri, Version::Http11);
+        request.set_body(body);
+        self.send_request(&request)
+    }
+
+    /// Sends a `PATCH` request with the specified `body` for `uri`, and returns its response.
+    pub fn patch(&mut self, uri: &str, body: Body) -> Result<Response, ClientError> {
+        let mut request = Request::new(Method::Patch, uri, Version::Http11);
+        request.set_body(body);
+        self.send_request(&request)
+    }
+
+    /// Sends `request` to the server and waits for its response. The interim `100 Continue`
+    /// responses are skipped. `GET`, `HEAD` and `PUT` requests are sent again on a new
+    /// connection when the server closed the connection before answering them, if the
+    /// client can reconnect.
+    ///
+    /// # Errors
+    /// `StreamError` is returned when the request can't be sent, or the response can't be
+    /// received, for instance because the timeout elapsed.
+    /// `ConnectionClosed` is returned when the server closes the connection before sending
+    /// the whole response.
+    /// `ParseError` is returned when the response is invalid.
+    pub fn send_request(&mut self, request: &Request) -> Result<Response, ClientError> {
+        match self.try_send_request(request) {
+            Err(ref e) if self.can_resend(request.method(), e) => {
+                self.reconnect()?;
+                self.try_send_request(request)
+            }
+            result => result,
+        }
+    }
+
+    // Whether a request which failed with `error` can be sent again on a new connection. This
+    // is the case for the idempotent requests which found the connection closed before any part
+    // of their response arrived, meaning that the server did not process them.
+    fn can_resend(&self, method: Method, error: &ClientError) -> bool {
+        let connection_closed = match error {
+            ClientError::ConnectionClosed => true,
+            ClientError::StreamError(e) => {
+                e.kind() == ErrorKind::BrokenPipe || e.kind() == ErrorKind::ConnectionReset
+            }
+            _ => false,
+        };
+        let idempotent = match method {
+            Method::Get | Method::Head | Method::Put => true,
+            _ => false,
+        };
+        connection_closed && idempotent && self.buffer.is_empty() && self.path_to_socket.is_some()
+    }
+
+    fn try_send_request(&mut self, request: &Request) -> Result<Response, ClientError> {
+        let mut request_bytes = vec![];
+        request
+            .write_all(&mut request_bytes)
+            .map_err(ClientError::StreamError)?;
+        self.stream
+            .write_all(&request_bytes)
+            .map_err(ClientError::StreamError)?;
+
+        loop {
+            let response = self.read_response(request.method())?;
+            if response.status() != StatusCode::Continue {
+                return Ok(response);
+            }
+        }
+    }
+
+    // Reads the next response from the connection. The responses to `HEAD` requests
+    // don't have a body, regardless of their `Content-Length`.
+    fn read_response(&mut self, method: Method) -> Result<Response, ClientError> {
+        let headers_end = loop {
+            if let Some(headers_end) = find(&self.buffer, &[CR, LF, CR, LF]) {
+                break headers_end + 2 * CRLF_LEN;
+            }
+            self.fill_buffer()?;
+        };
+        let response =
+            Response::try_from(&self.buffer[..headers_end]).map_err(ClientError::ParseError)?;
+
+        let body_len = match response.status() {
+            StatusCode::Continue | StatusCode::NoContent => 0,
+            _ if method == Method::Head => 0,
+            _ => response.content_length() as usize,
+        };
+        if body_len == 0 {
+            self.buffer.drain(..headers_end);
+            return Ok(response);
+        }
+
+        while self.buffer.len() < headers_end + body_len {
+            self.fill_buffer()?;
+        }
+        let response = Response::try_from(&self.buffer[..(headers_end + body_len)])
+            .map_err(ClientError::ParseError)?;
+        self.buffer.drain(..(headers_end + body_len));
+        Ok(response)
+    }
+
+    // Appends the bytes which can be read from the connection to the buffer.
+    fn fill_buffer(&mut self) -> Result<(), ClientError> {
+        let mut bytes = [0; BUFFER_SIZE];
+        match self.stream.read(&mut bytes) {
+            Ok(0) => Err(ClientError::ConnectionClosed),
+            Ok(bytes_read) => {
+                self.buffer.extend_from_slice(&bytes[..bytes_read]);
+                Ok(())
+            }
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => Ok(()),
+            Err(e) => Err(ClientError::StreamError(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Shutdown;
+    use std::thread;
+
+    use common::ResponseError;
+    use server::HttpServer;
+    use utils::tempfile::TempFile;
+
+    // Returns the bytes of the requests which were sent to `server_stream`.
+    fn read_requests(server_stream: &mut UnixStream) -> Vec<u8> {
+        server_stream
+            .set_read_timeout(Some(Duration::from_millis(100)))
+            .unwrap();
+        let mut requests = vec![];
+        let mut buf = [0; BUFFER_SIZE];
+        while let Ok(len) = server_stream.read(&mut buf) {
+            if len == 0 {
+                break;
+            }
+            requests.extend_from_slice(&buf[..len]);
+        }
+        requests
+    }
+
+    #[test]
+    fn test_send_request() {
+        let (client_stream, mut server_stream) = UnixStream::pair().unwrap();
+        let mut client = HttpClient::new(client_stream);
+
+        // The responses can be received before the requests are sent.
+        server_stream
+            .write_all(
+                b"HTTP/1.1 200 \r\n\
+                  Server: Firecracker API\r\n\
+                  Connection: keep-alive\r\n\
+                  Content-Type: application/json\r\n\
+                  Content-Length: 14\r\n\r\n\
+                  { \"id\": \"vm\" }\
+                  HTTP/1.1 204 \r\n\r\n",
+            )
+            .unwrap();
+
+        let response = client.get("/").unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.body().unwrap(), Body::new("{ \"id\": \"vm\" }"));
+
+        let response = client
+            .put(
+                "/actions",
+                Body::new("{ \"action_type\": \"InstanceStart\" }"),
+            )
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NoContent);
+        assert!(response.body().is_none());
+
+        assert_eq!(
+            &read_requests(&mut server_stream)[..],
+            &b"GET / HTTP/1.1\r\n\r\n\
+               PUT /actions HTTP/1.1\r\n\
+               Content-Length: 34\r\n\r\n\
+               { \"action_type\": \"InstanceStart\" }"[..]
+        );
+    }
+
+    #[test]
+    fn test_send_request_partial_response() {
+        let (client_stream, mut server_stream) = UnixStream::pair().unwrap();
+        let mut client = HttpClient::new(client_stream);
+
+        let response_bytes = b"HTTP/1.1 400 \r\n\
+                               Content-Length: 32\r\n\r\n\
+                               { \"fault_message\": \"Bad input\" }";
+        let server_thread = thread::spawn(move || {
+            // The interim responses are skipped.
+            server_stream
+                .write_all(b"HTTP/1.1 100 Continue\r\n\r\n")
+                .unwrap();
+            for byte in response_bytes.iter() {
+                server_stream.write_all(&[*byte]).unwrap();
+            }
+            server_stream
+        });
+
+        let response = client
+            .patch("/machine-config", Body::new("{ \"vcpu_count\": 0 }"))
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BadRequest);
+        assert_eq!(
+            response.body().unwrap(),
+            Body::new("{ \"fault_message\": \"Bad input\" }")
+        );
+
+        // The server closes the connection before sending the whole response.
+        let mut server_stream = server_thread.join().unwrap();
+        server_stream
+            .write_all(b"HTTP/1.1 200 \r\nContent-Length: 10\r\n\r\nbody")
+            .unwrap();
+        server_stream.shutdown(Shutdown::Write).unwrap();
+        match client.get("/mmds") {
+            Err(ClientError::ConnectionClosed) => (),
+            _ => panic!("Expected ConnectionClosed."),
+        }
+    }
+
+    #[test]
+    fn test_send_request_errors() {
+        // Invalid responses.
+        let (client_stream, mut server_stream) = UnixStream::pair().unwrap();
+        let mut client = HttpClient::new(client_stream);
+        server_stream.write_all(b"HTTP/1.1 299 \r\n\r\n").unwrap();
+        match client.get("/") {
+            Err(ClientError::ParseError(ResponseError::InvalidStatusCode)) => (),
+            _ => panic!("Expected ParseError."),
+        }
+
+        // Responses which don't arrive in time.
+        let (client_stream, _server_stream) = UnixStream::pair().unwrap();
+        let mut client = HttpClient::new(client_stream);
+        client.set_timeout(Some(Duration::from_millis(10))).unwrap();
+        match client.get("/") {
+            Err(ClientError::StreamError(_)) => (),
+            _ => panic!("Expected StreamError."),
+        }
+        match client.set_timeout(Some(Duration::from_secs(0))) {
+            Err(ClientError::StreamError(_)) => (),
+            _ => panic!("Expected StreamError."),
+        }
+
+        // Clients created from an open connection can't reconnect.
+        let (client_stream, _server_stream) = UnixStream::pair().unwrap();
+        let mut client = HttpClient::new(client_stream);
+        match client.reconnect() {
+            Err(ClientError::CannotReconnect) => (),
+            _ => panic!("Expected CannotReconnect."),
+        }
+
+        // Sockets which nobody listens on.
+        let path_to_socket = TempFile::new().unwrap();
+        match HttpClient::connect(path_to_socket.as_path()) {
+            Err(ClientError::StreamError(_)) => (),
+            _ => panic!("Expected StreamError."),
+        }
+    }
+
+    #[test]
+    fn test_send_request_to_server() {
+        let mut path_to_socket = TempFile::new().unwrap();
+        path_to_socket.remove().unwrap();
+
+        let mut server = HttpServer::new(path_to_socket.as_path()).unwrap();
+        server.start_server().unwrap();
+        let mut client = HttpClient::connect(path_to_socket.as_path()).unwrap();
+
+        let client_thread = thread::spawn(move || {
+            let get_response = client.get("/machine-config").unwrap();
+            let head_response = client
+                .send_request(&Request::new(
+                    Method::Head,
+                    "/machine-config",
+                    Version::Http11,
+                ))
+                .unwrap();
+            (get_response, head_response)
+        });
+
+        let mut served_requests = 0;
+        while served_requests < 2 {
+            for server_request in server.requests().unwrap() {
+                server
+                    .respond(server_request.process(|request| {
+                        assert_eq!(request.uri().get_abs_path(), "/machine-config");
+                        let mut response = Response::new(Version::Http11, StatusCode::OK);
+                        response.set_body(Body::new("{ \"vcpu_count\": 2 }"));
+                        response
+                    }))
+                    .unwrap();
+                served_requests += 1;
+            }
+        }
+        // Flush the responses.
+        server.requests().unwrap();
+
+        let (get_response, head_response) = client_thread.join().unwrap();
+        assert_eq!(get_response.status(), StatusCode::OK);
+        assert_eq!(
+            get_response.body().unwrap(),
+            Body::new("{ \"vcpu_count\": 2 }")
+        );
+        assert_eq!(head_response.status(), StatusCode::OK);
+        assert_eq!(head_response.content_length(), 19);
+        assert!(head_response.body().is_none());
+    }
+
+    #[test]
+    fn test_reconnect() {
+        let mut path_to_socket = TempFile::new().unwrap();
+        path_to_socket.remove().unwrap();
+
+        let mut server = HttpServer::new(path_to_socket.as_path()).unwrap();
+        server.set_idle_timeout(Some(Duration::from_millis(100)));
+        server.start_server().unwrap();
+        let mut client = HttpClient::connect(path_to_socket.as_path()).unwrap();
+        client.set_timeout(Some(Duration::from_secs(5))).unwrap();
+
+        let client_thread = thread::spawn(move || {
+            assert_eq!(client.get("/mmds").unwrap().status(), StatusCode::OK);
+
+            // The server closes the idle connection, so the `GET` request is sent again
+            // on a new one.
+            thread::sleep(Duration::from_millis(300));
+            assert_eq!(client.get("/mmds").unwrap().status(), StatusCode::OK);
+
+            // The `PATCH` request isn't, until the client reconnects.
+            thread::sleep(Duration::from_millis(300));
+            match client.patch("/mmds", Body::new("{}")) {
+                Err(ClientError::ConnectionClosed) | Err(ClientError::StreamError(_)) => (),
+                _ => panic!("Expected ConnectionClosed."),
+            }
+            client.reconnect().unwrap();
+            assert_eq!(
+                client.stream.read_timeout().unwrap(),
+                Some(Duration::from_secs(5))
+            );
+            client.patch("/mmds", Body::new("{}")).unwrap().status()
+        });
+
+        let mut served_requests = 0;
+        while served_requests < 3 {
+            for server_request in server.requests().unwrap() {
+                server
+                    .respond(server_request.process(|request| match request.method() {
+                        Method::Get => Response::new(Version::Http11, StatusCode::OK),
+                        _ => Response::new(Version::Http11, StatusCode::NoContent),
+                    }))
+                    .unwrap();
+                served_requests += 1;
+            }
+        }
+        // Flush the response.
+        server.requests().unwrap();
+
+        assert_eq!(client_thread.join().unwrap(), StatusCode::NoContent);
+    }
+}
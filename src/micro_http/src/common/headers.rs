@@ -1,8 +1,11 @@
 // Copyright 2018 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
+use std::collections::HashMap;
+use std::io::{Error as WriteError, Write};
 use std::result::Result;
 
+use ascii::{COLON, CR, LF, SP};
 use RequestError;
 
 /// Wrapper over an HTTP Header type.
@@ -66,8 +69,8 @@ impl Header {
 /// in plain text actually and don't influence our parsing process.
 ///
 /// All the other possible header fields are not necessary in order to serve this connection
-/// and, thus, are not of interest to us. However, we still look for header fields that might
-/// invalidate our request as we don't support the full set of HTTP/1.1 specification.
+/// and, thus, are only stored as custom entries. However, we still look for header fields that
+/// might invalidate our request as we don't support the full set of HTTP/1.1 specification.
 /// Such header entries are "Transfer-Encoding: identity; q=0", which means a compression
 /// algorithm is applied to the body of the request, or "Expect: 103-checkpoint".
 #[derive(Debug)]
@@ -87,6 +90,9 @@ pub struct Headers {
     /// `Accept` header might be used by HTTP clients to enforce server responses with content
     /// formatted in a specific way.
     accept: MediaType,
+    /// Header fields which are not interpreted by the parser, indexed by their lowercase name.
+    /// These are left for the request handlers to make sense of.
+    custom_entries: HashMap<String, String>,
 }
 
 impl Default for Headers {
@@ -99,6 +105,7 @@ impl Default for Headers {
             // The default `Accept` media type is plain text. This is inclusive enough
             // for structured and unstructured text.
             accept: MediaType::PlainText,
+            custom_entries: HashMap::new(),
         }
     }
 }
@@ -172,7 +179,11 @@ impl Headers {
                         Header::Server => Ok(()),
                     }
                 } else {
-                    Err(RequestError::UnsupportedHeader)
+                    self.custom_entries.insert(
+                        entry[0].trim().to_ascii_lowercase(),
+                        entry[1].trim().to_string(),
+                    );
+                    Ok(())
                 }
             }
             _ => Err(RequestError::InvalidHeader),
@@ -207,9 +218,8 @@ impl Headers {
     ///     * Request Header Lines "<header_line> CRLF"- Optional </br>
     /// There can be any number of request headers, including none, followed by
     /// an extra sequence of Carriage Return and Line Feed.
-    /// All header fields are parsed. However, only the ones present in the
-    /// [`Headers`](struct.Headers.html) struct are relevant to us and stored
-    /// for future use.
+    /// All header fields are parsed. The ones known to the parser are interpreted,
+    /// while the others are stored as custom entries for future use.
     ///
     /// # Errors
     /// The function returns `InvalidHeader` when parsing the byte stream fails.
@@ -242,10 +252,71 @@ impl Headers {
         Err(RequestError::InvalidRequest)
     }
 
+    /// Returns the value of the header field called `name`, if the field was not interpreted by
+    /// the parser. Header field names are case-insensitive.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate micro_http;
+    /// use micro_http::Headers;
+    ///
+    /// let request_headers = Headers::try_from(b"X-Custom-Header: value\r\n\r\n").unwrap();
+    /// assert_eq!(request_headers.custom_entry("x-custom-header"), Some("value"));
+    /// assert_eq!(request_headers.custom_entry("Content-Length"), None);
+    /// ```
+    pub fn custom_entry(&self, name: &str) -> Option<&str> {
+        self.custom_entries
+            .get(&name.to_ascii_lowercase())
+            .map(String::as_str)
+    }
+
     /// Accept header setter.
     pub fn set_accept(&mut self, media_type: MediaType) {
         self.accept = media_type;
     }
+
+    /// Sets the content length of the body.
+    pub fn set_content_length(&mut self, content_length: i32) {
+        self.content_length = content_length;
+    }
+
+    /// Sets the value of the header field called `name`, which is not interpreted by the
+    /// parser. Header field names are case-insensitive.
+    pub fn set_custom_entry(&mut self, name: &str, value: &str) {
+        self.custom_entries
+            .insert(name.to_ascii_lowercase(), String::from(value));
+    }
+
+    /// Writes the headers to `buf` using the HTTP specification, so that the request they
+    /// belong to can be sent.
+    ///
+    /// The `Accept` header field is written unless it holds the default `text/plain`, and the
+    /// `Content-Length` header field is written unless it is `0`.
+    pub fn write_all<T: Write>(&self, buf: &mut T) -> Result<(), WriteError> {
+        if self.accept != MediaType::PlainText {
+            buf.write_all(Header::Accept.raw())?;
+            buf.write_all(&[COLON, SP])?;
+            buf.write_all(self.accept.as_str().as_bytes())?;
+            buf.write_all(&[CR, LF])?;
+        }
+
+        for (name, value) in &self.custom_entries {
+            buf.write_all(name.as_bytes())?;
+            buf.write_all(&[COLON, SP])?;
+            buf.write_all(value.as_bytes())?;
+            buf.write_all(&[CR, LF])?;
+        }
+
+        if self.content_length != 0 {
+            buf.write_all(Header::ContentLength.raw())?;
+            buf.write_all(&[COLON, SP])?;
+            buf.write_all(self.content_length.to_string().as_bytes())?;
+            buf.write_all(&[CR, LF])?;
+        }
+
+        buf.write_all(&[CR, LF])
+    }
 }
 
 /// Wrapper over supported Media Types.
@@ -255,6 +326,8 @@ pub enum MediaType {
     PlainText,
     /// Media Type: "application/json".
     ApplicationJson,
+    /// Media Type: "application/yaml".
+    ApplicationYaml,
 }
 
 impl Default for MediaType {
@@ -290,6 +363,9 @@ impl MediaType {
         match utf8_slice.as_str().trim() {
             "text/plain" => Ok(Self::PlainText),
             "application/json" => Ok(Self::ApplicationJson),
+            // YAML has no registered media type of its own, so the unofficial names that
+            // clients commonly send are accepted as well.
+            "application/yaml" | "application/x-yaml" | "text/yaml" => Ok(Self::ApplicationYaml),
             _ => Err(RequestError::InvalidRequest),
         }
     }
@@ -309,6 +385,7 @@ impl MediaType {
         match self {
             Self::PlainText => "text/plain",
             Self::ApplicationJson => "application/json",
+            Self::ApplicationYaml => "application/yaml",
         }
     }
 }
@@ -324,6 +401,7 @@ mod tests {
                 expect,
                 chunked,
                 accept: MediaType::PlainText,
+                custom_entries: HashMap::new(),
             }
         }
     }
@@ -348,6 +426,15 @@ mod tests {
             MediaType::PlainText
         );
 
+        assert_eq!(
+            MediaType::try_from(b"application/yaml").unwrap(),
+            MediaType::ApplicationYaml
+        );
+        assert_eq!(
+            MediaType::try_from(b"application/x-yaml").unwrap(),
+            MediaType::ApplicationYaml
+        );
+
         assert_eq!(
             MediaType::try_from(b"").unwrap_err(),
             RequestError::InvalidRequest
@@ -366,6 +453,9 @@ mod tests {
 
         let media_type = MediaType::PlainText;
         assert_eq!(media_type.as_str(), "text/plain");
+
+        let media_type = MediaType::ApplicationYaml;
+        assert_eq!(media_type.as_str(), "application/yaml");
     }
 
     #[test]
@@ -392,6 +482,11 @@ mod tests {
         )
         .unwrap();
         assert_eq!(headers.content_length, 29);
+        assert_eq!(
+            headers.custom_entry("last-modified"),
+            Some("Tue, 15 Nov 1994 12:45:26 GMT")
+        );
+        assert_eq!(headers.custom_entry("Content-Length"), None);
 
         let bytes: [u8; 10] = [130, 140, 150, 130, 140, 150, 130, 140, 150, 160];
         // Invalid headers.
@@ -465,6 +560,13 @@ mod tests {
         assert!(header.accept == MediaType::ApplicationJson);
         assert!(header.parse_header_line(b"Accept: text/plain").is_ok());
         assert!(header.accept == MediaType::PlainText);
+        assert!(header.parse_header_line(b"Accept: text/yaml").is_ok());
+        assert!(header.accept == MediaType::ApplicationYaml);
+
+        // Test custom header.
+        assert!(header.parse_header_line(b"X-metadata-token: token").is_ok());
+        assert_eq!(header.custom_entry("X-Metadata-Token"), Some("token"));
+        assert_eq!(header.custom_entry("X-metadata-token-ttl-seconds"), None);
 
         // Test invalid accept media type.
         assert!(header
@@ -500,4 +602,35 @@ mod tests {
         let header = Header::try_from(b"Accept").unwrap();
         assert_eq!(header.raw(), b"Accept");
     }
+
+    #[test]
+    fn test_write_headers() {
+        let mut headers = Headers::default();
+        let mut buf = vec![];
+        headers.write_all(&mut buf).unwrap();
+        assert_eq!(buf, b"\r\n");
+
+        headers.set_accept(MediaType::ApplicationJson);
+        headers.set_content_length(15);
+        headers.set_custom_entry("X-Custom-Header", "value");
+        assert_eq!(headers.custom_entry("x-custom-header"), Some("value"));
+
+        let mut buf = vec![];
+        headers.write_all(&mut buf).unwrap();
+        assert_eq!(
+            &buf[..],
+            &b"Accept: application/json\r\n\
+               x-custom-header: value\r\n\
+               Content-Length: 15\r\n\r\n"[..]
+        );
+
+        // The headers can be parsed back.
+        let parsed_headers = Headers::try_from(&buf).unwrap();
+        assert_eq!(parsed_headers.accept(), MediaType::ApplicationJson);
+        assert_eq!(parsed_headers.content_length(), 15);
+        assert_eq!(
+            parsed_headers.custom_entry("X-Custom-Header"),
+            Some("value")
+        );
+    }
 }
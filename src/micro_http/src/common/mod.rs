@@ -28,6 +28,10 @@ pub enum RequestError {
     InvalidHeader,
     /// The Request is invalid and cannot be served.
     InvalidRequest,
+    /// The request line and header fields are larger than the limit, in bytes, of the connection.
+    HeadersTooLarge(usize),
+    /// The body of the Request is larger than the limit, in bytes, of the connection.
+    BodyTooLarge(usize),
 }
 
 impl Display for RequestError {
@@ -39,6 +43,40 @@ impl Display for RequestError {
             Self::UnsupportedHeader => write!(f, "Unsupported header."),
             Self::InvalidHeader => write!(f, "Invalid header."),
             Self::InvalidRequest => write!(f, "Invalid request."),
+            Self::HeadersTooLarge(limit) => write!(
+                f,
+                "Request headers are larger than the limit of {} bytes.",
+                limit
+            ),
+            Self::BodyTooLarge(limit) => write!(
+                f,
+                "Request body is larger than the limit of {} bytes.",
+                limit
+            ),
+        }
+    }
+}
+
+/// Errors associated with parsing the HTTP Response from a u8 slice.
+#[derive(Debug, PartialEq)]
+pub enum ResponseError {
+    /// The HTTP Version in the Response is not supported or it is invalid.
+    InvalidHttpVersion(&'static str),
+    /// The status code in the Response is not supported or it is invalid.
+    InvalidStatusCode,
+    /// Header specified is invalid.
+    InvalidHeader,
+    /// The Response is invalid and cannot be parsed.
+    InvalidResponse,
+}
+
+impl Display for ResponseError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        match self {
+            Self::InvalidHttpVersion(inner) => write!(f, "Invalid HTTP Version: {}", inner),
+            Self::InvalidStatusCode => write!(f, "Invalid status code."),
+            Self::InvalidHeader => write!(f, "Invalid header."),
+            Self::InvalidResponse => write!(f, "Invalid response."),
         }
     }
 }
@@ -88,6 +126,30 @@ impl Display for ServerError {
     }
 }
 
+/// Errors pertaining to `HttpClient`.
+#[derive(Debug)]
+pub enum ClientError {
+    /// The response parsing has failed.
+    ParseError(ResponseError),
+    /// Could not perform a stream operation successfully.
+    StreamError(std::io::Error),
+    /// The server closed the connection before sending the whole response.
+    ConnectionClosed,
+    /// The client was created from an open connection, so it can't open a new one.
+    CannotReconnect,
+}
+
+impl Display for ClientError {
+    fn fmt(&self, f: &mut Formatter) -> Result<(), Error> {
+        match self {
+            Self::ParseError(inner) => write!(f, "Parsing error: {}", inner),
+            Self::StreamError(inner) => write!(f, "Stream error: {}", inner),
+            Self::ConnectionClosed => write!(f, "Connection closed."),
+            Self::CannotReconnect => write!(f, "Cannot reconnect to an unknown socket."),
+        }
+    }
+}
+
 /// The Body associated with an HTTP Request or Response.
 ///
 /// ## Examples
@@ -135,6 +197,12 @@ pub enum Method {
     Put,
     /// PATCH Method.
     Patch,
+    /// DELETE Method.
+    Delete,
+    /// POST Method.
+    Post,
+    /// HEAD Method.
+    Head,
 }
 
 impl Method {
@@ -150,6 +218,9 @@ impl Method {
             b"GET" => Ok(Self::Get),
             b"PUT" => Ok(Self::Put),
             b"PATCH" => Ok(Self::Patch),
+            b"DELETE" => Ok(Self::Delete),
+            b"POST" => Ok(Self::Post),
+            b"HEAD" => Ok(Self::Head),
             _ => Err(RequestError::InvalidHttpMethod("Unsupported HTTP method.")),
         }
     }
@@ -160,6 +231,9 @@ impl Method {
             Self::Get => b"GET",
             Self::Put => b"PUT",
             Self::Patch => b"PATCH",
+            Self::Delete => b"DELETE",
+            Self::Post => b"POST",
+            Self::Head => b"HEAD",
         }
     }
 }
@@ -259,13 +333,23 @@ mod tests {
         assert_eq!(Method::Get.raw(), b"GET");
         assert_eq!(Method::Put.raw(), b"PUT");
         assert_eq!(Method::Patch.raw(), b"PATCH");
+        assert_eq!(Method::Delete.raw(), b"DELETE");
+        assert_eq!(Method::Post.raw(), b"POST");
+        assert_eq!(Method::Head.raw(), b"HEAD");
 
         // Tests for try_from
         assert_eq!(Method::try_from(b"GET").unwrap(), Method::Get);
         assert_eq!(Method::try_from(b"PUT").unwrap(), Method::Put);
         assert_eq!(Method::try_from(b"PATCH").unwrap(), Method::Patch);
+        assert_eq!(Method::try_from(b"DELETE").unwrap(), Method::Delete);
+        assert_eq!(Method::try_from(b"POST").unwrap(), Method::Post);
+        assert_eq!(Method::try_from(b"HEAD").unwrap(), Method::Head);
+        assert_eq!(
+            Method::try_from(b"head").unwrap_err(),
+            RequestError::InvalidHttpMethod("Unsupported HTTP method.")
+        );
         assert_eq!(
-            Method::try_from(b"POST").unwrap_err(),
+            Method::try_from(b"OPTIONS").unwrap_err(),
             RequestError::InvalidHttpMethod("Unsupported HTTP method.")
         );
     }
@@ -308,6 +392,34 @@ mod tests {
             format!("{}", RequestError::InvalidRequest),
             "Invalid request."
         );
+        assert_eq!(
+            format!("{}", RequestError::HeadersTooLarge(8192)),
+            "Request headers are larger than the limit of 8192 bytes."
+        );
+        assert_eq!(
+            format!("{}", RequestError::BodyTooLarge(1024)),
+            "Request body is larger than the limit of 1024 bytes."
+        );
+    }
+
+    #[test]
+    fn test_display_response_error() {
+        assert_eq!(
+            format!("{}", ResponseError::InvalidHttpVersion("test")),
+            "Invalid HTTP Version: test"
+        );
+        assert_eq!(
+            format!("{}", ResponseError::InvalidStatusCode),
+            "Invalid status code."
+        );
+        assert_eq!(
+            format!("{}", ResponseError::InvalidHeader),
+            "Invalid header."
+        );
+        assert_eq!(
+            format!("{}", ResponseError::InvalidResponse),
+            "Invalid response."
+        );
     }
 
     #[test]
@@ -354,4 +466,27 @@ mod tests {
             "IO error: Resource temporarily unavailable (os error 11)"
         );
     }
+
+    #[test]
+    fn test_display_client_error() {
+        assert_eq!(
+            format!("{}", ClientError::ParseError(ResponseError::InvalidHeader)),
+            "Parsing error: Invalid header."
+        );
+        assert_eq!(
+            format!(
+                "{}",
+                ClientError::StreamError(std::io::Error::from_raw_os_error(11))
+            ),
+            "Stream error: Resource temporarily unavailable (os error 11)"
+        );
+        assert_eq!(
+            format!("{}", ClientError::ConnectionClosed),
+            "Connection closed."
+        );
+        assert_eq!(
+            format!("{}", ClientError::CannotReconnect),
+            "Cannot reconnect to an unknown socket."
+        );
+    }
 }
@@ -5,19 +5,24 @@ use std::collections::VecDeque;
 use std::io::{Read, Write};
 
 use common::ascii::{CR, CRLF_LEN, LF};
-use common::Body;
+use common::{Body, Version};
 pub use common::{ConnectionError, RequestError};
 use headers::Headers;
-use request::{find, Request, RequestLine};
+use request::{find, ChunkedBodyDecoder, Request, RequestLine};
 use response::{Response, StatusCode};
 
 const BUFFER_SIZE: usize = 1024;
+/// The default limit for the size of the request line and header fields of a request.
+const DEFAULT_MAX_HEADERS_SIZE: usize = 8192;
+/// The default limit for the size of the body of a request.
+const DEFAULT_MAX_BODY_SIZE: usize = 1 << 20;
 
 /// Describes the state machine of an HTTP connection.
 pub enum ConnectionState {
     WaitingForRequestLine,
     WaitingForHeaders,
     WaitingForBody,
+    WaitingForChunkedBody,
     RequestReady,
 }
 
@@ -42,6 +47,16 @@ pub struct HttpConnection<T> {
     /// Represents how many bytes from the body of the request are still
     /// to be read.
     body_bytes_to_be_read: i32,
+    /// How many bytes of the request line and header fields of the request
+    /// that is currently being processed have been received.
+    headers_size: usize,
+    /// The maximum size of the request line and header fields of a request.
+    max_headers_size: usize,
+    /// The maximum size of the body of a request.
+    max_body_size: usize,
+    /// Decodes the body of the request that is currently being processed,
+    /// when it is sent with the `chunked` transfer coding.
+    chunked_decoder: ChunkedBodyDecoder,
     /// A queue of all requests that have been fully received and parsed.
     parsed_requests: VecDeque<Request>,
     /// A queue of requests that are waiting to be sent.
@@ -49,6 +64,12 @@ pub struct HttpConnection<T> {
     /// A buffer containing the bytes of a response that is currently
     /// being sent.
     response_buffer: Option<Vec<u8>>,
+    /// How many of the parsed requests have not been answered yet.
+    unanswered_requests: usize,
+    /// A `100 Continue` response for the request that is currently being
+    /// processed, which has to wait for the responses to the previous
+    /// requests in order to keep them in order.
+    deferred_continue: Option<Response>,
 }
 
 impl<T: Read + Write> HttpConnection<T> {
@@ -62,12 +83,30 @@ impl<T: Read + Write> HttpConnection<T> {
             read_cursor: 0,
             body_vec: vec![],
             body_bytes_to_be_read: 0,
+            headers_size: 0,
+            max_headers_size: DEFAULT_MAX_HEADERS_SIZE,
+            max_body_size: DEFAULT_MAX_BODY_SIZE,
+            chunked_decoder: ChunkedBodyDecoder::default(),
             parsed_requests: VecDeque::new(),
             response_queue: VecDeque::new(),
             response_buffer: None,
+            unanswered_requests: 0,
+            deferred_continue: None,
         }
     }
 
+    /// Sets the maximum size, in bytes, of the request line and header fields of a request.
+    /// Requests with larger headers are rejected with `HeadersTooLarge`.
+    pub fn set_max_headers_size(&mut self, max_headers_size: usize) {
+        self.max_headers_size = max_headers_size;
+    }
+
+    /// Sets the maximum size, in bytes, of the body of a request.
+    /// Requests with larger bodies are rejected with `BodyTooLarge`.
+    pub fn set_max_body_size(&mut self, max_body_size: usize) {
+        self.max_body_size = max_body_size;
+    }
+
     /// Tries to read new bytes from the stream and automatically update the request.
     /// Meant to be used only with non-blocking streams and an `EPOLL` structure.
     /// Should be called whenever an `EPOLLIN` event is signaled.
@@ -100,12 +139,23 @@ impl<T: Read + Write> HttpConnection<T> {
                         return Ok(());
                     }
                 }
+                ConnectionState::WaitingForChunkedBody => {
+                    if !self.parse_chunked_body(&mut line_start_index, end_cursor)? {
+                        return Ok(());
+                    }
+                }
                 ConnectionState::RequestReady => {
                     // This request is ready to be passed for handling.
                     // Update the state machine to expect a new request and push this request into
                     // the `parsed_requests` queue.
                     self.state = ConnectionState::WaitingForRequestLine;
                     self.body_bytes_to_be_read = 0;
+                    self.headers_size = 0;
+                    self.chunked_decoder = ChunkedBodyDecoder::default();
+                    // The client is not waiting for a `100 Continue` anymore, since it
+                    // has already sent the whole request.
+                    self.deferred_continue = None;
+                    self.unanswered_requests += 1;
                     self.parsed_requests
                         .push_back(self.pending_request.take().unwrap());
                 }
@@ -137,7 +187,8 @@ impl<T: Read + Write> HttpConnection<T> {
     /// Returns `false` if there are no more bytes to be parsed in the buffer.
     ///
     /// # Errors
-    /// `ParseError` is returned if unable to parse request line or line longer than BUFFER_SIZE.
+    /// `ParseError` is returned if unable to parse request line, line longer than BUFFER_SIZE,
+    /// or line longer than the headers size limit.
     fn parse_request_line(
         &mut self,
         start: &mut usize,
@@ -145,6 +196,8 @@ impl<T: Read + Write> HttpConnection<T> {
     ) -> Result<bool, ConnectionError> {
         match find(&self.buffer[*start..end], &[CR, LF]) {
             Some(line_end_index) => {
+                self.headers_size = 0;
+                self.add_headers_size(line_end_index + CRLF_LEN)?;
                 let line = &self.buffer[*start..(*start + line_end_index)];
 
                 *start = *start + line_end_index + CRLF_LEN;
@@ -180,7 +233,8 @@ impl<T: Read + Write> HttpConnection<T> {
     /// Returns `false` if there are no more bytes to be parsed in the buffer.
     ///
     /// # Errors
-    /// `ParseError` is returned if unable to parse header or line longer than BUFFER_SIZE.
+    /// `ParseError` is returned if unable to parse header, line longer than BUFFER_SIZE, or
+    /// headers or announced body larger than their limits.
     fn parse_headers(
         &mut self,
         line_start_index: &mut usize,
@@ -194,19 +248,37 @@ impl<T: Read + Write> HttpConnection<T> {
 
             // We have found the end of the header.
             Some(0) => {
+                self.add_headers_size(CRLF_LEN)?;
                 // If our current state is `WaitingForHeaders`, it means that we already have
                 // a valid request formed from a request line, so it's safe to unwrap.
                 let request = self.pending_request.as_mut().unwrap();
-                if request.headers.content_length() == 0 {
+                // Larger bodies are rejected before the client is told to send them.
+                if request.headers.content_length() as usize > self.max_body_size {
+                    return Err(ConnectionError::ParseError(RequestError::BodyTooLarge(
+                        self.max_body_size,
+                    )));
+                }
+                if request.headers.chunked() {
+                    // A body can't have both a length and a transfer coding.
+                    if request.headers.content_length() != 0 {
+                        return Err(ConnectionError::ParseError(RequestError::InvalidRequest));
+                    }
+                    if request.headers.expect() {
+                        let version = request.http_version();
+                        self.send_continue(version);
+                    }
+                    self.state = ConnectionState::WaitingForChunkedBody;
+                } else if request.headers.content_length() == 0 {
                     self.state = ConnectionState::RequestReady;
                 } else {
                     if request.headers.expect() {
-                        // Send expect.
-                        let expect_response =
-                            Response::new(request.http_version(), StatusCode::Continue);
-                        self.response_queue.push_back(expect_response);
+                        let version = request.http_version();
+                        self.send_continue(version);
                     }
 
+                    // The request has been borrowed again because `send_continue` needs
+                    // `self` as mutable.
+                    let request = self.pending_request.as_mut().unwrap();
                     self.body_bytes_to_be_read = request.headers.content_length();
                     request.body = Some(Body::new(vec![]));
                     self.state = ConnectionState::WaitingForBody;
@@ -218,6 +290,7 @@ impl<T: Read + Write> HttpConnection<T> {
             }
             // We have found the end of a header line.
             Some(relative_line_end_index) => {
+                self.add_headers_size(relative_line_end_index + CRLF_LEN)?;
                 let request = self.pending_request.as_mut().unwrap();
                 // The `line_end_index` relative to the whole buffer.
                 let line_end_index = relative_line_end_index + *line_start_index;
@@ -307,6 +380,81 @@ impl<T: Read + Write> HttpConnection<T> {
         Ok(true)
     }
 
+    /// Parses bytes in `buffer` to be put into the request body, when it is sent with the
+    /// `chunked` transfer coding.
+    /// Returns `false` if there are no more bytes to be parsed in the buffer.
+    ///
+    /// # Errors
+    /// `ParseError` is returned when the body is not properly encoded, when one of its
+    /// lines is longer than BUFFER_SIZE, or when it's larger than the body size limit.
+    fn parse_chunked_body(
+        &mut self,
+        line_start_index: &mut usize,
+        end_cursor: usize,
+    ) -> Result<bool, ConnectionError> {
+        *line_start_index += self
+            .chunked_decoder
+            .decode(
+                &self.buffer[*line_start_index..end_cursor],
+                &mut self.body_vec,
+            )
+            .map_err(ConnectionError::ParseError)?;
+
+        if self.body_vec.len() > self.max_body_size {
+            return Err(ConnectionError::ParseError(RequestError::BodyTooLarge(
+                self.max_body_size,
+            )));
+        }
+
+        if !self.chunked_decoder.is_done() {
+            // If we have parsed BUFFER_SIZE bytes and still haven't found the end of a
+            // chunk size or trailer line.
+            if *line_start_index == 0 && end_cursor == BUFFER_SIZE {
+                return Err(ConnectionError::ParseError(RequestError::InvalidRequest));
+            }
+            // Move the incomplete line to the beginning of the buffer, and wait for the
+            // next `try_read` call to complete it.
+            self.shift_buffer_left(*line_start_index, end_cursor);
+            return Ok(false);
+        }
+
+        // An empty body is the same as no body.
+        if !self.body_vec.is_empty() {
+            let body: Vec<_> = self.body_vec.drain(..).collect();
+            self.pending_request.as_mut().unwrap().body = Some(Body::new(body));
+        }
+
+        self.state = ConnectionState::RequestReady;
+        Ok(true)
+    }
+
+    /// Accounts for `len` more bytes of the request line and header fields of the request
+    /// that is currently being processed.
+    ///
+    /// # Errors
+    /// `ParseError` is returned when the headers become larger than `max_headers_size`.
+    fn add_headers_size(&mut self, len: usize) -> Result<(), ConnectionError> {
+        self.headers_size += len;
+        if self.headers_size > self.max_headers_size {
+            return Err(ConnectionError::ParseError(RequestError::HeadersTooLarge(
+                self.max_headers_size,
+            )));
+        }
+        Ok(())
+    }
+
+    /// Sends a `100 Continue` response for the request that is currently being processed.
+    /// If there are previous requests that have not been answered yet, the response is
+    /// deferred until they are, so that the responses are written in order.
+    fn send_continue(&mut self, version: Version) {
+        let continue_response = Response::new(version, StatusCode::Continue);
+        if self.unanswered_requests == 0 {
+            self.response_queue.push_back(continue_response);
+        } else {
+            self.deferred_continue = Some(continue_response);
+        }
+    }
+
     /// Tries to write the first available response to the provided stream.
     /// Meant to be used only with non-blocking streams and an `EPOLL` structure.
     /// Should be called whenever an `EPOLLOUT` event is signaled. If no bytes
@@ -367,8 +515,17 @@ impl<T: Read + Write> HttpConnection<T> {
     }
 
     /// Send a response back to the source of a request.
+    ///
+    /// The responses have to be enqueued in the same order as the requests
+    /// they answer have been parsed.
     pub fn enqueue_response(&mut self, response: Response) {
         self.response_queue.push_back(response);
+        self.unanswered_requests = self.unanswered_requests.saturating_sub(1);
+        if self.unanswered_requests == 0 {
+            if let Some(continue_response) = self.deferred_continue.take() {
+                self.response_queue.push_back(continue_response);
+            }
+        }
     }
 
     fn shift_buffer_left(&mut self, line_start_index: usize, end_cursor: usize) {
@@ -395,6 +552,15 @@ impl<T: Read + Write> HttpConnection<T> {
         self.parsed_requests.pop_front()
     }
 
+    /// Returns `true` if a request has been partially received, and the rest of it is
+    /// awaited.
+    pub fn pending_read(&self) -> bool {
+        match self.state {
+            ConnectionState::WaitingForRequestLine => self.read_cursor != 0,
+            _ => true,
+        }
+    }
+
     /// Returns `true` if there are bytes waiting to be written into the stream.
     pub fn pending_write(&self) -> bool {
         self.response_buffer.is_some() || !self.response_queue.is_empty()
@@ -417,8 +583,7 @@ mod tests {
             .write_all(
                 b"PATCH http://localhost/home HTTP/1.1\r\n\
                                  Expect: 100-continue\r\n\
-                                 Content-Length: 26\r\n\
-                                 Transfer-Encoding: chunked\r\n\r\n",
+                                 Content-Length: 26\r\n\r\n",
             )
             .unwrap();
         assert!(conn.try_read().is_ok());
@@ -429,7 +594,7 @@ mod tests {
 
         let expected_request = Request {
             request_line: RequestLine::new(Method::Patch, "http://localhost/home", Version::Http11),
-            headers: Headers::new(26, true, true),
+            headers: Headers::new(26, true, false),
             body: Some(Body::new(b"this is not\n\r\na json \nbody".to_vec())),
         };
 
@@ -446,7 +611,7 @@ mod tests {
             .write_all(
                 b"PATCH http://localhost/home HTTP/1.1\r\n\
                                  Expect: 100-continue\r\n\
-                                 Transfer-Encoding: chunked\r\n",
+                                 Cache-Control: max-age=600\r\n",
             )
             .unwrap();
 
@@ -465,7 +630,7 @@ mod tests {
 
         let expected_request = Request {
             request_line: RequestLine::new(Method::Patch, "http://localhost/home", Version::Http11),
-            headers: Headers::new(26, true, true),
+            headers: Headers::new(26, true, false),
             body: Some(Body::new(b"this is not\n\r\na json \nbody".to_vec())),
         };
         assert_eq!(request, expected_request);
@@ -481,7 +646,7 @@ mod tests {
             .write_all(
                 b"PATCH http://localhost/home HTTP/1.1\r\n\
                                  Expect: 100-continue\r\n\
-                                 Transfer-Encoding: chunked\r\n",
+                                 Cache-Control: max-age=600\r\n",
             )
             .unwrap();
 
@@ -498,7 +663,7 @@ mod tests {
         let request = conn.pop_parsed_request().unwrap();
         let expected_request = Request {
             request_line: RequestLine::new(Method::Patch, "http://localhost/home", Version::Http11),
-            headers: Headers::new(26, true, true),
+            headers: Headers::new(26, true, false),
             body: Some(Body::new(b"this is not\n\r\na json \nbody".to_vec())),
         };
         assert_eq!(request, expected_request);
@@ -544,7 +709,6 @@ mod tests {
             .write_all(
                 b"PATCH http://localhost/home HTTP/1.1\r\n\
                                  Expect: 100-continue\r\n\
-                                 Transfer-Encoding: chunked\r\n\
                                  Content-Length: 1400\r\n\r\n",
             )
             .unwrap();
@@ -559,7 +723,7 @@ mod tests {
         let request = conn.pop_parsed_request().unwrap();
         let expected_request = Request {
             request_line: RequestLine::new(Method::Patch, "http://localhost/home", Version::Http11),
-            headers: Headers::new(1400, true, true),
+            headers: Headers::new(1400, true, false),
             body: Some(Body::new(request_body)),
         };
         assert_eq!(request, expected_request);
@@ -616,15 +780,14 @@ mod tests {
         sender
             .write_all(
                 b"PATCH http://localhost/home HTTP/1.1\r\n\
-                                 Expect: 100-continue\r\n\
-                                 Transfer-Encoding: chunked\r\n\r\n",
+                                 Expect: 100-continue\r\n\r\n",
             )
             .unwrap();
         conn.try_read().unwrap();
         let request = conn.pop_parsed_request().unwrap();
         let expected_request = Request {
             request_line: RequestLine::new(Method::Patch, "http://localhost/home", Version::Http11),
-            headers: Headers::new(0, true, true),
+            headers: Headers::new(0, true, false),
             body: None,
         };
         assert_eq!(request, expected_request);
@@ -706,7 +869,6 @@ mod tests {
         sender
             .write_all(
                 b"PATCH http://localhost/home HTTP/1.1\r\n\
-                                 Transfer-Encoding: chunked\r\n\
                                  Content-Length: 26\r\n\r\nthis is not\n\r\na json \nbody",
             )
             .unwrap();
@@ -718,7 +880,7 @@ mod tests {
 
         let expected_request_first = Request {
             request_line: RequestLine::new(Method::Patch, "http://localhost/home", Version::Http11),
-            headers: Headers::new(26, false, true),
+            headers: Headers::new(26, false, false),
             body: Some(Body::new(b"this is not\n\r\na json \nbody".to_vec())),
         };
 
@@ -758,6 +920,281 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_try_read_chunked_body() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        receiver.set_nonblocking(true).expect("Can't modify socket");
+        let mut conn = HttpConnection::new(receiver);
+        sender
+            .write_all(
+                b"PUT http://localhost/home HTTP/1.1\r\n\
+                  Expect: 100-continue\r\n\
+                  Transfer-Encoding: chunked\r\n\r\n",
+            )
+            .unwrap();
+        conn.try_read().unwrap();
+        assert!(conn.pop_parsed_request().is_none());
+        // The client is told to send the body.
+        assert!(conn.pending_write());
+
+        // A chunk split across reads, including its size line.
+        let mut chunk = vec![b'a'; 1400];
+        sender.write_all(b"5").unwrap();
+        conn.try_read().unwrap();
+        sender.write_all(b"78\r\n").unwrap();
+        sender.write_all(&chunk[..1000]).unwrap();
+        conn.try_read().unwrap();
+        sender.write_all(&chunk[1000..]).unwrap();
+        conn.try_read().unwrap();
+        assert!(conn.pop_parsed_request().is_none());
+
+        // The end of the body, followed by another request.
+        sender
+            .write_all(b"\r\n4\r\nbbbb\r\n0\r\nTrailer: ignored\r\n\r\n")
+            .unwrap();
+        sender
+            .write_all(
+                b"PUT http://localhost/home HTTP/1.1\r\n\
+                  Transfer-Encoding: chunked\r\n\r\n0\r\n\r\n",
+            )
+            .unwrap();
+        conn.try_read().unwrap();
+
+        chunk.extend_from_slice(b"bbbb");
+        let expected_request = Request {
+            request_line: RequestLine::new(Method::Put, "http://localhost/home", Version::Http11),
+            headers: Headers::new(0, true, true),
+            body: Some(Body::new(chunk)),
+        };
+        assert_eq!(conn.pop_parsed_request().unwrap(), expected_request);
+        // An empty body is the same as no body.
+        let expected_request = Request {
+            request_line: RequestLine::new(Method::Put, "http://localhost/home", Version::Http11),
+            headers: Headers::new(0, false, true),
+            body: None,
+        };
+        assert_eq!(conn.pop_parsed_request().unwrap(), expected_request);
+        assert!(conn.pop_parsed_request().is_none());
+    }
+
+    #[test]
+    fn test_try_read_invalid_chunked_body() {
+        // Bodies with both a length and a transfer coding.
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        receiver.set_nonblocking(true).expect("Can't modify socket");
+        let mut conn = HttpConnection::new(receiver);
+        sender
+            .write_all(
+                b"PUT http://localhost/home HTTP/1.1\r\n\
+                  Transfer-Encoding: chunked\r\n\
+                  Content-Length: 5\r\n\r\n0\r\n\r\n",
+            )
+            .unwrap();
+        assert_eq!(
+            conn.try_read().unwrap_err(),
+            ConnectionError::ParseError(RequestError::InvalidRequest)
+        );
+
+        // Invalid chunk size.
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        receiver.set_nonblocking(true).expect("Can't modify socket");
+        let mut conn = HttpConnection::new(receiver);
+        sender
+            .write_all(
+                b"PUT http://localhost/home HTTP/1.1\r\n\
+                  Transfer-Encoding: chunked\r\n\r\nzz\r\n",
+            )
+            .unwrap();
+        assert_eq!(
+            conn.try_read().unwrap_err(),
+            ConnectionError::ParseError(RequestError::InvalidRequest)
+        );
+
+        // Chunk size line longer than BUFFER_SIZE bytes.
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        receiver.set_nonblocking(true).expect("Can't modify socket");
+        let mut conn = HttpConnection::new(receiver);
+        sender
+            .write_all(
+                b"PUT http://localhost/home HTTP/1.1\r\n\
+                  Transfer-Encoding: chunked\r\n\r\n1;",
+            )
+            .unwrap();
+        sender.write_all(&[b'a'; BUFFER_SIZE]).unwrap();
+        conn.try_read().unwrap();
+        assert_eq!(
+            conn.try_read().unwrap_err(),
+            ConnectionError::ParseError(RequestError::InvalidRequest)
+        );
+    }
+
+    #[test]
+    fn test_pipelined_expect() {
+        let (mut client, server) = UnixStream::pair().unwrap();
+        server.set_nonblocking(true).expect("Can't modify socket");
+        let mut conn = HttpConnection::new(server);
+
+        let mut ok_response = vec![];
+        Response::new(Version::Http11, StatusCode::OK)
+            .write_all(&mut ok_response)
+            .unwrap();
+        let mut continue_response = vec![];
+        Response::new(Version::Http11, StatusCode::Continue)
+            .write_all(&mut continue_response)
+            .unwrap();
+
+        client
+            .write_all(
+                b"GET http://localhost/home HTTP/1.1\r\n\r\n\
+                  PUT http://localhost/home HTTP/1.1\r\n\
+                  Expect: 100-continue\r\n\
+                  Content-Length: 4\r\n\r\n",
+            )
+            .unwrap();
+        conn.try_read().unwrap();
+        assert!(conn.pop_parsed_request().is_some());
+        // The `100 Continue` for the second request waits for the response to the first one.
+        assert!(!conn.pending_write());
+
+        conn.enqueue_response(Response::new(Version::Http11, StatusCode::OK));
+        while conn.pending_write() {
+            conn.try_write().unwrap();
+        }
+        let mut expected_responses = ok_response.clone();
+        expected_responses.extend_from_slice(&continue_response);
+        let mut responses = vec![0u8; expected_responses.len()];
+        client.read_exact(&mut responses).unwrap();
+        assert_eq!(responses, expected_responses);
+
+        // The `100 Continue` is dropped if the whole request arrives before it is sent.
+        client
+            .write_all(
+                b"test\
+                  GET http://localhost/home HTTP/1.1\r\n\r\n\
+                  PUT http://localhost/home HTTP/1.1\r\n\
+                  Expect: 100-continue\r\n\
+                  Content-Length: 4\r\n\r\n",
+            )
+            .unwrap();
+        conn.try_read().unwrap();
+        client.write_all(b"test").unwrap();
+        conn.try_read().unwrap();
+        for _ in 0..3 {
+            assert!(conn.pop_parsed_request().is_some());
+        }
+        for _ in 0..3 {
+            conn.enqueue_response(Response::new(Version::Http11, StatusCode::OK));
+        }
+        while conn.pending_write() {
+            conn.try_write().unwrap();
+        }
+        let mut responses = vec![0u8; 3 * ok_response.len()];
+        client.read_exact(&mut responses).unwrap();
+        assert_eq!(responses, ok_response.repeat(3));
+        client.set_nonblocking(true).unwrap();
+        assert!(client.read(&mut [0u8]).is_err());
+    }
+
+    #[test]
+    fn test_try_read_size_limits() {
+        // Headers larger than the limit.
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        receiver.set_nonblocking(true).expect("Can't modify socket");
+        let mut conn = HttpConnection::new(receiver);
+        conn.set_max_headers_size(64);
+        sender
+            .write_all(
+                b"GET http://localhost/home HTTP/1.1\r\n\
+                  Custom-Header: 0123456789\r\n\r\n",
+            )
+            .unwrap();
+        assert_eq!(
+            conn.try_read().unwrap_err(),
+            ConnectionError::ParseError(RequestError::HeadersTooLarge(64))
+        );
+
+        // The limit includes the final CR LF.
+        let request = b"GET http://localhost/home HTTP/1.1\r\n\
+                        Custom-Header: 01234\r\n\r\n";
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        receiver.set_nonblocking(true).expect("Can't modify socket");
+        let mut conn = HttpConnection::new(receiver);
+        conn.set_max_headers_size(request.len());
+        sender.write_all(request).unwrap();
+        sender.write_all(request).unwrap();
+        conn.try_read().unwrap();
+        assert!(conn.pop_parsed_request().is_some());
+        assert!(conn.pop_parsed_request().is_some());
+
+        // Bodies larger than the limit are rejected before the client is told to send them.
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        receiver.set_nonblocking(true).expect("Can't modify socket");
+        let mut conn = HttpConnection::new(receiver);
+        conn.set_max_body_size(16);
+        sender
+            .write_all(
+                b"PUT http://localhost/home HTTP/1.1\r\n\
+                  Expect: 100-continue\r\n\
+                  Content-Length: 17\r\n\r\n",
+            )
+            .unwrap();
+        assert_eq!(
+            conn.try_read().unwrap_err(),
+            ConnectionError::ParseError(RequestError::BodyTooLarge(16))
+        );
+        assert!(!conn.pending_write());
+
+        // Chunked bodies are checked as they arrive.
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        receiver.set_nonblocking(true).expect("Can't modify socket");
+        let mut conn = HttpConnection::new(receiver);
+        conn.set_max_body_size(16);
+        sender
+            .write_all(
+                b"PUT http://localhost/home HTTP/1.1\r\n\
+                  Transfer-Encoding: chunked\r\n\r\n\
+                  10\r\n0123456789abcdef\r\n",
+            )
+            .unwrap();
+        conn.try_read().unwrap();
+        sender.write_all(b"1\r\n").unwrap();
+        conn.try_read().unwrap();
+        sender.write_all(b"x").unwrap();
+        assert_eq!(
+            conn.try_read().unwrap_err(),
+            ConnectionError::ParseError(RequestError::BodyTooLarge(16))
+        );
+    }
+
+    #[test]
+    fn test_pending_read() {
+        let (mut sender, receiver) = UnixStream::pair().unwrap();
+        receiver.set_nonblocking(true).expect("Can't modify socket");
+        let mut conn = HttpConnection::new(receiver);
+        assert!(!conn.pending_read());
+
+        // A partial request line.
+        sender.write_all(b"PUT http://local").unwrap();
+        conn.try_read().unwrap();
+        assert!(conn.pending_read());
+
+        // A whole request, and the request line of the next one.
+        sender
+            .write_all(b"host/home HTTP/1.1\r\nContent-Length: 4\r\n\r\ntest")
+            .unwrap();
+        sender
+            .write_all(b"PUT http://localhost/home HTTP/1.1\r\n")
+            .unwrap();
+        conn.try_read().unwrap();
+        assert!(conn.pop_parsed_request().is_some());
+        assert!(conn.pending_read());
+
+        sender.write_all(b"\r\n").unwrap();
+        conn.try_read().unwrap();
+        assert!(conn.pop_parsed_request().is_some());
+        assert!(!conn.pending_read());
+    }
+
     #[test]
     fn test_enqueue_response() {
         // Response without body.
@@ -7,8 +7,12 @@
 //! HTTP/1.1 has a mandatory header **Host**, but as this crate is only used
 //! for parsing API requests, this header (if present) is ignored.
 //!
-//! This HTTP implementation is stateless thus it does not support chunking or
-//! compression.
+//! Request bodies can be sent either with a **Content-Length**, or with the
+//! `chunked` transfer coding, in which case they are decoded as they arrive.
+//! Responses are never chunked, and compression is not supported.
+//!
+//! Requests can be pipelined on the same connection, and the responses have
+//! to be sent in the order in which the requests were received.
 //!
 //! ## Supported Headers
 //! The **micro_http** crate has support for parsing the following **Request**
@@ -17,20 +21,29 @@
 //! - Expect
 //! - Transfer-Encoding
 //!
-//! The **Response** does not have a public interface for adding headers, but whenever
-//! a write to the **Body** is made, the headers **ContentLength** and **MediaType**
-//! are automatically updated.
+//! The other request headers are kept as custom entries, and can be looked up by name.
+//!
+//! Whenever a write to the **Body** of a **Response** is made, the headers **ContentLength**
+//! and **MediaType** are automatically updated. Other headers can be added to a **Response**
+//! by name, with `add_header`.
 //!
 //! ### Media Types
 //! The supported media types are:
 //! - text/plain
 //! - application/json
+//! - application/yaml
 //!
 //! ## Supported Methods
 //! The supported HTTP Methods are:
 //! - GET
 //! - PUT
 //! - PATCH
+//! - DELETE
+//! - POST
+//! - HEAD
+//!
+//! The responses to **HEAD** requests which go through `HttpServer` have their
+//! body removed, so the same handler can answer both **GET** and **HEAD** requests.
 //!
 //! ## Supported Status Codes
 //! The supported status codes are:
@@ -39,9 +52,20 @@
 //! - OK - 200
 //! - No Content - 204
 //! - Bad Request - 400
+//! - Unauthorized - 401
 //! - Not Found - 404
+//! - Method Not Allowed - 405
+//! - Request Timeout - 408
+//! - Payload Too Large - 413
 //! - Internal Server Error - 500
 //! - Not Implemented - 501
+//! - Service Unavailable - 503
+//!
+//! ## Limits and Timeouts
+//! `HttpServer` answers requests with headers or bodies larger than the configured
+//! limits with **413** and closes the connection. Connections which do not finish
+//! sending a request within the read timeout get a **408** response and are closed,
+//! while connections left idle for longer than the idle timeout are closed silently.
 //!
 //! ## Example for parsing an HTTP Request from a slice
 //! ```
@@ -108,10 +132,31 @@
 //!     break;
 //! }
 //! ```
+//!
+//! `HttpClient` sends requests to a server listening on a Unix domain socket, such
+//! as the Firecracker API server, and parses their responses.
+//!
+//! ## Example for using the client
+//!
+//! ```no_run
+//! extern crate micro_http;
+//! use micro_http::{Body, HttpClient, StatusCode};
+//!
+//! let mut client = HttpClient::connect("/tmp/firecracker.socket").unwrap();
+//! let response = client
+//!     .put("/actions", Body::new("{ \"action_type\": \"InstanceStart\" }"))
+//!     .unwrap();
+//! if response.status() != StatusCode::NoContent {
+//!     // The body describes the error.
+//!     println!("{:?}", response.body());
+//! }
+//! ```
 
 extern crate libc;
+extern crate timerfd;
 extern crate utils;
 
+mod client;
 mod common;
 mod connection;
 mod request;
@@ -120,9 +165,10 @@ mod server;
 use common::ascii;
 use common::headers;
 
+pub use client::{ClientError, HttpClient};
 pub use connection::{ConnectionError, HttpConnection};
 pub use request::{Request, RequestError};
-pub use response::{Response, StatusCode};
+pub use response::{Response, ResponseError, StatusCode};
 pub use server::{HttpServer, ServerError, ServerRequest, ServerResponse};
 
 pub use common::headers::{Headers, MediaType};
@@ -1,6 +1,8 @@
 // Copyright 2018 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
+use std::cmp::min;
+use std::io::{Error as WriteError, Write};
 use std::str::from_utf8;
 
 use common::ascii::{CR, CRLF_LEN, LF, SP};
@@ -18,6 +20,126 @@ pub fn find(bytes: &[u8], sequence: &[u8]) -> Option<usize> {
         .position(|window| window == sequence)
 }
 
+// Parses the size of a chunk, found at the beginning of each chunk of a body sent with the
+// `chunked` transfer coding. Chunk extensions, which follow the size, are ignored.
+fn parse_chunk_size(line: &[u8]) -> Result<usize, RequestError> {
+    let size = match find(line, b";") {
+        Some(extensions_start) => &line[..extensions_start],
+        None => line,
+    };
+    from_utf8(size)
+        .ok()
+        .map(str::trim)
+        .filter(|size| !size.is_empty() && size.bytes().all(|b| b.is_ascii_hexdigit()))
+        .and_then(|size| usize::from_str_radix(size, 16).ok())
+        .ok_or(RequestError::InvalidRequest)
+}
+
+/// Describes which part of a chunked body is expected next.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ChunkedBodyState {
+    /// The line holding the size of the next chunk.
+    Size,
+    /// This many bytes of chunk data.
+    Data(usize),
+    /// The CR LF sequence which follows the chunk data.
+    DataEnd,
+    /// The trailer fields, which end with an empty line.
+    Trailers,
+    /// Nothing, the body is complete.
+    Done,
+}
+
+/// Decodes a request body sent with the `chunked` transfer coding, as its bytes become available.
+///
+/// Details about the chunked transfer coding can be found in the
+/// [RFC](https://tools.ietf.org/html/rfc7230#section-4.1).
+pub struct ChunkedBodyDecoder {
+    state: ChunkedBodyState,
+}
+
+impl Default for ChunkedBodyDecoder {
+    fn default() -> Self {
+        Self {
+            state: ChunkedBodyState::Size,
+        }
+    }
+}
+
+impl ChunkedBodyDecoder {
+    /// Decodes as much of `bytes` as possible, appending the chunk data to `body`.
+    ///
+    /// Returns the number of bytes which have been consumed. The lines which are not complete
+    /// yet are left alone, so the next call has to start with them.
+    ///
+    /// # Errors
+    /// `InvalidRequest` is returned when the body is not properly encoded.
+    pub fn decode(&mut self, bytes: &[u8], body: &mut Vec<u8>) -> Result<usize, RequestError> {
+        let mut consumed = 0;
+        loop {
+            let bytes_left = &bytes[consumed..];
+            match self.state {
+                ChunkedBodyState::Size => {
+                    let line_end = match find(bytes_left, &[CR, LF]) {
+                        Some(line_end) => line_end,
+                        None => break,
+                    };
+                    let size = parse_chunk_size(&bytes_left[..line_end])?;
+                    consumed += line_end + CRLF_LEN;
+                    // The last chunk is empty, and it's followed by the trailer fields.
+                    self.state = if size == 0 {
+                        ChunkedBodyState::Trailers
+                    } else {
+                        ChunkedBodyState::Data(size)
+                    };
+                }
+                ChunkedBodyState::Data(size) => {
+                    if bytes_left.is_empty() {
+                        break;
+                    }
+                    let len = min(size, bytes_left.len());
+                    body.extend_from_slice(&bytes_left[..len]);
+                    consumed += len;
+                    self.state = if len == size {
+                        ChunkedBodyState::DataEnd
+                    } else {
+                        ChunkedBodyState::Data(size - len)
+                    };
+                }
+                ChunkedBodyState::DataEnd => {
+                    if bytes_left.len() < CRLF_LEN {
+                        break;
+                    }
+                    if bytes_left[..CRLF_LEN] != [CR, LF] {
+                        return Err(RequestError::InvalidRequest);
+                    }
+                    consumed += CRLF_LEN;
+                    self.state = ChunkedBodyState::Size;
+                }
+                ChunkedBodyState::Trailers => {
+                    let line_end = match find(bytes_left, &[CR, LF]) {
+                        Some(line_end) => line_end,
+                        None => break,
+                    };
+                    consumed += line_end + CRLF_LEN;
+                    // The trailer fields are not interpreted, so we're only looking for the
+                    // empty line which ends the body.
+                    if line_end == 0 {
+                        self.state = ChunkedBodyState::Done;
+                    }
+                }
+                ChunkedBodyState::Done => break,
+            }
+        }
+        Ok(consumed)
+    }
+
+    /// Returns `true` once the whole body has been decoded.
+    pub fn is_done(&self) -> bool {
+        self.state == ChunkedBodyState::Done
+    }
+}
+
 /// Wrapper over HTTP URIs.
 ///
 /// The `Uri` can not be used directly and it is only accessible from an HTTP Request.
@@ -56,8 +178,14 @@ impl Uri {
     pub fn get_abs_path(&self) -> &str {
         const HTTP_SCHEME_PREFIX: &str = "http://";
 
-        if self.string.starts_with(HTTP_SCHEME_PREFIX) {
-            let without_scheme = &self.string[HTTP_SCHEME_PREFIX.len()..];
+        // The query component is not part of the path.
+        let uri = match self.string.find('?') {
+            Some(query_start) => &self.string[..query_start],
+            None => self.string.as_str(),
+        };
+
+        if uri.starts_with(HTTP_SCHEME_PREFIX) {
+            let without_scheme = &uri[HTTP_SCHEME_PREFIX.len()..];
             if without_scheme.is_empty() {
                 return "";
             }
@@ -68,13 +196,21 @@ impl Uri {
                 None => "",
             }
         } else {
-            if self.string.starts_with('/') {
-                return self.string.as_str();
+            if uri.starts_with('/') {
+                return uri;
             }
 
             ""
         }
     }
+
+    /// Returns the query component of the `Uri`, which follows the first '?' character, or
+    /// `None` if the `Uri` doesn't have one.
+    pub fn get_query(&self) -> Option<&str> {
+        self.string
+            .find('?')
+            .map(|query_start| &self.string[query_start + 1..])
+    }
 }
 
 /// Wrapper over an HTTP Request Line.
@@ -86,6 +222,15 @@ pub struct RequestLine {
 }
 
 impl RequestLine {
+    /// Creates a new `RequestLine`.
+    pub fn new(method: Method, uri: &str, http_version: Version) -> Self {
+        Self {
+            method,
+            uri: Uri::new(uri),
+            http_version,
+        }
+    }
+
     fn parse_request_line(request_line: &[u8]) -> (&[u8], &[u8], &[u8]) {
         if let Some(method_end) = find(request_line, &[SP]) {
             let method = &request_line[..method_end];
@@ -122,6 +267,15 @@ impl RequestLine {
         })
     }
 
+    fn write_all<T: Write>(&self, buf: &mut T) -> Result<(), WriteError> {
+        buf.write_all(self.method.raw())?;
+        buf.write_all(&[SP])?;
+        buf.write_all(self.uri.string.as_bytes())?;
+        buf.write_all(&[SP])?;
+        buf.write_all(self.http_version.raw())?;
+        buf.write_all(&[CR, LF])
+    }
+
     // Returns the minimum length of a valid request. The request must contain
     // the method (GET), the URI (minmum 1 character), the HTTP version(HTTP/DIGIT.DIGIT) and
     // 2 separators (SP).
@@ -143,6 +297,25 @@ pub struct Request {
 }
 
 impl Request {
+    /// Creates a new HTTP `Request` with no headers and an empty body.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate micro_http;
+    /// use micro_http::{Method, Request, Version};
+    ///
+    /// let http_request = Request::new(Method::Get, "/machine-config", Version::Http11);
+    /// assert_eq!(http_request.uri().get_abs_path(), "/machine-config");
+    /// ```
+    pub fn new(method: Method, uri: &str, http_version: Version) -> Self {
+        Self {
+            request_line: RequestLine::new(method, uri, http_version),
+            headers: Headers::default(),
+            body: None,
+        }
+    }
+
     /// Parses a byte slice into a HTTP Request.
     ///
     /// The byte slice is expected to have the following format: </br>
@@ -200,6 +373,24 @@ impl Request {
                 // Parse the body of the request.
                 // Firstly check if we have a body.
                 let body = match headers.content_length() {
+                    0 if headers.chunked() => {
+                        let body_as_bytes = &headers_and_body[(headers_end + 2 * CRLF_LEN)..];
+                        let mut decoder = ChunkedBodyDecoder::default();
+                        let mut body = vec![];
+                        // The whole body has to be present, and nothing else.
+                        if decoder.decode(body_as_bytes, &mut body)? != body_as_bytes.len()
+                            || !decoder.is_done()
+                        {
+                            return Err(RequestError::InvalidRequest);
+                        }
+                        if body.is_empty() {
+                            None
+                        } else {
+                            Some(Body::new(body))
+                        }
+                    }
+                    // A body can't have both a length and a transfer coding.
+                    _ if headers.chunked() => return Err(RequestError::InvalidRequest),
                     0 => {
                         // No request body.
                         None
@@ -251,11 +442,36 @@ impl Request {
     pub fn method(&self) -> Method {
         self.request_line.method
     }
+
+    /// Updates the body of the `Request`.
+    ///
+    /// This function has side effects because it also updates the headers:
+    /// - `ContentLength`: this is set to the length of the specified body.
+    pub fn set_body(&mut self, body: Body) {
+        self.headers.set_content_length(body.len() as i32);
+        self.body = Some(body);
+    }
+
+    /// Writes the content of the `Request` to the specified `buf`, so that it can be sent
+    /// to a server.
+    ///
+    /// # Errors
+    /// Returns an error when the buffer is not large enough.
+    pub fn write_all<T: Write>(&self, buf: &mut T) -> Result<(), WriteError> {
+        self.request_line.write_all(buf)?;
+        self.headers.write_all(buf)?;
+        if let Some(ref body) = self.body {
+            buf.write_all(body.raw())?;
+        }
+
+        Ok(())
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use headers::MediaType;
 
     impl PartialEq for Request {
         fn eq(&self, other: &Self) -> bool {
@@ -267,16 +483,6 @@ mod tests {
         }
     }
 
-    impl RequestLine {
-        pub fn new(method: Method, uri: &str, http_version: Version) -> Self {
-            Self {
-                method,
-                uri: Uri::new(uri),
-                http_version,
-            }
-        }
-    }
-
     #[test]
     fn test_uri() {
         for tc in &vec![
@@ -287,9 +493,21 @@ mod tests {
             ("home", ""),
             ("http://", ""),
             ("http://192.168.0.0", ""),
+            ("http://localhost/home?wait=true", "/home"),
+            ("/home?", "/home"),
+            ("http://localhost?wait=true", ""),
         ] {
             assert_eq!(Uri::new(tc.0).get_abs_path(), tc.1);
         }
+
+        for tc in &vec![
+            ("http://localhost/home", None),
+            ("/home?", Some("")),
+            ("/home?wait=true&version=1", Some("wait=true&version=1")),
+            ("http://localhost?wait=true", Some("wait=true")),
+        ] {
+            assert_eq!(Uri::new(tc.0).get_query(), tc.1);
+        }
     }
 
     #[test]
@@ -344,7 +562,7 @@ mod tests {
         );
 
         // Test for invalid method.
-        let request_line = b"POST http://localhost/home HTTP/1.0";
+        let request_line = b"OPTIONS http://localhost/home HTTP/1.0";
         assert_eq!(
             RequestLine::try_from(request_line).unwrap_err(),
             RequestError::InvalidHttpMethod("Unsupported HTTP method.")
@@ -409,14 +627,13 @@ mod tests {
         let request = Request::try_from(
             b"PATCH http://localhost/home HTTP/1.1\r\n\
                                      Expect: 100-continue\r\n\
-                                     Transfer-Encoding: chunked\r\n\
                                      Content-Length: 26\r\n\r\nthis is not\n\r\na json \nbody",
         )
         .unwrap();
         assert_eq!(request.uri(), &Uri::new("http://localhost/home"));
         assert_eq!(request.http_version(), Version::Http11);
         assert_eq!(request.method(), Method::Patch);
-        assert_eq!(request.headers.chunked(), true);
+        assert_eq!(request.headers.chunked(), false);
         assert_eq!(request.headers.expect(), true);
         assert_eq!(request.headers.content_length(), 26);
         assert_eq!(
@@ -462,4 +679,118 @@ mod tests {
         assert_eq!(request.headers.content_length(), 0);
         assert!(request.body.is_none());
     }
+
+    #[test]
+    fn test_chunked_body() {
+        let encoded_body = b"1a;name=value\r\nthis is not\n\r\na json \nbody\r\n\
+                             A\r\n, chunked.\r\n0\r\nTrailer: ignored\r\n\r\n";
+        let decoded_body = b"this is not\n\r\na json \nbody, chunked.".to_vec();
+
+        // The body can be decoded all at once...
+        let mut decoder = ChunkedBodyDecoder::default();
+        let mut body = vec![];
+        assert_eq!(
+            decoder.decode(&encoded_body[..], &mut body).unwrap(),
+            encoded_body.len()
+        );
+        assert!(decoder.is_done());
+        assert_eq!(body, decoded_body);
+
+        // ... or byte by byte, in which case incomplete lines are not consumed.
+        let mut decoder = ChunkedBodyDecoder::default();
+        let mut body = vec![];
+        let mut start = 0;
+        for end in 1..=encoded_body.len() {
+            assert!(!decoder.is_done());
+            start += decoder
+                .decode(&encoded_body[start..end], &mut body)
+                .unwrap();
+        }
+        assert_eq!(start, encoded_body.len());
+        assert!(decoder.is_done());
+        assert_eq!(body, decoded_body);
+        // Nothing is consumed past the end of the body.
+        assert_eq!(decoder.decode(b"GET", &mut body).unwrap(), 0);
+
+        // Invalid chunk sizes, and chunk data of the wrong length.
+        for encoded_body in &[
+            &b"\r\n"[..],
+            b"-1\r\n",
+            b"+1\r\n",
+            b"x\r\n",
+            b"ffffffffffffffffffffffff\r\n",
+            b"2\r\nabc\r\n",
+        ] {
+            assert_eq!(
+                ChunkedBodyDecoder::default()
+                    .decode(encoded_body, &mut vec![])
+                    .unwrap_err(),
+                RequestError::InvalidRequest
+            );
+        }
+
+        let request_bytes = |body: &[u8]| {
+            let mut request = b"PUT http://localhost/home HTTP/1.1\r\n\
+                                Transfer-Encoding: chunked\r\n\r\n"
+                .to_vec();
+            request.extend_from_slice(body);
+            request
+        };
+        let request = Request::try_from(&request_bytes(encoded_body)).unwrap();
+        assert!(request.headers.chunked());
+        assert_eq!(request.body.unwrap().body, decoded_body);
+        // An empty body is the same as no body.
+        let request = Request::try_from(&request_bytes(b"0\r\n\r\n")).unwrap();
+        assert!(request.body.is_none());
+        // The body has to be complete...
+        assert_eq!(
+            Request::try_from(&request_bytes(b"1\r\na\r\n")).unwrap_err(),
+            RequestError::InvalidRequest
+        );
+        // ... and there can't be anything after it.
+        assert_eq!(
+            Request::try_from(&request_bytes(b"0\r\n\r\nGET")).unwrap_err(),
+            RequestError::InvalidRequest
+        );
+
+        // Bodies with both a length and a transfer coding are rejected.
+        assert_eq!(
+            Request::try_from(
+                b"PUT http://localhost/home HTTP/1.1\r\n\
+                  Transfer-Encoding: chunked\r\n\
+                  Content-Length: 8\r\n\r\n0\r\n\r\n"
+            )
+            .unwrap_err(),
+            RequestError::InvalidRequest
+        );
+    }
+
+    #[test]
+    fn test_write_request() {
+        // A request without a body.
+        let request = Request::new(Method::Get, "/machine-config", Version::Http11);
+        let mut buf = vec![];
+        request.write_all(&mut buf).unwrap();
+        assert_eq!(&buf[..], &b"GET /machine-config HTTP/1.1\r\n\r\n"[..]);
+        assert_eq!(Request::try_from(&buf).unwrap(), request);
+
+        // A request with headers and a body.
+        let mut request = Request::new(Method::Put, "/mmds", Version::Http11);
+        request.headers.set_accept(MediaType::ApplicationJson);
+        request.set_body(Body::new("{ \"key\": \"value\" }"));
+        assert_eq!(request.headers.content_length(), 18);
+        let mut buf = vec![];
+        request.write_all(&mut buf).unwrap();
+        assert_eq!(
+            &buf[..],
+            &b"PUT /mmds HTTP/1.1\r\n\
+               Accept: application/json\r\n\
+               Content-Length: 18\r\n\r\n\
+               { \"key\": \"value\" }"[..]
+        );
+        let parsed_request = Request::try_from(&buf).unwrap();
+        assert_eq!(parsed_request, request);
+        assert_eq!(parsed_request.headers.accept(), MediaType::ApplicationJson);
+        assert_eq!(parsed_request.body, request.body);
+    }
 }
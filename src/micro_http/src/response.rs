@@ -2,10 +2,13 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use std::io::{Error as WriteError, Write};
+use std::str::from_utf8;
 
-use ascii::{COLON, CR, LF, SP};
+use ascii::{COLON, CR, CRLF_LEN, LF, SP};
+pub use common::ResponseError;
 use common::{Body, Version};
 use headers::{Header, MediaType};
+use request::find;
 use Method;
 
 /// Wrapper over a response status code.
@@ -23,14 +26,22 @@ pub enum StatusCode {
     NoContent,
     /// 400, Bad Request
     BadRequest,
+    /// 401, Unauthorized
+    Unauthorized,
     /// 404, Not Found
     NotFound,
     /// 405, Method Not Allowed
     MethodNotAllowed,
+    /// 408, Request Timeout
+    RequestTimeout,
+    /// 413, Payload Too Large
+    PayloadTooLarge,
     /// 500, Internal Server Error
     InternalServerError,
     /// 501, Not Implemented
     NotImplemented,
+    /// 503, Service Unavailable
+    ServiceUnavailable,
 }
 
 impl StatusCode {
@@ -41,10 +52,46 @@ impl StatusCode {
             Self::OK => b"200",
             Self::NoContent => b"204",
             Self::BadRequest => b"400",
+            Self::Unauthorized => b"401",
             Self::NotFound => b"404",
             Self::MethodNotAllowed => b"405",
+            Self::RequestTimeout => b"408",
+            Self::PayloadTooLarge => b"413",
             Self::InternalServerError => b"500",
             Self::NotImplemented => b"501",
+            Self::ServiceUnavailable => b"503",
+        }
+    }
+
+    /// Parses a byte slice into a `StatusCode`.
+    ///
+    /// # Errors
+    /// `InvalidStatusCode` is returned if the status code is unsupported.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate micro_http;
+    /// use micro_http::StatusCode;
+    ///
+    /// assert_eq!(StatusCode::try_from(b"204").unwrap(), StatusCode::NoContent);
+    /// assert!(StatusCode::try_from(b"418").is_err());
+    /// ```
+    pub fn try_from(bytes: &[u8]) -> Result<Self, ResponseError> {
+        match bytes {
+            b"100" => Ok(Self::Continue),
+            b"200" => Ok(Self::OK),
+            b"204" => Ok(Self::NoContent),
+            b"400" => Ok(Self::BadRequest),
+            b"401" => Ok(Self::Unauthorized),
+            b"404" => Ok(Self::NotFound),
+            b"405" => Ok(Self::MethodNotAllowed),
+            b"408" => Ok(Self::RequestTimeout),
+            b"413" => Ok(Self::PayloadTooLarge),
+            b"500" => Ok(Self::InternalServerError),
+            b"501" => Ok(Self::NotImplemented),
+            b"503" => Ok(Self::ServiceUnavailable),
+            _ => Err(ResponseError::InvalidStatusCode),
         }
     }
 }
@@ -70,6 +117,21 @@ impl StatusLine {
 
         Ok(())
     }
+
+    // Parses a status line such as "HTTP/1.1 200 OK". The reason phrase is optional,
+    // and ignored.
+    fn try_from(status_line: &[u8]) -> Result<Self, ResponseError> {
+        let version_end = find(status_line, &[SP]).ok_or(ResponseError::InvalidResponse)?;
+        let http_version = Version::try_from(&status_line[..version_end])
+            .map_err(|_| ResponseError::InvalidHttpVersion("Unsupported HTTP version."))?;
+
+        let status_code_and_reason = &status_line[(version_end + 1)..];
+        let status_code_end =
+            find(status_code_and_reason, &[SP]).unwrap_or(status_code_and_reason.len());
+        let status_code = StatusCode::try_from(&status_code_and_reason[..status_code_end])?;
+
+        Ok(Self::new(http_version, status_code))
+    }
 }
 
 /// Wrapper over the list of headers associated with a HTTP Response.
@@ -80,6 +142,7 @@ pub struct ResponseHeaders {
     content_type: MediaType,
     server: String,
     allow: Vec<Method>,
+    custom_entries: Vec<(String, String)>,
 }
 
 impl Default for ResponseHeaders {
@@ -89,6 +152,7 @@ impl Default for ResponseHeaders {
             content_type: Default::default(),
             server: String::from("Firecracker API"),
             allow: Vec::new(),
+            custom_entries: Vec::new(),
         }
     }
 }
@@ -125,6 +189,13 @@ impl ResponseHeaders {
 
         self.write_allow_header(buf)?;
 
+        for (name, value) in &self.custom_entries {
+            buf.write_all(name.as_bytes())?;
+            buf.write_all(&[COLON, SP])?;
+            buf.write_all(value.as_bytes())?;
+            buf.write_all(&[CR, LF])?;
+        }
+
         if self.content_length != 0 {
             buf.write_all(Header::ContentType.raw())?;
             buf.write_all(&[COLON, SP])?;
@@ -155,14 +226,53 @@ impl ResponseHeaders {
     pub fn set_content_type(&mut self, content_type: MediaType) {
         self.content_type = content_type;
     }
+
+    // Parses one header line of a response. The header fields which are not known are
+    // stored as custom entries, and so are the media types which are not supported.
+    fn parse_header_line(&mut self, header_line: &str) -> Result<(), ResponseError> {
+        let name_end = header_line.find(':').ok_or(ResponseError::InvalidHeader)?;
+        let name = header_line[..name_end].trim();
+        let value = header_line[(name_end + 1)..].trim();
+
+        match name.to_ascii_lowercase().as_str() {
+            "content-length" => match value.parse::<i32>() {
+                Ok(content_length) if content_length >= 0 => self.content_length = content_length,
+                _ => return Err(ResponseError::InvalidHeader),
+            },
+            "content-type" => match MediaType::try_from(value.as_bytes()) {
+                Ok(content_type) => self.content_type = content_type,
+                Err(_) => self.add_custom_entry(name, value),
+            },
+            "server" => self.server = String::from(value),
+            "allow" => {
+                for method in value.split(',') {
+                    let method = Method::try_from(method.trim().as_bytes())
+                        .map_err(|_| ResponseError::InvalidHeader)?;
+                    self.allow.push(method);
+                }
+            }
+            // Every response is written with "Connection: keep-alive".
+            "connection" => (),
+            // The bodies of the responses are only delimited by their length.
+            "transfer-encoding" => return Err(ResponseError::InvalidHeader),
+            _ => self.add_custom_entry(name, value),
+        }
+
+        Ok(())
+    }
+
+    fn add_custom_entry(&mut self, name: &str, value: &str) {
+        self.custom_entries
+            .push((String::from(name), String::from(value)));
+    }
 }
 
 /// Wrapper over an HTTP Response.
 ///
 /// The Response is created using a `Version` and a `StatusCode`. When creating a Response object,
 /// the body is initialized to `None` and the header is initialized with the `default` value. The body
-/// can be updated with a call to `set_body`. The header can be updated with `set_content_type` and
-/// `set_server`.
+/// can be updated with a call to `set_body`. The header can be updated with `set_content_type`,
+/// `set_server` and `add_header`.
 pub struct Response {
     status_line: StatusLine,
     headers: ResponseHeaders,
@@ -179,6 +289,69 @@ impl Response {
         }
     }
 
+    /// Parses a byte slice into a HTTP Response.
+    ///
+    /// The byte slice is expected to hold a single response: </br>
+    ///     * Status Line: "HTTP/1.1 SP Status-Code SP Reason-Phrase CRLF" - Mandatory </br>
+    ///     * Response Headers "<headers> CRLF" - Optional </br>
+    ///     * Entity Body - Optional </br>
+    /// When the byte slice ends with the headers, the `Response` has no body, but keeps the
+    /// `Content-Length` of the headers, like the responses to `HEAD` requests. Otherwise, the
+    /// length of the body has to match the `Content-Length` header field.
+    ///
+    /// # Errors
+    /// `InvalidHttpVersion` is returned if the HTTP version is unsupported.
+    /// `InvalidStatusCode` is returned if the status code is unsupported.
+    /// `InvalidHeader` is returned if a header field is malformed, or if the body is sent
+    /// with a transfer coding.
+    /// `InvalidResponse` is returned when parsing the byte stream fails otherwise.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// extern crate micro_http;
+    /// use micro_http::{Response, StatusCode};
+    ///
+    /// let http_response = Response::try_from(b"HTTP/1.1 204 No Content\r\n\r\n").unwrap();
+    /// assert_eq!(http_response.status(), StatusCode::NoContent);
+    /// ```
+    pub fn try_from(byte_stream: &[u8]) -> Result<Self, ResponseError> {
+        // The first line of the response is the Status Line.
+        let status_line_end = find(byte_stream, &[CR, LF]).ok_or(ResponseError::InvalidResponse)?;
+        let status_line = StatusLine::try_from(&byte_stream[..status_line_end])?;
+
+        // The headers end with the first empty line, which may also end the Status Line.
+        let headers_end = find(&byte_stream[status_line_end..], &[CR, LF, CR, LF])
+            .ok_or(ResponseError::InvalidResponse)?
+            + status_line_end;
+        let mut headers = ResponseHeaders {
+            server: String::new(),
+            ..Default::default()
+        };
+        if headers_end > status_line_end {
+            let headers_text = from_utf8(&byte_stream[(status_line_end + CRLF_LEN)..headers_end])
+                .map_err(|_| ResponseError::InvalidHeader)?;
+            for header_line in headers_text.split("\r\n") {
+                headers.parse_header_line(header_line)?;
+            }
+        }
+
+        let body_as_bytes = &byte_stream[(headers_end + 2 * CRLF_LEN)..];
+        let body = if body_as_bytes.is_empty() {
+            None
+        } else if body_as_bytes.len() == headers.content_length as usize {
+            Some(Body::new(body_as_bytes))
+        } else {
+            return Err(ResponseError::InvalidResponse);
+        };
+
+        Ok(Self {
+            status_line,
+            headers,
+            body,
+        })
+    }
+
     /// Updates the body of the `Response`.
     ///
     /// This function has side effects because it also updates the headers:
@@ -208,6 +381,18 @@ impl Response {
         self.headers.allow.push(method);
     }
 
+    /// Adds a header which is not otherwise known to the `Response`, such as an application
+    /// specific `X-` header. The headers are written in the order they were added.
+    pub fn add_header(&mut self, name: &str, value: &str) {
+        self.headers.add_custom_entry(name, value);
+    }
+
+    /// Removes the body of the `Response`, but keeps the `ContentLength` and `ContentType`
+    /// headers which describe it, as required when answering `HEAD` requests.
+    pub fn remove_body(&mut self) {
+        self.body = None;
+    }
+
     fn write_body<T: Write>(&self, mut buf: T) -> Result<(), WriteError> {
         if let Some(ref body) = self.body {
             buf.write_all(body.raw())?;
@@ -257,6 +442,16 @@ impl Response {
     pub fn allow(&self) -> Vec<Method> {
         self.headers.allow.clone()
     }
+
+    /// Returns the value of the header called `name`, if it is not otherwise known to the
+    /// `Response`. Header names are case-insensitive.
+    pub fn custom_header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .custom_entries
+            .iter()
+            .find(|(entry_name, _)| entry_name.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
 }
 
 #[cfg(test)]
@@ -301,11 +496,44 @@ mod tests {
         assert!(response.write_all(&mut response_buf.as_mut()).is_ok());
         assert_eq!(response_buf.as_ref(), expected_response);
 
+        // Test custom headers.
+        let mut response = Response::new(Version::Http10, StatusCode::OK);
+        response.add_header("X-Custom", "foo");
+        response.add_header("X-Other", "bar");
+        let expected_response: &'static [u8] = b"HTTP/1.0 200 \r\n\
+            Server: Firecracker API\r\n\
+            Connection: keep-alive\r\n\
+            X-Custom: foo\r\n\
+            X-Other: bar\r\n\r\n";
+        let mut response_buf: [u8; 95] = [0; 95];
+        assert!(response.write_all(&mut response_buf.as_mut()).is_ok());
+        assert_eq!(response_buf.as_ref(), expected_response);
+
         // Test write failed.
         let mut response_buf: [u8; 1] = [0; 1];
         assert!(response.write_all(&mut response_buf.as_mut()).is_err());
     }
 
+    #[test]
+    fn test_remove_body() {
+        let mut response = Response::new(Version::Http10, StatusCode::OK);
+        response.set_body(Body::new("This is a test"));
+        response.set_content_type(MediaType::PlainText);
+        response.remove_body();
+
+        assert!(response.body().is_none());
+        assert_eq!(response.content_length(), 14);
+
+        let expected_response: &'static [u8] = b"HTTP/1.0 200 \r\n\
+            Server: Firecracker API\r\n\
+            Connection: keep-alive\r\n\
+            Content-Type: text/plain\r\n\
+            Content-Length: 14\r\n\r\n";
+        let mut response_buf = vec![];
+        assert!(response.write_all(&mut response_buf).is_ok());
+        assert_eq!(response_buf, expected_response);
+    }
+
     #[test]
     fn test_set_server() {
         let mut response = Response::new(Version::Http10, StatusCode::OK);
@@ -342,10 +570,42 @@ mod tests {
         assert_eq!(StatusCode::OK.raw(), b"200");
         assert_eq!(StatusCode::NoContent.raw(), b"204");
         assert_eq!(StatusCode::BadRequest.raw(), b"400");
+        assert_eq!(StatusCode::Unauthorized.raw(), b"401");
         assert_eq!(StatusCode::NotFound.raw(), b"404");
         assert_eq!(StatusCode::MethodNotAllowed.raw(), b"405");
+        assert_eq!(StatusCode::RequestTimeout.raw(), b"408");
+        assert_eq!(StatusCode::PayloadTooLarge.raw(), b"413");
         assert_eq!(StatusCode::InternalServerError.raw(), b"500");
         assert_eq!(StatusCode::NotImplemented.raw(), b"501");
+        assert_eq!(StatusCode::ServiceUnavailable.raw(), b"503");
+
+        for status_code in &[
+            StatusCode::Continue,
+            StatusCode::OK,
+            StatusCode::NoContent,
+            StatusCode::BadRequest,
+            StatusCode::Unauthorized,
+            StatusCode::NotFound,
+            StatusCode::MethodNotAllowed,
+            StatusCode::RequestTimeout,
+            StatusCode::PayloadTooLarge,
+            StatusCode::InternalServerError,
+            StatusCode::NotImplemented,
+            StatusCode::ServiceUnavailable,
+        ] {
+            assert_eq!(
+                StatusCode::try_from(status_code.raw()).unwrap(),
+                *status_code
+            );
+        }
+        assert_eq!(
+            StatusCode::try_from(b"418").unwrap_err(),
+            ResponseError::InvalidStatusCode
+        );
+        assert_eq!(
+            StatusCode::try_from(b"").unwrap_err(),
+            ResponseError::InvalidStatusCode
+        );
     }
 
     #[test]
@@ -355,4 +615,94 @@ mod tests {
         response.allow_method(Method::Put);
         assert_eq!(response.allow(), vec![Method::Get, Method::Put]);
     }
+
+    #[test]
+    fn test_try_from_response() {
+        // The responses written by `write_all` can be parsed back.
+        let mut response = Response::new(Version::Http11, StatusCode::MethodNotAllowed);
+        response.set_body(Body::new("{ \"error\": \"Not allowed.\" }"));
+        response.set_content_type(MediaType::ApplicationJson);
+        response.set_allow(vec![Method::Get, Method::Head]);
+        response.add_header("X-Custom", "foo");
+        let mut response_buf = vec![];
+        response.write_all(&mut response_buf).unwrap();
+
+        let parsed_response = Response::try_from(&response_buf).unwrap();
+        assert_eq!(parsed_response.http_version(), Version::Http11);
+        assert_eq!(parsed_response.status(), StatusCode::MethodNotAllowed);
+        assert_eq!(parsed_response.content_type(), MediaType::ApplicationJson);
+        assert_eq!(parsed_response.content_length(), 27);
+        assert_eq!(parsed_response.body(), response.body());
+        assert_eq!(parsed_response.allow(), vec![Method::Get, Method::Head]);
+        assert_eq!(parsed_response.custom_header("x-custom"), Some("foo"));
+        assert_eq!(parsed_response.custom_header("Connection"), None);
+        let mut parsed_response_buf = vec![];
+        parsed_response.write_all(&mut parsed_response_buf).unwrap();
+        assert_eq!(parsed_response_buf, response_buf);
+
+        // The reason phrase is ignored, and the headers are optional.
+        let parsed_response = Response::try_from(b"HTTP/1.0 204 No Content\r\n\r\n").unwrap();
+        assert_eq!(parsed_response.http_version(), Version::Http10);
+        assert_eq!(parsed_response.status(), StatusCode::NoContent);
+        assert!(parsed_response.body().is_none());
+        let parsed_response = Response::try_from(b"HTTP/1.1 200\r\n\r\n").unwrap();
+        assert_eq!(parsed_response.status(), StatusCode::OK);
+
+        // Unsupported media types are kept as custom headers.
+        let parsed_response = Response::try_from(
+            b"HTTP/1.1 200 OK\r\n\
+              content-type: text/html\r\n\
+              Content-Length: 4\r\n\r\n\
+              body",
+        )
+        .unwrap();
+        assert_eq!(
+            parsed_response.custom_header("Content-Type"),
+            Some("text/html")
+        );
+        assert_eq!(parsed_response.body().unwrap(), Body::new("body"));
+
+        // Without a body, the `Content-Length` is kept as in the responses to `HEAD` requests.
+        let parsed_response =
+            Response::try_from(b"HTTP/1.1 200 \r\nContent-Length: 4\r\n\r\n").unwrap();
+        assert_eq!(parsed_response.content_length(), 4);
+        assert!(parsed_response.body().is_none());
+
+        // Invalid responses.
+        for (response_bytes, error) in &[
+            (&b"HTTP/1.1 200 \r\n"[..], ResponseError::InvalidResponse),
+            (b"HTTP/1.1\r\n\r\n", ResponseError::InvalidResponse),
+            (
+                b"HTTP/2.0 200 \r\n\r\n",
+                ResponseError::InvalidHttpVersion("Unsupported HTTP version."),
+            ),
+            (b"HTTP/1.1 299 \r\n\r\n", ResponseError::InvalidStatusCode),
+            (
+                b"HTTP/1.1 200 \r\nContent-Length: -1\r\n\r\n",
+                ResponseError::InvalidHeader,
+            ),
+            (
+                b"HTTP/1.1 200 \r\nTransfer-Encoding: chunked\r\n\r\n",
+                ResponseError::InvalidHeader,
+            ),
+            (
+                b"HTTP/1.1 200 \r\nAllow: GET, FOO\r\n\r\n",
+                ResponseError::InvalidHeader,
+            ),
+            (
+                b"HTTP/1.1 200 \r\nServer\r\n\r\n",
+                ResponseError::InvalidHeader,
+            ),
+            (
+                b"HTTP/1.1 200 \r\nContent-Length: 4\r\n\r\nbodies",
+                ResponseError::InvalidResponse,
+            ),
+            (b"HTTP/1.1 200 \r\n\r\nbody", ResponseError::InvalidResponse),
+        ] {
+            assert_eq!(
+                Response::try_from(response_bytes).err().as_ref(),
+                Some(error)
+            );
+        }
+    }
 }
@@ -6,21 +6,26 @@ use std::os::unix::io::AsRawFd;
 use std::os::unix::io::RawFd;
 use std::os::unix::net::{UnixListener, UnixStream};
 use std::path::Path;
+use std::time::{Duration, Instant};
 
-use common::{Body, Version};
+use common::{Body, Method, Version};
 pub use common::{ConnectionError, RequestError, ServerError};
 use connection::HttpConnection;
 use request::Request;
 use response::{Response, StatusCode};
 use std::collections::HashMap;
 
+use timerfd::{ClockId, SetTimeFlags, TimerFd, TimerState};
 use utils::epoll;
 
 static SERVER_FULL_ERROR_MESSAGE: &[u8] = b"HTTP/1.1 503\r\n\
                                             Server: Firecracker API\r\n\
                                             Connection: close\r\n\
                                             Content-Length: 40\r\n\r\n{ \"error\": \"Too many open connections\" }";
-const MAX_CONNECTIONS: usize = 10;
+const DEFAULT_MAX_CONNECTIONS: usize = 10;
+// The `epoll` data of the timer which enforces the connection timeouts, chosen
+// so that it can't be mistaken for the file descriptor of a connection.
+const TIMER_EPOLL_DATA: u64 = u64::MAX;
 
 type Result<T> = std::result::Result<T, ServerError>;
 
@@ -52,7 +57,12 @@ impl ServerRequest {
     where
         F: Fn(&Request) -> Response,
     {
-        let http_response = callable(self.inner());
+        let mut http_response = callable(self.inner());
+        // The response to a `HEAD` request is the same as the one to a `GET` request,
+        // without the body.
+        if self.request.method() == Method::Head {
+            http_response.remove_body();
+        }
         ServerResponse::new(http_response, self.id)
     }
 }
@@ -77,6 +87,8 @@ impl ServerResponse {
 enum ClientConnectionState {
     AwaitingIncoming,
     AwaitingOutgoing,
+    /// The connection is closed once the outgoing bytes are written.
+    Closing,
     Closed,
 }
 
@@ -91,6 +103,11 @@ struct ClientConnection<T> {
     /// absorbed responses.
     /// This has to be `0` if we want to drop the connection.
     in_flight_response_count: u32,
+    /// When bytes were last read from or written to the connection.
+    last_activity: Instant,
+    /// When the first bytes of the request which is currently being
+    /// received arrived, if any.
+    request_start: Option<Instant>,
 }
 
 impl<T: Read + Write> ClientConnection<T> {
@@ -99,6 +116,8 @@ impl<T: Read + Write> ClientConnection<T> {
             connection,
             state: ClientConnectionState::AwaitingIncoming,
             in_flight_response_count: 0,
+            last_activity: Instant::now(),
+            request_start: None,
         }
     }
 
@@ -128,7 +147,16 @@ impl<T: Read + Write> ClientConnection<T> {
                 while let Some(_discarded_request) = self.connection.pop_parsed_request() {}
 
                 // Send an error response for the request that gave us the error.
-                let mut error_response = Response::new(Version::Http11, StatusCode::BadRequest);
+                let status_code = match inner {
+                    RequestError::HeadersTooLarge(_) | RequestError::BodyTooLarge(_) => {
+                        // The rest of the request can't be told apart from the next requests,
+                        // so the connection is closed after answering.
+                        self.state = ClientConnectionState::Closing;
+                        StatusCode::PayloadTooLarge
+                    }
+                    _ => StatusCode::BadRequest,
+                };
+                let mut error_response = Response::new(Version::Http11, status_code);
                 error_response.set_body(Body::new(
                     format!(
                         "{{ \"error\": \"{}\nAll previous unanswered requests will be dropped.\" }}",
@@ -152,10 +180,20 @@ impl<T: Read + Write> ClientConnection<T> {
         self.in_flight_response_count += parsed_requests.len() as u32;
         // If the state of the connection has changed, we need to update
         // the event set in the `epoll` structure.
-        if self.connection.pending_write() {
+        if self.connection.pending_write() && self.state == ClientConnectionState::AwaitingIncoming
+        {
             self.state = ClientConnectionState::AwaitingOutgoing;
         }
 
+        let now = Instant::now();
+        self.last_activity = now;
+        if !self.connection.pending_read() {
+            self.request_start = None;
+        } else if self.request_start.is_none() || !parsed_requests.is_empty() {
+            // The partially received request is a new one.
+            self.request_start = Some(now);
+        }
+
         Ok(parsed_requests)
     }
 
@@ -172,9 +210,13 @@ impl<T: Read + Write> ClientConnection<T> {
                 return Err(ServerError::ConnectionError(ConnectionError::InvalidWrite));
             }
             _ => {
+                self.last_activity = Instant::now();
                 // Check if we still have bytes to write for this connection.
                 if !self.connection.pending_write() {
-                    self.state = ClientConnectionState::AwaitingIncoming;
+                    self.state = match self.state {
+                        ClientConnectionState::Closing => ClientConnectionState::Closed,
+                        _ => ClientConnectionState::AwaitingIncoming,
+                    };
                 }
             }
         }
@@ -182,12 +224,56 @@ impl<T: Read + Write> ClientConnection<T> {
     }
 
     fn enqueue_response(&mut self, response: Response) {
-        if self.state != ClientConnectionState::Closed {
-            self.connection.enqueue_response(response);
+        match self.state {
+            ClientConnectionState::AwaitingIncoming | ClientConnectionState::AwaitingOutgoing => {
+                self.connection.enqueue_response(response)
+            }
+            ClientConnectionState::Closing | ClientConnectionState::Closed => {}
         }
         self.in_flight_response_count -= 1;
     }
 
+    // Returns when the connection times out, if it's waiting for the client.
+    fn deadline(
+        &self,
+        idle_timeout: Option<Duration>,
+        read_timeout: Option<Duration>,
+    ) -> Option<Instant> {
+        if self.state != ClientConnectionState::AwaitingIncoming
+            || self.in_flight_response_count != 0
+        {
+            return None;
+        }
+        match self.request_start {
+            Some(request_start) => read_timeout.map(|timeout| request_start + timeout),
+            None => idle_timeout.map(|timeout| self.last_activity + timeout),
+        }
+    }
+
+    // Closes the connection if it timed out. A client which takes too long to send
+    // a request is answered with `408 Request Timeout` before the connection is closed.
+    fn check_timeouts(
+        &mut self,
+        now: Instant,
+        idle_timeout: Option<Duration>,
+        read_timeout: Option<Duration>,
+    ) {
+        match self.deadline(idle_timeout, read_timeout) {
+            Some(deadline) if deadline <= now => (),
+            _ => return,
+        }
+        if self.request_start.is_some() {
+            let mut timeout_response = Response::new(Version::Http11, StatusCode::RequestTimeout);
+            timeout_response.set_body(Body::new(
+                "{ \"error\": \"The request was not received in time.\" }",
+            ));
+            self.connection.enqueue_response(timeout_response);
+            self.state = ClientConnectionState::Closing;
+        } else {
+            self.state = ClientConnectionState::Closed;
+        }
+    }
+
     // Returns `true` if the connection is closed and safe to drop.
     fn is_done(&self) -> bool {
         self.state == ClientConnectionState::Closed
@@ -250,6 +336,21 @@ pub struct HttpServer {
     /// We use the file descriptor of the stream as the key for mapping
     /// connections because the 1-to-1 relation is guaranteed by the OS.
     connections: HashMap<RawFd, ClientConnection<UnixStream>>,
+    /// The maximum number of open connections.
+    max_connections: usize,
+    /// The maximum size of the request line and header fields of a request,
+    /// if it differs from the `HttpConnection` default.
+    max_headers_size: Option<usize>,
+    /// The maximum size of the body of a request, if it differs from the
+    /// `HttpConnection` default.
+    max_body_size: Option<usize>,
+    /// How long a connection can wait for a new request before it is closed.
+    idle_timeout: Option<Duration>,
+    /// How long a client can take to send a whole request before it is
+    /// answered with `408 Request Timeout` and the connection is closed.
+    read_timeout: Option<Duration>,
+    /// Timer which fires when the first connection times out.
+    timer_fd: TimerFd,
 }
 
 impl HttpServer {
@@ -258,19 +359,72 @@ impl HttpServer {
     /// Returns the newly formed `HttpServer`.
     ///
     /// # Errors
-    /// Returns an `IOError` when binding or `epoll::create` fails.
+    /// Returns an `IOError` when binding, `epoll::create` or `timerfd_create` fails.
     pub fn new<P: AsRef<Path>>(path_to_socket: P) -> Result<Self> {
         let socket = UnixListener::bind(path_to_socket).map_err(ServerError::IOError)?;
         let epoll = epoll::Epoll::new().map_err(ServerError::IOError)?;
+        let timer_fd =
+            TimerFd::new_custom(ClockId::Monotonic, true, true).map_err(ServerError::IOError)?;
         Ok(Self {
             socket,
             epoll,
             connections: HashMap::new(),
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            max_headers_size: None,
+            max_body_size: None,
+            idle_timeout: None,
+            read_timeout: None,
+            timer_fd,
         })
     }
 
+    /// Sets the maximum number of open connections. The clients which connect
+    /// once this many connections are open are turned away.
+    pub fn set_max_connections(&mut self, max_connections: usize) {
+        self.max_connections = max_connections;
+    }
+
+    /// Sets the maximum size, in bytes, of the request line and header fields
+    /// of a request. Requests with larger headers are answered with
+    /// `413 Payload Too Large`, and the connection is closed.
+    ///
+    /// Only the connections accepted afterwards are affected.
+    pub fn set_max_headers_size(&mut self, max_headers_size: usize) {
+        self.max_headers_size = Some(max_headers_size);
+    }
+
+    /// Sets the maximum size, in bytes, of the body of a request. Requests with
+    /// larger bodies are answered with `413 Payload Too Large`, and the connection
+    /// is closed.
+    ///
+    /// Only the connections accepted afterwards are affected.
+    pub fn set_max_body_size(&mut self, max_body_size: usize) {
+        self.max_body_size = Some(max_body_size);
+    }
+
+    /// Sets how long a connection can wait for a new request, once the previous
+    /// ones are answered, before it is closed. There is no timeout by default.
+    pub fn set_idle_timeout(&mut self, idle_timeout: Option<Duration>) {
+        self.idle_timeout = idle_timeout;
+    }
+
+    /// Sets how long a client can take to send a whole request, from its first
+    /// byte, before it is answered with `408 Request Timeout` and the connection
+    /// is closed. There is no timeout by default.
+    pub fn set_read_timeout(&mut self, read_timeout: Option<Duration>) {
+        self.read_timeout = read_timeout;
+    }
+
     /// Starts the HTTP Server.
     pub fn start_server(&mut self) -> Result<()> {
+        // Add the timer which enforces the connection timeouts to the `epoll` structure.
+        self.epoll
+            .ctl(
+                epoll::ControlOperation::Add,
+                self.timer_fd.as_raw_fd(),
+                &epoll::EpollEvent::new(epoll::EventSet::IN, TIMER_EPOLL_DATA),
+            )
+            .map_err(ServerError::IOError)?;
         // Add the socket on which we listen for new connections to the
         // `epoll` structure.
         Self::epoll_add(&self.epoll, self.socket.as_raw_fd())
@@ -286,6 +440,7 @@ impl HttpServer {
     ///
     /// Returns a collection of complete and valid requests to be processed by the user
     /// of the server. Once processed, responses should be sent using `enqueue_responses()`.
+    /// The connections which have timed out are closed before this function returns.
     ///
     /// # Errors
     /// `IOError` is returned when `read`, `write` or `epoll::ctl` operations fail.
@@ -295,25 +450,32 @@ impl HttpServer {
     /// on a connection on which it is not possible.
     pub fn requests(&mut self) -> Result<Vec<ServerRequest>> {
         let mut parsed_requests: Vec<ServerRequest> = vec![];
-        let mut events = vec![epoll::EpollEvent::default(); MAX_CONNECTIONS];
+        // Besides the connections, the listener socket and the timer can produce events.
+        let max_events = self.max_connections + 2;
+        let mut events = vec![epoll::EpollEvent::default(); max_events];
         // This is a wrapper over the syscall `epoll_wait` and it will block the
         // current thread until at least one event is received.
         // The received notifications will then populate the `events` array with
-        // `event_count` elements, where 1 <= event_count <= MAX_CONNECTIONS.
-        let event_count = match self.epoll.wait(MAX_CONNECTIONS, -1, &mut events[..]) {
+        // `event_count` elements, where 1 <= event_count <= max_events.
+        let event_count = match self.epoll.wait(max_events, -1, &mut events[..]) {
             Ok(event_count) => event_count,
             Err(e) if e.raw_os_error() == Some(libc::EINTR) => 0,
             Err(e) => return Err(ServerError::IOError(e)),
         };
         // We use `take()` on the iterator over `events` as, even though only
         // `events_count` events have been inserted into `events`, the size of
-        // the array is still `MAX_CONNECTIONS`, so we discard empty elements
+        // the array is still `max_events`, so we discard empty elements
         // at the end of the array.
         for e in events.iter().take(event_count) {
             // Check the file descriptor which produced the notification `e`.
-            // It could be that we have a new connection, or one of our open
-            // connections is ready to exchange data with a client.
-            if e.fd() == self.socket.as_raw_fd() {
+            // It could be that we have a new connection, one of our open
+            // connections is ready to exchange data with a client, or a
+            // connection has timed out.
+            if e.data() == TIMER_EPOLL_DATA {
+                // The timed out connections are handled below, along with the
+                // ones which timed out while handling the other events.
+                self.timer_fd.read();
+            } else if e.fd() == self.socket.as_raw_fd() {
                 // We have received a notification on the listener socket, which
                 // means we have a new connection to accept.
                 match self.handle_new_connection() {
@@ -352,8 +514,12 @@ impl HttpServer {
                     // If the connection was incoming before we read and we now have to write
                     // either an error message or an `expect` response, we change its `epoll`
                     // event set to notify us when the stream is ready for writing.
-                    if client_connection.state == ClientConnectionState::AwaitingOutgoing {
-                        Self::epoll_mod(&self.epoll, fd, epoll::EventSet::OUT)?;
+                    match client_connection.state {
+                        ClientConnectionState::AwaitingOutgoing
+                        | ClientConnectionState::Closing => {
+                            Self::epoll_mod(&self.epoll, fd, epoll::EventSet::OUT)?
+                        }
+                        _ => {}
                     }
                 } else if e.event_set().contains(epoll::EventSet::OUT) {
                     // We have bytes to write on this connection.
@@ -361,13 +527,21 @@ impl HttpServer {
                     // If the connection was outgoing before we tried to write the responses
                     // and we don't have any more responses to write, we change the `epoll`
                     // event set to notify us when we have bytes to read from the stream.
-                    if client_connection.state == ClientConnectionState::AwaitingIncoming {
-                        Self::epoll_mod(&self.epoll, fd, epoll::EventSet::IN)?;
+                    match client_connection.state {
+                        ClientConnectionState::AwaitingIncoming => {
+                            Self::epoll_mod(&self.epoll, fd, epoll::EventSet::IN)?
+                        }
+                        // A connection which is closed while it's still waiting for responses
+                        // is kept around until they arrive, but it shouldn't be polled anymore.
+                        ClientConnectionState::Closed => Self::epoll_del(&self.epoll, fd)?,
+                        _ => {}
                     }
                 }
             }
         }
 
+        self.handle_timeouts()?;
+
         // Remove dead connections.
         self.connections
             .retain(|_, client_connection| !client_connection.is_done());
@@ -375,6 +549,40 @@ impl HttpServer {
         Ok(parsed_requests)
     }
 
+    /// Closes the connections which have timed out, then sets the timer to fire
+    /// when the next connection times out.
+    ///
+    /// # Errors
+    /// `IOError` is returned when an `epoll::ctl` operation fails.
+    fn handle_timeouts(&mut self) -> Result<()> {
+        let now = Instant::now();
+        let (idle_timeout, read_timeout) = (self.idle_timeout, self.read_timeout);
+        let mut next_deadline: Option<Instant> = None;
+        for (fd, client_connection) in self.connections.iter_mut() {
+            client_connection.check_timeouts(now, idle_timeout, read_timeout);
+            if client_connection.state == ClientConnectionState::Closing {
+                Self::epoll_mod(&self.epoll, *fd, epoll::EventSet::OUT)?;
+            }
+            if let Some(deadline) = client_connection.deadline(idle_timeout, read_timeout) {
+                next_deadline = Some(match next_deadline {
+                    Some(next_deadline) if next_deadline < deadline => next_deadline,
+                    _ => deadline,
+                });
+            }
+        }
+
+        let timer_state = match next_deadline {
+            // A zero duration would disarm the timer.
+            Some(deadline) => TimerState::Oneshot(std::cmp::max(
+                deadline.saturating_duration_since(now),
+                Duration::from_millis(1),
+            )),
+            None => TimerState::Disarmed,
+        };
+        self.timer_fd.set_state(timer_state, SetTimeFlags::Default);
+        Ok(())
+    }
+
     /// The file descriptor of the `epoll` structure can enable the server to become
     /// a non-blocking structure in an application.
     ///
@@ -472,7 +680,7 @@ impl HttpServer {
     /// `IOError` is returned when socket or epoll operations fail.
     /// `ServerFull` is returned if server full capacity has been reached.
     fn handle_new_connection(&mut self) -> Result<()> {
-        if self.connections.len() == MAX_CONNECTIONS {
+        if self.connections.len() >= self.max_connections {
             // If we want a replacement policy for connections
             // this is where we will have it.
             return Err(ServerError::ServerFull);
@@ -492,10 +700,16 @@ impl HttpServer {
                 // Add the stream to the `epoll` structure and listen for bytes to be read.
                 Self::epoll_add(&self.epoll, stream.as_raw_fd())?;
                 // Then add it to our open connections.
-                self.connections.insert(
-                    stream.as_raw_fd(),
-                    ClientConnection::new(HttpConnection::new(stream)),
-                );
+                let fd = stream.as_raw_fd();
+                let mut connection = HttpConnection::new(stream);
+                if let Some(max_headers_size) = self.max_headers_size {
+                    connection.set_max_headers_size(max_headers_size);
+                }
+                if let Some(max_body_size) = self.max_body_size {
+                    connection.set_max_body_size(max_body_size);
+                }
+                self.connections
+                    .insert(fd, ClientConnection::new(connection));
                 Ok(())
             })
     }
@@ -512,6 +726,20 @@ impl HttpServer {
             .map_err(ServerError::IOError)
     }
 
+    /// Removes a stream from the `epoll` notification structure.
+    ///
+    /// # Errors
+    /// `IOError` is returned when an `EPOLL_CTL_DEL` control operation fails.
+    fn epoll_del(epoll: &epoll::Epoll, stream_fd: RawFd) -> Result<()> {
+        epoll
+            .ctl(
+                epoll::ControlOperation::Delete,
+                stream_fd,
+                &epoll::EpollEvent::default(),
+            )
+            .map_err(ServerError::IOError)
+    }
+
     /// Adds a stream to the `epoll` notification structure with the `EPOLLIN` event set.
     ///
     /// # Errors
@@ -578,6 +806,46 @@ mod tests {
         assert!(socket.read(&mut buf[..]).unwrap() > 0);
     }
 
+    #[test]
+    fn test_wait_head_request() {
+        let path_to_socket = get_path_to_socket();
+
+        let mut server = HttpServer::new(path_to_socket.as_path()).unwrap();
+        server.start_server().unwrap();
+
+        let mut socket = UnixStream::connect(path_to_socket.as_path()).unwrap();
+        assert!(server.requests().unwrap().is_empty());
+
+        socket
+            .write_all(b"HEAD /machine-config HTTP/1.1\r\n\r\n")
+            .unwrap();
+
+        let mut req_vec = server.requests().unwrap();
+        let server_request = req_vec.remove(0);
+        assert_eq!(server_request.inner().method(), Method::Head);
+
+        server
+            .respond(server_request.process(|_request| {
+                let mut response = Response::new(Version::Http11, StatusCode::OK);
+                response.set_body(Body::new(b"response body".to_vec()));
+                response
+            }))
+            .unwrap();
+        assert!(server.requests().unwrap().is_empty());
+
+        // The headers describe the body, but the body itself is not sent.
+        let mut buf: [u8; 1024] = [0; 1024];
+        let len = socket.read(&mut buf[..]).unwrap();
+        assert_eq!(
+            &buf[..len],
+            &b"HTTP/1.1 200 \r\n\
+               Server: Firecracker API\r\n\
+               Connection: keep-alive\r\n\
+               Content-Type: application/json\r\n\
+               Content-Length: 13\r\n\r\n"[..]
+        );
+    }
+
     #[test]
     fn test_wait_concurrent_connections() {
         let path_to_socket = get_path_to_socket();
@@ -707,7 +975,7 @@ mod tests {
         server.start_server().unwrap();
 
         let mut sockets: Vec<UnixStream> = Vec::with_capacity(11);
-        for _ in 0..MAX_CONNECTIONS {
+        for _ in 0..DEFAULT_MAX_CONNECTIONS {
             sockets.push(UnixStream::connect(path_to_socket.as_path()).unwrap());
             assert!(server.requests().unwrap().is_empty());
         }
@@ -715,10 +983,129 @@ mod tests {
         sockets.push(UnixStream::connect(path_to_socket.as_path()).unwrap());
         assert!(server.requests().unwrap().is_empty());
         let mut buf: [u8; 120] = [0; 120];
-        sockets[MAX_CONNECTIONS].read_exact(&mut buf).unwrap();
+        sockets[DEFAULT_MAX_CONNECTIONS]
+            .read_exact(&mut buf)
+            .unwrap();
+        assert_eq!(&buf[..], SERVER_FULL_ERROR_MESSAGE);
+
+        // Test a custom limit.
+        let path_to_socket = get_path_to_socket();
+        let mut server = HttpServer::new(path_to_socket.as_path()).unwrap();
+        server.set_max_connections(1);
+        server.start_server().unwrap();
+
+        let _socket = UnixStream::connect(path_to_socket.as_path()).unwrap();
+        assert!(server.requests().unwrap().is_empty());
+        let mut socket = UnixStream::connect(path_to_socket.as_path()).unwrap();
+        assert!(server.requests().unwrap().is_empty());
+        socket.read_exact(&mut buf).unwrap();
         assert_eq!(&buf[..], SERVER_FULL_ERROR_MESSAGE);
     }
 
+    #[test]
+    fn test_wait_size_limits() {
+        let path_to_socket = get_path_to_socket();
+
+        let mut server = HttpServer::new(path_to_socket.as_path()).unwrap();
+        server.set_max_headers_size(64);
+        server.set_max_body_size(4);
+        server.start_server().unwrap();
+
+        let mut socket = UnixStream::connect(path_to_socket.as_path()).unwrap();
+        assert!(server.requests().unwrap().is_empty());
+
+        // Bodies larger than the limit are answered with `413 Payload Too Large`,
+        // and the connection is closed.
+        socket
+            .write_all(
+                b"PUT /mmds HTTP/1.1\r\n\
+                  Content-Length: 5\r\n\r\nhello\
+                  GET /mmds HTTP/1.1\r\n\r\n",
+            )
+            .unwrap();
+        assert!(server.requests().unwrap().is_empty());
+        assert!(server.requests().unwrap().is_empty());
+
+        let mut buf = vec![];
+        socket.read_to_end(&mut buf).unwrap();
+        let response = String::from_utf8(buf).unwrap();
+        assert!(response.starts_with("HTTP/1.1 413 \r\n"));
+        assert!(response.contains("Request body is larger than the limit of 4 bytes."));
+        assert!(server.connections.is_empty());
+
+        // So are headers larger than the limit.
+        let mut socket = UnixStream::connect(path_to_socket.as_path()).unwrap();
+        assert!(server.requests().unwrap().is_empty());
+        socket
+            .write_all(
+                b"GET /mmds HTTP/1.1\r\n\
+                  Custom-Header: 0123456789012345678901234567890123456789\r\n\r\n",
+            )
+            .unwrap();
+        assert!(server.requests().unwrap().is_empty());
+        assert!(server.requests().unwrap().is_empty());
+
+        let mut buf = vec![];
+        socket.read_to_end(&mut buf).unwrap();
+        let response = String::from_utf8(buf).unwrap();
+        assert!(response.starts_with("HTTP/1.1 413 \r\n"));
+        assert!(response.contains("Request headers are larger than the limit of 64 bytes."));
+    }
+
+    #[test]
+    fn test_wait_timeouts() {
+        let path_to_socket = get_path_to_socket();
+
+        let mut server = HttpServer::new(path_to_socket.as_path()).unwrap();
+        server.set_idle_timeout(Some(Duration::from_millis(100)));
+        server.set_read_timeout(Some(Duration::from_millis(50)));
+        server.start_server().unwrap();
+
+        // Clients which take too long to send a request are answered with
+        // `408 Request Timeout`, and the connection is closed.
+        let mut socket = UnixStream::connect(path_to_socket.as_path()).unwrap();
+        assert!(server.requests().unwrap().is_empty());
+        socket.write_all(b"GET /mmds HTTP/1.1\r\n").unwrap();
+        let start = Instant::now();
+        assert!(server.requests().unwrap().is_empty());
+        // The timer fires, then the response is written.
+        assert!(server.requests().unwrap().is_empty());
+        assert!(server.requests().unwrap().is_empty());
+        assert!(start.elapsed() >= Duration::from_millis(50));
+
+        let mut buf = vec![];
+        socket.read_to_end(&mut buf).unwrap();
+        let response = String::from_utf8(buf).unwrap();
+        assert!(response.starts_with("HTTP/1.1 408 \r\n"));
+        assert!(server.connections.is_empty());
+
+        // Idle connections are closed, but only once the responses are written.
+        let mut socket = UnixStream::connect(path_to_socket.as_path()).unwrap();
+        assert!(server.requests().unwrap().is_empty());
+        socket.write_all(b"GET /mmds HTTP/1.1\r\n\r\n").unwrap();
+        let server_request = server.requests().unwrap().remove(0);
+        std::thread::sleep(Duration::from_millis(150));
+        // The connection doesn't time out while the request is being handled.
+        server
+            .respond(
+                server_request
+                    .process(|_request| Response::new(Version::Http11, StatusCode::NoContent)),
+            )
+            .unwrap();
+        let start = Instant::now();
+        assert!(server.requests().unwrap().is_empty());
+        assert_eq!(server.connections.len(), 1);
+        // The timer fires.
+        assert!(server.requests().unwrap().is_empty());
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        assert!(server.connections.is_empty());
+
+        let mut buf = vec![];
+        socket.read_to_end(&mut buf).unwrap();
+        let response = String::from_utf8(buf).unwrap();
+        assert!(response.starts_with("HTTP/1.1 204 \r\n"));
+    }
+
     #[test]
     fn test_wait_parse_error() {
         let path_to_socket = get_path_to_socket();
@@ -2,23 +2,74 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use serde_json::Value;
+use std::collections::BTreeMap;
 use std::fmt;
+use std::sync::{Arc, Mutex, Weak};
+
+use logger::METRICS;
+use token::{Error as TokenError, TokenAuthority};
+use utils::eventfd::EventFd;
+
+/// The default limit of the serialized data store size, in bytes.
+pub const DEFAULT_DATA_STORE_LIMIT: usize = 51200;
 
 /// The Mmds is the Microvm Metadata Service represented as an untyped json.
 #[derive(Clone)]
 pub struct Mmds {
     data_store: Value,
+    // The maximum size of the serialized data store, in bytes.
+    data_store_limit: usize,
+    // Incremented whenever the data store contents are updated.
+    data_store_version: u64,
     is_initialized: bool,
+    version: MmdsVersion,
+    token_authority: TokenAuthority,
+    // Notified whenever the data store contents are updated.
+    update_listeners: Vec<Weak<EventFd>>,
+    // Restricts the guest access to the subtrees found at the given JSON pointers.
+    access_policies: BTreeMap<String, AccessPolicy>,
+}
+
+/// The ways in which guest requests can be authorized to read the MMDS contents.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MmdsVersion {
+    /// Requests are not authorized, and session tokens are not issued.
+    V1,
+    /// Requests must present a valid session token.
+    V2,
+    /// Session tokens are issued, and validated when presented, but they are not mandatory.
+    Both,
+}
+
+/// The restrictions which can be placed on the guest access to a subtree of the data store. The
+/// host can always read the whole data store.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AccessPolicy {
+    /// The subtree is never exposed to guests.
+    Hidden,
+    /// The subtree is only exposed to the guest requests which present a valid session token.
+    TokenRequired,
+}
+
+impl AccessPolicy {
+    fn allows(self, with_token: bool) -> bool {
+        match self {
+            AccessPolicy::Hidden => false,
+            AccessPolicy::TokenRequired => with_token,
+        }
+    }
 }
 
 /// MMDS possible outputs.
 pub enum OutputFormat {
     Json,
     Imds,
+    Yaml,
 }
 
 #[derive(Debug, PartialEq)]
 pub enum Error {
+    DataStoreLimitExceeded(usize),
     NotFound,
     NotInitialized,
     UnsupportedValueType,
@@ -27,6 +78,11 @@ pub enum Error {
 impl fmt::Display for Error {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match *self {
+            Error::DataStoreLimitExceeded(limit) => write!(
+                f,
+                "The MMDS data store would exceed its size limit of {} bytes.",
+                limit
+            ),
             Error::NotFound => write!(f, "The MMDS resource does not exist."),
             Error::NotInitialized => write!(f, "The MMDS data store is not initialized."),
             Error::UnsupportedValueType => write!(
@@ -41,7 +97,13 @@ impl Default for Mmds {
     fn default() -> Self {
         Mmds {
             data_store: Value::default(),
+            data_store_limit: DEFAULT_DATA_STORE_LIMIT,
+            data_store_version: 0,
             is_initialized: false,
+            version: MmdsVersion::V1,
+            token_authority: TokenAuthority::default(),
+            update_listeners: Vec::new(),
+            access_policies: BTreeMap::new(),
         }
     }
 }
@@ -58,18 +120,107 @@ impl Mmds {
         }
     }
 
+    /// Returns the serialized size of `data`, or an error if it is over the data store limit.
+    fn check_data_store_limit(&self, data: &Value) -> Result<usize, Error> {
+        let size = data.to_string().len();
+        if size > self.data_store_limit {
+            return Err(Error::DataStoreLimitExceeded(self.data_store_limit));
+        }
+        Ok(size)
+    }
+
+    /// Bumps the data store version, and notifies the listeners which are still around.
+    fn data_store_updated(&mut self) {
+        self.data_store_version += 1;
+        self.update_listeners
+            .retain(|listener| match listener.upgrade() {
+                Some(evt) => {
+                    // A listener whose counter is about to overflow already has an update
+                    // pending, so the error can be ignored.
+                    let _ = evt.write(1);
+                    true
+                }
+                None => false,
+            });
+    }
+
     pub fn put_data(&mut self, data: Value) -> Result<(), Error> {
+        let size = self.check_data_store_limit(&data)?;
         self.data_store = data;
         self.is_initialized = true;
+        METRICS.mmds.data_store_size.store(size);
+        self.data_store_updated();
         Ok(())
     }
 
     pub fn patch_data(&mut self, patch_data: Value) -> Result<(), Error> {
         self.check_data_store_initialized()?;
-        super::json_patch(&mut self.data_store, &patch_data);
+        // The patch is applied to a copy of the data store, which is dropped if it ends up
+        // over the limit.
+        let mut data = self.data_store.clone();
+        super::json_patch(&mut data, &patch_data);
+        let size = self.check_data_store_limit(&data)?;
+        self.data_store = data;
+        METRICS.mmds.data_store_size.store(size);
+        self.data_store_updated();
+        Ok(())
+    }
+
+    /// Returns the version of the data store contents, which is incremented by every
+    /// successful update.
+    pub fn data_store_version(&self) -> u64 {
+        self.data_store_version
+    }
+
+    /// Registers `evt` to be written to whenever the data store contents are updated. The
+    /// MMDS only keeps a weak reference to `evt`, so dropping it is enough to unregister.
+    pub fn add_update_listener(&mut self, evt: &Arc<EventFd>) {
+        // The dropped listeners are also forgotten here, so that they don't pile up while
+        // the data store isn't updated.
+        self.update_listeners
+            .retain(|listener| listener.upgrade().is_some());
+        self.update_listeners.push(Arc::downgrade(evt));
+    }
+
+    /// Sets the maximum size of the serialized data store, in bytes. The limit cannot be set
+    /// below the size of the current contents.
+    pub fn set_data_store_limit(&mut self, limit: usize) -> Result<(), Error> {
+        if self.is_initialized && self.data_store.to_string().len() > limit {
+            return Err(Error::DataStoreLimitExceeded(limit));
+        }
+        self.data_store_limit = limit;
         Ok(())
     }
 
+    /// Sets the way in which guest requests are authorized.
+    pub fn set_version(&mut self, version: MmdsVersion) {
+        self.version = version;
+    }
+
+    /// Returns the way in which guest requests are authorized.
+    pub fn version(&self) -> MmdsVersion {
+        self.version
+    }
+
+    /// Issues a session token, valid for the next `ttl_seconds` seconds.
+    pub fn generate_token(&mut self, ttl_seconds: u32) -> Result<String, TokenError> {
+        self.token_authority.generate_token(ttl_seconds)
+    }
+
+    /// Returns `true` if `token` is a session token issued by this MMDS, which hasn't expired.
+    pub fn is_valid_token(&self, token: &str) -> bool {
+        self.token_authority.is_valid(token)
+    }
+
+    /// Replaces the access policies, which restrict the guest access to the subtrees found at
+    /// the given JSON pointers. The subtrees don't have to exist yet.
+    pub fn set_access_policies(&mut self, policies: BTreeMap<String, AccessPolicy>) {
+        self.access_policies = policies
+            .into_iter()
+            .map(|(path, policy)| (path.trim_end_matches('/').to_string(), policy))
+            .collect();
+    }
+
     pub fn get_data_str(&self) -> String {
         if self.data_store.is_null() {
             return String::from("{}");
@@ -78,7 +229,8 @@ impl Mmds {
     }
 
     /// Returns the serde::Value in IMDS format plaintext.
-    /// Currently, only JSON objects and strings can be IMDS formatted.
+    /// Objects are listed by their keys, arrays by their elements, one per line, while strings,
+    /// numbers and booleans are returned as they are.
     ///
     /// See the docs for detailed description of the IMDS format:
     /// https://docs.aws.amazon.com/AWSEC2/latest/UserGuide/ec2-instance-metadata.html
@@ -113,13 +265,23 @@ impl Mmds {
     /// value
     /// ```
     ///
-    /// If the `serde_json::Value` is not supported, an `UnsupportedValueType` error is returned.
+    /// JSON array:
+    /// ```json
+    /// ["value1", 2, true]
+    /// ```
+    ///
+    /// IMDS formatted array:
+    /// ```text
+    /// value1
+    /// 2
+    /// true
+    /// ```
+    ///
+    /// Nulls, and arrays holding anything but strings, numbers and booleans, have no IMDS
+    /// representation, so an `UnsupportedValueType` error is returned for them.
     fn format_imds(json: &Value) -> Result<String, Error> {
-        // If the `dict` is Value::Null, Error::NotFound is thrown.
-        // If the `dict` is not a dictionary, a Vec with the value corresponding to
-        // the key is returned.
-        match json.as_object() {
-            Some(map) => {
+        match json {
+            Value::Object(map) => {
                 let mut ret = Vec::new();
                 // When the object is a map, push all the keys in the Vec.
                 for key in map.keys() {
@@ -134,41 +296,256 @@ impl Mmds {
                 }
                 Ok(ret.join("\n"))
             }
-            None => {
-                // When the object is not a map, return the value.
-                // Support only `Value::String`.
-                match json.as_str() {
-                    Some(str_val) => Ok(str_val.to_string()),
-                    None => Err(Error::UnsupportedValueType),
-                }
+            Value::Array(values) => {
+                let ret = values
+                    .iter()
+                    .map(Mmds::format_imds_scalar)
+                    .collect::<Result<Vec<String>, Error>>()?;
+                Ok(ret.join("\n"))
             }
+            _ => Mmds::format_imds_scalar(json),
         }
     }
 
+    /// Returns a string, number or boolean `serde_json::Value` in IMDS format plaintext.
+    fn format_imds_scalar(json: &Value) -> Result<String, Error> {
+        match json {
+            Value::String(str_val) => Ok(str_val.clone()),
+            Value::Number(_) | Value::Bool(_) => Ok(json.to_string()),
+            _ => Err(Error::UnsupportedValueType),
+        }
+    }
+
+    /// Returns the serde::Value as a YAML document, for consumers such as cloud-init.
+    ///
+    /// Objects and arrays are written as block mappings and sequences, indented by two spaces
+    /// per level. Strings are left unquoted when that can't change their meaning, and are
+    /// written as double-quoted JSON strings otherwise, which YAML parses back the same way.
+    ///
+    /// # Examples
+    ///
+    /// ```json
+    /// {
+    ///     "key1": {
+    ///         "key11": "value11"
+    ///     },
+    ///     "key2": ["value21", 22]
+    /// }
+    /// ```
+    ///
+    /// YAML formatted JSON object:
+    /// ```text
+    /// key1:
+    ///   key11: value11
+    /// key2:
+    ///   - value21
+    ///   - 22
+    /// ```
+    fn format_yaml(json: &Value) -> String {
+        yaml_lines(json).join("\n")
+    }
+
     /// Returns the subtree located at path. When the path corresponds to a leaf, it returns the value.
     /// Returns Error::NotFound when the path is invalid.
-    pub fn get_value(&self, path: String, format: OutputFormat) -> Result<String, Error> {
+    ///
+    /// The access policies are enforced, given whether the guest request presented a valid session
+    /// token. Subtrees the request can't read are left out of the value, and are reported as not
+    /// found when requested directly.
+    pub fn get_value(
+        &self,
+        path: String,
+        format: OutputFormat,
+        with_token: bool,
+    ) -> Result<String, Error> {
         // The pointer function splits the input by "/". With a trailing "/", pointer does not
         // know how to get the object.
-        let value = if path.ends_with('/') {
-            self.data_store.pointer(&path.as_str()[..(path.len() - 1)])
+        let path = if path.ends_with('/') {
+            &path.as_str()[..(path.len() - 1)]
         } else {
-            self.data_store.pointer(path.as_str())
+            path.as_str()
         };
 
-        if let Some(json) = value {
-            match format {
-                OutputFormat::Json => Ok(json.to_string()),
-                OutputFormat::Imds => Mmds::format_imds(json),
+        let denied_paths: Vec<&str> = self
+            .access_policies
+            .iter()
+            .filter(|(_, policy)| !policy.allows(with_token))
+            .map(|(denied_path, _)| denied_path.as_str())
+            .collect();
+        if denied_paths
+            .iter()
+            .any(|denied_path| is_subtree_path(path, denied_path))
+        {
+            return Err(Error::NotFound);
+        }
+
+        let json = self.data_store.pointer(path).ok_or(Error::NotFound)?;
+        // The value is only copied when some of its subtrees have to be left out.
+        let mut restricted_json = None;
+        for denied_path in denied_paths {
+            if is_subtree_path(denied_path, path) {
+                remove_subtree(
+                    restricted_json.get_or_insert_with(|| json.clone()),
+                    &denied_path[path.len()..],
+                );
+            }
+        }
+        let json = restricted_json.as_ref().unwrap_or(json);
+
+        match format {
+            OutputFormat::Json => Ok(json.to_string()),
+            OutputFormat::Imds => Mmds::format_imds(json),
+            OutputFormat::Yaml => Ok(Mmds::format_yaml(json)),
+        }
+    }
+}
+
+/// Returns `true` if `path` points to the subtree found at `root`, or to one of its descendants.
+fn is_subtree_path(path: &str, root: &str) -> bool {
+    path.starts_with(root) && (path.len() == root.len() || path[root.len()..].starts_with('/'))
+}
+
+/// Removes the subtree found at the JSON pointer `path`, relative to `json`, if there is one.
+fn remove_subtree(json: &mut Value, path: &str) {
+    if let Some(key_start) = path.rfind('/') {
+        // JSON pointers escape the '~' and '/' characters in key names.
+        let key = path[key_start + 1..].replace("~1", "/").replace("~0", "~");
+        if let Some(Value::Object(map)) = json.pointer_mut(&path[..key_start]) {
+            map.remove(&key);
+        }
+    }
+}
+
+/// Returns the lines of the YAML representation of `json`, without any indentation of their own.
+fn yaml_lines(json: &Value) -> Vec<String> {
+    let mut lines = Vec::new();
+    match json {
+        Value::Object(map) if !map.is_empty() => {
+            for (key, value) in map {
+                let key = yaml_string(key);
+                if is_yaml_collection(value) {
+                    lines.push(format!("{}:", key));
+                    lines.extend(yaml_lines(value).iter().map(|line| format!("  {}", line)));
+                } else {
+                    lines.push(format!("{}: {}", key, yaml_scalar(value)));
+                }
+            }
+        }
+        Value::Array(values) if !values.is_empty() => {
+            for value in values {
+                if is_yaml_collection(value) {
+                    // The first line of a nested collection follows the item marker, and the
+                    // rest are aligned with it.
+                    for (i, line) in yaml_lines(value).iter().enumerate() {
+                        let prefix = if i == 0 { "- " } else { "  " };
+                        lines.push(format!("{}{}", prefix, line));
+                    }
+                } else {
+                    lines.push(format!("- {}", yaml_scalar(value)));
+                }
             }
-        } else {
-            Err(Error::NotFound)
         }
+        _ => lines.push(yaml_scalar(json)),
+    }
+    lines
+}
+
+/// Returns `true` for the values written as YAML blocks, which are the non-empty objects and
+/// arrays.
+fn is_yaml_collection(json: &Value) -> bool {
+    match json {
+        Value::Object(map) => !map.is_empty(),
+        Value::Array(values) => !values.is_empty(),
+        _ => false,
+    }
+}
+
+/// Returns the single line YAML representation of a value which isn't written as a block.
+fn yaml_scalar(json: &Value) -> String {
+    match json {
+        Value::String(str_val) => yaml_string(str_val),
+        Value::Object(_) => "{}".to_string(),
+        Value::Array(_) => "[]".to_string(),
+        // Nulls, booleans and numbers are written the same way in JSON and YAML.
+        _ => json.to_string(),
+    }
+}
+
+/// Returns the YAML representation of a string, quoting it unless it only holds characters
+/// without a special meaning, and can't be mistaken for a null, boolean or number.
+fn yaml_string(str_val: &str) -> String {
+    let plain = str_val.starts_with(|c: char| c.is_ascii_alphabetic() || c == '/')
+        && str_val
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_./".contains(c))
+        && !["y", "n", "yes", "no", "on", "off", "true", "false", "null"]
+            .contains(&str_val.to_ascii_lowercase().as_str());
+    if plain {
+        str_val.to_string()
+    } else {
+        Value::String(str_val.to_string()).to_string()
+    }
+}
+
+/// The MMDS instances of a microVM, shared by the API, which manages their contents, and by the
+/// devices serving them to the guest. Besides the default instance, network interfaces can be
+/// served instances of their own, identified by an ID. Clones share the same instances.
+#[derive(Clone, Default)]
+pub struct MmdsInstances {
+    default: Arc<Mutex<Mmds>>,
+    named: Arc<Mutex<BTreeMap<String, Arc<Mutex<Mmds>>>>>,
+}
+
+impl MmdsInstances {
+    /// Returns the default instance.
+    pub fn default_instance(&self) -> Arc<Mutex<Mmds>> {
+        self.default.clone()
+    }
+
+    /// Returns the instance identified by `id`, if it exists, or the default instance if `id` is
+    /// `None`.
+    pub fn get(&self, id: Option<&str>) -> Option<Arc<Mutex<Mmds>>> {
+        match id {
+            Some(id) => self.named.lock().expect("Poisoned lock").get(id).cloned(),
+            None => Some(self.default_instance()),
+        }
+    }
+
+    /// Returns the instance identified by `id`, creating it if it does not exist yet, or the
+    /// default instance if `id` is `None`. New instances start out empty, and enforce the same
+    /// data store limit, version and access policies as the default instance.
+    pub fn get_or_create(&self, id: Option<&str>) -> Arc<Mutex<Mmds>> {
+        let id = match id {
+            Some(id) => id,
+            None => return self.default_instance(),
+        };
+        self.named
+            .lock()
+            .expect("Poisoned lock")
+            .entry(id.to_string())
+            .or_insert_with(|| {
+                let default = self.default.lock().expect("Poisoned lock");
+                Arc::new(Mutex::new(Mmds {
+                    data_store_limit: default.data_store_limit,
+                    version: default.version,
+                    access_policies: default.access_policies.clone(),
+                    ..Mmds::default()
+                }))
+            })
+            .clone()
+    }
+
+    /// Returns all the instances, starting with the default one.
+    pub fn all(&self) -> Vec<Arc<Mutex<Mmds>>> {
+        let mut instances = vec![self.default_instance()];
+        instances.extend(self.named.lock().expect("Poisoned lock").values().cloned());
+        instances
     }
 }
 
 #[cfg(test)]
 mod tests {
+    extern crate libc;
+
     use super::*;
     use serde_json;
 
@@ -195,6 +572,43 @@ mod tests {
             .unwrap();
         mmds_json = "{\"meta-data\":{\"iam\":\"dummy\"},\"user-data\":\"10\"}";
         assert_eq!(mmds.get_data_str(), mmds_json);
+
+        // Test the data store size limit.
+        assert_eq!(
+            mmds.set_data_store_limit(mmds_json.len() - 1),
+            Err(Error::DataStoreLimitExceeded(mmds_json.len() - 1))
+        );
+        assert_eq!(
+            Error::DataStoreLimitExceeded(10).to_string(),
+            "The MMDS data store would exceed its size limit of 10 bytes."
+        );
+        mmds.set_data_store_limit(mmds_json.len()).unwrap();
+        // An oversized patch leaves the data store untouched.
+        let patch_json = "{\"user-data\":\"100\"}";
+        assert_eq!(
+            mmds.patch_data(serde_json::from_str(patch_json).unwrap()),
+            Err(Error::DataStoreLimitExceeded(mmds_json.len()))
+        );
+        assert_eq!(mmds.get_data_str(), mmds_json);
+        let put_json = "{\"meta-data\":{\"iam\":\"dummy\"},\"user-data\":\"100\"}";
+        assert_eq!(
+            mmds.put_data(serde_json::from_str(put_json).unwrap()),
+            Err(Error::DataStoreLimitExceeded(mmds_json.len()))
+        );
+        assert_eq!(mmds.get_data_str(), mmds_json);
+        let put_json = "{\"user-data\":\"100\"}";
+        mmds.put_data(serde_json::from_str(put_json).unwrap())
+            .unwrap();
+        assert_eq!(mmds.get_data_str(), put_json);
+
+        assert_eq!(mmds.version(), MmdsVersion::V1);
+        mmds.set_version(MmdsVersion::V2);
+        assert_eq!(mmds.version(), MmdsVersion::V2);
+
+        let token = mmds.generate_token(60).unwrap();
+        assert!(mmds.is_valid_token(&token));
+        assert!(!mmds.is_valid_token("invalid-token"));
+        assert!(mmds.generate_token(0).is_err());
     }
 
     #[test]
@@ -219,11 +633,11 @@ mod tests {
 
         // Test invalid path.
         assert_eq!(
-            mmds.get_value("/invalid_path".to_string(), OutputFormat::Json),
+            mmds.get_value("/invalid_path".to_string(), OutputFormat::Json, false),
             Err(Error::NotFound)
         );
         assert_eq!(
-            mmds.get_value("/invalid_path".to_string(), OutputFormat::Imds),
+            mmds.get_value("/invalid_path".to_string(), OutputFormat::Imds, false),
             Err(Error::NotFound)
         );
 
@@ -235,28 +649,27 @@ mod tests {
         .to_string();
         expected_json.retain(|c| !c.is_whitespace());
         assert_eq!(
-            mmds.get_value("/name".to_string(), OutputFormat::Json)
+            mmds.get_value("/name".to_string(), OutputFormat::Json, false)
                 .unwrap(),
             expected_json
         );
         let expected_imds = "first\nsecond";
         assert_eq!(
-            mmds.get_value("/name".to_string(), OutputFormat::Imds)
+            mmds.get_value("/name".to_string(), OutputFormat::Imds, false)
                 .unwrap(),
             expected_imds
         );
 
         // Retrieve an integer.
         assert_eq!(
-            mmds.get_value("/age".to_string(), OutputFormat::Json)
+            mmds.get_value("/age".to_string(), OutputFormat::Json, false)
                 .unwrap(),
             "43"
         );
         assert_eq!(
-            mmds.get_value("/age".to_string(), OutputFormat::Imds)
-                .err()
+            mmds.get_value("/age".to_string(), OutputFormat::Imds, false)
                 .unwrap(),
-            Error::UnsupportedValueType
+            "43"
         );
 
         // Test path ends with /; Value is a dictionary.
@@ -268,79 +681,156 @@ mod tests {
         .to_string();
         expected.retain(|c| !c.is_whitespace());
         assert_eq!(
-            mmds.get_value("/phones/".to_string(), OutputFormat::Json)
+            mmds.get_value("/phones/".to_string(), OutputFormat::Json, false)
                 .unwrap(),
             expected
         );
         assert_eq!(
-            mmds.get_value("/phones/".to_string(), OutputFormat::Imds)
-                .err()
+            mmds.get_value("/phones/".to_string(), OutputFormat::Imds, false)
                 .unwrap(),
-            Error::UnsupportedValueType
+            "+401234567\n+441234567"
         );
 
         // Test path does NOT end with /; Value is a dictionary.
         assert_eq!(
-            mmds.get_value("/phones".to_string(), OutputFormat::Json)
+            mmds.get_value("/phones".to_string(), OutputFormat::Json, false)
                 .unwrap(),
             expected
         );
         assert_eq!(
-            mmds.get_value("/phones".to_string(), OutputFormat::Imds)
-                .err()
+            mmds.get_value("/phones".to_string(), OutputFormat::Imds, false)
                 .unwrap(),
-            Error::UnsupportedValueType
+            "+401234567\n+441234567"
         );
 
         // Retrieve the first element of an array.
         assert_eq!(
-            mmds.get_value("/phones/0/".to_string(), OutputFormat::Json)
+            mmds.get_value("/phones/0/".to_string(), OutputFormat::Json, false)
                 .unwrap(),
             "\"+401234567\""
         );
         assert_eq!(
-            mmds.get_value("/phones/0/".to_string(), OutputFormat::Imds)
+            mmds.get_value("/phones/0/".to_string(), OutputFormat::Imds, false)
                 .unwrap(),
             "+401234567"
         );
 
         // Retrieve a boolean.
         assert_eq!(
-            mmds.get_value("/member".to_string(), OutputFormat::Json)
+            mmds.get_value("/member".to_string(), OutputFormat::Json, false)
                 .unwrap(),
             "false"
         );
         assert_eq!(
-            mmds.get_value("/member".to_string(), OutputFormat::Imds)
-                .err()
+            mmds.get_value("/member".to_string(), OutputFormat::Imds, false)
                 .unwrap(),
-            Error::UnsupportedValueType
+            "false"
         );
 
         // Retrieve a float.
         assert_eq!(
-            mmds.get_value("/shares_percentage".to_string(), OutputFormat::Json)
+            mmds.get_value("/shares_percentage".to_string(), OutputFormat::Json, false)
                 .unwrap(),
             "12.12"
         );
         assert_eq!(
-            mmds.get_value("/shares_percentage".to_string(), OutputFormat::Imds)
-                .err()
+            mmds.get_value("/shares_percentage".to_string(), OutputFormat::Imds, false)
                 .unwrap(),
-            Error::UnsupportedValueType
+            "12.12"
         );
 
         // Retrieve a negative integer.
         assert_eq!(
-            mmds.get_value("/balance".to_string(), OutputFormat::Json)
+            mmds.get_value("/balance".to_string(), OutputFormat::Json, false)
+                .unwrap(),
+            "-24"
+        );
+        assert_eq!(
+            mmds.get_value("/balance".to_string(), OutputFormat::Imds, false)
                 .unwrap(),
             "-24"
         );
+
+        // Nulls and arrays of collections can't be IMDS formatted.
+        let data = r#"{
+            "nothing": null,
+            "interfaces": [{"mac": "06:00:ac:10:00:02"}]
+        }"#;
+        mmds.put_data(serde_json::from_str(data).unwrap()).unwrap();
+        assert_eq!(
+            mmds.get_value("/nothing".to_string(), OutputFormat::Imds, false),
+            Err(Error::UnsupportedValueType)
+        );
         assert_eq!(
-            mmds.get_value("/balance".to_string(), OutputFormat::Imds)
-                .err()
+            mmds.get_value("/interfaces".to_string(), OutputFormat::Imds, false),
+            Err(Error::UnsupportedValueType)
+        );
+        assert_eq!(
+            mmds.get_value("/interfaces/0".to_string(), OutputFormat::Imds, false)
                 .unwrap(),
-            Error::UnsupportedValueType
+            "mac"
+        );
+    }
+
+    #[test]
+    fn test_format_yaml() {
+        let mut mmds = Mmds::default();
+        let data = r##"{
+            "instance-id": "i-1234567890",
+            "hostname": "ip-10.0.0.2.ec2.internal",
+            "count": 2,
+            "enabled": true,
+            "nothing": null,
+            "empty": {},
+            "keys": [],
+            "phones": ["+401234567", "yes", "12"],
+            "interfaces": [
+                {"mac": "06:00:ac:10:00:02", "ips": ["10.0.0.2"]},
+                [1, 2]
+            ],
+            "user data": "#cloud-config\nruncmd: []"
+        }"##;
+        mmds.put_data(serde_json::from_str(data).unwrap()).unwrap();
+
+        let expected = "count: 2\n\
+                        empty: {}\n\
+                        enabled: true\n\
+                        hostname: ip-10.0.0.2.ec2.internal\n\
+                        instance-id: i-1234567890\n\
+                        interfaces:\n  \
+                          - ips:\n      \
+                              - \"10.0.0.2\"\n    \
+                            mac: \"06:00:ac:10:00:02\"\n  \
+                          - - 1\n    \
+                            - 2\n\
+                        keys: []\n\
+                        nothing: null\n\
+                        phones:\n  \
+                          - \"+401234567\"\n  \
+                          - \"yes\"\n  \
+                          - \"12\"\n\
+                        \"user data\": \"#cloud-config\\nruncmd: []\"";
+        assert_eq!(
+            mmds.get_value("/".to_string(), OutputFormat::Yaml, false)
+                .unwrap(),
+            expected
+        );
+
+        // Scalars make up a document of their own.
+        assert_eq!(
+            mmds.get_value("/hostname".to_string(), OutputFormat::Yaml, false)
+                .unwrap(),
+            "ip-10.0.0.2.ec2.internal"
+        );
+        assert_eq!(
+            mmds.get_value("/phones/0".to_string(), OutputFormat::Yaml, false)
+                .unwrap(),
+            "\"+401234567\""
+        );
+        assert_eq!(
+            mmds.get_value("/keys".to_string(), OutputFormat::Yaml, false)
+                .unwrap(),
+            "[]"
         );
     }
 
@@ -388,4 +878,176 @@ mod tests {
         let data_store: Value = serde_json::from_str(data).unwrap();
         assert!(mmds.patch_data(data_store).is_ok());
     }
+
+    #[test]
+    fn test_access_policies() {
+        let mut mmds = Mmds::default();
+        mmds.put_data(serde_json::json!({
+            "latest": {
+                "meta-data": {
+                    "ami-id": "ami-12345678",
+                    "credentials": {"secret-key": "foo"}
+                }
+            },
+            "operator": {"owner": "bar"},
+            "operator-notes": "baz",
+            "a/b": "escaped"
+        }))
+        .unwrap();
+
+        let mut policies = BTreeMap::new();
+        policies.insert("/operator/".to_string(), AccessPolicy::Hidden);
+        policies.insert("/a~1b".to_string(), AccessPolicy::Hidden);
+        policies.insert(
+            "/latest/meta-data/credentials".to_string(),
+            AccessPolicy::TokenRequired,
+        );
+        policies.insert("/does/not/exist".to_string(), AccessPolicy::Hidden);
+        mmds.set_access_policies(policies);
+
+        // Restricted subtrees are left out of their parents.
+        assert_eq!(
+            mmds.get_value("/".to_string(), OutputFormat::Imds, false)
+                .unwrap(),
+            "latest/\noperator-notes"
+        );
+        assert_eq!(
+            mmds.get_value("/latest/meta-data".to_string(), OutputFormat::Json, false)
+                .unwrap(),
+            r#"{"ami-id":"ami-12345678"}"#
+        );
+        assert_eq!(
+            mmds.get_value("/latest/meta-data".to_string(), OutputFormat::Json, true)
+                .unwrap(),
+            r#"{"ami-id":"ami-12345678","credentials":{"secret-key":"foo"}}"#
+        );
+
+        // Restricted subtrees, and their descendants, can't be requested directly.
+        for path in &[
+            "/operator",
+            "/operator/",
+            "/operator/owner",
+            "/a~1b",
+            "/latest/meta-data/credentials/secret-key",
+        ] {
+            assert_eq!(
+                mmds.get_value(path.to_string(), OutputFormat::Json, false),
+                Err(Error::NotFound)
+            );
+        }
+        assert_eq!(
+            mmds.get_value("/operator".to_string(), OutputFormat::Json, true),
+            Err(Error::NotFound)
+        );
+        assert_eq!(
+            mmds.get_value(
+                "/latest/meta-data/credentials/secret-key".to_string(),
+                OutputFormat::Imds,
+                true
+            )
+            .unwrap(),
+            "foo"
+        );
+        // Keys which merely share a prefix with a restricted one are not affected.
+        assert_eq!(
+            mmds.get_value("/operator-notes".to_string(), OutputFormat::Imds, false)
+                .unwrap(),
+            "baz"
+        );
+
+        // The host still sees everything.
+        assert!(mmds.get_data_str().contains("operator"));
+
+        mmds.set_access_policies(BTreeMap::new());
+        assert!(mmds
+            .get_value("/operator".to_string(), OutputFormat::Json, false)
+            .is_ok());
+    }
+
+    #[test]
+    fn test_update_listeners() {
+        let mut mmds = Mmds::default();
+        assert_eq!(mmds.data_store_version(), 0);
+        let evt = Arc::new(EventFd::new(libc::EFD_NONBLOCK).unwrap());
+        mmds.add_update_listener(&evt);
+
+        // Failed updates leave the version alone, and don't notify the listeners.
+        assert!(mmds
+            .patch_data(serde_json::json!({"key": "value"}))
+            .is_err());
+        assert_eq!(mmds.data_store_version(), 0);
+        assert!(evt.read().is_err());
+
+        mmds.put_data(serde_json::json!({"key": "value"})).unwrap();
+        assert_eq!(mmds.data_store_version(), 1);
+        mmds.patch_data(serde_json::json!({"key": "other"}))
+            .unwrap();
+        assert_eq!(mmds.data_store_version(), 2);
+        assert_eq!(evt.read().unwrap(), 2);
+
+        mmds.set_data_store_limit(20).unwrap();
+        assert!(mmds
+            .put_data(serde_json::json!({"key": "too long a value"}))
+            .is_err());
+        assert_eq!(mmds.data_store_version(), 2);
+        assert!(evt.read().is_err());
+
+        // Dropped listeners are forgotten.
+        drop(evt);
+        mmds.put_data(serde_json::json!({})).unwrap();
+        assert_eq!(mmds.data_store_version(), 3);
+        assert!(mmds.update_listeners.is_empty());
+
+        // Listeners registered and dropped in between updates don't accumulate.
+        let evt = Arc::new(EventFd::new(libc::EFD_NONBLOCK).unwrap());
+        mmds.add_update_listener(&evt);
+        for _ in 0..100 {
+            let other_evt = Arc::new(EventFd::new(libc::EFD_NONBLOCK).unwrap());
+            mmds.add_update_listener(&other_evt);
+        }
+        assert_eq!(mmds.update_listeners.len(), 2);
+        mmds.put_data(serde_json::json!({})).unwrap();
+        assert_eq!(evt.read().unwrap(), 1);
+        assert_eq!(mmds.update_listeners.len(), 1);
+    }
+
+    #[test]
+    fn test_mmds_instances() {
+        let instances = MmdsInstances::default();
+        assert!(Arc::ptr_eq(
+            &instances.get(None).unwrap(),
+            &instances.default_instance()
+        ));
+        assert!(instances.get(Some("eth1")).is_none());
+        assert_eq!(instances.all().len(), 1);
+
+        // New instances take the settings of the default one, but not its contents.
+        {
+            let mut default = instances.default.lock().unwrap();
+            default
+                .put_data(serde_json::json!({"key": "value"}))
+                .unwrap();
+            default.set_data_store_limit(20).unwrap();
+            default.set_version(MmdsVersion::V2);
+        }
+        let eth1 = instances.get_or_create(Some("eth1"));
+        {
+            let mut eth1 = eth1.lock().unwrap();
+            assert_eq!(eth1.version(), MmdsVersion::V2);
+            assert!(eth1
+                .put_data(serde_json::json!({"key": "too long a value"}))
+                .is_err());
+            eth1.put_data(serde_json::json!({"key": "other"})).unwrap();
+        }
+        assert_eq!(
+            instances.default.lock().unwrap().get_data_str(),
+            "{\"key\":\"value\"}"
+        );
+
+        // Clones share the same instances.
+        let clone = instances.clone();
+        assert!(Arc::ptr_eq(&clone.get(Some("eth1")).unwrap(), &eth1));
+        assert!(Arc::ptr_eq(&clone.get_or_create(Some("eth1")), &eth1));
+        assert_eq!(clone.all().len(), 2);
+    }
 }
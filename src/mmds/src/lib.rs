@@ -1,32 +1,39 @@
 // Copyright 2018 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
-#[macro_use]
-extern crate lazy_static;
 extern crate serde_json;
 
+extern crate logger;
 extern crate micro_http;
+extern crate utils;
 
 pub mod data_store;
+pub mod token;
 
 use serde_json::{Map, Value};
-use std::sync::{Arc, Mutex};
 
-use data_store::{Error as MmdsError, Mmds, OutputFormat};
+use data_store::{Error as MmdsError, Mmds, MmdsVersion, OutputFormat};
+use logger::{Metric, METRICS};
 use micro_http::{Body, MediaType, Method, Request, RequestError, Response, StatusCode, Version};
-
-lazy_static! {
-    // A static reference to a global Mmds instance. We currently use this for ease of access during
-    // prototyping. We'll consider something like passing Arc<Mutex<Mmds>> references to the
-    // appropriate threads in the future.
-    pub static ref MMDS: Arc<Mutex<Mmds>> = Arc::new(Mutex::new(Mmds::default()));
-}
+use token::Error as TokenError;
+
+/// The path at which guests request session tokens.
+const TOKEN_PATH: &str = "/latest/api/token";
+/// The header carrying the session token of a metadata request.
+const X_METADATA_TOKEN_HEADER: &str = "X-metadata-token";
+/// The header carrying the time to live, in seconds, of a requested session token.
+const X_METADATA_TOKEN_TTL_HEADER: &str = "X-metadata-token-ttl-seconds";
+/// The header which proxies add to the requests they relay.
+const X_FORWARDED_FOR_HEADER: &str = "X-Forwarded-For";
+/// The header carrying the version of the data store contents a response was built from.
+const X_METADATA_VERSION_HEADER: &str = "X-metadata-version";
 
 impl Into<OutputFormat> for MediaType {
     fn into(self) -> OutputFormat {
         match self {
             MediaType::ApplicationJson => OutputFormat::Json,
             MediaType::PlainText => OutputFormat::Imds,
+            MediaType::ApplicationYaml => OutputFormat::Yaml,
         }
     }
 }
@@ -65,61 +72,226 @@ fn build_response(http_version: Version, status_code: StatusCode, body: Body) ->
     response
 }
 
-pub fn parse_request(request_bytes: &[u8]) -> Response {
-    let request = Request::try_from(request_bytes);
-    match request {
-        Ok(request) => {
-            let uri = request.uri().get_abs_path();
-            if uri.is_empty() {
+/// Answers a session token request, issuing a token valid for the time to live specified in
+/// the request headers.
+fn respond_to_token_request(mmds: &mut Mmds, request: &Request) -> Response {
+    // Requests relayed by a proxy may well originate outside of the guest, and a token
+    // shouldn't be handed over to such requests.
+    if request
+        .headers
+        .custom_entry(X_FORWARDED_FOR_HEADER)
+        .is_some()
+    {
+        return build_response(
+            request.http_version(),
+            StatusCode::BadRequest,
+            Body::new("Token requests cannot be forwarded.".to_string()),
+        );
+    }
+
+    let ttl_seconds = match request.headers.custom_entry(X_METADATA_TOKEN_TTL_HEADER) {
+        Some(value) => match value.parse::<u32>() {
+            Ok(ttl_seconds) => ttl_seconds,
+            Err(_) => {
                 return build_response(
                     request.http_version(),
                     StatusCode::BadRequest,
-                    Body::new("Invalid URI.".to_string()),
-                );
+                    Body::new(format!(
+                        "Invalid `{}` header value: {}.",
+                        X_METADATA_TOKEN_TTL_HEADER, value
+                    )),
+                )
             }
+        },
+        None => {
+            return build_response(
+                request.http_version(),
+                StatusCode::BadRequest,
+                Body::new(format!(
+                    "Token time to live value not found. Use `{}` header to specify the \
+                     token's lifetime.",
+                    X_METADATA_TOKEN_TTL_HEADER
+                )),
+            )
+        }
+    };
+
+    match mmds.generate_token(ttl_seconds) {
+        Ok(token) => build_response(request.http_version(), StatusCode::OK, Body::new(token)),
+        Err(e @ TokenError::InvalidTtlValue(_)) => build_response(
+            request.http_version(),
+            StatusCode::BadRequest,
+            Body::new(e.to_string()),
+        ),
+        Err(e @ TokenError::EntropySource(_)) => build_response(
+            request.http_version(),
+            StatusCode::InternalServerError,
+            Body::new(e.to_string()),
+        ),
+    }
+}
 
-            if request.method() != Method::Get {
-                let mut response = build_response(
-                    request.http_version(),
-                    StatusCode::MethodNotAllowed,
-                    Body::new("Not allowed HTTP method."),
-                );
-                response.allow_method(Method::Get);
-                return response;
-            }
+/// Checks whether a metadata request is authorized to read the MMDS contents, given the
+/// configured MMDS version. Returns the response rejecting the request otherwise, or whether
+/// the request presented a valid session token.
+fn check_token(mmds: &Mmds, request: &Request) -> Result<bool, Response> {
+    match (
+        mmds.version(),
+        request.headers.custom_entry(X_METADATA_TOKEN_HEADER),
+    ) {
+        (MmdsVersion::V1, _) | (MmdsVersion::Both, None) => Ok(false),
+        (MmdsVersion::V2, None) => {
+            METRICS.mmds.rx_no_token.inc();
+            Err(build_response(
+                request.http_version(),
+                StatusCode::Unauthorized,
+                Body::new(format!(
+                    "No MMDS token provided. Use `{}` header to specify the session token.",
+                    X_METADATA_TOKEN_HEADER
+                )),
+            ))
+        }
+        (_, Some(token)) if mmds.is_valid_token(token) => Ok(true),
+        (_, Some(_)) => {
+            METRICS.mmds.rx_invalid_token.inc();
+            Err(build_response(
+                request.http_version(),
+                StatusCode::Unauthorized,
+                Body::new("MMDS token not valid.".to_string()),
+            ))
+        }
+    }
+}
 
-            // The lock can be held by one thread only, so it is safe to unwrap.
-            // If another thread poisoned the lock, we abort the execution.
-            let response = MMDS
-                .lock()
-                .expect("Poisoned lock")
-                .get_value(uri.to_string(), request.headers.accept().into());
+/// Returns the data store version that a long-poll request is waiting to see exceeded, or
+/// `None` if the request should be answered right away. Long-poll requests carry the
+/// `wait=true` and `version=N` query parameters, where `N` is the last version the guest has
+/// seen. Malformed long-poll queries yield the response rejecting the request.
+fn parse_wait_query(request: &Request) -> Result<Option<u64>, Response> {
+    let mut wait = false;
+    let mut version = None;
+    for param in request.uri().get_query().unwrap_or("").split('&') {
+        let mut key_value = param.splitn(2, '=');
+        match (key_value.next(), key_value.next()) {
+            (Some("wait"), Some(value)) => wait = value == "true",
+            (Some("version"), Some(value)) => version = Some(value),
+            _ => (),
+        }
+    }
 
-            match response {
-                Ok(response_body) => build_response(
-                    request.http_version(),
-                    StatusCode::OK,
-                    Body::new(response_body),
-                ),
-                Err(e) => match e {
-                    MmdsError::NotFound => {
-                        let error_msg = format!("Resource not found: {}.", uri);
-                        build_response(
-                            request.http_version(),
-                            StatusCode::NotFound,
-                            Body::new(error_msg),
-                        )
-                    }
-                    MmdsError::UnsupportedValueType => build_response(
-                        request.http_version(),
-                        StatusCode::NotImplemented,
-                        Body::new(e.to_string()),
-                    ),
-                    MmdsError::NotInitialized => unreachable!(),
-                },
+    if !wait {
+        return Ok(None);
+    }
+    match version.map(str::parse::<u64>) {
+        Some(Ok(version)) => Ok(Some(version)),
+        Some(Err(_)) | None => Err(build_response(
+            request.http_version(),
+            StatusCode::BadRequest,
+            Body::new(
+                "Long-poll requests must specify the last seen data store version, as in \
+                 `?wait=true&version=N`."
+                    .to_string(),
+            ),
+        )),
+    }
+}
+
+/// Answers `request`, unless it is a long-poll request waiting for an update of the data
+/// store, in which case `None` is returned.
+fn respond_to_request(mmds: &mut Mmds, request: &Request) -> Option<Response> {
+    let uri = request.uri().get_abs_path();
+    if uri.is_empty() {
+        return Some(build_response(
+            request.http_version(),
+            StatusCode::BadRequest,
+            Body::new("Invalid URI.".to_string()),
+        ));
+    }
+
+    match request.method() {
+        Method::Get | Method::Head => (),
+        Method::Put if mmds.version() != MmdsVersion::V1 && uri == TOKEN_PATH => {
+            return Some(respond_to_token_request(mmds, request));
+        }
+        _ => {
+            let mut response = build_response(
+                request.http_version(),
+                StatusCode::MethodNotAllowed,
+                Body::new("Not allowed HTTP method."),
+            );
+            response.allow_method(Method::Get);
+            response.allow_method(Method::Head);
+            if mmds.version() != MmdsVersion::V1 {
+                response.allow_method(Method::Put);
             }
+            return Some(response);
+        }
+    }
+
+    let with_token = match check_token(mmds, request) {
+        Ok(with_token) => with_token,
+        Err(response) => return Some(response),
+    };
+
+    match parse_wait_query(request) {
+        Ok(Some(version)) if version >= mmds.data_store_version() => return None,
+        Ok(_) => (),
+        Err(response) => return Some(response),
+    }
+
+    let accept = request.headers.accept();
+    let value = mmds.get_value(uri.to_string(), accept.into(), with_token);
+    let mut response = match value {
+        Ok(response_body) => {
+            let mut response = build_response(
+                request.http_version(),
+                StatusCode::OK,
+                Body::new(response_body),
+            );
+            response.set_content_type(accept);
+            response
         }
         Err(e) => match e {
+            MmdsError::NotFound => {
+                let error_msg = format!("Resource not found: {}.", uri);
+                build_response(
+                    request.http_version(),
+                    StatusCode::NotFound,
+                    Body::new(error_msg),
+                )
+            }
+            MmdsError::UnsupportedValueType => build_response(
+                request.http_version(),
+                StatusCode::NotImplemented,
+                Body::new(e.to_string()),
+            ),
+            MmdsError::DataStoreLimitExceeded(_) | MmdsError::NotInitialized => unreachable!(),
+        },
+    };
+    response.add_header(
+        X_METADATA_VERSION_HEADER,
+        &mmds.data_store_version().to_string(),
+    );
+    Some(response)
+}
+
+/// Answers a guest request for the contents of `mmds`.
+///
+/// Long-poll requests, which wait for the data store to change past the version they specify,
+/// are not answered while the data store is still at that version. `None` is returned in that
+/// case, and the caller is expected to hold on to the request, and to retry it once the data
+/// store is updated (see `Mmds::add_update_listener`).
+pub fn parse_request(mmds: &mut Mmds, request_bytes: &[u8]) -> Option<Response> {
+    let request = Request::try_from(request_bytes);
+    match request {
+        Ok(request) => respond_to_request(mmds, &request).map(|mut response| {
+            // `HEAD` requests are answered like `GET` requests, without the body.
+            if request.method() == Method::Head {
+                response.remove_body();
+            }
+            response
+        }),
+        Err(e) => Some(match e {
             RequestError::InvalidHttpVersion(err_msg) => build_response(
                 Version::default(),
                 StatusCode::NotImplemented,
@@ -145,11 +317,16 @@ pub fn parse_request(request_bytes: &[u8]) -> Response {
                 StatusCode::BadRequest,
                 Body::new("Invalid headers.".to_string()),
             ),
+            RequestError::HeadersTooLarge(_) | RequestError::BodyTooLarge(_) => build_response(
+                Version::default(),
+                StatusCode::PayloadTooLarge,
+                Body::new(e.to_string()),
+            ),
             // `micro-http` supports a predefined list of HTTP headers.
             // It shouldn't reach this point, because it ignores the
             // HTTP unsupported headers.
             RequestError::UnsupportedHeader => unreachable!(),
-        },
+        }),
     }
 }
 
@@ -158,11 +335,15 @@ mod tests {
     extern crate serde_json;
     use super::*;
 
+    use std::collections::BTreeMap;
+
+    use data_store::AccessPolicy;
+
     fn check_http_method_failure(method: String, status: StatusCode, err_msg: String) {
         let request = format!("{} http://169.254.169.254/ HTTP/1.1\r\n\r\n", method);
         let mut expected_response = Response::new(Version::Http11, status);
         expected_response.set_body(Body::new(err_msg));
-        let actual_response = parse_request(request.as_bytes());
+        let actual_response = parse_request(&mut Mmds::default(), request.as_bytes()).unwrap();
         assert!(expected_response.status() == actual_response.status());
         assert!(expected_response.body().unwrap() == actual_response.body().unwrap());
     }
@@ -175,6 +356,7 @@ mod tests {
                 "second": "Doe"
             },
             "age": 43,
+            "nothing": null,
             "phones": {
                 "home": {
                     "RO": "+401234567",
@@ -183,21 +365,19 @@ mod tests {
                 "mobile": "+442345678"
             }
         }"#;
-        MMDS.lock()
-            .unwrap()
-            .put_data(serde_json::from_str(data).unwrap())
-            .unwrap();
+        let mut mmds = Mmds::default();
+        mmds.put_data(serde_json::from_str(data).unwrap()).unwrap();
 
         // Test invalid request.
         let request = b"HTTP/1.1";
         let dummy_response = Response::new(Version::Http11, StatusCode::BadRequest);
-        assert!(parse_request(request).status() == dummy_response.status());
+        assert!(parse_request(&mut mmds, request).unwrap().status() == dummy_response.status());
 
         // Test unsupported HTTP version.
         let request = b"GET http://169.254.169.255/ HTTP/2.0\r\n\r\n";
         let mut expected_response = Response::new(Version::Http11, StatusCode::NotImplemented);
         expected_response.set_body(Body::new("Unsupported HTTP version.".to_string()));
-        let actual_response = parse_request(request);
+        let actual_response = parse_request(&mut mmds, request).unwrap();
 
         assert!(expected_response.status() == actual_response.status());
         assert!(expected_response.body().unwrap() == actual_response.body().unwrap());
@@ -207,7 +387,7 @@ mod tests {
         let request = b"GET http:// HTTP/1.0\r\n\r\n";
         let mut expected_response = Response::new(Version::Http10, StatusCode::BadRequest);
         expected_response.set_body(Body::new("Invalid URI.".to_string()));
-        let actual_response = parse_request(request);
+        let actual_response = parse_request(&mut mmds, request).unwrap();
 
         assert!(expected_response.status() == actual_response.status());
         assert!(expected_response.body().unwrap() == actual_response.body().unwrap());
@@ -217,7 +397,7 @@ mod tests {
         let request = b"GET / HTTP/1.1\r\n";
         let mut expected_response = Response::new(Version::Http11, StatusCode::BadRequest);
         expected_response.set_body(Body::new("Invalid request.".to_string()));
-        let actual_response = parse_request(request);
+        let actual_response = parse_request(&mut mmds, request).unwrap();
 
         assert!(expected_response.status() == actual_response.status());
         assert!(expected_response.body().unwrap() == actual_response.body().unwrap());
@@ -227,7 +407,7 @@ mod tests {
         let request = b"GET http://169.254.169.254/invalid HTTP/1.0\r\n\r\n";
         let mut expected_response = Response::new(Version::Http10, StatusCode::NotFound);
         expected_response.set_body(Body::new("Resource not found: /invalid.".to_string()));
-        let actual_response = parse_request(request);
+        let actual_response = parse_request(&mut mmds, request).unwrap();
 
         assert!(expected_response.status() == actual_response.status());
         assert!(expected_response.body().unwrap() == actual_response.body().unwrap());
@@ -243,6 +423,7 @@ mod tests {
                     "first": "John",
                     "second": "Doe"
                 },
+                "nothing": null,
                 "phones": {
                     "home": {
                         "RO": "+401234567",
@@ -254,17 +435,46 @@ mod tests {
         .to_string();
         body.retain(|c| !c.is_whitespace());
         expected_response.set_body(Body::new(body));
-        let actual_response = parse_request(request);
+        let actual_response = parse_request(&mut mmds, request).unwrap();
 
         assert!(expected_response.status() == actual_response.status());
         assert!(expected_response.body().unwrap() == actual_response.body().unwrap());
         assert!(expected_response.http_version() == actual_response.http_version());
 
         let request = b"GET /age HTTP/1.1\r\n\r\n";
+        let mut expected_response = Response::new(Version::Http11, StatusCode::OK);
+        expected_response.set_body(Body::new("43".to_string()));
+        let actual_response = parse_request(&mut mmds, request).unwrap();
+
+        assert!(expected_response.status() == actual_response.status());
+        assert!(expected_response.body().unwrap() == actual_response.body().unwrap());
+        assert!(expected_response.http_version() == actual_response.http_version());
+        assert_eq!(actual_response.content_type(), MediaType::PlainText);
+
+        // `HEAD` requests get the same response, without the body.
+        let request = b"HEAD /age HTTP/1.1\r\n\r\n";
+        let actual_response = parse_request(&mut mmds, request).unwrap();
+        assert_eq!(actual_response.status(), StatusCode::OK);
+        assert!(actual_response.body().is_none());
+        assert_eq!(actual_response.content_length(), 2);
+        assert_eq!(actual_response.content_type(), MediaType::PlainText);
+
+        // Test YAML output.
+        let request = b"GET /name HTTP/1.1\r\nAccept: application/yaml\r\n\r\n";
+        let actual_response = parse_request(&mut mmds, request).unwrap();
+        assert_eq!(actual_response.status(), StatusCode::OK);
+        assert_eq!(
+            actual_response.body().unwrap(),
+            Body::new("first: John\nsecond: Doe".to_string())
+        );
+        assert_eq!(actual_response.content_type(), MediaType::ApplicationYaml);
+
+        // Values which can't be IMDS formatted are still reported as such.
+        let request = b"GET /nothing HTTP/1.1\r\n\r\n";
         let mut expected_response = Response::new(Version::Http11, StatusCode::NotImplemented);
         let body = "Cannot retrieve value. The value has an unsupported type.".to_string();
         expected_response.set_body(Body::new(body));
-        let actual_response = parse_request(request);
+        let actual_response = parse_request(&mut mmds, request).unwrap();
 
         assert!(expected_response.status() == actual_response.status());
         assert!(expected_response.body().unwrap() == actual_response.body().unwrap());
@@ -285,23 +495,13 @@ mod tests {
         );
         check_http_method_failure(
             "POST".to_string(),
-            StatusCode::NotImplemented,
-            "Unsupported HTTP method.".to_string(),
+            StatusCode::MethodNotAllowed,
+            "Not allowed HTTP method.".to_string(),
         );
         check_http_method_failure(
             "DELETE".to_string(),
-            StatusCode::NotImplemented,
-            "Unsupported HTTP method.".to_string(),
-        );
-        check_http_method_failure(
-            "POST".to_string(),
-            StatusCode::NotImplemented,
-            "Unsupported HTTP method.".to_string(),
-        );
-        check_http_method_failure(
-            "HEAD".to_string(),
-            StatusCode::NotImplemented,
-            "Unsupported HTTP method.".to_string(),
+            StatusCode::MethodNotAllowed,
+            "Not allowed HTTP method.".to_string(),
         );
         check_http_method_failure(
             "CONNECT".to_string(),
@@ -325,6 +525,193 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_session_tokens() {
+        let mut mmds = Mmds::default();
+        mmds.put_data(serde_json::json!({"key": "value"})).unwrap();
+        let respond = |mmds: &mut Mmds, request: &[u8]| {
+            respond_to_request(mmds, &Request::try_from(request).unwrap()).unwrap()
+        };
+
+        // Tokens are not issued by default, and the token header is ignored.
+        let token_request = b"PUT /latest/api/token HTTP/1.1\r\n\
+                              X-metadata-token-ttl-seconds: 60\r\n\r\n";
+        let response = respond(&mut mmds, token_request);
+        assert!(response.status() == StatusCode::MethodNotAllowed);
+        assert_eq!(response.allow(), vec![Method::Get, Method::Head]);
+        let response = respond(
+            &mut mmds,
+            b"GET /key HTTP/1.1\r\nX-metadata-token: foo\r\n\r\n",
+        );
+        assert!(response.status() == StatusCode::OK);
+
+        mmds.set_version(MmdsVersion::V2);
+        let response = respond(&mut mmds, b"PUT /key HTTP/1.1\r\n\r\n");
+        assert!(response.status() == StatusCode::MethodNotAllowed);
+        assert_eq!(
+            response.allow(),
+            vec![Method::Get, Method::Head, Method::Put]
+        );
+
+        // Test invalid token requests.
+        let response = respond(&mut mmds, b"PUT /latest/api/token HTTP/1.1\r\n\r\n");
+        assert!(response.status() == StatusCode::BadRequest);
+        assert_eq!(
+            response.body().unwrap(),
+            Body::new(
+                "Token time to live value not found. Use `X-metadata-token-ttl-seconds` header \
+                 to specify the token's lifetime."
+            )
+        );
+        let response = respond(
+            &mut mmds,
+            b"PUT /latest/api/token HTTP/1.1\r\nX-metadata-token-ttl-seconds: foo\r\n\r\n",
+        );
+        assert!(response.status() == StatusCode::BadRequest);
+        assert_eq!(
+            response.body().unwrap(),
+            Body::new("Invalid `X-metadata-token-ttl-seconds` header value: foo.")
+        );
+        let response = respond(
+            &mut mmds,
+            b"PUT /latest/api/token HTTP/1.1\r\nX-metadata-token-ttl-seconds: 0\r\n\r\n",
+        );
+        assert!(response.status() == StatusCode::BadRequest);
+        let response = respond(
+            &mut mmds,
+            b"PUT /latest/api/token HTTP/1.1\r\nX-metadata-token-ttl-seconds: 60\r\n\
+              X-Forwarded-For: 203.0.113.1\r\n\r\n",
+        );
+        assert!(response.status() == StatusCode::BadRequest);
+        assert_eq!(
+            response.body().unwrap(),
+            Body::new("Token requests cannot be forwarded.")
+        );
+
+        // Test the Ok path.
+        let response = respond(&mut mmds, token_request);
+        assert!(response.status() == StatusCode::OK);
+        let token = String::from_utf8(response.body().unwrap().body).unwrap();
+        assert!(mmds.is_valid_token(&token));
+
+        // Metadata requests must present a valid token.
+        let no_token_count = METRICS.mmds.rx_no_token.count();
+        let response = respond(&mut mmds, b"GET /key HTTP/1.1\r\n\r\n");
+        assert!(response.status() == StatusCode::Unauthorized);
+        assert_eq!(
+            response.body().unwrap(),
+            Body::new(
+                "No MMDS token provided. Use `X-metadata-token` header to specify the session \
+                 token."
+            )
+        );
+        assert!(METRICS.mmds.rx_no_token.count() > no_token_count);
+
+        let invalid_token_count = METRICS.mmds.rx_invalid_token.count();
+        let response = respond(
+            &mut mmds,
+            b"GET /key HTTP/1.1\r\nX-metadata-token: foo\r\n\r\n",
+        );
+        assert!(response.status() == StatusCode::Unauthorized);
+        assert_eq!(response.body().unwrap(), Body::new("MMDS token not valid."));
+        assert!(METRICS.mmds.rx_invalid_token.count() > invalid_token_count);
+
+        let request = format!("GET /key HTTP/1.1\r\nX-metadata-token: {}\r\n\r\n", token);
+        let response = respond(&mut mmds, request.as_bytes());
+        assert!(response.status() == StatusCode::OK);
+        assert_eq!(response.body().unwrap(), Body::new("value"));
+
+        // Tokens are optional when both versions are allowed, but they are still checked.
+        mmds.set_version(MmdsVersion::Both);
+        let response = respond(&mut mmds, b"GET /key HTTP/1.1\r\n\r\n");
+        assert!(response.status() == StatusCode::OK);
+        let response = respond(
+            &mut mmds,
+            b"GET /key HTTP/1.1\r\nX-metadata-token: foo\r\n\r\n",
+        );
+        assert!(response.status() == StatusCode::Unauthorized);
+        let response = respond(&mut mmds, request.as_bytes());
+        assert!(response.status() == StatusCode::OK);
+
+        // Subtrees can be restricted to the requests presenting a valid token.
+        let mut policies = BTreeMap::new();
+        policies.insert("/key".to_string(), AccessPolicy::TokenRequired);
+        mmds.set_access_policies(policies);
+        let response = respond(&mut mmds, b"GET /key HTTP/1.1\r\n\r\n");
+        assert!(response.status() == StatusCode::NotFound);
+        let response = respond(&mut mmds, request.as_bytes());
+        assert!(response.status() == StatusCode::OK);
+        assert_eq!(response.body().unwrap(), Body::new("value"));
+
+        // Issuing new tokens doesn't invalidate the ones already issued.
+        for _ in 0..1000 {
+            assert!(respond(&mut mmds, token_request).status() == StatusCode::OK);
+        }
+        assert!(mmds.is_valid_token(&token));
+    }
+
+    #[test]
+    fn test_long_poll() {
+        let mut mmds = Mmds::default();
+        let respond = |mmds: &mut Mmds, request: &[u8]| {
+            respond_to_request(mmds, &Request::try_from(request).unwrap())
+        };
+        let version_header = |response: &Response| {
+            let mut buf = Vec::new();
+            response.write_all(&mut buf).unwrap();
+            String::from_utf8(buf)
+                .unwrap()
+                .lines()
+                .find(|line| line.starts_with(X_METADATA_VERSION_HEADER))
+                .map(str::to_string)
+        };
+
+        // Responses carry the data store version they were built from.
+        let response = respond(&mut mmds, b"GET /key HTTP/1.1\r\n\r\n").unwrap();
+        assert!(response.status() == StatusCode::NotFound);
+        assert_eq!(
+            version_header(&response),
+            Some("X-metadata-version: 0".to_string())
+        );
+
+        // Long-poll requests wait for a version newer than the one they specify.
+        let request = b"GET /key?wait=true&version=0 HTTP/1.1\r\n\r\n";
+        assert!(respond(&mut mmds, request).is_none());
+        mmds.put_data(serde_json::json!({"key": "value"})).unwrap();
+        let response = respond(&mut mmds, request).unwrap();
+        assert!(response.status() == StatusCode::OK);
+        assert_eq!(response.body().unwrap(), Body::new("value"));
+        assert_eq!(
+            version_header(&response),
+            Some("X-metadata-version: 1".to_string())
+        );
+        assert!(respond(&mut mmds, b"GET /key?version=1&wait=true HTTP/1.1\r\n\r\n").is_none());
+
+        // Requests which don't ask to wait are answered right away.
+        for request in &[
+            &b"GET /key?version=1 HTTP/1.1\r\n\r\n"[..],
+            &b"GET /key?wait=false&version=1 HTTP/1.1\r\n\r\n"[..],
+            &b"GET /key? HTTP/1.1\r\n\r\n"[..],
+        ] {
+            let response = respond(&mut mmds, request).unwrap();
+            assert!(response.status() == StatusCode::OK);
+        }
+
+        // Test malformed long-poll requests.
+        for request in &[
+            &b"GET /key?wait=true HTTP/1.1\r\n\r\n"[..],
+            &b"GET /key?wait=true&version=foo HTTP/1.1\r\n\r\n"[..],
+        ] {
+            let response = respond(&mut mmds, request).unwrap();
+            assert!(response.status() == StatusCode::BadRequest);
+        }
+
+        // Unauthorized requests are rejected instead of waiting.
+        mmds.set_version(MmdsVersion::V2);
+        let response = respond(&mut mmds, b"GET /key?wait=true&version=1 HTTP/1.1\r\n\r\n");
+        assert!(response.unwrap().status() == StatusCode::Unauthorized);
+    }
+
     #[test]
     fn test_json_patch() {
         let mut data = serde_json::json!({
@@ -0,0 +1,244 @@
+// Copyright 2020 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Session tokens, which guest processes have to obtain before reading the MMDS contents, when
+//! the MMDS is configured to require them.
+//!
+//! A token is issued by the MMDS in response to a `PUT` request, and is valid for the time to
+//! live chosen when it was requested. It carries its own expiry time, authenticated with a
+//! secret key which is only known to the MMDS, so the MMDS doesn't keep track of the tokens it
+//! issued, and there is no limit to how many of them can be valid at the same time. The key is
+//! generated from the kernel's secure random source when the first token is issued.
+
+use std::fmt;
+use std::hash::Hasher;
+// `SipHasher` is deprecated for hash tables, but it's the only keyed hasher in `std`.
+#[allow(deprecated)]
+use std::hash::SipHasher;
+use std::io;
+
+use utils::rand::fill_random;
+use utils::time::{get_time, ClockType};
+
+/// The minimum time to live of a token, in seconds.
+pub const MIN_TOKEN_TTL_SECONDS: u32 = 1;
+/// The maximum time to live of a token, in seconds.
+pub const MAX_TOKEN_TTL_SECONDS: u32 = 21600;
+
+// The number of bytes making up the expiry time of a token.
+const EXPIRY_LENGTH: usize = 8;
+// The number of bytes making up the authentication tag of a token.
+const TAG_LENGTH: usize = 16;
+// The number of bytes a token is made of, before being hex encoded.
+const TOKEN_LENGTH: usize = EXPIRY_LENGTH + TAG_LENGTH;
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+
+/// Errors associated with issuing session tokens.
+#[derive(Debug)]
+pub enum Error {
+    /// The secret key authenticating the tokens could not be generated.
+    EntropySource(io::Error),
+    /// The requested time to live is out of bounds.
+    InvalidTtlValue(u32),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::EntropySource(err) => write!(f, "Cannot generate the MMDS token: {}", err),
+            Error::InvalidTtlValue(ttl) => write!(
+                f,
+                "Invalid time to live value provided for token: {}. Please provide a value \
+                 between {} and {}.",
+                ttl, MIN_TOKEN_TTL_SECONDS, MAX_TOKEN_TTL_SECONDS
+            ),
+        }
+    }
+}
+
+/// Issues session tokens, and checks the ones presented back.
+#[derive(Clone, Default)]
+pub struct TokenAuthority {
+    // The two 128-bit SipHash keys authenticating the tokens, generated along with the first one.
+    key: Option<[u64; 4]>,
+}
+
+impl TokenAuthority {
+    /// Issues a new token, which is valid for the next `ttl_seconds` seconds.
+    pub fn generate_token(&mut self, ttl_seconds: u32) -> Result<String, Error> {
+        if ttl_seconds < MIN_TOKEN_TTL_SECONDS || ttl_seconds > MAX_TOKEN_TTL_SECONDS {
+            return Err(Error::InvalidTtlValue(ttl_seconds));
+        }
+
+        let key = match self.key {
+            Some(key) => key,
+            None => {
+                let key = generate_key().map_err(Error::EntropySource)?;
+                self.key = Some(key);
+                key
+            }
+        };
+        let expiry = get_time(ClockType::Monotonic) + u64::from(ttl_seconds) * NANOS_PER_SECOND;
+        Ok(encode_token(&key, expiry))
+    }
+
+    /// Returns `true` if `token` was issued by this authority, and has not yet expired.
+    pub fn is_valid(&self, token: &str) -> bool {
+        let key = match self.key {
+            Some(ref key) => key,
+            None => return false,
+        };
+        let bytes = match decode_hex(token) {
+            Some(bytes) => bytes,
+            None => return false,
+        };
+        if bytes.len() != TOKEN_LENGTH {
+            return false;
+        }
+
+        let mut expiry = [0u8; EXPIRY_LENGTH];
+        expiry.copy_from_slice(&bytes[..EXPIRY_LENGTH]);
+        let expiry = u64::from_le_bytes(expiry);
+        // The tags are compared in constant time, so that the response time doesn't tell how
+        // much of a forged tag is right.
+        let tag_mismatch = compute_tag(key, expiry)
+            .iter()
+            .zip(&bytes[EXPIRY_LENGTH..])
+            .fold(0, |acc, (a, b)| acc | (a ^ b));
+        tag_mismatch == 0 && get_time(ClockType::Monotonic) < expiry
+    }
+}
+
+fn generate_key() -> io::Result<[u64; 4]> {
+    let mut bytes = [0u8; 32];
+    fill_random(&mut bytes)?;
+    let mut key = [0u64; 4];
+    for (word, chunk) in key.iter_mut().zip(bytes.chunks(8)) {
+        let mut word_bytes = [0u8; 8];
+        word_bytes.copy_from_slice(chunk);
+        *word = u64::from_le_bytes(word_bytes);
+    }
+    Ok(key)
+}
+
+// Computes the tag authenticating `expiry`, made of two SipHash-2-4 MACs under independent keys.
+#[allow(deprecated)]
+fn compute_tag(key: &[u64; 4], expiry: u64) -> [u8; TAG_LENGTH] {
+    let mut tag = [0u8; TAG_LENGTH];
+    for (i, chunk) in tag.chunks_mut(8).enumerate() {
+        let mut hasher = SipHasher::new_with_keys(key[2 * i], key[2 * i + 1]);
+        hasher.write(&expiry.to_le_bytes());
+        chunk.copy_from_slice(&hasher.finish().to_le_bytes());
+    }
+    tag
+}
+
+fn encode_token(key: &[u64; 4], expiry: u64) -> String {
+    expiry
+        .to_le_bytes()
+        .iter()
+        .chain(compute_tag(key, expiry).iter())
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_generate_token() {
+        let mut authority = TokenAuthority::default();
+
+        let token = authority.generate_token(MIN_TOKEN_TTL_SECONDS).unwrap();
+        assert_eq!(token.len(), 2 * TOKEN_LENGTH);
+        assert!(token.chars().all(|c| c.is_ascii_hexdigit()));
+        assert!(authority.is_valid(&token));
+        assert!(!authority.is_valid("invalid-token"));
+        assert!(!authority.is_valid(""));
+
+        let other_token = authority.generate_token(MAX_TOKEN_TTL_SECONDS).unwrap();
+        assert_ne!(token, other_token);
+        assert!(authority.is_valid(&token));
+        assert!(authority.is_valid(&other_token));
+
+        // Expired tokens are no longer valid.
+        let expired_token = encode_token(authority.key.as_ref().unwrap(), 0);
+        assert!(!authority.is_valid(&expired_token));
+
+        match authority.generate_token(0) {
+            Err(Error::InvalidTtlValue(0)) => (),
+            _ => panic!("Expected an invalid TTL error."),
+        }
+        match authority.generate_token(MAX_TOKEN_TTL_SECONDS + 1) {
+            Err(Error::InvalidTtlValue(ttl)) => assert_eq!(ttl, MAX_TOKEN_TTL_SECONDS + 1),
+            _ => panic!("Expected an invalid TTL error."),
+        }
+    }
+
+    #[test]
+    fn test_forged_tokens() {
+        let mut authority = TokenAuthority::default();
+        let token = authority.generate_token(MIN_TOKEN_TTL_SECONDS).unwrap();
+
+        // The expiry time can't be extended without the key.
+        let mut bytes = decode_hex(&token).unwrap();
+        bytes[EXPIRY_LENGTH - 1] = 0xff;
+        let forged_token: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        assert!(!authority.is_valid(&forged_token));
+
+        // Neither can the tag.
+        let mut bytes = decode_hex(&token).unwrap();
+        bytes[TOKEN_LENGTH - 1] ^= 1;
+        let forged_token: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        assert!(!authority.is_valid(&forged_token));
+
+        // Truncated or malformed tokens are refused.
+        assert!(!authority.is_valid(&token[..token.len() - 2]));
+        assert!(!authority.is_valid(&format!("{}00", token)));
+        assert!(!authority.is_valid(&format!("+{}", &token[1..])));
+
+        // The tokens issued by another authority use another key.
+        let mut other_authority = TokenAuthority::default();
+        let other_token = other_authority
+            .generate_token(MAX_TOKEN_TTL_SECONDS)
+            .unwrap();
+        assert!(!authority.is_valid(&other_token));
+        assert!(other_authority.is_valid(&other_token));
+        assert!(!TokenAuthority::default().is_valid(&token));
+    }
+
+    #[test]
+    fn test_no_token_limit() {
+        // Issuing tokens doesn't use up any resource, so any number of them can be valid.
+        let mut authority = TokenAuthority::default();
+        let tokens: Vec<String> = (0..1000)
+            .map(|_| authority.generate_token(MAX_TOKEN_TTL_SECONDS).unwrap())
+            .collect();
+        assert!(tokens.iter().all(|token| authority.is_valid(token)));
+    }
+
+    #[test]
+    fn test_error_messages() {
+        assert_eq!(
+            Error::InvalidTtlValue(0).to_string(),
+            "Invalid time to live value provided for token: 0. Please provide a value between \
+             1 and 21600."
+        );
+        assert!(
+            Error::EntropySource(io::Error::new(io::ErrorKind::Other, "error"))
+                .to_string()
+                .starts_with("Cannot generate the MMDS token: ")
+        );
+    }
+}
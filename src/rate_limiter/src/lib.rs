@@ -31,6 +31,12 @@
 //! on top of their `size`. This initial extra credit does not replenish and
 //! can be used for an initial burst of data.
 //!
+//! Multiple rate limiters can additionally share a `RateLimiterGroup`, whose token
+//! buckets cap the aggregate rate of all its members. A `consume()` only succeeds
+//! if the tokens are available both in the limiter's own bucket and in the group
+//! bucket of the same `TokenType`. A limiter blocked on its group bucket relies on
+//! its own timer to be woken up and retry.
+//!
 //! The granularity for 'wake up' events when the rate limiter is blocked is
 //! currently hardcoded to `100 milliseconds`.
 //!
@@ -54,6 +60,7 @@ extern crate versionize;
 extern crate versionize_derive;
 
 use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
 use std::{fmt, io};
 use timerfd::{ClockId, SetTimeFlags, TimerFd, TimerState};
@@ -230,6 +237,7 @@ impl TokenBucket {
 }
 
 /// Enum that describes the type of token used.
+#[derive(Clone, Copy)]
 pub enum TokenType {
     /// Token type used for bandwidth limiting.
     Bytes,
@@ -237,6 +245,105 @@ pub enum TokenType {
     Ops,
 }
 
+/// Counters describing the activity of a `RateLimiter` since its creation.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct RateLimiterStats {
+    /// Number of times the rate limiter got blocked.
+    pub throttled_count: u64,
+    /// Total time spent in the blocked state, including the ongoing blocked interval.
+    pub blocked_time: Duration,
+    /// Number of `TokenType::Bytes` tokens consumed.
+    pub consumed_bytes: u64,
+    /// Number of `TokenType::Ops` tokens consumed.
+    pub consumed_ops: u64,
+}
+
+impl RateLimiterStats {
+    fn consumed_mut(&mut self, token_type: TokenType) -> &mut u64 {
+        match token_type {
+            TokenType::Bytes => &mut self.consumed_bytes,
+            TokenType::Ops => &mut self.consumed_ops,
+        }
+    }
+}
+
+/// Token buckets shared by multiple `RateLimiter`s, capping their aggregate rate.
+///
+/// A group does not own a timer; each member is woken up by its own timer when
+/// it gets blocked on the group budget.
+#[derive(Debug)]
+pub struct RateLimiterGroup {
+    id: String,
+    bandwidth: Option<TokenBucket>,
+    ops: Option<TokenBucket>,
+}
+
+impl RateLimiterGroup {
+    /// Creates a new group identified by `id` that can limit on both bytes/s and ops/s.
+    ///
+    /// The other arguments have the same meaning as for `RateLimiter::new()`. If either
+    /// bytes/ops *size* or *refill_time* are **zero**, the group is **disabled** for that
+    /// respective token type.
+    pub fn new(
+        id: String,
+        bytes_total_capacity: u64,
+        bytes_one_time_burst: Option<u64>,
+        bytes_complete_refill_time_ms: u64,
+        ops_total_capacity: u64,
+        ops_one_time_burst: Option<u64>,
+        ops_complete_refill_time_ms: u64,
+    ) -> Self {
+        RateLimiterGroup {
+            id,
+            bandwidth: RateLimiter::make_bucket(
+                bytes_total_capacity,
+                bytes_one_time_burst,
+                bytes_complete_refill_time_ms,
+            ),
+            ops: RateLimiter::make_bucket(
+                ops_total_capacity,
+                ops_one_time_burst,
+                ops_complete_refill_time_ms,
+            ),
+        }
+    }
+
+    fn token_bucket_mut(&mut self, token_type: TokenType) -> Option<&mut TokenBucket> {
+        match token_type {
+            TokenType::Bytes => self.bandwidth.as_mut(),
+            TokenType::Ops => self.ops.as_mut(),
+        }
+    }
+
+    /// Attempts to consume tokens from the group and returns whether that is possible.
+    pub fn consume(&mut self, tokens: u64, token_type: TokenType) -> bool {
+        self.token_bucket_mut(token_type)
+            .map_or(true, |bucket| bucket.reduce(tokens))
+    }
+
+    /// Adds tokens of `token_type` to their respective group bucket.
+    pub fn manual_replenish(&mut self, tokens: u64, token_type: TokenType) {
+        if let Some(bucket) = self.token_bucket_mut(token_type) {
+            bucket.replenish(tokens);
+        }
+    }
+
+    /// Returns the identifier of the group.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    /// Returns an immutable view of the inner bandwidth token bucket.
+    pub fn bandwidth(&self) -> Option<&TokenBucket> {
+        self.bandwidth.as_ref()
+    }
+
+    /// Returns an immutable view of the inner ops token bucket.
+    pub fn ops(&self) -> Option<&TokenBucket> {
+        self.ops.as_ref()
+    }
+}
+
 /// Rate Limiter that works on both bandwidth and ops/s limiting.
 ///
 /// Bandwidth (bytes/s) and ops/s limiting can be used at the same time or individually.
@@ -255,10 +362,15 @@ pub enum TokenType {
 pub struct RateLimiter {
     bandwidth: Option<TokenBucket>,
     ops: Option<TokenBucket>,
+    // Buckets shared with other rate limiters.
+    group: Option<Arc<Mutex<RateLimiterGroup>>>,
 
     timer_fd: TimerFd,
     // Internal flag that quickly determines timer state.
     timer_active: bool,
+    // When the limiter last got blocked, if it still is.
+    blocked_since: Option<Instant>,
+    stats: RateLimiterStats,
 }
 
 impl PartialEq for RateLimiter {
@@ -347,8 +459,11 @@ impl RateLimiter {
         Ok(RateLimiter {
             bandwidth: bytes_token_bucket,
             ops: ops_token_bucket,
+            group: None,
             timer_fd,
             timer_active: false,
+            blocked_since: None,
+            stats: RateLimiterStats::default(),
         })
     }
 
@@ -362,12 +477,28 @@ impl RateLimiter {
             TokenType::Ops => self.ops.as_mut(),
         };
         // Try to consume from the token bucket.
-        let success = match token_bucket {
-            Some(bucket) => bucket.reduce(tokens),
+        let mut success = match token_bucket {
+            Some(ref mut bucket) => bucket.reduce(tokens),
             // If bucket is not present rate limiting is disabled on token type,
             // consume() will always succeed.
             None => true,
         };
+        // The tokens must also be available in the group budget, if any.
+        if success {
+            if let Some(group) = self.group.as_ref() {
+                if !group
+                    .lock()
+                    .expect("Poisoned lock")
+                    .consume(tokens, token_type)
+                {
+                    // Give back the tokens taken from our own bucket.
+                    if let Some(bucket) = token_bucket {
+                        bucket.replenish(tokens);
+                    }
+                    success = false;
+                }
+            }
+        }
         // When we report budget is over, there will be no further calls here,
         // register a timer to replenish the bucket and resume processing;
         // make sure there is only one running timer for this limiter.
@@ -377,6 +508,12 @@ impl RateLimiter {
             self.timer_fd
                 .set_state(TIMER_REFILL_STATE, SetTimeFlags::Default);
             self.timer_active = true;
+            self.blocked_since = Some(Instant::now());
+            self.stats.throttled_count += 1;
+        }
+        if success {
+            let consumed = self.stats.consumed_mut(token_type);
+            *consumed = consumed.saturating_add(tokens);
         }
         success
     }
@@ -395,6 +532,15 @@ impl RateLimiter {
         if let Some(bucket) = token_bucket {
             bucket.replenish(tokens);
         }
+        // The reverted tokens were also consumed from the group budget.
+        if let Some(group) = self.group.as_ref() {
+            group
+                .lock()
+                .expect("Poisoned lock")
+                .manual_replenish(tokens, token_type);
+        }
+        let consumed = self.stats.consumed_mut(token_type);
+        *consumed = consumed.saturating_sub(tokens);
     }
 
     /// Returns whether this rate limiter is blocked.
@@ -419,6 +565,9 @@ impl RateLimiter {
             )),
             _ => {
                 self.timer_active = false;
+                if let Some(blocked_since) = self.blocked_since.take() {
+                    self.stats.blocked_time += blocked_since.elapsed();
+                }
                 Ok(())
             }
         }
@@ -454,6 +603,26 @@ impl RateLimiter {
     pub fn ops(&self) -> Option<&TokenBucket> {
         self.ops.as_ref()
     }
+
+    /// Returns the statistics of this rate limiter.
+    pub fn stats(&self) -> RateLimiterStats {
+        let mut stats = self.stats;
+        if let Some(blocked_since) = self.blocked_since {
+            stats.blocked_time += blocked_since.elapsed();
+        }
+        stats
+    }
+
+    /// Makes this rate limiter a member of `group`, whose buckets cap the aggregate rate of all
+    /// its members.
+    pub fn set_group(&mut self, group: Arc<Mutex<RateLimiterGroup>>) {
+        self.group = Some(group);
+    }
+
+    /// Returns the group this rate limiter is a member of, if any.
+    pub fn group(&self) -> Option<&Arc<Mutex<RateLimiterGroup>>> {
+        self.group.as_ref()
+    }
 }
 
 impl AsRawFd for RateLimiter {
@@ -641,6 +810,80 @@ pub(crate) mod tests {
         }
     }
 
+    #[test]
+    fn test_rate_limiter_stats() {
+        // rate limiter with limit of 1000 bytes/s, without ops limiting
+        let mut l = RateLimiter::new(1000, None, 1000, 0, None, 0).unwrap();
+        assert_eq!(l.stats(), RateLimiterStats::default());
+
+        assert!(l.consume(10, TokenType::Ops));
+        assert!(l.consume(900, TokenType::Bytes));
+        l.manual_replenish(100, TokenType::Bytes);
+        let stats = l.stats();
+        assert_eq!(stats.consumed_ops, 10);
+        assert_eq!(stats.consumed_bytes, 800);
+        assert_eq!(stats.throttled_count, 0);
+        assert_eq!(stats.blocked_time, Duration::from_secs(0));
+
+        // a failed consume blocks the limiter and does not count as consumed
+        assert!(!l.consume(500, TokenType::Bytes));
+        assert!(!l.consume(500, TokenType::Bytes));
+        assert_eq!(l.stats().consumed_bytes, 800);
+        assert_eq!(l.stats().throttled_count, 1);
+
+        thread::sleep(Duration::from_millis(REFILL_TIMER_INTERVAL_MS));
+        // the ongoing blocked interval is accounted for
+        assert!(l.stats().blocked_time >= Duration::from_millis(REFILL_TIMER_INTERVAL_MS));
+        assert!(l.event_handler().is_ok());
+        let blocked_time = l.stats().blocked_time;
+        assert!(blocked_time >= Duration::from_millis(REFILL_TIMER_INTERVAL_MS));
+        // once unblocked, the blocked time no longer increases
+        thread::sleep(Duration::from_millis(10));
+        assert_eq!(l.stats().blocked_time, blocked_time);
+    }
+
+    #[test]
+    fn test_rate_limiter_group() {
+        // group with a limit of 1000 bytes/s, without ops limiting
+        let group = Arc::new(Mutex::new(RateLimiterGroup::new(
+            "tenant".to_string(),
+            1000,
+            None,
+            1000,
+            0,
+            None,
+            0,
+        )));
+        // two members with a limit of 800 bytes/s each
+        let mut l1 = RateLimiter::new(800, None, 1000, 0, None, 0).unwrap();
+        let mut l2 = RateLimiter::new(800, None, 1000, 0, None, 0).unwrap();
+        l1.set_group(group.clone());
+        l2.set_group(group.clone());
+        assert!(l1.group().is_some());
+
+        // ops limiting is disabled on both the members and the group
+        assert!(l1.consume(u64::max_value(), TokenType::Ops));
+
+        assert!(l1.consume(600, TokenType::Bytes));
+        // l2 has enough budget of its own, but the group does not
+        assert!(!l2.consume(600, TokenType::Bytes));
+        assert!(l2.is_blocked());
+        // the failed consume must not have used l2's own budget
+        assert_eq!(l2.bandwidth().unwrap().budget(), 800);
+        assert!(l2.consume(400, TokenType::Bytes));
+        assert_eq!(group.lock().unwrap().bandwidth().unwrap().budget(), 0);
+
+        // reverting a consume also gives the tokens back to the group
+        l1.manual_replenish(100, TokenType::Bytes);
+        assert_eq!(group.lock().unwrap().bandwidth().unwrap().budget(), 100);
+        assert!(group.lock().unwrap().ops().is_none());
+
+        // after a timer period, the group budget is partially replenished
+        thread::sleep(Duration::from_millis(REFILL_TIMER_INTERVAL_MS));
+        assert!(l2.event_handler().is_ok());
+        assert!(l2.consume(100, TokenType::Bytes));
+    }
+
     #[test]
     fn test_rate_limiter_bandwidth() {
         // rate limiter with limit of 1000 bytes/s
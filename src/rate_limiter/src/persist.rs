@@ -3,6 +3,8 @@
 
 //! Defines the structures needed for saving/restoring a RateLimiter.
 
+use std::collections::BTreeMap;
+
 use super::*;
 use snapshot::Persist;
 use versionize::{VersionMap, Versionize, VersionizeResult};
@@ -49,26 +51,100 @@ impl Persist<'_> for TokenBucket {
     }
 }
 
+/// State for saving a RateLimiterGroup.
+#[derive(Versionize)]
+pub struct RateLimiterGroupState {
+    id: String,
+    ops: Option<TokenBucketState>,
+    bandwidth: Option<TokenBucketState>,
+}
+
+impl Persist<'_> for RateLimiterGroup {
+    type State = RateLimiterGroupState;
+    type ConstructorArgs = ();
+    type Error = ();
+
+    fn save(&self) -> Self::State {
+        RateLimiterGroupState {
+            id: self.id.clone(),
+            ops: self.ops.as_ref().map(|ops| ops.save()),
+            bandwidth: self.bandwidth.as_ref().map(|bw| bw.save()),
+        }
+    }
+
+    fn restore(_: Self::ConstructorArgs, state: &Self::State) -> Result<Self, Self::Error> {
+        Ok(RateLimiterGroup {
+            id: state.id.clone(),
+            // Safe to unwrap because TokenBucket::restore doesn't return errors.
+            ops: state
+                .ops
+                .as_ref()
+                .map(|ops| TokenBucket::restore((), ops).unwrap()),
+            bandwidth: state
+                .bandwidth
+                .as_ref()
+                .map(|bw| TokenBucket::restore((), bw).unwrap()),
+        })
+    }
+}
+
+/// The rate limiter groups restored so far, indexed by their ID.
+///
+/// Every member of a group saves the state of the group along with its own. The rate limiters
+/// restored with clones of the same `RestoredGroups` share their groups again, which are restored
+/// from the state saved by the first member.
+#[derive(Clone, Default)]
+pub struct RestoredGroups(Arc<Mutex<BTreeMap<String, Arc<Mutex<RateLimiterGroup>>>>>);
+
+impl RestoredGroups {
+    fn get_or_restore(&self, state: &RateLimiterGroupState) -> Arc<Mutex<RateLimiterGroup>> {
+        self.0
+            .lock()
+            .expect("Poisoned lock")
+            .entry(state.id.clone())
+            .or_insert_with(|| {
+                // Safe to unwrap because RateLimiterGroup::restore doesn't return errors.
+                Arc::new(Mutex::new(RateLimiterGroup::restore((), state).unwrap()))
+            })
+            .clone()
+    }
+}
+
 /// State for saving a RateLimiter.
 #[derive(Versionize)]
 pub struct RateLimiterState {
     ops: Option<TokenBucketState>,
     bandwidth: Option<TokenBucketState>,
+    #[version(start = 2, default_fn = "default_group")]
+    group: Option<RateLimiterGroupState>,
+}
+
+impl RateLimiterState {
+    fn default_group(_: u16) -> Option<RateLimiterGroupState> {
+        None
+    }
 }
 
 impl Persist<'_> for RateLimiter {
     type State = RateLimiterState;
-    type ConstructorArgs = ();
+    type ConstructorArgs = RestoredGroups;
     type Error = io::Error;
 
     fn save(&self) -> Self::State {
         RateLimiterState {
             ops: self.ops.as_ref().map(|ops| ops.save()),
             bandwidth: self.bandwidth.as_ref().map(|bw| bw.save()),
+            group: self
+                .group
+                .as_ref()
+                .map(|group| group.lock().expect("Poisoned lock").save()),
         }
     }
 
-    fn restore(_: Self::ConstructorArgs, state: &Self::State) -> Result<Self, Self::Error> {
+    fn restore(
+        constructor_args: Self::ConstructorArgs,
+        state: &Self::State,
+    ) -> Result<Self, Self::Error> {
         let rate_limiter = RateLimiter {
             // Safe to unwrap because TokenBucket::restore doesn't return errors.
             ops: state
@@ -79,8 +155,14 @@ impl Persist<'_> for RateLimiter {
                 .bandwidth
                 .as_ref()
                 .map(|bw| TokenBucket::restore((), bw).unwrap()),
+            group: state
+                .group
+                .as_ref()
+                .map(|group| constructor_args.get_or_restore(group)),
             timer_fd: TimerFd::new_custom(ClockId::Monotonic, true, true)?,
             timer_active: false,
+            blocked_since: None,
+            stats: RateLimiterStats::default(),
         };
 
         Ok(rate_limiter)
@@ -91,6 +173,22 @@ impl Persist<'_> for RateLimiter {
 mod tests {
     use super::*;
 
+    // The version map of the snapshots which save the rate limiter groups.
+    fn version_map() -> VersionMap {
+        let mut version_map = VersionMap::new();
+        version_map
+            .new_version()
+            .set_type_version(RateLimiterState::type_id(), 2);
+        version_map
+    }
+
+    // The layout of the rate limiter state saved by Firecracker v0.23.
+    #[derive(Versionize)]
+    struct RateLimiterStateV1 {
+        ops: Option<TokenBucketState>,
+        bandwidth: Option<TokenBucketState>,
+    }
+
     #[test]
     fn test_token_bucket_persistence() {
         let mut tb = TokenBucket::new(1000, Some(2000), 3000);
@@ -132,7 +230,8 @@ mod tests {
 
         // Check that RateLimiter restores correctly if untouched.
         let restored_rate_limiter =
-            RateLimiter::restore((), &rate_limiter.save()).expect("Unable to restore rate limiter");
+            RateLimiter::restore(RestoredGroups::default(), &rate_limiter.save())
+                .expect("Unable to restore rate limiter");
 
         assert!(rate_limiter
             .ops()
@@ -151,7 +250,8 @@ mod tests {
         rate_limiter.consume(10, TokenType::Bytes);
         rate_limiter.consume(10, TokenType::Ops);
         let restored_rate_limiter =
-            RateLimiter::restore((), &rate_limiter.save()).expect("Unable to restore rate limiter");
+            RateLimiter::restore(RestoredGroups::default(), &rate_limiter.save())
+                .expect("Unable to restore rate limiter");
 
         assert!(rate_limiter
             .ops()
@@ -169,7 +269,8 @@ mod tests {
         // Check that RateLimiter restores correctly after totally consuming tokens.
         rate_limiter.consume(1000, TokenType::Bytes);
         let restored_rate_limiter =
-            RateLimiter::restore((), &rate_limiter.save()).expect("Unable to restore rate limiter");
+            RateLimiter::restore(RestoredGroups::default(), &rate_limiter.save())
+                .expect("Unable to restore rate limiter");
 
         assert!(rate_limiter
             .ops()
@@ -188,7 +289,7 @@ mod tests {
             .serialize(&mut mem.as_mut_slice(), &version_map, 1)
             .unwrap();
         let restored_rate_limiter = RateLimiter::restore(
-            (),
+            RestoredGroups::default(),
             &RateLimiterState::deserialize(&mut mem.as_slice(), &version_map, 1).unwrap(),
         )
         .unwrap();
@@ -202,4 +303,90 @@ mod tests {
             .unwrap()
             .partial_eq(&restored_rate_limiter.bandwidth().unwrap()));
     }
+
+    #[test]
+    fn test_rate_limiter_group_persistence() {
+        let group = Arc::new(Mutex::new(RateLimiterGroup::new(
+            "tenant".to_string(),
+            1000,
+            None,
+            1000,
+            0,
+            None,
+            0,
+        )));
+        let mut l1 = RateLimiter::new(800, None, 1000, 0, None, 0).unwrap();
+        let mut l2 = RateLimiter::new(800, None, 1000, 0, None, 0).unwrap();
+        let l3 = RateLimiter::new(800, None, 1000, 0, None, 0).unwrap();
+        l1.set_group(group.clone());
+        l2.set_group(group.clone());
+        assert!(l1.consume(600, TokenType::Bytes));
+
+        // Save and restore the members of the group through serialization.
+        let version_map = version_map();
+        let restored_groups = RestoredGroups::default();
+        let mut restored = Vec::new();
+        for rate_limiter in [&l1, &l2, &l3].iter() {
+            let mut mem = vec![0; 4096];
+            rate_limiter
+                .save()
+                .serialize(&mut mem.as_mut_slice(), &version_map, 2)
+                .unwrap();
+            let state =
+                RateLimiterState::deserialize(&mut mem.as_slice(), &version_map, 2).unwrap();
+            restored.push(RateLimiter::restore(restored_groups.clone(), &state).unwrap());
+        }
+
+        // The restored members share the same group again, with the budget left when saved.
+        let restored_group = restored[0].group().unwrap().clone();
+        assert!(Arc::ptr_eq(&restored_group, restored[1].group().unwrap()));
+        assert!(!Arc::ptr_eq(&restored_group, &group));
+        assert!(restored[2].group().is_none());
+        assert_eq!(restored_group.lock().unwrap().id(), "tenant");
+        assert!(restored_group
+            .lock()
+            .unwrap()
+            .bandwidth()
+            .unwrap()
+            .partial_eq(group.lock().unwrap().bandwidth().unwrap()));
+        assert!(restored_group.lock().unwrap().ops().is_none());
+
+        // The group still caps the aggregate rate of the restored members.
+        assert!(!restored[1].consume(600, TokenType::Bytes));
+        assert!(restored[1].consume(400, TokenType::Bytes));
+        assert!(restored[2].consume(600, TokenType::Bytes));
+
+        // Rate limiters restored independently don't share their groups.
+        let other = RateLimiter::restore(RestoredGroups::default(), &l2.save()).unwrap();
+        assert!(!Arc::ptr_eq(&restored_group, other.group().unwrap()));
+    }
+
+    #[test]
+    fn test_restore_v1_state() {
+        let rate_limiter = RateLimiter::new(1000, None, 1000, 100, None, 1000).unwrap();
+        let state = rate_limiter.save();
+        let mut blob = Vec::new();
+        RateLimiterStateV1 {
+            ops: state.ops,
+            bandwidth: state.bandwidth,
+        }
+        .serialize(&mut blob, &version_map(), 1)
+        .unwrap();
+
+        // The whole v1 blob is read, and the rate limiter isn't part of any group.
+        let mut reader = blob.as_slice();
+        let state = RateLimiterState::deserialize(&mut reader, &version_map(), 1).unwrap();
+        assert!(reader.is_empty());
+        let restored_rate_limiter =
+            RateLimiter::restore(RestoredGroups::default(), &state).unwrap();
+        assert!(restored_rate_limiter.group().is_none());
+        assert!(rate_limiter
+            .ops()
+            .unwrap()
+            .partial_eq(&restored_rate_limiter.ops().unwrap()));
+        assert!(rate_limiter
+            .bandwidth()
+            .unwrap()
+            .partial_eq(&restored_rate_limiter.bandwidth().unwrap()));
+    }
 }
@@ -0,0 +1,53 @@
+// Copyright 2020 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::net::Ipv6Addr;
+
+/// Checks if an IPv6 address can only be reached from within the local network, which means it
+/// is either a link-local unicast address (fe80::/10, RFC 4291), or a unique local address
+/// (fc00::/7, RFC 4193), like the `fd00:ec2::254` address used by EC2.
+/// # Examples
+///
+/// ```
+/// use std::net::Ipv6Addr;
+/// use utils::net::ipv6addr::is_link_local_valid;
+///
+/// is_link_local_valid(Ipv6Addr::new(0xfd00, 0xec2, 0, 0, 0, 0, 0, 0x254));
+///
+pub fn is_link_local_valid(ipv6_addr: Ipv6Addr) -> bool {
+    let first_segment = ipv6_addr.segments()[0];
+    first_segment & 0xffc0 == 0xfe80 || first_segment & 0xfe00 == 0xfc00
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::net::ipv6addr::is_link_local_valid;
+    use std::net::Ipv6Addr;
+
+    #[test]
+    fn test_is_link_local_valid() {
+        // Global, loopback, unspecified and multicast addresses.
+        let mut ipv6_addr = Ipv6Addr::new(0x2001, 0xdb8, 0, 0, 0, 0, 0, 1);
+        assert!(!is_link_local_valid(ipv6_addr));
+        assert!(!is_link_local_valid(Ipv6Addr::LOCALHOST));
+        assert!(!is_link_local_valid(Ipv6Addr::UNSPECIFIED));
+        ipv6_addr = Ipv6Addr::new(0xff02, 0, 0, 0, 0, 0, 0, 1);
+        assert!(!is_link_local_valid(ipv6_addr));
+
+        // Right outside the link-local unicast range (fe80::/10).
+        ipv6_addr = Ipv6Addr::new(0xfec0, 0, 0, 0, 0, 0, 0, 1);
+        assert!(!is_link_local_valid(ipv6_addr));
+
+        // Link-local unicast addresses.
+        ipv6_addr = Ipv6Addr::new(0xfe80, 0, 0, 0, 0, 0, 0, 1);
+        assert!(is_link_local_valid(ipv6_addr));
+        ipv6_addr = Ipv6Addr::new(0xfebf, 0xffff, 0, 0, 0, 0, 0, 1);
+        assert!(is_link_local_valid(ipv6_addr));
+
+        // Unique local addresses (fc00::/7).
+        ipv6_addr = Ipv6Addr::new(0xfc00, 0, 0, 0, 0, 0, 0, 1);
+        assert!(is_link_local_valid(ipv6_addr));
+        ipv6_addr = Ipv6Addr::new(0xfd00, 0xec2, 0, 0, 0, 0, 0, 0x254);
+        assert!(is_link_local_valid(ipv6_addr));
+    }
+}
@@ -15,4 +15,6 @@ mod tap;
 
 /// Provides IPv4 address utility methods.
 pub mod ipv4addr;
+/// Provides IPv6 address utility methods.
+pub mod ipv6addr;
 pub use self::tap::{Error as TapError, Tap};
@@ -177,6 +177,50 @@ impl Tap {
         Ok(())
     }
 
+    /// Get the MTU of the tap interface.
+    pub fn mtu(&self) -> Result<u16> {
+        let sock = create_socket()?;
+
+        let mut ifreq = self.get_ifreq();
+
+        // ioctl is safe. Called with a valid sock fd, and we check the return.
+        let ret = unsafe {
+            ioctl_with_mut_ref(
+                &sock,
+                c_ulong::from(net_gen::sockios::SIOCGIFMTU),
+                &mut ifreq,
+            )
+        };
+        if ret < 0 {
+            return Err(Error::IoctlError(IoError::last_os_error()));
+        }
+
+        // We only access one field of the ifru union, hence this is safe.
+        Ok(unsafe { *ifreq.ifr_ifru.ifru_mtu.as_ref() } as u16)
+    }
+
+    /// Set the MTU of the tap interface.
+    pub fn set_mtu(&self, mtu: u16) -> Result<()> {
+        let sock = create_socket()?;
+
+        let mut ifreq = self.get_ifreq();
+
+        // We only access one field of the ifru union, hence this is safe.
+        unsafe {
+            let ifru_mtu = ifreq.ifr_ifru.ifru_mtu.as_mut();
+            *ifru_mtu = c_int::from(mtu);
+        }
+
+        // ioctl is safe. Called with a valid sock fd, and we check the return.
+        let ret =
+            unsafe { ioctl_with_ref(&sock, c_ulong::from(net_gen::sockios::SIOCSIFMTU), &ifreq) };
+        if ret < 0 {
+            return Err(Error::IoctlError(IoError::last_os_error()));
+        }
+
+        Ok(())
+    }
+
     /// Set the size of the vnet hdr.
     pub fn set_vnet_hdr_size(&self, size: c_int) -> Result<()> {
         // ioctl is safe. Called with a valid tap fd, and we check the return.
@@ -412,6 +456,16 @@ mod tests {
         assert!(ret.is_ok());
     }
 
+    #[test]
+    fn test_tap_mtu() {
+        let tap = Tap::new().unwrap();
+        tap.set_mtu(9000).unwrap();
+        assert_eq!(tap.mtu().unwrap(), 9000);
+        // The kernel rejects MTUs which are too small for an Ethernet device.
+        assert!(tap.set_mtu(10).is_err());
+        assert_eq!(tap.mtu().unwrap(), 9000);
+    }
+
     #[test]
     fn test_tap_get_ifreq() {
         let tap = Tap::new().unwrap();
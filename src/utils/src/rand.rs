@@ -1,6 +1,8 @@
 // Copyright 2019 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
+use std::io;
+
 use crate::time;
 
 /// Generates pseudo random u32 numbers based on the current timestamp.
@@ -12,6 +14,34 @@ pub fn xor_rng_u32() -> u32 {
     t ^ (t << 5)
 }
 
+/// Fills `buf` with random bytes, taken from the kernel's cryptographically secure source. The
+/// call blocks until the source is initialized.
+pub fn fill_random(buf: &mut [u8]) -> io::Result<()> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        let remaining = &mut buf[filled..];
+        // Safe because the kernel writes at most `remaining.len()` bytes to a valid buffer, and
+        // we check the return value.
+        let ret = unsafe {
+            libc::syscall(
+                libc::SYS_getrandom,
+                remaining.as_mut_ptr(),
+                remaining.len(),
+                0,
+            )
+        };
+        if ret < 0 {
+            let err = io::Error::last_os_error();
+            if err.kind() == io::ErrorKind::Interrupted {
+                continue;
+            }
+            return Err(err);
+        }
+        filled += ret as usize;
+    }
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -22,4 +52,14 @@ mod tests {
             assert_ne!(xor_rng_u32(), xor_rng_u32());
         }
     }
+
+    #[test]
+    fn test_fill_random() {
+        let mut a = [0u8; 32];
+        let mut b = [0u8; 32];
+        fill_random(&mut a).unwrap();
+        fill_random(&mut b).unwrap();
+        assert_ne!(a, b);
+        fill_random(&mut []).unwrap();
+    }
 }
@@ -11,14 +11,15 @@ use std::sync::{Arc, Mutex};
 use super::{Error, Vmm};
 
 use arch::InitrdConfig;
+use default_syscalls::{get_seccomp_filter, get_vmm_seccomp_filter};
 #[cfg(target_arch = "x86_64")]
 use device_manager::legacy::PortIODeviceManager;
 use device_manager::mmio::MMIODeviceManager;
 use devices::legacy::Serial;
-use devices::virtio::{MmioTransport, Vsock, VsockUnixBackend};
+use devices::virtio::{MmioTransport, VhostVsock, Vsock, VsockUnixBackend};
 use kernel::cmdline::Cmdline as KernelCmdline;
 use polly::event_manager::{Error as EventManagerError, EventManager};
-use seccomp::BpfProgramRef;
+use seccomp::{BpfProgram, BpfProgramRef, SeccompLevel};
 use utils::eventfd::EventFd;
 use utils::terminal::Terminal;
 use utils::time::TimestampUs;
@@ -72,6 +73,8 @@ pub enum StartMicrovmError {
     RegisterNetDevice(device_manager::mmio::Error),
     /// Cannot initialize a MMIO Vsock Device or add a device to the MMIO Bus.
     RegisterVsockDevice(device_manager::mmio::Error),
+    /// Cannot build the seccomp filters.
+    SeccompFilters(seccomp::SeccompError),
 }
 
 /// It's convenient to automatically convert `kernel::cmdline::Error`s
@@ -169,6 +172,7 @@ impl Display for StartMicrovmError {
                     err_msg
                 )
             }
+            SeccompFilters(err) => write!(f, "Cannot build the seccomp filters. {}", err),
         }
     }
 }
@@ -222,7 +226,38 @@ impl VmmEventsObserver for SerialStdin {
 pub fn build_microvm(
     vm_resources: &super::resources::VmResources,
     event_manager: &mut EventManager,
-    seccomp_filter: BpfProgramRef,
+    seccomp_level: SeccompLevel,
+) -> std::result::Result<Arc<Mutex<Vmm>>, StartMicrovmError> {
+    // Only the VMM thread may open TCP sockets, and only if vsock connections are forwarded
+    // to some.
+    let tcp_forwarding = vm_resources.vsock.get().map_or(false, |vsock| {
+        vsock
+            .lock()
+            .expect("Poisoned lock")
+            .backend()
+            .routes()
+            .has_tcp_routes()
+    });
+    let vmm_seccomp_filter = get_vmm_seccomp_filter(seccomp_level, tcp_forwarding)
+        .map_err(StartMicrovmError::SeccompFilters)?;
+    let vcpu_seccomp_filter =
+        get_seccomp_filter(seccomp_level).map_err(StartMicrovmError::SeccompFilters)?;
+
+    build_microvm_with_filters(
+        vm_resources,
+        event_manager,
+        vmm_seccomp_filter,
+        &vcpu_seccomp_filter,
+    )
+}
+
+/// Builds and starts a microVM like `build_microvm`, loading the given seccomp filters on the
+/// VMM and vCPU threads instead of the default ones.
+pub fn build_microvm_with_filters(
+    vm_resources: &super::resources::VmResources,
+    event_manager: &mut EventManager,
+    vmm_seccomp_filter: BpfProgram,
+    vcpu_seccomp_filter: BpfProgramRef,
 ) -> std::result::Result<Arc<Mutex<Vmm>>, StartMicrovmError> {
     let boot_config = vm_resources
         .boot_source()
@@ -333,6 +368,9 @@ pub fn build_microvm(
     if let Some(vsock) = vm_resources.vsock.get() {
         attach_unixsock_vsock_device(&mut vmm, &mut boot_cmdline, vsock, event_manager)?;
     }
+    if let Some(vsock) = vm_resources.vsock.get_vhost() {
+        attach_vhost_vsock_device(&mut vmm, &mut boot_cmdline, vsock, event_manager)?;
+    }
     attach_net_devices(
         &mut vmm,
         &mut boot_cmdline,
@@ -348,8 +386,7 @@ pub fn build_microvm(
         &initrd,
         boot_cmdline,
     )?;
-    // Firecracker uses the same seccomp filter for all threads.
-    vmm.start_vcpus(vcpus, seccomp_filter.to_vec(), seccomp_filter)
+    vmm.start_vcpus(vcpus, vmm_seccomp_filter, vcpu_seccomp_filter)
         .map_err(StartMicrovmError::Internal)?;
 
     let vmm = Arc::new(Mutex::new(vmm));
@@ -713,6 +750,28 @@ fn attach_unixsock_vsock_device(
     Ok(())
 }
 
+fn attach_vhost_vsock_device(
+    vmm: &mut Vmm,
+    cmdline: &mut KernelCmdline,
+    vhost_vsock: &Arc<Mutex<VhostVsock>>,
+    event_manager: &mut EventManager,
+) -> std::result::Result<(), StartMicrovmError> {
+    use self::StartMicrovmError::*;
+
+    event_manager
+        .add_subscriber(vhost_vsock.clone())
+        .map_err(RegisterEvent)?;
+
+    let id = String::from(vhost_vsock.lock().expect("Poisoned lock").id());
+    // The device mutex mustn't be locked here otherwise it will deadlock.
+    let device = MmioTransport::new(vmm.guest_memory().clone(), vhost_vsock.clone());
+    vmm.mmio_device_manager
+        .register_new_virtio_mmio_device(vmm.vm.fd(), id, device, cmdline)
+        .map_err(RegisterVsockDevice)?;
+
+    Ok(())
+}
+
 #[cfg(test)]
 pub mod tests {
     use std::io::Cursor;
@@ -721,6 +780,7 @@ pub mod tests {
     use arch::DeviceType;
     use devices::virtio::{TYPE_BLOCK, TYPE_VSOCK};
     use kernel::cmdline::Cmdline;
+    use mmds::data_store::MmdsInstances;
     use polly::event_manager::EventManager;
     use utils::tempfile::TempFile;
     use vmm_config::boot_source::DEFAULT_KERNEL_CMDLINE;
@@ -847,7 +907,9 @@ pub mod tests {
         net_config: NetworkInterfaceConfig,
     ) {
         let mut net_builder = NetBuilder::new();
-        net_builder.build(net_config).unwrap();
+        net_builder
+            .build(net_config, &MmdsInstances::default())
+            .unwrap();
 
         let res = attach_net_devices(vmm, cmdline, &net_builder, event_manager);
         assert!(res.is_ok());
@@ -965,6 +1027,9 @@ pub mod tests {
             rx_rate_limiter: None,
             tx_rate_limiter: None,
             allow_mmds_requests: true,
+            mmds_id: None,
+            mtu: None,
+            dhcp: None,
         };
 
         let mut cmdline = default_kernel_cmdline();
@@ -977,7 +1042,9 @@ pub mod tests {
 
         // We can not attach it once more.
         let mut net_builder = NetBuilder::new();
-        assert!(net_builder.build(network_interface).is_err());
+        assert!(net_builder
+            .build(network_interface, &MmdsInstances::default())
+            .is_err());
     }
 
     #[test]
@@ -1185,6 +1252,9 @@ pub mod tests {
             io::Error::from_raw_os_error(0),
         ));
         let _ = format!("{}{:?}", err, err);
+
+        let err = SeccompFilters(seccomp::SeccompError::Level(3));
+        let _ = format!("{}{:?}", err, err);
     }
 
     #[test]
@@ -28,6 +28,8 @@ pub fn default_filter() -> Result<SeccompFilter, Error> {
             allow_syscall(libc::SYS_epoll_pwait),
             #[cfg(all(target_env = "gnu", target_arch = "x86_64"))]
             allow_syscall(libc::SYS_epoll_wait),
+            // Used to create the event of each vsock connection served by the MMDS.
+            allow_syscall(libc::SYS_eventfd2),
             allow_syscall(libc::SYS_exit),
             allow_syscall(libc::SYS_exit_group),
             allow_syscall_if(
@@ -90,7 +92,7 @@ pub fn default_filter() -> Result<SeccompFilter, Error> {
             allow_syscall(libc::SYS_sigaltstack),
             allow_syscall_if(
                 libc::SYS_socket,
-                or![and![Cond::new(0, ArgLen::DWORD, Eq, libc::AF_UNIX as u64)?],],
+                or![and![Cond::new(0, ArgLen::DWORD, Eq, libc::AF_UNIX as u64)?]],
             ),
             #[cfg(target_arch = "x86_64")]
             allow_syscall(libc::SYS_stat),
@@ -114,15 +116,51 @@ pub fn default_filter() -> Result<SeccompFilter, Error> {
     )?)
 }
 
+/// The filter of the VMM thread, which is the default one, extended with the rules required
+/// to forward vsock connections to TCP sockets on the host loopback when `tcp_forwarding` is set.
+pub fn vmm_filter(tcp_forwarding: bool) -> Result<SeccompFilter, Error> {
+    let mut filter = default_filter()?;
+    if tcp_forwarding {
+        filter.add_rules(
+            libc::SYS_socket,
+            or![and![Cond::new(0, ArgLen::DWORD, Eq, libc::AF_INET as u64)?]],
+        )?;
+        // Used to disable Nagle's algorithm on the forwarded connections.
+        filter.add_rules(
+            libc::SYS_setsockopt,
+            or![and![
+                Cond::new(1, ArgLen::DWORD, Eq, libc::IPPROTO_TCP as u64)?,
+                Cond::new(2, ArgLen::DWORD, Eq, libc::TCP_NODELAY as u64)?,
+            ]],
+        )?;
+    }
+    Ok(filter)
+}
+
 /// Generate a BPF program based on a seccomp level value.
 pub fn get_seccomp_filter(seccomp_level: SeccompLevel) -> Result<BpfProgram, SeccompError> {
+    compile_filter(seccomp_level, default_filter)
+}
+
+/// Generate the BPF program of the VMM thread based on a seccomp level value.
+pub fn get_vmm_seccomp_filter(
+    seccomp_level: SeccompLevel,
+    tcp_forwarding: bool,
+) -> Result<BpfProgram, SeccompError> {
+    compile_filter(seccomp_level, || vmm_filter(tcp_forwarding))
+}
+
+fn compile_filter<F>(seccomp_level: SeccompLevel, filter: F) -> Result<BpfProgram, SeccompError>
+where
+    F: Fn() -> Result<SeccompFilter, Error>,
+{
     match seccomp_level {
         SeccompLevel::None => Ok(vec![]),
-        SeccompLevel::Basic => default_filter()
+        SeccompLevel::Basic => filter()
             .and_then(|filter| Ok(filter.allow_all()))
             .and_then(|filter| filter.try_into())
             .map_err(SeccompError::SeccompFilter),
-        SeccompLevel::Advanced => default_filter()
+        SeccompLevel::Advanced => filter()
             .and_then(|filter| filter.try_into())
             .map_err(SeccompError::SeccompFilter),
     }
@@ -130,7 +168,7 @@ pub fn get_seccomp_filter(seccomp_level: SeccompLevel) -> Result<BpfProgram, Sec
 
 #[cfg(test)]
 mod tests {
-    use super::get_seccomp_filter;
+    use super::{get_seccomp_filter, get_vmm_seccomp_filter};
     use seccomp::SeccompLevel;
 
     #[test]
@@ -139,4 +177,25 @@ mod tests {
         assert!(get_seccomp_filter(SeccompLevel::Basic).is_ok());
         assert!(get_seccomp_filter(SeccompLevel::Advanced).is_ok());
     }
+
+    #[test]
+    fn test_get_vmm_seccomp_filter() {
+        assert!(get_vmm_seccomp_filter(SeccompLevel::None, true)
+            .unwrap()
+            .is_empty());
+        assert!(get_vmm_seccomp_filter(SeccompLevel::Basic, true).is_ok());
+
+        // Without TCP forwarding, the VMM thread uses the same filter as the other threads.
+        assert_eq!(
+            get_vmm_seccomp_filter(SeccompLevel::Advanced, false).unwrap(),
+            get_seccomp_filter(SeccompLevel::Advanced).unwrap()
+        );
+        // The TCP forwarding rules are only added to the VMM thread filter.
+        assert!(
+            get_vmm_seccomp_filter(SeccompLevel::Advanced, true)
+                .unwrap()
+                .len()
+                > get_seccomp_filter(SeccompLevel::Advanced).unwrap().len()
+        );
+    }
 }
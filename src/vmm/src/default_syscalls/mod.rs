@@ -12,6 +12,8 @@ mod filters;
 
 pub use self::filters::default_filter;
 pub use self::filters::get_seccomp_filter;
+pub use self::filters::get_vmm_seccomp_filter;
+pub use self::filters::vmm_filter;
 
 // See include/uapi/asm-generic/fcntl.h in the kernel code.
 const FCNTL_FD_CLOEXEC: u64 = 1;
@@ -65,6 +67,19 @@ const TUNSETIFF: u64 = 0x4004_54ca;
 const TUNSETOFFLOAD: u64 = 0x4004_54d0;
 const TUNSETVNETHDRSZ: u64 = 0x4004_54d8;
 
+// See include/uapi/linux/vhost.h in the kernel code.
+const VHOST_GET_FEATURES: u64 = 0x8008_af00;
+const VHOST_SET_FEATURES: u64 = 0x4008_af00;
+const VHOST_SET_OWNER: u64 = 0xaf01;
+const VHOST_SET_MEM_TABLE: u64 = 0x4008_af03;
+const VHOST_SET_VRING_NUM: u64 = 0x4008_af10;
+const VHOST_SET_VRING_ADDR: u64 = 0x4028_af11;
+const VHOST_SET_VRING_BASE: u64 = 0x4008_af12;
+const VHOST_SET_VRING_KICK: u64 = 0x4008_af20;
+const VHOST_SET_VRING_CALL: u64 = 0x4008_af21;
+const VHOST_VSOCK_SET_GUEST_CID: u64 = 0x4008_af60;
+const VHOST_VSOCK_SET_RUNNING: u64 = 0x4004_af61;
+
 fn create_ioctl_seccomp_rule() -> Result<Vec<SeccompRule>, Error> {
     Ok(or![
         and![Cond::new(1, ArgLen::DWORD, Eq, TCSETS)?],
@@ -87,6 +102,17 @@ fn create_ioctl_seccomp_rule() -> Result<Vec<SeccompRule>, Error> {
         and![Cond::new(1, ArgLen::DWORD, Eq, TUNSETIFF)?],
         and![Cond::new(1, ArgLen::DWORD, Eq, TUNSETOFFLOAD)?],
         and![Cond::new(1, ArgLen::DWORD, Eq, TUNSETVNETHDRSZ)?],
+        and![Cond::new(1, ArgLen::DWORD, Eq, VHOST_GET_FEATURES)?],
+        and![Cond::new(1, ArgLen::DWORD, Eq, VHOST_SET_FEATURES)?],
+        and![Cond::new(1, ArgLen::DWORD, Eq, VHOST_SET_OWNER)?],
+        and![Cond::new(1, ArgLen::DWORD, Eq, VHOST_SET_MEM_TABLE)?],
+        and![Cond::new(1, ArgLen::DWORD, Eq, VHOST_SET_VRING_NUM)?],
+        and![Cond::new(1, ArgLen::DWORD, Eq, VHOST_SET_VRING_ADDR)?],
+        and![Cond::new(1, ArgLen::DWORD, Eq, VHOST_SET_VRING_BASE)?],
+        and![Cond::new(1, ArgLen::DWORD, Eq, VHOST_SET_VRING_KICK)?],
+        and![Cond::new(1, ArgLen::DWORD, Eq, VHOST_SET_VRING_CALL)?],
+        and![Cond::new(1, ArgLen::DWORD, Eq, VHOST_VSOCK_SET_GUEST_CID)?],
+        and![Cond::new(1, ArgLen::DWORD, Eq, VHOST_VSOCK_SET_RUNNING)?],
         and![Cond::new(1, ArgLen::DWORD, Eq, KVM_GET_LAPIC)?],
         and![Cond::new(1, ArgLen::DWORD, Eq, KVM_GET_SREGS)?],
         and![Cond::new(1, ArgLen::DWORD, Eq, KVM_RUN)?],
@@ -21,9 +21,12 @@ use devices::virtio::net::Net;
 use devices::virtio::persist::{MmioTransportConstructorArgs, MmioTransportState};
 use devices::virtio::vsock::persist::{VsockConstructorArgs, VsockState, VsockUdsConstructorArgs};
 use devices::virtio::vsock::{Vsock, VsockError, VsockUnixBackend, VsockUnixBackendError};
-use devices::virtio::{MmioTransport, TYPE_BLOCK, TYPE_NET, TYPE_VSOCK};
+use devices::virtio::{MmioTransport, TYPE_BLOCK, TYPE_NET, TYPE_VSOCK, VIRTIO_MMIO_INT_CONFIG};
+use devices::BusDevice;
 use kvm_ioctls::VmFd;
+use mmds::data_store::MmdsInstances;
 use polly::event_manager::{Error as EventMgrError, EventManager};
+use rate_limiter::persist::RestoredGroups;
 use snapshot::Persist;
 use versionize::{VersionMap, Versionize, VersionizeResult};
 use versionize_derive::Versionize;
@@ -38,6 +41,7 @@ pub enum Error {
     MmioTransport,
     Net(NetError),
     Vsock(VsockError),
+    VsockTransportReset(devices::Error),
     VsockUnixBackend(VsockUnixBackendError),
 }
 
@@ -95,6 +99,7 @@ pub struct MMIODevManagerConstructorArgs<'a> {
     pub mem: GuestMemoryMmap,
     pub vm: &'a VmFd,
     pub event_manager: &'a mut EventManager,
+    pub mmds: MmdsInstances,
 }
 
 impl<'a> Persist<'a> for MMIODeviceManager {
@@ -182,11 +187,16 @@ impl<'a> Persist<'a> for MMIODeviceManager {
         let mem = &constructor_args.mem;
         let vm = constructor_args.vm;
         let event_manager = constructor_args.event_manager;
+        // The members of a rate limiter group share it again once restored.
+        let rate_limiter_groups = RestoredGroups::default();
 
         for block_state in &state.block_devices {
             let device = Arc::new(Mutex::new(
                 Block::restore(
-                    BlockConstructorArgs { mem: mem.clone() },
+                    BlockConstructorArgs {
+                        mem: mem.clone(),
+                        rate_limiter_groups: rate_limiter_groups.clone(),
+                    },
                     &block_state.device_state,
                 )
                 .map_err(Error::Block)?,
@@ -213,7 +223,11 @@ impl<'a> Persist<'a> for MMIODeviceManager {
         for net_state in &state.net_devices {
             let device = Arc::new(Mutex::new(
                 Net::restore(
-                    NetConstructorArgs { mem: mem.clone() },
+                    NetConstructorArgs {
+                        mem: mem.clone(),
+                        mmds: constructor_args.mmds.clone(),
+                        rate_limiter_groups: rate_limiter_groups.clone(),
+                    },
                     &net_state.device_state,
                 )
                 .map_err(Error::Net)?,
@@ -229,6 +243,14 @@ impl<'a> Persist<'a> for MMIODeviceManager {
             };
             let mmio_transport = MmioTransport::restore(restore_args, transport_state)
                 .map_err(|()| Error::MmioTransport)?;
+
+            // The microVM may now be running on a different host, so ask the guest to
+            // announce itself (e.g. through gratuitous ARPs) to update its peers.
+            if device.lock().expect("Poisoned lock").announce() {
+                mmio_transport
+                    .interrupt(VIRTIO_MMIO_INT_CONFIG)
+                    .map_err(|_| Error::MmioTransport)?;
+            }
             dev_manager
                 .register_virtio_mmio_device(vm, device_id, mmio_transport, &mmio_slot)
                 .map_err(Error::DeviceManager);
@@ -240,6 +262,7 @@ impl<'a> Persist<'a> for MMIODeviceManager {
         if let Some(vsock_state) = &state.vsock_device {
             let ctor_args = VsockUdsConstructorArgs {
                 cid: vsock_state.device_state.frontend.cid,
+                mmds: constructor_args.mmds.default_instance(),
             };
             let backend = VsockUnixBackend::restore(ctor_args, &vsock_state.device_state.backend)
                 .map_err(Error::VsockUnixBackend)?;
@@ -260,13 +283,25 @@ impl<'a> Persist<'a> for MMIODeviceManager {
 
             let restore_args = MmioTransportConstructorArgs {
                 mem: mem.clone(),
-                device,
+                device: device.clone(),
             };
             let mmio_transport = MmioTransport::restore(restore_args, transport_state)
                 .map_err(|()| Error::MmioTransport)?;
+
+            // The host-side connections didn't survive the snapshot, so ask the guest driver
+            // to drop its side of them as well.
+            device
+                .lock()
+                .expect("Poisoned lock")
+                .send_transport_reset_event()
+                .map_err(Error::VsockTransportReset)?;
             dev_manager
                 .register_virtio_mmio_device(vm, device_id, mmio_transport, &mmio_slot)
                 .map_err(Error::DeviceManager);
+
+            event_manager
+                .add_subscriber(device)
+                .map_err(Error::EventManager);
         }
 
         Ok(dev_manager)
@@ -278,9 +313,10 @@ mod tests {
     use super::*;
     use builder::tests::*;
     use utils::tempfile::TempFile;
+    use version_map::VERSION_MAP;
     use vmm_config::net::NetworkInterfaceConfig;
     use vmm_config::vsock::tests::TempSockFile;
-    use vmm_config::vsock::VsockDeviceConfig;
+    use vmm_config::vsock::{VsockBackendType, VsockDeviceConfig};
 
     use polly::event_manager::EventManager;
 
@@ -389,7 +425,8 @@ mod tests {
     #[test]
     fn test_device_manager_persistence() {
         let mut buf = vec![0; 16384];
-        let version_map = VersionMap::new();
+        let version_map = VERSION_MAP.clone();
+        let version = version_map.latest_version();
         // These need to survive so the restored blocks find them.
         let _block_files;
         let _tmp_sock_file;
@@ -412,6 +449,9 @@ mod tests {
                 rx_rate_limiter: None,
                 tx_rate_limiter: None,
                 allow_mmds_requests: true,
+                mmds_id: None,
+                mtu: None,
+                dhcp: None,
             };
             insert_net_device(
                 &mut vmm,
@@ -425,7 +465,14 @@ mod tests {
             let vsock_config = VsockDeviceConfig {
                 vsock_id: vsock_dev_id.to_string(),
                 guest_cid: 3,
-                uds_path: orig_tmp_sock_file.path().clone(),
+                uds_path: Some(orig_tmp_sock_file.path().clone()),
+                backend: VsockBackendType::Unix,
+                guest_port_routes: Vec::new(),
+                host_allowed_ports: None,
+                max_connections: None,
+                per_port_metrics: false,
+                mmds_port: None,
+                seqpacket: false,
             };
             insert_vsock_device(&mut vmm, &mut cmdline, &mut event_manager, vsock_config);
             // This will be used by the restored device and will cleanup the UDS when test ends.
@@ -433,7 +480,7 @@ mod tests {
 
             vmm.mmio_device_manager
                 .save()
-                .serialize(&mut buf.as_mut_slice(), &version_map, 1)
+                .serialize(&mut buf.as_mut_slice(), &version_map, version)
                 .unwrap();
             vmm.mmio_device_manager.clone()
         };
@@ -441,11 +488,12 @@ mod tests {
         let mut event_manager = EventManager::new().expect("Unable to create EventManager");
         let vmm = default_vmm();
         let device_states: DeviceStates =
-            DeviceStates::deserialize(&mut buf.as_slice(), &version_map, 1).unwrap();
+            DeviceStates::deserialize(&mut buf.as_slice(), &version_map, version).unwrap();
         let restore_args = MMIODevManagerConstructorArgs {
             mem: vmm.guest_memory().clone(),
             vm: vmm.vm.fd(),
             event_manager: &mut event_manager,
+            mmds: MmdsInstances::default(),
         };
         let restored_dev_manager =
             MMIODeviceManager::restore(restore_args, &device_states).unwrap();
@@ -29,6 +29,7 @@ extern crate kernel;
 #[macro_use]
 extern crate logger;
 extern crate dumbo;
+extern crate mmds;
 extern crate rate_limiter;
 extern crate seccomp;
 extern crate snapshot;
@@ -384,6 +385,10 @@ impl Vmm {
     /// Saves the state of a paused Microvm.
     #[cfg(target_arch = "x86_64")]
     pub fn save_state(&mut self) -> std::result::Result<MicrovmState, SaveMicrovmStateError> {
+        if self.has_vhost_vsock() {
+            return Err(SaveMicrovmStateError::VhostVsock);
+        }
+
         let vcpu_states = self.save_vcpu_states()?;
 
         let vm_state = self
@@ -406,6 +411,27 @@ impl Vmm {
         })
     }
 
+    /// Checks whether the vsock device uses the vhost backend, whose state lives in the host
+    /// kernel.
+    #[cfg(target_arch = "x86_64")]
+    fn has_vhost_vsock(&self) -> bool {
+        use devices::virtio::{MmioTransport, VhostVsock, TYPE_VSOCK, VSOCK_DEV_ID};
+
+        self.get_bus_device(DeviceType::Virtio(TYPE_VSOCK), VSOCK_DEV_ID)
+            .map_or(false, |busdev| {
+                busdev
+                    .lock()
+                    .expect("Poisoned lock")
+                    .as_any()
+                    // Only MmioTransport implements BusDevice at this point.
+                    .downcast_ref::<MmioTransport>()
+                    .expect("Unexpected BusDevice type")
+                    .locked_device()
+                    .as_any()
+                    .is::<VhostVsock>()
+            })
+    }
+
     #[cfg(target_arch = "x86_64")]
     fn save_vcpu_states(&mut self) -> std::result::Result<Vec<VcpuState>, SaveMicrovmStateError> {
         for handle in self.vcpus_handles.iter() {
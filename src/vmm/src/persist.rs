@@ -53,6 +53,8 @@ pub enum SaveMicrovmStateError {
     InvalidVmState(vstate::Error),
     /// Failed to send event.
     SignalVcpu(vstate::Error),
+    /// The vhost-vsock device state cannot be saved.
+    VhostVsock,
 }
 
 impl Display for SaveMicrovmStateError {
@@ -62,6 +64,10 @@ impl Display for SaveMicrovmStateError {
             InvalidVcpuState => write!(f, "Unable to save Vcpu state."),
             InvalidVmState(err) => write!(f, "Unable to save Vm state. Error: {:?}", err),
             SignalVcpu(err) => write!(f, "Unable to signal Vcpu: {:?}", err),
+            VhostVsock => write!(
+                f,
+                "Unable to save the state of a microVM using a vhost-vsock device."
+            ),
         }
     }
 }
@@ -205,6 +211,7 @@ mod tests {
     use polly::event_manager::EventManager;
     use snapshot::Persist;
     use utils::tempfile::TempFile;
+    use version_map::VERSION_MAP;
     use vmm_config::net::NetworkInterfaceConfig;
     use vmm_config::vsock::tests::{default_config, TempSockFile};
 
@@ -225,6 +232,9 @@ mod tests {
             rx_rate_limiter: None,
             tx_rate_limiter: None,
             allow_mmds_requests: true,
+            mmds_id: None,
+            mtu: None,
+            dhcp: None,
         };
         insert_net_device(&mut vmm, &mut cmdline, event_manager, network_interface);
 
@@ -257,14 +267,15 @@ mod tests {
         };
 
         let mut buf = vec![0; 10000];
-        let version_map = VersionMap::new();
+        let version_map = VERSION_MAP.clone();
+        let version = version_map.latest_version();
 
         microvm_state
-            .serialize(&mut buf.as_mut_slice(), &version_map, 1)
+            .serialize(&mut buf.as_mut_slice(), &version_map, version)
             .unwrap();
 
         let restored_microvm_state =
-            MicrovmState::deserialize(&mut buf.as_slice(), &version_map, 1).unwrap();
+            MicrovmState::deserialize(&mut buf.as_slice(), &version_map, version).unwrap();
 
         assert_eq!(restored_microvm_state.vm_info, microvm_state.vm_info);
         assert_eq!(
@@ -314,5 +325,8 @@ mod tests {
 
         let err = SignalVcpu(vstate::Error::VcpuCountNotInitialized);
         let _ = format!("{}{:?}", err, err);
+
+        let err = VhostVsock;
+        let _ = format!("{}{:?}", err, err);
     }
 }
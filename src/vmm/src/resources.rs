@@ -3,10 +3,12 @@
 
 #![deny(warnings)]
 
+use std::collections::BTreeMap;
 use std::fs::File;
 
 use dumbo::ns::MmdsNetworkStack;
-use utils::net::ipv4addr::is_link_local_valid;
+use mmds::data_store::{AccessPolicy, MmdsInstances, DEFAULT_DATA_STORE_LIMIT};
+use utils::net::{ipv4addr, ipv6addr};
 use vmm_config::boot_source::{
     BootConfig, BootSourceConfig, BootSourceConfigError, DEFAULT_KERNEL_CMDLINE,
 };
@@ -17,6 +19,7 @@ use vmm_config::machine_config::{VmConfig, VmConfigError};
 use vmm_config::metrics::{init_metrics, MetricsConfig, MetricsConfigError};
 use vmm_config::mmds::{MmdsConfig, MmdsConfigError};
 use vmm_config::net::*;
+use vmm_config::rate_limiter_group::*;
 use vmm_config::vsock::*;
 use vstate::VcpuConfig;
 
@@ -43,6 +46,8 @@ pub enum Error {
     VsockDevice(VsockConfigError),
     /// MMDS configuration error.
     MmdsConfig(MmdsConfigError),
+    /// Rate limiter group configuration error.
+    RateLimiterGroup(RateLimiterGroupError),
 }
 
 /// Used for configuring a vmm from one single json passed to the Firecracker process.
@@ -50,6 +55,8 @@ pub enum Error {
 pub struct VmmConfig {
     #[serde(rename = "boot-source")]
     boot_source: BootSourceConfig,
+    #[serde(rename = "rate-limiter-groups", default)]
+    rate_limiter_groups: Vec<RateLimiterGroupConfig>,
     #[serde(rename = "drives")]
     block_devices: Vec<BlockDeviceConfig>,
     #[serde(rename = "network-interfaces", default)]
@@ -82,13 +89,19 @@ pub struct VmResources {
     pub net_builder: NetBuilder,
     /// The configuration for `MmdsNetworkStack`.
     pub mmds_config: Option<MmdsConfig>,
+    /// The rate limiter groups shared by devices.
+    pub rate_limiter_groups: RateLimiterGroups,
+    /// The MMDS instances served to this microVM's guest.
+    pub mmds: MmdsInstances,
 }
 
 impl VmResources {
-    /// Configures Vmm resources as described by the `config_json` param.
+    /// Configures Vmm resources as described by the `config_json` param. The microVM is served
+    /// the contents of the `mmds` instances.
     pub fn from_json(
         config_json: &str,
         instance_info: &InstanceInfo,
+        mmds: MmdsInstances,
     ) -> std::result::Result<Self, Error> {
         let vmm_config: VmmConfig = serde_json::from_slice::<VmmConfig>(config_json.as_bytes())
             .map_err(|_| Error::InvalidJson)?;
@@ -101,7 +114,10 @@ impl VmResources {
             init_metrics(metrics).map_err(Error::Metrics)?;
         }
 
-        let mut resources: Self = Self::default();
+        let mut resources: Self = Self {
+            mmds,
+            ..Self::default()
+        };
         if let Some(machine_config) = vmm_config.machine_config {
             resources
                 .set_vm_config(&machine_config)
@@ -112,6 +128,13 @@ impl VmResources {
             .set_boot_source(vmm_config.boot_source)
             .map_err(Error::BootSource)?;
 
+        // Groups must exist before the devices referencing them are created.
+        for group_config in vmm_config.rate_limiter_groups.into_iter() {
+            resources
+                .set_rate_limiter_group(group_config)
+                .map_err(Error::RateLimiterGroup)?;
+        }
+
         for drive_config in vmm_config.block_devices.into_iter() {
             resources
                 .set_block_device(drive_config)
@@ -245,7 +268,23 @@ impl VmResources {
         &mut self,
         block_device_config: BlockDeviceConfig,
     ) -> Result<DriveError> {
-        self.block.insert(block_device_config)
+        let group = self
+            .rate_limiter_groups
+            .resolve(block_device_config.rate_limiter.as_ref())
+            .map_err(DriveError::RateLimiterGroup)?;
+        let drive_id = block_device_config.drive_id.clone();
+        self.block.insert(block_device_config)?;
+
+        if let Some(group) = group {
+            self.block
+                .list
+                .iter()
+                .map(|block| block.lock().expect("Poisoned lock"))
+                .find(|block| block.id() == &drive_id)
+                .expect("Missing inserted block device")
+                .set_rate_limiter_group(group);
+        }
+        Ok(())
     }
 
     /// Builds a network device to be attached when the VM starts.
@@ -253,37 +292,105 @@ impl VmResources {
         &mut self,
         body: NetworkInterfaceConfig,
     ) -> Result<NetworkInterfaceError> {
-        self.net_builder.build(body).map(|net_device| {
-            // Update `Net` device `MmdsNetworkStack` IPv4 address.
+        let rx_group = self
+            .rate_limiter_groups
+            .resolve(body.rx_rate_limiter.as_ref())
+            .map_err(NetworkInterfaceError::RateLimiterGroup)?;
+        let tx_group = self
+            .rate_limiter_groups
+            .resolve(body.tx_rate_limiter.as_ref())
+            .map_err(NetworkInterfaceError::RateLimiterGroup)?;
+
+        self.net_builder.build(body, &self.mmds).map(|net_device| {
+            net_device
+                .lock()
+                .expect("Poisoned lock")
+                .set_rate_limiter_groups(rx_group, tx_group);
+            // Update `Net` device `MmdsNetworkStack` IPv4 and IPv6 addresses.
             match &self.mmds_config {
-                Some(cfg) => cfg.ipv4_addr().map_or((), |ipv4_addr| {
+                Some(cfg) => {
                     if let Some(mmds_ns) = net_device.lock().expect("Poisoned lock").mmds_ns_mut() {
-                        mmds_ns.set_ipv4_addr(ipv4_addr);
+                        if let Some(ipv4_addr) = cfg.ipv4_addr() {
+                            mmds_ns.set_ipv4_addr(ipv4_addr);
+                        }
+                        mmds_ns.set_ipv6_addr(cfg.ipv6_addr());
                     };
-                }),
+                }
                 None => (),
             };
         })
     }
 
+    /// Creates or replaces a rate limiter group that devices can reference by its ID.
+    pub fn set_rate_limiter_group(
+        &mut self,
+        config: RateLimiterGroupConfig,
+    ) -> Result<RateLimiterGroupError> {
+        self.rate_limiter_groups.insert(config)
+    }
+
     /// Sets a vsock device to be attached when the VM starts.
     pub fn set_vsock_device(&mut self, config: VsockDeviceConfig) -> Result<VsockConfigError> {
-        self.vsock.insert(config)
+        self.vsock.insert(config)?;
+        // The guest port routed to the MMDS is served from the default instance.
+        if let Some(vsock) = self.vsock.get() {
+            vsock
+                .lock()
+                .expect("Poisoned lock")
+                .backend_mut()
+                .set_mmds(self.mmds.default_instance());
+        }
+        Ok(())
     }
 
     /// Setter for mmds config.
     pub fn set_mmds_config(&mut self, config: MmdsConfig) -> Result<MmdsConfigError> {
         // Check IPv4 address validity.
         let ipv4_addr = match config.ipv4_addr() {
-            Some(ipv4_addr) if is_link_local_valid(ipv4_addr) => Ok(ipv4_addr),
+            Some(ipv4_addr) if ipv4addr::is_link_local_valid(ipv4_addr) => Ok(ipv4_addr),
             None => Ok(MmdsNetworkStack::default_ipv4_addr()),
             _ => Err(MmdsConfigError::InvalidIpv4Addr),
         }?;
 
-        // Update existing built network device `MmdsNetworkStack` IPv4 address.
+        // Check IPv6 address validity. The MMDS is not reachable over IPv6 unless an address
+        // is explicitly configured.
+        let ipv6_addr = match config.ipv6_addr() {
+            Some(ipv6_addr) if !ipv6addr::is_link_local_valid(ipv6_addr) => {
+                Err(MmdsConfigError::InvalidIpv6Addr)
+            }
+            ipv6_addr => Ok(ipv6_addr),
+        }?;
+
+        // Check that the access policies apply to keys, rather than to the whole contents.
+        let access_policies: BTreeMap<String, AccessPolicy> = config
+            .access_policies()
+            .iter()
+            .map(|(path, &policy)| {
+                if path.starts_with('/') && path.trim_end_matches('/').len() > 1 {
+                    Ok((path.clone(), policy.into()))
+                } else {
+                    Err(MmdsConfigError::InvalidAccessPolicyPath(path.clone()))
+                }
+            })
+            .collect::<std::result::Result<_, _>>()?;
+
+        // Apply the settings which the MMDS enforces by itself, to every instance.
+        let data_store_limit = config
+            .data_store_limit()
+            .unwrap_or(DEFAULT_DATA_STORE_LIMIT);
+        for mmds in self.mmds.all() {
+            let mut mmds = mmds.lock().expect("Poisoned lock");
+            mmds.set_data_store_limit(data_store_limit)
+                .map_err(|_| MmdsConfigError::DataStoreLimit(data_store_limit))?;
+            mmds.set_version(config.version().into());
+            mmds.set_access_policies(access_policies.clone());
+        }
+
+        // Update existing built network device `MmdsNetworkStack` IP addresses.
         for net_device in self.net_builder.iter_mut() {
             if let Some(mmds_ns) = net_device.lock().expect("Poisoned lock").mmds_ns_mut() {
-                mmds_ns.set_ipv4_addr(ipv4_addr)
+                mmds_ns.set_ipv4_addr(ipv4_addr);
+                mmds_ns.set_ipv6_addr(ipv6_addr);
             }
         }
 
@@ -307,7 +414,7 @@ mod tests {
     use vmm_config::machine_config::{CpuFeaturesTemplate, VmConfig, VmConfigError};
     use vmm_config::net::{NetBuilder, NetworkInterfaceConfig};
     use vmm_config::vsock::tests::{default_config, TempSockFile};
-    use vmm_config::RateLimiterConfig;
+    use vmm_config::{RateLimiterConfig, TokenBucketConfig};
     use vstate::VcpuConfig;
 
     fn default_net_cfg() -> NetworkInterfaceConfig {
@@ -324,12 +431,17 @@ mod tests {
             rx_rate_limiter: Some(RateLimiterConfig::default()),
             tx_rate_limiter: Some(RateLimiterConfig::default()),
             allow_mmds_requests: false,
+            mmds_id: None,
+            mtu: None,
+            dhcp: None,
         }
     }
 
     fn default_net_builder() -> NetBuilder {
         let mut net_builder = NetBuilder::new();
-        net_builder.build(default_net_cfg()).unwrap();
+        net_builder
+            .build(default_net_cfg(), &MmdsInstances::default())
+            .unwrap();
 
         net_builder
     }
@@ -375,6 +487,8 @@ mod tests {
             vsock: Default::default(),
             net_builder: default_net_builder(),
             mmds_config: None,
+            rate_limiter_groups: Default::default(),
+            mmds: MmdsInstances::default(),
         }
     }
 
@@ -411,6 +525,7 @@ mod tests {
             vmm_version: "SOME_VERSION".to_string(),
             app_name: "".to_string(),
         };
+        let mmds = MmdsInstances::default();
 
         // We will test different scenarios with invalid resources configuration and
         // check the expected errors. We include configuration for the kernel and rootfs
@@ -436,7 +551,7 @@ mod tests {
             rootfs_file.as_path().to_str().unwrap()
         );
 
-        match VmResources::from_json(json.as_str(), &default_instance_info) {
+        match VmResources::from_json(json.as_str(), &default_instance_info, mmds.clone()) {
             Err(Error::BootSource(BootSourceConfigError::InvalidKernelPath(_))) => (),
             _ => unreachable!(),
         }
@@ -460,7 +575,7 @@ mod tests {
             kernel_file.as_path().to_str().unwrap()
         );
 
-        match VmResources::from_json(json.as_str(), &default_instance_info) {
+        match VmResources::from_json(json.as_str(), &default_instance_info, mmds.clone()) {
             Err(Error::BlockDevice(DriveError::InvalidBlockDevicePath)) => (),
             _ => unreachable!(),
         }
@@ -490,7 +605,7 @@ mod tests {
             rootfs_file.as_path().to_str().unwrap()
         );
 
-        match VmResources::from_json(json.as_str(), &default_instance_info) {
+        match VmResources::from_json(json.as_str(), &default_instance_info, mmds.clone()) {
             Err(Error::VmConfig(VmConfigError::InvalidVcpuCount)) => (),
             _ => unreachable!(),
         }
@@ -520,7 +635,7 @@ mod tests {
             rootfs_file.as_path().to_str().unwrap()
         );
 
-        match VmResources::from_json(json.as_str(), &default_instance_info) {
+        match VmResources::from_json(json.as_str(), &default_instance_info, mmds.clone()) {
             Err(Error::VmConfig(VmConfigError::InvalidMemorySize)) => (),
             _ => unreachable!(),
         }
@@ -548,7 +663,7 @@ mod tests {
             rootfs_file.as_path().to_str().unwrap()
         );
 
-        match VmResources::from_json(json.as_str(), &default_instance_info) {
+        match VmResources::from_json(json.as_str(), &default_instance_info, mmds.clone()) {
             Err(Error::Logger(LoggerConfigError::InitializationFailure { .. })) => (),
             _ => unreachable!(),
         }
@@ -579,7 +694,7 @@ mod tests {
             rootfs_file.as_path().to_str().unwrap()
         );
 
-        match VmResources::from_json(json.as_str(), &default_instance_info) {
+        match VmResources::from_json(json.as_str(), &default_instance_info, mmds.clone()) {
             Err(Error::Metrics(MetricsConfigError::InitializationFailure { .. })) => (),
             _ => unreachable!(),
         }
@@ -614,7 +729,7 @@ mod tests {
             rootfs_file.as_path().to_str().unwrap()
         );
 
-        match VmResources::from_json(json.as_str(), &default_instance_info) {
+        match VmResources::from_json(json.as_str(), &default_instance_info, mmds.clone()) {
             Err(Error::NetDevice(NetworkInterfaceError::CreateNetworkDevice(
                 devices::virtio::net::Error::TapOpen { .. },
             ))) => (),
@@ -651,13 +766,22 @@ mod tests {
                         "ht_enabled": false
                     }},
                     "mmds-config": {{
-                        "ipv4_address": "169.254.170.2"
+                        "ipv4_address": "169.254.170.2",
+                        "ipv6_address": "fd00:ec2::254",
+                        "version": "V1",
+                        "data_store_limit": 51200,
+                        "access_policies": {{
+                            "/operator": "Hidden",
+                            "/latest/meta-data/credentials/": "TokenRequired"
+                        }}
                     }}
             }}"#,
             kernel_file.as_path().to_str().unwrap(),
             rootfs_file.as_path().to_str().unwrap(),
         );
-        assert!(VmResources::from_json(json.as_str(), &default_instance_info).is_ok());
+        assert!(
+            VmResources::from_json(json.as_str(), &default_instance_info, mmds.clone()).is_ok()
+        );
 
         // Test all configuration, this time trying to configure the MMDS with an
         // empty body. It will make it access the code path in which it sets the
@@ -694,7 +818,9 @@ mod tests {
             kernel_file.as_path().to_str().unwrap(),
             rootfs_file.as_path().to_str().unwrap(),
         );
-        assert!(VmResources::from_json(json.as_str(), &default_instance_info).is_ok());
+        assert!(
+            VmResources::from_json(json.as_str(), &default_instance_info, mmds.clone()).is_ok()
+        );
     }
 
     #[test]
@@ -818,6 +944,60 @@ mod tests {
         assert_eq!(vm_resources.block.list.len(), 2);
     }
 
+    #[test]
+    fn test_set_rate_limiter_group() {
+        let mut vm_resources = default_vm_resources();
+        let group_config = RateLimiterGroupConfig {
+            group_id: "tenant".to_string(),
+            bandwidth: None,
+            ops: Some(TokenBucketConfig {
+                size: 1000,
+                one_time_burst: None,
+                refill_time: 1000,
+            }),
+        };
+        let rate_limiter = Some(RateLimiterConfig {
+            group: Some("tenant".to_string()),
+            ..Default::default()
+        });
+
+        // Devices cannot reference a group that does not exist.
+        let (mut block_device_cfg, _file) = default_block_cfg();
+        block_device_cfg.drive_id = "block2".to_string();
+        block_device_cfg.rate_limiter = rate_limiter.clone();
+        // The test implementation of `Clone` drops the rate limiter config.
+        let mut retry_block_device_cfg = block_device_cfg.clone();
+        match vm_resources.set_block_device(block_device_cfg) {
+            Err(DriveError::RateLimiterGroup(RateLimiterGroupError::GroupNotFound(id))) => {
+                assert_eq!(id, "tenant")
+            }
+            _ => unreachable!(),
+        }
+
+        vm_resources
+            .set_rate_limiter_group(group_config.clone())
+            .unwrap();
+        retry_block_device_cfg.rate_limiter = rate_limiter.clone();
+        vm_resources
+            .set_block_device(retry_block_device_cfg)
+            .unwrap();
+        assert_eq!(vm_resources.block.list.len(), 2);
+
+        let mut net_device_cfg = default_net_cfg();
+        net_device_cfg.iface_id = "new_net_if".to_string();
+        net_device_cfg.guest_mac = Some(MacAddr::parse_str("01:23:45:67:89:0c").unwrap());
+        net_device_cfg.host_dev_name = "dummy_path3".to_string();
+        net_device_cfg.tx_rate_limiter = rate_limiter;
+        vm_resources.build_net_device(net_device_cfg).unwrap();
+        assert_eq!(vm_resources.net_builder.len(), 2);
+
+        // The group is now shared by the devices and cannot be replaced.
+        assert_eq!(
+            vm_resources.set_rate_limiter_group(group_config),
+            Err(RateLimiterGroupError::GroupInUse("tenant".to_string()))
+        );
+    }
+
     #[test]
     fn test_set_vsock_device() {
         let mut vm_resources = default_vm_resources();
@@ -834,6 +1014,26 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_set_mmds_config() {
+        let mut vm_resources = default_vm_resources();
+        // Access policies can't apply to the whole MMDS contents.
+        for path in &["", "/", "//", "key"] {
+            let config: MmdsConfig = serde_json::from_str(&format!(
+                r#"{{"access_policies": {{"{}": "Hidden"}}}}"#,
+                path
+            ))
+            .unwrap();
+            match vm_resources.set_mmds_config(config) {
+                Err(MmdsConfigError::InvalidAccessPolicyPath(invalid_path)) => {
+                    assert_eq!(invalid_path, *path)
+                }
+                _ => panic!("Expected an invalid access policy path error."),
+            }
+        }
+        assert!(vm_resources.mmds_config.is_none());
+    }
+
     #[test]
     fn test_set_net_device() {
         let mut vm_resources = default_vm_resources();
@@ -14,32 +14,40 @@ use super::Error as VmmError;
 use arch::DeviceType;
 use builder::StartMicrovmError;
 use device_manager::mmio::MMIO_CFG_SPACE_OFF;
-use devices::virtio::{Block, MmioTransport, Net, TYPE_BLOCK, TYPE_NET};
+use devices::virtio::{
+    Block, MmioTransport, Net, VirtioDevice, Vsock, VsockUnixBackend, TYPE_BLOCK, TYPE_NET,
+    TYPE_VSOCK, VSOCK_DEV_ID,
+};
 use logger::METRICS;
+use mmds::data_store::MmdsInstances;
 #[cfg(target_arch = "x86_64")]
 use persist;
 #[cfg(target_arch = "x86_64")]
 use persist::CreateSnapshotError;
 use polly::event_manager::EventManager;
 use resources::VmResources;
-use seccomp::BpfProgram;
+use seccomp::SeccompLevel;
 #[cfg(target_arch = "x86_64")]
 use version_map::VERSION_MAP;
 use vmm_config;
 use vmm_config::boot_source::{BootSourceConfig, BootSourceConfigError};
-use vmm_config::drive::{BlockDeviceConfig, DriveError};
+use vmm_config::drive::{BlockDeviceConfig, BlockDeviceStats, DriveError};
 use vmm_config::instance_info::InstanceInfo;
 use vmm_config::logger::{LoggerConfig, LoggerConfigError};
 use vmm_config::machine_config::{VmConfig, VmConfigError};
 use vmm_config::metrics::{MetricsConfig, MetricsConfigError};
 use vmm_config::mmds::{MmdsConfig, MmdsConfigError};
 use vmm_config::net::{
-    NetworkInterfaceConfig, NetworkInterfaceError, NetworkInterfaceUpdateConfig,
+    LinkState, NetworkInterfaceConfig, NetworkInterfaceError, NetworkInterfaceStats,
+    NetworkInterfaceUpdateConfig,
 };
+use vmm_config::rate_limiter_group::{RateLimiterGroupConfig, RateLimiterGroupError};
 #[cfg(target_arch = "x86_64")]
 use vmm_config::snapshot::CreateSnapshotParams;
 use vmm_config::snapshot::LoadSnapshotParams;
-use vmm_config::vsock::{VsockConfigError, VsockDeviceConfig};
+use vmm_config::vsock::{
+    VsockConfigError, VsockConnectionResetParams, VsockConnectionStats, VsockDeviceConfig,
+};
 
 /// This enum represents the public interface of the VMM. Each action contains various
 /// bits of information (ids, paths, etc.).
@@ -58,8 +66,15 @@ pub enum VmmAction {
     /// after the microVM has booted and only when the microVM is in `Paused` state.
     #[cfg(target_arch = "x86_64")]
     CreateSnapshot(CreateSnapshotParams),
+    /// Get the statistics of the block device with the given `drive_id`.
+    GetBlockDeviceStats(String),
     /// Get the configuration of the microVM.
     GetVmConfiguration,
+    /// Get the statistics of the network interface with the given `iface_id`.
+    GetNetworkInterfaceStats(String),
+    /// Get the statistics of the active vsock connections. This action can only be called after
+    /// the microVM has booted.
+    GetVsockConnections,
     /// Flush the metrics. This action can only be called after the logger has been configured.
     FlushMetrics,
     /// Add a new block device or update one that already exists using the `BlockDeviceConfig` as
@@ -75,10 +90,20 @@ pub enum VmmAction {
     LoadSnapshot(LoadSnapshotParams),
     /// Pause the guest, by pausing the microVM VCPUs.
     Pause,
+    /// Forcefully reset the vsock connection identified by the `VsockConnectionResetParams`.
+    /// This action can only be called after the microVM has booted.
+    ResetVsockConnection(VsockConnectionResetParams),
+    /// Drop all the vsock connections, and send a transport reset event to the guest. This action
+    /// can only be called after the microVM has booted.
+    ResetVsockTransport,
     /// Resume the guest, by resuming the microVM VCPUs.
     Resume,
     /// Set the MMDS configuration.
     SetMmdsConfiguration(MmdsConfig),
+    /// Add a new rate limiter group or update one that is not yet in use using the
+    /// `RateLimiterGroupConfig` as input. This action can only be called before the microVM has
+    /// booted.
+    SetRateLimiterGroup(RateLimiterGroupConfig),
     /// Set the vsock device or update the one that already exists using the
     /// `VsockDeviceConfig` as input. This action can only be called before the microVM has
     /// booted.
@@ -96,7 +121,7 @@ pub enum VmmAction {
     /// represents the `drive_id` and the `path_on_host`.
     UpdateBlockDevicePath(String, String),
     /// Update a network interface, after microVM start. Currently, the only updatable properties
-    /// are the RX and TX rate limiters.
+    /// are the RX and TX rate limiters and the link state.
     UpdateNetworkInterface(NetworkInterfaceUpdateConfig),
 }
 
@@ -108,8 +133,8 @@ pub enum VmmActionError {
     /// The action `CreateSnapshot` failed.
     #[cfg(target_arch = "x86_64")]
     CreateSnapshot(CreateSnapshotError),
-    /// One of the actions `InsertBlockDevice` or `UpdateBlockDevicePath`
-    /// failed because of bad user input.
+    /// One of the actions `InsertBlockDevice`, `UpdateBlockDevicePath` or
+    /// `GetBlockDeviceStats` failed because of bad user input.
     DriveConfig(DriveError),
     /// Internal Vmm error.
     InternalVmm(VmmError),
@@ -121,15 +146,20 @@ pub enum VmmActionError {
     Metrics(MetricsConfigError),
     /// The action `SetMmdsConfiguration` failed because of bad user input.
     MmdsConfig(MmdsConfigError),
-    /// The action `InsertNetworkDevice` failed because of bad user input.
+    /// One of the actions `InsertNetworkDevice`, `UpdateNetworkInterface` or
+    /// `GetNetworkInterfaceStats` failed because of bad user input.
     NetworkConfig(NetworkInterfaceError),
     /// The requested operation is not supported after starting the microVM.
     OperationNotSupportedPostBoot,
     /// The requested operation is not supported before starting the microVM.
     OperationNotSupportedPreBoot,
+    /// The action `SetRateLimiterGroup` failed because of bad user input.
+    RateLimiterGroup(RateLimiterGroupError),
     /// The action `StartMicroVm` failed because of an internal error.
     StartMicrovm(StartMicrovmError),
-    /// The action `SetVsockDevice` failed because of bad user input.
+    /// One of the actions `SetVsockDevice`, `GetVsockConnections`, `ResetVsockConnection` or
+    /// `ResetVsockTransport` failed because of bad user input, or because the transport reset
+    /// event could not be sent to the guest.
     VsockConfig(VsockConfigError),
 }
 
@@ -159,6 +189,7 @@ impl Display for VmmActionError {
                     "The requested operation is not supported before starting the microVM."
                         .to_string()
                 }
+                RateLimiterGroup(err) => err.to_string(),
                 StartMicrovm(err) => err.to_string(),
                 /// The action `SetVsockDevice` failed because of bad user input.
                 VsockConfig(err) => err.to_string(),
@@ -171,10 +202,16 @@ impl Display for VmmActionError {
 /// empty, when no data needs to be sent, or an internal VMM structure.
 #[derive(Debug)]
 pub enum VmmData {
+    /// The statistics of a block device represented by `BlockDeviceStats`.
+    BlockDeviceStats(BlockDeviceStats),
     /// No data is sent on the channel.
     Empty,
     /// The microVM configuration represented by `VmConfig`.
     MachineConfiguration(VmConfig),
+    /// The statistics of a network interface represented by `NetworkInterfaceStats`.
+    NetworkInterfaceStats(NetworkInterfaceStats),
+    /// The statistics of the active vsock connections.
+    VsockConnections(Vec<VsockConnectionStats>),
     /// No data is sent on the channel as the operation doesn't
     /// have a handler implemented yet.
     // This should be removed once we add an implementation for it.
@@ -183,7 +220,7 @@ pub enum VmmData {
 
 /// Enables pre-boot setup and instantiation of a Firecracker VMM.
 pub struct PrebootApiController<'a> {
-    seccomp_filter: BpfProgram,
+    seccomp_level: SeccompLevel,
     instance_info: InstanceInfo,
     vm_resources: &'a mut VmResources,
     event_manager: &'a mut EventManager,
@@ -193,13 +230,13 @@ pub struct PrebootApiController<'a> {
 impl<'a> PrebootApiController<'a> {
     /// Constructor for the PrebootApiController.
     pub fn new(
-        seccomp_filter: BpfProgram,
+        seccomp_level: SeccompLevel,
         instance_info: InstanceInfo,
         vm_resources: &'a mut VmResources,
         event_manager: &'a mut EventManager,
     ) -> PrebootApiController<'a> {
         PrebootApiController {
-            seccomp_filter,
+            seccomp_level,
             instance_info,
             vm_resources,
             event_manager,
@@ -211,21 +248,27 @@ impl<'a> PrebootApiController<'a> {
     /// It takes two closures `recv_req` and `respond` as params which abstract away
     /// the message transport.
     ///
+    /// The microVM is served the contents of the `mmds` instances.
+    ///
     /// Returns a populated `VmResources` object and a running `Vmm` object.
     pub fn build_microvm_from_requests<F, G>(
-        seccomp_filter: BpfProgram,
+        seccomp_level: SeccompLevel,
         event_manager: &mut EventManager,
         instance_info: InstanceInfo,
         recv_req: F,
         respond: G,
+        mmds: MmdsInstances,
     ) -> (VmResources, Arc<Mutex<Vmm>>)
     where
         F: Fn() -> VmmAction,
         G: Fn(result::Result<VmmData, VmmActionError>),
     {
-        let mut vm_resources = VmResources::default();
+        let mut vm_resources = VmResources {
+            mmds,
+            ..VmResources::default()
+        };
         let mut preboot_controller = PrebootApiController::new(
-            seccomp_filter,
+            seccomp_level,
             instance_info,
             &mut vm_resources,
             event_manager,
@@ -266,9 +309,30 @@ impl<'a> PrebootApiController<'a> {
             ConfigureMetrics(metrics_cfg) => vmm_config::metrics::init_metrics(metrics_cfg)
                 .map(|_| VmmData::Empty)
                 .map_err(VmmActionError::Metrics),
+            GetBlockDeviceStats(drive_id) => self
+                .vm_resources
+                .block
+                .list
+                .iter()
+                .map(|block| BlockDeviceStats::from(&*block.lock().expect("Poisoned lock")))
+                .find(|stats| stats.drive_id == drive_id)
+                .map(VmmData::BlockDeviceStats)
+                .ok_or(VmmActionError::DriveConfig(
+                    DriveError::InvalidBlockDeviceID,
+                )),
             GetVmConfiguration => Ok(VmmData::MachineConfiguration(
                 self.vm_resources.vm_config().clone(),
             )),
+            GetNetworkInterfaceStats(iface_id) => self
+                .vm_resources
+                .net_builder
+                .iter()
+                .map(|net| NetworkInterfaceStats::from(&*net.lock().expect("Poisoned lock")))
+                .find(|stats| stats.iface_id == iface_id)
+                .map(VmmData::NetworkInterfaceStats)
+                .ok_or(VmmActionError::NetworkConfig(
+                    NetworkInterfaceError::DeviceIdNotFound,
+                )),
             InsertBlockDevice(block_device_config) => self
                 .vm_resources
                 .set_block_device(block_device_config)
@@ -295,10 +359,15 @@ impl<'a> PrebootApiController<'a> {
                 .set_mmds_config(mmds_config)
                 .map(|_| VmmData::Empty)
                 .map_err(VmmActionError::MmdsConfig),
+            SetRateLimiterGroup(group_cfg) => self
+                .vm_resources
+                .set_rate_limiter_group(group_cfg)
+                .map(|_| VmmData::Empty)
+                .map_err(VmmActionError::RateLimiterGroup),
             StartMicroVm => super::builder::build_microvm(
                 &self.vm_resources,
                 &mut self.event_manager,
-                &self.seccomp_filter,
+                self.seccomp_level,
             )
             .map(|vmm| {
                 self.built_vmm = Some(vmm);
@@ -307,7 +376,10 @@ impl<'a> PrebootApiController<'a> {
             .map_err(VmmActionError::StartMicrovm),
             // Operations not allowed pre-boot.
             FlushMetrics
+            | GetVsockConnections
             | Pause
+            | ResetVsockConnection(_)
+            | ResetVsockTransport
             | Resume
             | UpdateBlockDevicePath(_, _)
             | UpdateNetworkInterface(_) => Err(VmmActionError::OperationNotSupportedPreBoot),
@@ -340,8 +412,28 @@ impl RuntimeApiController {
                 .create_snapshot(snapshot_create_cfg)
                 .map(|_| VmmData::Empty),
             FlushMetrics => self.flush_metrics().map(|_| VmmData::Empty),
+            GetBlockDeviceStats(drive_id) => self
+                .block_device_stats(&drive_id)
+                .map(VmmData::BlockDeviceStats)
+                .map_err(VmmActionError::DriveConfig),
             GetVmConfiguration => Ok(VmmData::MachineConfiguration(self.vm_config.clone())),
+            GetNetworkInterfaceStats(iface_id) => self
+                .net_device_stats(&iface_id)
+                .map(VmmData::NetworkInterfaceStats)
+                .map_err(VmmActionError::NetworkConfig),
+            GetVsockConnections => self
+                .vsock_connections()
+                .map(VmmData::VsockConnections)
+                .map_err(VmmActionError::VsockConfig),
             Pause => self.pause().map(|_| VmmData::Empty),
+            ResetVsockConnection(params) => self
+                .reset_vsock_connection(params)
+                .map(|_| VmmData::Empty)
+                .map_err(VmmActionError::VsockConfig),
+            ResetVsockTransport => self
+                .reset_vsock_transport()
+                .map(|_| VmmData::Empty)
+                .map_err(VmmActionError::VsockConfig),
             Resume => self.resume().map(|_| VmmData::Empty),
             #[cfg(target_arch = "x86_64")]
             SendCtrlAltDel => self.send_ctrl_alt_del().map(|_| VmmData::Empty),
@@ -349,9 +441,9 @@ impl RuntimeApiController {
                 .update_block_device_path(&drive_id, path_on_host)
                 .map(|_| VmmData::Empty)
                 .map_err(VmmActionError::DriveConfig),
-            UpdateNetworkInterface(netif_update) => self
-                .update_net_rate_limiters(netif_update)
-                .map(|_| VmmData::Empty),
+            UpdateNetworkInterface(netif_update) => {
+                self.update_net_device(netif_update).map(|_| VmmData::Empty)
+            }
 
             // Operations not allowed post-boot.
             ConfigureBootSource(_)
@@ -362,6 +454,7 @@ impl RuntimeApiController {
             | LoadSnapshot(_)
             | SetVsockDevice(_)
             | SetMmdsConfiguration(_)
+            | SetRateLimiterGroup(_)
             | SetVmConfiguration(_) => Err(VmmActionError::OperationNotSupportedPostBoot),
             StartMicroVm => Err(VmmActionError::StartMicrovm(
                 StartMicrovmError::MicroVMAlreadyRunning,
@@ -493,8 +586,131 @@ impl RuntimeApiController {
         }
     }
 
+    /// Retrieves the statistics of the emulated block device with id `drive_id`.
+    fn block_device_stats(&self, drive_id: &str) -> result::Result<BlockDeviceStats, DriveError> {
+        let busdev = self
+            .vmm
+            .lock()
+            .expect("Poisoned lock")
+            .get_bus_device(DeviceType::Virtio(TYPE_BLOCK), drive_id)
+            .ok_or(DriveError::InvalidBlockDeviceID)?;
+        let virtio_device = busdev
+            .lock()
+            .expect("Poisoned lock")
+            .as_any()
+            .downcast_ref::<MmioTransport>()
+            // Only MmioTransport implements BusDevice at this point.
+            .expect("Unexpected BusDevice type")
+            .device();
+
+        let locked_device = virtio_device.lock().expect("Poisoned lock");
+        let block = locked_device
+            .as_any()
+            .downcast_ref::<Block>()
+            .expect("Unexpected VirtioDevice type");
+        Ok(BlockDeviceStats::from(block))
+    }
+
+    /// Retrieves the statistics of the emulated net device with id `iface_id`.
+    fn net_device_stats(
+        &self,
+        iface_id: &str,
+    ) -> result::Result<NetworkInterfaceStats, NetworkInterfaceError> {
+        let busdev = self
+            .vmm
+            .lock()
+            .expect("Poisoned lock")
+            .get_bus_device(DeviceType::Virtio(TYPE_NET), iface_id)
+            .ok_or(NetworkInterfaceError::DeviceIdNotFound)?;
+        let virtio_device = busdev
+            .lock()
+            .expect("Poisoned lock")
+            .as_any()
+            .downcast_ref::<MmioTransport>()
+            // Only MmioTransport implements BusDevice at this point.
+            .expect("Unexpected BusDevice type")
+            .device();
+
+        let locked_device = virtio_device.lock().expect("Poisoned lock");
+        let net = locked_device
+            .as_any()
+            .downcast_ref::<Net>()
+            .expect("Unexpected VirtioDevice type");
+        Ok(NetworkInterfaceStats::from(net))
+    }
+
+    /// Runs `f` on the vsock device, provided it uses the Unix backend.
+    fn with_unix_vsock<F, T>(&self, f: F) -> result::Result<T, VsockConfigError>
+    where
+        F: FnOnce(&mut Vsock<VsockUnixBackend>) -> result::Result<T, VsockConfigError>,
+    {
+        let busdev = self
+            .vmm
+            .lock()
+            .expect("Poisoned lock")
+            .get_bus_device(DeviceType::Virtio(TYPE_VSOCK), VSOCK_DEV_ID)
+            .ok_or(VsockConfigError::MissingDevice)?;
+        let virtio_device = busdev
+            .lock()
+            .expect("Poisoned lock")
+            .as_any()
+            .downcast_ref::<MmioTransport>()
+            // Only MmioTransport implements BusDevice at this point.
+            .expect("Unexpected BusDevice type")
+            .device();
+
+        let mut locked_device = virtio_device.lock().expect("Poisoned lock");
+        let vsock = locked_device
+            .as_mut_any()
+            .downcast_mut::<Vsock<VsockUnixBackend>>()
+            // The vhost-vsock device doesn't mediate connections.
+            .ok_or(VsockConfigError::UnsupportedConnections)?;
+        f(vsock)
+    }
+
+    /// Retrieves the statistics of the connections mediated by the vsock device.
+    fn vsock_connections(&self) -> result::Result<Vec<VsockConnectionStats>, VsockConfigError> {
+        self.with_unix_vsock(|vsock| {
+            Ok(vsock
+                .backend()
+                .connections()
+                .iter()
+                .map(VsockConnectionStats::from)
+                .collect())
+        })
+    }
+
+    /// Forcefully resets a connection mediated by the vsock device.
+    fn reset_vsock_connection(
+        &mut self,
+        params: VsockConnectionResetParams,
+    ) -> result::Result<(), VsockConfigError> {
+        self.with_unix_vsock(|vsock| {
+            vsock
+                .backend_mut()
+                .reset_connection(params.local_port, params.peer_port)
+                .map_err(VsockConfigError::ResetConnection)?;
+            // Hand the RST over to the guest right away, rather than on the next device event.
+            if vsock.is_activated() && vsock.process_rx() {
+                vsock.signal_used_queue().unwrap_or_default();
+            }
+            Ok(())
+        })
+    }
+
+    /// Drops all the connections mediated by the vsock device, and asks the guest driver to do
+    /// the same, via a transport reset event.
+    fn reset_vsock_transport(&mut self) -> result::Result<(), VsockConfigError> {
+        self.with_unix_vsock(|vsock| {
+            vsock.backend_mut().clear_connections();
+            vsock
+                .send_transport_reset_event()
+                .map_err(VsockConfigError::TransportReset)
+        })
+    }
+
     /// Updates configuration for an emulated net device as described in `new_cfg`.
-    fn update_net_rate_limiters(&mut self, new_cfg: NetworkInterfaceUpdateConfig) -> ActionResult {
+    fn update_net_device(&mut self, new_cfg: NetworkInterfaceUpdateConfig) -> ActionResult {
         if let Some(busdev) = self
             .vmm
             .lock()
@@ -514,23 +730,39 @@ impl RuntimeApiController {
                 ($rate_limiter: ident, $metric: ident) => {{
                     new_cfg
                         .$rate_limiter
+                        .as_ref()
                         .map(|rl| rl.$metric.map(vmm_config::TokenBucketConfig::into))
                         .unwrap_or(None)
                 }};
             }
 
-            virtio_device
-                .lock()
-                .expect("Poisoned lock")
-                .as_mut_any()
-                .downcast_mut::<Net>()
-                .unwrap()
-                .patch_rate_limiters(
+            let link_changed = {
+                let mut locked_device = virtio_device.lock().expect("Poisoned lock");
+                let net = locked_device
+                    .as_mut_any()
+                    .downcast_mut::<Net>()
+                    .expect("Unexpected VirtioDevice type");
+                net.patch_rate_limiters(
                     get_handler_arg!(rx_rate_limiter, bandwidth),
                     get_handler_arg!(rx_rate_limiter, ops),
                     get_handler_arg!(tx_rate_limiter, bandwidth),
                     get_handler_arg!(tx_rate_limiter, ops),
                 );
+                new_cfg
+                    .link_state
+                    .map_or(false, |state| net.set_link_up(state == LinkState::Up))
+            };
+
+            // Kick the driver to pick up the new link state.
+            if link_changed {
+                busdev
+                    .lock()
+                    .expect("Poisoned lock")
+                    .interrupt(devices::virtio::VIRTIO_MMIO_INT_CONFIG)
+                    .map_err(|_| {
+                        VmmActionError::NetworkConfig(NetworkInterfaceError::DeviceUpdateFailed)
+                    })?;
+            }
         } else {
             return Err(VmmActionError::NetworkConfig(
                 NetworkInterfaceError::DeviceIdNotFound,
@@ -5,14 +5,30 @@
 
 use std::collections::HashMap;
 
+use devices::virtio::net::persist::{NetConfigSpaceState, NetState};
+use devices::virtio::vsock::persist::{VsockUdsPortRouteState, VsockUdsState};
+use dumbo::persist::MmdsNetworkStackState;
 use lazy_static::lazy_static;
-use versionize::VersionMap;
+use rate_limiter::persist::RateLimiterState;
+use versionize::{VersionMap, Versionize};
 
 lazy_static! {
     // Note: until we have a better design, this needs to be updated when the version changes.
     /// Static instance used for handling microVM state versions.
     pub static ref VERSION_MAP: VersionMap = {
-        VersionMap::new()
+        let mut version_map = VersionMap::new();
+
+        // v0.24 state change mappings.
+        version_map
+            .new_version()
+            .set_type_version(NetConfigSpaceState::type_id(), 2)
+            .set_type_version(NetState::type_id(), 2)
+            .set_type_version(RateLimiterState::type_id(), 2)
+            .set_type_version(MmdsNetworkStackState::type_id(), 2)
+            .set_type_version(VsockUdsState::type_id(), 2)
+            .set_type_version(VsockUdsPortRouteState::type_id(), 2);
+
+        version_map
     };
 
     /// Static instance used for creating a 1:1 mapping between Firecracker release version
@@ -20,7 +36,27 @@ lazy_static! {
     pub static ref FC_VERSION_TO_SNAP_VERSION: HashMap<String, u16> = {
         let mut mapping = HashMap::new();
         mapping.insert(String::from("0.23.0"), 1);
+        mapping.insert(String::from("0.24.0"), 2);
 
         mapping
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_version_map() {
+        // The snapshots are saved in the format of the latest release by default.
+        assert_eq!(
+            FC_VERSION_TO_SNAP_VERSION.get("0.24.0"),
+            Some(&VERSION_MAP.latest_version())
+        );
+        assert_eq!(VERSION_MAP.get_type_version(1, NetState::type_id()), 1);
+        assert_eq!(
+            VERSION_MAP.get_type_version(VERSION_MAP.latest_version(), NetState::type_id()),
+            2
+        );
+    }
+}
@@ -9,7 +9,8 @@ use std::path::PathBuf;
 use std::result;
 use std::sync::{Arc, Mutex};
 
-use super::RateLimiterConfig;
+use super::rate_limiter_group::RateLimiterGroupError;
+use super::{RateLimiterConfig, RateLimiterInfo};
 use devices::virtio::Block;
 
 type Result<T> = result::Result<T, DriveError>;
@@ -30,6 +31,8 @@ pub enum DriveError {
     InvalidBlockDevicePath,
     /// Cannot open block device due to invalid permissions or path.
     OpenBlockDevice(io::Error),
+    /// The rate limiter group of the block device is invalid.
+    RateLimiterGroup(RateLimiterGroupError),
     /// A root block device was already added.
     RootBlockDeviceAlreadyAdded,
 }
@@ -53,13 +56,14 @@ impl Display for DriveError {
                 "Cannot open block device. Invalid permission/path: {}",
                 e
             ),
+            RateLimiterGroup(ref e) => write!(f, "{}", e),
             RootBlockDeviceAlreadyAdded => write!(f, "A root block device already exists!"),
         }
     }
 }
 
 /// Use this structure to set up the Block Device before booting the kernel.
-#[derive(Debug, Deserialize, PartialEq)]
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct BlockDeviceConfig {
     /// Unique identifier of the drive.
@@ -80,6 +84,24 @@ pub struct BlockDeviceConfig {
     pub rate_limiter: Option<RateLimiterConfig>,
 }
 
+/// The statistics of a block device, as reported by a `GET` request.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct BlockDeviceStats {
+    /// Unique identifier of the drive.
+    pub drive_id: String,
+    /// The statistics of the drive rate limiter.
+    pub rate_limiter: RateLimiterInfo,
+}
+
+impl From<&Block> for BlockDeviceStats {
+    fn from(block: &Block) -> Self {
+        BlockDeviceStats {
+            drive_id: block.id().clone(),
+            rate_limiter: RateLimiterInfo::from(block.rate_limiter()),
+        }
+    }
+}
+
 /// Wrapper for the collection that holds all the Block Devices
 #[derive(Default)]
 pub struct BlockBuilder {
@@ -546,4 +568,32 @@ mod tests {
         );
         assert_eq!(block_config.is_read_only, expected_is_read_only);
     }
+
+    #[test]
+    fn test_block_stats() {
+        let dummy_block_file = TempFile::new().unwrap();
+        let block_config = BlockDeviceConfig {
+            drive_id: "stats_drive".to_string(),
+            path_on_host: dummy_block_file.as_path().to_str().unwrap().to_string(),
+            is_root_device: false,
+            partuuid: None,
+            is_read_only: false,
+            rate_limiter: Some(RateLimiterConfig {
+                ops: Some(super::super::TokenBucketConfig {
+                    size: 100,
+                    one_time_burst: None,
+                    refill_time: 1000,
+                }),
+                ..Default::default()
+            }),
+        };
+        let block = BlockBuilder::create_block(block_config).unwrap();
+
+        let stats = BlockDeviceStats::from(&block);
+        assert_eq!(stats.drive_id, "stats_drive");
+        assert!(!stats.rate_limiter.blocked);
+        assert_eq!(stats.rate_limiter.throttled_count, 0);
+        assert!(stats.rate_limiter.bandwidth.is_none());
+        assert_eq!(stats.rate_limiter.ops.unwrap().size, 100);
+    }
 }
@@ -75,13 +75,14 @@ impl fmt::Display for VmConfig {
         let vcpu_count = self.vcpu_count.unwrap_or(1);
         let mem_size = self.mem_size_mib.unwrap_or(128);
         let ht_enabled = self.ht_enabled.unwrap_or(false);
-        let cpu_template = self
-            .cpu_template
-            .map_or("Uninitialized".to_string(), |c| c.to_string());
+        // The CPU template is omitted when none is set, like when serializing.
+        let cpu_template = self.cpu_template.map_or(String::new(), |c| {
+            format!("\"cpu_template\": {:?}, ", c.to_string())
+        });
         write!(
             f,
             "{{ \"vcpu_count\": {:?}, \"mem_size_mib\": {:?}, \"ht_enabled\": {:?}, \
-             \"cpu_template\": {:?}, \"track_dirty_pages\": {:?} }}",
+             {}\"track_dirty_pages\": {:?} }}",
             vcpu_count, mem_size, ht_enabled, cpu_template, self.track_dirty_pages
         )
     }
@@ -132,6 +133,34 @@ mod tests {
         assert_eq!(CpuFeaturesTemplate::T2.to_string(), "T2".to_string());
     }
 
+    #[test]
+    fn test_display_vm_config() {
+        let config = VmConfig::default();
+        assert_eq!(
+            config.to_string(),
+            "{ \"vcpu_count\": 1, \"mem_size_mib\": 128, \"ht_enabled\": false, \
+             \"track_dirty_pages\": false }"
+        );
+        assert_eq!(
+            serde_json::from_str::<VmConfig>(&config.to_string()).unwrap(),
+            config
+        );
+
+        let config = VmConfig {
+            cpu_template: Some(CpuFeaturesTemplate::T2),
+            ..Default::default()
+        };
+        assert_eq!(
+            config.to_string(),
+            "{ \"vcpu_count\": 1, \"mem_size_mib\": 128, \"ht_enabled\": false, \
+             \"cpu_template\": \"T2\", \"track_dirty_pages\": false }"
+        );
+        assert_eq!(
+            serde_json::from_str::<VmConfig>(&config.to_string()).unwrap(),
+            config
+        );
+    }
+
     #[test]
     fn test_display_vm_config_error() {
         let expected_str = "The vCPU number is invalid! The vCPU number can only \
@@ -2,8 +2,56 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use serde::export::Formatter;
+use std::collections::HashMap;
 use std::fmt::{Display, Result};
-use std::net::Ipv4Addr;
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+use mmds::data_store::{AccessPolicy, MmdsVersion};
+
+/// The ways in which guest requests can be authorized to read the MMDS contents.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+pub enum MmdsVersionConfig {
+    /// Requests are not authorized, and session tokens are not issued.
+    V1,
+    /// Requests must present a valid session token.
+    V2,
+    /// Session tokens are issued, but requests are only rejected for presenting invalid ones.
+    Both,
+}
+
+impl Default for MmdsVersionConfig {
+    fn default() -> Self {
+        MmdsVersionConfig::V1
+    }
+}
+
+impl From<MmdsVersionConfig> for MmdsVersion {
+    fn from(config: MmdsVersionConfig) -> Self {
+        match config {
+            MmdsVersionConfig::V1 => MmdsVersion::V1,
+            MmdsVersionConfig::V2 => MmdsVersion::V2,
+            MmdsVersionConfig::Both => MmdsVersion::Both,
+        }
+    }
+}
+
+/// The restrictions which can be placed on the guest access to a subtree of the MMDS contents.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+pub enum MmdsAccessPolicyConfig {
+    /// The subtree is never exposed to guests.
+    Hidden,
+    /// The subtree is only exposed to the guest requests which present a valid session token.
+    TokenRequired,
+}
+
+impl From<MmdsAccessPolicyConfig> for AccessPolicy {
+    fn from(config: MmdsAccessPolicyConfig) -> Self {
+        match config {
+            MmdsAccessPolicyConfig::Hidden => AccessPolicy::Hidden,
+            MmdsAccessPolicyConfig::TokenRequired => AccessPolicy::TokenRequired,
+        }
+    }
+}
 
 /// Keeps the MMDS configuration.
 #[derive(Debug, Deserialize, PartialEq)]
@@ -11,6 +59,16 @@ use std::net::Ipv4Addr;
 pub struct MmdsConfig {
     /// MMDS IPv4 configured address.
     ipv4_address: Option<Ipv4Addr>,
+    /// MMDS IPv6 configured address.
+    ipv6_address: Option<Ipv6Addr>,
+    /// Whether guest requests need a session token to read the MMDS contents.
+    #[serde(default)]
+    version: MmdsVersionConfig,
+    /// The maximum size of the serialized MMDS contents, in bytes.
+    data_store_limit: Option<usize>,
+    /// Restricts the guest access to the subtrees found at the given JSON pointers.
+    #[serde(default)]
+    access_policies: HashMap<String, MmdsAccessPolicyConfig>,
 }
 
 impl MmdsConfig {
@@ -19,21 +77,63 @@ impl MmdsConfig {
     pub fn ipv4_addr(&self) -> Option<Ipv4Addr> {
         self.ipv4_address
     }
+
+    /// Returns the MMDS IPv6 address if one was configured.
+    /// Otherwise returns None, and the MMDS is not reachable over IPv6.
+    pub fn ipv6_addr(&self) -> Option<Ipv6Addr> {
+        self.ipv6_address
+    }
+
+    /// Returns the way in which guest requests are authorized.
+    pub fn version(&self) -> MmdsVersionConfig {
+        self.version
+    }
+
+    /// Returns the MMDS data store size limit if one was configured.
+    /// Otherwise returns None.
+    pub fn data_store_limit(&self) -> Option<usize> {
+        self.data_store_limit
+    }
+
+    /// Returns the access policies of the MMDS contents, keyed by JSON pointer.
+    pub fn access_policies(&self) -> &HashMap<String, MmdsAccessPolicyConfig> {
+        &self.access_policies
+    }
 }
 
 /// MMDS configuration related errors.
 #[derive(Debug)]
 pub enum MmdsConfigError {
+    /// The current MMDS contents don't fit the provided data store size limit.
+    DataStoreLimit(usize),
+    /// An access policy path is not a JSON pointer to a key.
+    InvalidAccessPolicyPath(String),
     /// The provided IPv4 address is not link-local valid.
     InvalidIpv4Addr,
+    /// The provided IPv6 address is neither link-local, nor unique local.
+    InvalidIpv6Addr,
 }
 
 impl Display for MmdsConfigError {
     fn fmt(&self, f: &mut Formatter<'_>) -> Result {
         match self {
+            MmdsConfigError::DataStoreLimit(limit) => write!(
+                f,
+                "The current MMDS contents exceed the data store size limit of {} bytes.",
+                limit
+            ),
+            MmdsConfigError::InvalidAccessPolicyPath(path) => write!(
+                f,
+                "The MMDS access policy path `{}` is not a JSON pointer to a key.",
+                path
+            ),
             MmdsConfigError::InvalidIpv4Addr => {
                 write!(f, "The MMDS IPv4 address is not link local.")
             }
+            MmdsConfigError::InvalidIpv6Addr => write!(
+                f,
+                "The MMDS IPv6 address is neither link local, nor unique local."
+            ),
         }
     }
 }
@@ -27,6 +27,8 @@ pub mod metrics;
 pub mod mmds;
 /// Wrapper for configuring the network devices attached to the microVM.
 pub mod net;
+/// Wrapper for configuring the rate limiter groups shared by devices.
+pub mod rate_limiter_group;
 /// Wrapper for configuring microVM snapshots and the microVM state.
 pub mod snapshot;
 /// Wrapper for configuring the vsock devices attached to the microVM.
@@ -43,7 +45,7 @@ pub mod vsock;
 
 /// A public-facing, stateless structure, holding all the data we need to create a TokenBucket
 /// (live) object.
-#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
 pub struct TokenBucketConfig {
     /// See TokenBucket::size.
     pub size: u64,
@@ -61,13 +63,16 @@ impl Into<TokenBucket> for TokenBucketConfig {
 
 /// A public-facing, stateless structure, holding all the data we need to create a RateLimiter
 /// (live) object.
-#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct RateLimiterConfig {
     /// Data used to initialize the RateLimiter::bandwidth bucket.
     pub bandwidth: Option<TokenBucketConfig>,
     /// Data used to initialize the RateLimiter::ops bucket.
     pub ops: Option<TokenBucketConfig>,
+    /// ID of a rate limiter group whose buckets additionally cap this rate limiter. The group
+    /// can only be set when the device is created.
+    pub group: Option<String>,
 }
 
 impl RateLimiterConfig {
@@ -99,6 +104,64 @@ impl TryInto<RateLimiter> for RateLimiterConfig {
     }
 }
 
+/// The statistics of a TokenBucket (live) object, as reported by the API.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct TokenBucketStats {
+    /// See TokenBucket::size.
+    pub size: u64,
+    /// The remaining one time burst budget.
+    pub one_time_burst: u64,
+    /// See TokenBucket::refill_time.
+    pub refill_time: u64,
+    /// The budget left after the last consumption of tokens.
+    pub budget: u64,
+}
+
+impl From<&TokenBucket> for TokenBucketStats {
+    fn from(bucket: &TokenBucket) -> Self {
+        TokenBucketStats {
+            size: bucket.capacity(),
+            one_time_burst: bucket.one_time_burst(),
+            refill_time: bucket.refill_time_ms(),
+            budget: bucket.budget(),
+        }
+    }
+}
+
+/// The statistics of a RateLimiter (live) object, as reported by the API.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize)]
+pub struct RateLimiterInfo {
+    /// Whether the rate limiter is currently blocked.
+    pub blocked: bool,
+    /// Number of times the rate limiter got blocked.
+    pub throttled_count: u64,
+    /// Total time spent blocked, in milliseconds.
+    pub blocked_time_ms: u64,
+    /// Number of bytes consumed.
+    pub consumed_bytes: u64,
+    /// Number of operations consumed.
+    pub consumed_ops: u64,
+    /// The state of the bandwidth bucket, if bandwidth limiting is enabled.
+    pub bandwidth: Option<TokenBucketStats>,
+    /// The state of the ops bucket, if ops limiting is enabled.
+    pub ops: Option<TokenBucketStats>,
+}
+
+impl From<&RateLimiter> for RateLimiterInfo {
+    fn from(rate_limiter: &RateLimiter) -> Self {
+        let stats = rate_limiter.stats();
+        RateLimiterInfo {
+            blocked: rate_limiter.is_blocked(),
+            throttled_count: stats.throttled_count,
+            blocked_time_ms: stats.blocked_time.as_millis() as u64,
+            consumed_bytes: stats.consumed_bytes,
+            consumed_ops: stats.consumed_ops,
+            bandwidth: rate_limiter.bandwidth().map(TokenBucketStats::from),
+            ops: rate_limiter.ops().map(TokenBucketStats::from),
+        }
+    }
+}
+
 type Result<T> = std::result::Result<T, std::io::Error>;
 
 /// Create and opens a File for writing to it.
@@ -150,8 +213,9 @@ mod tests {
                 one_time_burst: None,
                 refill_time: REFILL_TIME * 2,
             }),
+            group: None,
         };
-        let rl: RateLimiter = rlconf.try_into().unwrap();
+        let rl: RateLimiter = rlconf.clone().try_into().unwrap();
         assert_eq!(rl.bandwidth().unwrap().capacity(), SIZE);
         assert_eq!(rl.bandwidth().unwrap().one_time_burst(), ONE_TIME_BURST);
         assert_eq!(rl.bandwidth().unwrap().refill_time_ms(), REFILL_TIME);
@@ -166,6 +230,7 @@ mod tests {
                 refill_time: REFILL_TIME * 2,
             }),
             ops: None,
+            group: None,
         });
         assert_eq!(rlconf.bandwidth.unwrap().size, SIZE * 2);
         assert_eq!(
@@ -178,6 +243,30 @@ mod tests {
         assert_eq!(rlconf.ops.unwrap().refill_time, REFILL_TIME * 2);
     }
 
+    #[test]
+    fn test_rate_limiter_stats() {
+        let mut rl = RateLimiter::new(1000, Some(100), 1000, 0, None, 0).unwrap();
+        assert!(rl.consume(300, rate_limiter::TokenType::Bytes));
+        assert!(rl.consume(5, rate_limiter::TokenType::Ops));
+        assert!(!rl.consume(1000, rate_limiter::TokenType::Bytes));
+
+        let stats = RateLimiterInfo::from(&rl);
+        assert!(stats.blocked);
+        assert_eq!(stats.throttled_count, 1);
+        assert_eq!(stats.consumed_bytes, 300);
+        assert_eq!(stats.consumed_ops, 5);
+        assert!(stats.ops.is_none());
+        let bandwidth = stats.bandwidth.unwrap();
+        assert_eq!(bandwidth.size, 1000);
+        assert_eq!(bandwidth.one_time_burst, 0);
+        assert_eq!(bandwidth.refill_time, 1000);
+        assert!(bandwidth.budget < 1000);
+
+        let json = serde_json::to_value(&stats).unwrap();
+        assert_eq!(json["throttled_count"], 1);
+        assert_eq!(json["ops"], serde_json::Value::Null);
+    }
+
     #[test]
     fn test_fifo_line_writer() {
         let log_file_temp =
@@ -3,17 +3,21 @@
 
 use std::convert::TryInto;
 use std::fmt;
+use std::net::Ipv4Addr;
 use std::result;
 use std::sync::{Arc, Mutex};
 
-use super::RateLimiterConfig;
+use super::rate_limiter_group::RateLimiterGroupError;
+use super::{RateLimiterConfig, RateLimiterInfo};
 use devices::virtio::Net;
-use dumbo::MacAddr;
+use dumbo::{DhcpLease, MacAddr, DHCP_MAX_DNS_SERVERS};
+use logger::Metric;
+use mmds::data_store::MmdsInstances;
 use utils::net::TapError;
 
 /// This struct represents the strongly typed equivalent of the json body from net iface
 /// related requests.
-#[derive(Debug, Deserialize, PartialEq)]
+#[derive(Debug, Deserialize, PartialEq, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct NetworkInterfaceConfig {
     /// ID of the guest network interface.
@@ -33,8 +37,46 @@ pub struct NetworkInterfaceConfig {
     /// same address are intercepted by the device model, and do not reach
     /// the associated TAP device.
     pub allow_mmds_requests: bool,
+    /// The ID of the MMDS instance answering the requests sent via this interface, which
+    /// requires `allow_mmds_requests`. The default instance answers them when missing.
+    pub mmds_id: Option<String>,
+    /// The MTU of the guest network interface. If set, it is also configured on the host
+    /// TAP device and advertised to the guest driver.
+    pub mtu: Option<u16>,
+    /// The network configuration handed out to the guest over DHCP. The DHCP requests are
+    /// answered by the MMDS network stack, which requires `allow_mmds_requests`.
+    pub dhcp: Option<DhcpConfig>,
 }
 
+/// The network configuration handed out over DHCP, on behalf of a network interface.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct DhcpConfig {
+    /// The IPv4 address of the guest.
+    pub ipv4_address: Ipv4Addr,
+    /// The length of the network prefix of `ipv4_address`, in bits.
+    pub prefix_length: u8,
+    /// The default gateway of the guest.
+    pub gateway: Option<Ipv4Addr>,
+    /// The DNS servers of the guest, in order of preference.
+    #[serde(default)]
+    pub dns_servers: Vec<Ipv4Addr>,
+}
+
+impl From<DhcpConfig> for DhcpLease {
+    fn from(cfg: DhcpConfig) -> Self {
+        DhcpLease {
+            ipv4_addr: cfg.ipv4_address,
+            prefix_len: cfg.prefix_length,
+            gateway: cfg.gateway,
+            dns_servers: cfg.dns_servers,
+        }
+    }
+}
+
+/// The minimum MTU of an Ethernet device.
+pub const MIN_MTU: u16 = 68;
+
 // Serde does not allow specifying a default value for a field
 // that is not required. The workaround is to specify a function
 // that returns the value.
@@ -42,9 +84,18 @@ fn default_allow_mmds_requests() -> bool {
     false
 }
 
+/// The link state of a network interface, as reported to the guest driver.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum LinkState {
+    /// The cable is plugged in.
+    Up,
+    /// The cable is unplugged.
+    Down,
+}
+
 /// The data fed into a network iface update request. Currently, only the RX and TX rate limiters
-/// can be updated.
-#[derive(Debug, Deserialize, PartialEq, Clone)]
+/// and the link state can be updated.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
 #[serde(deny_unknown_fields)]
 pub struct NetworkInterfaceUpdateConfig {
     /// The net iface ID, as provided by the user at iface creation time.
@@ -55,6 +106,55 @@ pub struct NetworkInterfaceUpdateConfig {
     /// New TX rate limiter config. Only provided data will be updated. I.e. if any optional data
     /// is missing, it will not be nullified, but left unchanged.
     pub tx_rate_limiter: Option<RateLimiterConfig>,
+    /// New link state. The guest driver is notified through a configuration change interrupt
+    /// if the state actually changes.
+    pub link_state: Option<LinkState>,
+}
+
+/// The statistics of a network interface, as reported by a `GET` request.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct NetworkInterfaceStats {
+    /// The net iface ID, as provided by the user at iface creation time.
+    pub iface_id: String,
+    /// Number of bytes delivered to the guest.
+    pub rx_bytes: usize,
+    /// Number of frames delivered to the guest.
+    pub rx_packets: usize,
+    /// Number of errors while delivering frames to the guest.
+    pub rx_fails: usize,
+    /// Number of bytes sent by the guest.
+    pub tx_bytes: usize,
+    /// Number of frames sent by the guest.
+    pub tx_packets: usize,
+    /// Number of frames sent by the guest that were dropped.
+    pub tx_dropped: usize,
+    /// Number of frames sent by the guest that were detoured to the MMDS.
+    pub mmds_rx_frames: usize,
+    /// Number of frames sent to the guest by the MMDS.
+    pub mmds_tx_frames: usize,
+    /// The statistics of the RX rate limiter, including how many times it throttled the RX path.
+    pub rx_rate_limiter: RateLimiterInfo,
+    /// The statistics of the TX rate limiter, including how many times it throttled the TX path.
+    pub tx_rate_limiter: RateLimiterInfo,
+}
+
+impl From<&Net> for NetworkInterfaceStats {
+    fn from(net: &Net) -> Self {
+        let metrics = net.metrics();
+        NetworkInterfaceStats {
+            iface_id: net.id().clone(),
+            rx_bytes: metrics.rx_bytes_count.count(),
+            rx_packets: metrics.rx_packets_count.count(),
+            rx_fails: metrics.rx_fails.count(),
+            tx_bytes: metrics.tx_bytes_count.count(),
+            tx_packets: metrics.tx_packets_count.count(),
+            tx_dropped: metrics.tx_fails.count(),
+            mmds_rx_frames: metrics.mmds_rx_frames.count(),
+            mmds_tx_frames: metrics.mmds_tx_frames.count(),
+            rx_rate_limiter: RateLimiterInfo::from(net.rx_rate_limiter()),
+            tx_rate_limiter: RateLimiterInfo::from(net.tx_rate_limiter()),
+        }
+    }
 }
 
 /// Errors associated with `NetworkInterfaceConfig`.
@@ -68,8 +168,22 @@ pub enum NetworkInterfaceError {
     GuestMacAddressInUse(String),
     /// Couldn't find the interface to update (patch).
     DeviceIdNotFound,
+    /// Failed to notify the guest driver about the interface update.
+    DeviceUpdateFailed,
+    /// The MTU is too small.
+    InvalidMtu(u16),
+    /// DHCP leases can only be handed out by interfaces which allow MMDS requests.
+    DhcpWithoutMmds,
+    /// MMDS instances can only be selected by interfaces which allow MMDS requests.
+    MmdsIdWithoutMmds,
+    /// The DHCP network prefix length is larger than 32.
+    InvalidDhcpPrefixLength(u8),
+    /// The DHCP lease holds more DNS servers than fit in a single message.
+    TooManyDhcpDnsServers(usize),
     /// Cannot open/create tap device.
     OpenTap(TapError),
+    /// The rate limiter group of the network interface is invalid.
+    RateLimiterGroup(RateLimiterGroupError),
 }
 
 impl fmt::Display for NetworkInterfaceError {
@@ -84,6 +198,30 @@ impl fmt::Display for NetworkInterfaceError {
                 format!("The guest MAC address {} is already in use.", mac_addr)
             ),
             DeviceIdNotFound => write!(f, "Invalid interface ID - not found."),
+            DeviceUpdateFailed => write!(f, "The network interface update failed."),
+            InvalidMtu(mtu) => write!(
+                f,
+                "The MTU {} is invalid. The minimum supported MTU is {}.",
+                mtu, MIN_MTU
+            ),
+            DhcpWithoutMmds => write!(
+                f,
+                "DHCP leases can only be handed out by interfaces which allow MMDS requests."
+            ),
+            MmdsIdWithoutMmds => write!(
+                f,
+                "MMDS instances can only be selected by interfaces which allow MMDS requests."
+            ),
+            InvalidDhcpPrefixLength(prefix_length) => write!(
+                f,
+                "The DHCP network prefix length {} is invalid. It must be at most 32.",
+                prefix_length
+            ),
+            TooManyDhcpDnsServers(count) => write!(
+                f,
+                "The DHCP lease holds {} DNS servers, while at most {} are supported.",
+                count, DHCP_MAX_DNS_SERVERS
+            ),
             OpenTap(ref e) => {
                 // We are propagating the Tap Error. This error can contain
                 // imbricated quotes which would result in an invalid json.
@@ -97,6 +235,7 @@ impl fmt::Display for NetworkInterfaceError {
                     tap_err
                 )
             }
+            RateLimiterGroup(ref e) => write!(f, "{}", e),
         }
     }
 }
@@ -129,8 +268,13 @@ impl NetBuilder {
     }
 
     /// Builds a network device based on a network interface config. Keeps a device reference
-    /// in the builder's internal list.
-    pub fn build(&mut self, netif_config: NetworkInterfaceConfig) -> Result<Arc<Mutex<Net>>> {
+    /// in the builder's internal list. The device answers the guest MMDS requests from the
+    /// instance of `mmds` selected by the config, if the config allows them.
+    pub fn build(
+        &mut self,
+        netif_config: NetworkInterfaceConfig,
+        mmds: &MmdsInstances,
+    ) -> Result<Arc<Mutex<Net>>> {
         let mac_conflict = |net: &Arc<Mutex<Net>>| {
             let net = net.lock().expect("Poisoned lock");
             // Check if another net dev has same MAC.
@@ -157,14 +301,36 @@ impl NetBuilder {
         }
 
         // Add new device.
-        let net = Arc::new(Mutex::new(Self::create_net(netif_config)?));
+        let net = Arc::new(Mutex::new(Self::create_net(netif_config, mmds)?));
         self.net_devices.push(net.clone());
 
         Ok(net)
     }
 
     /// Creates a Net device from a NetworkInterfaceConfig.
-    pub fn create_net(cfg: NetworkInterfaceConfig) -> Result<Net> {
+    pub fn create_net(cfg: NetworkInterfaceConfig, mmds: &MmdsInstances) -> Result<Net> {
+        if let Some(mtu) = cfg.mtu.filter(|&mtu| mtu < MIN_MTU) {
+            return Err(NetworkInterfaceError::InvalidMtu(mtu));
+        }
+        if cfg.mmds_id.is_some() && !cfg.allow_mmds_requests {
+            return Err(NetworkInterfaceError::MmdsIdWithoutMmds);
+        }
+        if let Some(dhcp) = cfg.dhcp.as_ref() {
+            if !cfg.allow_mmds_requests {
+                return Err(NetworkInterfaceError::DhcpWithoutMmds);
+            }
+            if dhcp.prefix_length > 32 {
+                return Err(NetworkInterfaceError::InvalidDhcpPrefixLength(
+                    dhcp.prefix_length,
+                ));
+            }
+            if dhcp.dns_servers.len() > DHCP_MAX_DNS_SERVERS {
+                return Err(NetworkInterfaceError::TooManyDhcpDnsServers(
+                    dhcp.dns_servers.len(),
+                ));
+            }
+        }
+
         let rx_rate_limiter = cfg
             .rx_rate_limiter
             .map(super::RateLimiterConfig::try_into)
@@ -177,15 +343,26 @@ impl NetBuilder {
             .map_err(NetworkInterfaceError::CreateRateLimiter)?;
 
         // Create and return the Net device
-        devices::virtio::net::Net::new_with_tap(
+        let mut net = devices::virtio::net::Net::new_with_tap(
             cfg.iface_id,
             cfg.host_dev_name.clone(),
             cfg.guest_mac.as_ref(),
             rx_rate_limiter.unwrap_or_default(),
             tx_rate_limiter.unwrap_or_default(),
-            cfg.allow_mmds_requests,
+            if cfg.allow_mmds_requests {
+                Some(mmds.get_or_create(cfg.mmds_id.as_ref().map(String::as_str)))
+            } else {
+                None
+            },
+            cfg.mtu,
         )
-        .map_err(NetworkInterfaceError::CreateNetworkDevice)
+        .map_err(NetworkInterfaceError::CreateNetworkDevice)?;
+        net.set_mmds_id(cfg.mmds_id);
+
+        if let Some(mmds_ns) = net.mmds_ns_mut() {
+            mmds_ns.set_dhcp_lease(cfg.dhcp.map(DhcpLease::from));
+        }
+        Ok(net)
     }
 }
 
@@ -213,6 +390,9 @@ mod tests {
             rx_rate_limiter: Some(RateLimiterConfig::default()),
             tx_rate_limiter: Some(RateLimiterConfig::default()),
             allow_mmds_requests: false,
+            mmds_id: None,
+            mtu: None,
+            dhcp: None,
         }
     }
 
@@ -225,6 +405,9 @@ mod tests {
                 rx_rate_limiter: None,
                 tx_rate_limiter: None,
                 allow_mmds_requests: self.allow_mmds_requests,
+                mmds_id: self.mmds_id.clone(),
+                mtu: self.mtu,
+                dhcp: self.dhcp.clone(),
             }
         }
     }
@@ -232,6 +415,7 @@ mod tests {
     #[test]
     fn test_insert() {
         let mut net_builder = NetBuilder::new();
+        let mmds = MmdsInstances::default();
 
         let id_1 = "id_1";
         let mut host_dev_name_1 = "dev1";
@@ -239,26 +423,27 @@ mod tests {
 
         // Test create.
         let netif_1 = create_netif(id_1, host_dev_name_1, guest_mac_1);
-        assert!(net_builder.build(netif_1).is_ok());
+        assert!(net_builder.build(netif_1, &mmds).is_ok());
         assert_eq!(net_builder.net_devices.len(), 1);
 
         // Test update mac address (this test does not modify the tap).
         guest_mac_1 = "01:23:45:67:89:0b";
         let netif_1 = create_netif(id_1, host_dev_name_1, guest_mac_1);
 
-        assert!(net_builder.build(netif_1.clone()).is_ok());
+        assert!(net_builder.build(netif_1.clone(), &mmds).is_ok());
         assert_eq!(net_builder.net_devices.len(), 1);
 
         // Test update host_dev_name (the tap will be updated).
         host_dev_name_1 = "dev2";
         let netif_1 = create_netif(id_1, host_dev_name_1, guest_mac_1);
-        assert!(net_builder.build(netif_1.clone()).is_ok());
+        assert!(net_builder.build(netif_1.clone(), &mmds).is_ok());
         assert_eq!(net_builder.net_devices.len(), 1);
     }
 
     #[test]
     fn test_insert_error_cases() {
         let mut net_builder = NetBuilder::new();
+        let mmds = MmdsInstances::default();
 
         let id_1 = "id_1";
         let host_dev_name_1 = "dev3";
@@ -266,7 +451,7 @@ mod tests {
 
         // Adding the first valid network config.
         let netif_1 = create_netif(id_1, host_dev_name_1, guest_mac_1);
-        assert!(net_builder.build(netif_1.clone()).is_ok());
+        assert!(net_builder.build(netif_1.clone(), &mmds).is_ok());
 
         // Error Cases for CREATE
         // Error Case: Add new network config with the same mac as netif_1.
@@ -281,7 +466,7 @@ mod tests {
         );
         assert_eq!(
             net_builder
-                .build(netif_2.clone())
+                .build(netif_2.clone(), &mmds)
                 .err()
                 .unwrap()
                 .to_string(),
@@ -293,7 +478,7 @@ mod tests {
         let netif_2 = create_netif(id_2, host_dev_name_1, guest_mac_2);
         assert_eq!(
             net_builder
-                .build(netif_2.clone())
+                .build(netif_2.clone(), &mmds)
                 .err()
                 .unwrap()
                 .to_string(),
@@ -306,7 +491,7 @@ mod tests {
 
         // Adding the second valid network config.
         let netif_2 = create_netif(id_2, host_dev_name_2, guest_mac_2);
-        assert!(net_builder.build(netif_2.clone()).is_ok());
+        assert!(net_builder.build(netif_2.clone(), &mmds).is_ok());
 
         // Error Cases for UPDATE
         // Error Case: Update netif_2 mac using the same mac as netif_1.
@@ -317,7 +502,7 @@ mod tests {
         );
         assert_eq!(
             net_builder
-                .build(netif_2.clone())
+                .build(netif_2.clone(), &mmds)
                 .err()
                 .unwrap()
                 .to_string(),
@@ -328,7 +513,7 @@ mod tests {
         let netif_2 = create_netif(id_2, host_dev_name_1, guest_mac_2);
         assert_eq!(
             net_builder
-                .build(netif_2.clone())
+                .build(netif_2.clone(), &mmds)
                 .err()
                 .unwrap()
                 .to_string(),
@@ -353,6 +538,29 @@ mod tests {
             NetworkInterfaceError::DeviceIdNotFound,
             NetworkInterfaceError::DeviceIdNotFound
         );
+        let _ = format!(
+            "{}{:?}",
+            NetworkInterfaceError::DeviceUpdateFailed,
+            NetworkInterfaceError::DeviceUpdateFailed
+        );
+        assert_eq!(
+            NetworkInterfaceError::InvalidMtu(10).to_string(),
+            "The MTU 10 is invalid. The minimum supported MTU is 68."
+        );
+        assert_eq!(
+            NetworkInterfaceError::InvalidDhcpPrefixLength(33).to_string(),
+            "The DHCP network prefix length 33 is invalid. It must be at most 32."
+        );
+        let _ = format!(
+            "{}{:?}{}",
+            NetworkInterfaceError::DhcpWithoutMmds,
+            NetworkInterfaceError::DhcpWithoutMmds,
+            NetworkInterfaceError::TooManyDhcpDnsServers(64)
+        );
+        assert_eq!(
+            NetworkInterfaceError::MmdsIdWithoutMmds.to_string(),
+            "MMDS instances can only be selected by interfaces which allow MMDS requests."
+        );
         let _ = format!(
             "{}{:?}",
             NetworkInterfaceError::OpenTap(TapError::InvalidIfname),
@@ -373,4 +581,144 @@ mod tests {
         );
         assert_eq!(net_if.allow_mmds_requests, false);
     }
+
+    #[test]
+    fn test_net_mtu() {
+        let mut net_builder = NetBuilder::new();
+        let mmds = MmdsInstances::default();
+
+        let mut netif = create_netif("mtu_id", "dev6", "01:23:45:67:89:0d");
+        netif.mtu = Some(MIN_MTU - 1);
+        assert_eq!(
+            net_builder.build(netif, &mmds).err().unwrap().to_string(),
+            NetworkInterfaceError::InvalidMtu(MIN_MTU - 1).to_string()
+        );
+        assert!(net_builder.is_empty());
+
+        let mut netif = create_netif("mtu_id", "dev6", "01:23:45:67:89:0d");
+        netif.mtu = Some(9000);
+        assert!(net_builder.build(netif, &mmds).is_ok());
+    }
+
+    #[test]
+    fn test_net_dhcp() {
+        let mut net_builder = NetBuilder::new();
+        let mmds = MmdsInstances::default();
+        let dhcp = DhcpConfig {
+            ipv4_address: Ipv4Addr::new(192, 168, 0, 2),
+            prefix_length: 24,
+            gateway: Some(Ipv4Addr::new(192, 168, 0, 1)),
+            dns_servers: vec![Ipv4Addr::new(8, 8, 8, 8)],
+        };
+
+        let mut netif = create_netif("dhcp_id", "dev7", "01:23:45:67:89:0e");
+        netif.dhcp = Some(dhcp.clone());
+        assert_eq!(
+            net_builder.build(netif, &mmds).err().unwrap().to_string(),
+            NetworkInterfaceError::DhcpWithoutMmds.to_string()
+        );
+
+        let mut netif = create_netif("dhcp_id", "dev7", "01:23:45:67:89:0e");
+        netif.allow_mmds_requests = true;
+        netif.dhcp = Some(DhcpConfig {
+            prefix_length: 33,
+            ..dhcp.clone()
+        });
+        assert_eq!(
+            net_builder.build(netif, &mmds).err().unwrap().to_string(),
+            NetworkInterfaceError::InvalidDhcpPrefixLength(33).to_string()
+        );
+
+        let mut netif = create_netif("dhcp_id", "dev7", "01:23:45:67:89:0e");
+        netif.allow_mmds_requests = true;
+        netif.dhcp = Some(DhcpConfig {
+            dns_servers: vec![Ipv4Addr::new(8, 8, 8, 8); DHCP_MAX_DNS_SERVERS + 1],
+            ..dhcp.clone()
+        });
+        assert_eq!(
+            net_builder.build(netif, &mmds).err().unwrap().to_string(),
+            NetworkInterfaceError::TooManyDhcpDnsServers(DHCP_MAX_DNS_SERVERS + 1).to_string()
+        );
+        assert!(net_builder.is_empty());
+
+        let mut netif = create_netif("dhcp_id", "dev7", "01:23:45:67:89:0e");
+        netif.allow_mmds_requests = true;
+        netif.dhcp = Some(dhcp.clone());
+        let net = net_builder.build(netif, &mmds).unwrap();
+        assert_eq!(
+            net.lock().unwrap().mmds_ns_mut().unwrap().dhcp_lease(),
+            Some(&DhcpLease::from(dhcp))
+        );
+    }
+
+    #[test]
+    fn test_net_mmds_id() {
+        let mut net_builder = NetBuilder::new();
+        let mmds = MmdsInstances::default();
+
+        let mut netif = create_netif("mmds_id_1", "dev8", "01:23:45:67:89:0f");
+        netif.mmds_id = Some("mmds_1".to_string());
+        assert_eq!(
+            net_builder.build(netif, &mmds).err().unwrap().to_string(),
+            NetworkInterfaceError::MmdsIdWithoutMmds.to_string()
+        );
+        assert!(net_builder.is_empty());
+
+        let mut netif_1 = create_netif("mmds_id_1", "dev8", "01:23:45:67:89:0f");
+        netif_1.allow_mmds_requests = true;
+        netif_1.mmds_id = Some("mmds_1".to_string());
+        let net_1 = net_builder.build(netif_1, &mmds).unwrap();
+        let mut netif_2 = create_netif("mmds_id_2", "dev9", "01:23:45:67:89:10");
+        netif_2.allow_mmds_requests = true;
+        netif_2.mmds_id = Some("mmds_2".to_string());
+        let net_2 = net_builder.build(netif_2, &mmds).unwrap();
+        let mut netif_3 = create_netif("mmds_id_3", "dev10", "01:23:45:67:89:11");
+        netif_3.allow_mmds_requests = true;
+        let net_3 = net_builder.build(netif_3, &mmds).unwrap();
+
+        mmds.get(Some("mmds_1"))
+            .unwrap()
+            .lock()
+            .unwrap()
+            .put_data(serde_json::from_str(r#"{"name": "mmds_1"}"#).unwrap())
+            .unwrap();
+        mmds.get(Some("mmds_2"))
+            .unwrap()
+            .lock()
+            .unwrap()
+            .put_data(serde_json::from_str(r#"{"name": "mmds_2"}"#).unwrap())
+            .unwrap();
+
+        // Each interface serves the data of the instance it selected.
+        let served_data = |net: &Arc<Mutex<Net>>| {
+            let mut net = net.lock().unwrap();
+            let mmds = net.mmds_ns_mut().unwrap().mmds().lock().unwrap();
+            mmds.get_data_str()
+        };
+        assert_eq!(served_data(&net_1), r#"{"name":"mmds_1"}"#);
+        assert_eq!(served_data(&net_2), r#"{"name":"mmds_2"}"#);
+        assert_eq!(served_data(&net_3), "{}");
+        assert_eq!(net_1.lock().unwrap().mmds_id(), Some("mmds_1"));
+        assert_eq!(net_3.lock().unwrap().mmds_id(), None);
+    }
+
+    #[test]
+    fn test_net_stats() {
+        let mut net_builder = NetBuilder::new();
+        let mmds = MmdsInstances::default();
+        let netif = create_netif("stats_id", "dev5", "01:23:45:67:89:0c");
+        let net = net_builder.build(netif, &mmds).unwrap();
+
+        let stats = NetworkInterfaceStats::from(&*net.lock().unwrap());
+        assert_eq!(stats.iface_id, "stats_id");
+        assert_eq!(stats.tx_packets, 0);
+
+        net.lock().unwrap().metrics().tx_packets_count.add(3);
+        net.lock().unwrap().metrics().tx_fails.inc();
+        let stats = NetworkInterfaceStats::from(&*net.lock().unwrap());
+        assert_eq!(stats.tx_packets, 3);
+        assert_eq!(stats.tx_dropped, 1);
+        assert_eq!(stats.rx_rate_limiter, RateLimiterInfo::default());
+        assert_eq!(stats.tx_rate_limiter, RateLimiterInfo::default());
+    }
 }
@@ -0,0 +1,154 @@
+// Copyright 2020 Amazon.com, Inc. or its affiliates. All Rights Reserved.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::result;
+use std::sync::{Arc, Mutex};
+
+use super::{RateLimiterConfig, TokenBucketConfig};
+use rate_limiter::RateLimiterGroup;
+
+/// Use this structure to set up a rate limiter group. The token buckets of a group are shared by
+/// all the device rate limiters referencing it, capping their aggregate rate.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct RateLimiterGroupConfig {
+    /// Unique identifier of the group.
+    pub group_id: String,
+    /// Data used to initialize the group bandwidth bucket.
+    pub bandwidth: Option<TokenBucketConfig>,
+    /// Data used to initialize the group ops bucket.
+    pub ops: Option<TokenBucketConfig>,
+}
+
+/// Errors associated with rate limiter groups.
+#[derive(Debug, PartialEq)]
+pub enum RateLimiterGroupError {
+    /// The group is referenced by a device and cannot be replaced.
+    GroupInUse(String),
+    /// The group was not configured.
+    GroupNotFound(String),
+}
+
+impl fmt::Display for RateLimiterGroupError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::RateLimiterGroupError::*;
+        match *self {
+            GroupInUse(ref group_id) => write!(
+                f,
+                "The rate limiter group {} is in use and cannot be replaced.",
+                group_id
+            ),
+            GroupNotFound(ref group_id) => {
+                write!(f, "The rate limiter group {} does not exist.", group_id)
+            }
+        }
+    }
+}
+
+type Result<T> = result::Result<T, RateLimiterGroupError>;
+
+/// The rate limiter groups of a microVM, indexed by their ID.
+#[derive(Default)]
+pub struct RateLimiterGroups {
+    groups: BTreeMap<String, Arc<Mutex<RateLimiterGroup>>>,
+}
+
+impl RateLimiterGroups {
+    /// Creates a new group or replaces an existing one using the specified configuration.
+    /// Groups already referenced by a device cannot be replaced.
+    pub fn insert(&mut self, config: RateLimiterGroupConfig) -> Result<()> {
+        if let Some(group) = self.groups.get(&config.group_id) {
+            if Arc::strong_count(group) > 1 {
+                return Err(RateLimiterGroupError::GroupInUse(config.group_id));
+            }
+        }
+
+        let bw = config.bandwidth.unwrap_or_default();
+        let ops = config.ops.unwrap_or_default();
+        let group = RateLimiterGroup::new(
+            config.group_id.clone(),
+            bw.size,
+            bw.one_time_burst,
+            bw.refill_time,
+            ops.size,
+            ops.one_time_burst,
+            ops.refill_time,
+        );
+        self.groups
+            .insert(config.group_id, Arc::new(Mutex::new(group)));
+        Ok(())
+    }
+
+    /// Returns the group referenced by the rate limiter configuration, if any.
+    pub fn resolve(
+        &self,
+        config: Option<&RateLimiterConfig>,
+    ) -> Result<Option<Arc<Mutex<RateLimiterGroup>>>> {
+        match config.and_then(|cfg| cfg.group.as_ref()) {
+            Some(group_id) => self
+                .groups
+                .get(group_id)
+                .cloned()
+                .map(Some)
+                .ok_or_else(|| RateLimiterGroupError::GroupNotFound(group_id.clone())),
+            None => Ok(None),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn group_config(group_id: &str) -> RateLimiterGroupConfig {
+        RateLimiterGroupConfig {
+            group_id: group_id.to_string(),
+            bandwidth: Some(TokenBucketConfig {
+                size: 1000,
+                one_time_burst: None,
+                refill_time: 1000,
+            }),
+            ops: None,
+        }
+    }
+
+    #[test]
+    fn test_rate_limiter_groups() {
+        let mut groups = RateLimiterGroups::default();
+        groups.insert(group_config("tenant")).unwrap();
+
+        // Rate limiters without a group do not resolve to anything.
+        assert!(groups.resolve(None).unwrap().is_none());
+        assert!(groups
+            .resolve(Some(&RateLimiterConfig::default()))
+            .unwrap()
+            .is_none());
+
+        let rl_config = RateLimiterConfig {
+            group: Some("tenant".to_string()),
+            ..Default::default()
+        };
+        let group = groups.resolve(Some(&rl_config)).unwrap().unwrap();
+        assert_eq!(group.lock().unwrap().bandwidth().unwrap().capacity(), 1000);
+        assert!(group.lock().unwrap().ops().is_none());
+
+        // A group cannot be replaced while it is referenced.
+        assert_eq!(
+            groups.insert(group_config("tenant")),
+            Err(RateLimiterGroupError::GroupInUse("tenant".to_string()))
+        );
+        drop(group);
+        assert!(groups.insert(group_config("tenant")).is_ok());
+
+        let rl_config = RateLimiterConfig {
+            group: Some("other".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            groups.resolve(Some(&rl_config)).err().unwrap().to_string(),
+            "The rate limiter group other does not exist."
+        );
+    }
+}
@@ -1,12 +1,18 @@
 // Copyright 2018 Amazon.com, Inc. or its affiliates. All Rights Reserved.
 // SPDX-License-Identifier: Apache-2.0
 
+use std::collections::HashMap;
 use std::fmt;
 use std::sync::{Arc, Mutex};
 
-use devices::virtio::{Vsock, VsockError, VsockUnixBackend, VsockUnixBackendError};
+use devices::virtio::{
+    vsock_seqpacket_sock_path, VhostVsock, VhostVsockError, Vsock, VsockConnState,
+    VsockConnectionInfo, VsockError, VsockUnixBackend, VsockUnixBackendError, VsockUnixPortRoute,
+    VsockUnixRoutes,
+};
 
 type MutexVsockUnix = Arc<Mutex<Vsock<VsockUnixBackend>>>;
+type MutexVhostVsock = Arc<Mutex<VhostVsock>>;
 
 /// Errors associated with `NetworkInterfaceConfig`.
 #[derive(Debug)]
@@ -15,6 +21,32 @@ pub enum VsockConfigError {
     CreateVsockBackend(VsockUnixBackendError),
     /// Failed to create the vsock device.
     CreateVsockDevice(VsockError),
+    /// Failed to create the vhost-vsock device.
+    CreateVhostVsockDevice(VhostVsockError),
+    /// The connection limit must be greater than 0.
+    InvalidMaxConnections,
+    /// The MMDS port also has a routing rule.
+    InvalidMmdsPort(u32),
+    /// A guest port route has both a socket path and a TCP port.
+    InvalidPortRoute(u32),
+    /// No vsock device is attached to the microVM.
+    MissingDevice,
+    /// The Unix backend requires a socket path.
+    MissingUdsPath,
+    /// Failed to reset a vsock connection.
+    ResetConnection(VsockUnixBackendError),
+    /// Failed to send a transport reset event to the guest driver.
+    TransportReset(devices::Error),
+    /// The vhost backend does not use a socket path.
+    UnexpectedUdsPath,
+    /// The vhost backend does not support connection management.
+    UnsupportedConnections,
+    /// The vhost backend does not support per-port metrics.
+    UnsupportedPortMetrics,
+    /// The vhost backend does not support routing rules.
+    UnsupportedRoutes,
+    /// The vhost backend does not support enabling SOCK_SEQPACKET connections.
+    UnsupportedSeqpacket,
 }
 
 impl fmt::Display for VsockConfigError {
@@ -25,12 +57,81 @@ impl fmt::Display for VsockConfigError {
                 write!(f, "Cannot create backend for vsock device: {:?}", e)
             }
             CreateVsockDevice(ref e) => write!(f, "Cannot create vsock device: {:?}", e),
+            CreateVhostVsockDevice(ref e) => {
+                write!(f, "Cannot create vhost-vsock device: {:?}", e)
+            }
+            InvalidMaxConnections => {
+                write!(f, "The vsock connection limit must be greater than 0.")
+            }
+            InvalidMmdsPort(port) => write!(
+                f,
+                "The vsock port {} cannot both serve the MMDS and have a route.",
+                port
+            ),
+            InvalidPortRoute(port) => write!(
+                f,
+                "The route of vsock port {} cannot have both a uds_path and a tcp_port.",
+                port
+            ),
+            MissingDevice => write!(f, "No vsock device is attached to the microVM."),
+            MissingUdsPath => write!(f, "The Unix vsock backend requires a uds_path."),
+            ResetConnection(ref e) => write!(f, "Cannot reset vsock connection: {:?}", e),
+            TransportReset(ref e) => write!(f, "Cannot send vsock transport reset event: {:?}", e),
+            UnexpectedUdsPath => write!(f, "The vhost vsock backend does not accept a uds_path."),
+            UnsupportedConnections => write!(
+                f,
+                "Vsock connection management is only supported by the Unix vsock backend."
+            ),
+            UnsupportedPortMetrics => write!(
+                f,
+                "Per-port vsock metrics are only supported by the Unix vsock backend."
+            ),
+            UnsupportedRoutes => write!(
+                f,
+                "Vsock routing rules are only supported by the Unix vsock backend."
+            ),
+            UnsupportedSeqpacket => write!(
+                f,
+                "Enabling SOCK_SEQPACKET vsock connections is only supported by the Unix vsock \
+                 backend."
+            ),
         }
     }
 }
 
 type Result<T> = std::result::Result<T, VsockConfigError>;
 
+/// The data path used by the vsock device.
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum VsockBackendType {
+    /// Guest connections are mediated in userspace and forwarded to host Unix sockets.
+    Unix,
+    /// Guest connections are handled by the host kernel vhost-vsock driver and reach host
+    /// processes through native `AF_VSOCK` sockets.
+    Vhost,
+}
+
+impl Default for VsockBackendType {
+    fn default() -> Self {
+        VsockBackendType::Unix
+    }
+}
+
+/// Routes the guest-initiated connections to a vsock port.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct VsockGuestPortRoute {
+    /// The guest destination port.
+    pub port: u32,
+    /// Path to the host Unix socket the connections are forwarded to.
+    pub uds_path: Option<String>,
+    /// Port of the host loopback TCP socket the connections are forwarded to. Only
+    /// SOCK_STREAM connections can be forwarded to TCP sockets. Connections are denied when
+    /// neither this, nor `uds_path` is set.
+    #[serde(default)]
+    pub tcp_port: Option<u16>,
+}
+
 /// This struct represents the strongly typed equivalent of the json body
 /// from vsock related requests.
 #[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
@@ -40,56 +141,264 @@ pub struct VsockDeviceConfig {
     pub vsock_id: String,
     /// A 32-bit Context Identifier (CID) used to identify the guest.
     pub guest_cid: u32,
-    /// Path to local unix socket.
-    pub uds_path: String,
+    /// Path to local unix socket. Only used by the Unix backend.
+    pub uds_path: Option<String>,
+    /// The data path used by the device.
+    #[serde(default)]
+    pub backend: VsockBackendType,
+    /// Routing rules for guest-initiated connections. Only used by the Unix backend.
+    #[serde(default)]
+    pub guest_port_routes: Vec<VsockGuestPortRoute>,
+    /// Guest ports that host-initiated connections may target. All ports are allowed when
+    /// missing. Only used by the Unix backend.
+    #[serde(default)]
+    pub host_allowed_ports: Option<Vec<u32>>,
+    /// The maximum number of connections that can be active at the same time. Only used by the
+    /// Unix backend.
+    #[serde(default)]
+    pub max_connections: Option<u32>,
+    /// Whether to also record connection metrics per port. Only used by the Unix backend.
+    #[serde(default)]
+    pub per_port_metrics: bool,
+    /// Guest port on which the microVM metadata service is served, over HTTP. Only used by the
+    /// Unix backend.
+    #[serde(default)]
+    pub mmds_port: Option<u32>,
+    /// Whether to support SOCK_SEQPACKET connections, accepting host-initiated ones on a second
+    /// socket, at `<uds_path>_seqpacket`. Only used by the Unix backend.
+    #[serde(default)]
+    pub seqpacket: bool,
+}
+
+/// The state of a vsock connection.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub enum VsockConnectionState {
+    /// The host initiated the connection, and is waiting for the guest to accept it.
+    LocalInit,
+    /// The guest initiated the connection, which is about to be accepted.
+    PeerInit,
+    /// The connection is established.
+    Established,
+    /// The host closed its end of the connection.
+    LocalClosed,
+    /// The guest shut down its end of the connection.
+    PeerClosed,
+    /// The connection is about to be forcefully terminated.
+    Killed,
+}
+
+impl From<VsockConnState> for VsockConnectionState {
+    fn from(state: VsockConnState) -> Self {
+        match state {
+            VsockConnState::LocalInit => VsockConnectionState::LocalInit,
+            VsockConnState::PeerInit => VsockConnectionState::PeerInit,
+            VsockConnState::Established => VsockConnectionState::Established,
+            VsockConnState::LocalClosed => VsockConnectionState::LocalClosed,
+            VsockConnState::PeerClosed(_, _) => VsockConnectionState::PeerClosed,
+            VsockConnState::Killed => VsockConnectionState::Killed,
+        }
+    }
+}
+
+/// The statistics of a connection mediated by the Unix vsock backend.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct VsockConnectionStats {
+    /// The host-side port.
+    pub local_port: u32,
+    /// The guest-side port.
+    pub peer_port: u32,
+    /// Whether this is a SOCK_SEQPACKET connection.
+    pub seqpacket: bool,
+    /// The connection state.
+    pub state: VsockConnectionState,
+    /// Number of bytes sent by the guest, not yet written to the host socket.
+    pub tx_buffered_bytes: usize,
+    /// Number of bytes delivered to the guest, not yet consumed by the guest.
+    pub rx_in_flight_bytes: u32,
+    /// Number of bytes that can still be delivered to the guest before it grants more credit.
+    pub peer_credit: usize,
+    /// Total number of bytes delivered to the guest (modulo 2^32).
+    pub rx_bytes: u32,
+    /// Total number of bytes written to the host socket (modulo 2^32).
+    pub tx_bytes: u32,
+}
+
+impl From<&VsockConnectionInfo> for VsockConnectionStats {
+    fn from(info: &VsockConnectionInfo) -> Self {
+        VsockConnectionStats {
+            local_port: info.local_port,
+            peer_port: info.peer_port,
+            seqpacket: info.seqpacket,
+            state: VsockConnectionState::from(info.state),
+            tx_buffered_bytes: info.tx_buf_len,
+            rx_in_flight_bytes: info.rx_in_flight,
+            peer_credit: info.peer_credit,
+            rx_bytes: info.rx_cnt,
+            tx_bytes: info.fwd_cnt,
+        }
+    }
+}
+
+/// Identifies the vsock connection to be forcefully reset.
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct VsockConnectionResetParams {
+    /// The host-side port of the connection.
+    pub local_port: u32,
+    /// The guest-side port of the connection.
+    pub peer_port: u32,
 }
 
 struct VsockAndUnixPath {
     vsock: MutexVsockUnix,
     uds_path: String,
+    /// Whether the SOCK_SEQPACKET socket is bound too.
+    seqpacket: bool,
 }
 
-/// A builder of Vsock with Unix backend from 'VsockDeviceConfig'.
+/// A builder of Vsock with Unix or vhost backend from 'VsockDeviceConfig'.
 #[derive(Default)]
 pub struct VsockBuilder {
     inner: Option<VsockAndUnixPath>,
+    vhost: Option<MutexVhostVsock>,
 }
 
 impl VsockBuilder {
-    /// Creates an empty Vsock with Unix backend Store.
+    /// Creates an empty Vsock Store.
     pub fn new() -> Self {
-        Self { inner: None }
+        Self {
+            inner: None,
+            vhost: None,
+        }
     }
 
-    /// Inserts a Unix backend Vsock in the store.
+    /// Inserts a Vsock in the store.
     /// If an entry already exists, it will overwrite it.
     pub fn insert(&mut self, cfg: VsockDeviceConfig) -> Result<()> {
-        // Make sure to drop the old one and remove the socket before creating a new one.
+        match (cfg.backend, cfg.uds_path.is_some()) {
+            (VsockBackendType::Unix, false) => return Err(VsockConfigError::MissingUdsPath),
+            (VsockBackendType::Vhost, true) => return Err(VsockConfigError::UnexpectedUdsPath),
+            _ => (),
+        }
+        if cfg.backend == VsockBackendType::Vhost
+            && (!cfg.guest_port_routes.is_empty()
+                || cfg.host_allowed_ports.is_some()
+                || cfg.mmds_port.is_some())
+        {
+            return Err(VsockConfigError::UnsupportedRoutes);
+        }
+        if let Some(route) = cfg
+            .guest_port_routes
+            .iter()
+            .find(|route| route.uds_path.is_some() && route.tcp_port.is_some())
+        {
+            return Err(VsockConfigError::InvalidPortRoute(route.port));
+        }
+        if let Some(mmds_port) = cfg.mmds_port {
+            if cfg
+                .guest_port_routes
+                .iter()
+                .any(|route| route.port == mmds_port)
+            {
+                return Err(VsockConfigError::InvalidMmdsPort(mmds_port));
+            }
+        }
+        if cfg.backend == VsockBackendType::Vhost && cfg.max_connections.is_some() {
+            return Err(VsockConfigError::UnsupportedConnections);
+        }
+        if cfg.backend == VsockBackendType::Vhost && cfg.per_port_metrics {
+            return Err(VsockConfigError::UnsupportedPortMetrics);
+        }
+        if cfg.backend == VsockBackendType::Vhost && cfg.seqpacket {
+            return Err(VsockConfigError::UnsupportedSeqpacket);
+        }
+        if cfg.max_connections == Some(0) {
+            return Err(VsockConfigError::InvalidMaxConnections);
+        }
+
+        // Make sure to drop the old one and remove the sockets before creating a new one.
         if let Some(existing) = self.inner.take() {
+            if existing.seqpacket {
+                std::fs::remove_file(vsock_seqpacket_sock_path(&existing.uds_path))
+                    .map_err(VsockUnixBackendError::UnixBind)
+                    .map_err(VsockConfigError::CreateVsockBackend)?;
+            }
             std::fs::remove_file(existing.uds_path)
                 .map_err(VsockUnixBackendError::UnixBind)
                 .map_err(VsockConfigError::CreateVsockBackend)?;
         }
-        self.inner = Some(VsockAndUnixPath {
-            uds_path: cfg.uds_path.clone(),
-            vsock: Arc::new(Mutex::new(Self::create_unixsock_vsock(cfg)?)),
-        });
+        // The guest CID is only released by the host kernel once the old device is dropped.
+        self.vhost = None;
+
+        match cfg.backend {
+            VsockBackendType::Unix => {
+                self.inner = Some(VsockAndUnixPath {
+                    uds_path: cfg.uds_path.clone().unwrap_or_default(),
+                    seqpacket: cfg.seqpacket,
+                    vsock: Arc::new(Mutex::new(Self::create_unixsock_vsock(cfg)?)),
+                });
+            }
+            VsockBackendType::Vhost => {
+                self.vhost = Some(Arc::new(Mutex::new(Self::create_vhost_vsock(cfg)?)));
+            }
+        }
         Ok(())
     }
 
-    /// Provides a reference to the Vsock if present.
+    /// Provides a reference to the Unix backend Vsock if present.
     pub fn get(&self) -> Option<&MutexVsockUnix> {
         self.inner.as_ref().map(|pair| &pair.vsock)
     }
 
+    /// Provides a reference to the vhost-vsock device if present.
+    pub fn get_vhost(&self) -> Option<&MutexVhostVsock> {
+        self.vhost.as_ref()
+    }
+
     /// Creates a Vsock device from a VsockDeviceConfig.
     pub fn create_unixsock_vsock(cfg: VsockDeviceConfig) -> Result<Vsock<VsockUnixBackend>> {
-        let backend = VsockUnixBackend::new(u64::from(cfg.guest_cid), cfg.uds_path)
+        let uds_path = cfg.uds_path.ok_or(VsockConfigError::MissingUdsPath)?;
+        let mut backend = VsockUnixBackend::new(u64::from(cfg.guest_cid), uds_path)
             .map_err(VsockConfigError::CreateVsockBackend)?;
+        let mut guest_ports: HashMap<_, _> = cfg
+            .guest_port_routes
+            .into_iter()
+            .map(|route| {
+                let target = match (route.uds_path, route.tcp_port) {
+                    (_, Some(tcp_port)) => Some(VsockUnixPortRoute::Tcp(tcp_port)),
+                    (Some(path), None) => Some(VsockUnixPortRoute::Unix(path)),
+                    (None, None) => None,
+                };
+                (route.port, target)
+            })
+            .collect();
+        if let Some(mmds_port) = cfg.mmds_port {
+            guest_ports.insert(mmds_port, Some(VsockUnixPortRoute::Mmds));
+        }
+        backend.set_routes(VsockUnixRoutes {
+            guest_ports,
+            host_allowed_ports: cfg
+                .host_allowed_ports
+                .map(|ports| ports.into_iter().collect()),
+        });
+        if let Some(max_connections) = cfg.max_connections {
+            backend.set_max_connections(max_connections as usize);
+        }
+        backend.set_per_port_metrics(cfg.per_port_metrics);
+        if cfg.seqpacket {
+            backend
+                .enable_seqpacket()
+                .map_err(VsockConfigError::CreateVsockBackend)?;
+        }
 
         Ok(Vsock::new(u64::from(cfg.guest_cid), backend)
             .map_err(VsockConfigError::CreateVsockDevice)?)
     }
+
+    /// Creates a vhost-vsock device from a VsockDeviceConfig.
+    pub fn create_vhost_vsock(cfg: VsockDeviceConfig) -> Result<VhostVsock> {
+        VhostVsock::new(u64::from(cfg.guest_cid)).map_err(VsockConfigError::CreateVhostVsockDevice)
+    }
 }
 
 #[cfg(test)]
@@ -118,6 +427,7 @@ pub(crate) mod tests {
     impl Drop for TempSockFile {
         fn drop(&mut self) {
             let _ = std::fs::remove_file(&self.path);
+            let _ = std::fs::remove_file(vsock_seqpacket_sock_path(&self.path));
         }
     }
 
@@ -126,7 +436,14 @@ pub(crate) mod tests {
         VsockDeviceConfig {
             vsock_id: vsock_dev_id.to_string(),
             guest_cid: 3,
-            uds_path: tmp_sock_file.path().clone(),
+            uds_path: Some(tmp_sock_file.path().clone()),
+            backend: VsockBackendType::Unix,
+            guest_port_routes: Vec::new(),
+            host_allowed_ports: None,
+            max_connections: None,
+            per_port_metrics: false,
+            mmds_port: None,
+            seqpacket: false,
         }
     }
 
@@ -154,6 +471,265 @@ pub(crate) mod tests {
         assert_eq!(vsock.lock().unwrap().cid(), new_cid as u64);
     }
 
+    #[test]
+    fn test_vsock_routes() {
+        let tmp_sock_file = TempSockFile::new(TempFile::new().unwrap());
+        let mut vsock_config = default_config(&tmp_sock_file);
+        vsock_config.guest_port_routes = vec![
+            VsockGuestPortRoute {
+                port: 52,
+                uds_path: Some("/tmp/agent.sock".to_string()),
+                tcp_port: None,
+            },
+            VsockGuestPortRoute {
+                port: 53,
+                uds_path: None,
+                tcp_port: None,
+            },
+            VsockGuestPortRoute {
+                port: 55,
+                uds_path: None,
+                tcp_port: Some(8080),
+            },
+        ];
+        vsock_config.host_allowed_ports = Some(vec![1024]);
+
+        let vsock = VsockBuilder::create_unixsock_vsock(vsock_config.clone()).unwrap();
+        let routes = vsock.backend().routes();
+        assert_eq!(
+            routes.guest_ports.get(&52),
+            Some(&Some(VsockUnixPortRoute::Unix(
+                "/tmp/agent.sock".to_string()
+            )))
+        );
+        assert_eq!(routes.guest_ports.get(&53), Some(&None));
+        assert!(routes.guest_ports.get(&54).is_none());
+        assert_eq!(
+            routes.guest_ports.get(&55),
+            Some(&Some(VsockUnixPortRoute::Tcp(8080)))
+        );
+        assert!(routes.is_host_port_allowed(1024));
+        assert!(!routes.is_host_port_allowed(1025));
+        drop(vsock);
+
+        // A route can't forward connections to both a Unix and a TCP socket.
+        let mut store = VsockBuilder::new();
+        vsock_config.guest_port_routes[0].tcp_port = Some(8081);
+        assert_eq!(
+            store.insert(vsock_config.clone()).unwrap_err().to_string(),
+            "The route of vsock port 52 cannot have both a uds_path and a tcp_port."
+        );
+        assert!(store.get().is_none());
+        vsock_config.guest_port_routes[0].tcp_port = None;
+
+        // Routing rules are rejected by the vhost backend.
+        let mut store = VsockBuilder::new();
+        vsock_config.uds_path = None;
+        vsock_config.backend = VsockBackendType::Vhost;
+        assert_eq!(
+            store.insert(vsock_config).unwrap_err().to_string(),
+            "Vsock routing rules are only supported by the Unix vsock backend."
+        );
+    }
+
+    #[test]
+    fn test_vsock_mmds_port() {
+        let tmp_sock_file = TempSockFile::new(TempFile::new().unwrap());
+        let mut vsock_config = default_config(&tmp_sock_file);
+        vsock_config.mmds_port = Some(52);
+
+        let vsock = VsockBuilder::create_unixsock_vsock(vsock_config.clone()).unwrap();
+        assert_eq!(
+            vsock.backend().routes().guest_ports.get(&52),
+            Some(&Some(VsockUnixPortRoute::Mmds))
+        );
+        drop(vsock);
+
+        // The MMDS port can't also be routed.
+        let mut store = VsockBuilder::new();
+        vsock_config.guest_port_routes = vec![VsockGuestPortRoute {
+            port: 52,
+            uds_path: None,
+            tcp_port: None,
+        }];
+        assert_eq!(
+            store.insert(vsock_config.clone()).unwrap_err().to_string(),
+            "The vsock port 52 cannot both serve the MMDS and have a route."
+        );
+        vsock_config.guest_port_routes = Vec::new();
+
+        // The MMDS is not served by the vhost backend.
+        vsock_config.uds_path = None;
+        vsock_config.backend = VsockBackendType::Vhost;
+        assert_eq!(
+            store.insert(vsock_config).unwrap_err().to_string(),
+            "Vsock routing rules are only supported by the Unix vsock backend."
+        );
+    }
+
+    #[test]
+    fn test_vsock_max_connections() {
+        let mut store = VsockBuilder::new();
+        let tmp_sock_file = TempSockFile::new(TempFile::new().unwrap());
+        let mut vsock_config = default_config(&tmp_sock_file);
+
+        store.insert(vsock_config.clone()).unwrap();
+        assert_eq!(
+            store
+                .get()
+                .unwrap()
+                .lock()
+                .unwrap()
+                .backend()
+                .max_connections(),
+            1023
+        );
+
+        vsock_config.max_connections = Some(4096);
+        store.insert(vsock_config.clone()).unwrap();
+        assert_eq!(
+            store
+                .get()
+                .unwrap()
+                .lock()
+                .unwrap()
+                .backend()
+                .max_connections(),
+            4096
+        );
+
+        vsock_config.max_connections = Some(0);
+        assert_eq!(
+            store.insert(vsock_config.clone()).unwrap_err().to_string(),
+            "The vsock connection limit must be greater than 0."
+        );
+
+        // The connection limit is rejected by the vhost backend.
+        vsock_config.max_connections = Some(1);
+        vsock_config.uds_path = None;
+        vsock_config.backend = VsockBackendType::Vhost;
+        assert_eq!(
+            store.insert(vsock_config).unwrap_err().to_string(),
+            "Vsock connection management is only supported by the Unix vsock backend."
+        );
+    }
+
+    #[test]
+    fn test_vsock_per_port_metrics() {
+        let tmp_sock_file = TempSockFile::new(TempFile::new().unwrap());
+        let mut vsock_config = default_config(&tmp_sock_file);
+
+        let vsock = VsockBuilder::create_unixsock_vsock(vsock_config.clone()).unwrap();
+        assert!(!vsock.backend().per_port_metrics());
+        drop(vsock);
+
+        vsock_config.per_port_metrics = true;
+        let vsock = VsockBuilder::create_unixsock_vsock(vsock_config.clone()).unwrap();
+        assert!(vsock.backend().per_port_metrics());
+        drop(vsock);
+
+        // Per-port metrics are rejected by the vhost backend.
+        let mut store = VsockBuilder::new();
+        vsock_config.uds_path = None;
+        vsock_config.backend = VsockBackendType::Vhost;
+        assert_eq!(
+            store.insert(vsock_config).unwrap_err().to_string(),
+            "Per-port vsock metrics are only supported by the Unix vsock backend."
+        );
+    }
+
+    #[test]
+    fn test_vsock_seqpacket() {
+        let mut store = VsockBuilder::new();
+        let tmp_sock_file = TempSockFile::new(TempFile::new().unwrap());
+        let seqpacket_path = vsock_seqpacket_sock_path(tmp_sock_file.path());
+        let mut vsock_config = default_config(&tmp_sock_file);
+
+        // The SOCK_SEQPACKET socket is only bound when enabled.
+        store.insert(vsock_config.clone()).unwrap();
+        assert!(!store.get().unwrap().lock().unwrap().backend().seqpacket());
+        assert!(!std::path::Path::new(&seqpacket_path).exists());
+
+        vsock_config.seqpacket = true;
+        store.insert(vsock_config.clone()).unwrap();
+        assert!(store.get().unwrap().lock().unwrap().backend().seqpacket());
+        assert!(std::path::Path::new(&seqpacket_path).exists());
+
+        // Both sockets are removed when the device is replaced.
+        vsock_config.seqpacket = false;
+        store.insert(vsock_config.clone()).unwrap();
+        assert!(!std::path::Path::new(&seqpacket_path).exists());
+
+        // SOCK_SEQPACKET connections can't be enabled on the vhost backend.
+        let mut store = VsockBuilder::new();
+        vsock_config.seqpacket = true;
+        vsock_config.uds_path = None;
+        vsock_config.backend = VsockBackendType::Vhost;
+        assert_eq!(
+            store.insert(vsock_config).unwrap_err().to_string(),
+            "Enabling SOCK_SEQPACKET vsock connections is only supported by the Unix vsock \
+             backend."
+        );
+    }
+
+    #[test]
+    fn test_vsock_connection_stats() {
+        let info = VsockConnectionInfo {
+            local_port: 1024,
+            peer_port: 52,
+            seqpacket: true,
+            state: VsockConnState::PeerClosed(true, false),
+            tx_buf_len: 1,
+            rx_in_flight: 2,
+            peer_credit: 3,
+            rx_cnt: 4,
+            fwd_cnt: 5,
+        };
+        let stats = VsockConnectionStats::from(&info);
+        assert_eq!(stats.state, VsockConnectionState::PeerClosed);
+        assert_eq!(
+            serde_json::to_string(&stats).unwrap(),
+            "{\"local_port\":1024,\"peer_port\":52,\"seqpacket\":true,\"state\":\"PeerClosed\",\
+             \"tx_buffered_bytes\":1,\"rx_in_flight_bytes\":2,\"peer_credit\":3,\"rx_bytes\":4,\
+             \"tx_bytes\":5}"
+        );
+    }
+
+    #[test]
+    fn test_vsock_backend_config() {
+        let mut store = VsockBuilder::new();
+        let tmp_sock_file = TempSockFile::new(TempFile::new().unwrap());
+
+        // The Unix backend needs a socket path.
+        let mut vsock_config = default_config(&tmp_sock_file);
+        vsock_config.uds_path = None;
+        assert_eq!(
+            store.insert(vsock_config).unwrap_err().to_string(),
+            "The Unix vsock backend requires a uds_path."
+        );
+
+        // The vhost backend doesn't.
+        let mut vsock_config = default_config(&tmp_sock_file);
+        vsock_config.backend = VsockBackendType::Vhost;
+        assert_eq!(
+            store.insert(vsock_config).unwrap_err().to_string(),
+            "The vhost vsock backend does not accept a uds_path."
+        );
+        assert!(store.get().is_none());
+        assert!(store.get_vhost().is_none());
+
+        // The backend defaults to Unix.
+        let vsock_config: VsockDeviceConfig =
+            serde_json::from_str(r#"{"vsock_id": "vsock", "guest_cid": 3, "uds_path": "v.sock"}"#)
+                .unwrap();
+        assert_eq!(vsock_config.backend, VsockBackendType::Unix);
+        let vsock_config: VsockDeviceConfig =
+            serde_json::from_str(r#"{"vsock_id": "vsock", "guest_cid": 3, "backend": "Vhost"}"#)
+                .unwrap();
+        assert_eq!(vsock_config.backend, VsockBackendType::Vhost);
+        assert!(vsock_config.uds_path.is_none());
+    }
+
     #[test]
     fn test_error_messages() {
         use super::VsockConfigError::*;
@@ -167,5 +743,50 @@ pub(crate) mod tests {
             io::Error::from_raw_os_error(0),
         ));
         let _ = format!("{}{:?}", err, err);
+
+        let err = CreateVhostVsockDevice(devices::virtio::VhostVsockError::OpenVhostVsock(
+            io::Error::from_raw_os_error(0),
+        ));
+        let _ = format!("{}{:?}", err, err);
+
+        let err = MissingUdsPath;
+        let _ = format!("{}{:?}", err, err);
+
+        let err = UnexpectedUdsPath;
+        let _ = format!("{}{:?}", err, err);
+
+        let err = InvalidMaxConnections;
+        let _ = format!("{}{:?}", err, err);
+
+        let err = MissingDevice;
+        let _ = format!("{}{:?}", err, err);
+
+        let err = ResetConnection(devices::virtio::VsockUnixBackendError::ConnectionNotFound(
+            1024, 52,
+        ));
+        let _ = format!("{}{:?}", err, err);
+
+        let err = TransportReset(devices::Error::FailedSignalingUsedQueue(
+            io::Error::from_raw_os_error(0),
+        ));
+        let _ = format!("{}{:?}", err, err);
+
+        let err = UnsupportedConnections;
+        let _ = format!("{}{:?}", err, err);
+
+        let err = UnsupportedPortMetrics;
+        let _ = format!("{}{:?}", err, err);
+
+        let err = InvalidPortRoute(52);
+        let _ = format!("{}{:?}", err, err);
+
+        let err = InvalidMmdsPort(52);
+        let _ = format!("{}{:?}", err, err);
+
+        let err = UnsupportedRoutes;
+        let _ = format!("{}{:?}", err, err);
+
+        let err = UnsupportedSeqpacket;
+        let _ = format!("{}{:?}", err, err);
     }
 }
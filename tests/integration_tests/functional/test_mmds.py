@@ -259,15 +259,22 @@ def test_imds_response(test_microvm_with_ssh, network_config):
     _, stdout, stderr = ssh_connection.execute_command(cmd)
     _assert_out(stdout, stderr, 'arr_val1')
 
+    cmd = pre + 'latest/meta-data/dummy_array'
+    _, stdout, stderr = ssh_connection.execute_command(cmd)
+    _assert_out(stdout, stderr, 'arr_val1\narr_val2')
+
     cmd = pre + 'latest/Usage/CPU'
     _, stdout, stderr = ssh_connection.execute_command(cmd)
-    _assert_out(stdout, stderr, 'Cannot retrieve value. The value has an'
-                                ' unsupported type.')
+    _assert_out(stdout, stderr, '12.12')
 
     cmd = pre + 'latest/Limits/CPU'
     _, stdout, stderr = ssh_connection.execute_command(cmd)
-    _assert_out(stdout, stderr, 'Cannot retrieve value. The value has an'
-                                ' unsupported type.')
+    _assert_out(stdout, stderr, '512')
+
+    cmd = 'curl -s -H "Accept: application/yaml" ' \
+          'http://169.254.169.254/latest/Limits'
+    _, stdout, stderr = ssh_connection.execute_command(cmd)
+    _assert_out(stdout, stderr, 'CPU: 512\nMemory: 512')
 
 
 def test_larger_than_mss_payloads(test_microvm_with_ssh, network_config):